@@ -3,7 +3,7 @@ use chrono::{DateTime, TimeZone, Utc};
 use log;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -12,6 +12,47 @@ use super::{
     Checkpoint, CheckpointMetadata, CheckpointPaths, CheckpointResult, CheckpointStrategy,
     FileSnapshot, FileState, FileTracker, SessionTimeline,
 };
+use crate::utils::cloud_files::is_cloud_placeholder;
+use crate::utils::hashing::{hash_file, HashAlgorithm};
+use crate::utils::lock_retry::{with_lock_retry, RetryPolicy};
+use crate::utils::path_matcher::PathMatcher;
+use crate::utils::paths::{find_case_variant, make_relative, rename_case_safe};
+use crate::utils::trash;
+
+/// Recursively collect the project's files, relative to its root
+///
+/// Hidden directories such as `.git` and directories matched by the
+/// project's ignore rules (`node_modules`, build output) are skipped.
+/// Ignored files outside them, such as `.env` or local config, are kept:
+/// sessions edit those too, and restoring must be able to undo that.
+fn collect_project_files(
+    dir: &Path,
+    matcher: &PathMatcher,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let Ok(rel) = path.strip_prefix(matcher.root()) else {
+            continue;
+        };
+        if path.is_dir() {
+            // Skip hidden directories like .git
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                if name.starts_with('.') {
+                    continue;
+                }
+            }
+            if matcher.is_match(rel, true) {
+                continue;
+            }
+            collect_project_files(&path, matcher, files)?;
+        } else if path.is_file() {
+            files.push(rel.to_path_buf());
+        }
+    }
+    Ok(())
+}
 
 /// Manages checkpoint operations for a session
 pub struct CheckpointManager {
@@ -106,12 +147,21 @@ impl CheckpointManager {
 
     /// Track a file modification
     pub async fn track_file_modification(&self, file_path: &str) -> Result<()> {
+        // Tools report absolute paths; keep files inside the project relative
+        // to it, so checkpoints still restore after the project is moved
+        let relative = make_relative(&self.project_path, file_path)
+            .filter(|rel| !rel.starts_with(".."))
+            .map(|rel| rel.to_string_lossy().to_string());
+        let file_path = relative.as_deref().unwrap_or(file_path);
+
         let mut tracker = self.file_tracker.write().await;
         let full_path = self.project_path.join(file_path);
 
         // Read current file state
         let (hash, exists, _size, modified) = if full_path.exists() {
-            let content = fs::read_to_string(&full_path).unwrap_or_default();
+            let hash = hash_file(&full_path, HashAlgorithm::Sha256)
+                .await
+                .unwrap_or_default();
             let metadata = fs::metadata(&full_path)?;
             let modified = metadata
                 .modified()
@@ -124,7 +174,7 @@ impl CheckpointManager {
                 .unwrap_or_else(Utc::now);
 
             (
-                storage::CheckpointStorage::calculate_file_hash(&content),
+                hash,
                 true,
                 metadata.len(),
                 modified,
@@ -198,36 +248,17 @@ impl CheckpointManager {
             self.extract_checkpoint_metadata(&messages).await?;
 
         // Ensure every file in the project is tracked so new checkpoints include all files
-        // Recursively walk the project directory and track each file
-        fn collect_files(
-            dir: &std::path::Path,
-            base: &std::path::Path,
-            files: &mut Vec<std::path::PathBuf>,
-        ) -> Result<(), std::io::Error> {
-            for entry in std::fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    // Skip hidden directories like .git
-                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                        if name.starts_with('.') {
-                            continue;
-                        }
-                    }
-                    collect_files(&path, base, files)?;
-                } else if path.is_file() {
-                    // Compute relative path from project root
-                    if let Ok(rel) = path.strip_prefix(base) {
-                        files.push(rel.to_path_buf());
-                    }
-                }
-            }
-            Ok(())
-        }
         let mut all_files = Vec::new();
-        let project_dir = &self.project_path;
-        let _ = collect_files(project_dir.as_path(), project_dir.as_path(), &mut all_files);
+        let matcher = PathMatcher::for_project(&self.project_path);
+        let _ = collect_project_files(&self.project_path, &matcher, &mut all_files);
+        let mut skipped_placeholders = Vec::new();
         for rel in all_files {
+            // Reading an online-only file would download it
+            if is_cloud_placeholder(self.project_path.join(&rel)) {
+                log::debug!("Not snapshotting online-only file {:?}", rel);
+                skipped_placeholders.push(rel);
+                continue;
+            }
             if let Some(p) = rel.to_str() {
                 // Track each file for snapshot
                 let _ = self.track_file_modification(p).await;
@@ -266,6 +297,7 @@ impl CheckpointManager {
                     &messages.join("\n"),
                     &file_snapshots,
                 ),
+                skipped_placeholders,
             },
         };
 
@@ -459,74 +491,74 @@ impl CheckpointManager {
     }
 
     /// Restore a checkpoint
-    pub async fn restore_checkpoint(&self, checkpoint_id: &str) -> Result<CheckpointResult> {
+    ///
+    /// Files that can't be written because another account, usually an
+    /// elevated session, owns them are listed in `locked_files`; restore
+    /// again with `take_ownership` once the user agreed to take them over.
+    pub async fn restore_checkpoint(
+        &self,
+        checkpoint_id: &str,
+        take_ownership: bool,
+    ) -> Result<CheckpointResult> {
         // Load checkpoint data
         let (checkpoint, file_snapshots, messages) =
             self.storage
                 .load_checkpoint(&self.project_id, &self.session_id, checkpoint_id)?;
 
-        // First, collect all files currently in the project to handle deletions
-        fn collect_all_project_files(
-            dir: &std::path::Path,
-            base: &std::path::Path,
-            files: &mut Vec<std::path::PathBuf>,
-        ) -> Result<(), std::io::Error> {
-            for entry in std::fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    // Skip hidden directories like .git
-                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                        if name.starts_with('.') {
-                            continue;
-                        }
-                    }
-                    collect_all_project_files(&path, base, files)?;
-                } else if path.is_file() {
-                    // Compute relative path from project root
-                    if let Ok(rel) = path.strip_prefix(base) {
-                        files.push(rel.to_path_buf());
-                    }
-                }
-            }
-            Ok(())
-        }
-
+        // First, collect all files currently in the project to handle deletions.
+        // Ignored directories are never snapshotted, so they are left alone
         let mut current_files = Vec::new();
-        let _ =
-            collect_all_project_files(&self.project_path, &self.project_path, &mut current_files);
-
-        // Create a set of files that should exist after restore
-        let mut checkpoint_files = std::collections::HashSet::new();
+        let matcher = PathMatcher::for_project(&self.project_path);
+        let _ = collect_project_files(&self.project_path, &matcher, &mut current_files);
+
+        // Create a set of files that should exist after restore. Files that
+        // were online-only at checkpoint time were never snapshotted, so
+        // their absence from the checkpoint says nothing, even if they have
+        // been downloaded since
+        let mut checkpoint_files: std::collections::HashSet<PathBuf> = checkpoint
+            .metadata
+            .skipped_placeholders
+            .iter()
+            .cloned()
+            .collect();
         for snapshot in &file_snapshots {
             if !snapshot.is_deleted {
                 checkpoint_files.insert(snapshot.file_path.clone());
             }
         }
 
-        // Delete files that exist now but shouldn't exist in the checkpoint
+        // Delete files that exist now but shouldn't exist in the checkpoint,
+        // and files the checkpoint recorded as deleted
         let mut warnings = Vec::new();
-        let mut files_processed = 0;
-
+        let mut to_delete = Vec::new();
         for current_file in current_files {
             if !checkpoint_files.contains(&current_file) {
-                // This file exists now but not in the checkpoint, so delete it
                 let full_path = self.project_path.join(&current_file);
-                match fs::remove_file(&full_path) {
-                    Ok(_) => {
-                        files_processed += 1;
-                        log::info!("Deleted file not in checkpoint: {:?}", current_file);
-                    }
-                    Err(e) => {
-                        warnings.push(format!(
-                            "Failed to delete {}: {}",
-                            current_file.display(),
-                            e
-                        ));
-                    }
+                // Checkpoints from before placeholders were recorded
+                if is_cloud_placeholder(&full_path) {
+                    continue;
                 }
+                to_delete.push(full_path);
             }
         }
+        to_delete.extend(
+            file_snapshots
+                .iter()
+                .filter(|snapshot| snapshot.is_deleted)
+                .map(|snapshot| self.project_path.join(&snapshot.file_path))
+                .filter(|path| path.exists()),
+        );
+        to_delete.sort();
+        to_delete.dedup();
+
+        // Moved to the trash in one batch, as the user may have wanted to
+        // keep them
+        let failures = trash::delete_all(&to_delete);
+        let mut files_processed = to_delete.len() - failures.len();
+        log::info!("Trashed {} files not in checkpoint", files_processed);
+        for (path, e) in failures {
+            warnings.push(format!("Failed to delete {}: {:#}", path.display(), e));
+        }
 
         // Clean up empty directories
         fn remove_empty_dirs(
@@ -562,14 +594,25 @@ impl CheckpointManager {
         let _ = remove_empty_dirs(&self.project_path, &self.project_path);
 
         // Restore files from checkpoint
-        for snapshot in &file_snapshots {
-            match self.restore_file_snapshot(snapshot).await {
+        #[cfg_attr(not(target_os = "windows"), allow(unused_mut))]
+        let mut locked_files = Vec::new();
+        for snapshot in file_snapshots
+            .iter()
+            .filter(|snapshot| !snapshot.is_deleted)
+        {
+            match self.restore_file_snapshot(snapshot, take_ownership).await {
                 Ok(_) => files_processed += 1,
-                Err(e) => warnings.push(format!(
-                    "Failed to restore {}: {}",
-                    snapshot.file_path.display(),
-                    e
-                )),
+                Err(e) => {
+                    #[cfg(target_os = "windows")]
+                    if e.is::<crate::windows::permissions::FileLockedError>() {
+                        locked_files.push(snapshot.file_path.clone());
+                    }
+                    warnings.push(format!(
+                        "Failed to restore {}: {}",
+                        snapshot.file_path.display(),
+                        e
+                    ))
+                }
             }
         }
 
@@ -605,45 +648,71 @@ impl CheckpointManager {
             checkpoint: checkpoint.clone(),
             files_processed,
             warnings,
+            locked_files,
         })
     }
 
-    /// Restore a single file from snapshot
-    async fn restore_file_snapshot(&self, snapshot: &FileSnapshot) -> Result<()> {
+    /// Write a file back from its snapshot
+    ///
+    /// Files the snapshot records as deleted are trashed in one batch by
+    /// [`Self::restore_checkpoint`] instead.
+    async fn restore_file_snapshot(
+        &self,
+        snapshot: &FileSnapshot,
+        take_ownership: bool,
+    ) -> Result<()> {
         let full_path = self.project_path.join(&snapshot.file_path);
 
-        if snapshot.is_deleted {
-            // Delete the file if it exists
-            if full_path.exists() {
-                fs::remove_file(&full_path).context("Failed to delete file")?;
-            }
-        } else {
-            // Create parent directories if needed
-            if let Some(parent) = full_path.parent() {
-                fs::create_dir_all(parent).context("Failed to create parent directories")?;
-            }
-
-            // Write file content
-            fs::write(&full_path, &snapshot.content).context("Failed to write file")?;
+        // Create parent directories if needed
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create parent directories")?;
+        }
 
-            // Restore permissions if available
-            #[cfg(unix)]
-            if let Some(mode) = snapshot.permissions {
-                use std::os::unix::fs::PermissionsExt;
-                let permissions = std::fs::Permissions::from_mode(mode);
-                fs::set_permissions(&full_path, permissions)
-                    .context("Failed to set file permissions")?;
+        // Write file content
+        // On a case-insensitive volume the write would keep the casing
+        // of an existing `readme.md` when restoring `README.md`
+        if full_path.exists() {
+            if let Some(existing) = find_case_variant(&full_path) {
+                rename_case_safe(&existing, &full_path)?;
             }
+        }
 
-            #[cfg(target_os = "windows")]
-            if let Some(mode) = snapshot.permissions {
-                // On Windows, we stored 0o444 for readonly, 0o666 for read-write
-                use std::fs::Permissions;
-                let mut permissions = fs::metadata(&full_path)?.permissions();
-                permissions.set_readonly(mode == 0o444);
-                fs::set_permissions(&full_path, permissions)
-                    .context("Failed to set file permissions")?;
+        // Editors and indexers often still have the file open
+        let written = with_lock_retry(
+            || fs::write(&full_path, &snapshot.content),
+            &RetryPolicy::for_path(&full_path),
+        );
+        #[cfg(target_os = "windows")]
+        let written = match written {
+            Err(e) => {
+                recover_denied_write(&full_path, e, take_ownership, || {
+                    fs::write(&full_path, &snapshot.content)
+                })
+                .await
             }
+            written => written,
+        };
+        #[cfg(not(target_os = "windows"))]
+        let _ = take_ownership;
+        written.context("Failed to write file")?;
+
+        // Restore permissions if available
+        #[cfg(unix)]
+        if let Some(mode) = snapshot.permissions {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(mode);
+            fs::set_permissions(&full_path, permissions)
+                .context("Failed to set file permissions")?;
+        }
+
+        #[cfg(target_os = "windows")]
+        if let Some(mode) = snapshot.permissions {
+            // On Windows, we stored 0o444 for readonly, 0o666 for read-write
+            use std::fs::Permissions;
+            let mut permissions = fs::metadata(&full_path)?.permissions();
+            permissions.set_readonly(mode == 0o444);
+            fs::set_permissions(&full_path, permissions)
+                .context("Failed to set file permissions")?;
         }
 
         Ok(())
@@ -689,7 +758,7 @@ impl CheckpointManager {
                 .load_checkpoint(&self.project_id, &self.session_id, checkpoint_id)?;
 
         // Restore to that checkpoint first
-        self.restore_checkpoint(checkpoint_id).await?;
+        self.restore_checkpoint(checkpoint_id, false).await?;
 
         // Create a new checkpoint with the fork
         let fork_description =
@@ -805,3 +874,80 @@ impl CheckpointManager {
             .max()
     }
 }
+
+/// Make sense of a failed write of `path`
+///
+/// Controlled Folder Access denials are reported as such, since taking
+/// ownership doesn't help against them. Other access-denied failures usually
+/// mean an elevated session owns the file: if the user agreed, the file is
+/// taken over through the elevation broker (see
+/// [`run_as_admin`](crate::windows::elevation_broker::run_as_admin)) and
+/// `write` retried, and a
+/// [`FileLockedError`](crate::windows::permissions::FileLockedError) is
+/// returned otherwise.
+#[cfg(target_os = "windows")]
+async fn recover_denied_write(
+    path: &Path,
+    error: anyhow::Error,
+    take_ownership: bool,
+    mut write: impl FnMut() -> std::io::Result<()>,
+) -> Result<()> {
+    use crate::windows::controlled_folder_access::{
+        explain_access_denied, ControlledFolderAccessError,
+    };
+    use crate::windows::elevation_broker::{run_as_admin, BrokerRequest};
+    use crate::windows::permissions::{with_file_ownership_consent, FileLockedError};
+
+    let error = match error.downcast::<std::io::Error>() {
+        Ok(error) if error.kind() == std::io::ErrorKind::PermissionDenied => error,
+        Ok(error) => return Err(error.into()),
+        Err(error) => return Err(error),
+    };
+    let explained = explain_access_denied(path, error);
+    if explained.is::<ControlledFolderAccessError>() {
+        return Err(explained);
+    }
+
+    let file_path = path.to_string_lossy().to_string();
+    match with_file_ownership_consent(&file_path, false, &mut write) {
+        Err(e) if take_ownership && e.is::<FileLockedError>() => {
+            log::warn!("{}; taking ownership with user consent", e);
+            run_as_admin(BrokerRequest::TakeFileOwnership { path: file_path }).await?;
+            write().with_context(|| {
+                format!(
+                    "Access to {} is still denied after taking ownership",
+                    path.display()
+                )
+            })
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_project_files_keeps_ignored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join(".gitignore"), ".env\ntarget/\n").unwrap();
+        std::fs::write(root.join(".env"), "API_URL=http://localhost").unwrap();
+        std::fs::create_dir_all(root.join("target").join("debug")).unwrap();
+        std::fs::write(root.join("target").join("debug").join("app"), "").unwrap();
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+
+        let mut files = Vec::new();
+        collect_project_files(root, &PathMatcher::for_project(root), &mut files).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from(".env"),
+                PathBuf::from(".gitignore"),
+                PathBuf::from("main.rs")
+            ]
+        );
+    }
+}
@@ -43,6 +43,10 @@ pub struct CheckpointMetadata {
     pub file_changes: usize,
     /// Size of all file snapshots in bytes
     pub snapshot_size: u64,
+    /// Online-only files that were left out because reading them would
+    /// download them. Restore keeps these even after they were hydrated.
+    #[serde(default)]
+    pub skipped_placeholders: Vec<PathBuf>,
 }
 
 /// Represents a snapshot of a file at a checkpoint
@@ -138,6 +142,9 @@ pub struct CheckpointResult {
     pub files_processed: usize,
     /// Any warnings during the operation
     pub warnings: Vec<String>,
+    /// Files a restore couldn't write because another account owns them
+    #[serde(default)]
+    pub locked_files: Vec<PathBuf>,
 }
 
 /// Diff between two checkpoints
@@ -1,5 +1,4 @@
 use anyhow::{Context, Result};
-use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
@@ -8,6 +7,31 @@ use zstd::stream::{decode_all, encode_all};
 use super::{
     Checkpoint, CheckpointPaths, CheckpointResult, FileSnapshot, SessionTimeline, TimelineNode,
 };
+use crate::utils::atomic_write::atomic_write;
+use crate::utils::delete::{delete_path, DeleteMode};
+use crate::utils::disk_space::ensure_free_space;
+use crate::utils::file_permissions::{create_dir_all_with_permissions, FilePermissions};
+use crate::utils::hashing::{hash_bytes, HashAlgorithm};
+use crate::utils::paths::sanitize_filename;
+
+/// Longest readable prefix kept in a reference file name
+const REF_PREFIX_LEN: usize = 40;
+
+/// Name of the reference file for `file_path` within a checkpoint
+///
+/// A hash of the full path keeps names unique however long or similar the
+/// paths are; the sanitized file name in front is only for people browsing
+/// the directory. Loading reads the path from the reference itself.
+fn ref_file_name(file_path: &Path) -> String {
+    let path = file_path.to_string_lossy();
+    let hash = hash_bytes(path.as_bytes(), HashAlgorithm::Sha256);
+    let name = file_path
+        .file_name()
+        .map(|name| sanitize_filename(&name.to_string_lossy()))
+        .unwrap_or_default();
+    let prefix: String = name.chars().take(REF_PREFIX_LEN).collect();
+    format!("{}-{}.json", prefix, &hash[..32])
+}
 
 /// Manages checkpoint storage operations
 pub struct CheckpointStorage {
@@ -28,7 +52,12 @@ impl CheckpointStorage {
     pub fn init_storage(&self, project_id: &str, session_id: &str) -> Result<()> {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
 
-        // Create directory structure
+        // Create directory structure; snapshots and transcripts are only
+        // sealed on Windows, so keep other users out of the session's folder
+        if let Some(session_dir) = paths.checkpoints_dir.parent() {
+            create_dir_all_with_permissions(session_dir, FilePermissions::OwnerOnly)
+                .context("Failed to create session timeline directory")?;
+        }
         fs::create_dir_all(&paths.checkpoints_dir)
             .context("Failed to create checkpoints directory")?;
         fs::create_dir_all(&paths.files_dir).context("Failed to create files directory")?;
@@ -54,6 +83,11 @@ impl CheckpointStorage {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
         let checkpoint_dir = paths.checkpoint_dir(&checkpoint.id);
 
+        ensure_free_space(
+            &self.claude_dir,
+            Self::estimate_checkpoint_size(messages, &file_snapshots),
+        )?;
+
         // Create checkpoint directory
         fs::create_dir_all(&checkpoint_dir).context("Failed to create checkpoint directory")?;
 
@@ -61,13 +95,15 @@ impl CheckpointStorage {
         let metadata_path = paths.checkpoint_metadata_file(&checkpoint.id);
         let metadata_json = serde_json::to_string_pretty(checkpoint)
             .context("Failed to serialize checkpoint metadata")?;
-        fs::write(&metadata_path, metadata_json).context("Failed to write checkpoint metadata")?;
+        atomic_write(&metadata_path, metadata_json).context("Failed to write checkpoint metadata")?;
 
-        // Save messages (compressed)
+        // Save messages (compressed, then encrypted where supported)
         let messages_path = paths.checkpoint_messages_file(&checkpoint.id);
         let compressed_messages = encode_all(messages.as_bytes(), self.compression_level)
             .context("Failed to compress messages")?;
-        fs::write(&messages_path, compressed_messages)
+        let sealed_messages = crate::data_protection::seal(&compressed_messages)
+            .context("Failed to encrypt messages")?;
+        atomic_write(&messages_path, sealed_messages)
             .context("Failed to write compressed messages")?;
 
         // Save file snapshots
@@ -92,6 +128,7 @@ impl CheckpointStorage {
             checkpoint: checkpoint.clone(),
             files_processed,
             warnings,
+            locked_files: Vec::new(),
         })
     }
 
@@ -129,15 +166,9 @@ impl CheckpointStorage {
             "size": snapshot.size,
         });
 
-        // Use a sanitized filename for the reference
-        let safe_filename = snapshot
-            .file_path
-            .to_string_lossy()
-            .replace('/', "_")
-            .replace('\\', "_");
-        let ref_path = checkpoint_refs_dir.join(format!("{}.json", safe_filename));
+        let ref_path = checkpoint_refs_dir.join(ref_file_name(&snapshot.file_path));
 
-        fs::write(&ref_path, serde_json::to_string_pretty(&ref_metadata)?)
+        atomic_write(&ref_path, serde_json::to_string_pretty(&ref_metadata)?)
             .context("Failed to write file reference")?;
 
         Ok(())
@@ -161,8 +192,10 @@ impl CheckpointStorage {
 
         // Load messages
         let messages_path = paths.checkpoint_messages_file(checkpoint_id);
-        let compressed_messages =
+        let sealed_messages =
             fs::read(&messages_path).context("Failed to read compressed messages")?;
+        let compressed_messages = crate::data_protection::unseal(&sealed_messages)
+            .context("Failed to decrypt messages")?;
         let messages = String::from_utf8(
             decode_all(&compressed_messages[..]).context("Failed to decompress messages")?,
         )
@@ -241,7 +274,7 @@ impl CheckpointStorage {
     pub fn save_timeline(&self, timeline_path: &Path, timeline: &SessionTimeline) -> Result<()> {
         let timeline_json =
             serde_json::to_string_pretty(timeline).context("Failed to serialize timeline")?;
-        fs::write(timeline_path, timeline_json).context("Failed to write timeline")?;
+        atomic_write(timeline_path, timeline_json).context("Failed to write timeline")?;
         Ok(())
     }
 
@@ -314,9 +347,7 @@ impl CheckpointStorage {
 
     /// Calculate hash of file content
     pub fn calculate_file_hash(content: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(content.as_bytes());
-        format!("{:x}", hasher.finalize())
+        hash_bytes(content.as_bytes(), HashAlgorithm::Sha256)
     }
 
     /// Generate a new checkpoint ID
@@ -334,11 +365,16 @@ impl CheckpointStorage {
     }
 
     /// Clean up old checkpoints based on retention policy
+    ///
+    /// With [`DeleteMode::Trash`] the removed checkpoints can be restored
+    /// from the Recycle Bin; content they shared with kept checkpoints is
+    /// never removed.
     pub fn cleanup_old_checkpoints(
         &self,
         project_id: &str,
         session_id: &str,
         keep_count: usize,
+        mode: DeleteMode,
     ) -> Result<usize> {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
         let timeline = self.load_timeline(&paths.timeline_file)?;
@@ -357,7 +393,7 @@ impl CheckpointStorage {
         let mut removed_count = 0;
 
         for checkpoint in all_checkpoints.into_iter().take(to_remove) {
-            if self.remove_checkpoint(&paths, &checkpoint.id).is_ok() {
+            if self.remove_checkpoint(&paths, &checkpoint.id, mode).is_ok() {
                 removed_count += 1;
             }
         }
@@ -386,18 +422,19 @@ impl CheckpointStorage {
     }
 
     /// Remove a checkpoint and its associated files
-    fn remove_checkpoint(&self, paths: &CheckpointPaths, checkpoint_id: &str) -> Result<()> {
+    fn remove_checkpoint(
+        &self,
+        paths: &CheckpointPaths,
+        checkpoint_id: &str,
+        mode: DeleteMode,
+    ) -> Result<()> {
         // Remove checkpoint metadata directory
         let checkpoint_dir = paths.checkpoint_dir(checkpoint_id);
-        if checkpoint_dir.exists() {
-            fs::remove_dir_all(&checkpoint_dir).context("Failed to remove checkpoint directory")?;
-        }
+        delete_path(&checkpoint_dir, mode).context("Failed to remove checkpoint directory")?;
 
         // Remove file references for this checkpoint
         let refs_dir = paths.files_dir.join("refs").join(checkpoint_id);
-        if refs_dir.exists() {
-            fs::remove_dir_all(&refs_dir).context("Failed to remove file references")?;
-        }
+        delete_path(&refs_dir, mode).context("Failed to remove file references")?;
 
         // Note: We don't remove content from the pool here as it might be
         // referenced by other checkpoints. Use garbage_collect_content() for that.
@@ -408,11 +445,25 @@ impl CheckpointStorage {
     /// Garbage collect unreferenced content from the content pool
     pub fn garbage_collect_content(&self, project_id: &str, session_id: &str) -> Result<usize> {
         let paths = CheckpointPaths::new(&self.claude_dir, project_id, session_id);
-        let content_pool_dir = paths.files_dir.join("content_pool");
-        let refs_dir = paths.files_dir.join("refs");
+
+        // Remove unreferenced content
+        let mut removed_count = 0;
+        for content_file in Self::find_orphaned_content(&paths.files_dir)? {
+            if fs::remove_file(&content_file).is_ok() {
+                removed_count += 1;
+            }
+        }
+
+        Ok(removed_count)
+    }
+
+    /// List content pool files under `files_dir` that no checkpoint references
+    pub fn find_orphaned_content(files_dir: &Path) -> Result<Vec<PathBuf>> {
+        let content_pool_dir = files_dir.join("content_pool");
+        let refs_dir = files_dir.join("refs");
 
         if !content_pool_dir.exists() {
-            return Ok(0);
+            return Ok(Vec::new());
         }
 
         // Collect all referenced hashes
@@ -440,21 +491,40 @@ impl CheckpointStorage {
             }
         }
 
-        // Remove unreferenced content
-        let mut removed_count = 0;
+        let mut orphaned = Vec::new();
         for entry in fs::read_dir(&content_pool_dir)? {
             let content_file = entry?.path();
             if content_file.is_file() {
                 if let Some(hash) = content_file.file_name().and_then(|n| n.to_str()) {
                     if !referenced_hashes.contains(hash) {
-                        if fs::remove_file(&content_file).is_ok() {
-                            removed_count += 1;
-                        }
+                        orphaned.push(content_file);
                     }
                 }
             }
         }
 
-        Ok(removed_count)
+        Ok(orphaned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ref_file_name() {
+        let long = "a/".repeat(200);
+        let first = ref_file_name(&Path::new(&long).join("settings.json"));
+        let second = ref_file_name(&Path::new(&long).join("b").join("settings.json"));
+        assert_ne!(first, second);
+        assert!(first.starts_with("settings.json-"));
+        assert!(first.ends_with(".json"));
+        assert!(first.len() < 100);
+
+        assert_eq!(
+            ref_file_name(Path::new("src/main.rs")),
+            ref_file_name(Path::new("src/main.rs"))
+        );
+        assert!(!ref_file_name(Path::new("src/con:aux")).contains(':'));
     }
 }
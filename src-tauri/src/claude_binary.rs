@@ -6,7 +6,8 @@ use std::cmp::Ordering;
 /// Supports NVM installations, aliased paths, and version-based selection
 use std::path::PathBuf;
 use std::process::Command;
-use tauri::Manager;
+
+use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
 
 /// Type of Claude installation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -36,7 +37,7 @@ pub fn find_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, Strin
     info!("Searching for claude binary...");
 
     // First check if we have a stored path and preference in the database
-    if let Ok(app_data_dir) = app_handle.path().app_data_dir() {
+    if let Ok(app_data_dir) = crate::portable::app_data_dir(app_handle) {
         let db_path = app_data_dir.join("agents.db");
         if db_path.exists() {
             if let Ok(conn) = rusqlite::Connection::open(&db_path) {
@@ -49,9 +50,9 @@ pub fn find_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, Strin
                     info!("Found stored claude path in database: {}", stored_path);
                     
                     // Check if the path still exists
-                    let path_buf = PathBuf::from(&stored_path);
+                    let path_buf = crate::utils::paths::expand_env_path(&stored_path);
                     if path_buf.exists() && path_buf.is_file() {
-                        return Ok(stored_path);
+                        return Ok(path_buf.to_string_lossy().to_string());
                     } else {
                         warn!("Stored claude path no longer exists: {}", stored_path);
                     }
@@ -94,6 +95,34 @@ pub fn find_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, Strin
     }
 }
 
+/// Applies the Authenticode signature policy to a launch of the CLI at `claude_path`
+///
+/// Fails when the policy refuses a binary the launch would run. Signatures
+/// are a Windows feature; elsewhere this always succeeds.
+pub fn check_launch_signature(
+    app_handle: &tauri::AppHandle,
+    claude_path: &str,
+) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::authenticode::{check_launch_binaries, load_signature_policy};
+
+        let policy = crate::portable::app_data_dir(app_handle)
+            .ok()
+            .map(|dir| dir.join("agents.db"))
+            .filter(|db_path| db_path.exists())
+            .and_then(|db_path| rusqlite::Connection::open(db_path).ok())
+            .map(|conn| load_signature_policy(&conn))
+            .unwrap_or_default();
+        check_launch_binaries(claude_path, policy).map_err(|e| format!("{:#}", e))?;
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    let _ = (app_handle, claude_path);
+
+    Ok(())
+}
+
 /// Discovers all available Claude installations and returns them for selection
 /// This allows UI to show a version selector
 pub fn discover_claude_installations() -> Vec<ClaudeInstallation> {
@@ -169,7 +198,7 @@ fn try_which_command() -> Option<ClaudeInstallation> {
     if cfg!(target_os = "windows") {
         // On Windows, use 'where' instead of 'which'
         debug!("Trying 'where claude' to find binary...");
-        match Command::new("where").arg("claude").output() {
+        match run_with_timeout_blocking(Command::new("where").arg("claude"), DEFAULT_COMMAND_TIMEOUT) {
         Ok(output) if output.status.success() => {
             let output_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
 
@@ -210,7 +239,7 @@ fn try_which_command() -> Option<ClaudeInstallation> {
     } else {
         // Non-Windows path remains the same
         debug!("Trying 'which claude' to find binary...");
-        match Command::new("which").arg("claude").output() {
+        match run_with_timeout_blocking(Command::new("which").arg("claude"), DEFAULT_COMMAND_TIMEOUT) {
             Ok(output) if output.status.success() => {
                 let output_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
 
@@ -393,7 +422,9 @@ fn find_standard_installations() -> Vec<ClaudeInstallation> {
 
     // Also check if claude is available in PATH (without full path)
     let claude_cmd = if cfg!(target_os = "windows") { "claude.exe" } else { "claude" };
-    if let Ok(output) = Command::new(claude_cmd).arg("--version").output() {
+    if let Ok(output) =
+        run_with_timeout_blocking(Command::new(claude_cmd).arg("--version"), DEFAULT_COMMAND_TIMEOUT)
+    {
         if output.status.success() {
             debug!("claude is available in PATH");
             let version = extract_version_from_output(&output.stdout);
@@ -412,7 +443,7 @@ fn find_standard_installations() -> Vec<ClaudeInstallation> {
 
 /// Get Claude version by running --version command
 fn get_claude_version(path: &str) -> Result<Option<String>, String> {
-    match Command::new(path).arg("--version").output() {
+    match run_with_timeout_blocking(Command::new(path).arg("--version"), DEFAULT_COMMAND_TIMEOUT) {
         Ok(output) => {
             if output.status.success() {
                 Ok(extract_version_from_output(&output.stdout))
@@ -534,6 +565,9 @@ pub fn create_command_with_env(program: &str) -> Command {
     
     info!("Creating command for: {}", program);
 
+    // Spawning a process counts as activity for idle-based maintenance
+    crate::commands::maintenance::record_activity();
+
     // Inherit essential environment variables from parent process
     for (key, value) in std::env::vars() {
         // Pass through PATH and other essential environment variables
@@ -597,5 +631,9 @@ pub fn create_command_with_env(program: &str) -> Command {
         }
     }
 
+    // Strip inherited variables that are known to break or leak into children
+    // and point children at the custom CA bundle, if one is configured
+    crate::commands::environment::apply_child_env(&mut cmd);
+
     cmd
 }
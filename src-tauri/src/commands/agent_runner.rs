@@ -0,0 +1,101 @@
+//! Installing and controlling the headless agent runner
+//!
+//! The runner is this executable started with `--agent-runner` by a Windows
+//! service, a systemd user unit or a launchd job. The systemd and launchd
+//! jobs belong to the user; the Windows service template is machine-wide,
+//! so installing or removing it asks for administrator rights with a UAC
+//! prompt unless Opcode already has them. The elevated helper reports back
+//! through
+//! [`run_elevated_with_result`](crate::windows::elevation_broker::run_elevated_with_result),
+//! so its error reaches the UI.
+
+use crate::windows::service::{self, ServiceStatus};
+use crate::windows::unsupported::CommandError;
+
+/// Run a blocking service operation off the async runtime
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, CommandError> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+}
+
+fn current_exe() -> Result<String, String> {
+    std::env::current_exe()
+        .map(|exe| exe.to_string_lossy().to_string())
+        .map_err(|e| format!("Failed to locate the opcode executable: {}", e))
+}
+
+/// Run this executable elevated with a service helper argument and wait for
+/// its report
+#[cfg(target_os = "windows")]
+async fn run_elevated_helper(arg: &str) -> Result<(), String> {
+    use crate::windows::elevation_broker::{run_elevated_with_result, ElevatedRunOutcome};
+
+    let timeout = Some(std::time::Duration::from_secs(120));
+    match run_elevated_with_result(&current_exe()?, &[arg], timeout)
+        .await
+        .map_err(|e| format!("{:#}", e))?
+    {
+        ElevatedRunOutcome::Reported { report } if report.success => Ok(()),
+        ElevatedRunOutcome::Reported { report } => Err(report
+            .message
+            .unwrap_or_else(|| "The elevated helper failed".to_string())),
+        ElevatedRunOutcome::Exited { exit_code: 0 } => Ok(()),
+        ElevatedRunOutcome::Exited { exit_code } => Err(format!(
+            "The elevated helper failed with exit code {}; see the log for details",
+            exit_code
+        )),
+        ElevatedRunOutcome::Declined => Err("Administrator access was declined".to_string()),
+    }
+}
+
+/// Whether Opcode can change the machine-wide service itself
+#[cfg(target_os = "windows")]
+fn is_admin() -> bool {
+    crate::windows::permissions::is_running_as_admin().unwrap_or(false)
+}
+
+/// Whether the agent runner is installed and running
+#[tauri::command]
+pub async fn get_agent_runner_status() -> Result<ServiceStatus, CommandError> {
+    run_blocking(service::service_status).await
+}
+
+/// Install the agent runner for this executable
+#[tauri::command]
+pub async fn install_agent_runner() -> Result<(), CommandError> {
+    #[cfg(target_os = "windows")]
+    if !is_admin() {
+        return run_elevated_helper(service::INSTALL_SERVICE_ARG)
+            .await
+            .map_err(CommandError::from);
+    }
+
+    let exe = current_exe()?;
+    run_blocking(move || service::install_service(&exe)).await
+}
+
+/// Stop and remove the agent runner, returning whether it was installed
+#[tauri::command]
+pub async fn uninstall_agent_runner() -> Result<bool, CommandError> {
+    #[cfg(target_os = "windows")]
+    if !is_admin() {
+        // Don't show a UAC prompt for nothing
+        if run_blocking(service::service_status).await? == ServiceStatus::NotInstalled {
+            return Ok(false);
+        }
+        run_elevated_helper(service::UNINSTALL_SERVICE_ARG).await?;
+        return Ok(true);
+    }
+
+    run_blocking(service::uninstall_service).await
+}
+
+/// Start the installed agent runner
+#[tauri::command]
+pub async fn start_agent_runner() -> Result<(), CommandError> {
+    run_blocking(service::start_service).await
+}
@@ -0,0 +1,220 @@
+//! Running agents in a sandbox
+//!
+//! With [`AgentSandbox::AppContainer`] in the runtime config, each agent run
+//! gets an AppContainer of its own (see
+//! [`appcontainer`](crate::windows::appcontainer)) that can read Claude's
+//! installation, write the project and Claude's configuration, and reach the
+//! internet, but can't open the rest of the user's files. Opcode creates the
+//! container, grants it access and starts Claude in it through the Claude
+//! wrapper; the [`SandboxGuard`] takes the access away and deletes the
+//! container when the run is over, even if the wrapper was killed.
+//!
+//! [`AgentSandbox::RestrictedToken`] is lighter: Claude runs as an
+//! unelevated process of the same user (see
+//! [`restricted_token`](crate::windows::restricted_token)), which matters
+//! when opcode itself was started as administrator.
+//!
+//! Other platforms have no sandbox, and asking for one fails the run with
+//! the `unsupported` error instead of running the agent unconfined.
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// How agent runs are confined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentSandbox {
+    /// Agents run with opcode's rights
+    #[default]
+    None,
+    /// Agents run in an AppContainer (Windows)
+    AppContainer,
+    /// Agents run without administrator groups and privileges (Windows)
+    RestrictedToken,
+}
+
+/// The sandbox of one run, torn down when dropped
+///
+/// Hold it until the run's process has exited.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub struct SandboxGuard {
+    #[cfg(target_os = "windows")]
+    sandbox: crate::windows::secrets::ClaudeSandbox,
+    /// The run's own AppContainer, deleted with the guard
+    #[cfg(target_os = "windows")]
+    container: Option<crate::windows::appcontainer::AppContainerLauncher>,
+    #[cfg(target_os = "windows")]
+    grants: Option<crate::windows::appcontainer::PathGrants>,
+}
+
+#[cfg(target_os = "windows")]
+impl SandboxGuard {
+    /// Command starting Claude at `claude_path` in the sandbox
+    pub fn command(&self, claude_path: &str) -> Result<Command, String> {
+        let mut cmd = crate::commands::secrets::wrapper_command(claude_path)
+            .ok_or("Failed to locate the opcode executable for the Claude wrapper")?;
+        self.sandbox
+            .pass_to(cmd.as_std_mut())
+            .map_err(|e| format!("{:#}", e))?;
+        Ok(cmd)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+impl SandboxGuard {
+    /// Command starting Claude at `claude_path` in the sandbox
+    pub fn command(&self, _claude_path: &str) -> Result<Command, String> {
+        Err(crate::windows::Unsupported::new("Sandboxed agent runs").to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for SandboxGuard {
+    fn drop(&mut self) {
+        // Remove the entries while the container's SID still means something
+        drop(self.grants.take());
+        if let Some(container) = &self.container {
+            if let Err(e) = container.delete_profile() {
+                log::warn!("Failed to delete the agent sandbox: {:#}", e);
+            }
+        }
+    }
+}
+
+/// Set up `sandbox` for agent run `run_id`
+///
+/// Returns `None` for [`AgentSandbox::None`]. Blocks while the access is
+/// granted, which takes a while for large projects.
+pub fn prepare_agent_sandbox(
+    sandbox: AgentSandbox,
+    claude_path: &str,
+    project_path: &str,
+    run_id: i64,
+) -> Result<Option<SandboxGuard>, String> {
+    match sandbox {
+        AgentSandbox::None => Ok(None),
+        #[cfg(target_os = "windows")]
+        AgentSandbox::AppContainer => {
+            prepare_app_container(claude_path, project_path, run_id).map(Some)
+        }
+        #[cfg(target_os = "windows")]
+        AgentSandbox::RestrictedToken => {
+            use crate::windows::restricted_token::RestrictionSpec;
+            use crate::windows::secrets::ClaudeSandbox;
+
+            log::info!("Agent run {} runs with a restricted token", run_id);
+            Ok(Some(SandboxGuard {
+                sandbox: ClaudeSandbox::RestrictedToken {
+                    spec: RestrictionSpec::default(),
+                },
+                container: None,
+                grants: None,
+            }))
+        }
+        #[cfg(not(target_os = "windows"))]
+        AgentSandbox::AppContainer | AgentSandbox::RestrictedToken => {
+            let _ = (claude_path, project_path, run_id);
+            Err(crate::windows::Unsupported::new("Sandboxed agent runs").to_string())
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn prepare_app_container(
+    claude_path: &str,
+    project_path: &str,
+    run_id: i64,
+) -> Result<SandboxGuard, String> {
+    use crate::windows::appcontainer::{AppContainerCapability, AppContainerLauncher};
+    use crate::windows::secrets::ClaudeSandbox;
+    use std::path::PathBuf;
+
+    let mut launcher = AppContainerLauncher::new(&format!("opcode.agent-run-{}", run_id));
+    launcher
+        .capabilities
+        .push(AppContainerCapability::InternetClient);
+
+    // Every container can read the Windows and Program Files directories,
+    // and only administrators could grant access there
+    let readable_by_all: Vec<PathBuf> = ["SystemRoot", "ProgramFiles", "ProgramFiles(x86)"]
+        .iter()
+        .filter_map(|var| std::env::var_os(var).map(PathBuf::from))
+        .collect();
+    let install_dir = |program: &str| -> Option<PathBuf> {
+        let path = which::which(program).ok()?;
+        let dir = crate::utils::paths::canonicalize_path(path)
+            .ok()?
+            .parent()?
+            .to_path_buf();
+        let shared = readable_by_all
+            .iter()
+            .any(|root| crate::utils::paths::path_contains(root, &dir));
+        (!shared).then_some(dir)
+    };
+    // Claude is often an npm shim that needs Node next to it or on PATH
+    launcher.readable_paths.extend(install_dir(claude_path));
+    launcher
+        .readable_paths
+        .extend(install_dir("node").filter(|dir| !launcher.readable_paths.contains(dir)));
+
+    launcher.writable_paths.push(PathBuf::from(project_path));
+    if let Some(home) = dirs::home_dir() {
+        launcher.writable_paths.push(home.join(".claude"));
+        let config = home.join(".claude.json");
+        if config.exists() {
+            launcher.writable_paths.push(config);
+        }
+    }
+
+    let sid = launcher.container_sid().map_err(|e| format!("{:#}", e))?;
+    let mut guard = SandboxGuard {
+        sandbox: ClaudeSandbox::AppContainer {
+            name: launcher.name.clone(),
+            capabilities: launcher.capabilities.clone(),
+        },
+        container: None,
+        grants: None,
+    };
+    // Dropping the guard from here on deletes the container again
+    let container = guard.container.insert(launcher);
+    let grants = container
+        .grant_path_access(&sid)
+        .map_err(|e| format!("Failed to set up the agent sandbox: {:#}", e))?;
+    guard.grants = Some(grants);
+    log::info!(
+        "Agent run {} sandboxed in AppContainer {}",
+        run_id,
+        container.name
+    );
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_agent_sandbox() {
+        assert!(
+            prepare_agent_sandbox(AgentSandbox::None, "claude", "/work/app", 1)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            serde_json::to_value(AgentSandbox::AppContainer).unwrap(),
+            "app_container"
+        );
+        assert_eq!(
+            serde_json::from_value::<AgentSandbox>("restricted_token".into()).unwrap(),
+            AgentSandbox::RestrictedToken
+        );
+
+        #[cfg(not(target_os = "windows"))]
+        assert!(
+            prepare_agent_sandbox(AgentSandbox::AppContainer, "claude", "/work/app", 1)
+                .err()
+                .unwrap()
+                .contains("is not supported")
+        );
+    }
+}
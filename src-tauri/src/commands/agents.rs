@@ -9,11 +9,15 @@ use serde_json::Value as JsonValue;
 use std::io::{BufRead, BufReader};
 use std::process::Stdio;
 use std::sync::Mutex;
-use tauri::{AppHandle, Emitter, Manager, State};
+use tauri::{AppHandle, Emitter, State};
 // Sidecar support removed; using system binary execution only
 use tokio::io::{AsyncBufReadExt, BufReader as TokioBufReader};
 use tokio::process::Command;
 
+use crate::commands::agent_sandbox::{prepare_agent_sandbox, SandboxGuard};
+use crate::commands::run_webhook::{notify_run_exit, RunExitEvent};
+use crate::process::exit::{ExitClassification, ExitReport, OutputSignals, Termination};
+
 /// Finds the full path to the claude binary
 /// This is necessary because macOS apps have a limited PATH environment
 fn find_claude_binary(app_handle: &AppHandle) -> Result<String, String> {
@@ -53,6 +57,40 @@ pub struct AgentRun {
     pub process_started_at: Option<String>,
     pub created_at: String,
     pub completed_at: Option<String>,
+    pub agent_version: Option<i64>, // Version of the agent definition used for this run
+    pub exit_code: Option<i32>,
+    pub exit_classification: Option<ExitClassification>, // Why the run ended, once it has
+}
+
+/// Represents how an interactive Claude session ended
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionRun {
+    pub id: i64,
+    pub session_id: Option<String>,
+    pub project_path: String,
+    pub model: String,
+    pub exit_code: Option<i32>,
+    pub exit_classification: Option<ExitClassification>,
+    pub completed_at: String,
+}
+
+/// Represents a stored revision of an agent definition
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgentVersion {
+    pub id: Option<i64>,
+    pub agent_id: i64,
+    pub version: i64,
+    pub name: String,
+    pub icon: String,
+    pub system_prompt: String,
+    pub default_task: Option<String>,
+    pub model: String,
+    pub enable_file_read: bool,
+    pub enable_file_write: bool,
+    pub enable_network: bool,
+    pub hooks: Option<String>,
+    pub note: Option<String>,
+    pub created_at: String,
 }
 
 /// Represents runtime metrics calculated from JSONL
@@ -212,15 +250,40 @@ pub async fn get_agent_run_with_metrics(run: AgentRun) -> AgentRunWithMetrics {
 
 /// Initialize the agents database
 pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
-    let app_dir = app
-        .path()
-        .app_data_dir()
+    let app_dir = crate::portable::app_data_dir(app)
         .expect("Failed to get app data dir");
     std::fs::create_dir_all(&app_dir).expect("Failed to create app data dir");
 
-    let db_path = app_dir.join("agents.db");
-    let conn = Connection::open(db_path)?;
+    open_database(&app_dir)
+}
+
+/// Open or create the agents database in `app_dir`, migrate its tables and
+/// restrict its files to the current user
+pub fn open_database(app_dir: &std::path::Path) -> SqliteResult<Connection> {
+    let conn = Connection::open(app_dir.join("agents.db"))?;
+
+    init_schema(&conn)?;
+
+    // Settings in the database include proxy credentials. Existing databases
+    // may predate this, and the journal files hold recent writes too
+    for suffix in ["", "-wal", "-shm", "-journal"] {
+        let path = app_dir.join(format!("agents.db{}", suffix));
+        if !path.exists() {
+            continue;
+        }
+        if let Err(e) = crate::utils::file_permissions::set_file_permissions(
+            &path,
+            crate::utils::file_permissions::FilePermissions::OwnerOnly,
+        ) {
+            warn!("Failed to restrict access to {}: {}", path.display(), e);
+        }
+    }
 
+    Ok(conn)
+}
+
+/// Create or migrate the tables of the agents database
+fn init_schema(conn: &Connection) -> SqliteResult<()> {
     // Create agents table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS agents (
@@ -293,6 +356,55 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
         [],
     );
 
+    let _ = conn.execute("ALTER TABLE agent_runs ADD COLUMN agent_version INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agent_runs ADD COLUMN exit_code INTEGER", []);
+    let _ = conn.execute("ALTER TABLE agent_runs ADD COLUMN exit_classification TEXT", []);
+
+    // Create session_runs table recording how interactive Claude sessions ended
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS session_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id TEXT,
+            project_path TEXT NOT NULL,
+            model TEXT NOT NULL,
+            exit_code INTEGER,
+            exit_classification TEXT NOT NULL,
+            completed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // Create agent_versions table holding every revision of an agent definition
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_versions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            agent_id INTEGER NOT NULL,
+            version INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL,
+            system_prompt TEXT NOT NULL,
+            default_task TEXT,
+            model TEXT NOT NULL,
+            enable_file_read BOOLEAN NOT NULL,
+            enable_file_write BOOLEAN NOT NULL,
+            enable_network BOOLEAN NOT NULL,
+            hooks TEXT,
+            note TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (agent_id, version),
+            FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Record an initial version for agents created before versioning existed
+    conn.execute(
+        "INSERT INTO agent_versions (agent_id, version, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, note, created_at)
+         SELECT id, 1, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, 'Initial version', updated_at
+         FROM agents WHERE id NOT IN (SELECT agent_id FROM agent_versions)",
+        [],
+    )?;
+
     // Drop old columns that are no longer needed (data is now read from JSONL files)
     // Note: SQLite doesn't support DROP COLUMN, so we'll ignore errors for existing columns
     let _ = conn.execute(
@@ -340,7 +452,35 @@ pub fn init_database(app: &AppHandle) -> SqliteResult<Connection> {
         [],
     )?;
 
-    Ok(conn)
+    // Create eval suite and result tables
+    crate::commands::evals::init_eval_tables(conn)?;
+    crate::commands::maintenance::init_maintenance_tables(conn)?;
+
+    Ok(())
+}
+
+/// Snapshot the current definition of an agent as a new version
+///
+/// Returns the newly assigned version number.
+fn record_agent_version(conn: &Connection, agent_id: i64, note: Option<&str>) -> SqliteResult<i64> {
+    conn.execute(
+        "INSERT INTO agent_versions (agent_id, version, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, note)
+         SELECT id, COALESCE((SELECT MAX(version) FROM agent_versions WHERE agent_id = ?1), 0) + 1,
+                name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, ?2
+         FROM agents WHERE id = ?1",
+        params![agent_id, note],
+    )?;
+
+    current_agent_version(conn, agent_id)
+}
+
+/// Get the latest version number of an agent definition
+pub fn current_agent_version(conn: &Connection, agent_id: i64) -> SqliteResult<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM agent_versions WHERE agent_id = ?1",
+        params![agent_id],
+        |row| row.get(0),
+    )
 }
 
 /// List all agents
@@ -405,6 +545,7 @@ pub async fn create_agent(
     .map_err(|e| e.to_string())?;
 
     let id = conn.last_insert_rowid();
+    record_agent_version(&conn, id, Some("Created")).map_err(|e| e.to_string())?;
 
     // Fetch the created agent
     let agent = conn
@@ -447,6 +588,7 @@ pub async fn update_agent(
     enable_file_write: Option<bool>,
     enable_network: Option<bool>,
     hooks: Option<String>,
+    version_note: Option<String>,
 ) -> Result<Agent, String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
     let model = model.unwrap_or_else(|| "sonnet".to_string());
@@ -491,6 +633,9 @@ pub async fn update_agent(
     )
     .map_err(|e| e.to_string())?;
 
+    // Keep the edited definition as a new version
+    record_agent_version(&conn, id, version_note.as_deref()).map_err(|e| e.to_string())?;
+
     // Fetch the updated agent
     let agent = conn
         .query_row(
@@ -525,6 +670,8 @@ pub async fn delete_agent(db: State<'_, AgentDb>, id: i64) -> Result<(), String>
 
     conn.execute("DELETE FROM agents WHERE id = ?1", params![id])
         .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM agent_versions WHERE agent_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
 
     Ok(())
 }
@@ -560,6 +707,93 @@ pub async fn get_agent(db: State<'_, AgentDb>, id: i64) -> Result<Agent, String>
     Ok(agent)
 }
 
+/// List the stored versions of an agent, newest first
+#[tauri::command]
+pub async fn list_agent_versions(
+    db: State<'_, AgentDb>,
+    id: i64,
+) -> Result<Vec<AgentVersion>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, agent_id, version, name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks, note, created_at
+             FROM agent_versions WHERE agent_id = ?1 ORDER BY version DESC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let versions = stmt
+        .query_map(params![id], |row| {
+            Ok(AgentVersion {
+                id: Some(row.get(0)?),
+                agent_id: row.get(1)?,
+                version: row.get(2)?,
+                name: row.get(3)?,
+                icon: row.get(4)?,
+                system_prompt: row.get(5)?,
+                default_task: row.get(6)?,
+                model: row.get(7)?,
+                enable_file_read: row.get(8)?,
+                enable_file_write: row.get(9)?,
+                enable_network: row.get(10)?,
+                hooks: row.get(11)?,
+                note: row.get(12)?,
+                created_at: row.get(13)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(versions)
+}
+
+/// Roll an agent back to a previous version
+///
+/// The agent definition is restored from the stored version and recorded as a
+/// new version, so the history stays append-only.
+#[tauri::command]
+pub async fn rollback_agent(
+    db: State<'_, AgentDb>,
+    id: i64,
+    version: i64,
+) -> Result<Agent, String> {
+    {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+        let new_version = restore_agent_version(&conn, id, version)?;
+        info!(
+            "Rolled back agent {} to version {} (now version {})",
+            id, version, new_version
+        );
+    }
+
+    get_agent(db, id).await
+}
+
+/// Restore an agent's definition from a stored version, recorded as a new
+/// version
+///
+/// Returns the new version number.
+fn restore_agent_version(conn: &Connection, id: i64, version: i64) -> Result<i64, String> {
+    let updated = conn
+        .execute(
+            "UPDATE agents SET (name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks) =
+             (SELECT name, icon, system_prompt, default_task, model, enable_file_read, enable_file_write, enable_network, hooks
+              FROM agent_versions WHERE agent_id = ?1 AND version = ?2)
+             WHERE id = ?1 AND EXISTS (SELECT 1 FROM agent_versions WHERE agent_id = ?1 AND version = ?2)",
+            params![id, version],
+        )
+        .map_err(|e| e.to_string())?;
+
+    if updated == 0 {
+        return Err(format!("Version {} of agent {} not found", version, id));
+    }
+
+    let note = format!("Rolled back to version {}", version);
+    record_agent_version(conn, id, Some(&note)).map_err(|e| e.to_string())
+}
+
 /// List agent runs (optionally filtered by agent_id)
 #[tauri::command]
 pub async fn list_agent_runs(
@@ -569,10 +803,10 @@ pub async fn list_agent_runs(
     let conn = db.0.lock().map_err(|e| e.to_string())?;
 
     let query = if agent_id.is_some() {
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, agent_version, exit_code, exit_classification 
          FROM agent_runs WHERE agent_id = ?1 ORDER BY created_at DESC"
     } else {
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, agent_version, exit_code, exit_classification 
          FROM agent_runs ORDER BY created_at DESC"
     };
 
@@ -599,6 +833,11 @@ pub async fn list_agent_runs(
             process_started_at: row.get(10)?,
             created_at: row.get(11)?,
             completed_at: row.get(12)?,
+            agent_version: row.get(13)?,
+            exit_code: row.get(14)?,
+            exit_classification: row
+                .get::<_, Option<String>>(15)?
+                .and_then(|c| c.parse().ok()),
         })
     };
 
@@ -621,7 +860,7 @@ pub async fn get_agent_run(db: State<'_, AgentDb>, id: i64) -> Result<AgentRun,
 
     let run = conn
         .query_row(
-            "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+            "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, agent_version, exit_code, exit_classification 
              FROM agent_runs WHERE id = ?1",
             params![id],
             |row| {
@@ -639,6 +878,11 @@ pub async fn get_agent_run(db: State<'_, AgentDb>, id: i64) -> Result<AgentRun,
                     process_started_at: row.get(10)?,
                     created_at: row.get(11)?,
                     completed_at: row.get(12)?,
+                    agent_version: row.get(13)?,
+                    exit_code: row.get(14)?,
+                    exit_classification: row
+                        .get::<_, Option<String>>(15)?
+                        .and_then(|c| c.parse().ok()),
                 })
             },
         )
@@ -657,6 +901,97 @@ pub async fn get_agent_run_with_real_time_metrics(
     Ok(get_agent_run_with_metrics(run).await)
 }
 
+/// Run a finished agent run's task again with the same agent, project and model
+///
+/// Only runs whose exit classification is retryable (e.g. a rate limit or a
+/// crash, not a success or a cancel) can be retried.
+#[tauri::command]
+pub async fn retry_agent_run(
+    app: AppHandle,
+    run_id: i64,
+    db: State<'_, AgentDb>,
+    registry: State<'_, crate::process::ProcessRegistryState>,
+) -> Result<i64, String> {
+    let run = get_agent_run(db.clone(), run_id).await?;
+    match run.exit_classification {
+        None => return Err(format!("Agent run {} has not finished", run_id)),
+        Some(classification) if !classification.is_retryable() => {
+            return Err(format!(
+                "Agent run {} ended with {} and cannot be retried",
+                run_id, classification
+            ));
+        }
+        Some(_) => {}
+    }
+
+    info!("Retrying agent run {}", run_id);
+    execute_agent(
+        app,
+        run.agent_id,
+        run.project_path,
+        run.task,
+        Some(run.model),
+        db,
+        registry,
+    )
+    .await
+}
+
+/// Record how an interactive Claude session ended
+pub fn record_session_exit(
+    conn: &Connection,
+    session_id: Option<&str>,
+    project_path: &str,
+    model: &str,
+    report: &ExitReport,
+) -> SqliteResult<usize> {
+    conn.execute(
+        "INSERT INTO session_runs (session_id, project_path, model, exit_code, exit_classification)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            session_id,
+            project_path,
+            model,
+            report.exit_code,
+            report.classification.as_str()
+        ],
+    )
+}
+
+/// List how interactive Claude sessions ended (optionally filtered by project)
+#[tauri::command]
+pub async fn list_session_runs(
+    db: State<'_, AgentDb>,
+    project_path: Option<String>,
+) -> Result<Vec<SessionRun>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, session_id, project_path, model, exit_code, exit_classification, completed_at
+             FROM session_runs WHERE ?1 IS NULL OR project_path = ?1 ORDER BY completed_at DESC, id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let runs = stmt
+        .query_map(params![project_path], |row| {
+            Ok(SessionRun {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                project_path: row.get(2)?,
+                model: row.get(3)?,
+                exit_code: row.get(4)?,
+                exit_classification: row.get::<_, String>(5)?.parse().ok(),
+                completed_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(runs)
+}
+
 /// List agent runs with real-time metrics from JSONL
 #[tauri::command]
 pub async fn list_agent_runs_with_metrics(
@@ -686,6 +1021,10 @@ pub async fn execute_agent(
     registry: State<'_, crate::process::ProcessRegistryState>,
 ) -> Result<i64, String> {
     info!("Executing agent {} with task: {}", agent_id, task);
+    let project_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .to_string();
 
     // Get the agent from database
     let agent = get_agent(db.clone(), agent_id).await?;
@@ -718,8 +1057,8 @@ pub async fn execute_agent(
             let settings_content = serde_json::to_string_pretty(&settings)
                 .map_err(|e| format!("Failed to serialize settings: {}", e))?;
             
-            std::fs::write(&settings_path, settings_content)
-                .map_err(|e| format!("Failed to write settings.json: {}", e))?;
+            crate::utils::atomic_write::atomic_write(&settings_path, settings_content)
+                .map_err(|e| format!("Failed to write settings.json: {:#}", e))?;
             
             info!("Created settings.json with agent hooks at: {:?}", settings_path);
         } else {
@@ -727,12 +1066,20 @@ pub async fn execute_agent(
         }
     }
 
+    // Respect the concurrency limit from the runtime config; the slot is
+    // held until the run's process is registered
+    let _slot = reserve_agent_run_slot(
+        &registry.0,
+        crate::commands::runtime_config::runtime_config().max_concurrent_agent_runs,
+    )?;
+
     // Create a new run record
     let run_id = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let agent_version = current_agent_version(&conn, agent_id).map_err(|e| e.to_string())?;
         conn.execute(
-            "INSERT INTO agent_runs (agent_id, agent_name, agent_icon, task, model, project_path, session_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![agent_id, agent.name, agent.icon, task, execution_model, project_path, ""],
+            "INSERT INTO agent_runs (agent_id, agent_name, agent_icon, task, model, project_path, session_id, agent_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![agent_id, agent.name, agent.icon, task, execution_model, project_path, "", agent_version],
         )
         .map_err(|e| e.to_string())?;
         conn.last_insert_rowid()
@@ -747,6 +1094,7 @@ pub async fn execute_agent(
             return Err(e);
         }
     };
+    crate::claude_binary::check_launch_signature(&app, &claude_path)?;
 
     // Build arguments
     let args = vec![
@@ -778,14 +1126,57 @@ pub async fn execute_agent(
     ).await
 }
 
+/// Agent runs that passed the concurrency limit but aren't registered yet
+static PENDING_AGENT_RUNS: Mutex<usize> = Mutex::new(0);
+
+/// A place under the concurrency limit, given back when dropped
+struct AgentRunSlot {
+    counted: bool,
+}
+
+impl Drop for AgentRunSlot {
+    fn drop(&mut self) {
+        if self.counted {
+            if let Ok(mut pending) = PENDING_AGENT_RUNS.lock() {
+                *pending -= 1;
+            }
+        }
+    }
+}
+
+/// Reserve a place for a new agent run under `limit`
+///
+/// Counting the registered runs and the pending ones happens under one
+/// lock, so two runs started at once can't both take the last place.
+fn reserve_agent_run_slot(
+    registry: &crate::process::ProcessRegistry,
+    limit: Option<usize>,
+) -> Result<AgentRunSlot, String> {
+    let Some(limit) = limit else {
+        return Ok(AgentRunSlot { counted: false });
+    };
+
+    let mut pending = PENDING_AGENT_RUNS.lock().map_err(|e| e.to_string())?;
+    let running = registry.get_running_agent_processes()?.len() + *pending;
+    if running >= limit {
+        return Err(format!(
+            "{} agent runs are already running (limit {}). Wait for one to finish or raise the limit in settings.",
+            running, limit
+        ));
+    }
+    *pending += 1;
+    Ok(AgentRunSlot { counted: true })
+}
+
 /// Creates a system binary command for agent execution
 fn create_agent_system_command(
     claude_path: &str,
     args: Vec<String>,
     project_path: &str,
-) -> Command {
-    let mut cmd = create_command_with_env(claude_path);
-    
+    sandbox: Option<&SandboxGuard>,
+) -> Result<Command, String> {
+    let mut cmd = create_command_with_env(claude_path, sandbox)?;
+
     // Add all arguments
     for arg in args {
         cmd.arg(arg);
@@ -795,8 +1186,8 @@ fn create_agent_system_command(
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
-    
-    cmd
+
+    Ok(cmd)
 }
 
 /// Spawn agent using system binary command
@@ -813,12 +1204,25 @@ async fn spawn_agent_system(
     db: State<'_, AgentDb>,
     registry: State<'_, crate::process::ProcessRegistryState>,
 ) -> Result<i64, String> {
+    // Set up the sandbox the runtime config asks for; the monitor below
+    // tears it down once the run is over
+    let sandbox = {
+        let sandbox = crate::commands::runtime_config::runtime_config().agent_sandbox;
+        let claude_path = claude_path.clone();
+        let project_path = project_path.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            prepare_agent_sandbox(sandbox, &claude_path, &project_path, run_id)
+        })
+        .await
+        .map_err(|e| e.to_string())??
+    };
+
     // Build the command
-    let mut cmd = create_agent_system_command(&claude_path, args, &project_path);
+    let mut cmd = create_agent_system_command(&claude_path, args, &project_path, sandbox.as_ref())?;
 
     // Spawn the process
     info!("🚀 Spawning Claude system process...");
-    let mut child = cmd.spawn().map_err(|e| {
+    let mut child = crate::commands::secrets::spawn_claude(&mut cmd).map_err(|e| {
         error!("❌ Failed to spawn Claude process: {}", e);
         format!("Failed to spawn Claude: {}", e)
     })?;
@@ -850,21 +1254,21 @@ async fn spawn_agent_system(
     let stderr_reader = TokioBufReader::new(stderr);
 
     // Create variables we need for the spawned tasks
-    let app_dir = app
-        .path()
-        .app_data_dir()
+    let app_dir = crate::portable::app_data_dir(&app)
         .expect("Failed to get app data dir");
     let db_path = app_dir.join("agents.db");
 
     // Shared state for collecting session ID and live output
     let session_id = std::sync::Arc::new(Mutex::new(String::new()));
     let live_output = std::sync::Arc::new(Mutex::new(String::new()));
+    let output_signals = std::sync::Arc::new(Mutex::new(OutputSignals::default()));
     let start_time = std::time::Instant::now();
 
     // Spawn tasks to read stdout and stderr
     let app_handle = app.clone();
     let session_id_clone = session_id.clone();
     let live_output_clone = live_output.clone();
+    let output_signals_clone = output_signals.clone();
     let registry_clone = registry.0.clone();
     let first_output = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
     let first_output_clone = first_output.clone();
@@ -902,6 +1306,10 @@ async fn spawn_agent_system(
             // Also store in process registry for cross-session access
             let _ = registry_clone.append_live_output(run_id, &line);
 
+            if let Ok(mut signals) = output_signals_clone.lock() {
+                signals.observe(&line);
+            }
+
             // Extract session ID from JSONL output
             if let Ok(json) = serde_json::from_str::<JsonValue>(&line) {
                 // Claude Code uses "session_id" (underscore), not "sessionId"
@@ -950,6 +1358,7 @@ async fn spawn_agent_system(
     let app_handle_stderr = app.clone();
     let first_error = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
     let first_error_clone = first_error.clone();
+    let output_signals_stderr = output_signals.clone();
 
     let stderr_task = tokio::spawn(async move {
         info!("📖 Starting to read Claude stderr...");
@@ -966,6 +1375,9 @@ async fn spawn_agent_system(
             }
 
             error!("stderr[{}]: {}", error_count, line);
+            if let Ok(mut signals) = output_signals_stderr.lock() {
+                signals.observe(&line);
+            }
             // Emit error lines to the frontend with run_id for isolation
             let _ = app_handle_stderr.emit(&format!("agent-error:{}", run_id), &line);
             // Also emit to the generic event for backward compatibility
@@ -983,6 +1395,7 @@ async fn spawn_agent_system(
     });
 
     // Register the process in the registry for live output tracking (after stdout/stderr setup)
+    let webhook_agent_name = agent_name.clone();
     registry
         .0
         .register_process(
@@ -999,9 +1412,12 @@ async fn spawn_agent_system(
     info!("📋 Registered process in registry");
 
     let db_path_for_monitor = db_path.clone(); // Clone for the monitor task
+    let webhook_project_path = project_path.clone();
+    let registry_for_monitor = registry.0.clone();
 
     // Monitor process status and wait for completion
     tokio::spawn(async move {
+        let _sandbox = sandbox;
         info!("🕐 Starting process monitoring...");
 
         // Wait for first output with timeout
@@ -1028,35 +1444,34 @@ async fn spawn_agent_system(
                     "🔍 Process likely stuck waiting for input, attempting to kill PID: {}",
                     pid
                 );
-                let kill_result = std::process::Command::new("kill")
-                    .arg("-TERM")
-                    .arg(pid.to_string())
-                    .output();
-
-                match kill_result {
-                    Ok(output) if output.status.success() => {
-                        warn!("🔍 Successfully sent TERM signal to process");
-                    }
-                    Ok(_) => {
-                        warn!("🔍 Failed to kill process with TERM, trying KILL");
-                        let _ = std::process::Command::new("kill")
-                            .arg("-KILL")
-                            .arg(pid.to_string())
-                            .output();
-                    }
-                    Err(e) => {
-                        warn!("🔍 Error killing process: {}", e);
-                    }
+                match crate::windows::process_manager::process_manager()
+                    .kill_tree(pid)
+                    .await
+                {
+                    Ok(true) => warn!("🔍 Killed the stuck process tree"),
+                    Ok(false) => warn!("🔍 Process had already exited"),
+                    Err(e) => warn!("🔍 Error killing process: {}", e),
                 }
 
                 // Update database
+                let report = ExitReport::from_status(
+                    None,
+                    Some(Termination::Opcode),
+                    OutputSignals::default(),
+                );
                 if let Ok(conn) = Connection::open(&db_path_for_monitor) {
-                    let _ = conn.execute(
-                        "UPDATE agent_runs SET status = 'failed', completed_at = CURRENT_TIMESTAMP WHERE id = ?1",
-                        params![run_id],
-                    );
+                    let _ = record_run_exit(&conn, run_id, &report);
                 }
+                notify_run_exit(
+                    &app,
+                    RunExitEvent {
+                        run_id: Some(run_id),
+                        agent_name: Some(webhook_agent_name),
+                        ..RunExitEvent::new("agent_run", webhook_project_path, report)
+                    },
+                );
 
+                let _ = app.emit(&format!("agent-exit:{}", run_id), &report);
                 let _ = app.emit("agent-complete", false);
                 let _ = app.emit(&format!("agent-complete:{}", run_id), false);
                 return;
@@ -1080,14 +1495,27 @@ async fn spawn_agent_system(
             String::new()
         };
 
-        // Wait for process completion and update status
-        info!("✅ Claude process execution monitoring complete");
+        // Wait for process completion and classify how it ended
+        let status = registry_for_monitor
+            .wait_for_exit(run_id, std::time::Duration::from_secs(5))
+            .await;
+        let signals = output_signals.lock().map(|s| *s).unwrap_or_default();
+        let report = ExitReport::from_status(
+            status.as_ref(),
+            registry_for_monitor.take_termination(run_id),
+            signals,
+        );
+        info!(
+            "✅ Claude process execution monitoring complete: {} (exit code {:?})",
+            report.classification, report.exit_code
+        );
 
-        // Update the run record with session ID and mark as completed - open a new connection
+        // Update the run record with session ID and its outcome - open a new connection
         if let Ok(conn) = Connection::open(&db_path_for_monitor) {
             info!("🔄 Updating database with extracted session ID: {}", extracted_session_id);
+            let _ = record_run_exit(&conn, run_id, &report);
             match conn.execute(
-                "UPDATE agent_runs SET session_id = ?1, status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                "UPDATE agent_runs SET session_id = ?1 WHERE id = ?2",
                 params![extracted_session_id, run_id],
             ) {
                 Ok(rows_affected) => {
@@ -1107,13 +1535,39 @@ async fn spawn_agent_system(
 
         // Cleanup will be handled by the cleanup_finished_processes function
 
-        let _ = app.emit("agent-complete", true);
-        let _ = app.emit(&format!("agent-complete:{}", run_id), true);
+        notify_run_exit(
+            &app,
+            RunExitEvent {
+                run_id: Some(run_id),
+                agent_name: Some(webhook_agent_name),
+                session_id: Some(extracted_session_id).filter(|id| !id.is_empty()),
+                ..RunExitEvent::new("agent_run", webhook_project_path, report)
+            },
+        );
+
+        let success = report.classification == ExitClassification::Success;
+        let _ = app.emit(&format!("agent-exit:{}", run_id), &report);
+        let _ = app.emit("agent-complete", success);
+        let _ = app.emit(&format!("agent-complete:{}", run_id), success);
     });
 
     Ok(run_id)
 }
 
+/// Store how a run ended, unless it was already finalized (e.g. by a cancel)
+fn record_run_exit(conn: &Connection, run_id: i64, report: &ExitReport) -> SqliteResult<usize> {
+    conn.execute(
+        "UPDATE agent_runs SET status = ?1, exit_code = ?2, exit_classification = ?3, completed_at = CURRENT_TIMESTAMP
+         WHERE id = ?4 AND status = 'running'",
+        params![
+            report.classification.run_status(),
+            report.exit_code,
+            report.classification.as_str(),
+            run_id
+        ],
+    )
+}
+
 /// List all currently running agent sessions
 #[tauri::command]
 pub async fn list_running_sessions(
@@ -1124,7 +1578,7 @@ pub async fn list_running_sessions(
 
     // First get all running sessions from the database
     let mut stmt = conn.prepare(
-        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at 
+        "SELECT id, agent_id, agent_name, agent_icon, task, model, project_path, session_id, status, pid, process_started_at, created_at, completed_at, agent_version, exit_code, exit_classification 
          FROM agent_runs WHERE status = 'running' ORDER BY process_started_at DESC"
     ).map_err(|e| e.to_string())?;
 
@@ -1150,6 +1604,11 @@ pub async fn list_running_sessions(
                 process_started_at: row.get(10)?,
                 created_at: row.get(11)?,
                 completed_at: row.get(12)?,
+                agent_version: row.get(13)?,
+                exit_code: row.get(14)?,
+                exit_classification: row
+                    .get::<_, Option<String>>(15)?
+                    .and_then(|c| c.parse().ok()),
             })
         })
         .map_err(|e| e.to_string())?
@@ -1189,6 +1648,7 @@ pub async fn kill_agent_session(
     run_id: i64,
 ) -> Result<bool, String> {
     info!("Attempting to kill agent session {}", run_id);
+    registry.0.mark_termination(run_id, Termination::UserRequested)?;
 
     // First try to kill using the process registry
     let killed_via_registry = match registry.0.kill_process(run_id).await {
@@ -1208,6 +1668,7 @@ pub async fn kill_agent_session(
     };
 
     // If registry kill didn't work, try fallback with PID from database
+    let mut killed_via_pid = false;
     if !killed_via_registry {
         let pid_result = {
             let conn = db.0.lock().map_err(|e| e.to_string())?;
@@ -1221,15 +1682,20 @@ pub async fn kill_agent_session(
 
         if let Some(pid) = pid_result {
             info!("Attempting fallback kill for PID {} from database", pid);
-            let _ = registry.0.kill_process_by_pid(run_id, pid as u32)?;
+            killed_via_pid = registry.0.kill_process_by_pid(run_id, pid as u32).await?;
         }
     }
 
+    // A process that is still running must not be classified as cancelled when it exits
+    if !killed_via_registry && !killed_via_pid {
+        registry.0.take_termination(run_id);
+    }
+
     // Update the database to mark as cancelled
     let conn = db.0.lock().map_err(|e| e.to_string())?;
     let updated = conn.execute(
-        "UPDATE agent_runs SET status = 'cancelled', completed_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'running'",
-        params![run_id],
+        "UPDATE agent_runs SET status = 'cancelled', exit_classification = ?2, completed_at = CURRENT_TIMESTAMP WHERE id = ?1 AND status = 'running'",
+        params![run_id, ExitClassification::UserCancelled.as_str()],
     ).map_err(|e| e.to_string())?;
 
     // Emit cancellation event with run_id for proper isolation
@@ -1238,6 +1704,51 @@ pub async fn kill_agent_session(
     Ok(updated > 0 || killed_via_registry)
 }
 
+/// Kill every running agent run and Claude session, returning how many were
+/// stopped
+///
+/// May need Windows Hello first (see
+/// [`user_presence`](crate::commands::user_presence)).
+#[tauri::command]
+pub async fn kill_all_sessions(
+    app: AppHandle,
+    db: State<'_, AgentDb>,
+    registry: State<'_, crate::process::ProcessRegistryState>,
+) -> Result<usize, String> {
+    crate::commands::user_presence::confirm_operation(
+        &db,
+        crate::windows::user_presence::ProtectedOperation::KillAllSessions,
+    )
+    .await?;
+
+    let processes = registry.0.get_running_processes()?;
+    info!("Killing all {} running sessions", processes.len());
+    let mut killed = 0;
+    for process in processes {
+        let stopped = match process.process_type {
+            crate::process::ProcessType::AgentRun { .. } => {
+                kill_agent_session(app.clone(), db.clone(), registry.clone(), process.run_id).await
+            }
+            crate::process::ProcessType::ClaudeSession { .. } => {
+                registry
+                    .0
+                    .mark_termination(process.run_id, Termination::UserRequested)?;
+                let result = registry.0.kill_process(process.run_id).await;
+                if !matches!(result, Ok(true)) {
+                    registry.0.take_termination(process.run_id);
+                }
+                result
+            }
+        };
+        match stopped {
+            Ok(true) => killed += 1,
+            Ok(false) => {}
+            Err(e) => warn!("Failed to kill process {}: {}", process.run_id, e),
+        }
+    }
+    Ok(killed)
+}
+
 /// Get the status of a specific agent session
 #[tauri::command]
 pub async fn get_session_status(
@@ -1260,63 +1771,48 @@ pub async fn get_session_status(
 /// Cleanup finished processes and update their status
 #[tauri::command]
 pub async fn cleanup_finished_processes(db: State<'_, AgentDb>) -> Result<Vec<i64>, String> {
-    let conn = db.0.lock().map_err(|e| e.to_string())?;
-
     // Get all running processes
-    let mut stmt = conn
-        .prepare("SELECT id, pid FROM agent_runs WHERE status = 'running' AND pid IS NOT NULL")
-        .map_err(|e| e.to_string())?;
+    let running_processes = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT id, pid FROM agent_runs WHERE status = 'running' AND pid IS NOT NULL")
+            .map_err(|e| e.to_string())?;
 
-    let running_processes = stmt
-        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        rows
+    };
 
-    drop(stmt);
+    // Check which are still running without holding the database lock
+    let mut finished = Vec::new();
+    for (run_id, pid) in running_processes {
+        match crate::windows::process_manager::process_manager()
+            .is_running(pid as u32)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => finished.push((run_id, pid)),
+            Err(e) => warn!("Failed to check whether PID {} is running: {}", pid, e),
+        }
+    }
 
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
     let mut cleaned_up = Vec::new();
 
-    for (run_id, pid) in running_processes {
-        // Check if the process is still running
-        let is_running = if cfg!(target_os = "windows") {
-            // On Windows, use tasklist to check if process exists
-            match std::process::Command::new("tasklist")
-                .args(["/FI", &format!("PID eq {}", pid)])
-                .args(["/FO", "CSV"])
-                .output()
-            {
-                Ok(output) => {
-                    let output_str = String::from_utf8_lossy(&output.stdout);
-                    output_str.lines().count() > 1 // Header + process line if exists
-                }
-                Err(_) => false,
-            }
-        } else {
-            // On Unix-like systems, use kill -0 to check if process exists
-            match std::process::Command::new("kill")
-                .args(["-0", &pid.to_string()])
-                .output()
-            {
-                Ok(output) => output.status.success(),
-                Err(_) => false,
-            }
-        };
-
-        if !is_running {
-            // Process has finished, update status
-            let updated = conn.execute(
-                "UPDATE agent_runs SET status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = ?1",
-                params![run_id],
-            ).map_err(|e| e.to_string())?;
+    for (run_id, pid) in finished {
+        // Process has finished without its exit status being collected
+        let report = ExitReport::from_status(None, None, OutputSignals::default());
+        let updated = record_run_exit(&conn, run_id, &report).map_err(|e| e.to_string())?;
 
-            if updated > 0 {
-                cleaned_up.push(run_id);
-                info!(
-                    "Marked agent run {} as completed (PID {} no longer running)",
-                    run_id, pid
-                );
-            }
+        if updated > 0 {
+            cleaned_up.push(run_id);
+            info!(
+                "Marked agent run {} as {} (PID {} no longer running)",
+                run_id, report.classification, pid
+            );
         }
     }
 
@@ -1470,8 +1966,7 @@ pub async fn stream_session_output(
             // Check if the session is still running by querying the database
             // If the session is no longer running, stop streaming
             if let Ok(conn) = rusqlite::Connection::open(
-                app.path()
-                    .app_data_dir()
+                crate::portable::app_data_dir(&app)
                     .expect("Failed to get app data dir")
                     .join("agents.db"),
             ) {
@@ -1544,11 +2039,35 @@ pub async fn export_agent_to_file(
     id: i64,
     file_path: String,
 ) -> Result<(), String> {
+    if cfg!(target_os = "windows") {
+        if let Some(file_name) = std::path::Path::new(&file_path).file_name() {
+            crate::utils::paths::validate_windows_filename(&file_name.to_string_lossy())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
     // Get the JSON data
     let json_data = export_agent(db, id).await?;
+    crate::utils::disk_space::ensure_free_space(&file_path, json_data.len() as u64)
+        .map_err(|e| e.to_string())?;
 
     // Write to file
-    std::fs::write(&file_path, json_data).map_err(|e| format!("Failed to write file: {}", e))?;
+    std::fs::write(&file_path, &json_data).map_err(|e| format!("Failed to write file: {}", e))?;
+
+    // Remember which agent the file came from without a sidecar file; only
+    // NTFS keeps this, so failing to write it is fine
+    use crate::utils::hashing::{hash_bytes, HashAlgorithm};
+    let metadata = serde_json::json!({
+        "agent_id": id,
+        "sha256": hash_bytes(json_data.as_bytes(), HashAlgorithm::Sha256),
+    });
+    if let Err(e) = crate::utils::ads::write_ads(
+        &file_path,
+        crate::utils::ads::EXPORT_METADATA_STREAM,
+        metadata.to_string(),
+    ) {
+        debug!("Not recording export metadata: {:#}", e);
+    }
 
     Ok(())
 }
@@ -1574,8 +2093,10 @@ pub async fn get_claude_binary_path(db: State<'_, AgentDb>) -> Result<Option<Str
 pub async fn set_claude_binary_path(db: State<'_, AgentDb>, path: String) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
 
-    // Validate that the path exists and is executable
-    let path_buf = std::path::PathBuf::from(&path);
+    // Validate that the path exists and is executable. The path is stored as
+    // entered, so `%LOCALAPPDATA%\...` or `~/...` keep working after a
+    // profile move; it is expanded when used
+    let path_buf = crate::utils::paths::expand_env_path(&path);
     if !path_buf.exists() {
         return Err(format!("File does not exist: {}", path));
     }
@@ -1619,12 +2140,18 @@ pub async fn list_claude_installations(
 
 /// Helper function to create a tokio Command with proper environment variables
 /// This ensures commands like Claude can find Node.js and other dependencies
-fn create_command_with_env(program: &str) -> Command {
+fn create_command_with_env(
+    program: &str,
+    sandbox: Option<&SandboxGuard>,
+) -> Result<Command, String> {
     // Convert std::process::Command to tokio::process::Command
     let _std_cmd = crate::claude_binary::create_command_with_env(program);
 
-    // Create a new tokio Command from the program path
-    let mut tokio_cmd = Command::new(program);
+    // Create a new tokio Command for Claude at the program path
+    let mut tokio_cmd = match sandbox {
+        Some(sandbox) => sandbox.command(program)?,
+        None => crate::commands::secrets::claude_command(program),
+    };
 
     // Copy over all environment variables from the std::process::Command
     // This is a workaround since we can't directly convert between the two types
@@ -1672,7 +2199,11 @@ fn create_command_with_env(program: &str) -> Command {
         tokio_cmd.env("PATH", "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin");
     }
 
-    tokio_cmd
+    // Strip inherited variables that are known to break or leak into children
+    // and point children at the custom CA bundle, if one is configured
+    crate::commands::environment::apply_child_env(tokio_cmd.as_std_mut());
+
+    Ok(tokio_cmd)
 }
 
 /// Import an agent from JSON data
@@ -1724,6 +2255,7 @@ pub async fn import_agent(db: State<'_, AgentDb>, json_data: String) -> Result<A
     .map_err(|e| format!("Failed to create agent: {}", e))?;
 
     let id = conn.last_insert_rowid();
+    record_agent_version(&conn, id, Some("Imported")).map_err(|e| e.to_string())?;
 
     // Fetch the created agent
     let agent = conn
@@ -1757,7 +2289,18 @@ pub async fn import_agent(db: State<'_, AgentDb>, json_data: String) -> Result<A
 pub async fn import_agent_from_file(
     db: State<'_, AgentDb>,
     file_path: String,
+    unblock: Option<bool>,
 ) -> Result<Agent, String> {
+    // A downloaded definition is refused until the user agrees to unblock it
+    #[cfg(target_os = "windows")]
+    crate::windows::mark_of_the_web::ensure_unblocked(
+        std::path::Path::new(&file_path),
+        unblock.unwrap_or(false),
+    )
+    .map_err(|e| format!("{:#}", e))?;
+    #[cfg(not(target_os = "windows"))]
+    let _ = unblock;
+
     // Read the file
     let mut json_data =
         std::fs::read_to_string(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
@@ -1802,7 +2345,7 @@ struct GitHubApiResponse {
 pub async fn fetch_github_agents() -> Result<Vec<GitHubAgentFile>, String> {
     info!("Fetching agents from GitHub repository...");
 
-    let client = reqwest::Client::new();
+    let client = crate::commands::certificates::http_client()?;
     let url = "https://api.github.com/repos/getAsterisk/opcode/contents/cc_agents";
 
     let response = client
@@ -1848,7 +2391,7 @@ pub async fn fetch_github_agents() -> Result<Vec<GitHubAgentFile>, String> {
 pub async fn fetch_github_agent_content(download_url: String) -> Result<AgentExport, String> {
     info!("Fetching agent content from: {}", download_url);
 
-    let client = reqwest::Client::new();
+    let client = crate::commands::certificates::http_client()?;
     let response = client
         .get(&download_url)
         .header("Accept", "application/json")
@@ -1967,3 +2510,107 @@ pub async fn load_agent_session_history(
         Err(format!("Session file not found: {}", session_id))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    fn insert_agent(conn: &Connection, name: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO agents (name, icon, system_prompt) VALUES (?1, 'bot', 'Be helpful')",
+            params![name],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn agent_name(conn: &Connection, id: i64) -> String {
+        conn.query_row(
+            "SELECT name FROM agents WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_init_schema_records_initial_versions() {
+        let conn = test_db();
+        let id = insert_agent(&conn, "reviewer");
+        assert_eq!(current_agent_version(&conn, id).unwrap(), 0);
+
+        // Agents that predate versioning get version 1 on the next start
+        init_schema(&conn).unwrap();
+        assert_eq!(current_agent_version(&conn, id).unwrap(), 1);
+        init_schema(&conn).unwrap();
+        assert_eq!(current_agent_version(&conn, id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_record_agent_version() {
+        let conn = test_db();
+        let id = insert_agent(&conn, "reviewer");
+        assert_eq!(record_agent_version(&conn, id, None).unwrap(), 1);
+
+        conn.execute(
+            "UPDATE agents SET name = 'strict reviewer' WHERE id = ?1",
+            params![id],
+        )
+        .unwrap();
+        assert_eq!(
+            record_agent_version(&conn, id, Some("Stricter")).unwrap(),
+            2
+        );
+
+        let (name, note): (String, Option<String>) = conn
+            .query_row(
+                "SELECT name, note FROM agent_versions WHERE agent_id = ?1 AND version = 2",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(name, "strict reviewer");
+        assert_eq!(note.as_deref(), Some("Stricter"));
+    }
+
+    #[test]
+    fn test_restore_agent_version() {
+        let conn = test_db();
+        let id = insert_agent(&conn, "reviewer");
+        record_agent_version(&conn, id, None).unwrap();
+        conn.execute(
+            "UPDATE agents SET name = 'strict reviewer' WHERE id = ?1",
+            params![id],
+        )
+        .unwrap();
+        record_agent_version(&conn, id, None).unwrap();
+
+        assert_eq!(restore_agent_version(&conn, id, 1).unwrap(), 3);
+        assert_eq!(agent_name(&conn, id), "reviewer");
+
+        assert!(restore_agent_version(&conn, id, 7).is_err());
+        assert_eq!(agent_name(&conn, id), "reviewer");
+        assert_eq!(current_agent_version(&conn, id).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_reserve_agent_run_slot() {
+        let registry = crate::process::ProcessRegistry::new();
+
+        let first = reserve_agent_run_slot(&registry, Some(2)).unwrap();
+        let second = reserve_agent_run_slot(&registry, Some(2)).unwrap();
+        assert!(reserve_agent_run_slot(&registry, Some(2)).is_err());
+        assert!(reserve_agent_run_slot(&registry, None).is_ok());
+
+        drop(first);
+        let third = reserve_agent_run_slot(&registry, Some(2)).unwrap();
+        assert!(reserve_agent_run_slot(&registry, Some(2)).is_err());
+        drop((second, third));
+    }
+}
@@ -0,0 +1,239 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::State;
+
+use crate::commands::agents::AgentDb;
+
+/// Endpoint used to verify that the configured CA completes the TLS chain
+const VALIDATION_URL: &str = "https://api.anthropic.com";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CaCertificateSettings {
+    /// Path to a PEM file containing one or more root certificates
+    pub bundle_path: Option<String>,
+    pub enabled: bool,
+}
+
+/// Outcome of testing a CA bundle against the Anthropic API
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaCertificateTestResult {
+    pub success: bool,
+    /// Number of certificates parsed from the bundle
+    pub certificate_count: usize,
+    /// HTTP status returned once the TLS handshake succeeded
+    pub status: Option<u16>,
+    /// Proxy the request was routed through, if any
+    pub proxy: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Bundle path currently injected into spawned processes and the HTTP client
+static ACTIVE_BUNDLE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Get custom CA certificate settings from the database
+#[tauri::command]
+pub async fn get_ca_certificate_settings(
+    db: State<'_, AgentDb>,
+) -> Result<CaCertificateSettings, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_ca_certificate_settings(&conn))
+}
+
+/// Save custom CA certificate settings to the database
+#[tauri::command]
+pub async fn save_ca_certificate_settings(
+    db: State<'_, AgentDb>,
+    settings: CaCertificateSettings,
+) -> Result<(), String> {
+    // Refuse to enable a bundle that cannot be loaded
+    if settings.enabled {
+        let path = settings
+            .bundle_path
+            .as_deref()
+            .filter(|p| !p.is_empty())
+            .ok_or("A CA bundle path is required when custom certificates are enabled")?;
+        load_bundle(path)?;
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let values = vec![
+        ("ca_cert_enabled", settings.enabled.to_string()),
+        ("ca_cert_bundle", settings.bundle_path.clone().unwrap_or_default()),
+    ];
+
+    for (key, value) in values {
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )
+        .map_err(|e| format!("Failed to save {}: {}", key, e))?;
+    }
+
+    apply_ca_certificate_settings(&settings);
+
+    Ok(())
+}
+
+/// Test a CA bundle by completing a TLS handshake with the Anthropic API
+///
+/// The request goes through the configured proxy (if any) and trusts only the
+/// certificates in the bundle, so a success means the bundle alone is enough
+/// to validate the chain presented by a TLS-intercepting proxy.
+#[tauri::command]
+pub async fn test_ca_certificate(bundle_path: String) -> Result<CaCertificateTestResult, String> {
+    log::info!("Testing CA bundle {} against {}", bundle_path, VALIDATION_URL);
+
+    let certificates = load_bundle(&bundle_path)?;
+    let certificate_count = certificates.len();
+
+    let proxy = crate::commands::proxy::proxy_var("HTTPS_PROXY")
+        .or_else(|| crate::commands::proxy::proxy_var("ALL_PROXY"))
+        .filter(|p| !p.is_empty());
+
+    let mut builder = crate::commands::proxy::apply_client_proxy(
+        reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .timeout(Duration::from_secs(15)),
+    )?;
+    for certificate in certificates {
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    let result = match client.get(VALIDATION_URL).send().await {
+        Ok(response) => CaCertificateTestResult {
+            success: true,
+            certificate_count,
+            status: Some(response.status().as_u16()),
+            proxy,
+            error: None,
+        },
+        Err(e) => {
+            // Include the source chain so the actual TLS failure is visible
+            let mut message = e.to_string();
+            let mut source = std::error::Error::source(&e);
+            while let Some(inner) = source {
+                message.push_str(&format!(": {}", inner));
+                source = inner.source();
+            }
+            CaCertificateTestResult {
+                success: false,
+                certificate_count,
+                status: None,
+                proxy,
+                error: Some(message),
+            }
+        }
+    };
+
+    log::info!("CA bundle test result: success={}", result.success);
+    Ok(result)
+}
+
+/// Read the CA settings from the database, falling back to defaults
+pub fn load_ca_certificate_settings(conn: &Connection) -> CaCertificateSettings {
+    let mut settings = CaCertificateSettings::default();
+
+    let keys = vec![("ca_cert_enabled", "enabled"), ("ca_cert_bundle", "bundle_path")];
+
+    for (db_key, field) in keys {
+        if let Ok(value) = conn.query_row(
+            "SELECT value FROM app_settings WHERE key = ?1",
+            params![db_key],
+            |row| row.get::<_, String>(0),
+        ) {
+            match field {
+                "enabled" => settings.enabled = value == "true",
+                "bundle_path" => settings.bundle_path = Some(value).filter(|s| !s.is_empty()),
+                _ => {}
+            }
+        }
+    }
+
+    settings
+}
+
+/// Make the given settings the ones used for spawned processes and HTTP requests
+pub fn apply_ca_certificate_settings(settings: &CaCertificateSettings) {
+    let bundle = settings
+        .bundle_path
+        .clone()
+        .filter(|p| settings.enabled && !p.is_empty());
+
+    log::info!("Applying CA certificate settings: bundle={:?}", bundle);
+
+    if let Ok(mut active) = ACTIVE_BUNDLE.write() {
+        *active = bundle;
+    }
+}
+
+/// Whether a custom CA bundle is configured, which takes the place of
+/// inherited TLS interceptor variables
+pub fn has_custom_ca() -> bool {
+    ACTIVE_BUNDLE.read().is_ok_and(|bundle| bundle.is_some())
+}
+
+/// Environment variables pointing child processes at the configured CA bundle
+///
+/// `NODE_EXTRA_CA_CERTS` adds the bundle to Node's built-in roots, while
+/// `SSL_CERT_FILE` covers OpenSSL-based tools spawned by Claude.
+pub fn ca_bundle_env() -> Vec<(&'static str, String)> {
+    match ACTIVE_BUNDLE.read().ok().and_then(|b| b.clone()) {
+        Some(path) => vec![("NODE_EXTRA_CA_CERTS", path.clone()), ("SSL_CERT_FILE", path)],
+        None => Vec::new(),
+    }
+}
+
+/// Create an HTTP client that uses the configured proxy and also trusts the
+/// configured CA bundle
+pub fn http_client() -> Result<reqwest::Client, String> {
+    let mut builder = crate::commands::proxy::apply_client_proxy(reqwest::Client::builder())?;
+
+    if let Some(path) = ACTIVE_BUNDLE.read().ok().and_then(|b| b.clone()) {
+        for certificate in load_bundle(&path)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// Load all certificates from a PEM bundle
+fn load_bundle(path: &str) -> Result<Vec<reqwest::Certificate>, String> {
+    let pem = std::fs::read(path)
+        .map_err(|e| format!("Failed to read CA bundle {}: {}", path, e))?;
+
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("Failed to parse CA bundle {}: {}", path, e))?;
+
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in CA bundle {}", path));
+    }
+
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.pem");
+        assert!(load_bundle(&missing.to_string_lossy())
+            .unwrap_err()
+            .starts_with("Failed to read CA bundle"));
+
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+        assert!(load_bundle(&empty.to_string_lossy()).is_err());
+    }
+}
@@ -37,6 +37,10 @@ pub struct Project {
     pub created_at: u64,
     /// Unix timestamp of the most recent session (if any)
     pub most_recent_session: Option<u64>,
+    /// IDs of other project directories for the same folder, reached through
+    /// a symlink, junction or mapped drive
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alias_ids: Vec<String>,
 }
 
 /// Represents a session with its metadata
@@ -228,8 +232,8 @@ fn create_command_with_env(program: &str) -> Command {
     // Convert std::process::Command to tokio::process::Command
     let _std_cmd = crate::claude_binary::create_command_with_env(program);
 
-    // Create a new tokio Command from the program path
-    let mut tokio_cmd = Command::new(program);
+    // Create a new tokio Command for Claude at the program path
+    let mut tokio_cmd = crate::commands::secrets::claude_command(program);
 
     // Copy over all environment variables
     for (key, value) in std::env::vars() {
@@ -276,6 +280,10 @@ fn create_command_with_env(program: &str) -> Command {
         }
     }
 
+    // Strip inherited variables that are known to break or leak into children
+    // and point children at the custom CA bundle, if one is configured
+    crate::commands::environment::apply_child_env(tokio_cmd.as_std_mut());
+
     tokio_cmd
 }
 
@@ -397,10 +405,34 @@ pub async fn list_projects() -> Result<Vec<Project>, String> {
                 sessions,
                 created_at,
                 most_recent_session,
+                alias_ids: Vec::new(),
             });
         }
     }
 
+    // Resolving a path on a dead share would hang the listing, so each
+    // share is probed once and its projects are left unresolved if it's down
+    let share_available = std::cell::RefCell::new(std::collections::HashMap::new());
+    let mut projects = collapse_aliased_projects(projects, |path| {
+        if let Some(share) = crate::utils::paths::unc_share_root(path) {
+            let available = *share_available
+                .borrow_mut()
+                .entry(share)
+                .or_insert_with(|| {
+                    crate::utils::paths::check_unc_available(
+                        path,
+                        crate::utils::paths::UNC_PROBE_TIMEOUT,
+                    )
+                    .map_err(|e| log::warn!("{}", e))
+                    .is_ok()
+                });
+            if !available {
+                return None;
+            }
+        }
+        cached_canonical_path(path)
+    });
+
     // Sort projects by most recent session activity, then by creation time
     projects.sort_by(|a, b| {
         // First compare by most recent session
@@ -416,14 +448,133 @@ pub async fn list_projects() -> Result<Vec<Project>, String> {
     Ok(projects)
 }
 
+/// How long a resolved project path is reused before it is resolved again
+const CANONICAL_PATH_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Project paths resolved by earlier `list_projects` calls, with when they
+/// were resolved
+static CANONICAL_PATHS: std::sync::OnceLock<
+    std::sync::Mutex<std::collections::HashMap<String, (std::time::Instant, Option<String>)>>,
+> = std::sync::OnceLock::new();
+
+/// Resolve a project path, reusing the result of a recent call
+///
+/// Resolving follows every link in the path, so it isn't repeated for each
+/// project on every listing.
+fn cached_canonical_path(path: &str) -> Option<String> {
+    let cache = CANONICAL_PATHS.get_or_init(Default::default);
+    if let Ok(cache) = cache.lock() {
+        if let Some((resolved_at, canonical)) = cache.get(path) {
+            if resolved_at.elapsed() < CANONICAL_PATH_TTL {
+                return canonical.clone();
+            }
+        }
+    }
+
+    let canonical = crate::utils::paths::canonicalize_path(path)
+        .ok()
+        .map(|p| p.to_string_lossy().to_string());
+    if let Ok(mut cache) = cache.lock() {
+        cache.retain(|_, (resolved_at, _)| resolved_at.elapsed() < CANONICAL_PATH_TTL);
+        cache.insert(
+            path.to_string(),
+            (std::time::Instant::now(), canonical.clone()),
+        );
+    }
+    canonical
+}
+
+/// Merge projects whose paths resolve to the same folder
+///
+/// The same folder opened through a junction, symlink or mapped drive gets
+/// its own directory under `~/.claude/projects`. The most recently used entry
+/// is kept, with its path resolved by `canonicalize`, the others listed in
+/// `alias_ids` and the sessions of all of them. Paths that can't be resolved
+/// (e.g. deleted folders) are kept as they are.
+fn collapse_aliased_projects(
+    projects: Vec<Project>,
+    canonicalize: impl Fn(&str) -> Option<String>,
+) -> Vec<Project> {
+    let mut collapsed: Vec<Project> = Vec::with_capacity(projects.len());
+    // Indices into `collapsed` of projects whose path could be resolved, by
+    // the resolved path
+    let mut resolved: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+    for mut project in projects {
+        let Some(canonical) = canonicalize(&project.path) else {
+            collapsed.push(project);
+            continue;
+        };
+        project.path = canonical.clone();
+
+        // Canonical paths can still differ in case where the file system
+        // doesn't care, e.g. a drive letter typed in lower case
+        let key = if cfg!(any(target_os = "windows", target_os = "macos"))
+            && !crate::utils::paths::is_case_sensitive_dir(&canonical)
+        {
+            canonical.to_lowercase()
+        } else {
+            canonical.clone()
+        };
+        match resolved.get(&key).copied() {
+            Some(index) => {
+                let existing = &mut collapsed[index];
+                let newer = (project.most_recent_session, project.created_at)
+                    > (existing.most_recent_session, existing.created_at);
+                log::info!(
+                    "Projects {} and {} refer to the same folder {}",
+                    existing.id,
+                    project.id,
+                    canonical
+                );
+                if newer {
+                    std::mem::swap(existing, &mut project);
+                }
+                existing.alias_ids.append(&mut project.alias_ids);
+                existing.alias_ids.push(project.id);
+                existing.sessions.append(&mut project.sessions);
+                existing.most_recent_session = existing
+                    .most_recent_session
+                    .max(project.most_recent_session);
+            }
+            None => {
+                resolved.insert(key, collapsed.len());
+                collapsed.push(project);
+            }
+        }
+    }
+    collapsed
+}
+
+/// Lists what would stop a location from working, e.g. before creating a
+/// project there, so the UI can explain it up front
+#[tauri::command]
+pub async fn validate_path_for_use(
+    path: String,
+    usage: crate::utils::path_validation::PathUsage,
+) -> Result<Vec<crate::utils::path_validation::PathProblem>, String> {
+    // Paths that don't parse are still validated so the UI gets the reason
+    let path = crate::utils::paths::parse_user_path(&path)
+        .unwrap_or_else(|_| crate::utils::paths::expand_env_path(&path));
+    tokio::task::spawn_blocking(move || {
+        crate::utils::path_validation::validate_path_for_use(&path, usage)
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
 /// Creates a new project for the given directory path
 #[tauri::command]
 pub async fn create_project(path: String) -> Result<Project, String> {
     log::info!("Creating project for path: {}", path);
+    let path = crate::utils::paths::parse_user_path(&path).map_err(|e| e.to_string())?;
+    crate::utils::paths::check_unc_available(&path, crate::utils::paths::UNC_PROBE_TIMEOUT)
+        .map_err(|e| e.to_string())?;
+    let path = path.to_string_lossy().to_string();
     
     // Encode the path to create a project ID
-    let project_id = path.replace('/', "-");
-    
+    let project_id = project_id_for(&path);
+
     // Get claude directory
     let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
     let projects_dir = claude_dir.join("projects");
@@ -460,16 +611,125 @@ pub async fn create_project(path: String) -> Result<Project, String> {
         sessions: Vec::new(),
         created_at,
         most_recent_session: None,
+        alias_ids: Vec::new(),
+    })
+}
+
+/// The id of the project for `path`, i.e. the name of its directory under
+/// `~/.claude/projects`
+fn project_id_for(path: &str) -> String {
+    path.replace('/', "-")
+}
+
+/// Moves a project's folder to `destination`, e.g. to another drive, along
+/// with its directory of sessions
+///
+/// Only folders of known projects can be moved. Moves to another drive copy
+/// and verify every file before the original is removed, emitting
+/// `move-progress` events. Transcripts keep the working directory they were
+/// recorded in.
+#[tauri::command]
+pub async fn relocate_project(
+    app: AppHandle,
+    project_id: String,
+    destination: String,
+) -> Result<crate::utils::move_path::MoveReport, String> {
+    if project_id.is_empty() || project_id.contains(['/', '\\']) || project_id.starts_with('.') {
+        return Err(format!("Invalid project id: {}", project_id));
+    }
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let projects_dir = claude_dir.join("projects");
+    let project_dir = projects_dir.join(&project_id);
+    if !project_dir.is_dir() {
+        return Err(format!("Project directory not found: {}", project_id));
+    }
+
+    let source = PathBuf::from(get_project_path_from_sessions(&project_dir)?);
+    if !source.is_dir() {
+        return Err(format!("Project folder not found: {}", source.display()));
+    }
+    let destination =
+        crate::utils::paths::parse_user_path(&destination).map_err(|e| e.to_string())?;
+    let new_project_dir = projects_dir.join(project_id_for(&destination.to_string_lossy()));
+    if new_project_dir.exists() {
+        return Err(format!(
+            "A project already exists for {}",
+            destination.display()
+        ));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let report =
+            crate::commands::storage_cleanup::move_with_progress(&app, &source, &destination)?;
+        fs::rename(&project_dir, &new_project_dir).map_err(|e| {
+            format!(
+                "Moved the project to {} but could not move its sessions: {}",
+                destination.display(),
+                e
+            )
+        })?;
+        Ok(report)
     })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Deletes a session's transcript and checkpoints
+///
+/// Both go to the Recycle Bin unless `delete_mode` is `permanent`.
+#[tauri::command]
+pub async fn delete_session(
+    app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    project_id: String,
+    session_id: String,
+    delete_mode: Option<crate::utils::delete::DeleteMode>,
+) -> Result<(), String> {
+    log::info!("Deleting session {} of project {}", session_id, project_id);
+
+    for id in [&project_id, &session_id] {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(format!("Invalid id: {}", id));
+        }
+    }
+
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let project_dir = claude_dir.join("projects").join(&project_id);
+    let mode = delete_mode.unwrap_or_default();
+
+    app.remove_manager(&session_id).await;
+    crate::utils::delete::delete_path(
+        project_dir.join(format!("{}.jsonl", session_id)),
+        mode,
+    )
+    .map_err(|e| format!("Failed to delete session: {:#}", e))?;
+    crate::utils::delete::delete_path(project_dir.join(".timelines").join(&session_id), mode)
+        .map_err(|e| format!("Failed to delete session checkpoints: {:#}", e))?;
+
+    Ok(())
 }
 
 /// Gets sessions for a specific project
+///
+/// Sessions stored under the directories in `alias_ids` (the same folder
+/// reached through a symlink, junction or mapped drive) are listed too, each
+/// with the `project_id` of the directory it lives in.
 #[tauri::command]
-pub async fn get_project_sessions(project_id: String) -> Result<Vec<Session>, String> {
+pub async fn get_project_sessions(
+    project_id: String,
+    alias_ids: Option<Vec<String>>,
+) -> Result<Vec<Session>, String> {
     log::info!("Getting sessions for project: {}", project_id);
 
+    let alias_ids = alias_ids.unwrap_or_default();
+    for id in std::iter::once(&project_id).chain(&alias_ids) {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(format!("Invalid project id: {}", id));
+        }
+    }
+
     let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
-    let project_dir = claude_dir.join("projects").join(&project_id);
+    let projects_dir = claude_dir.join("projects");
+    let project_dir = projects_dir.join(&project_id);
     let todos_dir = claude_dir.join("todos");
 
     if !project_dir.exists() {
@@ -489,10 +749,37 @@ pub async fn get_project_sessions(project_id: String) -> Result<Vec<Session>, St
         }
     };
 
+    let mut sessions = read_project_sessions(&project_dir, &project_id, &project_path, &todos_dir)?;
+    for alias_id in &alias_ids {
+        let alias_dir = projects_dir.join(alias_id);
+        match read_project_sessions(&alias_dir, alias_id, &project_path, &todos_dir) {
+            Ok(mut alias_sessions) => sessions.append(&mut alias_sessions),
+            Err(e) => log::warn!("Skipping sessions of alias {}: {}", alias_id, e),
+        }
+    }
+
+    // Sort sessions by creation time (newest first)
+    sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    log::info!(
+        "Found {} sessions for project {}",
+        sessions.len(),
+        project_id
+    );
+    Ok(sessions)
+}
+
+/// Reads the sessions stored in one project directory
+fn read_project_sessions(
+    project_dir: &std::path::Path,
+    project_id: &str,
+    project_path: &str,
+    todos_dir: &std::path::Path,
+) -> Result<Vec<Session>, String> {
     let mut sessions = Vec::new();
 
     // Read all JSONL files in the project directory
-    let entries = fs::read_dir(&project_dir)
+    let entries = fs::read_dir(project_dir)
         .map_err(|e| format!("Failed to read project directory: {}", e))?;
 
     for entry in entries {
@@ -528,8 +815,8 @@ pub async fn get_project_sessions(project_id: String) -> Result<Vec<Session>, St
 
                 sessions.push(Session {
                     id: session_id.to_string(),
-                    project_id: project_id.clone(),
-                    project_path: project_path.clone(),
+                    project_id: project_id.to_string(),
+                    project_path: project_path.to_string(),
                     todo_data,
                     created_at,
                     first_message,
@@ -539,14 +826,6 @@ pub async fn get_project_sessions(project_id: String) -> Result<Vec<Session>, St
         }
     }
 
-    // Sort sessions by creation time (newest first)
-    sessions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-
-    log::info!(
-        "Found {} sessions for project {}",
-        sessions.len(),
-        project_id
-    );
     Ok(sessions)
 }
 
@@ -745,8 +1024,9 @@ pub async fn save_claude_settings(settings: serde_json::Value) -> Result<String,
     let json_string = serde_json::to_string_pretty(&settings)
         .map_err(|e| format!("Failed to serialize settings: {}", e))?;
 
-    fs::write(&settings_path, json_string)
-        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+    crate::utils::atomic_write::atomic_write(&settings_path, json_string)
+        .map_err(|e| format!("Failed to write settings file: {:#}", e))?;
+    crate::commands::secrets::protect_settings_file(&settings_path, &settings);
 
     Ok("Settings saved successfully".to_string())
 }
@@ -756,7 +1036,7 @@ pub async fn save_claude_settings(settings: serde_json::Value) -> Result<String,
 pub async fn find_claude_md_files(project_path: String) -> Result<Vec<ClaudeMdFile>, String> {
     log::info!("Finding CLAUDE.md files in project: {}", project_path);
 
-    let path = PathBuf::from(&project_path);
+    let path = crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     if !path.exists() {
         return Err(format!("Project path does not exist: {}", project_path));
     }
@@ -842,10 +1122,13 @@ fn find_claude_md_recursive(
 pub async fn read_claude_md_file(file_path: String) -> Result<String, String> {
     log::info!("Reading CLAUDE.md file: {}", file_path);
 
-    let path = PathBuf::from(&file_path);
+    let path = crate::utils::paths::parse_user_path(&file_path).map_err(|e| e.to_string())?;
     if !path.exists() {
         return Err(format!("File does not exist: {}", file_path));
     }
+    // Explains an online-only file that can't be downloaded right now
+    // instead of failing the read with a generic IO error
+    crate::utils::cloud_files::hydrate_file(&path).map_err(|e| e.to_string())?;
 
     fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))
 }
@@ -855,7 +1138,7 @@ pub async fn read_claude_md_file(file_path: String) -> Result<String, String> {
 pub async fn save_claude_md_file(file_path: String, content: String) -> Result<String, String> {
     log::info!("Saving CLAUDE.md file: {}", file_path);
 
-    let path = PathBuf::from(&file_path);
+    let path = crate::utils::paths::parse_user_path(&file_path).map_err(|e| e.to_string())?;
 
     // Ensure the parent directory exists
     if let Some(parent) = path.parent() {
@@ -863,7 +1146,13 @@ pub async fn save_claude_md_file(file_path: String, content: String) -> Result<S
             .map_err(|e| format!("Failed to create parent directory: {}", e))?;
     }
 
-    fs::write(&path, content).map_err(|e| format!("Failed to write file: {}", e))?;
+    fs::write(&path, content).map_err(|e| {
+        // Controlled Folder Access denies writes under Documents with a
+        // plain access-denied error
+        #[cfg(target_os = "windows")]
+        let e = crate::windows::controlled_folder_access::explain_access_denied(&path, e);
+        format!("Failed to write file: {:#}", e)
+    })?;
 
     Ok("File saved successfully".to_string())
 }
@@ -907,7 +1196,38 @@ pub async fn load_session_history(
     Ok(messages)
 }
 
+/// Export a session's JSONL history to `file_path`, for opening it on
+/// another machine or attaching it to a bug report
+#[tauri::command]
+pub async fn export_session(
+    session_id: String,
+    project_id: String,
+    file_path: String,
+) -> Result<(), String> {
+    if cfg!(target_os = "windows") {
+        if let Some(file_name) = std::path::Path::new(&file_path).file_name() {
+            crate::utils::paths::validate_windows_filename(&file_name.to_string_lossy())
+                .map_err(|e| e.to_string())?;
+        }
+    }
 
+    let claude_dir = get_claude_dir().map_err(|e| e.to_string())?;
+    let session_path = claude_dir
+        .join("projects")
+        .join(&project_id)
+        .join(format!("{}.jsonl", session_id));
+    let size = fs::metadata(&session_path)
+        .map_err(|_| format!("Session file not found: {}", session_id))?
+        .len();
+    crate::utils::disk_space::ensure_free_space(&file_path, size).map_err(|e| e.to_string())?;
+
+    fs::copy(&session_path, &file_path).map_err(|e| format!("Failed to export session: {}", e))?;
+    crate::utils::hashing::verify_copy(&session_path, &file_path)
+        .await
+        .map_err(|e| format!("Failed to export session: {:#}", e))?;
+    log::info!("Exported session {} to {}", session_id, file_path);
+    Ok(())
+}
 
 /// Execute a new interactive Claude Code session with streaming output
 #[tauri::command]
@@ -924,7 +1244,8 @@ pub async fn execute_claude_code(
     );
 
     let claude_path = find_claude_binary(&app)?;
-    
+    crate::claude_binary::check_launch_signature(&app, &claude_path)?;
+
     let args = vec![
         "-p".to_string(),
         prompt.clone(),
@@ -955,7 +1276,8 @@ pub async fn continue_claude_code(
     );
 
     let claude_path = find_claude_binary(&app)?;
-    
+    crate::claude_binary::check_launch_signature(&app, &claude_path)?;
+
     let args = vec![
         "-c".to_string(), // Continue flag
         "-p".to_string(),
@@ -989,7 +1311,8 @@ pub async fn resume_claude_code(
     );
 
     let claude_path = find_claude_binary(&app)?;
-    
+    crate::claude_binary::check_launch_signature(&app, &claude_path)?;
+
     let args = vec![
         "--resume".to_string(),
         session_id.clone(),
@@ -1028,6 +1351,10 @@ pub async fn cancel_claude_execution(
             Ok(Some(process_info)) => {
                 log::info!("Found process in registry for session {}: run_id={}, PID={}", 
                     sid, process_info.run_id, process_info.pid);
+                let _ = registry.0.mark_termination(
+                    process_info.run_id,
+                    crate::process::exit::Termination::UserRequested,
+                );
                 match registry.0.kill_process(process_info.run_id).await {
                     Ok(success) => {
                         if success {
@@ -1041,6 +1368,9 @@ pub async fn cancel_claude_execution(
                         log::warn!("Failed to kill via registry: {}", e);
                     }
                 }
+                if !killed {
+                    registry.0.take_termination(process_info.run_id);
+                }
                 attempted_methods.push("registry");
             }
             Ok(None) => {
@@ -1074,24 +1404,16 @@ pub async fn cancel_claude_execution(
                     // Method 3: If we have a PID, try system kill as last resort
                     if let Some(pid) = pid {
                         log::info!("Attempting system kill as last resort for PID: {}", pid);
-                        let kill_result = if cfg!(target_os = "windows") {
-                            std::process::Command::new("taskkill")
-                                .args(["/F", "/PID", &pid.to_string()])
-                                .output()
-                        } else {
-                            std::process::Command::new("kill")
-                                .args(["-KILL", &pid.to_string()])
-                                .output()
-                        };
-                        
-                        match kill_result {
-                            Ok(output) if output.status.success() => {
+                        match crate::windows::process_manager::process_manager()
+                            .kill_tree(pid)
+                            .await
+                        {
+                            Ok(true) => {
                                 log::info!("Successfully killed process via system command");
                                 killed = true;
                             }
-                            Ok(output) => {
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                log::error!("System kill failed: {}", stderr);
+                            Ok(false) => {
+                                log::error!("System kill failed: process {} not found", pid);
                             }
                             Err(e) => {
                                 log::error!("Failed to execute system kill command: {}", e);
@@ -1158,9 +1480,13 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
     use tokio::io::{AsyncBufReadExt, BufReader};
     use std::sync::Mutex;
 
+    // Fail with a pointer to System Settings instead of a silent read error
+    #[cfg(target_os = "macos")]
+    crate::windows::tcc::check_folder_access(std::path::Path::new(&project_path))
+        .map_err(|e| e.to_string())?;
+
     // Spawn the process
-    let mut child = cmd
-        .spawn()
+    let mut child = crate::commands::secrets::spawn_claude(&mut cmd)
         .map_err(|e| format!("Failed to spawn Claude: {}", e))?;
 
     // Get stdout and stderr
@@ -1181,6 +1507,7 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
     // We'll extract the session ID from Claude's init message
     let session_id_holder: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
     let run_id_holder: Arc<Mutex<Option<i64>>> = Arc::new(Mutex::new(None));
+    let output_signals = Arc::new(Mutex::new(crate::process::exit::OutputSignals::default()));
 
     // Store the child process in the global state (for backward compatibility)
     let claude_state = app.state::<ClaudeProcessState>();
@@ -1203,10 +1530,12 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
     let project_path_clone = project_path.clone();
     let prompt_clone = prompt.clone();
     let model_clone = model.clone();
+    let output_signals_stdout = output_signals.clone();
     let stdout_task = tokio::spawn(async move {
         let mut lines = stdout_reader.lines();
         while let Ok(Some(line)) = lines.next_line().await {
             log::debug!("Claude stdout: {}", line);
+            output_signals_stdout.lock().unwrap().observe(&line);
             
             // Parse the line to check for init message with session ID
             if let Ok(msg) = serde_json::from_str::<serde_json::Value>(&line) {
@@ -1255,10 +1584,12 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
 
     let app_handle_stderr = app.clone();
     let session_id_holder_clone2 = session_id_holder.clone();
+    let output_signals_stderr = output_signals.clone();
     let stderr_task = tokio::spawn(async move {
         let mut lines = stderr_reader.lines();
         while let Ok(Some(line)) = lines.next_line().await {
             log::error!("Claude stderr: {}", line);
+            output_signals_stderr.lock().unwrap().observe(&line);
             // Emit error lines to the frontend with session isolation if we have session ID
             if let Some(ref session_id) = *session_id_holder_clone2.lock().unwrap() {
                 let _ = app_handle_stderr.emit(&format!("claude-error:{}", session_id), &line);
@@ -1274,6 +1605,8 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
     let session_id_holder_clone3 = session_id_holder.clone();
     let run_id_holder_clone2 = run_id_holder.clone();
     let registry_clone2 = registry.0.clone();
+    let project_path_wait = project_path.clone();
+    let model_wait = model.clone();
     tokio::spawn(async move {
         let _ = stdout_task.await;
         let _ = stderr_task.await;
@@ -1283,10 +1616,32 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
         if let Some(mut child) = current_process.take() {
             match child.wait().await {
                 Ok(status) => {
-                    log::info!("Claude process exited with status: {}", status);
+                    let termination = run_id_holder_clone2
+                        .lock()
+                        .unwrap()
+                        .and_then(|run_id| registry_clone2.take_termination(run_id));
+                    let report = crate::process::exit::ExitReport::from_status(
+                        Some(&status),
+                        termination,
+                        *output_signals.lock().unwrap(),
+                    );
+                    log::info!(
+                        "Claude process exited with status: {} ({})",
+                        status,
+                        report.classification
+                    );
+                    let session_id = session_id_holder_clone3.lock().unwrap().clone();
+                    record_session_exit(
+                        &app_handle_wait,
+                        session_id,
+                        project_path_wait,
+                        &model_wait,
+                        report,
+                    );
                     // Add a small delay to ensure all messages are processed
                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                     if let Some(ref session_id) = *session_id_holder_clone3.lock().unwrap() {
+                        let _ = app_handle_wait.emit(&format!("claude-exit:{}", session_id), &report);
                         let _ = app_handle_wait.emit(
                             &format!("claude-complete:{}", session_id),
                             status.success(),
@@ -1297,6 +1652,19 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
                 }
                 Err(e) => {
                     log::error!("Failed to wait for Claude process: {}", e);
+                    let report = crate::process::exit::ExitReport::from_status(
+                        None,
+                        None,
+                        *output_signals.lock().unwrap(),
+                    );
+                    let session_id = session_id_holder_clone3.lock().unwrap().clone();
+                    record_session_exit(
+                        &app_handle_wait,
+                        session_id,
+                        project_path_wait,
+                        &model_wait,
+                        report,
+                    );
                     // Add a small delay to ensure all messages are processed
                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                     if let Some(ref session_id) = *session_id_holder_clone3.lock().unwrap() {
@@ -1321,6 +1689,35 @@ async fn spawn_claude_process(app: AppHandle, mut cmd: Command, prompt: String,
     Ok(())
 }
 
+/// Store how an interactive session ended and notify the run webhook
+fn record_session_exit(
+    app: &AppHandle,
+    session_id: Option<String>,
+    project_path: String,
+    model: &str,
+    report: crate::process::exit::ExitReport,
+) {
+    let db = app.state::<crate::commands::agents::AgentDb>();
+    if let Ok(conn) = db.0.lock() {
+        if let Err(e) = crate::commands::agents::record_session_exit(
+            &conn,
+            session_id.as_deref(),
+            &project_path,
+            model,
+            &report,
+        ) {
+            log::warn!("Failed to record the session exit: {}", e);
+        }
+    }
+
+    crate::commands::run_webhook::notify_run_exit(
+        app,
+        crate::commands::run_webhook::RunExitEvent {
+            session_id,
+            ..crate::commands::run_webhook::RunExitEvent::new("session", project_path, report)
+        },
+    );
+}
 
 /// Lists files and directories in a given path
 #[tauri::command]
@@ -1333,7 +1730,7 @@ pub async fn list_directory_contents(directory_path: String) -> Result<Vec<FileE
         return Err("Directory path cannot be empty".to_string());
     }
 
-    let path = PathBuf::from(&directory_path);
+    let path = crate::utils::paths::parse_user_path(&directory_path).map_err(|e| e.to_string())?;
     log::debug!("Resolved path: {:?}", path);
 
     if !path.exists() {
@@ -1416,7 +1813,7 @@ pub async fn search_files(base_path: String, query: String) -> Result<Vec<FileEn
         return Ok(Vec::new());
     }
 
-    let path = PathBuf::from(&base_path);
+    let path = crate::utils::paths::parse_user_path(&base_path).map_err(|e| e.to_string())?;
     log::debug!("Resolved search base path: {:?}", path);
 
     if !path.exists() {
@@ -1531,12 +1928,15 @@ pub async fn create_checkpoint(
         session_id,
         project_id
     );
+    crate::commands::maintenance::record_activity();
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
         .get_or_create_manager(
             session_id.clone(),
             project_id.clone(),
-            PathBuf::from(&project_path),
+            project_path,
         )
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
@@ -1577,6 +1977,9 @@ pub async fn create_checkpoint(
 }
 
 /// Restores a session to a specific checkpoint
+///
+/// Set `take_ownership` only after the user agreed to take over the
+/// `locked_files` of an earlier attempt.
 #[tauri::command]
 pub async fn restore_checkpoint(
     app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
@@ -1584,24 +1987,28 @@ pub async fn restore_checkpoint(
     session_id: String,
     project_id: String,
     project_path: String,
+    take_ownership: Option<bool>,
 ) -> Result<crate::checkpoint::CheckpointResult, String> {
     log::info!(
         "Restoring checkpoint: {} for session: {}",
         checkpoint_id,
         session_id
     );
+    crate::commands::maintenance::record_activity();
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
         .get_or_create_manager(
             session_id.clone(),
             project_id.clone(),
-            PathBuf::from(&project_path),
+            project_path,
         )
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
     let result = manager
-        .restore_checkpoint(&checkpoint_id)
+        .restore_checkpoint(&checkpoint_id, take_ownership.unwrap_or(false))
         .await
         .map_err(|e| format!("Failed to restore checkpoint: {}", e))?;
 
@@ -1619,8 +2026,8 @@ pub async fn restore_checkpoint(
         .load_checkpoint(&result.checkpoint.project_id, &session_id, &checkpoint_id)
         .map_err(|e| format!("Failed to load checkpoint data: {}", e))?;
 
-    fs::write(&session_path, messages)
-        .map_err(|e| format!("Failed to update session file: {}", e))?;
+    crate::utils::atomic_write::atomic_write(&session_path, messages)
+        .map_err(|e| format!("Failed to update session file: {:#}", e))?;
 
     Ok(result)
 }
@@ -1639,8 +2046,10 @@ pub async fn list_checkpoints(
         project_id
     );
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1679,14 +2088,19 @@ pub async fn fork_from_checkpoint(
     if source_session_path.exists() {
         fs::copy(&source_session_path, &new_session_path)
             .map_err(|e| format!("Failed to copy session file: {}", e))?;
+        crate::utils::hashing::verify_copy(&source_session_path, &new_session_path)
+            .await
+            .map_err(|e| format!("Failed to copy session file: {:#}", e))?;
     }
 
     // Create manager for the new session
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
         .get_or_create_manager(
             new_session_id.clone(),
             project_id,
-            PathBuf::from(&project_path),
+            project_path,
         )
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
@@ -1711,8 +2125,10 @@ pub async fn get_session_timeline(
         project_id
     );
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1746,8 +2162,10 @@ pub async fn update_checkpoint_settings(
         }
     };
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(&project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1855,8 +2273,10 @@ pub async fn track_checkpoint_message(
 ) -> Result<(), String> {
     log::info!("Tracking message for session: {}", session_id);
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1877,8 +2297,10 @@ pub async fn check_auto_checkpoint(
 ) -> Result<bool, String> {
     log::info!("Checking auto-checkpoint for session: {}", session_id);
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id.clone(), project_id, PathBuf::from(project_path))
+        .get_or_create_manager(session_id.clone(), project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1886,13 +2308,18 @@ pub async fn check_auto_checkpoint(
 }
 
 /// Triggers cleanup of old checkpoints
+///
+/// Keeping none deletes them all, which may need Windows Hello first (see
+/// [`user_presence`](crate::commands::user_presence)).
 #[tauri::command]
 pub async fn cleanup_old_checkpoints(
     app: tauri::State<'_, crate::checkpoint::state::CheckpointState>,
+    db: tauri::State<'_, crate::commands::agents::AgentDb>,
     session_id: String,
     project_id: String,
     project_path: String,
     keep_count: usize,
+    delete_mode: Option<crate::utils::delete::DeleteMode>,
 ) -> Result<usize, String> {
     log::info!(
         "Cleaning up old checkpoints for session: {}, keeping {}",
@@ -1900,18 +2327,33 @@ pub async fn cleanup_old_checkpoints(
         keep_count
     );
 
+    if keep_count == 0 {
+        crate::commands::user_presence::confirm_operation(
+            &db,
+            crate::windows::user_presence::ProtectedOperation::DeleteAllCheckpoints,
+        )
+        .await?;
+    }
+
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
         .get_or_create_manager(
             session_id.clone(),
             project_id.clone(),
-            PathBuf::from(project_path),
+            project_path,
         )
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
     manager
         .storage
-        .cleanup_old_checkpoints(&project_id, &session_id, keep_count)
+        .cleanup_old_checkpoints(
+            &project_id,
+            &session_id,
+            keep_count,
+            delete_mode.unwrap_or_default(),
+        )
         .map_err(|e| format!("Failed to cleanup checkpoints: {}", e))
 }
 
@@ -1925,8 +2367,10 @@ pub async fn get_checkpoint_settings(
 ) -> Result<serde_json::Value, String> {
     log::info!("Getting checkpoint settings for session: {}", session_id);
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -1983,8 +2427,10 @@ pub async fn get_recently_modified_files(
         session_id
     );
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = app
-        .get_or_create_manager(session_id, project_id, PathBuf::from(project_path))
+        .get_or_create_manager(session_id, project_id, project_path)
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
 
@@ -2017,11 +2463,13 @@ pub async fn track_session_messages(
         session_id
     );
 
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
     let manager = state
         .get_or_create_manager(
             session_id.clone(),
             project_id.clone(),
-            PathBuf::from(&project_path),
+            project_path,
         )
         .await
         .map_err(|e| format!("Failed to get checkpoint manager: {}", e))?;
@@ -2049,11 +2497,17 @@ pub async fn get_hooks_config(scope: String, project_path: Option<String>) -> Re
         },
         "project" => {
             let path = project_path.ok_or("Project path required for project scope")?;
-            PathBuf::from(path).join(".claude").join("settings.json")
+            crate::utils::paths::parse_user_path(&path)
+                .map_err(|e| e.to_string())?
+                .join(".claude")
+                .join("settings.json")
         },
         "local" => {
             let path = project_path.ok_or("Project path required for local scope")?;
-            PathBuf::from(path).join(".claude").join("settings.local.json")
+            crate::utils::paths::parse_user_path(&path)
+                .map_err(|e| e.to_string())?
+                .join(".claude")
+                .join("settings.local.json")
         },
         _ => return Err("Invalid scope".to_string())
     };
@@ -2089,14 +2543,18 @@ pub async fn update_hooks_config(
         },
         "project" => {
             let path = project_path.ok_or("Project path required for project scope")?;
-            let claude_dir = PathBuf::from(path).join(".claude");
+            let claude_dir = crate::utils::paths::parse_user_path(&path)
+                .map_err(|e| e.to_string())?
+                .join(".claude");
             fs::create_dir_all(&claude_dir)
                 .map_err(|e| format!("Failed to create .claude directory: {}", e))?;
             claude_dir.join("settings.json")
         },
         "local" => {
             let path = project_path.ok_or("Project path required for local scope")?;
-            let claude_dir = PathBuf::from(path).join(".claude");
+            let claude_dir = crate::utils::paths::parse_user_path(&path)
+                .map_err(|e| e.to_string())?
+                .join(".claude");
             fs::create_dir_all(&claude_dir)
                 .map_err(|e| format!("Failed to create .claude directory: {}", e))?;
             claude_dir.join("settings.local.json")
@@ -2121,8 +2579,8 @@ pub async fn update_hooks_config(
     let json_string = serde_json::to_string_pretty(&settings)
         .map_err(|e| format!("Failed to serialize settings: {}", e))?;
     
-    fs::write(&settings_path, json_string)
-        .map_err(|e| format!("Failed to write settings: {}", e))?;
+    crate::utils::atomic_write::atomic_write(&settings_path, json_string)
+        .map_err(|e| format!("Failed to write settings: {:#}", e))?;
 
     Ok("Hooks configuration updated successfully".to_string())
 }
@@ -2156,3 +2614,64 @@ pub async fn validate_hook_command(command: String) -> Result<serde_json::Value,
         Err(e) => Err(format!("Failed to validate command: {}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(id: &str, path: &str, most_recent_session: Option<u64>) -> Project {
+        Project {
+            id: id.to_string(),
+            path: path.to_string(),
+            sessions: Vec::new(),
+            created_at: 0,
+            most_recent_session,
+            alias_ids: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_collapse_aliased_projects() {
+        let mut projects = vec![
+            project("-home-me-code", "/home/me/code", Some(10)),
+            project("-mnt-code", "/mnt/code", Some(20)),
+            project("-home-me-other", "/home/me/other", None),
+            project("-gone", "/gone", None),
+        ];
+        projects[0].sessions = vec!["a".to_string()];
+        projects[1].sessions = vec!["b".to_string()];
+        let collapsed = collapse_aliased_projects(projects, |path| match path {
+            "/home/me/code" | "/mnt/code" => Some("/home/me/code".to_string()),
+            "/gone" => None,
+            other => Some(other.to_string()),
+        });
+
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(collapsed[0].id, "-mnt-code");
+        assert_eq!(collapsed[0].path, "/home/me/code");
+        assert_eq!(collapsed[0].alias_ids, vec!["-home-me-code".to_string()]);
+        assert_eq!(
+            collapsed[0].sessions,
+            vec!["b".to_string(), "a".to_string()]
+        );
+        assert_eq!(collapsed[0].most_recent_session, Some(20));
+        assert!(collapsed[1].alias_ids.is_empty());
+        assert_eq!(collapsed[2].path, "/gone");
+    }
+
+    #[test]
+    fn test_read_project_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path().join("-mnt-code");
+        std::fs::create_dir(&project_dir).unwrap();
+        std::fs::write(project_dir.join("session-1.jsonl"), "").unwrap();
+        std::fs::write(project_dir.join("notes.txt"), "").unwrap();
+
+        let sessions =
+            read_project_sessions(&project_dir, "-mnt-code", "/home/me/code", dir.path()).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "session-1");
+        assert_eq!(sessions[0].project_id, "-mnt-code");
+        assert_eq!(sessions[0].project_path, "/home/me/code");
+    }
+}
@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::State;
+
+/// URL scheme registered for deep links
+pub const DEEP_LINK_SCHEME: &str = "opcode";
+
+/// Command-line switch that introduces a deep link. The protocol handler is
+/// registered as `"exe" --deep-link "%1"`, so the URL must be the only
+/// argument that follows it.
+pub const DEEP_LINK_SWITCH: &str = "--deep-link";
+
+/// Routes (the URL host) that deep links may target
+const ALLOWED_ROUTES: &[&str] = &["session", "project", "agent", "settings"];
+
+/// Upper bound on the length of an accepted deep link
+const MAX_URL_LENGTH: usize = 2048;
+
+/// A deep link that passed validation
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DeepLink {
+    /// Target route, e.g. "session"
+    pub route: String,
+    /// Decoded path segments after the route
+    pub segments: Vec<String>,
+    /// Decoded query parameters, in order
+    pub params: Vec<(String, String)>,
+}
+
+/// Deep link the app was launched with, waiting to be picked up by the UI
+#[derive(Default)]
+pub struct PendingDeepLink(pub Mutex<Option<DeepLink>>);
+
+/// Take the deep link the app was launched with, if any
+#[tauri::command]
+pub async fn take_pending_deep_link(
+    pending: State<'_, PendingDeepLink>,
+) -> Result<Option<DeepLink>, String> {
+    let mut link = pending.0.lock().map_err(|e| e.to_string())?;
+    Ok(link.take())
+}
+
+/// Extract and validate a deep link from the process arguments
+///
+/// Returns `None` when the app was not launched through the protocol handler.
+/// Anything other than the switch right after the executable followed by
+/// exactly one opaque URL is rejected, since other arguments mean the link
+/// broke out of its quotes.
+pub fn deep_link_from_args(args: &[String]) -> Option<Result<DeepLink, String>> {
+    let position = args.iter().position(|arg| arg == DEEP_LINK_SWITCH)?;
+    if position != 1 {
+        return Some(Err(format!(
+            "Expected {} to be the first argument",
+            DEEP_LINK_SWITCH
+        )));
+    }
+
+    Some(match &args[position + 1..] {
+        [url] => parse_deep_link(url),
+        [] => Err("Missing deep link URL".to_string()),
+        rest => Err(format!(
+            "Expected a single deep link URL, got {} arguments",
+            rest.len()
+        )),
+    })
+}
+
+/// Queue URLs the OS hands to the running app and notify the UI
+///
+/// macOS delivers `opcode://` links and opened documents as Apple Events
+/// (`RunEvent::Opened`) instead of arguments, both on launch and while
+/// running. They are queued like a launch argument would be, and a
+/// `pending-deep-link` or `pending-dropped-paths` event tells a loaded UI to
+/// take them.
+#[cfg(target_os = "macos")]
+pub fn handle_opened_urls(app: &tauri::AppHandle, urls: &[url::Url]) {
+    use super::drop_target::{classify_path, PendingDroppedPaths};
+    use tauri::{Emitter, Manager};
+
+    for url in urls {
+        if url.scheme() == "file" {
+            let Some(dropped) = url.to_file_path().ok().and_then(|p| classify_path(&p)) else {
+                continue;
+            };
+            log::info!("Opened with path: {}", dropped.path);
+            if let Ok(mut pending) = app.state::<PendingDroppedPaths>().0.lock() {
+                pending.push(dropped);
+            }
+            let _ = app.emit("pending-dropped-paths", ());
+            continue;
+        }
+
+        match parse_deep_link(url.as_str()) {
+            Ok(link) => {
+                log::info!("Opened with deep link to route: {}", link.route);
+                if let Ok(mut pending) = app.state::<PendingDeepLink>().0.lock() {
+                    *pending = Some(link);
+                }
+                let _ = app.emit("pending-deep-link", ());
+            }
+            Err(e) => log::warn!("Rejected deep link: {}", e),
+        }
+    }
+}
+
+/// Queue the deep link or dropped paths of a second launch and notify the UI
+///
+/// A second launch forwards its arguments and working directory to the
+/// running instance and exits. They are handled like this instance's own
+/// launch arguments, with `pending-deep-link` and `pending-dropped-paths`
+/// events telling the loaded UI to take them.
+pub fn handle_forwarded_args(app: &tauri::AppHandle, args: &[String], cwd: &str) {
+    use super::drop_target::{dropped_paths_from_forwarded_args, PendingDroppedPaths};
+    use tauri::{Emitter, Manager};
+
+    match deep_link_from_args(args) {
+        Some(Ok(link)) => {
+            log::info!("Forwarded deep link to route: {}", link.route);
+            if let Ok(mut pending) = app.state::<PendingDeepLink>().0.lock() {
+                *pending = Some(link);
+            }
+            let _ = app.emit("pending-deep-link", ());
+        }
+        Some(Err(e)) => log::warn!("Rejected forwarded deep link: {}", e),
+        None => {}
+    }
+
+    let dropped = dropped_paths_from_forwarded_args(args, std::path::Path::new(cwd));
+    if !dropped.is_empty() {
+        log::info!("Forwarded {} dropped paths", dropped.len());
+        if let Ok(mut pending) = app.state::<PendingDroppedPaths>().0.lock() {
+            pending.extend(dropped);
+        }
+        let _ = app.emit("pending-dropped-paths", ());
+    }
+}
+
+/// Validate an `opcode://route/segments?key=value` URL
+pub fn parse_deep_link(raw: &str) -> Result<DeepLink, String> {
+    if raw.len() > MAX_URL_LENGTH {
+        return Err("Deep link is too long".to_string());
+    }
+    if raw.chars().any(char::is_whitespace) {
+        return Err("Deep link contains unencoded whitespace".to_string());
+    }
+    check_untrusted_text(raw)?;
+
+    let url = url::Url::parse(raw).map_err(|e| format!("Invalid deep link: {}", e))?;
+
+    if url.scheme() != DEEP_LINK_SCHEME {
+        return Err(format!("Unsupported deep link scheme: {}", url.scheme()));
+    }
+    if !url.username().is_empty() || url.password().is_some() || url.port().is_some() {
+        return Err("Deep links must not contain credentials or a port".to_string());
+    }
+
+    let route = url
+        .host_str()
+        .ok_or("Deep link has no route")?
+        .to_ascii_lowercase();
+    if !ALLOWED_ROUTES.contains(&route.as_str()) {
+        return Err(format!("Unknown deep link route: {}", route));
+    }
+
+    let mut segments = Vec::new();
+    for segment in url.path_segments().into_iter().flatten() {
+        if segment.is_empty() {
+            continue;
+        }
+        let decoded = percent_decode(segment)?;
+        if decoded == "." || decoded == ".." || decoded.contains(['/', '\\']) {
+            return Err(format!("Invalid deep link path segment: {}", decoded));
+        }
+        check_untrusted_text(&decoded)?;
+        segments.push(decoded);
+    }
+
+    let mut params = Vec::new();
+    for (key, value) in url.query_pairs() {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid deep link parameter name: {}", key));
+        }
+        check_untrusted_text(&value)?;
+        params.push((key.into_owned(), value.into_owned()));
+    }
+
+    Ok(DeepLink {
+        route,
+        segments,
+        params,
+    })
+}
+
+/// Reject text that could be interpreted as extra arguments or switches
+fn check_untrusted_text(text: &str) -> Result<(), String> {
+    if text.contains(['"', '\'', '`']) {
+        return Err("Deep link contains embedded quotes".to_string());
+    }
+    if text.chars().any(char::is_control) {
+        return Err("Deep link contains control characters".to_string());
+    }
+    if text.starts_with('-') {
+        return Err("Deep link looks like a command-line switch".to_string());
+    }
+    Ok(())
+}
+
+fn percent_decode(segment: &str) -> Result<String, String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| format!("Invalid percent-encoding in deep link: {}", segment))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| "Deep link is not valid UTF-8".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_deep_link() {
+        let link = parse_deep_link("opcode://session/abc%20123?project=demo&tab=2").unwrap();
+        assert_eq!(link.route, "session");
+        assert_eq!(link.segments, vec!["abc 123".to_string()]);
+        assert_eq!(
+            link.params,
+            vec![
+                ("project".to_string(), "demo".to_string()),
+                ("tab".to_string(), "2".to_string())
+            ]
+        );
+
+        assert!(parse_deep_link("https://session/abc").is_err());
+        assert!(parse_deep_link("opcode://unknown/abc").is_err());
+        assert!(parse_deep_link("opcode://session/..%2F..%2Fetc").is_err());
+        assert!(parse_deep_link("opcode://session/abc\" --flag").is_err());
+        assert!(parse_deep_link("opcode://session/abc?x=--dangerous").is_err());
+        assert!(parse_deep_link("opcode://user:pw@session/abc").is_err());
+    }
+
+    #[test]
+    fn test_deep_link_from_args() {
+        assert!(deep_link_from_args(&args(&["opcode.exe"])).is_none());
+        assert!(deep_link_from_args(&args(&["opcode.exe", "--deep-link", "opcode://agent/7"]))
+            .unwrap()
+            .is_ok());
+        assert!(deep_link_from_args(&args(&["opcode.exe", "--deep-link"]))
+            .unwrap()
+            .is_err());
+        assert!(deep_link_from_args(&args(&[
+            "opcode.exe",
+            "--deep-link",
+            "opcode://agent/7",
+            "--inject"
+        ]))
+        .unwrap()
+        .is_err());
+        assert!(deep_link_from_args(&args(&[
+            "opcode.exe",
+            "--agent-runner",
+            "--deep-link",
+            "opcode://agent/7"
+        ]))
+        .unwrap()
+        .is_err());
+    }
+}
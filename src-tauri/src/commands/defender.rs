@@ -0,0 +1,98 @@
+//! Excluding project directories from Windows Defender scanning
+//!
+//! See [`defender_exclusions`](crate::windows::defender_exclusions) for why
+//! and how. Other platforms have no Defender: the commands fail with the
+//! `unsupported` [`CommandError`].
+
+use serde::Serialize;
+
+use crate::windows::unsupported::CommandError;
+
+/// Outcome of [`set_defender_exclusion`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub enum ExclusionChange {
+    Applied,
+    /// Nothing changed; show `warning` and call again with `consent`
+    ConsentRequired {
+        warning: String,
+    },
+}
+
+/// Paths excluded from Defender scanning, `None` if only administrators may
+/// read them
+#[tauri::command]
+pub async fn list_defender_exclusions() -> Result<Option<Vec<String>>, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        tauri::async_runtime::spawn_blocking(
+            crate::windows::defender_exclusions::list_defender_exclusions,
+        )
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    Err(crate::windows::Unsupported::new("Defender exclusions").into())
+}
+
+/// Add (`excluded`) or remove the Defender exclusion of `path`, a project or
+/// opcode's data directory
+///
+/// Adding one needs `consent`, given after the user saw the warning of a
+/// first call without it. Asks for administrator rights unless opcode has
+/// them.
+#[tauri::command]
+pub async fn set_defender_exclusion(
+    path: String,
+    excluded: bool,
+    consent: Option<bool>,
+) -> Result<ExclusionChange, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::defender_exclusions::{self, ExclusionConsentRequired};
+
+        match defender_exclusions::set_defender_exclusion(&path, excluded, consent.unwrap_or(false))
+            .await
+        {
+            Ok(()) => Ok(ExclusionChange::Applied),
+            Err(e) => match e.downcast::<ExclusionConsentRequired>() {
+                Ok(required) => Ok(ExclusionChange::ConsentRequired {
+                    warning: required.warning,
+                }),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = (path, excluded, consent);
+        Err(crate::windows::Unsupported::new("Defender exclusions").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusion_change_json() {
+        assert_eq!(
+            serde_json::to_value(ExclusionChange::ConsentRequired {
+                warning: "Only exclude trusted directories".to_string()
+            })
+            .unwrap(),
+            serde_json::json!({
+                "status": "consent_required",
+                "warning": "Only exclude trusted directories"
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ExclusionChange::Applied).unwrap(),
+            serde_json::json!({ "status": "applied" })
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{Emitter, State, Window};
+
+use super::deep_link::DEEP_LINK_SWITCH;
+
+/// File extensions opened as session exports
+const SESSION_EXPORT_EXTENSIONS: &[&str] = &["opcsession", "jsonl"];
+
+/// How a dropped path is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DroppedPathKind {
+    /// A folder, opened as a project
+    Project,
+    /// An exported session, opened in its project
+    SessionExport,
+}
+
+/// A path dropped onto the window or the Opcode icon
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DroppedPath {
+    pub path: String,
+    pub kind: DroppedPathKind,
+}
+
+/// Paths the app was launched with (dropped onto its icon or a registered file)
+#[derive(Default)]
+pub struct PendingDroppedPaths(pub Mutex<Vec<DroppedPath>>);
+
+/// Take the paths the app was launched with, if any
+#[tauri::command]
+pub async fn take_pending_dropped_paths(
+    pending: State<'_, PendingDroppedPaths>,
+) -> Result<Vec<DroppedPath>, String> {
+    let mut paths = pending.0.lock().map_err(|e| e.to_string())?;
+    Ok(std::mem::take(&mut *paths))
+}
+
+/// Decide how a path should be opened, or `None` if it is not supported
+pub fn classify_path(path: &Path) -> Option<DroppedPath> {
+    let kind = if path.is_dir() {
+        DroppedPathKind::Project
+    } else if path.is_file()
+        && path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+            SESSION_EXPORT_EXTENSIONS
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(e))
+        })
+    {
+        DroppedPathKind::SessionExport
+    } else {
+        debug!("Ignoring unsupported dropped path: {}", path.display());
+        return None;
+    };
+
+    Some(DroppedPath {
+        path: path.to_string_lossy().to_string(),
+        kind,
+    })
+}
+
+/// Collect supported paths from the process arguments
+///
+/// Explorer passes items dropped onto the executable, a shortcut or a file
+/// handled by the drop target as plain arguments. Switches and deep link
+/// launches are left alone.
+pub fn dropped_paths_from_args(args: &[String]) -> Vec<DroppedPath> {
+    if args.iter().any(|arg| arg == DEEP_LINK_SWITCH) {
+        return Vec::new();
+    }
+
+    args.iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .filter_map(|arg| classify_path(Path::new(arg)))
+        .collect()
+}
+
+/// Collect supported paths from the arguments of another launch
+///
+/// Relative paths are resolved against `cwd`, the working directory of that
+/// launch, rather than this one's.
+pub fn dropped_paths_from_forwarded_args(args: &[String], cwd: &Path) -> Vec<DroppedPath> {
+    let resolved: Vec<String> = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            if i == 0 || arg.starts_with('-') {
+                arg.clone()
+            } else {
+                cwd.join(arg).to_string_lossy().to_string()
+            }
+        })
+        .collect();
+    dropped_paths_from_args(&resolved)
+}
+
+/// Forward paths dropped onto a window to the UI as a `paths-dropped` event
+pub fn handle_window_drop(window: &Window, paths: &[PathBuf]) {
+    let dropped: Vec<DroppedPath> = paths.iter().filter_map(|p| classify_path(p)).collect();
+    if dropped.is_empty() {
+        return;
+    }
+
+    info!(
+        "{} paths dropped onto window {}",
+        dropped.len(),
+        window.label()
+    );
+    let _ = window.emit("paths-dropped", &dropped);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dropped_paths_from_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("session.opcsession");
+        let other = dir.path().join("notes.txt");
+        std::fs::write(&export, "{}").unwrap();
+        std::fs::write(&other, "").unwrap();
+
+        let arg = |p: &Path| p.to_string_lossy().to_string();
+        let args = vec![
+            "opcode.exe".to_string(),
+            arg(dir.path()),
+            arg(&export),
+            arg(&other),
+            "--verbose".to_string(),
+        ];
+
+        assert_eq!(
+            dropped_paths_from_args(&args),
+            vec![
+                DroppedPath {
+                    path: arg(dir.path()),
+                    kind: DroppedPathKind::Project
+                },
+                DroppedPath {
+                    path: arg(&export),
+                    kind: DroppedPathKind::SessionExport
+                },
+            ]
+        );
+
+        let deep_link = vec![
+            "opcode.exe".to_string(),
+            DEEP_LINK_SWITCH.to_string(),
+            arg(dir.path()),
+        ];
+        assert!(dropped_paths_from_args(&deep_link).is_empty());
+    }
+
+    #[test]
+    fn test_dropped_paths_from_forwarded_args() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("project")).unwrap();
+
+        let args = vec![
+            "opcode".to_string(),
+            "project".to_string(),
+            "--verbose".to_string(),
+        ];
+        assert_eq!(
+            dropped_paths_from_forwarded_args(&args, dir.path()),
+            vec![DroppedPath {
+                path: dir.path().join("project").to_string_lossy().to_string(),
+                kind: DroppedPathKind::Project
+            }]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! EFS encryption of the directories holding transcripts and credentials
+//!
+//! See [`efs`](crate::windows::efs). Without a path the commands work on
+//! `~/.claude`, where Claude keeps transcripts and credentials. Other
+//! platforms have no EFS: the commands fail with the `unsupported`
+//! [`CommandError`].
+
+use crate::windows::unsupported::CommandError;
+
+/// `path`, or `~/.claude` without one
+#[cfg(target_os = "windows")]
+fn target_dir(path: Option<String>) -> Result<std::path::PathBuf, CommandError> {
+    match path {
+        Some(path) => crate::utils::paths::parse_user_path(&path)
+            .map_err(|e| CommandError::from(e.to_string())),
+        None => dirs::home_dir()
+            .map(|home| home.join(".claude"))
+            .ok_or_else(|| CommandError::from("Could not find home directory".to_string())),
+    }
+}
+
+/// Run a blocking EFS operation off the async runtime
+#[cfg(target_os = "windows")]
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, CommandError> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+}
+
+/// Whether the volume of `path` supports EFS and `path` is encrypted, as
+/// an [`EfsStatus`](crate::windows::efs::EfsStatus)
+#[tauri::command]
+pub async fn get_efs_status(path: Option<String>) -> Result<serde_json::Value, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        let dir = target_dir(path)?;
+        let status = run_blocking(move || crate::windows::efs::get_efs_status(&dir)).await?;
+        Ok(serde_json::to_value(status).map_err(|e| e.to_string())?)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        Err(crate::windows::Unsupported::new("EFS encryption").into())
+    }
+}
+
+/// Encrypt (`encrypted`) or decrypt the directory at `path` and everything
+/// in it, returning how many entries changed
+#[tauri::command]
+pub async fn set_efs_encrypted(
+    path: Option<String>,
+    encrypted: bool,
+) -> Result<usize, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::efs::{decrypt_directory, encrypt_directory};
+
+        let dir = target_dir(path)?;
+        run_blocking(move || {
+            if encrypted {
+                encrypt_directory(&dir)
+            } else {
+                decrypt_directory(&dir)
+            }
+        })
+        .await
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = (path, encrypted);
+        Err(crate::windows::Unsupported::new("EFS encryption").into())
+    }
+}
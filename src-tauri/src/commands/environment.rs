@@ -0,0 +1,281 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use tauri::State;
+
+use crate::commands::agents::AgentDb;
+
+/// app_settings key the sanitizer configuration is stored under (as JSON)
+const SETTINGS_KEY: &str = "env_sanitizer";
+
+/// Variables that are stripped from child environments by default.
+/// Entries ending in `*` match by prefix.
+const DEFAULT_DENY: &[&str] = &[
+    // Stale model overrides left behind by older setups
+    "ANTHROPIC_MODEL",
+    "ANTHROPIC_SMALL_FAST_MODEL",
+    // Code injected into every Node.js process
+    "NODE_OPTIONS",
+    "ELECTRON_RUN_AS_NODE",
+    // Disables TLS verification entirely
+    "NODE_TLS_REJECT_UNAUTHORIZED",
+];
+
+/// Certificate overrides typically set by corporate SSL interceptors. These
+/// are only stripped once a custom CA is configured in opcode, since that
+/// setting takes their place.
+const TLS_INTERCEPTOR_VARS: &[&str] = &[
+    "NODE_EXTRA_CA_CERTS",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "REQUESTS_CA_BUNDLE",
+    "CURL_CA_BUNDLE",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EnvSanitizerSettings {
+    pub enabled: bool,
+    /// Extra variables (or `PREFIX*` patterns) to strip
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Variables that are never stripped, even when a deny entry matches
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl Default for EnvSanitizerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            deny: Vec::new(),
+            allow: Vec::new(),
+        }
+    }
+}
+
+static SANITIZER_SETTINGS: RwLock<Option<EnvSanitizerSettings>> = RwLock::new(None);
+
+/// Get environment sanitizer settings from the database
+#[tauri::command]
+pub async fn get_env_sanitizer_settings(
+    db: State<'_, AgentDb>,
+) -> Result<EnvSanitizerSettings, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_env_sanitizer_settings(&conn))
+}
+
+/// Save environment sanitizer settings to the database
+#[tauri::command]
+pub async fn save_env_sanitizer_settings(
+    db: State<'_, AgentDb>,
+    settings: EnvSanitizerSettings,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let value = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![SETTINGS_KEY, value],
+    )
+    .map_err(|e| format!("Failed to save {}: {}", SETTINGS_KEY, e))?;
+
+    apply_env_sanitizer_settings(&settings);
+
+    Ok(())
+}
+
+/// Preview which variables of the current environment would be stripped
+#[tauri::command]
+pub async fn preview_env_sanitizer() -> Result<Vec<String>, String> {
+    Ok(scrubbed_env_vars())
+}
+
+/// Read the sanitizer settings from the database, falling back to defaults
+pub fn load_env_sanitizer_settings(conn: &Connection) -> EnvSanitizerSettings {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        params![SETTINGS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+/// Make the given settings the ones used for subsequent spawns
+pub fn apply_env_sanitizer_settings(settings: &EnvSanitizerSettings) {
+    log::info!(
+        "Applying environment sanitizer settings: enabled={}, deny={:?}, allow={:?}",
+        settings.enabled,
+        settings.deny,
+        settings.allow
+    );
+    if let Ok(mut current) = SANITIZER_SETTINGS.write() {
+        *current = Some(settings.clone());
+    }
+}
+
+/// Strip inherited variables that are known to break or leak into children
+/// from the environment of `cmd`, apply the proxy settings, point it at the
+/// custom CA bundle if one is configured, and pass it the API keys from the
+/// secret store
+///
+/// Takes a `std` command; pass tokio commands through `as_std_mut`.
+pub fn apply_child_env(cmd: &mut std::process::Command) {
+    for key in scrubbed_env_vars() {
+        cmd.env_remove(key);
+    }
+    for (key, value) in crate::commands::proxy::proxy_env() {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    for (key, value) in crate::commands::certificates::ca_bundle_env() {
+        cmd.env(key, value);
+    }
+    // Windows passes them through pipes when the child is spawned
+    #[cfg(not(target_os = "windows"))]
+    for (key, value) in crate::commands::secrets::secret_env() {
+        cmd.env(key, value);
+    }
+}
+
+/// Names of the variables in the current process environment that must not
+/// reach a spawned child. Each stripped name is logged; values never are.
+pub fn scrubbed_env_vars() -> Vec<String> {
+    let settings = SANITIZER_SETTINGS
+        .read()
+        .ok()
+        .and_then(|s| s.clone())
+        .unwrap_or_default();
+
+    let stripped = vars_to_strip(
+        std::env::vars_os().filter_map(|(k, _)| k.into_string().ok()),
+        &settings,
+        crate::commands::certificates::has_custom_ca(),
+    );
+
+    for key in &stripped {
+        log::info!("Stripping environment variable from child process: {}", key);
+    }
+
+    stripped
+}
+
+fn vars_to_strip(
+    keys: impl IntoIterator<Item = String>,
+    settings: &EnvSanitizerSettings,
+    custom_ca_configured: bool,
+) -> Vec<String> {
+    if !settings.enabled {
+        return Vec::new();
+    }
+
+    let mut stripped: Vec<String> = keys
+        .into_iter()
+        .filter(|key| {
+            let denied = DEFAULT_DENY.iter().any(|p| matches_pattern(p, key))
+                || settings.deny.iter().any(|p| matches_pattern(p, key))
+                || (custom_ca_configured
+                    && TLS_INTERCEPTOR_VARS.iter().any(|p| matches_pattern(p, key)));
+            denied && !settings.allow.iter().any(|p| matches_pattern(p, key))
+        })
+        .collect();
+    stripped.sort();
+    stripped
+}
+
+fn matches_pattern(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.trim();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key
+            .get(..prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(prefix)),
+        None => key.eq_ignore_ascii_case(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_vars_to_strip() {
+        let env = keys(&[
+            "PATH",
+            "ANTHROPIC_API_KEY",
+            "ANTHROPIC_BASE_URL",
+            "ANTHROPIC_MODEL",
+            "NODE_OPTIONS",
+            "SSL_CERT_FILE",
+            "ACME_TOKEN",
+        ]);
+
+        let defaults = EnvSanitizerSettings::default();
+        assert_eq!(
+            vars_to_strip(env.clone(), &defaults, false),
+            keys(&["ANTHROPIC_MODEL", "NODE_OPTIONS"])
+        );
+        assert_eq!(
+            vars_to_strip(env.clone(), &defaults, true),
+            keys(&["ANTHROPIC_MODEL", "NODE_OPTIONS", "SSL_CERT_FILE"])
+        );
+
+        let custom = EnvSanitizerSettings {
+            enabled: true,
+            deny: keys(&["acme_*"]),
+            allow: keys(&["NODE_OPTIONS"]),
+        };
+        assert_eq!(
+            vars_to_strip(env.clone(), &custom, false),
+            keys(&["ACME_TOKEN", "ANTHROPIC_MODEL"])
+        );
+
+        let disabled = EnvSanitizerSettings {
+            enabled: false,
+            ..custom
+        };
+        assert!(vars_to_strip(env, &disabled, true).is_empty());
+    }
+
+    #[test]
+    fn test_apply_child_env() {
+        use crate::commands::certificates::{apply_ca_certificate_settings, CaCertificateSettings};
+        use std::ffi::OsStr;
+
+        let env_of = |cmd: &std::process::Command, key: &str| {
+            cmd.get_envs()
+                .find(|(k, _)| *k == OsStr::new(key))
+                .and_then(|(_, v)| v.map(|v| v.to_string_lossy().to_string()))
+        };
+
+        apply_ca_certificate_settings(&CaCertificateSettings {
+            bundle_path: Some("/etc/opcode/ca.pem".to_string()),
+            enabled: true,
+        });
+        let mut cmd = std::process::Command::new("node");
+        apply_child_env(&mut cmd);
+        assert_eq!(
+            env_of(&cmd, "NODE_EXTRA_CA_CERTS").as_deref(),
+            Some("/etc/opcode/ca.pem")
+        );
+        assert_eq!(
+            env_of(&cmd, "SSL_CERT_FILE").as_deref(),
+            Some("/etc/opcode/ca.pem")
+        );
+
+        // A disabled bundle is not passed on
+        apply_ca_certificate_settings(&CaCertificateSettings {
+            bundle_path: Some("/etc/opcode/ca.pem".to_string()),
+            enabled: false,
+        });
+        let mut cmd = std::process::Command::new("node");
+        apply_child_env(&mut cmd);
+        assert_eq!(env_of(&cmd, "NODE_EXTRA_CA_CERTS"), None);
+    }
+}
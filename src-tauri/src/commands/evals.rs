@@ -0,0 +1,638 @@
+use log::{error, info, warn};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+use tokio::process::Command;
+
+use crate::commands::agents::{current_agent_version, get_agent, Agent, AgentDb};
+use crate::utils::paths::join_within;
+
+/// Default time limit for a single eval case
+const DEFAULT_CASE_TIMEOUT_SECS: u64 = 300;
+
+/// An assertion checked after an eval case has run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EvalAssertion {
+    /// The file (relative to the project path) must exist
+    FileExists { path: String },
+    /// The file (relative to the project path) must contain the given text
+    FileContains { path: String, text: String },
+    /// The agent process must exit with the given code
+    ExitCode { code: i32 },
+    /// The agent output must contain the given text
+    OutputContains { text: String },
+}
+
+/// A single task in an eval suite together with its expected outcome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    pub task: String,
+    pub assertions: Vec<EvalAssertion>,
+    pub timeout_secs: Option<u64>,
+}
+
+/// A named collection of eval cases run against a project directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalSuite {
+    pub id: Option<i64>,
+    pub name: String,
+    pub description: Option<String>,
+    pub project_path: String,
+    pub cases: Vec<EvalCase>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Outcome of a single assertion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub assertion: EvalAssertion,
+    pub passed: bool,
+    pub message: Option<String>,
+}
+
+/// Stored result of running one eval case against one agent version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalResult {
+    pub id: Option<i64>,
+    pub suite_id: i64,
+    pub case_name: String,
+    pub agent_id: i64,
+    pub agent_name: String,
+    pub agent_version: i64,
+    pub passed: bool,
+    pub exit_code: Option<i32>,
+    pub duration_ms: i64,
+    pub assertions: Vec<AssertionResult>,
+    pub created_at: String,
+}
+
+/// An agent configuration (agent at a specific version) shown as a matrix column
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EvalConfiguration {
+    pub agent_id: i64,
+    pub agent_name: String,
+    pub agent_version: i64,
+}
+
+/// Pass/fail matrix of the latest results for a suite
+///
+/// `cells[i][j]` is the result of case `cases[i]` for `configurations[j]`,
+/// or `None` if that combination has not been run yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalMatrix {
+    pub suite_id: i64,
+    pub cases: Vec<String>,
+    pub configurations: Vec<EvalConfiguration>,
+    pub cells: Vec<Vec<Option<bool>>>,
+}
+
+/// Create the eval tables
+pub fn init_eval_tables(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS eval_suites (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            project_path TEXT NOT NULL,
+            cases TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS eval_results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            suite_id INTEGER NOT NULL,
+            case_name TEXT NOT NULL,
+            agent_id INTEGER NOT NULL,
+            agent_name TEXT NOT NULL,
+            agent_version INTEGER NOT NULL,
+            passed BOOLEAN NOT NULL,
+            exit_code INTEGER,
+            duration_ms INTEGER NOT NULL,
+            assertions TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (suite_id) REFERENCES eval_suites(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn row_to_suite(row: &rusqlite::Row) -> rusqlite::Result<EvalSuite> {
+    let cases_json: String = row.get(4)?;
+    Ok(EvalSuite {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        description: row.get(2)?,
+        project_path: row.get(3)?,
+        cases: serde_json::from_str(&cases_json).unwrap_or_default(),
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+fn load_suite(conn: &Connection, id: i64) -> Result<EvalSuite, String> {
+    conn.query_row(
+        "SELECT id, name, description, project_path, cases, created_at, updated_at FROM eval_suites WHERE id = ?1",
+        params![id],
+        row_to_suite,
+    )
+    .map_err(|e| format!("Failed to load eval suite {}: {}", id, e))
+}
+
+/// List all eval suites
+#[tauri::command]
+pub async fn list_eval_suites(db: State<'_, AgentDb>) -> Result<Vec<EvalSuite>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, name, description, project_path, cases, created_at, updated_at FROM eval_suites ORDER BY created_at DESC")
+        .map_err(|e| e.to_string())?;
+
+    let suites = stmt
+        .query_map([], row_to_suite)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(suites)
+}
+
+/// Create a new eval suite
+#[tauri::command]
+pub async fn create_eval_suite(
+    db: State<'_, AgentDb>,
+    name: String,
+    description: Option<String>,
+    project_path: String,
+    cases: Vec<EvalCase>,
+) -> Result<EvalSuite, String> {
+    if cases.is_empty() {
+        return Err("An eval suite needs at least one case".to_string());
+    }
+
+    let project_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .to_string();
+    let cases_json = serde_json::to_string(&cases).map_err(|e| e.to_string())?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO eval_suites (name, description, project_path, cases) VALUES (?1, ?2, ?3, ?4)",
+        params![name, description, project_path, cases_json],
+    )
+    .map_err(|e| e.to_string())?;
+
+    load_suite(&conn, conn.last_insert_rowid())
+}
+
+/// Update the definition of an existing eval suite
+#[tauri::command]
+pub async fn update_eval_suite(
+    db: State<'_, AgentDb>,
+    id: i64,
+    name: String,
+    description: Option<String>,
+    project_path: String,
+    cases: Vec<EvalCase>,
+) -> Result<EvalSuite, String> {
+    let project_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .to_string();
+    let cases_json = serde_json::to_string(&cases).map_err(|e| e.to_string())?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "UPDATE eval_suites SET name = ?1, description = ?2, project_path = ?3, cases = ?4, updated_at = CURRENT_TIMESTAMP WHERE id = ?5",
+        params![name, description, project_path, cases_json, id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    load_suite(&conn, id)
+}
+
+/// Delete an eval suite and its results
+#[tauri::command]
+pub async fn delete_eval_suite(db: State<'_, AgentDb>, id: i64) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    conn.execute("DELETE FROM eval_results WHERE suite_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM eval_suites WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Run every case of a suite against each of the given agents
+///
+/// Cases run sequentially in the suite's project directory, since they may
+/// modify files checked by later assertions. Each result is stored together
+/// with the agent version it was produced by.
+#[tauri::command]
+pub async fn run_eval_suite(
+    app: AppHandle,
+    db: State<'_, AgentDb>,
+    suite_id: i64,
+    agent_ids: Vec<i64>,
+) -> Result<Vec<EvalResult>, String> {
+    let suite = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        load_suite(&conn, suite_id)?
+    };
+
+    if agent_ids.is_empty() {
+        return Err("Select at least one agent to evaluate".to_string());
+    }
+
+    let claude_path = crate::claude_binary::find_claude_binary(&app)?;
+    let mut results = Vec::new();
+
+    for agent_id in agent_ids {
+        let agent = get_agent(db.clone(), agent_id).await?;
+        let version = {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            current_agent_version(&conn, agent_id).map_err(|e| e.to_string())?
+        };
+        info!(
+            "Running eval suite '{}' against agent '{}' (version {})",
+            suite.name, agent.name, version
+        );
+
+        for case in &suite.cases {
+            let result = run_eval_case(&claude_path, &suite, case, &agent, version).await;
+            let result = store_result(&db, result)?;
+            results.push(result);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Run a single case and evaluate its assertions
+async fn run_eval_case(
+    claude_path: &str,
+    suite: &EvalSuite,
+    case: &EvalCase,
+    agent: &Agent,
+    agent_version: i64,
+) -> EvalResult {
+    let suite_id = suite.id.unwrap_or_default();
+    let agent_id = agent.id.unwrap_or_default();
+    let timeout = Duration::from_secs(case.timeout_secs.unwrap_or(DEFAULT_CASE_TIMEOUT_SECS));
+
+    let mut cmd = Command::from(crate::claude_binary::create_command_with_env(claude_path));
+    cmd.args([
+        "-p",
+        &case.task,
+        "--system-prompt",
+        &agent.system_prompt,
+        "--model",
+        &agent.model,
+        "--output-format",
+        "stream-json",
+        "--verbose",
+        "--dangerously-skip-permissions",
+    ])
+    .current_dir(&suite.project_path)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .kill_on_drop(true);
+
+    let start = Instant::now();
+    let (exit_code, output, run_error) = match cmd.spawn() {
+        Ok(child) => match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(out)) => (
+                out.status.code(),
+                String::from_utf8_lossy(&out.stdout).to_string(),
+                None,
+            ),
+            Ok(Err(e)) => (
+                None,
+                String::new(),
+                Some(format!("Failed to wait for agent: {}", e)),
+            ),
+            Err(_) => (
+                None,
+                String::new(),
+                Some(format!("Case timed out after {}s", timeout.as_secs())),
+            ),
+        },
+        Err(e) => (
+            None,
+            String::new(),
+            Some(format!("Failed to spawn Claude: {}", e)),
+        ),
+    };
+    let duration_ms = start.elapsed().as_millis() as i64;
+
+    let assertions: Vec<AssertionResult> = match &run_error {
+        Some(message) => {
+            warn!("Eval case '{}' did not complete: {}", case.name, message);
+            case.assertions
+                .iter()
+                .map(|assertion| AssertionResult {
+                    assertion: assertion.clone(),
+                    passed: false,
+                    message: Some(message.clone()),
+                })
+                .collect()
+        }
+        None => case
+            .assertions
+            .iter()
+            .map(|assertion| check_assertion(assertion, &suite.project_path, exit_code, &output))
+            .collect(),
+    };
+
+    let passed = run_error.is_none() && assertions.iter().all(|a| a.passed);
+
+    EvalResult {
+        id: None,
+        suite_id,
+        case_name: case.name.clone(),
+        agent_id,
+        agent_name: agent.name.clone(),
+        agent_version,
+        passed,
+        exit_code,
+        duration_ms,
+        assertions,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    }
+}
+
+/// Evaluate one assertion against the project directory and process outcome
+fn check_assertion(
+    assertion: &EvalAssertion,
+    project_path: &str,
+    exit_code: Option<i32>,
+    output: &str,
+) -> AssertionResult {
+    let (passed, message) = match assertion {
+        EvalAssertion::FileExists { path } => match join_within(project_path, path) {
+            Ok(file) => {
+                let exists = file.exists();
+                (
+                    exists,
+                    (!exists).then(|| format!("{} does not exist", path)),
+                )
+            }
+            Err(e) => (false, Some(e.to_string())),
+        },
+        EvalAssertion::FileContains { path, text } => {
+            match join_within(project_path, path)
+                .and_then(|file| Ok(std::fs::read_to_string(file)?))
+            {
+                Ok(content) if content.contains(text.as_str()) => (true, None),
+                Ok(_) => (
+                    false,
+                    Some(format!("{} does not contain the expected text", path)),
+                ),
+                Err(e) => (false, Some(format!("Failed to read {}: {}", path, e))),
+            }
+        }
+        EvalAssertion::ExitCode { code } => match exit_code {
+            Some(actual) if actual == *code => (true, None),
+            Some(actual) => (
+                false,
+                Some(format!("Expected exit code {}, got {}", code, actual)),
+            ),
+            None => (
+                false,
+                Some("Process was terminated by a signal".to_string()),
+            ),
+        },
+        EvalAssertion::OutputContains { text } => {
+            let found = output.contains(text.as_str());
+            (
+                found,
+                (!found).then(|| "Output does not contain the expected text".to_string()),
+            )
+        }
+    };
+
+    AssertionResult {
+        assertion: assertion.clone(),
+        passed,
+        message,
+    }
+}
+
+/// Persist an eval result and return it with its database id
+fn store_result(db: &State<'_, AgentDb>, mut result: EvalResult) -> Result<EvalResult, String> {
+    let assertions_json = serde_json::to_string(&result.assertions).map_err(|e| e.to_string())?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO eval_results (suite_id, case_name, agent_id, agent_name, agent_version, passed, exit_code, duration_ms, assertions, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            result.suite_id,
+            result.case_name,
+            result.agent_id,
+            result.agent_name,
+            result.agent_version,
+            result.passed,
+            result.exit_code,
+            result.duration_ms,
+            assertions_json,
+            result.created_at,
+        ],
+    )
+    .map_err(|e| {
+        error!("Failed to store eval result: {}", e);
+        e.to_string()
+    })?;
+
+    result.id = Some(conn.last_insert_rowid());
+    Ok(result)
+}
+
+/// List stored results for a suite, newest first
+#[tauri::command]
+pub async fn list_eval_results(
+    db: State<'_, AgentDb>,
+    suite_id: i64,
+) -> Result<Vec<EvalResult>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, suite_id, case_name, agent_id, agent_name, agent_version, passed, exit_code, duration_ms, assertions, created_at
+             FROM eval_results WHERE suite_id = ?1 ORDER BY id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let results = stmt
+        .query_map(params![suite_id], |row| {
+            let assertions_json: String = row.get(9)?;
+            Ok(EvalResult {
+                id: Some(row.get(0)?),
+                suite_id: row.get(1)?,
+                case_name: row.get(2)?,
+                agent_id: row.get(3)?,
+                agent_name: row.get(4)?,
+                agent_version: row.get(5)?,
+                passed: row.get(6)?,
+                exit_code: row.get(7)?,
+                duration_ms: row.get(8)?,
+                assertions: serde_json::from_str(&assertions_json).unwrap_or_default(),
+                created_at: row.get(10)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(results)
+}
+
+/// Build the pass/fail matrix for a suite from the latest result of every
+/// case and agent version combination
+#[tauri::command]
+pub async fn get_eval_matrix(db: State<'_, AgentDb>, suite_id: i64) -> Result<EvalMatrix, String> {
+    let suite = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        load_suite(&conn, suite_id)?
+    };
+    let results = list_eval_results(db, suite_id).await?;
+
+    let cases: Vec<String> = suite.cases.iter().map(|c| c.name.clone()).collect();
+
+    // Results are ordered newest first, so the first one seen for a cell wins
+    let mut configurations: Vec<EvalConfiguration> = Vec::new();
+    let mut latest: std::collections::HashMap<(String, usize), bool> =
+        std::collections::HashMap::new();
+
+    for result in &results {
+        let configuration = EvalConfiguration {
+            agent_id: result.agent_id,
+            agent_name: result.agent_name.clone(),
+            agent_version: result.agent_version,
+        };
+        let column = match configurations.iter().position(|c| *c == configuration) {
+            Some(column) => column,
+            None => {
+                configurations.push(configuration);
+                configurations.len() - 1
+            }
+        };
+        latest
+            .entry((result.case_name.clone(), column))
+            .or_insert(result.passed);
+    }
+
+    let cells = cases
+        .iter()
+        .map(|case| {
+            (0..configurations.len())
+                .map(|column| latest.get(&(case.clone(), column)).copied())
+                .collect()
+        })
+        .collect();
+
+    Ok(EvalMatrix {
+        suite_id,
+        cases,
+        configurations,
+        cells,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(assertion: EvalAssertion, project: &std::path::Path) -> AssertionResult {
+        check_assertion(
+            &assertion,
+            &project.to_string_lossy(),
+            Some(0),
+            "All tests passed",
+        )
+    }
+
+    #[test]
+    fn test_file_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("README.md"), "# Project").unwrap();
+
+        let exists = |path: &str| EvalAssertion::FileExists {
+            path: path.to_string(),
+        };
+        assert!(check(exists("README.md"), dir.path()).passed);
+        assert!(!check(exists("missing.md"), dir.path()).passed);
+    }
+
+    #[test]
+    fn test_file_contains() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("README.md"), "# Project").unwrap();
+
+        let contains = |path: &str, text: &str| EvalAssertion::FileContains {
+            path: path.to_string(),
+            text: text.to_string(),
+        };
+        assert!(check(contains("README.md", "Project"), dir.path()).passed);
+        assert!(!check(contains("README.md", "Changelog"), dir.path()).passed);
+        assert!(!check(contains("missing.md", "Project"), dir.path()).passed);
+    }
+
+    #[test]
+    fn test_file_assertions_stay_in_project() {
+        let parent = tempfile::tempdir().unwrap();
+        let project = parent.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        std::fs::write(parent.path().join("secret.txt"), "token").unwrap();
+
+        let outside = EvalAssertion::FileExists {
+            path: "../secret.txt".to_string(),
+        };
+        let result = check(outside, &project);
+        assert!(!result.passed);
+        assert!(result.message.unwrap().contains("within the project"));
+
+        let absolute = EvalAssertion::FileContains {
+            path: parent
+                .path()
+                .join("secret.txt")
+                .to_string_lossy()
+                .to_string(),
+            text: "token".to_string(),
+        };
+        assert!(!check(absolute, &project).passed);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check(EvalAssertion::ExitCode { code: 0 }, dir.path()).passed);
+        assert!(!check(EvalAssertion::ExitCode { code: 1 }, dir.path()).passed);
+
+        let killed = check_assertion(&EvalAssertion::ExitCode { code: 0 }, "", None, "");
+        assert!(!killed.passed);
+    }
+
+    #[test]
+    fn test_output_contains() {
+        let dir = tempfile::tempdir().unwrap();
+        let contains = |text: &str| EvalAssertion::OutputContains {
+            text: text.to_string(),
+        };
+        assert!(check(contains("tests passed"), dir.path()).passed);
+        assert!(!check(contains("error"), dir.path()).passed);
+    }
+}
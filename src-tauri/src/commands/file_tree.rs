@@ -0,0 +1,493 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::watcher::{spawn_watcher, PathWatcher, WatchOptions};
+use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
+use crate::utils::path_matcher::PathMatcher;
+use crate::utils::paths::{check_unc_available, join_within, parse_user_path, UNC_PROBE_TIMEOUT};
+
+/// Git status marker for a tree entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitFileStatus {
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+    Untracked,
+    Conflicted,
+}
+
+/// A file or directory in the project tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTreeNode {
+    pub name: String,
+    /// Path relative to the project root, using `/` separators
+    pub path: String,
+    pub is_directory: bool,
+    /// File size in bytes (0 for directories)
+    pub size: u64,
+    /// Last modification time as Unix seconds
+    pub modified: Option<i64>,
+    pub is_ignored: bool,
+    /// Git status of the file, or of any changed file below a directory
+    pub git_status: Option<GitFileStatus>,
+    /// Whether a directory has entries, so the UI can show an expander
+    pub has_children: bool,
+    /// Loaded children; `None` until the directory is expanded
+    pub children: Option<Vec<FileTreeNode>>,
+}
+
+/// Payload of the `file-tree-changed` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTreeChange {
+    pub project_path: String,
+    /// Directories (relative to the project) whose listing changed
+    pub directories: Vec<String>,
+    /// The git index or HEAD changed, so status markers may be stale
+    pub git_status_changed: bool,
+}
+
+/// Active watchers keyed by project path
+static WATCHERS: Mutex<Option<HashMap<String, PathWatcher>>> = Mutex::new(None);
+
+/// List a directory of a project, expanding `depth` levels
+///
+/// `path` is relative to the project root (empty for the root). With the
+/// default depth of 1 only direct children are returned; deeper levels are
+/// fetched lazily as the user expands the tree.
+#[tauri::command]
+pub async fn get_file_tree(
+    project_path: String,
+    path: Option<String>,
+    depth: Option<usize>,
+) -> Result<Vec<FileTreeNode>, String> {
+    let root = parse_user_path(&project_path).map_err(|e| e.to_string())?;
+    let relative = path.unwrap_or_default();
+    let depth = depth.unwrap_or(1).max(1);
+
+    let dir = join_within(&root, &relative).map_err(|e| e.to_string())?;
+
+    check_unc_available(&root, UNC_PROBE_TIMEOUT).map_err(|e| e.to_string())?;
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {}", dir.display()));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let context = TreeContext::load(&root);
+        read_tree(&context, &dir, depth)
+    })
+    .await
+    .map_err(|e| format!("File tree task failed: {}", e))?
+}
+
+/// Start emitting `file-tree-changed` events for a project
+///
+/// Changes are debounced, and changes below gitignored directories such as
+/// `node_modules` or `target` are not reported.
+#[tauri::command]
+pub async fn watch_file_tree(app: AppHandle, project_path: String) -> Result<(), String> {
+    let mut watchers = WATCHERS.lock().map_err(|e| e.to_string())?;
+    let watchers = watchers.get_or_insert_with(HashMap::new);
+    if watchers.contains_key(&project_path) {
+        return Ok(());
+    }
+
+    let root = parse_user_path(&project_path).map_err(|e| e.to_string())?;
+    check_unc_available(&root, UNC_PROBE_TIMEOUT).map_err(|e| e.to_string())?;
+    let event_root = root.clone();
+    let event_project = project_path.clone();
+    // Recent changes are looked up by the parsed path, not the string the UI sent
+    let record_project = root.to_string_lossy().to_string();
+
+    let watcher = spawn_watcher(&root, &WatchOptions::default(), move |changes| {
+        let files: Vec<String> = changes
+            .iter()
+            .map(|change| change.path.clone())
+            .filter(|path| path != ".git" && !path.starts_with(".git/"))
+            .collect();
+        crate::commands::recent_changes::record_file_events(&record_project, &files);
+
+        let paths: Vec<PathBuf> = changes
+            .iter()
+            .map(|change| event_root.join(&change.path))
+            .collect();
+        if let Some(change) = summarize_event(&event_project, &event_root, &paths) {
+            let _ = app.emit("file-tree-changed", &change);
+        }
+    })?;
+
+    info!("Watching file tree of {}", project_path);
+    watchers.insert(project_path, watcher);
+    Ok(())
+}
+
+/// Stop watching a project
+#[tauri::command]
+pub async fn unwatch_file_tree(project_path: String) -> Result<bool, String> {
+    let mut watchers = WATCHERS.lock().map_err(|e| e.to_string())?;
+    let removed = watchers
+        .as_mut()
+        .and_then(|w| w.remove(&project_path))
+        .is_some();
+    if removed {
+        info!("Stopped watching file tree of {}", project_path);
+    }
+    Ok(removed)
+}
+
+/// Ignore rules and git status shared by one tree request
+struct TreeContext {
+    root: PathBuf,
+    /// Status by project-relative path; directory entries end with `/`
+    git_status: Option<HashMap<String, GitFileStatus>>,
+    /// Paths reported as ignored by git; directory entries end with `/`
+    git_ignored: Vec<String>,
+    /// Fallback for projects that are not git repositories
+    ignore_rules: Option<PathMatcher>,
+}
+
+impl TreeContext {
+    fn load(root: &Path) -> Self {
+        match load_git_status(root) {
+            Some((status, ignored)) => Self {
+                root: root.to_path_buf(),
+                git_status: Some(status),
+                git_ignored: ignored,
+                ignore_rules: None,
+            },
+            None => Self {
+                root: root.to_path_buf(),
+                git_status: None,
+                git_ignored: Vec::new(),
+                ignore_rules: Some(PathMatcher::for_project(root)),
+            },
+        }
+    }
+
+    fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        if let Some(rules) = &self.ignore_rules {
+            return rules.is_match(relative, is_dir);
+        }
+        self.git_ignored
+            .iter()
+            .any(|ignored| covers(ignored, relative, is_dir))
+    }
+
+    fn status(&self, relative: &str, is_dir: bool) -> Option<GitFileStatus> {
+        let statuses = self.git_status.as_ref()?;
+        if let Some(status) = statuses.get(relative) {
+            return Some(*status);
+        }
+
+        // Untracked directories are reported once; changes inside a
+        // directory mark the directory itself as modified
+        statuses.iter().find_map(|(path, status)| {
+            if path.ends_with('/') && covers(path, relative, is_dir) {
+                Some(*status)
+            } else if is_dir && path.starts_with(&format!("{}/", relative)) {
+                Some(GitFileStatus::Modified)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Whether a git path (directories end with `/`) equals or contains `relative`
+fn covers(git_path: &str, relative: &str, is_dir: bool) -> bool {
+    match git_path.strip_suffix('/') {
+        Some(dir) => relative == dir || relative.starts_with(git_path),
+        None => !is_dir && relative == git_path,
+    }
+}
+
+fn read_tree(context: &TreeContext, dir: &Path, depth: usize) -> Result<Vec<FileTreeNode>, String> {
+    let mut nodes = Vec::new();
+
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read directory: {}", e))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" {
+            continue;
+        }
+
+        let entry_path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let is_directory = metadata.is_dir();
+        let relative = relative_path(&context.root, &entry_path);
+
+        let has_children = is_directory
+            && fs::read_dir(&entry_path)
+                .map(|mut d| d.next().is_some())
+                .unwrap_or(false);
+
+        let children = if is_directory && depth > 1 {
+            Some(read_tree(context, &entry_path, depth - 1)?)
+        } else {
+            None
+        };
+
+        nodes.push(FileTreeNode {
+            name,
+            is_directory,
+            size: if is_directory { 0 } else { metadata.len() },
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64),
+            is_ignored: context.is_ignored(&relative, is_directory),
+            git_status: context.status(&relative, is_directory),
+            has_children,
+            children,
+            path: relative,
+        });
+    }
+
+    // Directories first, then case-insensitive by name
+    nodes.sort_by(|a, b| {
+        b.is_directory
+            .cmp(&a.is_directory)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+
+    Ok(nodes)
+}
+
+pub(crate) fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative.to_path_buf(),
+        // Watcher events can spell the root differently (`c:\` vs `C:\`)
+        Err(_) if crate::utils::paths::path_contains(root, path) => {
+            crate::utils::paths::make_relative(root, path)
+                .filter(|relative| relative != Path::new("."))
+                .unwrap_or_default()
+        }
+        Err(_) => path.to_path_buf(),
+    };
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// Run `git status` for the project, returning statuses and ignored paths
+/// relative to the project root, or `None` if it is not a git repository
+pub(crate) fn load_git_status(
+    root: &Path,
+) -> Option<(HashMap<String, GitFileStatus>, Vec<String>)> {
+    let prefix = run_git(root, &["rev-parse", "--show-prefix"])?;
+    let prefix = prefix.trim();
+
+    let output = run_git(
+        root,
+        &[
+            "status",
+            "--porcelain=v1",
+            "-z",
+            "--ignored",
+            "--untracked-files=normal",
+            "--",
+            ".",
+        ],
+    )?;
+
+    let (entries, ignored) = parse_porcelain(&output);
+    let strip = |path: String| path.strip_prefix(prefix).map(str::to_string);
+
+    Some((
+        entries
+            .into_iter()
+            .filter_map(|(path, status)| strip(path).map(|p| (p, status)))
+            .collect(),
+        ignored.into_iter().filter_map(strip).collect(),
+    ))
+}
+
+pub(crate) fn run_git(root: &Path, args: &[&str]) -> Option<String> {
+    let output = run_with_timeout_blocking(
+        Command::new("git").arg("-C").arg(root).args(args),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `git status --porcelain=v1 -z --ignored` output
+fn parse_porcelain(output: &str) -> (Vec<(String, GitFileStatus)>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut ignored = Vec::new();
+    let mut records = output.split('\0').filter(|r| !r.is_empty());
+
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (code, path) = record.split_at(3);
+        let mut chars = code.chars();
+        let (x, y) = (chars.next().unwrap_or(' '), chars.next().unwrap_or(' '));
+
+        let status = match (x, y) {
+            ('!', '!') => {
+                ignored.push(path.to_string());
+                continue;
+            }
+            ('?', '?') => GitFileStatus::Untracked,
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => GitFileStatus::Conflicted,
+            ('R', _) | ('C', _) => {
+                // Renames and copies are followed by the original path
+                records.next();
+                GitFileStatus::Renamed
+            }
+            ('A', _) => GitFileStatus::Added,
+            ('D', _) | (_, 'D') => GitFileStatus::Deleted,
+            _ => GitFileStatus::Modified,
+        };
+        entries.push((path.to_string(), status));
+    }
+
+    (entries, ignored)
+}
+
+/// Turn watcher paths into the set of directories whose listing changed
+fn summarize_event(project_path: &str, root: &Path, paths: &[PathBuf]) -> Option<FileTreeChange> {
+    let mut directories = Vec::new();
+    let mut git_status_changed = false;
+
+    for path in paths {
+        let relative = relative_path(root, path);
+
+        if let Some(git_path) = relative.strip_prefix(".git/") {
+            if git_path == "index" || git_path == "HEAD" {
+                git_status_changed = true;
+            }
+            continue;
+        }
+        if relative == ".git" {
+            continue;
+        }
+
+        let parent = match relative.rsplit_once('/') {
+            Some((parent, _)) => parent.to_string(),
+            None => String::new(),
+        };
+        if !directories.contains(&parent) {
+            directories.push(parent);
+        }
+    }
+
+    if directories.is_empty() && !git_status_changed {
+        return None;
+    }
+
+    Some(FileTreeChange {
+        project_path: project_path.to_string(),
+        directories,
+        git_status_changed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = " M src/main.rs\0?? notes/\0R  new.rs\0old.rs\0!! target/\0UU conflict.rs\0";
+        let (entries, ignored) = parse_porcelain(output);
+
+        assert_eq!(
+            entries,
+            vec![
+                ("src/main.rs".to_string(), GitFileStatus::Modified),
+                ("notes/".to_string(), GitFileStatus::Untracked),
+                ("new.rs".to_string(), GitFileStatus::Renamed),
+                ("conflict.rs".to_string(), GitFileStatus::Conflicted),
+            ]
+        );
+        assert_eq!(ignored, vec!["target/".to_string()]);
+    }
+
+    #[test]
+    fn test_tree_context_markers() {
+        let context = TreeContext {
+            root: PathBuf::from("/project"),
+            git_status: Some(
+                vec![
+                    ("src/main.rs".to_string(), GitFileStatus::Modified),
+                    ("notes/".to_string(), GitFileStatus::Untracked),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            git_ignored: vec!["target/".to_string()],
+            ignore_rules: None,
+        };
+
+        assert_eq!(
+            context.status("src/main.rs", false),
+            Some(GitFileStatus::Modified)
+        );
+        assert_eq!(context.status("src", true), Some(GitFileStatus::Modified));
+        assert_eq!(
+            context.status("notes/todo.md", false),
+            Some(GitFileStatus::Untracked)
+        );
+        assert_eq!(context.status("README.md", false), None);
+        assert!(context.is_ignored("target", true));
+        assert!(context.is_ignored("target/debug/app", false));
+        assert!(!context.is_ignored("src", true));
+    }
+
+    #[test]
+    fn test_summarize_event() {
+        let root = Path::new("/project");
+        let change = summarize_event(
+            "/project",
+            root,
+            &[
+                root.join("src/lib.rs"),
+                root.join("README.md"),
+                root.join(".git/index"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(change.directories, vec!["src".to_string(), String::new()]);
+        assert!(change.git_status_changed);
+
+        assert!(summarize_event("/project", root, &[root.join(".git/objects/ab")]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_file_tree_stays_in_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let project = dir.path().to_string_lossy().to_string();
+
+        let outside = std::env::temp_dir().to_string_lossy().to_string();
+        for path in [outside.as_str(), "..", "src/../.."] {
+            assert!(
+                get_file_tree(project.clone(), Some(path.to_string()), None)
+                    .await
+                    .is_err(),
+                "{} should be rejected",
+                path
+            );
+        }
+        assert!(get_file_tree(project, Some("src".to_string()), None)
+            .await
+            .is_ok());
+    }
+}
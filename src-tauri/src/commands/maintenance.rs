@@ -0,0 +1,534 @@
+use chrono::{Local, NaiveTime, Utc};
+use log::{info, warn};
+use rusqlite::{params, Connection, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::commands::agents::AgentDb;
+use crate::commands::storage_cleanup::StorageCategory;
+use crate::process::ProcessRegistryState;
+
+/// app_settings key the maintenance window is stored under (as JSON)
+const SETTINGS_KEY: &str = "maintenance_window";
+
+/// How often the scheduler checks whether maintenance may run
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Minimum time between two scheduled maintenance cycles
+const MIN_CYCLE_INTERVAL_HOURS: i64 = 20;
+
+/// Maintenance reports older than this are removed
+const REPORT_RETENTION_DAYS: i64 = 30;
+
+/// When background maintenance is allowed to run
+///
+/// Maintenance runs inside the daily `start`–`end` window (local time, may
+/// wrap past midnight) or, when `idle_minutes` is set, whenever there has
+/// been no keyboard or mouse input and no activity (see [`record_activity`])
+/// for that long.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub enabled: bool,
+    /// Window start as "HH:MM"
+    pub start: String,
+    /// Window end as "HH:MM"
+    pub end: String,
+    pub idle_minutes: Option<u32>,
+}
+
+impl Default for MaintenanceWindow {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            start: "02:00".to_string(),
+            end: "04:00".to_string(),
+            idle_minutes: Some(10),
+        }
+    }
+}
+
+/// Outcome of a single task within a maintenance cycle
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceTaskReport {
+    pub task: String,
+    pub success: bool,
+    pub detail: String,
+    pub duration_ms: i64,
+}
+
+/// What a maintenance cycle did
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceReport {
+    pub id: Option<i64>,
+    /// "scheduled" or "manual"
+    pub trigger: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub tasks: Vec<MaintenanceTaskReport>,
+}
+
+/// Whether background tasks may currently run, and why
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceStatus {
+    pub window_open: bool,
+    pub reason: String,
+    pub last_report: Option<MaintenanceReport>,
+}
+
+/// Unix time of the last activity, used for idle detection
+static LAST_ACTIVITY: AtomicI64 = AtomicI64::new(0);
+
+/// Guards against overlapping maintenance cycles
+static CYCLE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Create the maintenance tables
+pub fn init_maintenance_tables(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS maintenance_reports (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            trigger TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            tasks TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Note user-driven activity, postponing idle-triggered maintenance
+///
+/// Called when a process is spawned, the window gains focus, a checkpoint
+/// is created or restored, and the frontend reports input.
+pub fn record_activity() {
+    LAST_ACTIVITY.store(Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+/// Note keyboard or mouse input in the window, reported by the frontend
+#[tauri::command]
+pub fn report_user_activity() {
+    record_activity();
+}
+
+/// Get the maintenance window configuration
+#[tauri::command]
+pub async fn get_maintenance_window(db: State<'_, AgentDb>) -> Result<MaintenanceWindow, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_maintenance_window(&conn))
+}
+
+/// Save the maintenance window configuration
+#[tauri::command]
+pub async fn save_maintenance_window(
+    db: State<'_, AgentDb>,
+    window: MaintenanceWindow,
+) -> Result<(), String> {
+    parse_time(&window.start)?;
+    parse_time(&window.end)?;
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let value = serde_json::to_string(&window).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![SETTINGS_KEY, value],
+    )
+    .map_err(|e| format!("Failed to save {}: {}", SETTINGS_KEY, e))?;
+
+    Ok(())
+}
+
+/// Check whether background maintenance (including update downloads) may run now
+#[tauri::command]
+pub async fn get_maintenance_status(app: AppHandle) -> Result<MaintenanceStatus, String> {
+    let (window, last_report) = {
+        let db = app.state::<AgentDb>();
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        (load_maintenance_window(&conn), load_reports(&conn, 1)?.pop())
+    };
+
+    let (window_open, reason) = match window_open_reason(&window, &app) {
+        Some(reason) => (true, reason),
+        None => (false, window_closed_reason(&window)),
+    };
+
+    Ok(MaintenanceStatus {
+        window_open,
+        reason,
+        last_report,
+    })
+}
+
+/// Fail with the reason unless background work such as an update download
+/// may run now
+pub fn ensure_window_open(app: &AppHandle) -> Result<(), String> {
+    let window = with_connection(app, |conn| Ok(load_maintenance_window(conn)))?;
+    match window_open_reason(&window, app) {
+        Some(_) => Ok(()),
+        None => Err(window_closed_reason(&window)),
+    }
+}
+
+/// Run a maintenance cycle immediately, regardless of the window
+#[tauri::command]
+pub async fn run_maintenance_now(app: AppHandle) -> Result<MaintenanceReport, String> {
+    run_maintenance_cycle(&app, "manual").await
+}
+
+/// List recent maintenance reports, newest first
+#[tauri::command]
+pub async fn list_maintenance_reports(
+    db: State<'_, AgentDb>,
+    limit: Option<i64>,
+) -> Result<Vec<MaintenanceReport>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    load_reports(&conn, limit.unwrap_or(20))
+}
+
+/// Start the background scheduler that runs maintenance inside the window
+pub fn start_maintenance_scheduler(app: AppHandle) {
+    record_activity();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            let due = maintenance_due(&app);
+
+            if due {
+                if let Err(e) = run_maintenance_cycle(&app, "scheduled").await {
+                    warn!("Scheduled maintenance failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Clears [`CYCLE_RUNNING`] when the cycle ends, even by panicking
+struct CycleGuard;
+
+impl CycleGuard {
+    /// Mark a cycle as running, or `None` if one already is
+    fn acquire() -> Option<Self> {
+        (!CYCLE_RUNNING.swap(true, Ordering::SeqCst)).then_some(CycleGuard)
+    }
+}
+
+impl Drop for CycleGuard {
+    fn drop(&mut self) {
+        CYCLE_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Whether a scheduled maintenance cycle should start now
+fn maintenance_due(app: &AppHandle) -> bool {
+    let db = app.state::<AgentDb>();
+    let conn = match db.0.lock() {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("Maintenance scheduler could not lock database: {}", e);
+            return false;
+        }
+    };
+
+    let window = load_maintenance_window(&conn);
+    window.enabled && !ran_recently(&conn) && window_open_reason(&window, app).is_some()
+}
+
+/// Run every maintenance task and store the resulting report
+async fn run_maintenance_cycle(
+    app: &AppHandle,
+    trigger: &str,
+) -> Result<MaintenanceReport, String> {
+    let Some(_running) = CycleGuard::acquire() else {
+        return Err("A maintenance cycle is already running".to_string());
+    };
+
+    info!("Starting {} maintenance cycle", trigger);
+    let started_at = Utc::now().to_rfc3339();
+    let mut tasks = Vec::new();
+
+    let timer = Instant::now();
+    let result = crate::commands::agents::cleanup_finished_processes(app.state::<AgentDb>())
+        .await
+        .map(|runs| format!("Marked {} stale agent runs as completed", runs.len()));
+    tasks.push(task_report("stale_agent_runs", timer, result));
+
+    let timer = Instant::now();
+    let result = app
+        .state::<ProcessRegistryState>()
+        .0
+        .cleanup_finished_processes()
+        .await
+        .map(|runs| format!("Removed {} finished processes from the registry", runs.len()));
+    tasks.push(task_report("process_registry", timer, result));
+
+    let timer = Instant::now();
+    let result = with_connection(app, |conn| {
+        let cutoff = (Utc::now() - chrono::Duration::days(REPORT_RETENTION_DAYS)).to_rfc3339();
+        conn.execute(
+            "DELETE FROM maintenance_reports WHERE started_at < ?1",
+            params![cutoff],
+        )
+        .map(|removed| format!("Removed {} maintenance reports older than {} days", removed, REPORT_RETENTION_DAYS))
+        .map_err(|e| e.to_string())
+    });
+    tasks.push(task_report("report_retention", timer, result));
+
+    let timer = Instant::now();
+    let result = crate::commands::storage_cleanup::clean_storage(
+        app.clone(),
+        vec![StorageCategory::OrphanedCheckpointBlobs],
+    )
+    .await
+    .map(|results| {
+        format!(
+            "Removed {} orphaned checkpoint files ({} bytes)",
+            results.iter().map(|r| r.removed_files).sum::<u64>(),
+            results.iter().map(|r| r.freed_bytes).sum::<u64>()
+        )
+    });
+    tasks.push(task_report("storage_cleanup", timer, result));
+
+    let timer = Instant::now();
+    let result = with_connection(app, |conn| {
+        conn.execute_batch("PRAGMA optimize; VACUUM;")
+            .map(|_| "Optimized and compacted the database".to_string())
+            .map_err(|e| e.to_string())
+    });
+    tasks.push(task_report("database_compaction", timer, result));
+
+    let mut report = MaintenanceReport {
+        id: None,
+        trigger: trigger.to_string(),
+        started_at,
+        finished_at: Utc::now().to_rfc3339(),
+        tasks,
+    };
+
+    let saved = with_connection(app, |conn| {
+        let tasks_json = serde_json::to_string(&report.tasks).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO maintenance_reports (trigger, started_at, finished_at, tasks) VALUES (?1, ?2, ?3, ?4)",
+            params![report.trigger, report.started_at, report.finished_at, tasks_json],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(conn.last_insert_rowid())
+    });
+
+    report.id = Some(saved?);
+    info!(
+        "Maintenance cycle finished: {}/{} tasks succeeded",
+        report.tasks.iter().filter(|t| t.success).count(),
+        report.tasks.len()
+    );
+    Ok(report)
+}
+
+fn with_connection<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&Connection) -> Result<T, String>,
+) -> Result<T, String> {
+    let db = app.state::<AgentDb>();
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    f(&conn)
+}
+
+fn task_report(task: &str, timer: Instant, result: Result<String, String>) -> MaintenanceTaskReport {
+    let (success, detail) = match result {
+        Ok(detail) => (true, detail),
+        Err(e) => {
+            warn!("Maintenance task {} failed: {}", task, e);
+            (false, e)
+        }
+    };
+
+    MaintenanceTaskReport {
+        task: task.to_string(),
+        success,
+        detail,
+        duration_ms: timer.elapsed().as_millis() as i64,
+    }
+}
+
+/// Read the maintenance window from the database, falling back to defaults
+pub fn load_maintenance_window(conn: &Connection) -> MaintenanceWindow {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        params![SETTINGS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+fn load_reports(conn: &Connection, limit: i64) -> Result<Vec<MaintenanceReport>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, trigger, started_at, finished_at, tasks FROM maintenance_reports
+             ORDER BY id DESC LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let reports = stmt
+        .query_map(params![limit], |row| {
+            let tasks: String = row.get(4)?;
+            Ok(MaintenanceReport {
+                id: Some(row.get(0)?),
+                trigger: row.get(1)?,
+                started_at: row.get(2)?,
+                finished_at: row.get(3)?,
+                tasks: serde_json::from_str(&tasks).unwrap_or_default(),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(reports)
+}
+
+/// Whether a scheduled cycle already ran within the minimum interval
+fn ran_recently(conn: &Connection) -> bool {
+    let cutoff = (Utc::now() - chrono::Duration::hours(MIN_CYCLE_INTERVAL_HOURS)).to_rfc3339();
+    conn.query_row(
+        "SELECT COUNT(*) FROM maintenance_reports WHERE trigger = 'scheduled' AND started_at >= ?1",
+        params![cutoff],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+    .unwrap_or(false)
+}
+
+/// Explain why the window is closed
+fn window_closed_reason(window: &MaintenanceWindow) -> String {
+    if window.enabled {
+        "Outside the maintenance window".to_string()
+    } else {
+        "Maintenance is disabled".to_string()
+    }
+}
+
+/// Explain why the window is open, or `None` if it is closed
+fn window_open_reason(window: &MaintenanceWindow, app: &AppHandle) -> Option<String> {
+    if !window.enabled {
+        return None;
+    }
+
+    let now = Local::now().time();
+    if within_window(window, now) {
+        return Some(format!("Inside the {}–{} maintenance window", window.start, window.end));
+    }
+
+    let idle_minutes = idle_seconds(app)? / 60;
+    match window.idle_minutes {
+        Some(threshold) if idle_minutes >= i64::from(threshold) => {
+            Some(format!("Idle for {} minutes", idle_minutes))
+        }
+        _ => None,
+    }
+}
+
+/// Seconds since the last activity or input, or `None` while processes are
+/// still running
+fn idle_seconds(app: &AppHandle) -> Option<i64> {
+    let running = app
+        .state::<ProcessRegistryState>()
+        .0
+        .get_running_processes()
+        .map(|processes| !processes.is_empty())
+        .unwrap_or(true);
+    if running {
+        return None;
+    }
+
+    let since_activity = Utc::now().timestamp() - LAST_ACTIVITY.load(Ordering::Relaxed);
+    Some(match input_idle_seconds() {
+        Some(since_input) => since_activity.min(since_input),
+        None => since_activity,
+    })
+}
+
+/// Seconds since the last keyboard or mouse input in the session
+#[cfg(target_os = "windows")]
+fn input_idle_seconds() -> Option<i64> {
+    match crate::windows::input_idle::input_idle_time() {
+        Ok(idle) => Some(idle.as_secs() as i64),
+        Err(e) => {
+            warn!("Failed to read the input idle time: {}", e);
+            None
+        }
+    }
+}
+
+/// Seconds since the last keyboard or mouse input in the session, which
+/// only the frontend's reports (see [`report_user_activity`]) cover here
+#[cfg(not(target_os = "windows"))]
+fn input_idle_seconds() -> Option<i64> {
+    None
+}
+
+/// Whether `now` falls inside the daily window, which may wrap past midnight
+fn within_window(window: &MaintenanceWindow, now: NaiveTime) -> bool {
+    let (Ok(start), Ok(end)) = (parse_time(&window.start), parse_time(&window.end)) else {
+        return false;
+    };
+
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| format!("Invalid time '{}', expected HH:MM", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str) -> MaintenanceWindow {
+        MaintenanceWindow {
+            start: start.to_string(),
+            end: end.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn at(time: &str) -> NaiveTime {
+        parse_time(time).unwrap()
+    }
+
+    #[test]
+    fn test_within_window() {
+        let night = window("02:00", "04:00");
+        assert!(within_window(&night, at("02:00")));
+        assert!(within_window(&night, at("03:59")));
+        assert!(!within_window(&night, at("04:00")));
+        assert!(!within_window(&night, at("12:00")));
+
+        let wrapping = window("23:00", "01:30");
+        assert!(within_window(&wrapping, at("23:30")));
+        assert!(within_window(&wrapping, at("00:45")));
+        assert!(!within_window(&wrapping, at("02:00")));
+
+        assert!(!within_window(&window("2am", "4am"), at("03:00")));
+    }
+
+    #[test]
+    fn test_cycle_guard_released_on_panic() {
+        let result = std::panic::catch_unwind(|| {
+            let _running = CycleGuard::acquire().unwrap();
+            assert!(CycleGuard::acquire().is_none());
+            panic!("maintenance task failed");
+        });
+        assert!(result.is_err());
+        assert!(CycleGuard::acquire().is_some());
+    }
+}
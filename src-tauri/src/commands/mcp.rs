@@ -4,7 +4,6 @@ use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
 use std::process::Command;
 use tauri::AppHandle;
 
@@ -683,7 +682,9 @@ pub async fn mcp_get_server_status() -> Result<HashMap<String, ServerStatus>, St
 pub async fn mcp_read_project_config(project_path: String) -> Result<MCPProjectConfig, String> {
     info!("Reading .mcp.json from project: {}", project_path);
 
-    let mcp_json_path = PathBuf::from(&project_path).join(".mcp.json");
+    let mcp_json_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .join(".mcp.json");
 
     if !mcp_json_path.exists() {
         return Ok(MCPProjectConfig {
@@ -714,13 +715,15 @@ pub async fn mcp_save_project_config(
 ) -> Result<String, String> {
     info!("Saving .mcp.json to project: {}", project_path);
 
-    let mcp_json_path = PathBuf::from(&project_path).join(".mcp.json");
+    let mcp_json_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .join(".mcp.json");
 
     let json_content = serde_json::to_string_pretty(&config)
         .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
-    fs::write(&mcp_json_path, json_content)
-        .map_err(|e| format!("Failed to write .mcp.json: {}", e))?;
+    crate::utils::atomic_write::atomic_write(&mcp_json_path, json_content)
+        .map_err(|e| format!("Failed to write .mcp.json: {:#}", e))?;
 
     Ok("Project MCP configuration saved".to_string())
 }
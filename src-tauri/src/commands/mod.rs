@@ -1,7 +1,32 @@
 pub mod agents;
 pub mod claude;
+pub mod evals;
 pub mod mcp;
 pub mod usage;
 pub mod storage;
 pub mod slash_commands;
 pub mod proxy;
+pub mod environment;
+pub mod certificates;
+pub mod deep_link;
+pub mod maintenance;
+pub mod search;
+pub mod file_tree;
+pub mod recent_changes;
+pub mod drop_target;
+pub mod runtime_config;
+pub mod storage_cleanup;
+pub mod watcher;
+pub mod privacy;
+pub mod platform;
+pub mod agent_runner;
+pub mod secrets;
+pub mod run_webhook;
+pub mod terminal_profile;
+pub mod agent_sandbox;
+pub mod user_presence;
+pub mod defender;
+pub mod efs;
+pub mod volume_protection;
+pub mod updates;
+pub mod process_dumps;
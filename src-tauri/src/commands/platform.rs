@@ -0,0 +1,268 @@
+//! Which platform features this build and OS offer
+//!
+//! Many features have a backend on some platforms only, and the fallbacks
+//! return an `Unsupported` error. [`get_platform_capabilities`] lets the
+//! frontend find out up front, so it can hide menus or explain why a
+//! feature is missing instead of calling into it and showing the error.
+//! Only features a command or run actually uses are listed.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::windows::unsupported::CommandError;
+
+/// Whether one feature is available, and how
+#[derive(Debug, Clone, Serialize)]
+pub struct PlatformCapability {
+    pub available: bool,
+    /// What implements the feature, e.g. `procfs` or `registry`
+    pub backend: Option<String>,
+    /// Why the feature is unavailable, for display
+    pub reason: Option<String>,
+}
+
+impl PlatformCapability {
+    fn available(backend: &str) -> Self {
+        Self {
+            available: true,
+            backend: Some(backend.to_string()),
+            reason: None,
+        }
+    }
+
+    /// Unavailable because the platform has no backend for `feature`
+    fn unsupported(feature: &'static str) -> Self {
+        Self::unavailable(crate::windows::Unsupported::new(feature).to_string())
+    }
+
+    fn unavailable(reason: impl Into<String>) -> Self {
+        Self {
+            available: false,
+            backend: None,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// The features of the running platform
+#[derive(Debug, Clone, Serialize)]
+pub struct PlatformCapabilities {
+    /// `windows`, `macos`, `linux`, ...
+    pub os: String,
+    pub arch: String,
+    /// Capabilities by feature name, e.g. `process_tree_kill`
+    pub features: BTreeMap<String, PlatformCapability>,
+}
+
+/// Killing a session's whole process tree
+fn process_tree_kill() -> PlatformCapability {
+    match crate::windows::process_manager::process_manager().backend() {
+        "unsupported" => PlatformCapability::unsupported("Process management"),
+        backend => PlatformCapability::available(backend),
+    }
+}
+
+/// Windows access control lists beyond owner-only permissions
+fn acls() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("dacl")
+    } else {
+        PlatformCapability::unsupported("Windows ACLs")
+    }
+}
+
+/// Running a program with administrator or root rights
+fn elevation() -> PlatformCapability {
+    let elevation = crate::windows::elevation::elevation();
+    if elevation.is_available() {
+        PlatformCapability::available(elevation.backend())
+    } else if elevation.backend() == "unsupported" {
+        PlatformCapability::unsupported("Running programs as administrator")
+    } else {
+        PlatformCapability::unavailable(format!(
+            "{} is not installed or is disabled",
+            elevation.backend()
+        ))
+    }
+}
+
+/// Storing API keys in the OS credential store
+fn secret_storage() -> PlatformCapability {
+    match crate::windows::secret_store::secret_store().backend() {
+        "unsupported" => PlatformCapability::unsupported("Secure credential storage"),
+        backend => PlatformCapability::available(backend),
+    }
+}
+
+/// Running the headless agent runner as a background service
+fn background_service() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("service_control_manager")
+    } else if cfg!(target_os = "macos") {
+        PlatformCapability::available("launchd")
+    } else if cfg!(target_os = "linux") {
+        // systemctl being installed doesn't mean a user manager runs, e.g.
+        // in containers or under WSL without systemd
+        let user_manager = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(|dir| std::path::Path::new(&dir).join("systemd").is_dir())
+            .unwrap_or(false);
+        if user_manager {
+            PlatformCapability::available("systemd")
+        } else {
+            PlatformCapability::unavailable("The systemd user manager is not running")
+        }
+    } else {
+        PlatformCapability::unsupported("Background services")
+    }
+}
+
+/// Running agents in a sandbox, see [`agent_sandbox`](crate::commands::agent_sandbox)
+fn agent_sandbox() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("appcontainer")
+    } else {
+        PlatformCapability::unsupported("Sandboxed agent runs")
+    }
+}
+
+/// Excluding directories from antivirus scanning, see
+/// [`defender`](crate::commands::defender)
+fn defender_exclusions() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("mp_preference")
+    } else {
+        PlatformCapability::unsupported("Defender exclusions")
+    }
+}
+
+/// Encrypting directories with the user's key, see
+/// [`efs`](crate::commands::efs)
+fn efs() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("efs")
+    } else {
+        PlatformCapability::unsupported("EFS encryption")
+    }
+}
+
+/// Reading the BitLocker state of drives, see
+/// [`volume_protection`](crate::commands::volume_protection)
+fn volume_protection() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("bitlocker")
+    } else {
+        PlatformCapability::unsupported("BitLocker status")
+    }
+}
+
+/// Writing minidumps of hung runs, see
+/// [`process_dumps`](crate::commands::process_dumps)
+fn process_dumps() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("minidump")
+    } else {
+        PlatformCapability::unsupported("Process dumps")
+    }
+}
+
+/// Moving deleted files to the trash
+fn trash() -> PlatformCapability {
+    if cfg!(target_os = "windows") {
+        PlatformCapability::available("recycle_bin")
+    } else if cfg!(target_os = "macos") {
+        PlatformCapability::available("ns_file_manager")
+    } else if which::which("gio").is_ok() {
+        PlatformCapability::available("gio")
+    } else {
+        PlatformCapability::available("xdg_home_trash")
+    }
+}
+
+/// The features this build offers on the running OS
+pub fn platform_capabilities() -> PlatformCapabilities {
+    let features = [
+        ("process_tree_kill", process_tree_kill()),
+        ("acls", acls()),
+        ("elevation", elevation()),
+        ("secret_storage", secret_storage()),
+        ("background_service", background_service()),
+        ("trash", trash()),
+        ("agent_sandbox", agent_sandbox()),
+        ("defender_exclusions", defender_exclusions()),
+        ("efs", efs()),
+        ("volume_protection", volume_protection()),
+        ("process_dumps", process_dumps()),
+    ];
+    PlatformCapabilities {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        features: features
+            .into_iter()
+            .map(|(name, capability)| (name.to_string(), capability))
+            .collect(),
+    }
+}
+
+/// Which platform features are available, for the frontend to adapt to
+#[tauri::command]
+pub async fn get_platform_capabilities() -> Result<PlatformCapabilities, String> {
+    tauri::async_runtime::spawn_blocking(platform_capabilities)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// What a privileged operation needs and whether this process has it
+///
+/// Takes an [`Operation`](crate::windows::preflight::Operation) and returns
+/// a [`RequirementReport`](crate::windows::preflight::RequirementReport), so
+/// the UI can ask for elevation before starting the operation. Preflight
+/// checks exist on Windows only; elsewhere this fails with the `unsupported`
+/// [`CommandError`].
+#[tauri::command]
+pub async fn preflight_operation(
+    operation: serde_json::Value,
+) -> Result<serde_json::Value, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::preflight::{preflight, Operation};
+
+        let operation: Operation =
+            serde_json::from_value(operation).map_err(|e| format!("Invalid operation: {}", e))?;
+        let report = tauri::async_runtime::spawn_blocking(move || preflight(&operation))
+            .await
+            .map_err(|e| e.to_string())??;
+        return Ok(serde_json::to_value(report).map_err(|e| e.to_string())?);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = operation;
+        Err(crate::windows::Unsupported::new("Preflight checks").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platform_capabilities() {
+        let capabilities = platform_capabilities();
+        assert_eq!(capabilities.os, std::env::consts::OS);
+        for (name, capability) in &capabilities.features {
+            assert_eq!(
+                capability.available,
+                capability.backend.is_some(),
+                "{} should name a backend exactly when available",
+                name
+            );
+            assert_eq!(capability.available, capability.reason.is_none());
+        }
+        assert!(capabilities.features["trash"].available);
+
+        #[cfg(unix)]
+        assert!(capabilities.features["process_tree_kill"].available);
+        #[cfg(not(target_os = "windows"))]
+        assert!(!capabilities.features["acls"].available);
+    }
+}
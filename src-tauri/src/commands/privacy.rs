@@ -0,0 +1,97 @@
+//! macOS privacy permissions for the settings UI
+//!
+//! Other platforms have no equivalent: the status list is empty there and
+//! requesting a permission fails with the `unsupported` [`CommandError`].
+
+use serde::Serialize;
+
+use crate::windows::unsupported::CommandError;
+
+/// App the Automation permission is checked against
+#[cfg(target_os = "macos")]
+const AUTOMATION_TARGET: &str = "com.apple.Terminal";
+
+/// A privacy permission and whether Opcode has it
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivacyPermissionStatus {
+    /// `full_disk_access`, `files_and_folders`, `automation` or `developer_tools`
+    pub permission: String,
+    /// `granted`, `denied`, `not_determined` or `unknown`
+    pub status: String,
+}
+
+/// Status of the privacy permissions sessions depend on
+#[tauri::command]
+pub async fn get_privacy_permissions() -> Result<Vec<PrivacyPermissionStatus>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        use crate::windows::tcc::{self, PrivacyPermission};
+
+        let automation = tcc::automation_status(AUTOMATION_TARGET, false)
+            .unwrap_or(tcc::PermissionStatus::Unknown);
+        let statuses = [
+            (
+                PrivacyPermission::FullDiskAccess,
+                tcc::full_disk_access_status(),
+            ),
+            (PrivacyPermission::Automation, automation),
+            (
+                PrivacyPermission::DeveloperTools,
+                tcc::developer_tools_status(),
+            ),
+        ];
+        statuses
+            .into_iter()
+            .map(|(permission, status)| {
+                Ok(PrivacyPermissionStatus {
+                    permission: to_name(&permission)?,
+                    status: to_name(&status)?,
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    Ok(Vec::new())
+}
+
+/// Ask for `permission`: shows the macOS prompt where there is one and
+/// otherwise opens its pane in System Settings
+#[tauri::command]
+pub async fn request_privacy_permission(permission: String) -> Result<(), CommandError> {
+    #[cfg(target_os = "macos")]
+    {
+        use crate::windows::tcc::{self, PermissionStatus, PrivacyPermission};
+
+        let permission: PrivacyPermission =
+            serde_json::from_value(serde_json::Value::String(permission))
+                .map_err(|e| format!("Unknown privacy permission: {}", e))?;
+
+        if permission == PrivacyPermission::Automation {
+            // The prompt blocks until the user answers
+            let status = tauri::async_runtime::spawn_blocking(|| {
+                tcc::automation_status(AUTOMATION_TARGET, true)
+            })
+            .await
+            .map_err(|e| e.to_string())??;
+            if status == PermissionStatus::Granted {
+                return Ok(());
+            }
+        }
+        Ok(tcc::open_privacy_settings(permission)?)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = permission;
+        Err(crate::windows::Unsupported::new("Privacy permissions").into())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn to_name<T: Serialize>(value: &T) -> Result<String, String> {
+    match serde_json::to_value(value).map_err(|e| e.to_string())? {
+        serde_json::Value::String(name) => Ok(name),
+        other => Err(format!("Unexpected permission value: {}", other)),
+    }
+}
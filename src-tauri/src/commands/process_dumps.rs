@@ -0,0 +1,103 @@
+//! Minidumps of hung sessions and agent runs
+//!
+//! A run that stopped responding can be dumped (see
+//! [`write_minidump`](crate::windows::process::write_minidump)) before it is
+//! killed. Dumps go to `dumps` under opcode's data directory, where the UI
+//! lists them for attaching to a bug report and the storage cleanup removes
+//! them with the logs. Other platforms can't write dumps: capturing one
+//! fails with the `unsupported` [`CommandError`].
+
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+
+use crate::process::ProcessRegistryState;
+use crate::windows::unsupported::CommandError;
+
+/// Directory under the data directory holding the dumps
+pub const DUMPS_DIR: &str = "dumps";
+
+/// A dump written by [`capture_process_dump`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessDump {
+    pub path: String,
+    pub size: u64,
+    /// RFC 3339 modification time
+    pub created: Option<String>,
+}
+
+/// Directory holding the dumps
+pub fn dumps_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    crate::portable::app_data_dir(app)
+        .map(|dir| dir.join(DUMPS_DIR))
+        .map_err(|e| e.to_string())
+}
+
+/// Write a minidump of the process of run `run_id`, returning its path
+#[tauri::command]
+pub async fn capture_process_dump(
+    app: AppHandle,
+    registry: State<'_, ProcessRegistryState>,
+    run_id: i64,
+) -> Result<String, CommandError> {
+    let info = registry
+        .0
+        .get_process(run_id)?
+        .ok_or_else(|| format!("Run {} is not running", run_id))?;
+
+    #[cfg(target_os = "windows")]
+    {
+        let dir = dumps_dir(&app)?;
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let path = dir.join(format!(
+            "run-{}-{}.dmp",
+            run_id,
+            chrono::Utc::now().format("%Y%m%d-%H%M%S")
+        ));
+
+        let target = path.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            crate::windows::process::write_minidump(info.pid, &target)
+        })
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))??;
+        log::info!("Dumped run {} to {}", run_id, path.display());
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = (app, info);
+        Err(crate::windows::Unsupported::new("Process dumps").into())
+    }
+}
+
+/// Dumps written so far, newest first
+#[tauri::command]
+pub async fn list_process_dumps(app: AppHandle) -> Result<Vec<ProcessDump>, String> {
+    let dir = dumps_dir(&app)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+
+    let mut dumps: Vec<ProcessDump> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "dmp"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some(ProcessDump {
+                path: entry.path().to_string_lossy().to_string(),
+                size: metadata.len(),
+                created: metadata
+                    .modified()
+                    .ok()
+                    .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+            })
+        })
+        .collect();
+    dumps.sort_by(|a, b| b.created.cmp(&a.created));
+    Ok(dumps)
+}
@@ -0,0 +1,442 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{debug, info};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::State;
+
+use super::agents::AgentDb;
+use super::file_tree::{load_git_status, run_git, GitFileStatus};
+
+/// Watcher events kept per project
+const MAX_EVENTS_PER_PROJECT: usize = 5000;
+
+/// Entries returned by [`get_recent_changes`]
+const MAX_RECENT_CHANGES: usize = 1000;
+
+/// Look-back used when no `since` is given
+const DEFAULT_LOOKBACK_HOURS: i64 = 24;
+
+/// Writes flushed this long after an agent run ended still count as the agent's
+const ATTRIBUTION_GRACE_SECS: i64 = 5;
+
+/// Where a change was observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSource {
+    /// Reported by the file tree watcher while opcode was running
+    Watcher,
+    /// Part of a commit in the git history
+    Commit,
+    /// Uncommitted change in the working tree
+    WorkingTree,
+}
+
+/// Who made a change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeAttribution {
+    /// Changed while an agent run was active in the project
+    Agent { run_id: i64, agent_name: String },
+    /// Changed outside any agent run (editor, terminal, other tools)
+    External,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeCommit {
+    pub hash: String,
+    pub author: String,
+    pub summary: String,
+}
+
+/// A recently changed file of a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentChange {
+    /// Path relative to the project root, using `/` separators
+    pub path: String,
+    /// Latest change time as Unix seconds
+    pub changed_at: i64,
+    pub sources: Vec<ChangeSource>,
+    /// Current git status, if the file has uncommitted changes
+    pub git_status: Option<GitFileStatus>,
+    /// Most recent commit touching the file within the period
+    pub commit: Option<ChangeCommit>,
+    pub attribution: ChangeAttribution,
+}
+
+/// Time window of an agent run, used for attribution
+#[derive(Debug, Clone)]
+struct RunWindow {
+    run_id: i64,
+    agent_name: String,
+    start: i64,
+    /// `None` while the run is still active
+    end: Option<i64>,
+}
+
+/// Watcher events of one project as (relative path, Unix seconds)
+type EventLog = VecDeque<(String, i64)>;
+
+/// Recent watcher events keyed by project path
+static FILE_EVENTS: Mutex<Option<HashMap<String, EventLog>>> = Mutex::new(None);
+
+/// Record files reported by the file tree watcher
+pub fn record_file_events(project_path: &str, paths: &[String]) {
+    if paths.is_empty() {
+        return;
+    }
+
+    let now = Utc::now().timestamp();
+    if let Ok(mut events) = FILE_EVENTS.lock() {
+        let events = events
+            .get_or_insert_with(HashMap::new)
+            .entry(project_path.to_string())
+            .or_default();
+        for path in paths {
+            events.push_back((path.clone(), now));
+        }
+        while events.len() > MAX_EVENTS_PER_PROJECT {
+            events.pop_front();
+        }
+    }
+}
+
+/// List files changed in a project since a point in time
+///
+/// Combines file tree watcher events (only available while the project is
+/// watched), commits and uncommitted changes into one entry per file, newest
+/// first, attributing each change to the agent run active at the time or to
+/// an external edit. `since` is an RFC 3339 timestamp and defaults to the
+/// last 24 hours.
+#[tauri::command]
+pub async fn get_recent_changes(
+    db: State<'_, AgentDb>,
+    project_path: String,
+    since: Option<String>,
+) -> Result<Vec<RecentChange>, String> {
+    let project_path = crate::utils::paths::parse_user_path(&project_path)
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .to_string();
+    let since = match since {
+        Some(since) => DateTime::parse_from_rfc3339(&since)
+            .map_err(|e| format!("Invalid timestamp '{}': {}", since, e))?
+            .timestamp(),
+        None => Utc::now().timestamp() - DEFAULT_LOOKBACK_HOURS * 3600,
+    };
+
+    let runs = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        load_run_windows(&conn, &project_path, since).map_err(|e| e.to_string())?
+    };
+
+    let events = watcher_events(&project_path, since);
+    let root = PathBuf::from(&project_path);
+    let mut changes = tokio::task::spawn_blocking(move || {
+        let mut changes: HashMap<String, RecentChange> = HashMap::new();
+
+        for (path, time) in events {
+            merge_change(&mut changes, &path, time, ChangeSource::Watcher);
+        }
+        for (commit, time, files) in git_commits(&root, since) {
+            for file in files {
+                let change = merge_change(&mut changes, &file, time, ChangeSource::Commit);
+                if change.commit.is_none() {
+                    change.commit = Some(commit.clone());
+                }
+            }
+        }
+        if let Some((statuses, _)) = load_git_status(&root) {
+            for (path, status) in statuses {
+                let time =
+                    modified_time(&root.join(&path)).unwrap_or_else(|| Utc::now().timestamp());
+                if time >= since {
+                    merge_change(&mut changes, &path, time, ChangeSource::WorkingTree).git_status =
+                        Some(status);
+                }
+            }
+        }
+
+        changes.into_values().collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| format!("Recent changes task failed: {}", e))?;
+
+    for change in &mut changes {
+        change.attribution = attribute(&runs, change.changed_at);
+    }
+
+    changes.sort_by(|a, b| {
+        b.changed_at
+            .cmp(&a.changed_at)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    changes.truncate(MAX_RECENT_CHANGES);
+
+    info!("Found {} recent changes in {}", changes.len(), project_path);
+    Ok(changes)
+}
+
+/// Add an observation, keeping one entry per path with the latest time
+fn merge_change<'a>(
+    changes: &'a mut HashMap<String, RecentChange>,
+    path: &str,
+    time: i64,
+    source: ChangeSource,
+) -> &'a mut RecentChange {
+    let path = path.trim_end_matches('/');
+    let change = changes
+        .entry(path.to_string())
+        .or_insert_with(|| RecentChange {
+            path: path.to_string(),
+            changed_at: time,
+            sources: Vec::new(),
+            git_status: None,
+            commit: None,
+            attribution: ChangeAttribution::External,
+        });
+
+    change.changed_at = change.changed_at.max(time);
+    if !change.sources.contains(&source) {
+        change.sources.push(source);
+    }
+    change
+}
+
+fn watcher_events(project_path: &str, since: i64) -> Vec<(String, i64)> {
+    let Ok(events) = FILE_EVENTS.lock() else {
+        return Vec::new();
+    };
+    events
+        .as_ref()
+        .and_then(|e| {
+            // The watcher may have been started with a differently written path
+            e.get(project_path).or_else(|| {
+                e.iter()
+                    .find(|(path, _)| crate::utils::paths::paths_equal(path, project_path))
+                    .map(|(_, events)| events)
+            })
+        })
+        .map(|e| {
+            e.iter()
+                .filter(|(_, time)| *time >= since)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Commits since `since` with their time and the files they touched
+fn git_commits(root: &Path, since: i64) -> Vec<(ChangeCommit, i64, Vec<String>)> {
+    let since_arg = format!("--since=@{}", since);
+    match run_git(
+        root,
+        &[
+            "log",
+            &since_arg,
+            "--relative",
+            "--name-only",
+            "--format=%x1e%H%x1f%ct%x1f%an%x1f%s",
+        ],
+    ) {
+        Some(output) => parse_git_log(&output),
+        None => {
+            debug!("No git history for {}", root.display());
+            Vec::new()
+        }
+    }
+}
+
+/// Parse `git log --name-only` output with `\x1e`-separated records and
+/// `\x1f`-separated header fields
+fn parse_git_log(output: &str) -> Vec<(ChangeCommit, i64, Vec<String>)> {
+    output
+        .split('\x1e')
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let mut fields = lines.next()?.split('\x1f');
+            let hash = fields.next()?.to_string();
+            let time = fields.next()?.parse().ok()?;
+            let author = fields.next()?.to_string();
+            let summary = fields.next().unwrap_or_default().to_string();
+
+            let files = lines
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect();
+
+            Some((
+                ChangeCommit {
+                    hash,
+                    author,
+                    summary,
+                },
+                time,
+                files,
+            ))
+        })
+        .collect()
+}
+
+fn modified_time(path: &Path) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+/// Agent runs in the project that were active at or after `since`
+fn load_run_windows(
+    conn: &Connection,
+    project_path: &str,
+    since: i64,
+) -> rusqlite::Result<Vec<RunWindow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, agent_name, COALESCE(process_started_at, created_at), completed_at, status
+         FROM agent_runs WHERE project_path = ?1",
+    )?;
+
+    let rows = stmt.query_map(params![project_path], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+
+    let mut runs = Vec::new();
+    for row in rows {
+        let (run_id, agent_name, started, completed, status) = row?;
+        let Some(start) = parse_timestamp(&started) else {
+            continue;
+        };
+        let end = completed.as_deref().and_then(parse_timestamp);
+
+        // Runs that ended without a completion time are not active anymore
+        if end.is_none() && status.as_deref() != Some("running") {
+            continue;
+        }
+        if end.is_some_and(|end| end < since) {
+            continue;
+        }
+
+        runs.push(RunWindow {
+            run_id,
+            agent_name,
+            start,
+            end,
+        });
+    }
+    Ok(runs)
+}
+
+/// Parse RFC 3339 or SQLite `CURRENT_TIMESTAMP` (UTC) values
+fn parse_timestamp(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc().timestamp())
+        })
+        .ok()
+}
+
+/// Attribute a change to the most recently started run active at `time`
+fn attribute(runs: &[RunWindow], time: i64) -> ChangeAttribution {
+    runs.iter()
+        .filter(|run| {
+            time >= run.start
+                && run
+                    .end
+                    .is_none_or(|end| time <= end + ATTRIBUTION_GRACE_SECS)
+        })
+        .max_by_key(|run| run.start)
+        .map(|run| ChangeAttribution::Agent {
+            run_id: run.run_id,
+            agent_name: run.agent_name.clone(),
+        })
+        .unwrap_or(ChangeAttribution::External)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_git_log() {
+        let output = "\x1eabc123\x1f1700000000\x1fAda\x1fFix parser\n\nsrc/lib.rs\nREADME.md\n\x1edef456\x1f1690000000\x1fBob\x1fInitial\n\nCargo.toml\n";
+        let commits = parse_git_log(output);
+
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].0.hash, "abc123");
+        assert_eq!(commits[0].0.summary, "Fix parser");
+        assert_eq!(commits[0].1, 1_700_000_000);
+        assert_eq!(
+            commits[0].2,
+            vec!["src/lib.rs".to_string(), "README.md".to_string()]
+        );
+        assert_eq!(commits[1].2, vec!["Cargo.toml".to_string()]);
+    }
+
+    #[test]
+    fn test_attribute() {
+        let runs = vec![
+            RunWindow {
+                run_id: 1,
+                agent_name: "Reviewer".to_string(),
+                start: 100,
+                end: Some(200),
+            },
+            RunWindow {
+                run_id: 2,
+                agent_name: "Builder".to_string(),
+                start: 150,
+                end: None,
+            },
+        ];
+
+        assert_eq!(attribute(&runs, 50), ChangeAttribution::External);
+        assert_eq!(
+            attribute(&runs, 120),
+            ChangeAttribution::Agent {
+                run_id: 1,
+                agent_name: "Reviewer".to_string()
+            }
+        );
+        assert!(matches!(
+            attribute(&runs, 180),
+            ChangeAttribution::Agent { run_id: 2, .. }
+        ));
+        assert!(matches!(
+            attribute(&runs, 10_000),
+            ChangeAttribution::Agent { run_id: 2, .. }
+        ));
+
+        assert_eq!(parse_timestamp("2024-01-01 00:00:00"), Some(1_704_067_200));
+        assert_eq!(
+            parse_timestamp("2024-01-01T00:00:00+00:00"),
+            Some(1_704_067_200)
+        );
+    }
+
+    #[test]
+    fn test_merge_change() {
+        let mut changes = HashMap::new();
+        merge_change(&mut changes, "src/lib.rs", 10, ChangeSource::Watcher);
+        merge_change(&mut changes, "src/lib.rs", 30, ChangeSource::WorkingTree);
+        merge_change(&mut changes, "src/lib.rs", 20, ChangeSource::Watcher);
+        merge_change(&mut changes, "notes/", 5, ChangeSource::WorkingTree);
+
+        let change = &changes["src/lib.rs"];
+        assert_eq!(change.changed_at, 30);
+        assert_eq!(
+            change.sources,
+            vec![ChangeSource::Watcher, ChangeSource::WorkingTree]
+        );
+        assert!(changes.contains_key("notes"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Notifying a webhook when an agent run or Claude session ends
+//!
+//! The URL is kept in `app_settings`, encrypted like the proxy credentials
+//! since webhook URLs usually embed a token. Each finished run is POSTed as a
+//! [`RunExitEvent`] carrying the same [`ExitReport`] the UI receives, so the
+//! receiver can tell a rate-limited run from a crash or a cancel.
+
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::commands::agents::AgentDb;
+use crate::process::exit::ExitReport;
+
+/// app_settings key the webhook URL is stored under
+const WEBHOOK_SETTINGS_KEY: &str = "run_webhook_url";
+
+/// How long a webhook request may take
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body POSTed to the webhook when a run ends
+#[derive(Debug, Clone, Serialize)]
+pub struct RunExitEvent {
+    /// `agent_run` or `session`
+    pub kind: &'static str,
+    pub run_id: Option<i64>,
+    pub agent_name: Option<String>,
+    pub session_id: Option<String>,
+    pub project_path: String,
+    /// `completed`, `cancelled` or `failed`, as in `agent_runs.status`
+    pub status: &'static str,
+    #[serde(flatten)]
+    pub report: ExitReport,
+}
+
+impl RunExitEvent {
+    pub fn new(kind: &'static str, project_path: String, report: ExitReport) -> Self {
+        Self {
+            kind,
+            run_id: None,
+            agent_name: None,
+            session_id: None,
+            project_path,
+            status: report.classification.run_status(),
+            report,
+        }
+    }
+}
+
+/// Read the webhook URL from the database
+pub fn load_run_webhook(conn: &Connection) -> Option<String> {
+    let sealed = conn
+        .query_row(
+            "SELECT value FROM app_settings WHERE key = ?1",
+            params![WEBHOOK_SETTINGS_KEY],
+            |row| row.get::<_, String>(0),
+        )
+        .ok()?;
+    match crate::data_protection::unseal_string(&sealed) {
+        Ok(url) => Some(url).filter(|url| !url.is_empty()),
+        Err(e) => {
+            warn!(
+                "Failed to decrypt {}, ignoring it: {}",
+                WEBHOOK_SETTINGS_KEY, e
+            );
+            None
+        }
+    }
+}
+
+/// Get the URL notified when a run ends
+#[tauri::command]
+pub async fn get_run_webhook(db: State<'_, AgentDb>) -> Result<Option<String>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_run_webhook(&conn))
+}
+
+/// Set the URL notified when a run ends; `None` or an empty URL turns it off
+#[tauri::command]
+pub async fn save_run_webhook(db: State<'_, AgentDb>, url: Option<String>) -> Result<(), String> {
+    let url = url.map(|url| url.trim().to_string()).unwrap_or_default();
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    if url.is_empty() {
+        conn.execute(
+            "DELETE FROM app_settings WHERE key = ?1",
+            params![WEBHOOK_SETTINGS_KEY],
+        )
+        .map_err(|e| format!("Failed to remove {}: {}", WEBHOOK_SETTINGS_KEY, e))?;
+        return Ok(());
+    }
+
+    validate_webhook_url(&url)?;
+    let sealed = crate::data_protection::seal_string(&url)
+        .map_err(|e| format!("Failed to encrypt {}: {}", WEBHOOK_SETTINGS_KEY, e))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![WEBHOOK_SETTINGS_KEY, sealed],
+    )
+    .map_err(|e| format!("Failed to save {}: {}", WEBHOOK_SETTINGS_KEY, e))?;
+    Ok(())
+}
+
+fn validate_webhook_url(url: &str) -> Result<(), String> {
+    let parsed = url::Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "Webhook URLs must use http or https, not {}",
+            parsed.scheme()
+        ));
+    }
+    Ok(())
+}
+
+/// Post `event` to the configured webhook, if any, in the background
+pub fn notify_run_exit(app: &AppHandle, event: RunExitEvent) {
+    let url = {
+        let db = app.state::<AgentDb>();
+        let Ok(conn) = db.0.lock() else {
+            return;
+        };
+        load_run_webhook(&conn)
+    };
+    let Some(url) = url else {
+        return;
+    };
+
+    tauri::async_runtime::spawn(async move {
+        let builder = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT);
+        let client = match crate::commands::proxy::apply_client_proxy(builder) {
+            Ok(builder) => builder.build(),
+            Err(e) => {
+                warn!("Run exit webhook failed: {}", e);
+                return;
+            }
+        };
+        let result = match client {
+            Ok(client) => client
+                .post(&url)
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => debug!("Sent the {} exit webhook", event.kind),
+            // The URL may hold a token, so it is left out of the log
+            Err(e) => warn!("Run exit webhook failed: {}", e.without_url()),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::exit::{ExitClassification, OutputSignals};
+
+    #[test]
+    fn test_validate_webhook_url() {
+        assert!(validate_webhook_url("https://hooks.example.com/T000/B000/secret").is_ok());
+        assert!(validate_webhook_url("http://localhost:8080/runs").is_ok());
+        assert!(validate_webhook_url("file:///etc/passwd").is_err());
+        assert!(validate_webhook_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_run_exit_event_body() {
+        let report = ExitReport::from_status(None, None, OutputSignals::default());
+        let event = RunExitEvent {
+            run_id: Some(7),
+            agent_name: Some("Reviewer".to_string()),
+            ..RunExitEvent::new("agent_run", "/work/app".to_string(), report)
+        };
+
+        let body = serde_json::to_value(&event).unwrap();
+        assert_eq!(body["classification"], ExitClassification::Unknown.as_str());
+        assert_eq!(body["retryable"], true);
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["run_id"], 7);
+    }
+}
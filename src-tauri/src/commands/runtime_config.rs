@@ -0,0 +1,280 @@
+//! Settings that take effect without restarting opcode
+//!
+//! Log filters, feature flags and the agent concurrency limit are kept in a
+//! [`RuntimeConfig`] snapshot that is swapped as a whole whenever the settings
+//! change, so readers always see one consistent version. A background watcher
+//! notices changes to `app_settings` made outside the settings commands (the
+//! storage editor, another opcode window, external tools) and re-applies the
+//! runtime config together with the proxy, sanitizer and CA settings.
+
+use log::{info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::agent_sandbox::AgentSandbox;
+use crate::commands::agents::AgentDb;
+
+/// app_settings key the runtime configuration is stored under (as JSON)
+const SETTINGS_KEY: &str = "runtime_config";
+
+/// How often the settings watcher checks `app_settings` for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// `RUST_LOG`-style filter directives applied on top of the environment,
+    /// e.g. `info,opcode_lib::commands::agents=debug`
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// Named feature toggles read by the UI and backend
+    #[serde(default)]
+    pub feature_flags: BTreeMap<String, bool>,
+    /// Maximum number of agent runs executing at the same time (unlimited if unset)
+    #[serde(default)]
+    pub max_concurrent_agent_runs: Option<usize>,
+    /// How agent runs are confined
+    #[serde(default)]
+    pub agent_sandbox: AgentSandbox,
+}
+
+static RUNTIME_CONFIG: RwLock<Option<Arc<RuntimeConfig>>> = RwLock::new(None);
+
+/// Logger whose filter can be replaced while the app is running
+struct ReloadableLogger {
+    inner: RwLock<env_logger::Logger>,
+}
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner
+            .read()
+            .map(|logger| logger.enabled(metadata))
+            .unwrap_or(false)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let Ok(logger) = self.inner.read() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(logger) = self.inner.read() {
+            logger.flush();
+        }
+    }
+}
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+/// Install the global logger, configured from `RUST_LOG` like `env_logger::init`
+pub fn init_logging() {
+    let logger = LOGGER.get_or_init(|| ReloadableLogger {
+        inner: RwLock::new(build_logger(None)),
+    });
+    let max_level = logger
+        .inner
+        .read()
+        .map(|logger| logger.filter())
+        .unwrap_or(log::LevelFilter::Error);
+
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+fn build_logger(filter: Option<&str>) -> env_logger::Logger {
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::default());
+    if let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) {
+        // Later directives win, so the setting overrides RUST_LOG per module
+        builder.parse_filters(filter);
+    }
+    builder.build()
+}
+
+/// Swap the active log filter, if the reloadable logger is installed
+fn reload_log_filter(filter: Option<&str>) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+
+    let replacement = build_logger(filter);
+    let max_level = replacement.filter();
+    if let Ok(mut current) = logger.inner.write() {
+        *current = replacement;
+    }
+    log::set_max_level(max_level);
+}
+
+/// Get the runtime configuration from the database
+#[tauri::command]
+pub async fn get_runtime_config(db: State<'_, AgentDb>) -> Result<RuntimeConfig, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_runtime_config(&conn))
+}
+
+/// Save the runtime configuration and apply it immediately
+#[tauri::command]
+pub async fn save_runtime_config(
+    db: State<'_, AgentDb>,
+    config: RuntimeConfig,
+) -> Result<(), String> {
+    if config.max_concurrent_agent_runs == Some(0) {
+        return Err("The concurrent agent run limit must be at least 1".to_string());
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+    let value = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![SETTINGS_KEY, value],
+    )
+    .map_err(|e| format!("Failed to save {}: {}", SETTINGS_KEY, e))?;
+
+    apply_runtime_config(&config);
+
+    Ok(())
+}
+
+/// Read the runtime configuration from the database, falling back to defaults
+pub fn load_runtime_config(conn: &Connection) -> RuntimeConfig {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        params![SETTINGS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+/// Make the given configuration the active snapshot and reconfigure logging
+pub fn apply_runtime_config(config: &RuntimeConfig) {
+    let previous = runtime_config();
+    if previous.log_filter != config.log_filter {
+        reload_log_filter(config.log_filter.as_deref());
+    }
+
+    info!(
+        "Applying runtime config: log_filter={:?}, feature_flags={:?}, max_concurrent_agent_runs={:?}, agent_sandbox={:?}",
+        config.log_filter, config.feature_flags, config.max_concurrent_agent_runs, config.agent_sandbox
+    );
+    if let Ok(mut current) = RUNTIME_CONFIG.write() {
+        *current = Some(Arc::new(config.clone()));
+    }
+}
+
+/// The active runtime configuration snapshot
+pub fn runtime_config() -> Arc<RuntimeConfig> {
+    RUNTIME_CONFIG
+        .read()
+        .ok()
+        .and_then(|config| config.clone())
+        .unwrap_or_default()
+}
+
+/// Start the background watcher that re-applies settings changed in the database
+pub fn start_settings_watcher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut last = settings_fingerprint(&app);
+
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+
+            let current = settings_fingerprint(&app);
+            if current.is_none() || current == last {
+                continue;
+            }
+            last = current;
+
+            info!("Settings changed, re-applying");
+            if let Err(e) = reload_settings(&app) {
+                warn!("Failed to re-apply settings: {}", e);
+            }
+        }
+    });
+}
+
+/// Hash of every `app_settings` entry, or `None` if the table could not be read
+fn settings_fingerprint(app: &AppHandle) -> Option<u64> {
+    let db = app.state::<AgentDb>();
+    let conn = db.0.lock().ok()?;
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM app_settings ORDER BY key")
+        .ok()?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .ok()?
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    rows.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Re-read every live setting from the database and apply it
+fn reload_settings(app: &AppHandle) -> Result<(), String> {
+    let db = app.state::<AgentDb>();
+    let config = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+
+        crate::commands::proxy::apply_proxy_settings(&crate::commands::proxy::load_proxy_settings(
+            &conn,
+        ));
+        crate::commands::environment::apply_env_sanitizer_settings(
+            &crate::commands::environment::load_env_sanitizer_settings(&conn),
+        );
+        crate::commands::certificates::apply_ca_certificate_settings(
+            &crate::commands::certificates::load_ca_certificate_settings(&conn),
+        );
+
+        load_runtime_config(&conn)
+    };
+
+    if *runtime_config() != config {
+        apply_runtime_config(&config);
+        let _ = app.emit("runtime-config-changed", &config);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_runtime_config() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE app_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(load_runtime_config(&conn), RuntimeConfig::default());
+
+        conn.execute(
+            "INSERT INTO app_settings (key, value) VALUES (?1, ?2)",
+            params![
+                SETTINGS_KEY,
+                r#"{"log_filter":"debug","feature_flags":{"file_tree":true}}"#
+            ],
+        )
+        .unwrap();
+        let config = load_runtime_config(&conn);
+        assert_eq!(config.log_filter.as_deref(), Some("debug"));
+        assert_eq!(config.feature_flags.get("file_tree"), Some(&true));
+        assert_eq!(config.max_concurrent_agent_runs, None);
+        assert_eq!(config.agent_sandbox, AgentSandbox::None);
+    }
+}
@@ -0,0 +1,362 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use log::{debug, info};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Files larger than this are skipped unless overridden
+const DEFAULT_MAX_FILE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Matches returned before the search stops, unless overridden
+const DEFAULT_MAX_RESULTS: usize = 500;
+
+/// Longest line returned to the UI; longer lines are truncated
+const MAX_LINE_LENGTH: usize = 500;
+
+/// Bytes inspected for NUL to detect binary files
+const BINARY_PROBE_SIZE: usize = 8192;
+
+/// Options for [`grep_project`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrepOptions {
+    pub case_insensitive: bool,
+    /// Treat the pattern as a literal string instead of a regex
+    pub fixed_strings: bool,
+    pub whole_word: bool,
+    /// Lines of context to include before and after each match
+    pub context_lines: usize,
+    pub max_results: Option<usize>,
+    pub max_file_size: Option<u64>,
+    /// Also search hidden files and directories
+    pub include_hidden: bool,
+    /// Glob filters such as `*.rs` or `!target/**`
+    pub globs: Vec<String>,
+    /// Identifier that can be passed to [`cancel_grep`]
+    pub search_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GrepLine {
+    pub line_number: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrepMatch {
+    /// Path relative to the project root
+    pub path: String,
+    pub line_number: usize,
+    /// Byte offset of the first match within the line
+    pub column: usize,
+    pub line: String,
+    pub before: Vec<GrepLine>,
+    pub after: Vec<GrepLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    pub files_searched: usize,
+    /// The result limit was reached before the search finished
+    pub truncated: bool,
+    pub cancelled: bool,
+    /// Files skipped because they are stored online only and reading them
+    /// would download them
+    #[serde(default)]
+    pub online_only_skipped: usize,
+}
+
+/// Cancellation flags of running searches, keyed by search ID
+static ACTIVE_SEARCHES: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+/// Search a project for a pattern, honouring .gitignore
+///
+/// Works without ripgrep being installed. Pass `options.search_id` to be able
+/// to stop a long search with [`cancel_grep`].
+#[tauri::command]
+pub async fn grep_project(
+    project_path: String,
+    pattern: String,
+    options: Option<GrepOptions>,
+) -> Result<GrepResult, String> {
+    let options = options.unwrap_or_default();
+    let project_path =
+        crate::utils::paths::parse_user_path(&project_path).map_err(|e| e.to_string())?;
+    info!(
+        "Searching '{}' for pattern: {}",
+        project_path.display(),
+        pattern
+    );
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Some(id) = &options.search_id {
+        let mut searches = ACTIVE_SEARCHES.lock().map_err(|e| e.to_string())?;
+        searches
+            .get_or_insert_with(HashMap::new)
+            .insert(id.clone(), cancel.clone());
+    }
+
+    let search_id = options.search_id.clone();
+    let flag = cancel.clone();
+    let result =
+        tokio::task::spawn_blocking(move || grep_path(&project_path, &pattern, &options, &flag))
+            .await
+            .map_err(|e| format!("Search task failed: {}", e))?;
+
+    if let Some(id) = search_id {
+        if let Ok(mut searches) = ACTIVE_SEARCHES.lock() {
+            if let Some(searches) = searches.as_mut() {
+                searches.remove(&id);
+            }
+        }
+    }
+
+    result
+}
+
+/// Cancel a running [`grep_project`] search
+#[tauri::command]
+pub async fn cancel_grep(search_id: String) -> Result<bool, String> {
+    let searches = ACTIVE_SEARCHES.lock().map_err(|e| e.to_string())?;
+    match searches.as_ref().and_then(|s| s.get(&search_id)) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            info!("Cancelled search {}", search_id);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Search files under `root` synchronously; checks `cancel` between files
+pub fn grep_path(
+    root: &Path,
+    pattern: &str,
+    options: &GrepOptions,
+    cancel: &AtomicBool,
+) -> Result<GrepResult, String> {
+    if pattern.is_empty() {
+        return Err("Search pattern cannot be empty".to_string());
+    }
+    if !root.is_dir() {
+        return Err(format!("Path does not exist: {}", root.display()));
+    }
+
+    let regex = build_regex(pattern, options)?;
+    let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+    let max_file_size = options.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE);
+
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .hidden(!options.include_hidden)
+        .require_git(false)
+        .max_filesize(Some(max_file_size));
+
+    if !options.globs.is_empty() {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in &options.globs {
+            overrides
+                .add(glob)
+                .map_err(|e| format!("Invalid glob '{}': {}", glob, e))?;
+        }
+        walker.overrides(
+            overrides
+                .build()
+                .map_err(|e| format!("Invalid glob filters: {}", e))?,
+        );
+    }
+
+    let mut result = GrepResult::default();
+
+    for entry in walker.build() {
+        if cancel.load(Ordering::Relaxed) {
+            result.cancelled = true;
+            break;
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        if crate::utils::cloud_files::is_cloud_placeholder(entry.path()) {
+            result.online_only_skipped += 1;
+            continue;
+        }
+
+        let Some(contents) = read_text_file(entry.path()) else {
+            continue;
+        };
+        result.files_searched += 1;
+
+        let relative = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .to_string();
+
+        let remaining = max_results - result.matches.len();
+        let file_matches = search_lines(&contents, &regex, options.context_lines, remaining + 1);
+        for (line_number, column, line, before, after) in file_matches {
+            if result.matches.len() >= max_results {
+                result.truncated = true;
+                break;
+            }
+            result.matches.push(GrepMatch {
+                path: relative.clone(),
+                line_number,
+                column,
+                line,
+                before,
+                after,
+            });
+        }
+
+        if result.truncated {
+            break;
+        }
+    }
+
+    info!(
+        "Search finished: {} matches in {} files{}",
+        result.matches.len(),
+        result.files_searched,
+        if result.cancelled { " (cancelled)" } else { "" }
+    );
+    Ok(result)
+}
+
+fn build_regex(pattern: &str, options: &GrepOptions) -> Result<Regex, String> {
+    let mut pattern = if options.fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    if options.whole_word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(options.case_insensitive)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))
+}
+
+/// Read a file as text, returning `None` for binary or unreadable files
+fn read_text_file(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+
+    let probe = &bytes[..bytes.len().min(BINARY_PROBE_SIZE)];
+    if probe.contains(&0) {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Line number, column, line text and the context before/after it
+type LineMatch = (usize, usize, String, Vec<GrepLine>, Vec<GrepLine>);
+
+/// Find up to `limit` matching lines, each with its surrounding context
+fn search_lines(contents: &str, regex: &Regex, context: usize, limit: usize) -> Vec<LineMatch> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let context_line = |index: usize| GrepLine {
+        line_number: index + 1,
+        text: truncate_line(lines[index]),
+    };
+
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| regex.find(line).map(|m| (index, m.start())))
+        .take(limit)
+        .map(|(index, column)| {
+            let before = (index.saturating_sub(context)..index).map(context_line).collect();
+            let after = (index + 1..(index + 1 + context).min(lines.len()))
+                .map(context_line)
+                .collect();
+            (index + 1, column, truncate_line(lines[index]), before, after)
+        })
+        .collect()
+}
+
+fn truncate_line(line: &str) -> String {
+    if line.len() <= MAX_LINE_LENGTH {
+        return line.to_string();
+    }
+    let mut end = MAX_LINE_LENGTH;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &line[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_grep_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+        fs::write(root.join("ignored.txt"), "needle\n").unwrap();
+        fs::write(root.join("main.rs"), "fn main() {\n    let needle = 1;\n}\n").unwrap();
+        fs::write(root.join("notes.md"), "Needle in caps\nneedles plural\n").unwrap();
+        fs::write(root.join("blob.bin"), b"needle\0binary").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let options = GrepOptions {
+            context_lines: 1,
+            ..Default::default()
+        };
+        let result = grep_path(root, "needle", &options, &cancel).unwrap();
+        let mut paths: Vec<&str> = result.matches.iter().map(|m| m.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["main.rs", "notes.md"]);
+
+        let hit = result.matches.iter().find(|m| m.path == "main.rs").unwrap();
+        assert_eq!(hit.line_number, 2);
+        assert_eq!(hit.column, 8);
+        assert_eq!(hit.before, vec![GrepLine { line_number: 1, text: "fn main() {".to_string() }]);
+        assert_eq!(hit.after, vec![GrepLine { line_number: 3, text: "}".to_string() }]);
+
+        let options = GrepOptions {
+            case_insensitive: true,
+            whole_word: true,
+            globs: vec!["*.md".to_string()],
+            ..Default::default()
+        };
+        let result = grep_path(root, "needle", &options, &cancel).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].line, "Needle in caps");
+
+        let options = GrepOptions {
+            case_insensitive: true,
+            max_results: Some(1),
+            ..Default::default()
+        };
+        let result = grep_path(root, "needle", &options, &cancel).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.truncated);
+
+        cancel.store(true, Ordering::Relaxed);
+        let result = grep_path(root, "needle", &GrepOptions::default(), &cancel).unwrap();
+        assert!(result.cancelled);
+        assert!(result.matches.is_empty());
+    }
+}
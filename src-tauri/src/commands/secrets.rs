@@ -0,0 +1,413 @@
+//! API keys and tokens in the platform secret store
+//!
+//! Keys are kept in the store behind
+//! [`secret_store`](crate::windows::secret_store::secret_store) under the
+//! `default` account and handed to the Claude processes opcode starts.
+//!
+//! Keys set in the `env` section of `~/.claude/settings.json` are plaintext,
+//! but that file belongs to the Claude CLI: a `claude` started outside
+//! opcode stops seeing keys moved out of it. So opcode only moves them when
+//! the user asks, through [`migrate_settings_secrets`], after being told
+//! which keys will be removed. Until then the file is only restricted to
+//! the current user.
+//!
+//! The stores block (the Secret Service one panics inside the async
+//! runtime), so every access goes through a blocking task.
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::RwLock;
+
+use crate::windows::secret_store::{secret_store, SecretInfo, SecretKind};
+use crate::windows::unsupported::CommandError;
+
+/// Account the keys from the settings are stored under
+const DEFAULT_ACCOUNT: &str = "default";
+
+/// Environment variables holding secrets, and the kind each is stored as
+const SECRET_ENV_VARS: &[(&str, SecretKind)] = &[
+    ("ANTHROPIC_API_KEY", SecretKind::AnthropicApiKey),
+    ("OPENROUTER_API_KEY", SecretKind::OpenRouterApiKey),
+    ("CLAUDE_CODE_OAUTH_TOKEN", SecretKind::OAuthToken),
+];
+
+/// Stored secrets by environment variable, so spawning a process never
+/// waits on the store
+static SECRET_ENV: RwLock<Vec<(&str, String)>> = RwLock::new(Vec::new());
+
+/// Run a blocking secret store operation off the async runtime
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, CommandError> {
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+}
+
+/// List the stored secrets, without their values
+#[tauri::command]
+pub async fn list_secrets() -> Result<Vec<SecretInfo>, CommandError> {
+    run_blocking(|| secret_store().list()).await
+}
+
+/// Store a secret, replacing any previous value
+#[tauri::command]
+pub async fn store_secret(
+    kind: SecretKind,
+    account: Option<String>,
+    secret: String,
+) -> Result<(), CommandError> {
+    let account = account.unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+    run_blocking(move || {
+        secret_store().store(kind, &account, &secret)?;
+        refresh_secret_env();
+        Ok(())
+    })
+    .await
+}
+
+/// Read a stored secret's value, `None` if none is stored
+///
+/// May need Windows Hello first (see
+/// [`user_presence`](crate::commands::user_presence)).
+#[tauri::command]
+pub async fn reveal_secret(
+    db: tauri::State<'_, crate::commands::agents::AgentDb>,
+    kind: SecretKind,
+    account: Option<String>,
+) -> Result<Option<String>, CommandError> {
+    crate::commands::user_presence::confirm_operation(
+        &db,
+        crate::windows::user_presence::ProtectedOperation::RevealApiKey,
+    )
+    .await?;
+    let account = account.unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+    run_blocking(move || secret_store().read(kind, &account)).await
+}
+
+/// Delete a secret, returning whether one was stored
+#[tauri::command]
+pub async fn delete_secret(
+    kind: SecretKind,
+    account: Option<String>,
+) -> Result<bool, CommandError> {
+    let account = account.unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+    run_blocking(move || {
+        let deleted = secret_store().delete(kind, &account)?;
+        refresh_secret_env();
+        Ok(deleted)
+    })
+    .await
+}
+
+/// Outcome of [`migrate_settings_secrets`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SettingsSecretsMigration {
+    /// The settings hold no keys
+    NothingToMove,
+    /// Nothing changed; show `warning` and call again with `consent`
+    ConsentRequired {
+        /// Environment variables that will be removed from the settings
+        secrets: Vec<String>,
+        warning: String,
+    },
+    /// The keys were moved; any the store refused are still in the settings
+    Migrated { moved: Vec<String> },
+}
+
+/// Load the stored secrets for spawned processes, and restrict the Claude
+/// settings to the current user while they hold keys
+///
+/// The keys in the settings are left where they are. Blocks; call it from a
+/// blocking task.
+pub fn init_secrets() {
+    if let Some(path) = settings_path() {
+        if let Some(settings) = read_settings(&path) {
+            protect_settings_file(&path, &settings);
+        }
+    }
+    refresh_secret_env();
+}
+
+/// Move the API keys in `~/.claude/settings.json` into the secret store
+///
+/// Without `consent`, only reports which keys would be removed from the
+/// file, as a `claude` started outside opcode no longer sees them
+/// afterwards.
+#[tauri::command]
+pub async fn migrate_settings_secrets(
+    consent: Option<bool>,
+) -> Result<SettingsSecretsMigration, CommandError> {
+    let consent = consent.unwrap_or(false);
+    run_blocking(move || {
+        let Some(path) = settings_path() else {
+            return Ok(SettingsSecretsMigration::NothingToMove);
+        };
+        let Some(mut settings) = read_settings(&path) else {
+            return Ok(SettingsSecretsMigration::NothingToMove);
+        };
+        let secrets = settings_secret_names(&settings);
+        if secrets.is_empty() {
+            return Ok(SettingsSecretsMigration::NothingToMove);
+        }
+        if !consent {
+            return Ok(SettingsSecretsMigration::ConsentRequired {
+                warning: format!(
+                    "{} will be removed from {}; a claude started outside opcode won't \
+                     see them anymore",
+                    secrets.join(", "),
+                    path.display()
+                ),
+                secrets,
+            });
+        }
+
+        let moved = extract_env_secrets(&mut settings, |kind, secret| {
+            secret_store().store(kind, DEFAULT_ACCOUNT, secret)
+        });
+        if !moved.is_empty() {
+            crate::utils::atomic_write::atomic_write(
+                &path,
+                serde_json::to_string_pretty(&settings)?,
+            )?;
+            info!("Moved {} out of {}", moved.join(", "), path.display());
+            refresh_secret_env();
+        }
+        protect_settings_file(&path, &settings);
+        Ok(SettingsSecretsMigration::Migrated { moved })
+    })
+    .await
+}
+
+/// Stored secrets as environment variables for a spawned Claude process
+pub fn secret_env() -> Vec<(&'static str, String)> {
+    SECRET_ENV
+        .read()
+        .map(|vars| vars.clone())
+        .unwrap_or_default()
+}
+
+/// Command starting Claude at `claude_path`
+///
+/// On Windows, while secrets are stored, that is opcode's Claude wrapper, so
+/// [`spawn_claude`] can hand them over through inherited pipes instead of
+/// the environment (see [`pass_secret`](crate::windows::secrets::pass_secret)).
+pub fn claude_command(claude_path: &str) -> tokio::process::Command {
+    #[cfg(target_os = "windows")]
+    if !secret_env().is_empty() {
+        if let Some(cmd) = wrapper_command(claude_path) {
+            return cmd;
+        }
+    }
+    tokio::process::Command::new(claude_path)
+}
+
+/// Command starting Claude at `claude_path` through opcode's Claude wrapper,
+/// whether or not secrets are stored
+///
+/// `None` if opcode's executable can't be located.
+#[cfg(target_os = "windows")]
+pub fn wrapper_command(claude_path: &str) -> Option<tokio::process::Command> {
+    let exe = std::env::current_exe().ok()?;
+    let mut cmd = tokio::process::Command::new(exe);
+    cmd.arg(crate::windows::secrets::CLAUDE_WRAPPER_ARG)
+        .arg(claude_path);
+    Some(cmd)
+}
+
+/// Spawn a command made by [`claude_command`] with the stored secrets
+///
+/// Elsewhere than on Windows,
+/// [`apply_child_env`](crate::commands::environment::apply_child_env) has
+/// already put them in its environment.
+pub fn spawn_claude(cmd: &mut tokio::process::Command) -> std::io::Result<tokio::process::Child> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::secrets::{pass_secret, spawn_inheriting, SecretChannel};
+
+        let wrapped = std::env::current_exe()
+            .map(|exe| cmd.as_std().get_program() == exe.as_os_str())
+            .unwrap_or(false);
+        let mut pipes = Vec::new();
+        for (name, secret) in secret_env() {
+            if !wrapped {
+                // Stored after the command was made
+                cmd.env(name, secret);
+            } else if let SecretChannel::Handle(pipe) = pass_secret(cmd.as_std_mut(), name, &secret)
+            {
+                pipes.push(pipe);
+            }
+        }
+        return spawn_inheriting(&pipes, || cmd.spawn());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    cmd.spawn()
+}
+
+/// `~/.claude/settings.json`
+fn settings_path() -> Option<std::path::PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude").join("settings.json"))
+}
+
+fn read_settings(path: &std::path::Path) -> Option<Value> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            warn!("Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Environment variables in the `env` section of `settings` holding secrets
+fn settings_secret_names(settings: &Value) -> Vec<String> {
+    let Some(env) = settings.get("env").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    SECRET_ENV_VARS
+        .iter()
+        .filter(|(name, _)| {
+            env.get(*name)
+                .and_then(Value::as_str)
+                .is_some_and(|secret| !secret.is_empty())
+        })
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Restrict the settings file at `path` to the current user while
+/// `settings` still hold secrets the store refused
+pub fn protect_settings_file(path: &std::path::Path, settings: &Value) {
+    let Some(env) = settings.get("env").and_then(Value::as_object) else {
+        return;
+    };
+    if !SECRET_ENV_VARS
+        .iter()
+        .any(|(name, _)| env.contains_key(*name))
+    {
+        return;
+    }
+    if let Err(e) = crate::utils::file_permissions::set_file_permissions(
+        path,
+        crate::utils::file_permissions::FilePermissions::OwnerOnly,
+    ) {
+        warn!("Failed to restrict access to {}: {:#}", path.display(), e);
+    }
+}
+
+/// Reload [`SECRET_ENV`] from the store
+fn refresh_secret_env() {
+    let mut vars = Vec::new();
+    for (name, kind) in SECRET_ENV_VARS {
+        match secret_store().read(*kind, DEFAULT_ACCOUNT) {
+            Ok(Some(secret)) => vars.push((*name, secret)),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read the stored {}: {:#}", name, e),
+        }
+    }
+    if let Ok(mut current) = SECRET_ENV.write() {
+        *current = vars;
+    }
+}
+
+/// Remove the secrets from the `env` section of `settings` that `store`
+/// accepts, returning the variables removed
+///
+/// A secret `store` fails on stays in the settings, so it isn't lost.
+fn extract_env_secrets(
+    settings: &mut Value,
+    store: impl Fn(SecretKind, &str) -> anyhow::Result<()>,
+) -> Vec<String> {
+    let Some(env) = settings.get_mut("env").and_then(Value::as_object_mut) else {
+        return Vec::new();
+    };
+
+    let mut moved = Vec::new();
+    for (name, kind) in SECRET_ENV_VARS {
+        let Some(secret) = env.get(*name).and_then(Value::as_str) else {
+            continue;
+        };
+        if secret.is_empty() {
+            continue;
+        }
+        match store(*kind, secret) {
+            Ok(()) => {
+                env.remove(*name);
+                info!("Moved {} into the secret store", name);
+                moved.push(name.to_string());
+            }
+            Err(e) => warn!("Keeping {} in the settings: {:#}", name, e),
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_extract_env_secrets() {
+        let mut settings = serde_json::json!({
+            "env": {
+                "ANTHROPIC_API_KEY": "sk-ant-test",
+                "OPENROUTER_API_KEY": "sk-or-test",
+                "DISABLE_TELEMETRY": "1"
+            },
+            "model": "sonnet"
+        });
+        let stored = RefCell::new(Vec::new());
+        assert_eq!(
+            settings_secret_names(&settings),
+            ["ANTHROPIC_API_KEY", "OPENROUTER_API_KEY"]
+        );
+
+        let moved = extract_env_secrets(&mut settings, |kind, secret| {
+            if kind == SecretKind::OpenRouterApiKey {
+                anyhow::bail!("store is locked");
+            }
+            stored.borrow_mut().push((kind, secret.to_string()));
+            Ok(())
+        });
+
+        assert_eq!(moved, ["ANTHROPIC_API_KEY"]);
+        assert_eq!(
+            stored.into_inner(),
+            vec![(SecretKind::AnthropicApiKey, "sk-ant-test".to_string())]
+        );
+        assert_eq!(
+            settings,
+            serde_json::json!({
+                "env": {
+                    "OPENROUTER_API_KEY": "sk-or-test",
+                    "DISABLE_TELEMETRY": "1"
+                },
+                "model": "sonnet"
+            })
+        );
+
+        let mut empty = serde_json::json!({});
+        assert!(settings_secret_names(&empty).is_empty());
+        assert!(extract_env_secrets(&mut empty, |_, _| Ok(())).is_empty());
+    }
+    #[test]
+    fn test_settings_secrets_migration_json() {
+        assert_eq!(
+            serde_json::to_value(SettingsSecretsMigration::Migrated {
+                moved: vec!["ANTHROPIC_API_KEY".to_string()]
+            })
+            .unwrap(),
+            serde_json::json!({ "status": "migrated", "moved": ["ANTHROPIC_API_KEY"] })
+        );
+        assert_eq!(
+            serde_json::to_value(SettingsSecretsMigration::NothingToMove).unwrap(),
+            serde_json::json!({ "status": "nothing_to_move" })
+        );
+    }
+}
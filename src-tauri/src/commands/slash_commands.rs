@@ -258,7 +258,10 @@ pub async fn slash_commands_list(
     
     // Load project commands if project path is provided
     if let Some(proj_path) = project_path {
-        let project_commands_dir = PathBuf::from(&proj_path).join(".claude").join("commands");
+        let project_commands_dir = crate::utils::paths::parse_user_path(&proj_path)
+            .map_err(|e| e.to_string())?
+            .join(".claude")
+            .join("commands");
         if project_commands_dir.exists() {
             debug!("Scanning project commands at: {:?}", project_commands_dir);
             
@@ -348,6 +351,15 @@ pub async fn slash_command_save(
     if name.is_empty() {
         return Err("Command name cannot be empty".to_string());
     }
+
+    // The name and namespace become file and directory names
+    if cfg!(target_os = "windows") {
+        let components = namespace.iter().flat_map(|ns| ns.split(':'));
+        for component in components.chain(std::iter::once(name.as_str())) {
+            crate::utils::paths::validate_windows_filename(component)
+                .map_err(|e| format!("Invalid command name: {}", e))?;
+        }
+    }
     
     if !["project", "user"].contains(&scope.as_str()) {
         return Err("Invalid scope. Must be 'project' or 'user'".to_string());
@@ -356,7 +368,10 @@ pub async fn slash_command_save(
     // Determine base directory
     let base_dir = if scope == "project" {
         if let Some(proj_path) = project_path {
-            PathBuf::from(proj_path).join(".claude").join("commands")
+            crate::utils::paths::parse_user_path(&proj_path)
+                .map_err(|e| e.to_string())?
+                .join(".claude")
+                .join("commands")
         } else {
             return Err("Project path required for project scope".to_string());
         }
@@ -436,9 +451,9 @@ pub async fn slash_command_delete(command_id: String, project_path: Option<Strin
         .find(|cmd| cmd.id == command_id)
         .ok_or_else(|| format!("Command not found: {}", command_id))?;
     
-    // Delete the file
-    fs::remove_file(&command.file_path)
-        .map_err(|e| format!("Failed to delete command file: {}", e))?;
+    // Move the file to the trash so it can be restored
+    crate::utils::trash::delete(&command.file_path)
+        .map_err(|e| format!("Failed to delete command file: {:#}", e))?;
     
     // Clean up empty directories
     if let Some(parent) = Path::new(&command.file_path).parent() {
@@ -0,0 +1,665 @@
+//! Disk usage report and cleanup of regenerable data
+//!
+//! Sizes are computed per [`StorageCategory`], and cleaning only ever
+//! removes regular files found below that category's own directories;
+//! symlinks are neither followed nor deleted.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+use walkdir::WalkDir;
+
+use crate::checkpoint::storage::CheckpointStorage;
+use crate::commands::agents::{open_database, AgentDb};
+use crate::process::ProcessRegistryState;
+use crate::utils::dir_size::{compute_dir_size, dir_size_blocking, DirSize, PROGRESS_INTERVAL};
+use crate::utils::move_path::{MoveProgress, MoveReport};
+
+/// Exports older than this are reported as old
+const OLD_EXPORT_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// WebView2 cache folders below the app's local data directory. On Windows
+/// the cache directory is the local data directory itself, which also holds
+/// cookies and local storage, so only these folders count as cache there.
+#[cfg(target_os = "windows")]
+const WEBVIEW2_CACHE_DIRS: &[&str] = &[
+    r"EBWebView\Default\Cache",
+    r"EBWebView\Default\Code Cache",
+    r"EBWebView\Default\GPUCache",
+];
+
+/// Data that can be deleted without losing sessions, agents or settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageCategory {
+    /// WebView and HTTP caches
+    Cache,
+    /// Log files and process dumps
+    Logs,
+    /// Generated image previews
+    Thumbnails,
+    /// Session exports older than 30 days
+    OldExports,
+    /// Checkpoint file contents no longer referenced by any checkpoint
+    OrphanedCheckpointBlobs,
+}
+
+impl StorageCategory {
+    const ALL: [StorageCategory; 5] = [
+        StorageCategory::Cache,
+        StorageCategory::Logs,
+        StorageCategory::Thumbnails,
+        StorageCategory::OldExports,
+        StorageCategory::OrphanedCheckpointBlobs,
+    ];
+}
+
+/// Disk usage of one category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageCategoryUsage {
+    pub category: StorageCategory,
+    pub bytes: u64,
+    pub files: u64,
+    /// Directories the category's files live in
+    pub locations: Vec<String>,
+}
+
+/// Disk usage of every category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageBreakdown {
+    pub categories: Vec<StorageCategoryUsage>,
+    pub total_bytes: u64,
+}
+
+/// Result of cleaning one category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageCleanupResult {
+    pub category: StorageCategory,
+    pub freed_bytes: u64,
+    pub removed_files: u64,
+    /// Files that could not be removed, with the reason
+    pub errors: Vec<String>,
+}
+
+/// Disk usage of one checkpoint: its messages, metadata and file
+/// references, without the content pool shared with other checkpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointStorageUsage {
+    pub session_id: String,
+    pub checkpoint_id: String,
+    pub bytes: u64,
+}
+
+/// Disk usage of one Claude project directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectStorageUsage {
+    pub project_id: String,
+    pub bytes: u64,
+    pub files: u64,
+    pub checkpoints: Vec<CheckpointStorageUsage>,
+}
+
+/// Running totals of [`get_project_storage_usage`], sent as
+/// `storage-usage-progress`
+#[derive(Debug, Clone, Serialize)]
+struct StorageUsageProgressEvent {
+    scan_id: String,
+    project_id: String,
+    progress: DirSize,
+}
+
+/// Cancel flags of running [`get_project_storage_usage`] scans by scan ID
+static ACTIVE_USAGE_SCANS: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+/// A file that belongs to a category
+struct CandidateFile {
+    path: PathBuf,
+    bytes: u64,
+}
+
+/// Directories of opcode's own data and of Claude's checkpoints
+struct StorageRoots {
+    cache: Vec<PathBuf>,
+    logs: Vec<PathBuf>,
+    thumbnails: PathBuf,
+    exports: PathBuf,
+    claude_projects: Option<PathBuf>,
+}
+
+impl StorageRoots {
+    fn resolve(app: &AppHandle) -> Result<Self, String> {
+        let data_dir = crate::portable::app_data_dir(app).map_err(|e| e.to_string())?;
+
+        // Portable installs keep everything below the data directory
+        let (cache, mut logs) = if crate::portable::is_portable() {
+            (vec![data_dir.join("cache")], vec![data_dir.join("logs")])
+        } else {
+            (
+                Self::cache_dirs(app),
+                app.path().app_log_dir().into_iter().collect(),
+            )
+        };
+        logs.push(data_dir.join(crate::commands::process_dumps::DUMPS_DIR));
+
+        Ok(StorageRoots {
+            cache,
+            logs,
+            thumbnails: data_dir.join("thumbnails"),
+            exports: data_dir.join("exports"),
+            claude_projects: dirs::home_dir().map(|home| home.join(".claude").join("projects")),
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    fn cache_dirs(app: &AppHandle) -> Vec<PathBuf> {
+        app.path()
+            .app_local_data_dir()
+            .map(|dir| {
+                WEBVIEW2_CACHE_DIRS
+                    .iter()
+                    .map(|sub| dir.join(sub))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn cache_dirs(app: &AppHandle) -> Vec<PathBuf> {
+        app.path().app_cache_dir().into_iter().collect()
+    }
+
+    fn locations(&self, category: StorageCategory) -> Vec<PathBuf> {
+        match category {
+            StorageCategory::Cache => self.cache.clone(),
+            StorageCategory::Logs => self.logs.clone(),
+            StorageCategory::Thumbnails => vec![self.thumbnails.clone()],
+            StorageCategory::OldExports => vec![self.exports.clone()],
+            StorageCategory::OrphanedCheckpointBlobs => {
+                self.claude_projects.iter().cloned().collect()
+            }
+        }
+    }
+
+    fn candidates(&self, category: StorageCategory) -> Vec<CandidateFile> {
+        match category {
+            StorageCategory::OldExports => {
+                let cutoff = SystemTime::now() - OLD_EXPORT_AGE;
+                files_below(&self.exports, |meta| {
+                    meta.modified().map(|m| m < cutoff).unwrap_or(false)
+                })
+            }
+            StorageCategory::OrphanedCheckpointBlobs => self
+                .claude_projects
+                .as_deref()
+                .map(orphaned_checkpoint_blobs)
+                .unwrap_or_default(),
+            _ => self
+                .locations(category)
+                .iter()
+                .flat_map(|dir| files_below(dir, |_| true))
+                .collect(),
+        }
+    }
+}
+
+/// Report how much disk space each cleanable category uses
+#[tauri::command]
+pub async fn get_storage_breakdown(app: AppHandle) -> Result<StorageBreakdown, String> {
+    let roots = StorageRoots::resolve(&app)?;
+
+    tokio::task::spawn_blocking(move || {
+        let categories: Vec<StorageCategoryUsage> = StorageCategory::ALL
+            .iter()
+            .map(|&category| {
+                let files = roots.candidates(category);
+                StorageCategoryUsage {
+                    category,
+                    bytes: files.iter().map(|f| f.bytes).sum(),
+                    files: files.len() as u64,
+                    locations: roots
+                        .locations(category)
+                        .iter()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect(),
+                }
+            })
+            .collect();
+
+        StorageBreakdown {
+            total_bytes: categories.iter().map(|c| c.bytes).sum(),
+            categories,
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Delete the files of the selected categories
+#[tauri::command]
+pub async fn clean_storage(
+    app: AppHandle,
+    categories: Vec<StorageCategory>,
+) -> Result<Vec<StorageCleanupResult>, String> {
+    let roots = StorageRoots::resolve(&app)?;
+
+    tokio::task::spawn_blocking(move || {
+        StorageCategory::ALL
+            .into_iter()
+            .filter(|category| categories.contains(category))
+            .map(|category| {
+                let result = clean_files(category, roots.candidates(category));
+                info!(
+                    "Cleaned {:?}: removed {} files ({} bytes), {} errors",
+                    category,
+                    result.removed_files,
+                    result.freed_bytes,
+                    result.errors.len()
+                );
+                result
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Report how much disk space each Claude project and each of its
+/// checkpoints uses
+///
+/// Large projects take a while to walk, so `storage-usage-progress` events
+/// carry running totals and [`cancel_storage_usage`] with the same
+/// `scan_id` stops the scan.
+#[tauri::command]
+pub async fn get_project_storage_usage(
+    app: AppHandle,
+    scan_id: String,
+) -> Result<Vec<ProjectStorageUsage>, String> {
+    let roots = StorageRoots::resolve(&app)?;
+    let Some(projects_dir) = roots.claude_projects else {
+        return Ok(Vec::new());
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    ACTIVE_USAGE_SCANS
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get_or_insert_with(HashMap::new)
+        .insert(scan_id.clone(), cancel.clone());
+
+    let result = project_storage_usage(&app, &projects_dir, &scan_id, cancel).await;
+
+    if let Ok(mut scans) = ACTIVE_USAGE_SCANS.lock() {
+        if let Some(scans) = scans.as_mut() {
+            scans.remove(&scan_id);
+        }
+    }
+    result
+}
+
+/// Stop a running [`get_project_storage_usage`] scan
+#[tauri::command]
+pub async fn cancel_storage_usage(scan_id: String) -> Result<bool, String> {
+    let scans = ACTIVE_USAGE_SCANS.lock().map_err(|e| e.to_string())?;
+    match scans.as_ref().and_then(|scans| scans.get(&scan_id)) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Free bytes on the volume holding `path`
+///
+/// With `required_bytes`, fails with a "need X MB free on D:" message when
+/// there isn't enough room, so the frontend can check before downloading
+/// an update or writing an export.
+#[tauri::command]
+pub async fn get_free_space(path: String, required_bytes: Option<u64>) -> Result<u64, String> {
+    tokio::task::spawn_blocking(move || {
+        if let Some(required) = required_bytes {
+            crate::utils::disk_space::ensure_free_space(&path, required)
+                .map_err(|e| e.to_string())?;
+        }
+        crate::utils::disk_space::get_free_space(&path).map_err(|e| format!("{:#}", e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Progress of a move that has to copy, sent as `move-progress`
+#[derive(Debug, Clone, Serialize)]
+struct MoveProgressEvent {
+    source: String,
+    destination: String,
+    progress: MoveProgress,
+}
+
+/// Move `source` to `destination`, emitting `move-progress` events when the
+/// move has to copy
+///
+/// Blocks; call it from a blocking task.
+pub(crate) fn move_with_progress(
+    app: &AppHandle,
+    source: &Path,
+    destination: &Path,
+) -> Result<MoveReport, String> {
+    info!("Moving {} to {}", source.display(), destination.display());
+    let (source_name, destination_name) = (
+        source.to_string_lossy().to_string(),
+        destination.to_string_lossy().to_string(),
+    );
+    crate::utils::move_path::move_path(source, destination, |progress| {
+        let _ = app.emit(
+            "move-progress",
+            MoveProgressEvent {
+                source: source_name.clone(),
+                destination: destination_name.clone(),
+                progress: progress.clone(),
+            },
+        );
+    })
+    .map_err(|e| format!("{:#}", e))
+}
+
+/// Move opcode's data directory (database, settings, exports) to
+/// `destination`, e.g. to another drive
+///
+/// The database is closed for the move and reopened at the new location,
+/// which later launches find through a note left in the platform data
+/// directory. Moves to another drive copy and verify every file before the
+/// original is removed. Refused while agents or Claude sessions are running,
+/// as they write to the database at its old path. Not available in portable
+/// mode, where the data lives next to the executable.
+#[tauri::command]
+pub async fn move_data_directory(
+    app: AppHandle,
+    destination: String,
+) -> Result<MoveReport, String> {
+    if crate::portable::is_portable() {
+        return Err("The data directory of a portable install can't be moved".to_string());
+    }
+    let destination =
+        crate::utils::paths::parse_user_path(&destination).map_err(|e| e.to_string())?;
+    let source = crate::portable::app_data_dir(&app).map_err(|e| e.to_string())?;
+
+    tokio::task::spawn_blocking(move || {
+        let db = app.state::<AgentDb>();
+        let mut conn = db.0.lock().map_err(|e| e.to_string())?;
+
+        // Running agents write their status to the database at the path they
+        // were started with
+        let running = app
+            .state::<ProcessRegistryState>()
+            .0
+            .get_running_processes()?
+            .len();
+        if running > 0 {
+            return Err(format!(
+                "Stop the {} running agent or Claude session(s) before moving the data directory",
+                running
+            ));
+        }
+
+        // Close the database so every file in the directory can be moved
+        let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
+        let in_memory = rusqlite::Connection::open_in_memory().map_err(|e| e.to_string())?;
+        drop(std::mem::replace(&mut *conn, in_memory));
+
+        // A failed move leaves the data where it was
+        let moved = move_with_progress(&app, &source, &destination);
+        let data_dir = if moved.is_ok() { &destination } else { &source };
+        *conn =
+            open_database(data_dir).map_err(|e| format!("Failed to reopen the database: {}", e))?;
+
+        let report = moved?;
+        crate::portable::set_data_location(&app, &destination).map_err(|e| {
+            format!(
+                "Moved the data to {} but could not record the new location: {}",
+                destination.display(),
+                e
+            )
+        })?;
+        Ok(report)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn project_storage_usage(
+    app: &AppHandle,
+    projects_dir: &Path,
+    scan_id: &str,
+    cancel: Arc<AtomicBool>,
+) -> Result<Vec<ProjectStorageUsage>, String> {
+    let projects: Vec<PathBuf> = match std::fs::read_dir(projects_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let cancelled = || "Storage usage scan was cancelled".to_string();
+    let mut usage = Vec::new();
+    for project_dir in projects {
+        let project_id = project_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let app = app.clone();
+        let (event_scan, event_project) = (scan_id.to_string(), project_id.clone());
+        let size = compute_dir_size(project_dir.clone(), cancel.clone(), move |progress| {
+            let _ = app.emit(
+                "storage-usage-progress",
+                StorageUsageProgressEvent {
+                    scan_id: event_scan.clone(),
+                    project_id: event_project.clone(),
+                    progress: *progress,
+                },
+            );
+        })
+        .await
+        .map_err(|e| format!("{:#}", e))?
+        .ok_or_else(cancelled)?;
+
+        let flag = cancel.clone();
+        let checkpoints =
+            tokio::task::spawn_blocking(move || checkpoint_usage(&project_dir, &flag))
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(cancelled)?;
+
+        usage.push(ProjectStorageUsage {
+            project_id,
+            bytes: size.bytes,
+            files: size.files,
+            checkpoints,
+        });
+    }
+    Ok(usage)
+}
+
+/// Sizes of the checkpoints of every session timeline in `project_dir`, or
+/// `None` if cancelled
+fn checkpoint_usage(
+    project_dir: &Path,
+    cancel: &AtomicBool,
+) -> Option<Vec<CheckpointStorageUsage>> {
+    let Ok(sessions) = std::fs::read_dir(project_dir.join(".timelines")) else {
+        return Some(Vec::new());
+    };
+
+    let mut usage = Vec::new();
+    for session in sessions.flatten() {
+        let session_dir = session.path();
+        let Ok(checkpoints) = std::fs::read_dir(session_dir.join("checkpoints")) else {
+            continue;
+        };
+        for checkpoint in checkpoints.flatten() {
+            let checkpoint_id = checkpoint.file_name().to_string_lossy().to_string();
+            let mut bytes = 0;
+            for dir in [
+                checkpoint.path(),
+                session_dir.join("files").join("refs").join(&checkpoint_id),
+            ] {
+                match dir_size_blocking(&dir, cancel, PROGRESS_INTERVAL, |_| {}) {
+                    Ok(Some(size)) => bytes += size.bytes,
+                    Ok(None) => return None,
+                    // Checkpoints without file references have no refs dir
+                    Err(_) => {}
+                }
+            }
+            usage.push(CheckpointStorageUsage {
+                session_id: session.file_name().to_string_lossy().to_string(),
+                checkpoint_id,
+                bytes,
+            });
+        }
+    }
+    Some(usage)
+}
+
+fn clean_files(category: StorageCategory, files: Vec<CandidateFile>) -> StorageCleanupResult {
+    let mut result = StorageCleanupResult {
+        category,
+        freed_bytes: 0,
+        removed_files: 0,
+        errors: Vec::new(),
+    };
+
+    for file in files {
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                result.freed_bytes += file.bytes;
+                result.removed_files += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("Failed to remove {}: {}", file.path.display(), e);
+                result
+                    .errors
+                    .push(format!("{}: {}", file.path.display(), e));
+            }
+        }
+    }
+
+    result
+}
+
+/// Regular files below `dir` whose metadata matches `keep`
+fn files_below(dir: &Path, keep: impl Fn(&std::fs::Metadata) -> bool) -> Vec<CandidateFile> {
+    if !dir.is_dir() {
+        return Vec::new();
+    }
+
+    WalkDir::new(dir)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            keep(&meta).then(|| CandidateFile {
+                path: entry.into_path(),
+                bytes: meta.len(),
+            })
+        })
+        .collect()
+}
+
+/// Unreferenced content pool files of every session timeline below `projects_dir`
+fn orphaned_checkpoint_blobs(projects_dir: &Path) -> Vec<CandidateFile> {
+    let Ok(projects) = std::fs::read_dir(projects_dir) else {
+        return Vec::new();
+    };
+
+    let mut blobs = Vec::new();
+    for project in projects.flatten() {
+        let Ok(sessions) = std::fs::read_dir(project.path().join(".timelines")) else {
+            continue;
+        };
+        for session in sessions.flatten() {
+            let files_dir = session.path().join("files");
+            match CheckpointStorage::find_orphaned_content(&files_dir) {
+                Ok(orphaned) => blobs.extend(orphaned.into_iter().filter_map(|path| {
+                    let meta = std::fs::symlink_metadata(&path).ok()?;
+                    meta.is_file().then_some(CandidateFile {
+                        path,
+                        bytes: meta.len(),
+                    })
+                })),
+                Err(e) => warn!(
+                    "Skipping checkpoint content in {}: {}",
+                    files_dir.display(),
+                    e
+                ),
+            }
+        }
+    }
+    blobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphaned_checkpoint_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let files_dir = dir
+            .path()
+            .join("project")
+            .join(".timelines")
+            .join("session")
+            .join("files");
+        let pool = files_dir.join("content_pool");
+        let refs = files_dir.join("refs").join("checkpoint");
+        std::fs::create_dir_all(&pool).unwrap();
+        std::fs::create_dir_all(&refs).unwrap();
+        std::fs::write(pool.join("kept"), "kept").unwrap();
+        std::fs::write(pool.join("orphan"), "orphan!").unwrap();
+        std::fs::write(refs.join("file.json"), r#"{"hash":"kept"}"#).unwrap();
+
+        let blobs = orphaned_checkpoint_blobs(dir.path());
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].path, pool.join("orphan"));
+        assert_eq!(blobs[0].bytes, 7);
+
+        let result = clean_files(StorageCategory::OrphanedCheckpointBlobs, blobs);
+        assert_eq!(result.removed_files, 1);
+        assert_eq!(result.freed_bytes, 7);
+        assert!(pool.join("kept").exists());
+        assert!(!pool.join("orphan").exists());
+    }
+
+    #[test]
+    fn test_checkpoint_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = dir.path().join(".timelines").join("session");
+        let checkpoint = session.join("checkpoints").join("cp1");
+        let refs = session.join("files").join("refs").join("cp1");
+        std::fs::create_dir_all(&checkpoint).unwrap();
+        std::fs::create_dir_all(&refs).unwrap();
+        std::fs::create_dir_all(session.join("checkpoints").join("cp2")).unwrap();
+        std::fs::write(checkpoint.join("messages.jsonl"), "{}\n").unwrap();
+        std::fs::write(refs.join("file.json"), "{}").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut usage = checkpoint_usage(dir.path(), &cancel).unwrap();
+        usage.sort_by(|a, b| a.checkpoint_id.cmp(&b.checkpoint_id));
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].session_id, "session");
+        assert_eq!(usage[0].bytes, 3 + 2);
+        assert_eq!(usage[1].bytes, 0);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(checkpoint_usage(dir.path(), &cancel).is_none());
+    }
+}
@@ -0,0 +1,34 @@
+//! Registering the "Opcode Claude Shell" Windows Terminal profile
+//!
+//! See [`terminal`](crate::windows::terminal) for the fragment itself. Other
+//! platforms have no Windows Terminal: installing fails with the
+//! `unsupported` [`CommandError`] and removing finds nothing to remove.
+
+use tauri::AppHandle;
+
+use crate::windows::terminal;
+use crate::windows::unsupported::CommandError;
+
+/// Write the profile for the detected Claude CLI
+///
+/// Returns the path of the fragment, or `None` in portable mode, where
+/// nothing outside the portable directory is written.
+#[tauri::command]
+pub async fn install_terminal_profile(app: AppHandle) -> Result<Option<String>, CommandError> {
+    let claude_path = crate::claude_binary::find_claude_binary(&app)?;
+    let path = tauri::async_runtime::spawn_blocking(move || {
+        terminal::install_terminal_profile(&claude_path)
+    })
+    .await
+    .map_err(|e| CommandError::from(e.to_string()))??;
+    Ok(path.map(|path| path.to_string_lossy().to_string()))
+}
+
+/// Remove the profile, returning whether one was installed
+#[tauri::command]
+pub async fn remove_terminal_profile() -> Result<bool, CommandError> {
+    tauri::async_runtime::spawn_blocking(terminal::remove_terminal_profile)
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+}
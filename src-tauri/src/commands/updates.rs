@@ -0,0 +1,121 @@
+//! Downloading opcode updates
+//!
+//! The installer is downloaded into `updates` under opcode's data directory
+//! and only kept once it hashes to the SHA-256 published with the release,
+//! so a truncated or tampered download is never offered to the user.
+
+use log::{info, warn};
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tokio::io::AsyncWriteExt;
+
+use crate::utils::hashing::{verify_file_hash, HashAlgorithm};
+
+/// Directory under the data directory holding downloaded installers
+const UPDATES_DIR: &str = "updates";
+
+/// Name to save the installer at `url` under
+///
+/// The last path segment of the URL, unless it could point outside the
+/// updates directory.
+fn installer_file_name(url: &reqwest::Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| {
+            !name.is_empty() && *name != "." && *name != ".." && !name.contains(['/', '\\', ':'])
+        })
+        .unwrap_or("opcode-update")
+        .to_string()
+}
+
+/// Download the installer at `url` and check it against `sha256`, the hex
+/// digest published with the release
+///
+/// Returns the path of the verified installer. A `background` download,
+/// one the user didn't ask for, only runs inside the maintenance window.
+#[tauri::command]
+pub async fn download_update(
+    app: AppHandle,
+    url: String,
+    sha256: String,
+    background: Option<bool>,
+) -> Result<String, String> {
+    if background.unwrap_or(false) {
+        crate::commands::maintenance::ensure_window_open(&app)
+            .map_err(|reason| format!("Not downloading the update now: {}", reason))?;
+    }
+
+    let url = reqwest::Url::parse(&url).map_err(|e| format!("Invalid update URL: {}", e))?;
+    let dir = crate::portable::app_data_dir(&app)
+        .map_err(|e| e.to_string())?
+        .join(UPDATES_DIR);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(installer_file_name(&url));
+    let partial = PathBuf::from(format!("{}.part", path.display()));
+
+    info!("Downloading update from {}", url);
+    let client = crate::commands::certificates::http_client()?;
+    let mut response = client
+        .get(url)
+        .header("User-Agent", "opcode-App")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download update: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to download update: HTTP {}",
+            response.status()
+        ));
+    }
+
+    if let Some(size) = response.content_length() {
+        crate::utils::disk_space::ensure_free_space(&dir, size).map_err(|e| e.to_string())?;
+    }
+
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to download update: {}", e))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    }
+    file.sync_all()
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    drop(file);
+
+    if let Err(e) = verify_file_hash(&partial, HashAlgorithm::Sha256, &sha256).await {
+        warn!("Discarding update download: {:#}", e);
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(format!("{:#}", e));
+    }
+    tokio::fs::rename(&partial, &path)
+        .await
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+
+    info!("Update downloaded to {}", path.display());
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_installer_file_name() {
+        let name = |url: &str| installer_file_name(&reqwest::Url::parse(url).unwrap());
+        assert_eq!(
+            name("https://example.com/releases/v1.2.0/opcode_1.2.0_x64-setup.exe"),
+            "opcode_1.2.0_x64-setup.exe"
+        );
+        assert_eq!(name("https://example.com/"), "opcode-update");
+        assert_eq!(name("https://example.com/C:evil.exe"), "opcode-update");
+    }
+}
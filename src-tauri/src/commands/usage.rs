@@ -712,3 +712,298 @@ pub fn get_session_stats(
 
     Ok(by_session)
 }
+
+/// app_settings key the spending budgets are stored under (as JSON)
+const BUDGETS_KEY: &str = "usage_budgets";
+
+/// Smoothing factor for the exponentially weighted daily spend
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Monthly spending budgets used for burn-rate alerts
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UsageBudgets {
+    /// Budget for all projects combined
+    pub monthly: Option<f64>,
+    /// Budgets keyed by project path
+    #[serde(default)]
+    pub projects: HashMap<String, f64>,
+}
+
+/// Month-end spend projection for all projects or a single project
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpendForecast {
+    /// `None` for the overall forecast
+    project_path: Option<String>,
+    project_name: String,
+    spent: f64,
+    /// Average daily spend so far this month
+    daily_rate_linear: f64,
+    /// Exponentially weighted daily spend, favouring recent days
+    daily_rate_ewma: f64,
+    projected_linear: f64,
+    projected_ewma: f64,
+    budget: Option<f64>,
+}
+
+/// Raised when the projected month-end spend exceeds a budget
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BudgetAlert {
+    project_path: Option<String>,
+    project_name: String,
+    budget: f64,
+    spent: f64,
+    projected: f64,
+    /// Date the budget is expected to run out at the current burn rate
+    exhaustion_date: Option<String>,
+    /// Whether the budget is already used up
+    exceeded: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageForecast {
+    /// Month being projected, as YYYY-MM
+    month: String,
+    days_elapsed: u32,
+    days_in_month: u32,
+    overall: SpendForecast,
+    by_project: Vec<SpendForecast>,
+    alerts: Vec<BudgetAlert>,
+}
+
+/// Alerts already emitted, keyed by month and project, so each fires once
+static EMITTED_ALERTS: std::sync::Mutex<Option<HashSet<String>>> = std::sync::Mutex::new(None);
+
+#[command]
+pub async fn get_usage_budgets(
+    db: tauri::State<'_, crate::commands::agents::AgentDb>,
+) -> Result<UsageBudgets, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(load_usage_budgets(&conn))
+}
+
+#[command]
+pub async fn save_usage_budgets(
+    db: tauri::State<'_, crate::commands::agents::AgentDb>,
+    budgets: UsageBudgets,
+) -> Result<(), String> {
+    if budgets.monthly.is_some_and(|b| b <= 0.0) || budgets.projects.values().any(|b| *b <= 0.0) {
+        return Err("Budgets must be greater than zero".to_string());
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let value = serde_json::to_string(&budgets).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        rusqlite::params![BUDGETS_KEY, value],
+    )
+    .map_err(|e| format!("Failed to save {}: {}", BUDGETS_KEY, e))?;
+
+    Ok(())
+}
+
+/// Project month-end spend overall and per project, emitting a
+/// `usage-budget-alert` event the first time a projection exceeds its budget
+#[command]
+pub async fn get_usage_forecast(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, crate::commands::agents::AgentDb>,
+) -> Result<UsageForecast, String> {
+    use chrono::Datelike;
+    use tauri::Emitter;
+
+    let budgets = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        load_usage_budgets(&conn)
+    };
+
+    let claude_path = dirs::home_dir()
+        .ok_or("Failed to get home directory")?
+        .join(".claude");
+
+    let today = Local::now().date_naive();
+    let month_start = today.with_day(1).ok_or("Failed to determine start of month")?;
+    let days_in_month = days_in_month(today).ok_or("Failed to determine length of month")?;
+    let days_elapsed = today.day();
+
+    // Daily cost series for this month, overall and per project
+    let mut overall_daily = vec![0.0; days_elapsed as usize];
+    let mut project_daily: HashMap<String, Vec<f64>> = HashMap::new();
+
+    for entry in get_all_usage_entries(&claude_path) {
+        let Ok(dt) = DateTime::parse_from_rfc3339(&entry.timestamp) else {
+            continue;
+        };
+        let date = dt.with_timezone(&Local).date_naive();
+        if date < month_start || date > today {
+            continue;
+        }
+
+        let day = (date.day() - 1) as usize;
+        overall_daily[day] += entry.cost;
+        project_daily
+            .entry(entry.project_path.clone())
+            .or_insert_with(|| vec![0.0; days_elapsed as usize])[day] += entry.cost;
+    }
+
+    let overall = spend_forecast(None, &overall_daily, days_in_month, budgets.monthly);
+
+    let mut by_project: Vec<SpendForecast> = project_daily
+        .iter()
+        .map(|(path, daily)| {
+            spend_forecast(Some(path), daily, days_in_month, budgets.projects.get(path).copied())
+        })
+        .collect();
+    by_project.sort_by(|a, b| b.projected_ewma.total_cmp(&a.projected_ewma));
+
+    let alerts: Vec<BudgetAlert> = std::iter::once(&overall)
+        .chain(by_project.iter())
+        .filter_map(|forecast| budget_alert(forecast, today, days_in_month))
+        .collect();
+
+    let month = today.format("%Y-%m").to_string();
+    if let Ok(mut emitted) = EMITTED_ALERTS.lock() {
+        let emitted = emitted.get_or_insert_with(HashSet::new);
+        for alert in &alerts {
+            let key = format!("{}:{}", month, alert.project_path.as_deref().unwrap_or(""));
+            if emitted.insert(key) {
+                log::warn!(
+                    "Projected spend for {} (${:.2}) exceeds budget ${:.2}",
+                    alert.project_name,
+                    alert.projected,
+                    alert.budget
+                );
+                let _ = app.emit("usage-budget-alert", alert);
+            }
+        }
+    }
+
+    Ok(UsageForecast {
+        month,
+        days_elapsed,
+        days_in_month,
+        overall,
+        by_project,
+        alerts,
+    })
+}
+
+fn load_usage_budgets(conn: &rusqlite::Connection) -> UsageBudgets {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        rusqlite::params![BUDGETS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+fn days_in_month(date: NaiveDate) -> Option<u32> {
+    use chrono::Datelike;
+
+    let (year, month) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    let next_month = NaiveDate::from_ymd_opt(year, month, 1)?;
+    Some(next_month.pred_opt()?.day())
+}
+
+fn spend_forecast(
+    project_path: Option<&String>,
+    daily: &[f64],
+    days_in_month: u32,
+    budget: Option<f64>,
+) -> SpendForecast {
+    let spent: f64 = daily.iter().sum();
+    let days_remaining = days_in_month.saturating_sub(daily.len() as u32) as f64;
+    let daily_rate_linear = if daily.is_empty() { 0.0 } else { spent / daily.len() as f64 };
+    let daily_rate_ewma = ewma(daily, EWMA_ALPHA);
+
+    SpendForecast {
+        project_path: project_path.cloned(),
+        project_name: project_path
+            .map(|p| p.rsplit('/').next().unwrap_or(p).to_string())
+            .unwrap_or_else(|| "All projects".to_string()),
+        spent,
+        daily_rate_linear,
+        daily_rate_ewma,
+        projected_linear: spent + daily_rate_linear * days_remaining,
+        projected_ewma: spent + daily_rate_ewma * days_remaining,
+        budget,
+    }
+}
+
+/// Exponentially weighted moving average, seeded with the first value
+fn ewma(values: &[f64], alpha: f64) -> f64 {
+    let mut iter = values.iter();
+    let Some(first) = iter.next() else {
+        return 0.0;
+    };
+    iter.fold(*first, |avg, value| alpha * value + (1.0 - alpha) * avg)
+}
+
+fn budget_alert(forecast: &SpendForecast, today: NaiveDate, days_in_month: u32) -> Option<BudgetAlert> {
+    use chrono::Datelike;
+
+    let budget = forecast.budget?;
+    // Alert on the larger of the two projections so a recent spike is not averaged away
+    let projected = forecast.projected_ewma.max(forecast.projected_linear);
+    if projected <= budget {
+        return None;
+    }
+
+    let exceeded = forecast.spent >= budget;
+    let rate = forecast.daily_rate_ewma.max(forecast.daily_rate_linear);
+    let exhaustion_date = if exceeded || rate <= 0.0 {
+        None
+    } else {
+        let days_left = ((budget - forecast.spent) / rate).ceil() as u32;
+        (today.day() + days_left <= days_in_month)
+            .then(|| today + chrono::Duration::days(days_left as i64))
+            .map(|date| date.format("%Y-%m-%d").to_string())
+    };
+
+    Some(BudgetAlert {
+        project_path: forecast.project_path.clone(),
+        project_name: forecast.project_name.clone(),
+        budget,
+        spent: forecast.spent,
+        projected,
+        exhaustion_date,
+        exceeded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spend_forecast_and_alert() {
+        assert_eq!(ewma(&[], EWMA_ALPHA), 0.0);
+        assert!((ewma(&[10.0, 10.0, 10.0], EWMA_ALPHA) - 10.0).abs() < 1e-9);
+        // Recent spikes pull the EWMA above the linear average
+        assert!(ewma(&[1.0, 1.0, 1.0, 20.0], EWMA_ALPHA) > 23.0 / 4.0);
+
+        let daily = vec![2.0; 10];
+        let forecast = spend_forecast(None, &daily, 30, Some(50.0));
+        assert!((forecast.spent - 20.0).abs() < 1e-9);
+        assert!((forecast.projected_linear - 60.0).abs() < 1e-9);
+
+        let today = NaiveDate::from_ymd_opt(2026, 4, 10).unwrap();
+        let alert = budget_alert(&forecast, today, 30).expect("projection exceeds budget");
+        assert!(!alert.exceeded);
+        assert_eq!(alert.exhaustion_date.as_deref(), Some("2026-04-25"));
+
+        let relaxed = spend_forecast(None, &daily, 30, Some(100.0));
+        assert!(budget_alert(&relaxed, today, 30).is_none());
+    }
+
+    #[test]
+    fn test_days_in_month() {
+        assert_eq!(days_in_month(NaiveDate::from_ymd_opt(2026, 2, 14).unwrap()), Some(28));
+        assert_eq!(days_in_month(NaiveDate::from_ymd_opt(2026, 12, 1).unwrap()), Some(31));
+    }
+}
@@ -0,0 +1,68 @@
+//! Windows Hello confirmation settings and checks
+//!
+//! See [`user_presence`](crate::windows::user_presence) for the prompt.
+//! Deleting all of a session's checkpoints, revealing a stored secret and
+//! stopping every running session call [`confirm_operation`] first. Other
+//! platforms have no Windows Hello: checking for it and turning the
+//! confirmation on fail with the `unsupported` [`CommandError`].
+
+use tauri::State;
+
+use crate::commands::agents::AgentDb;
+use crate::windows::unsupported::CommandError;
+use crate::windows::user_presence::{
+    self, ProtectedOperation, UserPresenceAvailability, UserPresenceSettings,
+};
+
+/// Get the Windows Hello confirmation settings
+#[tauri::command]
+pub async fn get_user_presence_settings(
+    db: State<'_, AgentDb>,
+) -> Result<UserPresenceSettings, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(user_presence::load_user_presence_settings(&conn))
+}
+
+/// Save the Windows Hello confirmation settings
+#[tauri::command]
+pub async fn save_user_presence_settings(
+    db: State<'_, AgentDb>,
+    settings: UserPresenceSettings,
+) -> Result<(), CommandError> {
+    // Turning it on where no prompt can be shown would lock the operations
+    #[cfg(not(target_os = "windows"))]
+    if settings.enabled {
+        return Err(crate::windows::Unsupported::new("Windows Hello").into());
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    user_presence::save_user_presence_settings(&conn, &settings)?;
+    Ok(())
+}
+
+/// Whether Windows Hello is set up and usable
+#[tauri::command]
+pub async fn get_user_presence_availability() -> Result<UserPresenceAvailability, CommandError> {
+    tauri::async_runtime::spawn_blocking(user_presence::user_presence_availability)
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+        .map_err(CommandError::from)
+}
+
+/// Ask for Windows Hello before `operation` if the settings require it
+pub async fn confirm_operation(db: &AgentDb, operation: ProtectedOperation) -> Result<(), String> {
+    let settings = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        user_presence::load_user_presence_settings(&conn)
+    };
+    if !settings.requires(operation) {
+        return Ok(());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        user_presence::confirm_protected_operation(&settings, operation)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{:#}", e))
+}
@@ -0,0 +1,36 @@
+//! BitLocker status of the drives holding projects and opcode's data
+//!
+//! See [`volume_protection`](crate::windows::volume_protection). Other
+//! platforms fail with the `unsupported` [`CommandError`].
+
+use crate::windows::unsupported::CommandError;
+
+/// Protection of the volume holding `path`, a drive such as `E:` or any
+/// path on it
+///
+/// Returns a
+/// [`VolumeProtectionStatus`](crate::windows::volume_protection::VolumeProtectionStatus)
+/// with its `warning` for the security panel, `null` when there is nothing
+/// to warn about.
+#[tauri::command]
+pub async fn get_volume_protection_status(path: String) -> Result<serde_json::Value, CommandError> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::windows::volume_protection;
+
+        let status = tauri::async_runtime::spawn_blocking(move || {
+            volume_protection::get_volume_protection_status(&path)
+        })
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))??;
+        let mut value = serde_json::to_value(&status).map_err(|e| e.to_string())?;
+        value["warning"] = serde_json::json!(status.warning());
+        Ok(value)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        Err(crate::windows::Unsupported::new("BitLocker status").into())
+    }
+}
@@ -0,0 +1,381 @@
+use log::{debug, info, warn};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::file_tree::relative_path;
+use crate::utils::path_matcher::PathMatcher;
+
+/// A batch is emitted at the latest after this many debounce periods, even
+/// while changes keep arriving (e.g. during `npm install`)
+const MAX_DELAY_FACTOR: u32 = 10;
+
+/// Options for [`watch_path`] and [`spawn_watcher`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchOptions {
+    /// Watch subdirectories too
+    pub recursive: bool,
+    /// Quiet period in milliseconds before a batch of changes is reported
+    pub debounce_ms: u64,
+    /// Extra patterns (gitignore syntax, e.g. `*.tmp` or `dist/**`) whose
+    /// changes are dropped
+    pub ignore: Vec<String>,
+    /// Also drop changes to paths the project's `.gitignore` excludes
+    pub respect_gitignore: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            recursive: true,
+            debounce_ms: 200,
+            ignore: Vec::new(),
+            respect_gitignore: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathChange {
+    /// Path relative to the watched directory, using `/` separators
+    pub path: String,
+    pub kind: PathChangeKind,
+}
+
+/// Payload of the `path-changed` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathChangeBatch {
+    pub watch_id: String,
+    /// The watched directory as passed to `watch_path`
+    pub root: String,
+    pub changes: Vec<PathChange>,
+}
+
+/// A running watcher; dropping it stops watching
+pub struct PathWatcher {
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// Active watchers started through [`watch_path`], keyed by watch ID
+static WATCHES: Mutex<Option<HashMap<String, PathWatcher>>> = Mutex::new(None);
+
+/// Changes of one batch, in the order paths were first seen
+#[derive(Debug, Default)]
+struct PendingChanges {
+    changes: Vec<PathChange>,
+}
+
+impl PendingChanges {
+    /// Merge a change into the batch, so a file created and modified in one
+    /// burst is reported once as created, and one created and removed again
+    /// not at all
+    fn add(&mut self, path: String, kind: PathChangeKind) {
+        use PathChangeKind::*;
+
+        let Some(index) = self.changes.iter().position(|c| c.path == path) else {
+            self.changes.push(PathChange { path, kind });
+            return;
+        };
+        let merged = match (self.changes[index].kind, kind) {
+            (Created, Removed) => None,
+            (Created, _) => Some(Created),
+            (Removed, Created) => Some(Modified),
+            (_, kind) => Some(kind),
+        };
+        match merged {
+            Some(kind) => self.changes[index].kind = kind,
+            None => {
+                self.changes.remove(index);
+            }
+        }
+    }
+
+    fn take(&mut self) -> Vec<PathChange> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+/// Map a notify event to (path, change) pairs
+fn classify(event: &notify::Event) -> Vec<(PathBuf, PathChangeKind)> {
+    let kind = match event.kind {
+        EventKind::Access(_) => return Vec::new(),
+        EventKind::Create(_) => PathChangeKind::Created,
+        EventKind::Remove(_) => PathChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => PathChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => PathChangeKind::Created,
+        // A rename reported as one event: old path first, new path second
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            return event
+                .paths
+                .iter()
+                .enumerate()
+                .map(|(i, path)| {
+                    let kind = if i == 0 {
+                        PathChangeKind::Removed
+                    } else {
+                        PathChangeKind::Created
+                    };
+                    (path.clone(), kind)
+                })
+                .collect();
+        }
+        _ => PathChangeKind::Modified,
+    };
+    event.paths.iter().map(|path| (path.clone(), kind)).collect()
+}
+
+/// Build the matchers that decide which changes are dropped
+fn matchers(root: &Path, options: &WatchOptions) -> Result<Vec<PathMatcher>, String> {
+    let mut matchers = Vec::new();
+    if options.respect_gitignore {
+        matchers.push(PathMatcher::for_project(root));
+    }
+    if !options.ignore.is_empty() {
+        matchers.push(PathMatcher::new(root, &options.ignore).map_err(|e| format!("{:#}", e))?);
+    }
+    Ok(matchers)
+}
+
+/// Collect events into batches and hand each batch to `on_changes`
+///
+/// Runs until the watcher is dropped, which closes the channel.
+fn debounce(
+    events: Receiver<notify::Event>,
+    root: PathBuf,
+    matchers: Vec<PathMatcher>,
+    quiet: Duration,
+    on_changes: impl Fn(Vec<PathChange>),
+) {
+    let mut pending = PendingChanges::default();
+    let add = |pending: &mut PendingChanges, event: notify::Event| {
+        for (path, kind) in classify(&event) {
+            let relative = relative_path(&root, &path);
+            if relative.is_empty() {
+                continue;
+            }
+            let is_dir = path.is_dir();
+            if matchers.iter().any(|m| m.is_match(&relative, is_dir)) {
+                continue;
+            }
+            pending.add(relative, kind);
+        }
+    };
+
+    let mut connected = true;
+    while connected {
+        let Ok(event) = events.recv() else {
+            break;
+        };
+        add(&mut pending, event);
+
+        let deadline = Instant::now() + quiet * MAX_DELAY_FACTOR;
+        while Instant::now() < deadline {
+            let wait = quiet.min(deadline.saturating_duration_since(Instant::now()));
+            match events.recv_timeout(wait) {
+                Ok(event) => add(&mut pending, event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    connected = false;
+                    break;
+                }
+            }
+        }
+
+        let changes = pending.take();
+        if !changes.is_empty() {
+            on_changes(changes);
+        }
+    }
+    debug!("Stopped delivering changes for {}", root.display());
+}
+
+/// Watch `path` and report debounced, filtered changes to `on_changes`
+///
+/// Uses `ReadDirectoryChangesW` on Windows, FSEvents on macOS and inotify on
+/// Linux (through `notify`). Bursts of events are coalesced into one batch
+/// per quiet period, and changes matched by the ignore rules in `options`
+/// are dropped. The callback runs on a background thread.
+pub fn spawn_watcher(
+    path: &Path,
+    options: &WatchOptions,
+    on_changes: impl Fn(Vec<PathChange>) + Send + 'static,
+) -> Result<PathWatcher, String> {
+    if !path.is_dir() {
+        return Err(format!("Not a directory: {}", path.display()));
+    }
+    let matchers = matchers(path, options)?;
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                let _ = tx.send(event);
+            }
+            Err(e) => warn!("File watcher error: {}", e),
+        }
+    })
+    .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher
+        .watch(path, mode)
+        .map_err(|e| format!("Failed to watch {}: {}", path.display(), e))?;
+
+    let root = path.to_path_buf();
+    let interval = Duration::from_millis(options.debounce_ms.max(1));
+    std::thread::Builder::new()
+        .name("opcode-watcher".to_string())
+        .spawn(move || debounce(rx, root, matchers, interval, on_changes))
+        .map_err(|e| format!("Failed to start the watcher thread: {}", e))?;
+
+    Ok(PathWatcher { _watcher: watcher })
+}
+
+/// Start emitting `path-changed` events for a directory
+///
+/// Returns an ID for [`unwatch_path`]; each event carries it.
+#[tauri::command]
+pub async fn watch_path(
+    app: AppHandle,
+    path: String,
+    options: Option<WatchOptions>,
+) -> Result<String, String> {
+    let watch_path = crate::utils::paths::parse_user_path(&path).map_err(|e| e.to_string())?;
+    let watch_id = uuid::Uuid::new_v4().to_string();
+    let event_id = watch_id.clone();
+    let root = path.clone();
+
+    let watcher = spawn_watcher(&watch_path, &options.unwrap_or_default(), move |changes| {
+        let batch = PathChangeBatch {
+            watch_id: event_id.clone(),
+            root: root.clone(),
+            changes,
+        };
+        let _ = app.emit("path-changed", &batch);
+    })?;
+
+    let mut watches = WATCHES.lock().map_err(|e| e.to_string())?;
+    watches
+        .get_or_insert_with(HashMap::new)
+        .insert(watch_id.clone(), watcher);
+    info!("Watching {} as {}", path, watch_id);
+    Ok(watch_id)
+}
+
+/// Stop a watcher started with [`watch_path`]
+#[tauri::command]
+pub async fn unwatch_path(watch_id: String) -> Result<bool, String> {
+    let mut watches = WATCHES.lock().map_err(|e| e.to_string())?;
+    let removed = watches
+        .as_mut()
+        .and_then(|w| w.remove(&watch_id))
+        .is_some();
+    if removed {
+        info!("Stopped watch {}", watch_id);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_changes_merge() {
+        let mut pending = PendingChanges::default();
+        pending.add("a.txt".to_string(), PathChangeKind::Created);
+        pending.add("a.txt".to_string(), PathChangeKind::Modified);
+        pending.add("b.txt".to_string(), PathChangeKind::Modified);
+        pending.add("tmp.swp".to_string(), PathChangeKind::Created);
+        pending.add("tmp.swp".to_string(), PathChangeKind::Removed);
+        pending.add("c.txt".to_string(), PathChangeKind::Removed);
+        pending.add("c.txt".to_string(), PathChangeKind::Created);
+
+        assert_eq!(
+            pending.take(),
+            vec![
+                PathChange {
+                    path: "a.txt".to_string(),
+                    kind: PathChangeKind::Created
+                },
+                PathChange {
+                    path: "b.txt".to_string(),
+                    kind: PathChangeKind::Modified
+                },
+                PathChange {
+                    path: "c.txt".to_string(),
+                    kind: PathChangeKind::Modified
+                },
+            ]
+        );
+        assert!(pending.take().is_empty());
+    }
+
+    #[test]
+    fn test_classify_rename() {
+        let event = notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/p/old.rs"))
+            .add_path(PathBuf::from("/p/new.rs"));
+        assert_eq!(
+            classify(&event),
+            vec![
+                (PathBuf::from("/p/old.rs"), PathChangeKind::Removed),
+                (PathBuf::from("/p/new.rs"), PathChangeKind::Created),
+            ]
+        );
+
+        let access = notify::Event::new(EventKind::Access(notify::event::AccessKind::Any));
+        assert!(classify(&access.add_path(PathBuf::from("/p/x"))).is_empty());
+    }
+
+    #[test]
+    fn test_spawn_watcher_debounces_and_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        std::fs::create_dir(dir.path().join("build")).unwrap();
+
+        let (tx, rx) = channel();
+        let options = WatchOptions {
+            debounce_ms: 100,
+            ignore: vec!["*.tmp".to_string()],
+            ..WatchOptions::default()
+        };
+        let _watcher = spawn_watcher(dir.path(), &options, move |changes| {
+            let _ = tx.send(changes);
+        })
+        .unwrap();
+
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() { run() }").unwrap();
+        std::fs::write(dir.path().join("scratch.tmp"), "x").unwrap();
+        std::fs::write(dir.path().join("build").join("out.o"), "x").unwrap();
+
+        let changes = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            changes,
+            vec![PathChange {
+                path: "main.rs".to_string(),
+                kind: PathChangeKind::Created
+            }]
+        );
+    }
+}
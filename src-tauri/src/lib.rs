@@ -4,8 +4,11 @@
 pub mod checkpoint;
 pub mod claude_binary;
 pub mod commands;
+pub mod data_protection;
+pub mod portable;
 pub mod process;
 pub mod utils;
+pub mod windows;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -4,7 +4,10 @@
 mod checkpoint;
 mod claude_binary;
 mod commands;
-mod process;
+mod data_protection;
+mod portable;
+#[allow(dead_code)] // Shared with the library, which uses more of it
+mod utils;
 
 use checkpoint::state::CheckpointState;
 use commands::agents::{
@@ -12,13 +15,15 @@ use commands::agents::{
     export_agent_to_file, fetch_github_agent_content, fetch_github_agents, get_agent,
     get_agent_run, get_agent_run_with_real_time_metrics, get_claude_binary_path,
     get_live_session_output, get_session_output, get_session_status, import_agent,
-    import_agent_from_file, import_agent_from_github, init_database, kill_agent_session,
-    list_agent_runs, list_agent_runs_with_metrics, list_agents, list_claude_installations,
-    list_running_sessions, load_agent_session_history, set_claude_binary_path, stream_session_output, update_agent, AgentDb,
+    import_agent_from_file, import_agent_from_github, init_database, kill_agent_session, kill_all_sessions,
+    list_agent_runs, list_agent_runs_with_metrics, list_agent_versions, list_agents, list_claude_installations,
+    list_running_sessions, list_session_runs, load_agent_session_history, retry_agent_run, rollback_agent, set_claude_binary_path, stream_session_output, update_agent, AgentDb,
 };
 use commands::claude::{
     cancel_claude_execution, check_auto_checkpoint, check_claude_version, cleanup_old_checkpoints,
-    clear_checkpoint_manager, continue_claude_code, create_checkpoint, create_project, execute_claude_code,
+    clear_checkpoint_manager, continue_claude_code, create_checkpoint, create_project, delete_session,
+    relocate_project, validate_path_for_use,
+    execute_claude_code, export_session,
     find_claude_md_files, fork_from_checkpoint, get_checkpoint_diff, get_checkpoint_settings,
     get_checkpoint_state_stats, get_claude_session_output, get_claude_settings, get_home_directory, get_project_sessions,
     get_recently_modified_files, get_session_timeline, get_system_prompt, list_checkpoints,
@@ -29,6 +34,15 @@ use commands::claude::{
     get_hooks_config, update_hooks_config, validate_hook_command,
     ClaudeProcessState,
 };
+use commands::evals::{
+    create_eval_suite, delete_eval_suite, get_eval_matrix, list_eval_results, list_eval_suites,
+    run_eval_suite, update_eval_suite,
+};
+use commands::maintenance::{
+    get_maintenance_status, get_maintenance_window, list_maintenance_reports,
+    record_activity, report_user_activity, run_maintenance_now, save_maintenance_window,
+    start_maintenance_scheduler,
+};
 use commands::mcp::{
     mcp_add, mcp_add_from_claude_desktop, mcp_add_json, mcp_get, mcp_get_server_status, mcp_list,
     mcp_read_project_config, mcp_remove, mcp_reset_project_choices, mcp_save_project_config,
@@ -36,15 +50,61 @@ use commands::mcp::{
 };
 
 use commands::usage::{
-    get_session_stats, get_usage_by_date_range, get_usage_details, get_usage_stats,
+    get_session_stats, get_usage_budgets, get_usage_by_date_range, get_usage_details,
+    get_usage_forecast, get_usage_stats, save_usage_budgets,
 };
+use commands::search::{cancel_grep, grep_project};
+use commands::file_tree::{get_file_tree, unwatch_file_tree, watch_file_tree};
+use commands::recent_changes::get_recent_changes;
+use commands::watcher::{unwatch_path, watch_path};
 use commands::storage::{
     storage_list_tables, storage_read_table, storage_update_row, storage_delete_row,
     storage_insert_row, storage_execute_sql, storage_reset_database,
 };
-use commands::proxy::{get_proxy_settings, save_proxy_settings, apply_proxy_settings};
+use commands::runtime_config::{
+    apply_runtime_config, get_runtime_config, init_logging, load_runtime_config,
+    save_runtime_config, start_settings_watcher,
+};
+use commands::storage_cleanup::{
+    cancel_storage_usage, clean_storage, get_free_space, get_project_storage_usage,
+    get_storage_breakdown, move_data_directory,
+};
+use commands::run_webhook::{get_run_webhook, save_run_webhook};
+use commands::secrets::{
+    delete_secret, list_secrets, migrate_settings_secrets, reveal_secret, store_secret,
+};
+use commands::proxy::{get_proxy_settings, save_proxy_settings, apply_proxy_settings, load_proxy_settings};
+use commands::certificates::{
+    apply_ca_certificate_settings, get_ca_certificate_settings, load_ca_certificate_settings,
+    save_ca_certificate_settings, test_ca_certificate,
+};
+use commands::defender::{list_defender_exclusions, set_defender_exclusion};
+use commands::deep_link::{deep_link_from_args, take_pending_deep_link, PendingDeepLink};
+use commands::platform::{get_platform_capabilities, preflight_operation};
+use commands::privacy::{get_privacy_permissions, request_privacy_permission};
+use commands::process_dumps::{capture_process_dump, list_process_dumps};
+use commands::drop_target::{
+    dropped_paths_from_args, handle_window_drop, take_pending_dropped_paths, PendingDroppedPaths,
+};
+use commands::efs::{get_efs_status, set_efs_encrypted};
+use commands::environment::{
+    apply_env_sanitizer_settings, get_env_sanitizer_settings, load_env_sanitizer_settings,
+    preview_env_sanitizer, save_env_sanitizer_settings,
+};
+use commands::agent_runner::{
+    get_agent_runner_status, install_agent_runner, start_agent_runner, uninstall_agent_runner,
+};
+use commands::terminal_profile::{install_terminal_profile, remove_terminal_profile};
+use commands::updates::download_update;
+use commands::user_presence::{
+    get_user_presence_availability, get_user_presence_settings, save_user_presence_settings,
+};
+use commands::volume_protection::get_volume_protection_status;
 use process::ProcessRegistryState;
-use std::sync::Mutex;
+// Lets the shared modules reach the library's process and platform code as
+// `crate::process` and `crate::windows`
+use opcode_lib::{process, windows};
+use std::sync::{Mutex, OnceLock};
 use tauri::Manager;
 
 #[cfg(target_os = "macos")]
@@ -52,93 +112,73 @@ use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
 
 
 fn main() {
-    // Initialize logger
-    env_logger::init();
+    // Initialize logger (its filter can be changed at runtime)
+    init_logging();
 
+    // A deep link launch is dispatched first: what follows its switch comes
+    // from a URL, so none of the helper switches are honoured along with it
+    let args: Vec<String> = std::env::args().collect();
+    if !args.iter().any(|arg| arg == commands::deep_link::DEEP_LINK_SWITCH) {
+        if let Some(code) = run_helper_from_args(&args) {
+            std::process::exit(code);
+        }
+    }
+
+    // Let the running instance take the foreground if this launch is forwarded to it
+    windows::activation::prepare_activation();
+
+    // Portable mode creates the windows itself, to keep the webview's data
+    // beside the executable as well
+    let mut context = app_context();
+    let portable_windows = if portable::is_portable() {
+        std::mem::take(&mut context.config_mut().app.windows)
+    } else {
+        Vec::new()
+    };
 
     tauri::Builder::default()
+        // Must come first: a second launch forwards its arguments and exits here
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            commands::deep_link::handle_forwarded_args(app, &args, &cwd);
+            if let Some(window) = app.get_webview_window("main") {
+                if let Err(e) = windows::activation::activate_window(&window) {
+                    log::warn!("Failed to activate the main window: {}", e);
+                }
+            }
+        }))
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
-        .setup(|app| {
-            // Initialize agents database
-            let conn = init_database(&app.handle()).expect("Failed to initialize agents database");
-            
-            // Load and apply proxy settings from the database
-            {
-                let db = AgentDb(Mutex::new(conn));
-                let proxy_settings = match db.0.lock() {
-                    Ok(conn) => {
-                        // Directly query proxy settings from the database
-                        let mut settings = commands::proxy::ProxySettings::default();
-                        
-                        let keys = vec![
-                            ("proxy_enabled", "enabled"),
-                            ("proxy_http", "http_proxy"),
-                            ("proxy_https", "https_proxy"),
-                            ("proxy_no", "no_proxy"),
-                            ("proxy_all", "all_proxy"),
-                        ];
-                        
-                        for (db_key, field) in keys {
-                            if let Ok(value) = conn.query_row(
-                                "SELECT value FROM app_settings WHERE key = ?1",
-                                rusqlite::params![db_key],
-                                |row| row.get::<_, String>(0),
-                            ) {
-                                match field {
-                                    "enabled" => settings.enabled = value == "true",
-                                    "http_proxy" => settings.http_proxy = Some(value).filter(|s| !s.is_empty()),
-                                    "https_proxy" => settings.https_proxy = Some(value).filter(|s| !s.is_empty()),
-                                    "no_proxy" => settings.no_proxy = Some(value).filter(|s| !s.is_empty()),
-                                    "all_proxy" => settings.all_proxy = Some(value).filter(|s| !s.is_empty()),
-                                    _ => {}
-                                }
-                            }
-                        }
-                        
-                        log::info!("Loaded proxy settings: enabled={}", settings.enabled);
-                        settings
-                    }
-                    Err(e) => {
-                        log::warn!("Failed to lock database for proxy settings: {}", e);
-                        commands::proxy::ProxySettings::default()
-                    }
-                };
-                
-                // Apply the proxy settings
-                apply_proxy_settings(&proxy_settings);
-            }
-            
-            // Re-open the connection for the app to manage
-            let conn = init_database(&app.handle()).expect("Failed to initialize agents database");
-            app.manage(AgentDb(Mutex::new(conn)));
-
-            // Initialize checkpoint state
-            let checkpoint_state = CheckpointState::new();
-
-            // Set the Claude directory path
-            if let Ok(claude_dir) = dirs::home_dir()
-                .ok_or_else(|| "Could not find home directory")
-                .and_then(|home| {
-                    let claude_path = home.join(".claude");
-                    claude_path
-                        .canonicalize()
-                        .map_err(|_| "Could not find ~/.claude directory")
-                })
-            {
-                let state_clone = checkpoint_state.clone();
-                tauri::async_runtime::spawn(async move {
-                    state_clone.set_claude_dir(claude_dir).await;
-                });
-            }
+        .setup(move |app| {
+            init_backend(app);
 
-            app.manage(checkpoint_state);
+            if let Some(data_dir) = portable::webview_data_dir() {
+                for config in &portable_windows {
+                    tauri::WebviewWindowBuilder::from_config(app.handle(), config)?
+                        .data_directory(data_dir.clone())
+                        .build()?;
+                }
+            }
 
-            // Initialize process registry
-            app.manage(ProcessRegistryState::default());
+            // Validate a deep link passed by the URL protocol handler
+            let pending_deep_link = PendingDeepLink::default();
+            match deep_link_from_args(&std::env::args().collect::<Vec<_>>()) {
+                Some(Ok(link)) => {
+                    log::info!("Launched with deep link to route: {}", link.route);
+                    if let Ok(mut pending) = pending_deep_link.0.lock() {
+                        *pending = Some(link);
+                    }
+                }
+                Some(Err(e)) => log::warn!("Rejected deep link: {}", e),
+                None => {}
+            }
+            app.manage(pending_deep_link);
 
-            // Initialize Claude process state
-            app.manage(ClaudeProcessState::default());
+            // Folders and session exports dropped onto the Opcode icon arrive as arguments
+            let dropped_paths = dropped_paths_from_args(&std::env::args().collect::<Vec<_>>());
+            if !dropped_paths.is_empty() {
+                log::info!("Launched with {} dropped paths", dropped_paths.len());
+            }
+            app.manage(PendingDroppedPaths(Mutex::new(dropped_paths)));
 
             // Apply window vibrancy with rounded corners on macOS
             #[cfg(target_os = "macos")]
@@ -171,11 +211,22 @@ fn main() {
 
             Ok(())
         })
+        .on_window_event(|window, event| match event {
+            tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                handle_window_drop(window, paths);
+            }
+            // Someone is using opcode, so idle-triggered maintenance waits
+            tauri::WindowEvent::Focused(true) => record_activity(),
+            _ => {}
+        })
         .invoke_handler(tauri::generate_handler![
             // Claude & Project Management
             list_projects,
             create_project,
+            validate_path_for_use,
             get_project_sessions,
+            relocate_project,
+            delete_session,
             get_home_directory,
             get_claude_settings,
             open_new_session,
@@ -187,6 +238,7 @@ fn main() {
             read_claude_md_file,
             save_claude_md_file,
             load_session_history,
+            export_session,
             execute_claude_code,
             continue_claude_code,
             resume_claude_code,
@@ -222,13 +274,20 @@ fn main() {
             update_agent,
             delete_agent,
             get_agent,
+            list_agent_versions,
+            rollback_agent,
             execute_agent,
             list_agent_runs,
             get_agent_run,
             list_agent_runs_with_metrics,
             get_agent_run_with_real_time_metrics,
+            retry_agent_run,
+            list_session_runs,
+            get_run_webhook,
+            save_run_webhook,
             list_running_sessions,
             kill_agent_session,
+            kill_all_sessions,
             get_session_status,
             cleanup_finished_processes,
             get_session_output,
@@ -246,12 +305,36 @@ fn main() {
             fetch_github_agent_content,
             import_agent_from_github,
             
+            // Agent Evals
+            list_eval_suites,
+            create_eval_suite,
+            update_eval_suite,
+            delete_eval_suite,
+            run_eval_suite,
+            list_eval_results,
+            get_eval_matrix,
+            
             // Usage & Analytics
             get_usage_stats,
+            get_usage_forecast,
+            get_usage_budgets,
+            save_usage_budgets,
             get_usage_by_date_range,
             get_usage_details,
             get_session_stats,
             
+            // Project Search
+            grep_project,
+            cancel_grep,
+            
+            // File Tree
+            get_file_tree,
+            watch_file_tree,
+            unwatch_file_tree,
+            get_recent_changes,
+            watch_path,
+            unwatch_path,
+            
             // MCP (Model Context Protocol)
             mcp_add,
             mcp_list,
@@ -274,6 +357,19 @@ fn main() {
             storage_insert_row,
             storage_execute_sql,
             storage_reset_database,
+            get_storage_breakdown,
+            get_project_storage_usage,
+            cancel_storage_usage,
+            get_free_space,
+            move_data_directory,
+            clean_storage,
+
+            // Secrets
+            list_secrets,
+            store_secret,
+            delete_secret,
+            reveal_secret,
+            migrate_settings_secrets,
             
             // Slash Commands
             commands::slash_commands::slash_commands_list,
@@ -284,7 +380,293 @@ fn main() {
             // Proxy Settings
             get_proxy_settings,
             save_proxy_settings,
+            
+            // Environment Sanitizer
+            get_env_sanitizer_settings,
+            save_env_sanitizer_settings,
+            preview_env_sanitizer,
+            
+            // Custom CA Certificates
+            get_ca_certificate_settings,
+            save_ca_certificate_settings,
+            test_ca_certificate,
+            
+            // Runtime Config
+            get_runtime_config,
+            save_runtime_config,
+            
+            // Deep Links
+            take_pending_deep_link,
+            take_pending_dropped_paths,
+            
+            // Portable Mode
+            portable::get_portable_mode,
+            
+            // macOS Privacy Permissions
+            get_privacy_permissions,
+            request_privacy_permission,
+            
+            // Platform Capabilities
+            get_platform_capabilities,
+            preflight_operation,
+            
+            // Headless Agent Runner
+            get_agent_runner_status,
+            install_agent_runner,
+            uninstall_agent_runner,
+            start_agent_runner,
+            
+            // Windows Terminal Profile
+            install_terminal_profile,
+            remove_terminal_profile,
+            
+            // Defender Exclusions
+            list_defender_exclusions,
+            set_defender_exclusion,
+            
+            // EFS Encryption
+            get_efs_status,
+            set_efs_encrypted,
+            
+            // Volume Protection
+            get_volume_protection_status,
+            
+            // Process Dumps
+            capture_process_dump,
+            list_process_dumps,
+            
+            // Windows Hello Confirmation
+            get_user_presence_settings,
+            save_user_presence_settings,
+            get_user_presence_availability,
+            
+            // Updates
+            download_update,
+            
+            // Maintenance
+            get_maintenance_window,
+            save_maintenance_window,
+            get_maintenance_status,
+            run_maintenance_now,
+            list_maintenance_reports,
+            report_user_activity,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(context)
+        .expect("error while building tauri application")
+        .run(|_app, _event| {
+            // Deep links and documents opened through Launch Services
+            #[cfg(target_os = "macos")]
+            if let tauri::RunEvent::Opened { urls } = _event {
+                commands::deep_link::handle_opened_urls(_app, &urls);
+            }
+
+            // Files written during the session when run through sudo
+            if let tauri::RunEvent::Exit = _event {
+                hand_back_data_dir(_app);
+            }
+        });
+}
+
+/// Give the data directory back to the user who started opcode through
+/// `sudo` or `pkexec`, so it still opens without root
+fn hand_back_data_dir(app: &tauri::AppHandle) {
+    #[cfg(target_os = "linux")]
+    if let Ok(data_dir) = portable::app_data_dir(app) {
+        if let Err(e) = windows::permissions::hand_back_to_invoking_user(&data_dir) {
+            log::warn!("Data directory stays owned by root: {:#}", e);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = app;
+}
+
+/// Run the helper mode selected by the switch in `args[1]`, returning its
+/// exit code, or `None` for a normal launch
+fn run_helper_from_args(args: &[String]) -> Option<i32> {
+    // An elevated copy of opcode started to perform admin operations
+    #[cfg(target_os = "windows")]
+    if let Some(code) = opcode_lib::windows::elevation_broker::run_elevation_broker_from_args(args)
+    {
+        return Some(code);
+    }
+
+    // Started between opcode and a Claude process to hand it the stored secrets
+    #[cfg(target_os = "windows")]
+    if let Some(code) = windows::secrets::run_claude_wrapper_from_args(args) {
+        return Some(code);
+    }
+
+    // An elevated copy of opcode started to install or remove the agent runner service
+    #[cfg(target_os = "windows")]
+    if let Some(code) = windows::service::run_service_helper_from_args(args) {
+        return Some(code);
+    }
+
+    // Started by the agent runner service, unit or job: no window, and not
+    // forwarded to a running instance by the single-instance plugin
+    if args.get(1).map(String::as_str) == Some(windows::service::AGENT_RUNNER_ARG) {
+        return Some(run_agent_runner());
+    }
+
+    None
+}
+
+/// The app's configuration and assets, embedded once for both modes
+fn app_context() -> tauri::Context {
+    tauri::generate_context!()
+}
+
+/// Open the database, apply the stored settings and start the background
+/// tasks shared by the app and the headless agent runner
+fn init_backend(app: &tauri::App) {
+    // Initialize agents database
+    let conn = init_database(&app.handle()).expect("Failed to initialize agents database");
+    hand_back_data_dir(app.handle());
+
+    // Load and apply proxy settings from the database
+    {
+        let db = AgentDb(Mutex::new(conn));
+        let proxy_settings = match db.0.lock() {
+            Ok(conn) => {
+                let settings = load_proxy_settings(&conn);
+                log::info!("Loaded proxy settings: enabled={}", settings.enabled);
+                settings
+            }
+            Err(e) => {
+                log::warn!("Failed to lock database for proxy settings: {}", e);
+                commands::proxy::ProxySettings::default()
+            }
+        };
+
+        // Apply the proxy settings
+        apply_proxy_settings(&proxy_settings);
+
+        // Move API keys out of Claude's settings and load them for spawned processes
+        tauri::async_runtime::spawn_blocking(commands::secrets::init_secrets);
+
+        // Load the spawn-time environment sanitizer settings
+        match db.0.lock() {
+            Ok(conn) => apply_env_sanitizer_settings(&load_env_sanitizer_settings(&conn)),
+            Err(e) => log::warn!("Failed to lock database for sanitizer settings: {}", e),
+        };
+
+        // Load the custom CA certificate settings
+        match db.0.lock() {
+            Ok(conn) => apply_ca_certificate_settings(&load_ca_certificate_settings(&conn)),
+            Err(e) => log::warn!("Failed to lock database for CA certificate settings: {}", e),
+        };
+
+        // Load log filters, feature flags and limits
+        match db.0.lock() {
+            Ok(conn) => apply_runtime_config(&load_runtime_config(&conn)),
+            Err(e) => log::warn!("Failed to lock database for runtime config: {}", e),
+        };
+    }
+
+    // Re-open the connection for the app to manage
+    let conn = init_database(&app.handle()).expect("Failed to initialize agents database");
+    app.manage(AgentDb(Mutex::new(conn)));
+
+    // Initialize checkpoint state
+    let checkpoint_state = CheckpointState::new();
+
+    // Set the Claude directory path
+    if let Ok(claude_dir) = dirs::home_dir()
+        .ok_or_else(|| "Could not find home directory")
+        .and_then(|home| {
+            let claude_path = home.join(".claude");
+            claude_path
+                .canonicalize()
+                .map_err(|_| "Could not find ~/.claude directory")
+        })
+    {
+        let state_clone = checkpoint_state.clone();
+        tauri::async_runtime::spawn(async move {
+            state_clone.set_claude_dir(claude_dir).await;
+        });
+    }
+
+    app.manage(checkpoint_state);
+
+    // Initialize process registry
+    app.manage(ProcessRegistryState::default());
+
+    // Initialize Claude process state
+    app.manage(ClaudeProcessState::default());
+
+    // Run background maintenance inside the configured window
+    start_maintenance_scheduler(app.handle().clone());
+
+    // Apply settings changed outside the settings commands without a restart
+    start_settings_watcher(app.handle().clone());
+}
+
+/// The headless agent runner, so a service stop request can end it
+static AGENT_RUNNER: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+/// Run the headless agent runner until it is stopped, returning the exit code
+fn run_agent_runner() -> i32 {
+    #[cfg(target_os = "windows")]
+    match windows::service::run_as_service(run_headless, stop_headless) {
+        Ok(true) => return 0,
+        // Started from a console rather than by the SCM
+        Ok(false) => {}
+        Err(e) => {
+            log::error!("Agent runner failed to start as a service: {:#}", e);
+            return 1;
+        }
+    }
+
+    run_headless()
+}
+
+/// Run the background tasks without a window until the runner is stopped
+fn run_headless() -> i32 {
+    log::info!("Starting the headless agent runner");
+    let mut context = app_context();
+    context.config_mut().app.windows.clear();
+
+    let builder = tauri::Builder::default();
+    // The Windows service runs this on a thread of the service dispatcher
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    let builder = builder.any_thread();
+
+    let app = match builder
+        .setup(|app| {
+            // Keep the runner out of the Dock
+            #[cfg(target_os = "macos")]
+            app.set_activation_policy(tauri::ActivationPolicy::Accessory);
+
+            init_backend(app);
+            Ok(())
+        })
+        .build(context)
+    {
+        Ok(app) => app,
+        Err(e) => {
+            log::error!("Failed to start the agent runner: {}", e);
+            return 1;
+        }
+    };
+    let _ = AGENT_RUNNER.set(app.handle().clone());
+
+    app.run_return(|_app, event| {
+        // Without windows, only an explicit exit ends the runner
+        if let tauri::RunEvent::ExitRequested {
+            code: None, api, ..
+        } = event
+        {
+            api.prevent_exit();
+        }
+    })
+}
+
+/// Ask the headless agent runner to exit
+#[cfg(target_os = "windows")]
+fn stop_headless() {
+    match AGENT_RUNNER.get() {
+        Some(app) => app.exit(0),
+        None => std::process::exit(0),
+    }
 }
@@ -0,0 +1,150 @@
+//! Portable mode support
+//!
+//! When a `portable.marker` file sits next to the executable, opcode runs in
+//! portable mode: all configuration, including the database and the webview's
+//! local storage, is stored in a `data` directory beside the executable and
+//! registry-based system integration (file associations, URL
+//! protocols, auto-start, PATH) is skipped. This allows running from a USB
+//! stick or on locked-down machines without leaving traces behind.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+
+/// File whose presence next to the executable enables portable mode
+pub const PORTABLE_MARKER: &str = "portable.marker";
+
+/// Directory (next to the executable) holding portable configuration
+const PORTABLE_DATA_DIR: &str = "data";
+
+/// Directory under the portable data directory holding the webview's data
+const WEBVIEW_DATA_DIR: &str = "webview";
+
+/// File left in the platform data directory when the data was moved
+/// elsewhere, holding the new location
+const DATA_LOCATION_FILE: &str = "data-location";
+
+static PORTABLE_ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Directory containing the executable, if running in portable mode
+pub fn portable_root() -> Option<&'static Path> {
+    PORTABLE_ROOT
+        .get_or_init(|| {
+            let exe = std::env::current_exe().ok()?;
+            let root = detect_portable_root(&exe);
+            if let Some(root) = &root {
+                log::info!("Portable mode enabled, storing data in {}", root.display());
+            }
+            root
+        })
+        .as_deref()
+}
+
+/// Whether opcode is running in portable mode
+pub fn is_portable() -> bool {
+    portable_root().is_some()
+}
+
+/// Report whether portable mode is active, so the UI can hide system integration options
+#[tauri::command]
+pub async fn get_portable_mode() -> Result<bool, String> {
+    Ok(is_portable())
+}
+
+/// Directory for opcode's own data (database, settings)
+///
+/// Resolves to `<exe dir>/data` in portable mode, and otherwise to the
+/// platform app data directory or wherever the data was moved from there.
+pub fn app_data_dir(app: &AppHandle) -> tauri::Result<PathBuf> {
+    match portable_root() {
+        Some(root) => Ok(root.join(PORTABLE_DATA_DIR)),
+        None => {
+            let default = app.path().app_data_dir()?;
+            Ok(relocated_data_dir(&default).unwrap_or(default))
+        }
+    }
+}
+
+/// Directory for the webview's data (local storage, cookies, caches) in
+/// portable mode
+///
+/// The frontend keeps settings such as open tabs and analytics consent in
+/// local storage, which otherwise ends up in the platform's local app data
+/// directory. WKWebView on macOS can't be pointed elsewhere.
+pub fn webview_data_dir() -> Option<PathBuf> {
+    portable_root().map(|root| root.join(PORTABLE_DATA_DIR).join(WEBVIEW_DATA_DIR))
+}
+
+/// Record where the data directory was moved to, or that it is back in the
+/// platform app data directory when `location` is that directory
+pub fn set_data_location(app: &AppHandle, location: &Path) -> std::io::Result<()> {
+    let default = app.path().app_data_dir().map_err(std::io::Error::other)?;
+    let pointer = default.join(DATA_LOCATION_FILE);
+    if location == default {
+        return match std::fs::remove_file(&pointer) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    std::fs::create_dir_all(&default)?;
+    crate::utils::atomic_write::atomic_write(&pointer, location.to_string_lossy().as_bytes())
+        .map_err(std::io::Error::other)
+}
+
+/// Where the data in `default` was moved to, if it was
+fn relocated_data_dir(default: &Path) -> Option<PathBuf> {
+    let location = std::fs::read_to_string(default.join(DATA_LOCATION_FILE)).ok()?;
+    let location = PathBuf::from(location.trim());
+    if !location.is_dir() {
+        // E.g. a drive that isn't connected; don't create a fresh database there
+        log::warn!(
+            "Moved data directory {} is missing, using {}",
+            location.display(),
+            default.display()
+        );
+        return None;
+    }
+    Some(location)
+}
+
+fn detect_portable_root(exe: &Path) -> Option<PathBuf> {
+    let dir = exe.parent()?;
+    dir.join(PORTABLE_MARKER)
+        .is_file()
+        .then(|| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_portable_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("opcode.exe");
+
+        assert_eq!(detect_portable_root(&exe), None);
+
+        std::fs::write(dir.path().join(PORTABLE_MARKER), "").unwrap();
+        assert_eq!(detect_portable_root(&exe), Some(dir.path().to_path_buf()));
+    }
+
+    #[test]
+    fn test_relocated_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default");
+        let moved = dir.path().join("moved");
+        std::fs::create_dir_all(&default).unwrap();
+        assert_eq!(relocated_data_dir(&default), None);
+
+        std::fs::write(
+            default.join(DATA_LOCATION_FILE),
+            moved.to_string_lossy().as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(relocated_data_dir(&default), None);
+
+        std::fs::create_dir(&moved).unwrap();
+        assert_eq!(relocated_data_dir(&default), Some(moved));
+    }
+}
@@ -0,0 +1,293 @@
+//! Running external commands with a deadline
+//!
+//! Helper tools such as `powershell`, `wmic` or `icacls` occasionally hang
+//! (waiting on a dialog, a locked resource or a stalled WMI provider).
+//! [`run_with_timeout`] and [`run_with_timeout_blocking`] collect a command's
+//! output like `Command::output`, but terminate the process tree once the
+//! deadline passes and fail with a [`CommandTimeoutError`].
+//!
+//! PowerShell scripts are never built by formatting values into their source.
+//! [`powershell_command`] runs a fixed script and hands it its inputs through
+//! environment variables, where no quoting mistake can turn data into code.
+
+use anyhow::{Context, Result};
+use log::warn;
+use std::io::Read;
+use std::process::{Output, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+/// Deadline for short-lived helper commands (process listing, ACL changes, version checks)
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval at which [`run_with_timeout_blocking`] checks for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A command was terminated because it did not finish in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandTimeoutError {
+    pub program: String,
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for CommandTimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} did not finish within {:.1}s and was terminated",
+            self.program,
+            self.timeout.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for CommandTimeoutError {}
+
+/// Run a command to completion, killing it after `timeout`
+///
+/// Stdin is closed and stdout/stderr are captured. On timeout the process and
+/// its children are terminated and a [`CommandTimeoutError`] is returned.
+pub async fn run_with_timeout(
+    cmd: &mut tokio::process::Command,
+    timeout: Duration,
+) -> Result<Output> {
+    let program = cmd.as_std().get_program().to_string_lossy().to_string();
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program))?;
+
+    let mut stdout = child.stdout.take();
+    let mut stderr = child.stderr.take();
+
+    let finished = tokio::time::timeout(timeout, async {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let (status, _, _) = tokio::join!(
+            child.wait(),
+            async {
+                if let Some(pipe) = stdout.as_mut() {
+                    let _ = pipe.read_to_end(&mut out).await;
+                }
+            },
+            async {
+                if let Some(pipe) = stderr.as_mut() {
+                    let _ = pipe.read_to_end(&mut err).await;
+                }
+            }
+        );
+        status.map(|status| Output {
+            status,
+            stdout: out,
+            stderr: err,
+        })
+    })
+    .await;
+
+    match finished {
+        Ok(output) => output.with_context(|| format!("Failed to wait for {}", program)),
+        Err(_) => {
+            warn!("{} timed out after {:?}, terminating", program, timeout);
+            if let Some(pid) = child.id() {
+                kill_tree(pid).await;
+            }
+            let _ = child.kill().await;
+            Err(anyhow::Error::new(CommandTimeoutError { program, timeout }))
+        }
+    }
+}
+
+/// Blocking variant of [`run_with_timeout`] for synchronous call sites
+pub fn run_with_timeout_blocking(
+    cmd: &mut std::process::Command,
+    timeout: Duration,
+) -> Result<Output> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program))?;
+
+    // Drain the pipes on threads so a chatty child cannot block on a full pipe
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("Failed to wait for {}", program))?
+        {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            warn!("{} timed out after {:?}, terminating", program, timeout);
+            kill_tree_blocking(child.id());
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow::Error::new(CommandTimeoutError { program, timeout }));
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    let collect = |reader: Option<std::thread::JoinHandle<Vec<u8>>>| {
+        reader
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    };
+
+    Ok(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+/// Prefix of the environment variables carrying [`powershell_command`] inputs
+pub const POWERSHELL_INPUT_PREFIX: &str = "OPCODE_PS_";
+
+/// Build a PowerShell invocation of a fixed `script`
+///
+/// Each `(name, value)` input is passed as the environment variable
+/// `OPCODE_PS_<name>`, so the script reads it as `$env:OPCODE_PS_<name>`.
+/// The script itself is passed with `-EncodedCommand`, which keeps it out of
+/// command line parsing entirely. Input names must be ASCII letters, digits
+/// or underscores.
+pub fn powershell_command(script: &str, inputs: &[(&str, &str)]) -> std::process::Command {
+    let mut cmd = std::process::Command::new("powershell");
+    cmd.args([
+        "-NoProfile",
+        "-NonInteractive",
+        "-EncodedCommand",
+        &encode_powershell_script(script),
+    ]);
+    for (name, value) in inputs {
+        assert!(
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "invalid PowerShell input name {:?}",
+            name
+        );
+        cmd.env(format!("{}{}", POWERSHELL_INPUT_PREFIX, name), value);
+    }
+    cmd
+}
+
+/// Encode a script for `-EncodedCommand` (base64 of UTF-16LE)
+fn encode_powershell_script(script: &str) -> String {
+    use base64::Engine;
+
+    let bytes: Vec<u8> = script.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+/// Terminate a process together with the processes it started
+async fn kill_tree(pid: u32) {
+    if let Err(e) = crate::windows::process_manager::process_manager()
+        .kill_tree(pid)
+        .await
+    {
+        warn!(
+            "Failed to terminate the process tree of PID {}: {:#}",
+            pid, e
+        );
+    }
+}
+
+/// [`kill_tree`] for synchronous call sites
+fn kill_tree_blocking(pid: u32) {
+    // block_on panics on a runtime worker thread, which the caller may be on
+    let _ = std::thread::spawn(move || tauri::async_runtime::block_on(kill_tree(pid))).join();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_with_timeout_blocking() {
+        let output = run_with_timeout_blocking(
+            std::process::Command::new("sh").args(["-c", "echo out; echo err >&2"]),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        let started = Instant::now();
+        let error = run_with_timeout_blocking(
+            std::process::Command::new("sleep").arg("10"),
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let timeout = error.downcast_ref::<CommandTimeoutError>().unwrap();
+        assert_eq!(timeout.program, "sleep");
+    }
+
+    #[test]
+    fn test_powershell_command() {
+        // "Write-Output $env:OPCODE_PS_PATH" as UTF-16LE
+        let script = "Write-Output $env:OPCODE_PS_PATH";
+        assert_eq!(
+            encode_powershell_script(script),
+            "VwByAGkAdABlAC0ATwB1AHQAcAB1AHQAIAAkAGUAbgB2ADoATwBQAEMATwBEAEUAXwBQAFMAXwBQAEEAVABIAA=="
+        );
+
+        let cmd = powershell_command(script, &[("PATH", "C:\\it's $(calc)")]);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(args[2], "-EncodedCommand");
+        assert!(args.iter().all(|a| !a.contains("calc")));
+        let env: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            env,
+            vec![(
+                std::ffi::OsStr::new("OPCODE_PS_PATH"),
+                Some(std::ffi::OsStr::new("C:\\it's $(calc)"))
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid PowerShell input name")]
+    fn test_powershell_command_rejects_bad_names() {
+        powershell_command("Write-Output 1", &[("A;B", "x")]);
+    }
+
+    #[tokio::test]
+    async fn test_run_with_timeout() {
+        let output = run_with_timeout(
+            tokio::process::Command::new("sh").args(["-c", "echo out"]),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+
+        let error = run_with_timeout(
+            tokio::process::Command::new("sleep").arg("10"),
+            Duration::from_millis(200),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.downcast_ref::<CommandTimeoutError>().is_some());
+    }
+}
@@ -0,0 +1,309 @@
+//! Classification of claude and agent process exits
+//!
+//! Exit codes alone do not say why a run ended: claude exits with `1` for a
+//! bad API key, an exhausted rate limit and an internal error alike. An
+//! [`ExitClassification`] combines the exit status, the terminating signal,
+//! what opcode itself did to the process and markers seen in its output into
+//! one category that run records, events and the UI all share.
+
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+
+/// Exit code used by shells and claude for an interrupt (128 + SIGINT)
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// SIGINT, sent when the user interrupts claude in a terminal
+const SIGINT: i32 = 2;
+
+/// Output fragments (lowercase) that identify an authentication failure
+const AUTH_ERROR_MARKERS: &[&str] = &[
+    "invalid api key",
+    "invalid x-api-key",
+    "authentication_error",
+    "authentication failed",
+    "oauth token has expired",
+    "please run /login",
+    "not logged in",
+    "401 unauthorized",
+];
+
+/// Output fragments (lowercase) that identify rate limiting
+const RATE_LIMIT_MARKERS: &[&str] = &[
+    "rate_limit_error",
+    "rate limit",
+    "rate-limited",
+    "usage limit reached",
+    "429 too many requests",
+];
+
+/// Why a claude or agent process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitClassification {
+    /// Exited normally with code 0
+    Success,
+    /// Stopped at the user's request, from opcode or by an interrupt
+    UserCancelled,
+    /// Claude could not authenticate (missing, invalid or expired credentials)
+    AuthError,
+    /// The API rejected requests because of rate or usage limits
+    RateLimited,
+    /// Exited with an error or was terminated by a signal opcode did not send
+    Crashed,
+    /// Terminated by opcode itself, e.g. after producing no output in time
+    KilledByOpcode,
+    /// The exit status could not be collected, or had neither code nor signal
+    Unknown,
+}
+
+impl ExitClassification {
+    /// Stable identifier stored in run records
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExitClassification::Success => "success",
+            ExitClassification::UserCancelled => "user_cancelled",
+            ExitClassification::AuthError => "auth_error",
+            ExitClassification::RateLimited => "rate_limited",
+            ExitClassification::Crashed => "crashed",
+            ExitClassification::KilledByOpcode => "killed_by_opcode",
+            ExitClassification::Unknown => "unknown",
+        }
+    }
+
+    /// The `agent_runs.status` value for a run that ended this way
+    pub fn run_status(&self) -> &'static str {
+        match self {
+            ExitClassification::Success => "completed",
+            ExitClassification::UserCancelled => "cancelled",
+            _ => "failed",
+        }
+    }
+
+    /// Whether running the same task again may succeed without user action
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ExitClassification::RateLimited
+                | ExitClassification::Crashed
+                | ExitClassification::KilledByOpcode
+                | ExitClassification::Unknown
+        )
+    }
+}
+
+impl std::fmt::Display for ExitClassification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ExitClassification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(ExitClassification::Success),
+            "user_cancelled" => Ok(ExitClassification::UserCancelled),
+            "auth_error" => Ok(ExitClassification::AuthError),
+            "rate_limited" => Ok(ExitClassification::RateLimited),
+            "crashed" => Ok(ExitClassification::Crashed),
+            "killed_by_opcode" => Ok(ExitClassification::KilledByOpcode),
+            "unknown" => Ok(ExitClassification::Unknown),
+            other => Err(format!("Unknown exit classification: {}", other)),
+        }
+    }
+}
+
+/// Who, if anyone, asked for a process to be terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// The user cancelled the run
+    UserRequested,
+    /// Opcode terminated the run on its own (watchdogs, shutdown)
+    Opcode,
+}
+
+/// Failure markers collected from a process's output while it runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputSignals {
+    pub auth_error: bool,
+    pub rate_limited: bool,
+}
+
+impl OutputSignals {
+    /// Check one line of stdout or stderr for failure markers
+    pub fn observe(&mut self, line: &str) {
+        let line = line.to_lowercase();
+        if AUTH_ERROR_MARKERS
+            .iter()
+            .any(|marker| line.contains(marker))
+        {
+            self.auth_error = true;
+        }
+        if RATE_LIMIT_MARKERS
+            .iter()
+            .any(|marker| line.contains(marker))
+        {
+            self.rate_limited = true;
+        }
+    }
+}
+
+/// How a process ended, as reported to the UI and stored on run records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExitReport {
+    pub classification: ExitClassification,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Whether the UI should offer to run the task again
+    pub retryable: bool,
+}
+
+impl ExitReport {
+    /// Classify an exit status; `None` means the status could not be collected
+    pub fn from_status(
+        status: Option<&ExitStatus>,
+        termination: Option<Termination>,
+        signals: OutputSignals,
+    ) -> Self {
+        let exit_code = status.and_then(|s| s.code());
+        let signal = status.and_then(exit_signal);
+        let classification = classify_exit(exit_code, signal, termination, signals);
+        ExitReport {
+            classification,
+            exit_code,
+            signal,
+            retryable: classification.is_retryable(),
+        }
+    }
+}
+
+/// Classify a process exit
+///
+/// A termination requested by the user or opcode takes precedence over the
+/// status it produced. A failing exit is then attributed to authentication or
+/// rate limiting when the output said so. An exit whose status is unknown
+/// (neither code nor signal, e.g. because it wasn't collected in time) is
+/// [`ExitClassification::Unknown`] unless the output reported a failure.
+pub fn classify_exit(
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    termination: Option<Termination>,
+    signals: OutputSignals,
+) -> ExitClassification {
+    match termination {
+        Some(Termination::UserRequested) => return ExitClassification::UserCancelled,
+        Some(Termination::Opcode) => return ExitClassification::KilledByOpcode,
+        None => {}
+    }
+
+    if exit_code == Some(0) {
+        return ExitClassification::Success;
+    }
+
+    if exit_code == Some(INTERRUPTED_EXIT_CODE) || signal == Some(SIGINT) {
+        return ExitClassification::UserCancelled;
+    }
+
+    if signals.auth_error {
+        ExitClassification::AuthError
+    } else if signals.rate_limited {
+        ExitClassification::RateLimited
+    } else if exit_code.is_none() && signal.is_none() {
+        ExitClassification::Unknown
+    } else {
+        ExitClassification::Crashed
+    }
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_exit() {
+        let quiet = OutputSignals::default();
+        assert_eq!(
+            classify_exit(Some(0), None, None, quiet),
+            ExitClassification::Success
+        );
+        assert_eq!(
+            classify_exit(None, None, None, quiet),
+            ExitClassification::Unknown
+        );
+        assert_eq!(
+            ExitReport::from_status(None, None, quiet).classification,
+            ExitClassification::Unknown
+        );
+        assert_eq!(
+            classify_exit(Some(1), None, None, quiet),
+            ExitClassification::Crashed
+        );
+        assert_eq!(
+            classify_exit(None, Some(11), None, quiet),
+            ExitClassification::Crashed
+        );
+        assert_eq!(
+            classify_exit(Some(130), None, None, quiet),
+            ExitClassification::UserCancelled
+        );
+        assert_eq!(
+            classify_exit(None, Some(9), Some(Termination::UserRequested), quiet),
+            ExitClassification::UserCancelled
+        );
+        assert_eq!(
+            classify_exit(None, Some(15), Some(Termination::Opcode), quiet),
+            ExitClassification::KilledByOpcode
+        );
+
+        let mut signals = OutputSignals::default();
+        signals.observe(r#"{"type":"error","error":{"type":"rate_limit_error"}}"#);
+        assert_eq!(
+            classify_exit(Some(1), None, None, signals),
+            ExitClassification::RateLimited
+        );
+        // A successful exit is not turned into a failure by a retried rate limit
+        assert_eq!(
+            classify_exit(Some(0), None, None, signals),
+            ExitClassification::Success
+        );
+
+        signals.observe("Invalid API key · Please run /login");
+        assert_eq!(
+            classify_exit(Some(1), None, None, signals),
+            ExitClassification::AuthError
+        );
+    }
+
+    #[test]
+    fn test_classification_round_trip() {
+        for classification in [
+            ExitClassification::Success,
+            ExitClassification::UserCancelled,
+            ExitClassification::AuthError,
+            ExitClassification::RateLimited,
+            ExitClassification::Crashed,
+            ExitClassification::KilledByOpcode,
+            ExitClassification::Unknown,
+        ] {
+            assert_eq!(classification.as_str().parse(), Ok(classification));
+            assert_eq!(
+                serde_json::to_value(classification).unwrap(),
+                serde_json::json!(classification.as_str())
+            );
+        }
+        assert_eq!(ExitClassification::RateLimited.run_status(), "failed");
+        assert!(!ExitClassification::AuthError.is_retryable());
+    }
+}
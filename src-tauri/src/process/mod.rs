@@ -1,3 +1,5 @@
+pub mod command;
+pub mod exit;
 pub mod registry;
 
 pub use registry::*;
@@ -4,6 +4,8 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tokio::process::Child;
 
+use super::exit::Termination;
+
 /// Type of process being tracked
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ProcessType {
@@ -40,6 +42,7 @@ pub struct ProcessHandle {
 pub struct ProcessRegistry {
     processes: Arc<Mutex<HashMap<i64, ProcessHandle>>>, // run_id -> ProcessHandle
     next_id: Arc<Mutex<i64>>, // Auto-incrementing ID for non-agent processes
+    terminations: Arc<Mutex<HashMap<i64, Termination>>>, // run_id -> who asked for the kill
 }
 
 impl ProcessRegistry {
@@ -47,6 +50,7 @@ impl ProcessRegistry {
         Self {
             processes: Arc::new(Mutex::new(HashMap::new())),
             next_id: Arc::new(Mutex::new(1000000)), // Start at high number to avoid conflicts
+            terminations: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -281,7 +285,7 @@ impl ProcessRegistry {
         // If direct kill didn't work, try system command as fallback
         if !kill_sent {
             info!("Attempting fallback kill for process {} (PID: {})", run_id, pid);
-            match self.kill_process_by_pid(run_id, pid) {
+            match self.kill_process_by_pid(run_id, pid).await {
                 Ok(true) => return Ok(true),
                 Ok(false) => warn!("Fallback kill also failed for process {} (PID: {})", run_id, pid),
                 Err(e) => error!("Error during fallback kill: {}", e),
@@ -342,7 +346,7 @@ impl ProcessRegistry {
                     *child_guard = None;
                 }
                 // One more attempt with system kill
-                let _ = self.kill_process_by_pid(run_id, pid);
+                let _ = self.kill_process_by_pid(run_id, pid).await;
             }
         }
 
@@ -352,76 +356,79 @@ impl ProcessRegistry {
         Ok(true)
     }
 
-    /// Kill a process by PID using system commands (fallback method)
-    pub fn kill_process_by_pid(&self, run_id: i64, pid: u32) -> Result<bool, String> {
-        use log::{error, info, warn};
+    /// Record who is about to terminate a run, so its exit can be classified
+    pub fn mark_termination(&self, run_id: i64, termination: Termination) -> Result<(), String> {
+        let mut terminations = self.terminations.lock().map_err(|e| e.to_string())?;
+        terminations.insert(run_id, termination);
+        Ok(())
+    }
 
-        info!("Attempting to kill process {} by PID {}", run_id, pid);
+    /// Take the termination recorded for a run, if any
+    pub fn take_termination(&self, run_id: i64) -> Option<Termination> {
+        self.terminations
+            .lock()
+            .ok()
+            .and_then(|mut terminations| terminations.remove(&run_id))
+    }
 
-        let kill_result = if cfg!(target_os = "windows") {
-            std::process::Command::new("taskkill")
-                .args(["/F", "/PID", &pid.to_string()])
-                .output()
-        } else {
-            // First try SIGTERM
-            let term_result = std::process::Command::new("kill")
-                .args(["-TERM", &pid.to_string()])
-                .output();
-
-            match &term_result {
-                Ok(output) if output.status.success() => {
-                    info!("Sent SIGTERM to PID {}", pid);
-                    // Give it 2 seconds to exit gracefully
-                    std::thread::sleep(std::time::Duration::from_secs(2));
-
-                    // Check if still running
-                    let check_result = std::process::Command::new("kill")
-                        .args(["-0", &pid.to_string()])
-                        .output();
-
-                    if let Ok(output) = check_result {
-                        if output.status.success() {
-                            // Still running, send SIGKILL
-                            warn!(
-                                "Process {} still running after SIGTERM, sending SIGKILL",
-                                pid
-                            );
-                            std::process::Command::new("kill")
-                                .args(["-KILL", &pid.to_string()])
-                                .output()
-                        } else {
-                            term_result
-                        }
-                    } else {
-                        term_result
+    /// Wait for a registered process to exit and return its status
+    ///
+    /// Returns `None` if the process is unknown, its handle was already
+    /// consumed (e.g. by [`Self::kill_process`]) or it does not exit in time.
+    pub async fn wait_for_exit(
+        &self,
+        run_id: i64,
+        timeout: std::time::Duration,
+    ) -> Option<std::process::ExitStatus> {
+        let child_arc = {
+            let processes = self.processes.lock().ok()?;
+            processes.get(&run_id)?.child.clone()
+        };
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            {
+                let mut child_guard = child_arc.lock().ok()?;
+                match child_guard.as_mut()?.try_wait() {
+                    Ok(Some(status)) => {
+                        *child_guard = None;
+                        return Some(status);
                     }
-                }
-                _ => {
-                    // SIGTERM failed, try SIGKILL directly
-                    warn!("SIGTERM failed for PID {}, trying SIGKILL", pid);
-                    std::process::Command::new("kill")
-                        .args(["-KILL", &pid.to_string()])
-                        .output()
+                    Ok(None) => {}
+                    Err(_) => return None,
                 }
             }
-        };
 
-        match kill_result {
-            Ok(output) => {
-                if output.status.success() {
-                    info!("Successfully killed process with PID {}", pid);
-                    // Remove from registry
-                    self.unregister_process(run_id)?;
-                    Ok(true)
-                } else {
-                    let error_msg = String::from_utf8_lossy(&output.stderr);
-                    warn!("Failed to kill PID {}: {}", pid, error_msg);
-                    Ok(false)
-                }
+            if tokio::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Kill a process tree by PID through the platform process backend (fallback method)
+    pub async fn kill_process_by_pid(&self, run_id: i64, pid: u32) -> Result<bool, String> {
+        use log::{error, info, warn};
+
+        info!("Attempting to kill process {} by PID {}", run_id, pid);
+
+        match crate::windows::process_manager::process_manager()
+            .kill_tree(pid)
+            .await
+        {
+            Ok(true) => {
+                info!("Successfully killed process with PID {}", pid);
+                // Remove from registry
+                self.unregister_process(run_id)?;
+                Ok(true)
+            }
+            Ok(false) => {
+                warn!("Failed to kill PID {}: process not found", pid);
+                Ok(false)
             }
             Err(e) => {
-                error!("Failed to execute kill command for PID {}: {}", pid, e);
-                Err(format!("Failed to execute kill command: {}", e))
+                error!("Failed to kill process tree of PID {}: {}", pid, e);
+                Err(format!("Failed to kill process: {}", e))
             }
         }
     }
@@ -484,16 +491,16 @@ impl ProcessRegistry {
     }
 
     /// Cleanup finished processes
-    #[allow(dead_code)]
     pub async fn cleanup_finished_processes(&self) -> Result<Vec<i64>, String> {
         let mut finished_runs = Vec::new();
         let processes_lock = self.processes.clone();
 
         // First, identify finished processes
         {
-            let processes = processes_lock.lock().map_err(|e| e.to_string())?;
-            let run_ids: Vec<i64> = processes.keys().cloned().collect();
-            drop(processes);
+            let run_ids: Vec<i64> = {
+                let processes = processes_lock.lock().map_err(|e| e.to_string())?;
+                processes.keys().cloned().collect()
+            };
 
             for run_id in run_ids {
                 if !self.is_process_running(run_id).await? {
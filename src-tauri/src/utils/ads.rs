@@ -0,0 +1,149 @@
+//! NTFS alternate data streams
+//!
+//! A file on NTFS can carry named streams next to its contents, addressed
+//! as `file:stream`. They travel with the file when it is copied or moved
+//! between NTFS volumes, which makes them a good place for small metadata
+//! such as the agent an export came from, without a sidecar file the user
+//! has to keep alongside it. Browsers use the same mechanism for the
+//! `Zone.Identifier` mark of downloaded files.
+//!
+//! Other file systems and platforms have no streams: reads find nothing
+//! and writes fail, so callers should treat stream metadata as optional.
+
+#[cfg(target_os = "windows")]
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+#[cfg(target_os = "windows")]
+use std::path::PathBuf;
+
+/// Stream holding JSON metadata about where an exported file came from
+pub const EXPORT_METADATA_STREAM: &str = "opcode.export";
+
+/// Contents of the `stream` stream of `path`, or `None` if it has none
+pub fn read_ads<P: AsRef<Path>>(path: P, stream: &str) -> Result<Option<Vec<u8>>> {
+    let path = path.as_ref();
+    validate_stream_name(stream)?;
+
+    #[cfg(target_os = "windows")]
+    {
+        match std::fs::read(stream_path(path, stream)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read stream {} of {}", stream, path.display())),
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
+
+/// Replace the `stream` stream of the existing file `path` with `contents`
+///
+/// Fails on volumes without streams, such as FAT32 drives and shares of
+/// other systems, and on other platforms.
+pub fn write_ads<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, stream: &str, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    validate_stream_name(stream)?;
+    if !path.is_file() {
+        anyhow::bail!("Cannot add a stream to {}: not a file", path.display());
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        std::fs::write(stream_path(path, stream), contents)
+            .with_context(|| format!("Failed to write stream {} of {}", stream, path.display()))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = contents;
+        anyhow::bail!("Alternate data streams are only supported on Windows")
+    }
+}
+
+/// Remove the `stream` stream of `path`, returning whether it had one
+pub fn remove_ads<P: AsRef<Path>>(path: P, stream: &str) -> Result<bool> {
+    let path = path.as_ref();
+    validate_stream_name(stream)?;
+
+    #[cfg(target_os = "windows")]
+    {
+        match std::fs::remove_file(stream_path(path, stream)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| {
+                format!("Failed to remove stream {} of {}", stream, path.display())
+            }),
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        Ok(false)
+    }
+}
+
+/// `path:stream`, the name Windows opens the stream under
+#[cfg(target_os = "windows")]
+fn stream_path(path: &Path, stream: &str) -> PathBuf {
+    let mut stream_path = path.as_os_str().to_os_string();
+    stream_path.push(":");
+    stream_path.push(stream);
+    PathBuf::from(stream_path)
+}
+
+/// Stream names can't be empty or contain separators, and a `:` would
+/// select a stream type instead (`file:stream:$DATA`)
+fn validate_stream_name(stream: &str) -> Result<()> {
+    if stream.is_empty() || stream.contains([':', '\\', '/', '\0']) || stream.chars().count() > 255
+    {
+        anyhow::bail!("Invalid stream name '{}'", stream);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_stream_name() {
+        assert!(validate_stream_name("Zone.Identifier").is_ok());
+        assert!(validate_stream_name(EXPORT_METADATA_STREAM).is_ok());
+        assert!(validate_stream_name("").is_err());
+        assert!(validate_stream_name("a:$DATA").is_err());
+        assert!(validate_stream_name(r"..\other").is_err());
+        assert!(read_ads("missing", "a/b").is_err());
+    }
+
+    #[test]
+    fn test_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("agent.opcode.json");
+        std::fs::write(&file, "{}").unwrap();
+        assert_eq!(read_ads(&file, EXPORT_METADATA_STREAM).unwrap(), None);
+        assert!(!remove_ads(&file, EXPORT_METADATA_STREAM).unwrap());
+        assert!(write_ads(dir.path().join("missing"), EXPORT_METADATA_STREAM, "x").is_err());
+
+        #[cfg(target_os = "windows")]
+        {
+            write_ads(&file, EXPORT_METADATA_STREAM, r#"{"agent_id":1}"#).unwrap();
+            assert_eq!(
+                read_ads(&file, EXPORT_METADATA_STREAM).unwrap().as_deref(),
+                Some(br#"{"agent_id":1}"#.as_slice())
+            );
+            // The file's own contents are untouched
+            assert_eq!(std::fs::read_to_string(&file).unwrap(), "{}");
+            assert!(remove_ads(&file, EXPORT_METADATA_STREAM).unwrap());
+            assert_eq!(read_ads(&file, EXPORT_METADATA_STREAM).unwrap(), None);
+        }
+        #[cfg(not(target_os = "windows"))]
+        assert!(write_ads(&file, EXPORT_METADATA_STREAM, "x").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Replacing files without leaving them half-written
+//!
+//! [`atomic_write`] writes to a temporary file next to the destination and
+//! swaps it into place, so a crash or power loss leaves either the old or
+//! the new contents, never a truncated settings file. On Windows the swap
+//! uses `ReplaceFileW`, which keeps the destination's ACL and attributes,
+//! and is retried briefly while antivirus or indexing services hold the
+//! file open.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+
+use super::lock_retry::{describe_lock, retry_while, RetryPolicy};
+
+/// Write `contents` to `path`, replacing any existing file atomically
+///
+/// The parent directory must exist. Other processes reading `path` see
+/// either the old or the new contents. When `path` is a symlink, such as a
+/// settings file managed by a dotfiles repository, the file it points to is
+/// replaced and the link is kept. An existing file keeps its permissions.
+pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = resolve_symlink(path.as_ref())?;
+    let path = path.as_path();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .with_context(|| format!("Not a file path: {}", path.display()))?;
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(&format!(".{}.", name.to_string_lossy()))
+        .suffix(".tmp")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+    temp.write_all(contents.as_ref())
+        .and_then(|_| temp.as_file().sync_all())
+        .with_context(|| format!("Failed to write {}", temp.path().display()))?;
+
+    // The temporary file is created owner-only; ReplaceFileW keeps the
+    // destination's ACL on Windows, elsewhere copy the mode over
+    #[cfg(not(target_os = "windows"))]
+    if let Ok(metadata) = std::fs::metadata(path) {
+        std::fs::set_permissions(temp.path(), metadata.permissions())
+            .with_context(|| format!("Failed to copy the permissions of {}", path.display()))?;
+    }
+
+    // Dropping the temp path on any error below deletes it
+    let temp = temp.into_temp_path();
+    retry_while(
+        || replace_file(&temp, path),
+        is_sharing_violation,
+        &RetryPolicy::default(),
+    )
+    .map_err(|e| describe_lock(e, path))
+    .with_context(|| format!("Failed to replace {}", path.display()))?;
+    temp.keep().context("Failed to keep the replacement file")?;
+
+    #[cfg(unix)]
+    {
+        // Make the rename itself durable
+        if let Ok(dir) = std::fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// The file `path` points to when it is a symlink, otherwise `path`
+///
+/// Renaming over a symlink would replace the link with a regular file.
+fn resolve_symlink(path: &Path) -> Result<std::path::PathBuf> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve the symlink {}", path.display())),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// Whether another process briefly holding the file open caused `error`
+fn is_sharing_violation(error: &std::io::Error) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows_sys::Win32::Foundation::{
+            ERROR_ACCESS_DENIED, ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION,
+            ERROR_UNABLE_TO_REMOVE_REPLACED,
+        };
+
+        // A file that is being deleted or scanned can also report access
+        // denied; a real permission problem fails every attempt anyway
+        matches!(
+            error.raw_os_error().map(|code| code as u32),
+            Some(
+                ERROR_SHARING_VIOLATION
+                    | ERROR_LOCK_VIOLATION
+                    | ERROR_ACCESS_DENIED
+                    | ERROR_UNABLE_TO_REMOVE_REPLACED
+            )
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Move `replacement` over `destination`
+fn replace_file(replacement: &Path, destination: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::{
+            ReplaceFileW, REPLACEFILE_IGNORE_MERGE_ERRORS,
+        };
+
+        // ReplaceFileW needs an existing destination
+        if !destination.exists() {
+            return std::fs::rename(replacement, destination);
+        }
+        let wide =
+            |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+        let (destination, replacement) = (wide(destination), wide(replacement));
+        let ok = unsafe {
+            ReplaceFileW(
+                destination.as_ptr(),
+                replacement.as_ptr(),
+                std::ptr::null(),
+                REPLACEFILE_IGNORE_MERGE_ERRORS,
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        std::fs::rename(replacement, destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+
+        atomic_write(&path, "{}").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        atomic_write(&path, r#"{"theme":"dark"}"#).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"theme":"dark"}"#
+        );
+
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert!(atomic_write(dir.path().join("missing").join("a.json"), "{}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_keeps_symlink_and_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("dotfiles-settings.json");
+        let link = dir.path().join("settings.json");
+        std::fs::write(&target, "{}").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        atomic_write(&link, r#"{"theme":"dark"}"#).unwrap();
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&target).unwrap(),
+            r#"{"theme":"dark"}"#
+        );
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+}
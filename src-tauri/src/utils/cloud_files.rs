@@ -0,0 +1,133 @@
+//! Online-only files of OneDrive and other cloud sync providers
+//!
+//! With Files On-Demand, a synced folder holds placeholders for files that
+//! are only stored online. They list like normal files, but the first read
+//! makes the provider download them, which for a whole project can mean
+//! gigabytes of transfers, and fails when offline. Code that reads every
+//! file of a project skips placeholders found by [`is_cloud_placeholder`];
+//! [`hydrate_file`] downloads one on purpose.
+
+use anyhow::Result;
+use std::path::Path;
+
+/// Whether `path` is an online-only placeholder whose contents aren't on
+/// this PC
+///
+/// Always `false` on other platforms and for paths that can't be read.
+pub fn is_cloud_placeholder<P: AsRef<Path>>(path: P) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::MetadataExt;
+        use windows_sys::Win32::Storage::FileSystem::{
+            FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS,
+            FILE_ATTRIBUTE_RECALL_ON_OPEN,
+        };
+        const NOT_LOCAL: u32 = FILE_ATTRIBUTE_OFFLINE
+            | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
+            | FILE_ATTRIBUTE_RECALL_ON_OPEN;
+
+        // Reading the attributes doesn't trigger a download
+        std::fs::metadata(path)
+            .map(|metadata| metadata.file_attributes() & NOT_LOCAL != 0)
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// `path` or the closest of its parents that is a placeholder
+pub fn find_cloud_placeholder(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .find(|ancestor| is_cloud_placeholder(ancestor))
+}
+
+/// Download the contents of the placeholder `path` so it can be read
+/// without waiting on the network
+///
+/// Files that are already local are left alone. Fails with a message
+/// saying why when the provider can't download it, e.g. while offline.
+pub fn hydrate_file<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    if !is_cloud_placeholder(path) {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use anyhow::Context;
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Foundation::{
+            ERROR_CLOUD_FILE_NETWORK_UNAVAILABLE, ERROR_CLOUD_FILE_PROVIDER_NOT_RUNNING,
+        };
+        use windows_sys::Win32::Storage::CloudFilters::{
+            CfHydratePlaceholder, CF_HYDRATE_FLAG_NONE,
+        };
+
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        // A length of -1 means up to the end of the file
+        let hr = unsafe {
+            CfHydratePlaceholder(
+                file.as_raw_handle() as _,
+                0,
+                -1,
+                CF_HYDRATE_FLAG_NONE,
+                std::ptr::null_mut(),
+            )
+        };
+        if hr < 0 {
+            match win32_code(hr) {
+                Some(ERROR_CLOUD_FILE_NETWORK_UNAVAILABLE) => anyhow::bail!(
+                    "{} is stored online only and can't be downloaded while offline",
+                    path.display()
+                ),
+                Some(ERROR_CLOUD_FILE_PROVIDER_NOT_RUNNING) => anyhow::bail!(
+                    "{} is stored online only, and its sync app (such as OneDrive) isn't running",
+                    path.display()
+                ),
+                _ => anyhow::bail!(
+                    "Failed to download {} (HRESULT {:#010x})",
+                    path.display(),
+                    hr
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The Win32 error wrapped in an `HRESULT_FROM_WIN32` value
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn win32_code(hr: i32) -> Option<u32> {
+    let hr = hr as u32;
+    (hr & 0xFFFF_0000 == 0x8007_0000).then_some(hr & 0xFFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win32_code() {
+        // HRESULT_FROM_WIN32(ERROR_CLOUD_FILE_NETWORK_UNAVAILABLE)
+        assert_eq!(win32_code(0x8007_0184_u32 as i32), Some(388));
+        assert_eq!(win32_code(0x8000_4005_u32 as i32), None);
+    }
+
+    #[test]
+    fn test_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        std::fs::write(&file, "fn main() {}").unwrap();
+
+        assert!(!is_cloud_placeholder(&file));
+        assert!(!is_cloud_placeholder(dir.path().join("missing")));
+        assert_eq!(find_cloud_placeholder(&file), None);
+        hydrate_file(&file).unwrap();
+    }
+}
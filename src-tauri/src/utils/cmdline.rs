@@ -0,0 +1,130 @@
+//! Quoting for command lines and shell scripts
+//!
+//! Every place that turns untrusted strings (paths, user arguments) into a
+//! command line or script must go through these helpers instead of ad-hoc
+//! `format!` escaping.
+
+/// Quote an argument for a Windows command line, if needed
+///
+/// Follows the `CommandLineToArgvW` / MSVC CRT rules: backslashes are only
+/// special before a double quote, so they are doubled there and at the end of
+/// a quoted argument.
+pub fn quote_windows_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty() || arg.contains([' ', '\t', '\n', '\u{b}', '"']);
+    if needs_quotes {
+        quote_windows_arg_always(arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Quote an argument for a Windows command line, even if it has no spaces
+///
+/// Use for executable paths and `%1` placeholders in registry commands, whose
+/// substituted value may contain spaces.
+pub fn quote_windows_arg_always(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+
+        // Backslashes before a quote are doubled and the quote is escaped
+        let escaped = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+        quoted.extend(std::iter::repeat_n('\\', escaped));
+        quoted.push(c);
+        backslashes = 0;
+    }
+
+    // Trailing backslashes would otherwise escape the closing quote
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Join arguments into a Windows command line
+pub fn join_windows_args<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| quote_windows_arg(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote a string as a PowerShell single-quoted literal
+///
+/// Nothing is expanded inside single quotes; the only escape is doubling the
+/// quote character, which includes the typographic quotes PowerShell also
+/// accepts as single quotes.
+pub fn quote_powershell(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Quote an argument for a POSIX shell, if needed
+pub fn quote_posix(arg: &str) -> String {
+    let is_safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if is_safe {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Join arguments into a POSIX shell command
+pub fn join_posix_args<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| quote_posix(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_windows_arg() {
+        assert_eq!(quote_windows_arg("plain"), "plain");
+        assert_eq!(quote_windows_arg(""), r#""""#);
+        assert_eq!(quote_windows_arg(r"C:\Program Files\app.exe"), r#""C:\Program Files\app.exe""#);
+        assert_eq!(quote_windows_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_windows_arg(r"C:\dir with space\"), r#""C:\dir with space\\""#);
+        assert_eq!(quote_windows_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_windows_arg_always("%1"), r#""%1""#);
+        assert_eq!(
+            join_windows_args(&["--install", r"C:\My Apps\"]),
+            r#"--install "C:\My Apps\\""#
+        );
+    }
+
+    #[test]
+    fn test_quote_powershell() {
+        assert_eq!(quote_powershell(r"C:\Apps\app.exe"), r"'C:\Apps\app.exe'");
+        assert_eq!(quote_powershell("it's $env:PATH"), "'it''s $env:PATH'");
+        assert_eq!(quote_powershell("a\u{2019}b"), "'a\u{2019}\u{2019}b'");
+    }
+
+    #[test]
+    fn test_quote_posix() {
+        assert_eq!(quote_posix("--flag=value"), "--flag=value");
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_posix("two words"), "'two words'");
+        assert_eq!(quote_posix("it's $(rm -rf /)"), r"'it'\''s $(rm -rf /)'");
+        assert_eq!(join_posix_args(&["echo", "a b"]), "echo 'a b'");
+    }
+}
@@ -0,0 +1,70 @@
+//! Deleting files and directories, to the Recycle Bin by default
+//!
+//! Sessions and checkpoints are easy to delete by mistake, so
+//! [`delete_path`] with [`DeleteMode::Trash`] moves them somewhere the user
+//! can restore them from with [`trash::delete`](super::trash::delete).
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::lock_retry::{with_lock_retry, RetryPolicy};
+
+/// How [`delete_path`] gets rid of a path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    /// Move to the Recycle Bin or trash so it can be restored
+    #[default]
+    Trash,
+    Permanent,
+}
+
+/// Delete the file or directory at `path`
+///
+/// A path that doesn't exist is already deleted. On Windows, paths the
+/// Recycle Bin doesn't accept, such as those on network shares, are
+/// deleted permanently even in [`DeleteMode::Trash`], as Explorer does.
+pub fn delete_path<P: AsRef<Path>>(path: P, mode: DeleteMode) -> Result<()> {
+    let path = path.as_ref();
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    match mode {
+        DeleteMode::Permanent => with_lock_retry(
+            || {
+                if metadata.is_dir() {
+                    std::fs::remove_dir_all(path)
+                } else {
+                    std::fs::remove_file(path)
+                }
+            },
+            &RetryPolicy::for_path(path),
+        )
+        .with_context(|| format!("Failed to delete {}", path.display())),
+        DeleteMode::Trash => super::trash::delete(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_permanent() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = dir.path().join("session.jsonl");
+        let timeline = dir.path().join(".timelines").join("session");
+        std::fs::write(&session, "{}").unwrap();
+        std::fs::create_dir_all(timeline.join("checkpoints")).unwrap();
+
+        delete_path(&session, DeleteMode::Permanent).unwrap();
+        delete_path(&timeline, DeleteMode::Permanent).unwrap();
+        assert!(!session.exists());
+        assert!(!timeline.exists());
+        delete_path(&session, DeleteMode::Trash).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Measuring how much space a directory tree takes
+//!
+//! Project folders with years of sessions and checkpoint content can hold
+//! hundreds of thousands of files, so [`compute_dir_size`] walks them on a
+//! blocking task, reports running totals while it goes and stops early when
+//! the caller's cancel flag is set.
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// How often [`compute_dir_size`] reports progress
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Size of a directory tree, or the part of it walked so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DirSize {
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+}
+
+/// Add up the files below `path` on a blocking task
+///
+/// `on_progress` gets the running totals every [`PROGRESS_INTERVAL`].
+/// Returns `None` if `cancel` was set before the walk finished.
+pub async fn compute_dir_size(
+    path: PathBuf,
+    cancel: Arc<AtomicBool>,
+    on_progress: impl FnMut(&DirSize) + Send + 'static,
+) -> Result<Option<DirSize>> {
+    tokio::task::spawn_blocking(move || {
+        dir_size_blocking(&path, &cancel, PROGRESS_INTERVAL, on_progress)
+    })
+    .await
+    .context("Directory size task failed")?
+}
+
+/// [`compute_dir_size`] on the current thread
+///
+/// Symlinks and junctions are counted as entries but not followed, and
+/// entries that can't be read are skipped rather than failing the walk.
+pub fn dir_size_blocking(
+    path: &Path,
+    cancel: &AtomicBool,
+    interval: Duration,
+    mut on_progress: impl FnMut(&DirSize),
+) -> Result<Option<DirSize>> {
+    std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut size = DirSize::default();
+    let mut last_report = Instant::now();
+    for entry in WalkDir::new(path).follow_links(false) {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Skipping while sizing {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if entry.depth() == 0 && entry.file_type().is_dir() {
+            continue;
+        }
+
+        if entry.file_type().is_dir() {
+            size.dirs += 1;
+        } else {
+            size.files += 1;
+            if entry.file_type().is_file() {
+                size.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+
+        if last_report.elapsed() >= interval {
+            on_progress(&size);
+            last_report = Instant::now();
+        }
+    }
+    Ok(Some(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_size_blocking() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a").join("b")).unwrap();
+        std::fs::write(dir.path().join("one.txt"), "12345").unwrap();
+        std::fs::write(dir.path().join("a").join("b").join("two.txt"), "123").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut reports = 0;
+        let size = dir_size_blocking(dir.path(), &cancel, Duration::ZERO, |_| reports += 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            size,
+            DirSize {
+                bytes: 8,
+                files: 2,
+                dirs: 2
+            }
+        );
+        assert_eq!(reports, 4);
+
+        let file = dir_size_blocking(
+            &dir.path().join("one.txt"),
+            &cancel,
+            PROGRESS_INTERVAL,
+            |_| {},
+        );
+        assert_eq!(file.unwrap().unwrap().bytes, 5);
+        assert!(dir_size_blocking(
+            &dir.path().join("missing"),
+            &cancel,
+            PROGRESS_INTERVAL,
+            |_| {}
+        )
+        .is_err());
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(
+            dir_size_blocking(dir.path(), &cancel, PROGRESS_INTERVAL, |_| {}).unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compute_dir_size() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("session.jsonl"), "{}").unwrap();
+        let size = compute_dir_size(dir.path().to_path_buf(), Arc::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(size.map(|s| s.bytes), Some(2));
+    }
+}
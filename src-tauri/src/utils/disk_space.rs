@@ -0,0 +1,146 @@
+//! Checking for free disk space before large writes
+//!
+//! Running out of space halfway through a checkpoint or export leaves a
+//! partial write and an IO error that doesn't say what went wrong. Callers
+//! that know roughly how much they are about to write check with
+//! [`ensure_free_space`] first.
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path};
+
+/// Space [`ensure_free_space`] keeps free on top of what was asked for, so
+/// a write that only just fits doesn't fill the disk for everything else
+pub const WRITE_HEADROOM: u64 = 16 * 1024 * 1024;
+
+/// Bytes available to the current user on the volume holding `path`
+///
+/// `path` doesn't need to exist yet; its nearest existing ancestor is
+/// checked. On Windows this honors per-user disk quotas.
+pub fn get_free_space<P: AsRef<Path>>(path: P) -> Result<u64> {
+    let path = path.as_ref();
+    let existing = path
+        .ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .unwrap_or(Path::new("."));
+    free_space(existing).with_context(|| format!("Failed to get free space for {}", path.display()))
+}
+
+/// Fail with a message naming the volume unless `required` bytes, plus
+/// [`WRITE_HEADROOM`], are free where `path` will be written
+pub fn ensure_free_space<P: AsRef<Path>>(path: P, required: u64) -> Result<()> {
+    let path = path.as_ref();
+    let available = get_free_space(path)?;
+    let needed = required.saturating_add(WRITE_HEADROOM);
+    if available < needed {
+        anyhow::bail!(
+            "Need {} free on {}, but only {} is available",
+            format_megabytes(needed),
+            volume_name(path),
+            format_megabytes(available)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn free_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = super::paths::to_extended_length_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut available: u64 = 0;
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn free_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // f_bavail excludes blocks reserved for root; the field types differ
+    // between platforms
+    #[allow(clippy::unnecessary_cast)]
+    let available = stats.f_bavail as u64 * stats.f_frsize as u64;
+    Ok(available)
+}
+
+/// How to name the volume holding `path` in messages: `D:` or
+/// `\\server\share` on Windows, the path itself elsewhere
+fn volume_name(path: &Path) -> String {
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => {
+            let prefix = prefix.as_os_str().to_string_lossy();
+            prefix
+                .strip_prefix(r"\\?\UNC\")
+                .map(|share| format!(r"\\{}", share))
+                .or_else(|| prefix.strip_prefix(r"\\?\").map(str::to_string))
+                .unwrap_or_else(|| prefix.to_string())
+        }
+        _ => path.display().to_string(),
+    }
+}
+
+/// `bytes` in whole megabytes, rounded up so "need 1 MB" is never 0 MB
+fn format_megabytes(bytes: u64) -> String {
+    format!("{} MB", bytes.div_ceil(1024 * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_megabytes() {
+        assert_eq!(format_megabytes(0), "0 MB");
+        assert_eq!(format_megabytes(1), "1 MB");
+        assert_eq!(format_megabytes(5 * 1024 * 1024), "5 MB");
+        assert_eq!(format_megabytes(5 * 1024 * 1024 + 1), "6 MB");
+    }
+
+    #[test]
+    fn test_volume_name() {
+        #[cfg(target_os = "windows")]
+        {
+            assert_eq!(volume_name(Path::new(r"D:\exports\a.json")), "D:");
+            assert_eq!(volume_name(Path::new(r"\\?\D:\exports")), "D:");
+            assert_eq!(
+                volume_name(Path::new(r"\\nas\projects\app")),
+                r"\\nas\projects"
+            );
+        }
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(volume_name(Path::new("/home/dev")), "/home/dev");
+    }
+
+    #[test]
+    fn test_free_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let free = get_free_space(dir.path()).unwrap();
+        assert!(free > 0);
+        assert!(get_free_space(dir.path().join("not").join("yet")).is_ok());
+
+        ensure_free_space(dir.path(), 0).unwrap();
+        let err = ensure_free_space(dir.path(), u64::MAX / 2).unwrap_err();
+        assert!(err.to_string().starts_with("Need "));
+    }
+}
@@ -0,0 +1,128 @@
+//! Who may access a file, in the same terms on every platform
+//!
+//! [`FilePermissions`] names the few access levels opcode needs. On Unix
+//! they become mode bits; `chmod` also sets the mask of any POSIX ACL, so
+//! extra named entries lose their effect too. On Windows they become the
+//! protected DACL of
+//! [`set_owner_only_dacl`](crate::windows::secure_temp::set_owner_only_dacl),
+//! which stops the file from inheriting the (often wider) ACL of its parent
+//! folder.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// An access level for a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilePermissions {
+    /// Only the current user can read or change it (`0600`/`0700`; on
+    /// Windows SYSTEM and Administrators keep access too)
+    OwnerOnly,
+    /// The current user can change it, everyone else can only read it
+    /// (`0644`/`0755`)
+    ReadOnlyForOthers,
+}
+
+impl FilePermissions {
+    /// Unix mode for a directory or a file, keeping the owner's execute bit
+    #[cfg(unix)]
+    fn mode(self, is_dir: bool, current_mode: u32) -> u32 {
+        let executable = is_dir || current_mode & 0o100 != 0;
+        match (self, executable) {
+            (FilePermissions::OwnerOnly, false) => 0o600,
+            (FilePermissions::OwnerOnly, true) => 0o700,
+            (FilePermissions::ReadOnlyForOthers, false) => 0o644,
+            (FilePermissions::ReadOnlyForOthers, true) => 0o755,
+        }
+    }
+}
+
+/// Give `path` the access level `permissions`
+///
+/// Replaces whatever access the file or directory had. For a directory
+/// this covers what is created inside it later; files already inside it
+/// keep their own permissions on Unix.
+pub fn set_file_permissions(path: &Path, permissions: FilePermissions) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read the permissions of {}", path.display()))?;
+        let mode = permissions.mode(metadata.is_dir(), metadata.permissions().mode());
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set the permissions of {}", path.display()))?;
+    }
+
+    #[cfg(target_os = "windows")]
+    crate::windows::secure_temp::set_owner_only_dacl(
+        path,
+        permissions == FilePermissions::ReadOnlyForOthers,
+    )?;
+
+    log::debug!("Set {} to {:?}", path.display(), permissions);
+    Ok(())
+}
+
+/// Create `path` and its missing parents, giving the directories created
+/// here the access level `permissions`
+///
+/// Directories that already existed are left alone, so calling this again
+/// is cheap and doesn't undo changes the user made.
+pub fn create_dir_all_with_permissions(path: &Path, permissions: FilePermissions) -> Result<()> {
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+
+    // Outermost first; on Windows the children then inherit its ACL anyway
+    for dir in missing.into_iter().rev() {
+        set_file_permissions(dir, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_set_file_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("secret.json");
+        let script = dir.path().join("run.sh");
+        std::fs::write(&file, "{}").unwrap();
+        std::fs::write(&script, "").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o775)).unwrap();
+
+        set_file_permissions(&file, FilePermissions::OwnerOnly).unwrap();
+        assert_eq!(mode(&file), 0o600);
+        set_file_permissions(&script, FilePermissions::OwnerOnly).unwrap();
+        assert_eq!(mode(&script), 0o700);
+        set_file_permissions(&file, FilePermissions::ReadOnlyForOthers).unwrap();
+        assert_eq!(mode(&file), 0o644);
+        set_file_permissions(dir.path(), FilePermissions::ReadOnlyForOthers).unwrap();
+        assert_eq!(mode(dir.path()), 0o755);
+    }
+
+    #[test]
+    fn test_create_dir_all_with_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let nested = dir.path().join("a").join("b");
+
+        create_dir_all_with_permissions(&nested, FilePermissions::OwnerOnly).unwrap();
+        assert_eq!(mode(&dir.path().join("a")), 0o700);
+        assert_eq!(mode(&nested), 0o700);
+        // Existing directories are untouched
+        assert_eq!(mode(dir.path()), 0o755);
+    }
+}
@@ -0,0 +1,230 @@
+//! Hashing files to check they arrived intact
+//!
+//! Files are read in fixed-size chunks, so hashing a large checkpoint blob
+//! or installer doesn't load it into memory. The async functions read with
+//! `tokio::fs` and are meant for command handlers; the `_blocking` variants
+//! are for code already running on a blocking thread.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::io::Read;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// Bytes read per chunk
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Hash functions [`hash_file`] supports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+/// A running hash of either algorithm
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The digest as lowercase hex
+    fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Hash of the contents of `path` as lowercase hex
+pub async fn hash_file<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> Result<String> {
+    let path = path.as_ref();
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// [`hash_file`] on the current thread
+pub fn hash_file_blocking<P: AsRef<Path>>(path: P, algorithm: HashAlgorithm) -> Result<String> {
+    let path = path.as_ref();
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash of in-memory data, matching what [`hash_file`] returns for a file
+/// with these contents
+pub fn hash_bytes(data: &[u8], algorithm: HashAlgorithm) -> String {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Fail unless `path` hashes to `expected`, e.g. a downloaded update
+/// against its published checksum
+///
+/// `expected` is hex and compared case-insensitively.
+pub async fn verify_file_hash<P: AsRef<Path>>(
+    path: P,
+    algorithm: HashAlgorithm,
+    expected: &str,
+) -> Result<()> {
+    let path = path.as_ref();
+    let actual = hash_file(path, algorithm).await?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        anyhow::bail!(
+            "{} is corrupted or incomplete: expected {:?} hash {}, got {}",
+            path.display(),
+            algorithm,
+            expected.trim(),
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Fail unless the file `copy` has the same contents as `original`
+pub async fn verify_copy<P: AsRef<Path>, Q: AsRef<Path>>(original: P, copy: Q) -> Result<()> {
+    let (original, copy) = (original.as_ref(), copy.as_ref());
+    let (original_len, copy_len) = (
+        tokio::fs::metadata(original)
+            .await
+            .with_context(|| format!("Failed to read {}", original.display()))?
+            .len(),
+        tokio::fs::metadata(copy)
+            .await
+            .with_context(|| format!("Failed to read {}", copy.display()))?
+            .len(),
+    );
+    if original_len != copy_len
+        || hash_file(original, HashAlgorithm::Sha256).await?
+            != hash_file(copy, HashAlgorithm::Sha256).await?
+    {
+        anyhow::bail!(
+            "The copy {} does not match {}",
+            copy.display(),
+            original.display()
+        );
+    }
+    Ok(())
+}
+
+/// [`verify_copy`] on the current thread
+pub fn verify_copy_blocking<P: AsRef<Path>, Q: AsRef<Path>>(original: P, copy: Q) -> Result<()> {
+    let (original, copy) = (original.as_ref(), copy.as_ref());
+    let len = |path: &Path| -> Result<u64> {
+        Ok(std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len())
+    };
+    if len(original)? != len(copy)?
+        || hash_file_blocking(original, HashAlgorithm::Sha256)?
+            != hash_file_blocking(copy, HashAlgorithm::Sha256)?
+    {
+        anyhow::bail!(
+            "The copy {} does not match {}",
+            copy.display(),
+            original.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_hash_bytes() {
+        assert_eq!(hash_bytes(b"abc", HashAlgorithm::Sha256), ABC_SHA256);
+        assert!(hash_bytes(b"abc", HashAlgorithm::Sha512).starts_with("ddaf35a193617aba"));
+    }
+
+    #[tokio::test]
+    async fn test_hash_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("abc.txt");
+        std::fs::write(&file, "abc").unwrap();
+
+        assert_eq!(
+            hash_file(&file, HashAlgorithm::Sha256).await.unwrap(),
+            ABC_SHA256
+        );
+        assert_eq!(
+            hash_file_blocking(&file, HashAlgorithm::Sha256).unwrap(),
+            ABC_SHA256
+        );
+        assert!(hash_file(dir.path().join("missing"), HashAlgorithm::Sha256)
+            .await
+            .is_err());
+
+        verify_file_hash(&file, HashAlgorithm::Sha256, &ABC_SHA256.to_uppercase())
+            .await
+            .unwrap();
+        assert!(verify_file_hash(&file, HashAlgorithm::Sha512, ABC_SHA256)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (original, copy) = (dir.path().join("a"), dir.path().join("b"));
+        // Larger than one chunk
+        let data = vec![7u8; CHUNK_SIZE + 10];
+        std::fs::write(&original, &data).unwrap();
+        std::fs::write(&copy, &data).unwrap();
+        verify_copy(&original, &copy).await.unwrap();
+        verify_copy_blocking(&original, &copy).unwrap();
+
+        let mut changed = data.clone();
+        changed[CHUNK_SIZE + 5] = 8;
+        std::fs::write(&copy, &changed).unwrap();
+        assert!(verify_copy(&original, &copy).await.is_err());
+        assert!(verify_copy_blocking(&original, &copy).is_err());
+    }
+}
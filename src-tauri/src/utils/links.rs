@@ -0,0 +1,268 @@
+//! Creating symlinks, with fallbacks where Windows doesn't allow them
+//!
+//! Creating a symlink on Windows needs `SeCreateSymbolicLinkPrivilege`,
+//! which standard users only have with Developer Mode turned on. Directory
+//! junctions and hard links need no privilege, so [`create_link`] falls back
+//! to them: a junction for a directory, a hard link for a file on the same
+//! volume. On other platforms it always creates a symlink.
+
+use anyhow::{Context, Result};
+#[cfg(target_os = "windows")]
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What the link points at; Windows needs to know when creating it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkType {
+    File,
+    Directory,
+}
+
+/// The kind of link [`create_link`] created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CreatedLink {
+    Symlink,
+    /// Windows directory junction, always pointing at an absolute path
+    Junction,
+    /// A second name for the same file; later replacing the target file
+    /// (rather than writing to it) doesn't update the link
+    HardLink,
+}
+
+/// Create `link` pointing at `target`
+///
+/// Tries a symlink first, keeping a relative `target` relative. If Windows
+/// refuses because the privilege is missing, a directory gets a junction
+/// and a file a hard link; both need `target` to exist, and a hard link
+/// needs it on the same volume as `link`.
+pub fn create_link<P: AsRef<Path>, Q: AsRef<Path>>(
+    target: P,
+    link: Q,
+    link_type: LinkType,
+) -> Result<CreatedLink> {
+    let (target, link) = (target.as_ref(), link.as_ref());
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = link_type;
+        std::os::unix::fs::symlink(target, link).with_context(|| {
+            format!("Failed to link {} to {}", link.display(), target.display())
+        })?;
+        Ok(CreatedLink::Symlink)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows_sys::Win32::Foundation::ERROR_PRIVILEGE_NOT_HELD;
+
+        let result = match link_type {
+            LinkType::File => std::os::windows::fs::symlink_file(target, link),
+            LinkType::Directory => std::os::windows::fs::symlink_dir(target, link),
+        };
+        match result {
+            Ok(()) => return Ok(CreatedLink::Symlink),
+            Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD as i32) => {
+                debug!(
+                    "Symlink {} not allowed, falling back: {}",
+                    link.display(),
+                    e
+                );
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to link {} to {}", link.display(), target.display())
+                })
+            }
+        }
+
+        let absolute = absolute_target(target, link)?;
+        match link_type {
+            LinkType::Directory => {
+                create_junction(&absolute, link)?;
+                Ok(CreatedLink::Junction)
+            }
+            LinkType::File => {
+                std::fs::hard_link(&absolute, link).with_context(|| {
+                    format!(
+                        "Symlinks need Developer Mode or administrator rights, and a hard link from {} to {} failed",
+                        link.display(),
+                        absolute.display()
+                    )
+                })?;
+                Ok(CreatedLink::HardLink)
+            }
+        }
+    }
+}
+
+/// `target` as an absolute path; relative targets are relative to the
+/// directory holding `link`, as they would be for a symlink
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn absolute_target(target: &Path, link: &Path) -> Result<PathBuf> {
+    let joined = match link.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target.to_path_buf(),
+    };
+    std::path::absolute(&joined).with_context(|| format!("Failed to resolve {}", joined.display()))
+}
+
+/// `REPARSE_DATA_BUFFER` for a mount point (junction) to `target`
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn mount_point_reparse_data(target: &str) -> Vec<u8> {
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+    let utf16 = |s: &str| -> Vec<u8> {
+        s.encode_utf16()
+            .chain(Some(0))
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    };
+    // The NT path the junction resolves to, and the path tools display
+    let substitute = utf16(&format!(r"\??\{}", target));
+    let print = utf16(target);
+    let substitute_len = (substitute.len() - 2) as u16;
+    let print_len = (print.len() - 2) as u16;
+
+    // Mount point header (4 u16 fields) followed by both strings
+    let data_len = 8 + substitute.len() + print.len();
+    let mut buffer = Vec::with_capacity(8 + data_len);
+    buffer.extend_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    buffer.extend_from_slice(&(data_len as u16).to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&substitute_len.to_le_bytes());
+    buffer.extend_from_slice(&(substitute.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(&print_len.to_le_bytes());
+    buffer.extend_from_slice(&substitute);
+    buffer.extend_from_slice(&print);
+    buffer
+}
+
+/// Create a directory junction at `link` pointing at the absolute `target`
+#[cfg(target_os = "windows")]
+fn create_junction(target: &Path, link: &Path) -> Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    };
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_REPARSE_POINT;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    if !target.is_dir() {
+        anyhow::bail!(
+            "Cannot create a junction to {}: not an existing directory",
+            target.display()
+        );
+    }
+    let target = super::paths::canonicalize_path(target)?;
+    let data = mount_point_reparse_data(&target.to_string_lossy());
+
+    std::fs::create_dir(link).with_context(|| format!("Failed to create {}", link.display()))?;
+    let result = (|| -> Result<()> {
+        let dir = std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS)
+            .open(link)?;
+        let mut returned: u32 = 0;
+        if unsafe {
+            DeviceIoControl(
+                dir.as_raw_handle() as _,
+                FSCTL_SET_REPARSE_POINT,
+                data.as_ptr() as _,
+                data.len() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        } == 0
+        {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = std::fs::remove_dir(link);
+        return Err(e).with_context(|| {
+            format!(
+                "Failed to create a junction from {} to {}",
+                link.display(),
+                target.display()
+            )
+        });
+    }
+    debug!(
+        "Created junction {} -> {}",
+        link.display(),
+        target.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_point_reparse_data() {
+        let data = mount_point_reparse_data(r"C:\x");
+        // \??\C:\x is 8 UTF-16 units, C:\x is 4, each with a terminator
+        let substitute_bytes = 8 * 2;
+        let print_bytes = 4 * 2;
+        assert_eq!(&data[..4], &0xA000_0003u32.to_le_bytes());
+        assert_eq!(
+            u16::from_le_bytes([data[4], data[5]]) as usize,
+            8 + substitute_bytes + 2 + print_bytes + 2
+        );
+        assert_eq!(
+            u16::from_le_bytes([data[10], data[11]]) as usize,
+            substitute_bytes
+        );
+        assert_eq!(
+            u16::from_le_bytes([data[12], data[13]]) as usize,
+            substitute_bytes + 2
+        );
+        assert_eq!(
+            u16::from_le_bytes([data[14], data[15]]) as usize,
+            print_bytes
+        );
+        assert_eq!(data.len(), 16 + substitute_bytes + 2 + print_bytes + 2);
+    }
+
+    #[test]
+    fn test_absolute_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("links").join("shared");
+        assert_eq!(
+            absolute_target(Path::new("../data"), &link).unwrap(),
+            dir.path().join("links").join("../data")
+        );
+    }
+
+    #[test]
+    fn test_create_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("shared");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("config.json"), "{}").unwrap();
+
+        let link = dir.path().join("link");
+        let created = create_link(&target, &link, LinkType::Directory).unwrap();
+        assert_ne!(created, CreatedLink::HardLink);
+        assert_eq!(
+            std::fs::read_to_string(link.join("config.json")).unwrap(),
+            "{}"
+        );
+
+        let file_link = dir.path().join("config.json");
+        create_link("shared/config.json", &file_link, LinkType::File).unwrap();
+        assert_eq!(std::fs::read_to_string(&file_link).unwrap(), "{}");
+
+        assert!(create_link(&target, &link, LinkType::Directory).is_err());
+    }
+}
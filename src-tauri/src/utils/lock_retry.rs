@@ -0,0 +1,339 @@
+//! Retrying file operations while another process has the file open
+//!
+//! Search indexers, antivirus scanners and sync clients open files right
+//! after they change, so a write or delete that follows closely on another
+//! one often fails with a sharing violation that would be gone a few
+//! milliseconds later. [`with_lock_retry`] retries those with jittered
+//! backoff and, if the file stays locked, names the process holding it
+//! (found through the Windows Restart Manager) instead of reporting a bare
+//! "being used by another process".
+
+use anyhow::Result;
+use log::debug;
+use serde::Serialize;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often and how long [`with_lock_retry`] retries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub attempts: u32,
+    /// Delay before the second attempt; it doubles after each attempt
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// File to look up the locking process of when every attempt failed
+    pub path: Option<PathBuf>,
+}
+
+impl Default for RetryPolicy {
+    /// About 1.5 seconds of retries in the worst case
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 8,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(640),
+            path: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, reporting the process that locks `path`
+    pub fn for_path<P: AsRef<Path>>(path: P) -> Self {
+        RetryPolicy {
+            path: Some(path.as_ref().to_path_buf()),
+            ..Default::default()
+        }
+    }
+}
+
+/// A process that has a file open
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockingProcess {
+    pub pid: u32,
+    /// Display name of the application or service
+    pub name: String,
+}
+
+/// Run `op`, retrying while it fails because the file is locked
+///
+/// Other errors are returned right away. If the file is still locked after
+/// the last attempt and `policy.path` is set, the error names the processes
+/// that have it open.
+pub fn with_lock_retry<T>(
+    op: impl FnMut() -> std::io::Result<T>,
+    policy: &RetryPolicy,
+) -> Result<T> {
+    retry_while(op, is_lock_violation, policy).map_err(|e| match &policy.path {
+        Some(path) => describe_lock(e, path),
+        None => e.into(),
+    })
+}
+
+/// Run `op`, retrying with the backoff of `policy` while `retryable` says
+/// the error is temporary
+pub fn retry_while<T>(
+    mut op: impl FnMut() -> std::io::Result<T>,
+    retryable: impl Fn(&std::io::Error) -> bool,
+    policy: &RetryPolicy,
+) -> std::io::Result<T> {
+    for attempt in 1..policy.attempts.max(1) {
+        match op() {
+            Err(e) if retryable(&e) => {
+                let delay = backoff_delay(policy, attempt, random());
+                debug!("File in use, retrying in {:?}: {}", delay, e);
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Whether `error` is a sharing or lock violation, i.e. another process
+/// has the file open
+pub fn is_lock_violation(error: &std::io::Error) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows_sys::Win32::Foundation::{ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION};
+        matches!(
+            error.raw_os_error().map(|code| code as u32),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Turn `error` from an operation on `path` into a message naming the
+/// processes that have `path` open, if it's a lock violation and they can
+/// be found
+pub fn describe_lock(error: std::io::Error, path: &Path) -> anyhow::Error {
+    if !is_lock_violation(&error) {
+        return error.into();
+    }
+    let holders = find_locking_processes(path);
+    if holders.is_empty() {
+        return error.into();
+    }
+    let names: Vec<String> = holders
+        .iter()
+        .map(|holder| format!("{} (PID {})", holder.name, holder.pid))
+        .collect();
+    anyhow::Error::new(error).context(format!(
+        "{} is in use by {}",
+        path.display(),
+        names.join(", ")
+    ))
+}
+
+/// Processes that have `path` open, as far as the Restart Manager knows
+///
+/// Empty on other platforms and when the lookup fails.
+pub fn find_locking_processes<P: AsRef<Path>>(path: P) -> Vec<LockingProcess> {
+    #[cfg(target_os = "windows")]
+    {
+        match restart_manager_list(path.as_ref()) {
+            Ok(processes) => processes,
+            Err(code) => {
+                debug!(
+                    "Restart Manager lookup for {} failed with error {}",
+                    path.as_ref().display(),
+                    code
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = path;
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn restart_manager_list(path: &Path) -> std::result::Result<Vec<LockingProcess>, u32> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{ERROR_MORE_DATA, ERROR_SUCCESS};
+    use windows_sys::Win32::System::RestartManager::{
+        RmEndSession, RmGetList, RmRegisterResources, RmStartSession, CCH_RM_SESSION_KEY,
+        RM_PROCESS_INFO,
+    };
+
+    let mut session = 0u32;
+    let mut key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+    let code = unsafe { RmStartSession(&mut session, 0, key.as_mut_ptr()) };
+    if code != ERROR_SUCCESS {
+        return Err(code);
+    }
+
+    let result = (|| {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let files = [wide.as_ptr()];
+        let code = unsafe {
+            RmRegisterResources(
+                session,
+                1,
+                files.as_ptr(),
+                0,
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        if code != ERROR_SUCCESS {
+            return Err(code);
+        }
+
+        // The list can grow between the size query and the read
+        let mut infos: Vec<RM_PROCESS_INFO> = Vec::new();
+        loop {
+            let (mut needed, mut count, mut reasons) = (0u32, infos.len() as u32, 0u32);
+            let code = unsafe {
+                RmGetList(
+                    session,
+                    &mut needed,
+                    &mut count,
+                    infos.as_mut_ptr(),
+                    &mut reasons,
+                )
+            };
+            match code {
+                ERROR_SUCCESS => {
+                    infos.truncate(count as usize);
+                    break;
+                }
+                ERROR_MORE_DATA => {
+                    infos = vec![unsafe { std::mem::zeroed() }; needed as usize];
+                }
+                code => return Err(code),
+            }
+        }
+
+        Ok(infos
+            .iter()
+            .map(|info| {
+                let name = &info.strAppName;
+                let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                LockingProcess {
+                    pid: info.Process.dwProcessId,
+                    name: String::from_utf16_lossy(&name[..len]),
+                }
+            })
+            .collect())
+    })();
+
+    unsafe { RmEndSession(session) };
+    result
+}
+
+/// Delay after the `attempt`th failure: exponential up to `max_delay`, with
+/// the upper half randomized so processes retrying together spread out
+fn backoff_delay(policy: &RetryPolicy, attempt: u32, random: u64) -> Duration {
+    let exponential = policy
+        .initial_delay
+        .saturating_mul(1u32 << (attempt - 1).min(16))
+        .min(policy.max_delay);
+    let half = exponential / 2;
+    let jitter_nanos = half.as_nanos() as u64;
+    let jitter = if jitter_nanos == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_nanos(random % (jitter_nanos + 1))
+    };
+    half + jitter
+}
+
+/// A random number for jitter, without pulling in a random number crate
+fn random() -> u64 {
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quick() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 4,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            path: None,
+        }
+    }
+
+    #[test]
+    fn test_retry_while() {
+        let mut attempts = 0;
+        let result = retry_while(
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    Err(std::io::Error::from(std::io::ErrorKind::WouldBlock))
+                } else {
+                    Ok(attempts)
+                }
+            },
+            |e| e.kind() == std::io::ErrorKind::WouldBlock,
+            &quick(),
+        );
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: std::io::Result<()> = retry_while(
+            || {
+                attempts += 1;
+                Err(std::io::Error::from(std::io::ErrorKind::WouldBlock))
+            },
+            |e| e.kind() == std::io::ErrorKind::WouldBlock,
+            &quick(),
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+
+        let mut attempts = 0;
+        let result: Result<()> = with_lock_retry(
+            || {
+                attempts += 1;
+                Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+            },
+            &quick(),
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(backoff_delay(&policy, 1, 0), Duration::from_millis(5));
+        assert_eq!(backoff_delay(&policy, 2, 0), Duration::from_millis(10));
+        let jittered = backoff_delay(&policy, 2, u64::MAX);
+        assert!(jittered >= Duration::from_millis(10) && jittered <= Duration::from_millis(20));
+        for attempt in 1..40 {
+            let delay = backoff_delay(&policy, attempt, random());
+            assert!(delay <= policy.max_delay);
+        }
+        assert_eq!(backoff_delay(&policy, 30, 0), Duration::from_millis(320));
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_find_locking_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locked.txt");
+        let _file = std::fs::File::create(&path).unwrap();
+        let holders = find_locking_processes(&path);
+        assert!(holders.iter().any(|p| p.pid == std::process::id()));
+    }
+}
@@ -1 +1,16 @@
-pub mod paths;
\ No newline at end of file
+pub mod paths;
+pub mod cmdline;
+pub mod path_matcher;
+pub mod links;
+pub mod atomic_write;
+pub mod delete;
+pub mod trash;
+pub mod disk_space;
+pub mod ads;
+pub mod path_validation;
+pub mod move_path;
+pub mod dir_size;
+pub mod lock_retry;
+pub mod cloud_files;
+pub mod hashing;
+pub mod file_permissions;
\ No newline at end of file
@@ -0,0 +1,332 @@
+//! Moving files and directories, including between volumes
+//!
+//! A rename only works within one volume. [`move_path`] tries it first and,
+//! when the destination is on another drive, copies everything instead,
+//! checks each copied file's SHA-256 against the original and only then
+//! removes the source. The copy is made under a temporary name next to the
+//! destination, so an interrupted move never leaves a half-filled
+//! destination behind, and it is discarded if the source changed while it
+//! was being copied, so nothing written meanwhile is lost.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use super::disk_space::ensure_free_space;
+use super::hashing::verify_copy_blocking;
+use super::links::{create_link, LinkType};
+use super::paths::path_contains;
+
+/// How far a copying [`move_path`] has got
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MoveProgress {
+    pub files_done: u64,
+    pub total_files: u64,
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+}
+
+/// How [`move_path`] moved the path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveMethod {
+    Renamed,
+    /// Copied, verified and then removed from the source
+    Copied,
+}
+
+/// Result of [`move_path`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MoveReport {
+    pub method: MoveMethod,
+    pub files: u64,
+    pub bytes: u64,
+    /// Whether the source is gone; after a verified copy a source that
+    /// can't be deleted (e.g. a file open elsewhere) is left behind
+    pub source_removed: bool,
+}
+
+/// Move the file or directory `source` to `destination`, which must not
+/// exist yet
+///
+/// `on_progress` is called after each copied file when the move has to
+/// copy. Symlinks are moved as links, not followed.
+pub fn move_path<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    destination: Q,
+    mut on_progress: impl FnMut(&MoveProgress),
+) -> Result<MoveReport> {
+    let (source, destination) = (source.as_ref(), destination.as_ref());
+    if std::fs::symlink_metadata(source).is_err() {
+        anyhow::bail!("{} does not exist", source.display());
+    }
+    if std::fs::symlink_metadata(destination).is_ok() {
+        anyhow::bail!("{} already exists", destination.display());
+    }
+    if path_contains(source, destination) {
+        anyhow::bail!("Cannot move {} into itself", source.display());
+    }
+
+    match std::fs::rename(source, destination) {
+        Ok(()) => {
+            return Ok(MoveReport {
+                method: MoveMethod::Renamed,
+                files: 0,
+                bytes: 0,
+                source_removed: true,
+            })
+        }
+        Err(e) if is_cross_device(&e) => {
+            debug!(
+                "{} and {} are on different volumes, copying",
+                source.display(),
+                destination.display()
+            );
+        }
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    source.display(),
+                    destination.display()
+                )
+            })
+        }
+    }
+
+    let staging = staging_path(destination)?;
+    let before = tree_snapshot(source)?;
+    let result = copy_verified(source, &staging, &mut on_progress).and_then(|progress| {
+        // Files written during the copy were never copied or verified
+        if tree_snapshot(source)? != before {
+            anyhow::bail!(
+                "{} changed while it was being copied; nothing was moved",
+                source.display()
+            );
+        }
+        std::fs::rename(&staging, destination)
+            .with_context(|| format!("Failed to rename {}", staging.display()))?;
+        Ok(progress)
+    });
+    let progress = match result {
+        Ok(progress) => progress,
+        Err(e) => {
+            let _ = super::delete::delete_path(&staging, super::delete::DeleteMode::Permanent);
+            return Err(e);
+        }
+    };
+
+    let removed = if source.is_dir() && !source.is_symlink() {
+        std::fs::remove_dir_all(source)
+    } else {
+        std::fs::remove_file(source)
+    };
+    if let Err(e) = &removed {
+        warn!(
+            "Copied {} to {} but could not remove the original: {}",
+            source.display(),
+            destination.display(),
+            e
+        );
+    }
+    Ok(MoveReport {
+        method: MoveMethod::Copied,
+        files: progress.total_files,
+        bytes: progress.total_bytes,
+        source_removed: removed.is_ok(),
+    })
+}
+
+/// Whether a rename failed because source and destination are on
+/// different volumes
+fn is_cross_device(error: &std::io::Error) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows_sys::Win32::Foundation::ERROR_NOT_SAME_DEVICE;
+        error.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE as i32)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        error.raw_os_error() == Some(libc::EXDEV)
+    }
+}
+
+/// Hidden sibling of `destination` the copy is assembled in
+fn staging_path(destination: &Path) -> Result<PathBuf> {
+    let name = destination
+        .file_name()
+        .with_context(|| format!("Not a valid destination: {}", destination.display()))?;
+    Ok(destination.with_file_name(format!(
+        ".{}.moving-{}",
+        name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// An entry below a moved path: relative path, whether it is a directory,
+/// size and modification time
+type SnapshotEntry = (PathBuf, bool, u64, Option<SystemTime>);
+
+/// Everything below `source`, to notice changes made during a copy
+fn tree_snapshot(source: &Path) -> Result<Vec<SnapshotEntry>> {
+    WalkDir::new(source)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .map(|entry| {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            Ok((
+                entry.path().strip_prefix(source)?.to_path_buf(),
+                metadata.is_dir(),
+                metadata.len(),
+                metadata.modified().ok(),
+            ))
+        })
+        .collect::<Result<_>>()
+        .with_context(|| format!("Failed to read {}", source.display()))
+}
+
+/// Copy `source` to `target`, checking every file against its original
+fn copy_verified(
+    source: &Path,
+    target: &Path,
+    on_progress: &mut impl FnMut(&MoveProgress),
+) -> Result<MoveProgress> {
+    let entries: Vec<walkdir::DirEntry> = WalkDir::new(source)
+        .follow_links(false)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to read {}", source.display()))?;
+
+    let mut progress = MoveProgress::default();
+    for entry in entries.iter().filter(|e| e.file_type().is_file()) {
+        progress.total_files += 1;
+        progress.total_bytes += entry.metadata()?.len();
+    }
+    ensure_free_space(target, progress.total_bytes)?;
+
+    for entry in &entries {
+        let relative = entry.path().strip_prefix(source)?;
+        let to = if relative.as_os_str().is_empty() {
+            target.to_path_buf()
+        } else {
+            target.join(relative)
+        };
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            std::fs::create_dir(&to)
+                .with_context(|| format!("Failed to create {}", to.display()))?;
+        } else if file_type.is_symlink() {
+            let link_target = std::fs::read_link(entry.path())?;
+            let link_type = if entry.path().is_dir() {
+                LinkType::Directory
+            } else {
+                LinkType::File
+            };
+            create_link(&link_target, &to, link_type)?;
+        } else {
+            let bytes = copy_file_verified(entry.path(), &to)?;
+            progress.files_done += 1;
+            progress.bytes_done += bytes;
+            on_progress(&progress);
+        }
+    }
+    Ok(progress)
+}
+
+/// Copy one file, then read both back and compare; returns the size
+fn copy_file_verified(from: &Path, to: &Path) -> Result<u64> {
+    let mut reader =
+        File::open(from).with_context(|| format!("Failed to open {}", from.display()))?;
+    let mut writer =
+        File::create(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    let bytes = std::io::copy(&mut reader, &mut writer)
+        .with_context(|| format!("Failed to copy {}", from.display()))?;
+    writer
+        .sync_all()
+        .with_context(|| format!("Failed to write {}", to.display()))?;
+    drop(writer);
+
+    if let Ok(modified) = std::fs::metadata(from).and_then(|m| m.modified()) {
+        let _ = File::options()
+            .write(true)
+            .open(to)
+            .and_then(|file| file.set_modified(modified));
+    }
+
+    verify_copy_blocking(from, to)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(dir: &Path) {
+        std::fs::create_dir_all(dir.join("src").join("empty")).unwrap();
+        std::fs::write(dir.join("README.md"), "# app").unwrap();
+        std::fs::write(dir.join("src").join("main.rs"), "fn main() {}").unwrap();
+    }
+
+    #[test]
+    fn test_move_path_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("app");
+        fill(&source);
+
+        let destination = dir.path().join("moved");
+        let report = move_path(&source, &destination, |_| {}).unwrap();
+        assert_eq!(report.method, MoveMethod::Renamed);
+        assert!(!source.exists());
+        assert!(destination.join("src").join("main.rs").is_file());
+
+        assert!(move_path(&source, dir.path().join("again"), |_| {}).is_err());
+        std::fs::create_dir(&source).unwrap();
+        assert!(move_path(&source, &destination, |_| {}).is_err());
+        assert!(move_path(&source, source.join("inner"), |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_copy_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("app");
+        fill(&source);
+
+        let staging = staging_path(&dir.path().join("copy")).unwrap();
+        let mut updates = Vec::new();
+        let progress = copy_verified(&source, &staging, &mut |p: &MoveProgress| {
+            updates.push(p.clone())
+        })
+        .unwrap();
+        assert_eq!(progress.total_files, 2);
+        assert_eq!(progress.total_bytes, 5 + 12);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].bytes_done, progress.total_bytes);
+        assert_eq!(
+            std::fs::read_to_string(staging.join("src").join("main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(staging.join("src").join("empty").is_dir());
+        // The source is untouched until the caller removes it
+        assert!(source.join("README.md").is_file());
+    }
+
+    #[test]
+    fn test_tree_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        fill(dir.path());
+
+        let before = tree_snapshot(dir.path()).unwrap();
+        assert_eq!(tree_snapshot(dir.path()).unwrap(), before);
+
+        std::fs::write(dir.path().join("src").join("new.rs"), "").unwrap();
+        assert_ne!(tree_snapshot(dir.path()).unwrap(), before);
+    }
+}
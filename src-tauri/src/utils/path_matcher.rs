@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::debug;
+use std::path::{Path, PathBuf};
+
+use super::paths::make_relative;
+
+/// Matches project paths against glob patterns with `.gitignore` semantics
+///
+/// Patterns follow the gitignore rules: `node_modules` matches a file or
+/// directory of that name at any depth, `/build` only at the project root,
+/// `node_modules/**` everything inside it, `*.log` by name, and `!keep.log`
+/// re-includes a path excluded by an earlier pattern. A path also matches
+/// when one of its parent directories does, so `target/debug/app` is covered
+/// by `target/`.
+///
+/// The file tree, the checkpoint snapshotter and anything else that decides
+/// which project files to skip should go through this type so they agree.
+#[derive(Debug, Clone)]
+pub struct PathMatcher {
+    root: PathBuf,
+    gitignore: Gitignore,
+}
+
+impl PathMatcher {
+    /// Matcher for `patterns`, which are relative to `root`
+    pub fn new<P: AsRef<Path>, S: AsRef<str>>(root: P, patterns: &[S]) -> Result<Self> {
+        let root = root.as_ref();
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern.as_ref())
+                .with_context(|| format!("Invalid pattern '{}'", pattern.as_ref()))?;
+        }
+        Ok(PathMatcher {
+            root: root.to_path_buf(),
+            gitignore: builder.build().context("Failed to build the path matcher")?,
+        })
+    }
+
+    /// Matcher for the project's own ignore rules: `.gitignore` at `root`
+    /// and `.git/info/exclude`
+    ///
+    /// Missing files are fine and unparsable lines are skipped, so this
+    /// never fails. `.gitignore` files in subdirectories are not read.
+    pub fn for_project<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref();
+        let mut builder = GitignoreBuilder::new(root);
+        for file in [
+            root.join(".gitignore"),
+            root.join(".git").join("info").join("exclude"),
+        ] {
+            if file.is_file() {
+                if let Some(e) = builder.add(&file) {
+                    debug!("Could not fully parse {}: {}", file.display(), e);
+                }
+            }
+        }
+        let gitignore = builder.build().unwrap_or_else(|e| {
+            debug!("Ignoring the ignore files of {}: {}", root.display(), e);
+            Gitignore::empty()
+        });
+        PathMatcher {
+            root: root.to_path_buf(),
+            gitignore,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether `path` or one of its parent directories matches
+    ///
+    /// `path` is relative to the root (with `/` or `\` separators) or an
+    /// absolute path inside it. Paths outside the root never match.
+    pub fn is_match<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> bool {
+        let path = path.as_ref();
+        let relative = if path.has_root() {
+            match make_relative(&self.root, path) {
+                Some(relative) if !relative.starts_with("..") => relative,
+                _ => return false,
+            }
+        } else {
+            PathBuf::from(path.to_string_lossy().replace('\\', "/"))
+        };
+        if relative.as_os_str().is_empty() || relative == Path::new(".") {
+            return false;
+        }
+        self.gitignore
+            .matched_path_or_any_parents(&relative, is_dir)
+            .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_patterns() {
+        let matcher = PathMatcher::new(
+            "/project",
+            &["node_modules/**", "/build", "*.log", "!keep.log", "target/"],
+        )
+        .unwrap();
+
+        assert!(matcher.is_match("node_modules/pkg/index.js", false));
+        assert!(matcher.is_match("build", true));
+        assert!(matcher.is_match("build/out.js", false));
+        assert!(!matcher.is_match("src/build", true));
+        assert!(matcher.is_match("src/debug.log", false));
+        assert!(!matcher.is_match("keep.log", false));
+        assert!(matcher.is_match("target", true));
+        assert!(!matcher.is_match("target", false));
+        assert!(matcher.is_match("crates/app/target/debug/app", false));
+        assert!(matcher.is_match("src\\server.log", false));
+        assert!(!matcher.is_match("src/main.rs", false));
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert!(matcher.is_match("/project/node_modules/a.js", false));
+            assert!(!matcher.is_match("/elsewhere/node_modules/a.js", false));
+        }
+
+        assert!(PathMatcher::new("/project", &["[z-a]"]).is_err());
+    }
+
+    #[test]
+    fn test_for_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "dist/\n*.tmp\n").unwrap();
+        std::fs::create_dir_all(dir.path().join(".git").join("info")).unwrap();
+        std::fs::write(
+            dir.path().join(".git").join("info").join("exclude"),
+            "secrets.env\n",
+        )
+        .unwrap();
+
+        let matcher = PathMatcher::for_project(dir.path());
+        assert!(matcher.is_match("dist/app.js", false));
+        assert!(matcher.is_match("notes.tmp", false));
+        assert!(matcher.is_match("secrets.env", false));
+        assert!(!matcher.is_match("src/app.ts", false));
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(!PathMatcher::for_project(empty.path()).is_match("dist", true));
+    }
+}
@@ -0,0 +1,335 @@
+//! Checking a location before the user commits to it
+//!
+//! [`validate_path_for_use`] collects everything that would make a chosen
+//! project or export location fail later: names Windows won't accept, paths
+//! too long for the tools that work inside them, drives that are gone,
+//! folders only admins can write to and cloud folders whose files aren't on
+//! the disk. The UI lists them all at once instead of showing the first OS
+//! error a write runs into.
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::cloud_files::find_cloud_placeholder;
+use super::paths::{
+    check_unc_available, is_reserved_device_name, unc_share_root, validate_windows_filename,
+    LEGACY_PATH_LIMIT, UNC_PROBE_TIMEOUT,
+};
+
+/// What a path is going to be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathUsage {
+    /// A folder opened as a project
+    Project,
+    /// A folder opcode keeps its own data in
+    DataDirectory,
+    /// A file opcode writes, such as an export
+    ExportFile,
+}
+
+impl PathUsage {
+    fn is_directory(self) -> bool {
+        !matches!(self, PathUsage::ExportFile)
+    }
+
+    /// Longest path that still leaves room for what ends up inside it
+    fn max_length(self) -> usize {
+        match self {
+            // node_modules, build output and checkpoint content easily add
+            // 100 characters below the folder itself
+            PathUsage::Project | PathUsage::DataDirectory => LEGACY_PATH_LIMIT - 100,
+            PathUsage::ExportFile => 259,
+        }
+    }
+}
+
+/// Why a path won't work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathProblemKind {
+    Empty,
+    NotAbsolute,
+    TooLong,
+    ReservedName,
+    InvalidName,
+    DriveNotConnected,
+    ShareUnavailable,
+    WrongType,
+    RequiresAdmin,
+    CloudPlaceholder,
+}
+
+/// A problem found by [`validate_path_for_use`], with a message for the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathProblem {
+    pub kind: PathProblemKind,
+    pub message: String,
+}
+
+impl PathProblem {
+    fn new(kind: PathProblemKind, message: impl Into<String>) -> Self {
+        PathProblem {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Everything that would stop `path` from working for `usage`, or nothing
+/// if it's fine
+///
+/// The path doesn't need to exist yet. Touches the file system (and, for
+/// UNC paths, the network) but doesn't change anything.
+pub fn validate_path_for_use<P: AsRef<Path>>(path: P, usage: PathUsage) -> Vec<PathProblem> {
+    let path = path.as_ref();
+    let windows = cfg!(target_os = "windows");
+    let mut problems = lexical_problems(&path.to_string_lossy(), usage, windows);
+    if problems.iter().any(|p| {
+        matches!(
+            p.kind,
+            PathProblemKind::Empty | PathProblemKind::NotAbsolute
+        )
+    }) {
+        return problems;
+    }
+
+    // Nothing else can be checked on a drive or share that isn't there
+    if let Some(problem) = location_problem(&path.to_string_lossy(), windows) {
+        problems.push(problem);
+        return problems;
+    }
+
+    if path.exists() && path.is_dir() != usage.is_directory() {
+        problems.push(PathProblem::new(
+            PathProblemKind::WrongType,
+            if usage.is_directory() {
+                format!("{} is a file, not a folder", path.display())
+            } else {
+                format!("{} is a folder, not a file", path.display())
+            },
+        ));
+    }
+    if let Some(placeholder) = find_cloud_placeholder(path) {
+        problems.push(PathProblem::new(
+            PathProblemKind::CloudPlaceholder,
+            format!(
+                "{} is an online-only cloud folder, so its files aren't on this PC; right-click it and choose \"Always keep on this device\"",
+                placeholder.display()
+            ),
+        ));
+    }
+    if let Some(problem) = write_problem(path, usage) {
+        problems.push(problem);
+    }
+    problems
+}
+
+/// Problems visible from the path string alone
+fn lexical_problems(path: &str, usage: PathUsage, windows: bool) -> Vec<PathProblem> {
+    if path.trim().is_empty() {
+        return vec![PathProblem::new(
+            PathProblemKind::Empty,
+            "No location chosen",
+        )];
+    }
+    let is_unc = path.starts_with(r"\\") || path.starts_with("//");
+    let drive = drive_letter(path);
+    let absolute = if windows {
+        is_unc || (drive.is_some() && path[2..].starts_with(['\\', '/']))
+    } else {
+        path.starts_with('/')
+    };
+    if !absolute {
+        return vec![PathProblem::new(
+            PathProblemKind::NotAbsolute,
+            format!("\"{}\" is not a full path", path),
+        )];
+    }
+    if !windows {
+        return Vec::new();
+    }
+
+    let mut problems = Vec::new();
+    let length = path.chars().count();
+    if length > usage.max_length() {
+        problems.push(PathProblem::new(
+            PathProblemKind::TooLong,
+            format!(
+                "The path is {} characters long; keep it under {} so files created inside stay within Windows' 260-character limit",
+                length,
+                usage.max_length()
+            ),
+        ));
+    }
+
+    // Skip the drive (`C:`) or the `\\server\share` prefix
+    let skip = if is_unc { 4 } else { 1 };
+    for name in path.split(['\\', '/']).skip(skip) {
+        if name.is_empty() || name == "." || name == ".." {
+            continue;
+        }
+        if let Err(e) = validate_windows_filename(name) {
+            let kind = if is_reserved_device_name(name) {
+                PathProblemKind::ReservedName
+            } else {
+                PathProblemKind::InvalidName
+            };
+            problems.push(PathProblem::new(kind, e.to_string()));
+        }
+    }
+    problems
+}
+
+/// The drive letter of `C:\...`, uppercased
+fn drive_letter(path: &str) -> Option<char> {
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+            Some(letter.to_ascii_uppercase())
+        }
+        _ => None,
+    }
+}
+
+/// A drive that isn't connected or a share that doesn't answer
+fn location_problem(path: &str, windows: bool) -> Option<PathProblem> {
+    if !windows {
+        return None;
+    }
+    if unc_share_root(path).is_some() {
+        return check_unc_available(path, UNC_PROBE_TIMEOUT)
+            .err()
+            .map(|e| PathProblem::new(PathProblemKind::ShareUnavailable, e.to_string()));
+    }
+    let letter = drive_letter(path)?;
+    if Path::new(&format!("{}:\\", letter)).exists() {
+        return None;
+    }
+    Some(PathProblem::new(
+        PathProblemKind::DriveNotConnected,
+        format!(
+            "Drive {}: is not connected; reconnect it or choose another location",
+            letter
+        ),
+    ))
+}
+
+/// Whether the current user can create files where `path` will be written
+fn write_problem(path: &Path, usage: PathUsage) -> Option<PathProblem> {
+    let start = if usage.is_directory() {
+        path
+    } else {
+        path.parent()?
+    };
+    let dir = start.ancestors().find(|ancestor| ancestor.is_dir())?;
+
+    #[cfg(target_os = "windows")]
+    {
+        match crate::windows::permissions::requires_admin_access(&dir.to_string_lossy()) {
+            Ok(false) => None,
+            Ok(true) => Some(PathProblem::new(
+                PathProblemKind::RequiresAdmin,
+                format!(
+                    "Writing to {} requires administrator rights; choose a folder in your user profile instead",
+                    dir.display()
+                ),
+            )),
+            Err(e) => {
+                debug!("Could not test writing to {}: {:#}", dir.display(), e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let probe = dir.join(format!(".opcode_test-{}", uuid::Uuid::new_v4().simple()));
+        match crate::windows::secure_temp::create_secure_file(&probe) {
+            Ok(_) => {
+                let _ = std::fs::remove_file(&probe);
+                None
+            }
+            Err(e)
+                if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                    == Some(std::io::ErrorKind::PermissionDenied) =>
+            {
+                Some(PathProblem::new(
+                    PathProblemKind::RequiresAdmin,
+                    format!("You don't have permission to write to {}", dir.display()),
+                ))
+            }
+            Err(e) => {
+                debug!("Could not test writing to {}: {:#}", dir.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(problems: &[PathProblem]) -> Vec<PathProblemKind> {
+        problems.iter().map(|p| p.kind).collect()
+    }
+
+    #[test]
+    fn test_lexical_problems() {
+        use PathProblemKind::*;
+        let project = PathUsage::Project;
+
+        assert_eq!(kinds(&lexical_problems("", project, true)), [Empty]);
+        assert_eq!(
+            kinds(&lexical_problems(r"projects\app", project, true)),
+            [NotAbsolute]
+        );
+        assert_eq!(
+            kinds(&lexical_problems(r"C:app", project, true)),
+            [NotAbsolute]
+        );
+        assert!(lexical_problems(r"C:\Users\dev\app", project, true).is_empty());
+        assert!(lexical_problems(r"\\nas\projects\app", project, true).is_empty());
+        assert!(lexical_problems("/home/dev/aux", project, false).is_empty());
+        assert_eq!(
+            kinds(&lexical_problems("home/dev", project, false)),
+            [NotAbsolute]
+        );
+
+        assert_eq!(
+            kinds(&lexical_problems(r"C:\Users\dev\con\app", project, true)),
+            [ReservedName]
+        );
+        assert_eq!(
+            kinds(&lexical_problems(r"C:\Users\dev\what?\app.", project, true)),
+            [InvalidName, InvalidName]
+        );
+
+        let long = format!(r"C:\{}", "a".repeat(200));
+        assert_eq!(kinds(&lexical_problems(&long, project, true)), [TooLong]);
+        assert!(lexical_problems(&long, PathUsage::ExportFile, true).is_empty());
+    }
+
+    #[test]
+    fn test_drive_letter() {
+        assert_eq!(drive_letter(r"d:\projects"), Some('D'));
+        assert_eq!(drive_letter(r"\\nas\share"), None);
+        assert_eq!(drive_letter("/home"), None);
+    }
+
+    #[test]
+    fn test_validate_path_for_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("app");
+        assert!(validate_path_for_use(&project, PathUsage::Project).is_empty());
+
+        std::fs::write(&project, "").unwrap();
+        assert_eq!(
+            kinds(&validate_path_for_use(&project, PathUsage::Project)),
+            [PathProblemKind::WrongType]
+        );
+        assert!(validate_path_for_use(&project, PathUsage::ExportFile).is_empty());
+    }
+}
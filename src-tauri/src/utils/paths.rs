@@ -1,4 +1,11 @@
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Longest directory path the legacy Win32 APIs accept (`MAX_PATH` minus
+/// room for an 8.3 file name)
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) const LEGACY_PATH_LIMIT: usize = 248;
 
 /// Normalize a path for the current platform
 /// On Windows, converts forward slashes to backslashes
@@ -45,6 +52,28 @@ pub fn join_paths<P: AsRef<Path>, Q: AsRef<Path>>(base: P, path: Q) -> PathBuf {
     result
 }
 
+/// Join a user-supplied relative path onto `root`, refusing paths that
+/// would leave it
+///
+/// Absolute paths, drive and UNC prefixes and `..` components are rejected,
+/// since `Path::join` would let them replace or climb out of `root`.
+pub fn join_within<P: AsRef<Path>>(root: P, relative: &str) -> anyhow::Result<PathBuf> {
+    use std::path::Component;
+
+    let relative_path = Path::new(relative);
+    if relative_path
+        .components()
+        .any(|c| matches!(c, Component::RootDir | Component::Prefix(_)))
+    {
+        anyhow::bail!("Path must be relative: {}", relative);
+    }
+    // Also split on `\`, which `Path` keeps inside components on Unix
+    if relative.split(['/', '\\']).any(|part| part == "..") {
+        anyhow::bail!("Path must stay within the project: {}", relative);
+    }
+    Ok(root.as_ref().join(relative_path))
+}
+
 /// Check if a path is absolute for the current platform
 pub fn is_absolute_path<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
@@ -109,6 +138,723 @@ pub fn from_windows_path(path: &str) -> PathBuf {
     }
 }
 
+/// Convert a path to the `\\?\` extended-length form when it is too long
+/// for the legacy `MAX_PATH` limit
+///
+/// `std::fs` already does this internally, but paths passed straight to Win32
+/// functions (ACLs, integrity labels, EFS) fail beyond 260 characters unless
+/// the LongPathsEnabled policy is set, which is common with deep
+/// `node_modules` trees. Extended-length paths bypass Windows' path
+/// normalization, so the path is made absolute first. Shorter paths are
+/// returned unchanged, and on other platforms this is a no-op.
+pub fn to_extended_length_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+
+    #[cfg(target_os = "windows")]
+    {
+        if path.as_os_str().len() < LEGACY_PATH_LIMIT {
+            return path.to_path_buf();
+        }
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        match absolute.to_str() {
+            Some(absolute) => PathBuf::from(extended_length_form(absolute)),
+            None => absolute,
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// Prefix an absolute Windows path with `\\?\` (or `\\?\UNC\` for shares)
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn extended_length_form(absolute: &str) -> String {
+    if absolute.starts_with(r"\\?\") || absolute.starts_with(r"\\.\") {
+        return absolute.to_string();
+    }
+    match absolute.strip_prefix(r"\\") {
+        Some(share) => format!(r"\\?\UNC\{}", share),
+        None => format!(r"\\?\{}", absolute),
+    }
+}
+
+/// Resolve symlinks, junctions and mapped network drives to the real
+/// location of an existing path
+///
+/// Unlike [`std::fs::canonicalize`], the result has no `\\?\` prefix on
+/// Windows, so it can be shown to the user and compared with paths they
+/// entered. A mapped drive resolves to its `\\server\share` path.
+pub fn canonicalize_path<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf> {
+    let canonical = std::fs::canonicalize(path)?;
+
+    #[cfg(target_os = "windows")]
+    {
+        if let Some(canonical) = canonical.to_str() {
+            return Ok(PathBuf::from(strip_verbatim_prefix(canonical)));
+        }
+    }
+    Ok(canonical)
+}
+
+/// Turn `\\?\C:\...` and `\\?\UNC\server\...` back into their usual form
+///
+/// Other verbatim paths, such as volume GUID paths, have no usual form and
+/// are returned unchanged.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn strip_verbatim_prefix(path: &str) -> String {
+    if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", share);
+    }
+    match path.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest.to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Expand 8.3 short names such as `C:\PROGRA~1` to their long form
+///
+/// Drag and drop from some shells and older installers hand over short
+/// names, which would otherwise be stored as a second, different path for
+/// the same directory. Only components that exist can be expanded; the
+/// rest of the path is kept as given. A no-op on other platforms.
+pub fn expand_short_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if !has_short_component(&path.to_string_lossy()) {
+        return path.to_path_buf();
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        for ancestor in path.ancestors() {
+            if !has_short_component(&ancestor.to_string_lossy()) {
+                break;
+            }
+            if let Some(long) = long_path_name(ancestor) {
+                return match path.strip_prefix(ancestor) {
+                    Ok(rest) if !rest.as_os_str().is_empty() => long.join(rest),
+                    _ => long,
+                };
+            }
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Whether a component looks like a generated short name (`NAME~N`)
+fn has_short_component(path: &str) -> bool {
+    path.split(['\\', '/']).any(|component| {
+        let stem = component.split('.').next().unwrap_or_default();
+        stem.len() <= 8
+            && stem
+                .split_once('~')
+                .is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// `GetLongPathNameW` for an existing path
+#[cfg(target_os = "windows")]
+fn long_path_name(path: &Path) -> Option<PathBuf> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows_sys::Win32::Storage::FileSystem::GetLongPathNameW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let len = unsafe { GetLongPathNameW(wide.as_ptr(), std::ptr::null_mut(), 0) };
+    if len == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u16; len as usize];
+    let written = unsafe { GetLongPathNameW(wide.as_ptr(), buffer.as_mut_ptr(), len) };
+    if written == 0 || written >= len {
+        return None;
+    }
+    buffer.truncate(written as usize);
+    Some(PathBuf::from(std::ffi::OsString::from_wide(&buffer)))
+}
+
+/// How long [`check_unc_available`] waits for a share before calling it
+/// unreachable when opening a project
+pub const UNC_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The `\\server\share` root of a UNC path, also accepting `\\?\UNC\...`
+///
+/// Returns `None` for anything else, including device paths like `\\.\`.
+pub fn unc_share_root(path: &str) -> Option<String> {
+    let rest = match path.strip_prefix(r"\\?\UNC\") {
+        Some(rest) => rest,
+        None if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") => return None,
+        None => path
+            .strip_prefix(r"\\")
+            .or_else(|| path.strip_prefix("//"))?,
+    };
+    let mut parts = rest.split(['\\', '/']).filter(|part| !part.is_empty());
+    let server = parts.next()?;
+    let share = parts.next()?;
+    Some(format!(r"\\{}\{}", server, share))
+}
+
+/// Check that the network share holding `path` answers within `timeout`
+///
+/// Touching a path on a share whose server is gone blocks until SMB gives
+/// up, which can take a minute, so the share root is probed on a separate
+/// thread instead. A probe that hangs keeps its thread until the system
+/// call returns, but the caller gets an error after `timeout`. Local paths,
+/// and every path on platforms without UNC paths, are available.
+pub fn check_unc_available<P: AsRef<Path>>(path: P, timeout: Duration) -> anyhow::Result<()> {
+    if !cfg!(target_os = "windows") {
+        return Ok(());
+    }
+    let Some(share) = unc_share_root(&path.as_ref().to_string_lossy()) else {
+        return Ok(());
+    };
+    probe_with_timeout(&share, timeout, |share| {
+        std::fs::metadata(share).map(|_| ())
+    })
+}
+
+/// Run `probe` on `share` in the background, giving up after `timeout`
+fn probe_with_timeout(
+    share: &str,
+    timeout: Duration,
+    probe: impl FnOnce(&str) -> std::io::Result<()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let target = share.to_string();
+    std::thread::Builder::new()
+        .name("unc-probe".to_string())
+        .spawn(move || {
+            let _ = sender.send(probe(&target));
+        })?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(anyhow::anyhow!(
+            "Network share {} is not available: {}",
+            share,
+            e
+        )),
+        Err(_) => Err(anyhow::anyhow!(
+            "Network share {} did not respond within {} seconds; check that the server is reachable and you are connected to its network",
+            share,
+            timeout.as_secs_f32()
+        )),
+    }
+}
+
+/// Expand `~`, `%VAR%`, `${VAR}` and `$VAR` in a user-entered path
+///
+/// All forms work on every platform, so a path written on one system
+/// resolves on the other: `%USERPROFILE%` and `${HOME}` both mean the home
+/// directory, `%APPDATA%` the roaming config directory and `%LOCALAPPDATA%`
+/// the local data directory when the variable itself is not set. Unknown
+/// variables are left as written, and so is a path that exists as written,
+/// e.g. a folder really named `$work`.
+pub fn expand_env_path(path: &str) -> PathBuf {
+    if path.contains(['%', '$']) && Path::new(path).exists() {
+        return PathBuf::from(path);
+    }
+
+    let home = dirs::home_dir();
+    PathBuf::from(expand_with(path, home.as_deref(), |name| {
+        std::env::var(name).ok().or_else(|| {
+            let dir = match name.to_ascii_uppercase().as_str() {
+                "HOME" | "USERPROFILE" => dirs::home_dir(),
+                "APPDATA" => dirs::config_dir(),
+                "LOCALAPPDATA" => dirs::data_local_dir(),
+                _ => None,
+            };
+            dir.map(|dir| dir.to_string_lossy().to_string())
+        })
+    }))
+}
+
+fn expand_with(path: &str, home: Option<&Path>, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    // Windows names such as `ProgramFiles(x86)` contain parentheses
+    let is_percent_name_char = |c: char| is_name_char(c) || c == '(' || c == ')';
+    let mut expanded = String::with_capacity(path.len());
+
+    let mut rest = path;
+    if let Some(home) = home {
+        if let Some(after) = rest.strip_prefix('~') {
+            if after.is_empty() || after.starts_with(['/', '\\']) {
+                expanded.push_str(&home.to_string_lossy());
+                rest = after;
+            }
+        }
+    }
+
+    while let Some(start) = rest.find(['%', '$']) {
+        expanded.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        // (variable name, length of the whole reference)
+        let reference = if let Some(inner) = tail.strip_prefix('%') {
+            inner
+                .find('%')
+                .map(|end| &inner[..end])
+                .filter(|name| !name.is_empty() && name.chars().all(is_percent_name_char))
+                .map(|name| (name, name.len() + 2))
+        } else if let Some(inner) = tail.strip_prefix("${") {
+            inner
+                .find('}')
+                .map(|end| &inner[..end])
+                .filter(|name| !name.is_empty() && name.chars().all(is_name_char))
+                .map(|name| (name, name.len() + 3))
+        } else {
+            let inner = &tail[1..];
+            let len = inner.find(|c| !is_name_char(c)).unwrap_or(inner.len());
+            (len > 0).then(|| (&inner[..len], len + 1))
+        };
+
+        match reference.and_then(|(name, len)| lookup(name).map(|value| (value, len))) {
+            Some((value, len)) => {
+                expanded.push_str(&value);
+                rest = &tail[len..];
+            }
+            None => {
+                expanded.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Turn a path argument from the frontend into the path commands work with
+///
+/// Every command taking a user-entered path goes through here so they all
+/// accept the same input: surrounding whitespace and the quotes Explorer's
+/// "Copy as path" adds are dropped, `~` and variables are expanded (see
+/// [`expand_env_path`]), separators are normalized, a `\\?\` prefix is
+/// removed, `.` components and trailing separators are dropped and 8.3 short
+/// names are expanded. Empty and relative paths are rejected.
+pub fn parse_user_path(input: &str) -> anyhow::Result<PathBuf> {
+    let trimmed = input.trim();
+    let unquoted = trimmed
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or(trimmed)
+        .trim();
+    if unquoted.is_empty() {
+        anyhow::bail!("No path given");
+    }
+
+    let path = normalize_path(expand_env_path(unquoted));
+    #[cfg(target_os = "windows")]
+    let path = PathBuf::from(strip_verbatim_prefix(&path.to_string_lossy()));
+    if !path.is_absolute() {
+        anyhow::bail!("\"{}\" is not a full path", unquoted);
+    }
+    let path: PathBuf = path.components().collect();
+    Ok(expand_short_path(path))
+}
+
+/// Characters Windows doesn't allow in file names, besides control characters
+const INVALID_FILENAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Longest file name NTFS and most other file systems accept, in bytes
+const MAX_FILENAME_LEN: usize = 255;
+
+/// Whether Windows opens a device instead of a file for `name`
+///
+/// The device name is matched before the first dot and ignoring trailing
+/// spaces, so `nul.txt` and `COM1 .log` are devices too.
+pub(crate) fn is_reserved_device_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    let upper = stem.to_uppercase();
+    match upper.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" | "CONIN$" | "CONOUT$" => true,
+        _ => matches!(
+            upper.strip_prefix("COM").or_else(|| upper.strip_prefix("LPT")),
+            Some("1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³")
+        ),
+    }
+}
+
+/// Check that `name` is usable as a file name on Windows
+///
+/// Catches reserved device names, invalid characters and trailing dots or
+/// spaces (which Windows silently strips), so the user learns what is wrong
+/// with the name instead of getting an OS error from the write.
+pub fn validate_windows_filename(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("File name is empty");
+    }
+    if name == "." || name == ".." {
+        anyhow::bail!("\"{}\" is not a file name", name);
+    }
+    if name.chars().any(char::is_control) {
+        anyhow::bail!(
+            "File name \"{}\" contains a control character",
+            name.escape_debug()
+        );
+    }
+    if let Some(c) = name.chars().find(|c| INVALID_FILENAME_CHARS.contains(c)) {
+        anyhow::bail!(
+            "File name \"{}\" contains '{}'; Windows file names can't contain < > : \" / \\ | ? *",
+            name,
+            c
+        );
+    }
+    if name.ends_with(['.', ' ']) {
+        anyhow::bail!(
+            "File name \"{}\" ends with a dot or space, which Windows drops; remove it",
+            name
+        );
+    }
+    if is_reserved_device_name(name) {
+        anyhow::bail!(
+            "\"{}\" is a reserved device name on Windows; choose another name",
+            name
+        );
+    }
+    if name.len() > MAX_FILENAME_LEN {
+        anyhow::bail!(
+            "File name is {} bytes long; the limit is {}",
+            name.len(),
+            MAX_FILENAME_LEN
+        );
+    }
+    Ok(())
+}
+
+/// Turn `name` into a file name that passes [`validate_windows_filename`]
+///
+/// Invalid and control characters become `_`, trailing dots and spaces are
+/// dropped, reserved device names get a `_` after their stem and overlong
+/// names are shortened, keeping a short extension.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if INVALID_FILENAME_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+    if sanitized.is_empty() {
+        return "_".to_string();
+    }
+
+    if is_reserved_device_name(&sanitized) {
+        let stem = sanitized.split('.').next().unwrap_or_default();
+        sanitized.insert(stem.trim_end_matches(' ').len(), '_');
+    }
+
+    if sanitized.len() > MAX_FILENAME_LEN {
+        let extension = sanitized
+            .rfind('.')
+            .map(|dot| sanitized[dot..].to_string())
+            .filter(|extension| extension.len() <= 16)
+            .unwrap_or_default();
+        let mut end = MAX_FILENAME_LEN - extension.len();
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+        sanitized.push_str(&extension);
+    }
+    sanitized
+}
+
+/// Path of `target` relative to the directory `base`, e.g. `src\main.rs` or
+/// `..\shared\lib.rs`
+///
+/// Works lexically, without touching the file system, and compares
+/// case-insensitively on Windows. Returns `None` when no relative path
+/// exists: the paths are on different drives or UNC shares, or only one of
+/// them is absolute.
+pub fn make_relative<P: AsRef<Path>, Q: AsRef<Path>>(base: P, target: Q) -> Option<PathBuf> {
+    relative_path(
+        &base.as_ref().to_string_lossy(),
+        &target.as_ref().to_string_lossy(),
+        cfg!(target_os = "windows"),
+    )
+    .map(PathBuf::from)
+}
+
+/// Split a path into its root (`C:`, `\\server\share`, `/` or empty for
+/// relative paths) and its components, resolving `.` and `..`
+fn split_root(path: &str, windows: bool) -> (String, Vec<&str>) {
+    let is_separator = |c: char| c == '/' || (windows && c == '\\');
+    let (root, rest) = if windows && path.starts_with(r"\\") {
+        // \\server\share is the root of a UNC path
+        let mut parts = path[2..].splitn(3, is_separator);
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        (format!(r"\\{}\{}", server, share), parts.next().unwrap_or_default())
+    } else if windows && path.as_bytes().get(1) == Some(&b':') {
+        (path[..2].to_string(), &path[2..])
+    } else if path.starts_with(is_separator) {
+        ("/".to_string(), path)
+    } else {
+        (String::new(), path)
+    };
+
+    let mut components = Vec::new();
+    for component in rest.split(is_separator) {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            // `..` can't go above the root of an absolute path
+            ".." if !root.is_empty() => {}
+            _ => components.push(component),
+        }
+    }
+    (root, components)
+}
+
+fn relative_path(base: &str, target: &str, windows: bool) -> Option<String> {
+    let (base, target) = if windows {
+        (strip_verbatim_prefix(base), strip_verbatim_prefix(target))
+    } else {
+        (base.to_string(), target.to_string())
+    };
+    let same = |a: &str, b: &str| {
+        if windows {
+            a.to_lowercase() == b.to_lowercase()
+        } else {
+            a == b
+        }
+    };
+
+    let (base_root, base_components) = split_root(&base, windows);
+    let (target_root, target_components) = split_root(&target, windows);
+    if !same(&base_root, &target_root) {
+        return None;
+    }
+
+    let common = base_components
+        .iter()
+        .zip(&target_components)
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    // A relative base that climbs out of the common part can't be undone
+    if base_components[common..].contains(&"..") {
+        return None;
+    }
+
+    let relative: Vec<&str> = std::iter::repeat_n("..", base_components.len() - common)
+        .chain(target_components[common..].iter().copied())
+        .collect();
+    if relative.is_empty() {
+        return Some(".".to_string());
+    }
+    Some(relative.join(if windows { "\\" } else { "/" }))
+}
+
+/// Whether names in `dir` are case-sensitive
+///
+/// Windows directories are case-insensitive unless case sensitivity was
+/// turned on for them (`fsutil file setCaseSensitiveInfo`, which WSL does for
+/// directories it creates). On macOS the volume decides; other systems are
+/// case-sensitive. A path that doesn't exist is judged by its nearest
+/// existing ancestor.
+pub fn is_case_sensitive_dir<P: AsRef<Path>>(dir: P) -> bool {
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        let _ = dir;
+        true
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Storage::FileSystem::{
+            FileCaseSensitiveInfo, GetFileInformationByHandleEx, FILE_FLAG_BACKUP_SEMANTICS,
+            FILE_READ_ATTRIBUTES,
+        };
+        const FILE_CS_FLAG_CASE_SENSITIVE_DIR: u32 = 0x1;
+
+        let Some(existing) = dir.as_ref().ancestors().find(|p| p.is_dir()) else {
+            return false;
+        };
+        let Ok(handle) = std::fs::OpenOptions::new()
+            .access_mode(FILE_READ_ATTRIBUTES)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(existing)
+        else {
+            return false;
+        };
+        // FILE_CASE_SENSITIVE_INFO is a single ULONG of flags
+        let mut flags: u32 = 0;
+        let ok = unsafe {
+            GetFileInformationByHandleEx(
+                handle.as_raw_handle() as _,
+                FileCaseSensitiveInfo,
+                &mut flags as *mut u32 as *mut _,
+                std::mem::size_of::<u32>() as u32,
+            )
+        };
+        ok != 0 && flags & FILE_CS_FLAG_CASE_SENSITIVE_DIR != 0
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Some(existing) = dir.as_ref().ancestors().find(|p| p.is_dir()) else {
+            return false;
+        };
+        let Ok(path) = std::ffi::CString::new(existing.as_os_str().as_bytes()) else {
+            return false;
+        };
+        unsafe { libc::pathconf(path.as_ptr(), libc::_PC_CASE_SENSITIVE) == 1 }
+    }
+}
+
+/// Rename `from` to `to`, also when the names differ only in case
+///
+/// On a case-insensitive directory renaming `readme.md` to `README.md`
+/// directly fails or keeps the old casing, so such renames go through a
+/// temporary name. Other renames are plain renames. Files another process
+/// holds open are retried briefly.
+pub fn rename_case_safe<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> anyhow::Result<()> {
+    use super::lock_retry::{with_lock_retry, RetryPolicy};
+    use anyhow::Context;
+
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let rename = |from: &Path, to: &Path| {
+        with_lock_retry(|| std::fs::rename(from, to), &RetryPolicy::for_path(from))
+            .with_context(|| format!("Failed to rename {} to {}", from.display(), to.display()))
+    };
+    if from == to {
+        return Ok(());
+    }
+    if !differs_only_in_case(from, to) {
+        return rename(from, to);
+    }
+
+    let name = from
+        .file_name()
+        .with_context(|| format!("Cannot rename {}", from.display()))?;
+    let temp = from.with_file_name(format!(
+        ".{}.rename-{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    rename(from, &temp)?;
+    if let Err(e) = rename(&temp, to) {
+        let _ = std::fs::rename(&temp, from);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// The existing entry of `path`'s directory whose name matches `path`'s
+/// file name except for case, when no entry matches it exactly
+///
+/// This is how a file created as `readme.md` is found when asking for
+/// `README.md` on a case-insensitive volume, whose lookups would happily
+/// open it under either name. Only the last component is compared.
+pub fn find_case_variant<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = path.as_ref();
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let folded = name.to_string_lossy().to_lowercase();
+
+    let mut variant = None;
+    for entry in std::fs::read_dir(parent).ok()?.flatten() {
+        let entry_name = entry.file_name();
+        if entry_name == name {
+            return None;
+        }
+        if entry_name.to_string_lossy().to_lowercase() == folded {
+            variant = Some(path.with_file_name(entry_name));
+        }
+    }
+    variant
+}
+
+fn differs_only_in_case(a: &Path, b: &Path) -> bool {
+    a != b && a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Whether two paths name the same file, comparing each component the way
+/// the directory holding it does
+///
+/// Works lexically: `.`, `..` and trailing separators are resolved, but
+/// symlinks and junctions are not (see [`canonicalize_path`]). Short names
+/// are expanded first.
+pub fn paths_equal<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> bool {
+    components_after(
+        &expand_short_path(a).to_string_lossy(),
+        &expand_short_path(b).to_string_lossy(),
+        cfg!(target_os = "windows"),
+        |dir| is_case_sensitive_dir(dir),
+    ) == Some(0)
+}
+
+/// Whether `path` is `dir` or inside it, with the same case rules as
+/// [`paths_equal`]
+pub fn path_contains<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, path: Q) -> bool {
+    components_after(
+        &expand_short_path(dir).to_string_lossy(),
+        &expand_short_path(path).to_string_lossy(),
+        cfg!(target_os = "windows"),
+        |dir| is_case_sensitive_dir(dir),
+    )
+    .is_some()
+}
+
+/// Number of components `path` has beyond `prefix`, or `None` if `prefix`
+/// isn't `path` or one of its ancestors
+///
+/// Components differing only in case match unless `is_case_sensitive` says
+/// the directory holding them is case-sensitive. Roots (drive letters, UNC
+/// shares) always compare case-insensitively on Windows.
+fn components_after(
+    prefix: &str,
+    path: &str,
+    windows: bool,
+    is_case_sensitive: impl Fn(&Path) -> bool,
+) -> Option<usize> {
+    let (prefix, path) = if windows {
+        (strip_verbatim_prefix(prefix), strip_verbatim_prefix(path))
+    } else {
+        (prefix.to_string(), path.to_string())
+    };
+    let (prefix_root, prefix_components) = split_root(&prefix, windows);
+    let (root, components) = split_root(&path, windows);
+
+    let roots_match = if windows {
+        prefix_root.to_lowercase() == root.to_lowercase()
+    } else {
+        prefix_root == root
+    };
+    if !roots_match || prefix_components.len() > components.len() {
+        return None;
+    }
+
+    let mut parent = match root.as_str() {
+        "" => PathBuf::from("."),
+        "/" => PathBuf::from("/"),
+        _ => PathBuf::from(format!("{}{}", root, MAIN_SEPARATOR)),
+    };
+    for (expected, actual) in prefix_components.iter().zip(&components) {
+        if expected != actual
+            && (expected.to_lowercase() != actual.to_lowercase() || is_case_sensitive(&parent))
+        {
+            return None;
+        }
+        parent.push(actual);
+    }
+    Some(components.len() - prefix_components.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,4 +919,433 @@ mod tests {
             assert_eq!(result.to_str().unwrap(), "/home/user/file.txt");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_join_within() {
+        let root = Path::new("project");
+        assert_eq!(
+            join_within(root, "src/main.rs").unwrap(),
+            root.join("src/main.rs")
+        );
+        assert_eq!(join_within(root, "").unwrap(), root.join(""));
+        assert!(join_within(root, "../secrets").is_err());
+        assert!(join_within(root, "src/../../secrets").is_err());
+        assert!(join_within(root, "src\\..\\..\\secrets").is_err());
+        assert!(join_within(root, "/etc/passwd").is_err());
+        #[cfg(target_os = "windows")]
+        {
+            assert!(join_within(root, r"C:\Windows").is_err());
+            assert!(join_within(root, r"\\server\share").is_err());
+        }
+    }
+
+    #[test]
+    fn test_extended_length_form() {
+        assert_eq!(
+            extended_length_form(r"C:\Projects\app"),
+            r"\\?\C:\Projects\app"
+        );
+        assert_eq!(
+            extended_length_form(r"\\server\share\app"),
+            r"\\?\UNC\server\share\app"
+        );
+        assert_eq!(
+            extended_length_form(r"\\?\C:\Projects\app"),
+            r"\\?\C:\Projects\app"
+        );
+    }
+
+    #[test]
+    fn test_to_extended_length_path() {
+        #[cfg(target_os = "windows")]
+        {
+            let short = "C:\\Projects\\app";
+            assert_eq!(to_extended_length_path(short), PathBuf::from(short));
+
+            let long = format!("C:\\Projects\\{}index.js", "node_modules\\pkg\\".repeat(20));
+            let extended = to_extended_length_path(&long);
+            assert!(extended
+                .to_str()
+                .unwrap()
+                .starts_with("\\\\?\\C:\\Projects\\"));
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let long = format!("/projects/{}", "node_modules/pkg/".repeat(20));
+            assert_eq!(to_extended_length_path(&long), PathBuf::from(&long));
+        }
+    }
+
+    #[test]
+    fn test_strip_verbatim_prefix() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\Users\me\code"),
+            r"C:\Users\me\code"
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\code"),
+            r"\\server\share\code"
+        );
+        let volume = r"\\?\Volume{01234567-89ab-cdef-0123-456789abcdef}\code";
+        assert_eq!(strip_verbatim_prefix(volume), volume);
+        assert_eq!(strip_verbatim_prefix(r"C:\code"), r"C:\code");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_canonicalize_path_resolves_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("code");
+        let alias = dir.path().join("alias");
+        std::fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &alias).unwrap();
+
+        assert_eq!(
+            canonicalize_path(&alias).unwrap(),
+            canonicalize_path(&real).unwrap()
+        );
+        assert!(canonicalize_path(dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_expand_with() {
+        let home = Path::new("/home/me");
+        let lookup = |name: &str| match name {
+            "APPDATA" => Some("C:\\Users\\me\\AppData\\Roaming".to_string()),
+            "HOME" => Some("/home/me".to_string()),
+            "ProgramFiles(x86)" => Some("C:\\Program Files (x86)".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(expand_with("~/code", Some(home), lookup), "/home/me/code");
+        assert_eq!(expand_with("~", Some(home), lookup), "/home/me");
+        assert_eq!(
+            expand_with("~other/code", Some(home), lookup),
+            "~other/code"
+        );
+        assert_eq!(
+            expand_with("%APPDATA%\\opcode", Some(home), lookup),
+            "C:\\Users\\me\\AppData\\Roaming\\opcode"
+        );
+        assert_eq!(
+            expand_with("%ProgramFiles(x86)%\\app", Some(home), lookup),
+            "C:\\Program Files (x86)\\app"
+        );
+        assert_eq!(
+            expand_with("${HOME}/code", Some(home), lookup),
+            "/home/me/code"
+        );
+        assert_eq!(
+            expand_with("$HOME/code", Some(home), lookup),
+            "/home/me/code"
+        );
+
+        // Unknown variables and lone markers are kept
+        assert_eq!(expand_with("%NOPE%\\x", Some(home), lookup), "%NOPE%\\x");
+        assert_eq!(
+            expand_with("C:\\$Recycle.Bin", Some(home), lookup),
+            "C:\\$Recycle.Bin"
+        );
+        assert_eq!(
+            expand_with("\\\\server\\c$\\x", Some(home), lookup),
+            "\\\\server\\c$\\x"
+        );
+        assert_eq!(expand_with("50%", Some(home), lookup), "50%");
+    }
+
+    #[test]
+    fn test_expand_env_path_keeps_existing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let literal = dir.path().join("$HOME");
+        std::fs::create_dir(&literal).unwrap();
+
+        assert_eq!(expand_env_path(&literal.to_string_lossy()), literal);
+        assert_ne!(
+            expand_env_path(&dir.path().join("$HOME").join("x").to_string_lossy()),
+            literal.join("x")
+        );
+    }
+
+    #[test]
+    fn test_parse_user_path() {
+        assert!(parse_user_path("").is_err());
+        assert!(parse_user_path("  \"\" ").is_err());
+        assert!(parse_user_path("projects/app").is_err());
+
+        #[cfg(target_os = "windows")]
+        {
+            assert_eq!(
+                parse_user_path(" \"C:/Users/dev/app/\" ").unwrap(),
+                PathBuf::from(r"C:\Users\dev\app")
+            );
+            assert_eq!(
+                parse_user_path(r"\\?\C:\Users\dev\.\app").unwrap(),
+                PathBuf::from(r"C:\Users\dev\app")
+            );
+            assert!(parse_user_path(r"C:app").is_err());
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert_eq!(
+                parse_user_path(" \"/home/dev/app/\" ").unwrap(),
+                PathBuf::from("/home/dev/app")
+            );
+            assert_eq!(
+                parse_user_path("/home/dev/./app").unwrap(),
+                PathBuf::from("/home/dev/app")
+            );
+        }
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(parse_user_path("~/app").unwrap(), home.join("app"));
+        }
+    }
+
+    #[test]
+    fn test_validate_windows_filename() {
+        assert!(validate_windows_filename("timeline.json").is_ok());
+        assert!(validate_windows_filename("CONSOLE.md").is_ok());
+        assert!(validate_windows_filename("com10").is_ok());
+
+        for name in ["", "..", "a:b", "what?", "tab\there", "trailing.", "trailing "] {
+            assert!(validate_windows_filename(name).is_err(), "{:?}", name);
+        }
+        for name in ["CON", "nul.txt", "Com1", "LPT9.log", "aux .md", "COM¹"] {
+            let error = validate_windows_filename(name).unwrap_err().to_string();
+            assert!(error.contains("reserved"), "{}", error);
+        }
+        assert!(validate_windows_filename(&"a".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("src/main.rs.json"), "src_main.rs.json");
+        assert_eq!(sanitize_filename("a<b>c:d\"e|f?g*"), "a_b_c_d_e_f_g_");
+        assert_eq!(sanitize_filename("notes. . "), "notes");
+        assert_eq!(sanitize_filename("CON"), "CON_");
+        assert_eq!(sanitize_filename("nul.tar.gz"), "nul_.tar.gz");
+        assert_eq!(sanitize_filename("aux .md"), "aux_ .md");
+        assert_eq!(sanitize_filename("..."), "_");
+
+        let long = format!("{}.json", "é".repeat(200));
+        let sanitized = sanitize_filename(&long);
+        assert!(sanitized.len() <= MAX_FILENAME_LEN);
+        assert!(sanitized.ends_with(".json"));
+
+        let overlong = "x".repeat(300);
+        for name in ["CON", "a:b.", &overlong, "lpt1 .txt", "\u{7}bell"] {
+            assert!(validate_windows_filename(&sanitize_filename(name)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_relative_path_windows() {
+        let rel = |base, target| relative_path(base, target, true);
+        assert_eq!(
+            rel(r"C:\Projects\app", r"C:\Projects\app\src\main.rs").as_deref(),
+            Some(r"src\main.rs")
+        );
+        assert_eq!(
+            rel(r"C:\Projects\app", r"c:/projects/APP/src/main.rs").as_deref(),
+            Some(r"src\main.rs")
+        );
+        assert_eq!(
+            rel(r"C:\Projects\app\", r"C:\Projects\shared\lib.rs").as_deref(),
+            Some(r"..\shared\lib.rs")
+        );
+        assert_eq!(rel(r"C:\Projects\app", r"C:\Projects\app").as_deref(), Some("."));
+        assert_eq!(rel(r"C:\Projects\app", r"D:\Projects\app"), None);
+        assert_eq!(
+            rel(r"\\server\share\app", r"\\SERVER\Share\app\x.txt").as_deref(),
+            Some("x.txt")
+        );
+        assert_eq!(rel(r"\\server\share\app", r"\\server\other\app"), None);
+        assert_eq!(rel(r"\\server\share\app", r"C:\app"), None);
+        assert_eq!(
+            rel(r"C:\Projects\app", r"\\?\C:\Projects\app\.\src\..\README.md").as_deref(),
+            Some("README.md")
+        );
+        assert_eq!(rel(r"C:\Projects\app", r"src\main.rs"), None);
+    }
+
+    #[test]
+    fn test_make_relative() {
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert_eq!(
+                make_relative("/home/me/app", "/home/me/app/src/main.rs"),
+                Some(PathBuf::from("src/main.rs"))
+            );
+            assert_eq!(
+                make_relative("/home/me/app", "/home/me/lib/x.rs"),
+                Some(PathBuf::from("../lib/x.rs"))
+            );
+            // Case matters on Unix
+            assert_eq!(
+                make_relative("/home/me/app", "/home/me/App/x.rs"),
+                Some(PathBuf::from("../App/x.rs"))
+            );
+            assert_eq!(make_relative("/home/me/app", "src/main.rs"), None);
+            assert_eq!(
+                make_relative("app", "app/src/main.rs"),
+                Some(PathBuf::from("src/main.rs"))
+            );
+            assert_eq!(make_relative("../app", "src"), None);
+        }
+    }
+
+    #[test]
+    fn test_components_after() {
+        // C:\wsl is case-sensitive, everything else isn't
+        let sensitive = |dir: &Path| dir.to_string_lossy().to_lowercase().contains("wsl");
+        let after = |prefix, path| components_after(prefix, path, true, sensitive);
+
+        assert_eq!(after(r"C:\Projects\App", r"c:\projects\app\"), Some(0));
+        assert_eq!(after(r"C:\Projects\App", r"C:/Projects/App/src/x.rs"), Some(2));
+        assert_eq!(after(r"C:\Projects\App", r"\\?\C:\PROJECTS\app\src"), Some(1));
+        assert_eq!(after(r"C:\Projects\App", r"C:\Projects\Apple"), None);
+        assert_eq!(after(r"C:\Projects\App", r"D:\Projects\App"), None);
+        assert_eq!(after(r"C:\Projects\App\src", r"C:\Projects\App"), None);
+        assert_eq!(after(r"\\Server\Share\app", r"\\server\share\APP"), Some(0));
+
+        // Inside the case-sensitive directory only exact names match
+        assert_eq!(after(r"C:\wsl\Code", r"C:\WSL\Code"), Some(0));
+        assert_eq!(after(r"C:\wsl\Code", r"C:\wsl\code"), None);
+
+        let exact = |prefix, path| components_after(prefix, path, false, |_: &Path| true);
+        assert_eq!(exact("/home/me/app", "/home/me/app/"), Some(0));
+        assert_eq!(exact("/home/me/app", "/home/me/./lib/../app/x"), Some(1));
+        assert_eq!(exact("/home/me/app", "/home/me/App"), None);
+        assert_eq!(exact("/home/me/app", "home/me/app"), None);
+    }
+
+    #[test]
+    fn test_rename_case_safe() {
+        let dir = tempfile::tempdir().unwrap();
+        let lower = dir.path().join("readme.md");
+        let upper = dir.path().join("README.md");
+        std::fs::write(&lower, "# app").unwrap();
+
+        assert_eq!(
+            find_case_variant(&upper),
+            Some(upper.with_file_name("readme.md"))
+        );
+        assert_eq!(find_case_variant(&lower), None);
+        assert_eq!(find_case_variant(dir.path().join("other.md")), None);
+
+        rename_case_safe(&lower, &upper).unwrap();
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["README.md"]);
+        assert_eq!(find_case_variant(&upper), None);
+
+        rename_case_safe(&upper, dir.path().join("docs.md")).unwrap();
+        assert!(dir.path().join("docs.md").is_file());
+        assert!(rename_case_safe(&upper, &lower).is_err());
+    }
+
+    #[test]
+    fn test_paths_equal() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        assert!(paths_equal(&project, dir.path().join("project/")));
+        assert!(path_contains(&project, project.join("src").join("main.rs")));
+        assert!(!path_contains(project.join("src"), &project));
+
+        let upper = dir.path().join("PROJECT");
+        assert_eq!(
+            paths_equal(&project, &upper),
+            !is_case_sensitive_dir(dir.path())
+        );
+    }
+
+    #[test]
+    fn test_unc_share_root() {
+        assert_eq!(
+            unc_share_root(r"\\nas\projects\app\src").as_deref(),
+            Some(r"\\nas\projects")
+        );
+        assert_eq!(
+            unc_share_root(r"\\?\UNC\nas\projects\app").as_deref(),
+            Some(r"\\nas\projects")
+        );
+        assert_eq!(
+            unc_share_root("//nas/projects").as_deref(),
+            Some(r"\\nas\projects")
+        );
+        assert_eq!(unc_share_root(r"\\nas"), None);
+        assert_eq!(unc_share_root(r"\\?\C:\projects"), None);
+        assert_eq!(unc_share_root(r"\\.\pipe\name"), None);
+        assert_eq!(unc_share_root(r"C:\projects"), None);
+        assert_eq!(unc_share_root("/home/user/projects"), None);
+    }
+
+    #[test]
+    fn test_probe_with_timeout() {
+        let timeout = Duration::from_millis(100);
+        assert!(probe_with_timeout(r"\\nas\ok", timeout, |_| Ok(())).is_ok());
+
+        let refused = probe_with_timeout(r"\\nas\gone", timeout, |_| {
+            Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        assert!(refused.unwrap_err().to_string().contains(r"\\nas\gone"));
+
+        let hung = probe_with_timeout(r"\\nas\dead", timeout, |_| {
+            std::thread::sleep(Duration::from_secs(5));
+            Ok(())
+        });
+        assert!(hung.unwrap_err().to_string().contains("did not respond"));
+
+        assert!(check_unc_available("relative/path", timeout).is_ok());
+    }
+
+    #[test]
+    fn test_has_short_component() {
+        assert!(has_short_component(r"C:\PROGRA~1\Claude"));
+        assert!(has_short_component(r"C:\Users\JOHND~12\app"));
+        assert!(has_short_component("C:/Users/RUNNER~1/AppData/Local/Temp/BUILD~1.LOG"));
+        assert!(!has_short_component(r"C:\Program Files\Claude"));
+        assert!(!has_short_component("~/projects/app"));
+        assert!(!has_short_component(r"C:\backups\notes~old"));
+        assert!(!has_short_component(r"C:\a-much-longer-name~1"));
+    }
+
+    #[test]
+    fn test_expand_short_path() {
+        assert_eq!(
+            expand_short_path(r"C:\Program Files\Claude"),
+            PathBuf::from(r"C:\Program Files\Claude")
+        );
+
+        #[cfg(target_os = "windows")]
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let long = canonicalize_path(dir.path()).unwrap();
+            let project = long.join("long project name");
+            std::fs::create_dir(&project).unwrap();
+
+            // Short names can be disabled per volume; only check when present
+            let wide: Vec<u16> = {
+                use std::os::windows::ffi::OsStrExt;
+                project.as_os_str().encode_wide().chain(Some(0)).collect()
+            };
+            let mut buffer = vec![0u16; 512];
+            let len = unsafe {
+                windows_sys::Win32::Storage::FileSystem::GetShortPathNameW(
+                    wide.as_ptr(),
+                    buffer.as_mut_ptr(),
+                    buffer.len() as u32,
+                )
+            };
+            buffer.truncate(len as usize);
+            let short = PathBuf::from(String::from_utf16_lossy(&buffer));
+            if len > 0 && short != project {
+                assert_eq!(expand_short_path(&short), project);
+                assert_eq!(expand_short_path(short.join("src")), project.join("src"));
+                assert!(paths_equal(&short, &project));
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Moving files and directories to the platform's trash
+//!
+//! [`delete`] is how opcode removes anything the user may want back:
+//! sessions, checkpoints, slash commands and project files a checkpoint
+//! restore removes. It uses the Recycle Bin on Windows, `NSFileManager` on
+//! macOS, so Finder's "Put Back" works and files on other volumes go to
+//! that volume's trash, and `gio trash` on Linux, which also knows about
+//! per-volume trash directories. Without `gio`, files go to the
+//! freedesktop.org home trash, or the `.Trash-$uid` directory of their
+//! volume when that is another file system. [`delete_all`] trashes many
+//! paths with one call where the platform allows it.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use super::lock_retry::{with_lock_retry, RetryPolicy};
+
+/// Move the file or directory at `path` to the trash
+///
+/// A path that doesn't exist is already deleted. On Windows, paths the
+/// Recycle Bin doesn't accept, such as those on network shares, are
+/// deleted permanently, as Explorer does. Elsewhere, files on a volume
+/// without a usable trash directory are deleted permanently too.
+pub fn delete<P: AsRef<Path>>(path: P) -> Result<()> {
+    let Some(path) = existing_absolute(path.as_ref())? else {
+        return Ok(());
+    };
+    delete_one(&path)
+}
+
+/// Move every path in `paths` to the trash, returning the ones that failed
+///
+/// Paths are handed to the platform in one batch where it accepts them
+/// that way, so restoring a checkpoint doesn't start one `gio` process per
+/// file. When the batch fails the paths are retried one by one, so a
+/// single bad path only fails itself.
+pub fn delete_all<P: AsRef<Path>>(paths: &[P]) -> Vec<(PathBuf, anyhow::Error)> {
+    let mut failures = Vec::new();
+    let mut batch = Vec::new();
+    for path in paths {
+        match existing_absolute(path.as_ref()) {
+            Ok(Some(path)) => batch.push(path),
+            Ok(None) => {}
+            Err(e) => failures.push((path.as_ref().to_path_buf(), e)),
+        }
+    }
+    if batch.is_empty() {
+        return failures;
+    }
+
+    match move_all_to_trash(&batch) {
+        Ok(()) => log::debug!("Moved {} paths to the trash", batch.len()),
+        Err(e) => {
+            log::debug!("Trashing {} paths at once failed: {:#}", batch.len(), e);
+            failures.extend(
+                batch
+                    .into_iter()
+                    .filter(|path| path.symlink_metadata().is_ok())
+                    .filter_map(|path| delete_one(&path).err().map(|e| (path, e))),
+            );
+        }
+    }
+    failures
+}
+
+/// `path` made absolute, or `None` when it doesn't exist
+fn existing_absolute(path: &Path) -> Result<Option<PathBuf>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+    std::path::absolute(path)
+        .map(Some)
+        .with_context(|| format!("Failed to resolve {}", path.display()))
+}
+
+/// Trash one existing absolute path, retrying while it is locked
+fn delete_one(path: &Path) -> Result<()> {
+    with_lock_retry(|| move_to_trash(path), &RetryPolicy::for_path(path))
+        .with_context(|| format!("Failed to move {} to the trash", path.display()))?;
+    log::debug!("Moved {} to the trash", path.display());
+    Ok(())
+}
+
+/// Recycle `paths` with one `SHFileOperationW` call
+#[cfg(target_os = "windows")]
+fn recycle(paths: &[PathBuf]) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::UI::Shell::{
+        SHFileOperationW, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, FO_DELETE,
+        SHFILEOPSTRUCTW,
+    };
+
+    // pFrom is a list of null-terminated paths ending with an extra terminator
+    let mut from: Vec<u16> = Vec::new();
+    for path in paths {
+        from.extend(path.as_os_str().encode_wide());
+        from.push(0);
+    }
+    from.push(0);
+    let mut operation = SHFILEOPSTRUCTW {
+        hwnd: std::ptr::null_mut(),
+        wFunc: FO_DELETE,
+        pFrom: from.as_ptr(),
+        pTo: std::ptr::null(),
+        fFlags: (FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_NOERRORUI | FOF_SILENT) as u16,
+        fAnyOperationsAborted: 0,
+        hNameMappings: std::ptr::null_mut(),
+        lpszProgressTitle: std::ptr::null(),
+    };
+    let code = unsafe { SHFileOperationW(&mut operation) };
+    // Below 0x71 the result is a Win32 error, such as a sharing violation
+    // the caller retries; above it one of the shell's own DE_* codes
+    match code {
+        0 if operation.fAnyOperationsAborted != 0 => {
+            Err(std::io::Error::other("The operation was aborted"))
+        }
+        0 => Ok(()),
+        code if code < 0x71 => Err(std::io::Error::from_raw_os_error(code)),
+        code => Err(std::io::Error::other(format!(
+            "SHFileOperation failed with code {:#x}",
+            code
+        ))),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn move_to_trash(path: &Path) -> std::io::Result<()> {
+    recycle(&[path.to_path_buf()])
+}
+
+#[cfg(target_os = "windows")]
+fn move_all_to_trash(paths: &[PathBuf]) -> Result<()> {
+    recycle(paths)?;
+    Ok(())
+}
+
+/// Trash `path` with `-[NSFileManager trashItemAtURL:resultingItemURL:error:]`
+#[cfg(target_os = "macos")]
+fn move_to_trash(path: &Path) -> std::io::Result<()> {
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::{NSAutoreleasePool, NSString};
+    use objc::runtime::{BOOL, YES};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+
+    let path = path
+        .to_str()
+        .ok_or_else(|| std::io::Error::other("Path is not valid UTF-8"))?;
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let ns_path: id = NSString::alloc(nil).init_str(path).autorelease();
+        let url: id = msg_send![class!(NSURL), fileURLWithPath: ns_path];
+        let manager: id = msg_send![class!(NSFileManager), defaultManager];
+
+        let mut error: id = nil;
+        let trashed: BOOL =
+            msg_send![manager, trashItemAtURL: url resultingItemURL: nil error: &mut error];
+        let result = if trashed == YES {
+            Ok(())
+        } else if error == nil {
+            Err(std::io::Error::other(
+                "NSFileManager refused to trash the item",
+            ))
+        } else {
+            let description: id = msg_send![error, localizedDescription];
+            let utf8: *const c_char = msg_send![description, UTF8String];
+            Err(std::io::Error::other(
+                CStr::from_ptr(utf8).to_string_lossy().into_owned(),
+            ))
+        };
+        pool.drain();
+        result
+    }
+}
+
+/// `NSFileManager` is called in-process, so there is nothing to batch
+#[cfg(target_os = "macos")]
+fn move_all_to_trash(paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        move_to_trash(path)?;
+    }
+    Ok(())
+}
+
+/// Most paths passed to one `gio trash` call, to stay clear of argument
+/// length limits
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const GIO_BATCH_SIZE: usize = 200;
+
+/// Trash `paths` with `gio trash`, which also knows per-volume trash
+/// directories
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn gio_trash(paths: &[PathBuf]) -> Result<()> {
+    use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
+
+    let gio = which::which("gio").context("gio is not installed")?;
+    for chunk in paths.chunks(GIO_BATCH_SIZE) {
+        let output = run_with_timeout_blocking(
+            std::process::Command::new(&gio)
+                .args(["trash", "--"])
+                .args(chunk),
+            DEFAULT_COMMAND_TIMEOUT,
+        )
+        .context("Failed to run gio trash")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "gio trash failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn move_all_to_trash(paths: &[PathBuf]) -> Result<()> {
+    gio_trash(paths)
+}
+
+/// Trash `path` with `gio trash`, or in the freedesktop.org trash of its
+/// volume when `gio` is missing or fails
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn move_to_trash(path: &Path) -> std::io::Result<()> {
+    if gio_trash(&[path.to_path_buf()]).is_ok() {
+        return Ok(());
+    }
+    match move_to_home_trash(path) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            // The home trash is on another file system than `path`
+            if let Err(e) = move_to_volume_trash(path) {
+                log::warn!(
+                    "No usable trash for {}, deleting it permanently: {}",
+                    path.display(),
+                    e
+                );
+                return remove_permanently(path);
+            }
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Move `path` to the freedesktop.org home trash
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn move_to_home_trash(path: &Path) -> std::io::Result<()> {
+    let trash = dirs::data_dir()
+        .ok_or_else(|| std::io::Error::other("Could not find the data directory"))?
+        .join("Trash");
+    move_to_trash_dir(path, &trash)
+}
+
+/// Move `path` to `$topdir/.Trash-$uid` of the volume it is on, as the
+/// freedesktop.org spec allows when the home trash is on another volume
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn move_to_volume_trash(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    let device = path.symlink_metadata()?.dev();
+    let mut top = path.parent().unwrap_or(path);
+    while let Some(parent) = top.parent() {
+        if parent.metadata()?.dev() != device {
+            break;
+        }
+        top = parent;
+    }
+
+    let trash = top.join(format!(".Trash-{}", unsafe { libc::getuid() }));
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&trash)?;
+    move_to_trash_dir(path, &trash)
+}
+
+/// Move `path` into the trash directory `trash`, with a `.trashinfo` file
+/// so file managers can restore it
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn move_to_trash_dir(path: &Path, trash: &Path) -> std::io::Result<()> {
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    std::fs::create_dir_all(&files)?;
+    std::fs::create_dir_all(&info)?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other("Cannot trash a root directory"))?
+        .to_string_lossy();
+    let name = unused_name(&name, |candidate| {
+        files.join(candidate).exists() || info.join(format!("{}.trashinfo", candidate)).exists()
+    });
+    let info_file = info.join(format!("{}.trashinfo", name));
+    std::fs::write(
+        &info_file,
+        trash_info(path, chrono::Local::now().naive_local()),
+    )?;
+    if let Err(e) = std::fs::rename(path, files.join(&name)) {
+        let _ = std::fs::remove_file(&info_file);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Delete `path` for good, for volumes without a trash directory
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn remove_permanently(path: &Path) -> std::io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// `name`, or `name (2)`, `name (3)`, ... if `taken` says it's in use
+#[cfg_attr(any(target_os = "windows", target_os = "macos"), allow(dead_code))]
+fn unused_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(name) {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    (2..)
+        .map(|n| format!("{} ({}){}", stem, n, extension))
+        .find(|candidate| !taken(candidate))
+        .expect("unbounded range")
+}
+
+/// Contents of a freedesktop.org `.trashinfo` file for `path`
+#[cfg_attr(any(target_os = "windows", target_os = "macos"), allow(dead_code))]
+fn trash_info(path: &Path, deleted_at: chrono::NaiveDateTime) -> String {
+    // The path is URL-encoded, keeping `/` and unreserved characters
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encoded,
+        deleted_at.format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_name() {
+        assert_eq!(unused_name("session.jsonl", |_| false), "session.jsonl");
+        let taken = ["session.jsonl", "session (2).jsonl"];
+        assert_eq!(
+            unused_name("session.jsonl", |name| taken.contains(&name)),
+            "session (3).jsonl"
+        );
+        assert_eq!(
+            unused_name(".timelines", |name| name == ".timelines"),
+            ".timelines (2)"
+        );
+    }
+
+    #[test]
+    fn test_trash_info() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 9)
+            .unwrap()
+            .and_hms_opt(14, 5, 0)
+            .unwrap();
+        assert_eq!(
+            trash_info(Path::new("/home/dev/my project/a#1.jsonl"), date),
+            "[Trash Info]\nPath=/home/dev/my%20project/a%231.jsonl\nDeletionDate=2024-03-09T14:05:00\n"
+        );
+    }
+
+    #[test]
+    fn test_delete_missing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        delete(dir.path().join("missing.jsonl")).unwrap();
+    }
+
+    #[test]
+    fn test_delete_all_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = [dir.path().join("a.jsonl"), dir.path().join("b.jsonl")];
+        assert!(delete_all(&missing).is_empty());
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn test_move_to_trash_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = dir.path().join(".Trash-1000");
+        let file = dir.path().join("notes.md");
+        std::fs::write(&file, "x").unwrap();
+
+        move_to_trash_dir(&file, &trash).unwrap();
+        assert!(!file.exists());
+        assert!(trash.join("files").join("notes.md").exists());
+        let info = std::fs::read_to_string(trash.join("info").join("notes.md.trashinfo")).unwrap();
+        assert!(info.contains("notes.md"));
+    }
+
+    #[test]
+    #[ignore] // Integration test - moves a file to the user's trash
+    fn test_delete_to_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("opcode-trash-test.txt");
+        std::fs::write(&file, "x").unwrap();
+        delete(&file).unwrap();
+        assert!(!file.exists());
+    }
+}
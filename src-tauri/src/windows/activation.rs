@@ -0,0 +1,181 @@
+//! Bringing the existing window to the front when Opcode is launched again
+//!
+//! A second launch forwards its arguments to the running instance and exits,
+//! and the running instance should then come to the foreground. Every
+//! platform restricts which process may take focus, so the launch that has
+//! it, the second one, must hand it over:
+//!
+//! - **Windows**: the second launch calls `AllowSetForegroundWindow`, so the
+//!   running instance's `SetForegroundWindow` is honoured. Otherwise the
+//!   taskbar button flashes.
+//! - **Wayland**: the launcher gives the second launch an xdg-activation
+//!   token in `XDG_ACTIVATION_TOKEN`. It is passed on through a file in
+//!   `$XDG_RUNTIME_DIR`, as the forwarded arguments can't carry it, and GTK
+//!   activates the window with it.
+//! - **X11**: the startup notification ID in `DESKTOP_STARTUP_ID` is passed
+//!   on the same way, and GTK sends `_NET_ACTIVE_WINDOW` with the timestamp
+//!   it carries, which window managers accept as recent user activity.
+//!
+//! Call [`prepare_activation`] at startup, before the single-instance check,
+//! and [`activate_window`] in the running instance.
+
+use anyhow::Result;
+
+/// Hand the right to take the foreground to the instance this launch
+/// forwards to
+///
+/// Called on every launch, as only the single-instance check finds out
+/// whether one is running. When none is, the primary launch passes the
+/// right to nobody in particular and it lapses with the next user input.
+pub fn prepare_activation() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use winapi::um::winuser::{AllowSetForegroundWindow, ASFW_ANY};
+        AllowSetForegroundWindow(ASFW_ANY);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Err(e) = linux::save_activation_token() {
+        log::warn!("Failed to pass on the activation token: {}", e);
+    }
+}
+
+/// Restore, show and focus `window` after another launch forwarded to it
+pub fn activate_window(window: &tauri::WebviewWindow) -> Result<()> {
+    window.unminimize()?;
+    window.show()?;
+    bring_to_front(window)
+}
+
+#[cfg(target_os = "windows")]
+fn bring_to_front(window: &tauri::WebviewWindow) -> Result<()> {
+    use winapi::shared::windef::HWND;
+    use winapi::um::winuser::{
+        FlashWindowEx, IsIconic, SetForegroundWindow, ShowWindow, FLASHWINFO, FLASHW_ALL,
+        FLASHW_TIMERNOFG, SW_RESTORE,
+    };
+
+    let hwnd = window.hwnd()?.0 as HWND;
+    unsafe {
+        if IsIconic(hwnd) != 0 {
+            ShowWindow(hwnd, SW_RESTORE);
+        }
+        if SetForegroundWindow(hwnd) == 0 {
+            // Denied by the foreground lock; ask for attention instead
+            let mut flash = FLASHWINFO {
+                cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
+                hwnd,
+                dwFlags: FLASHW_ALL | FLASHW_TIMERNOFG,
+                uCount: 0,
+                dwTimeout: 0,
+            };
+            FlashWindowEx(&mut flash);
+            log::debug!("SetForegroundWindow was denied; flashing the taskbar button");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn bring_to_front(window: &tauri::WebviewWindow) -> Result<()> {
+    let token = linux::take_activation_token();
+    let target = window.clone();
+    // GTK may only be used from the main thread
+    window.run_on_main_thread(move || {
+        if let Err(e) = linux::present(&target, token.as_deref()) {
+            log::warn!("Failed to activate the window: {}", e);
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn bring_to_front(window: &tauri::WebviewWindow) -> Result<()> {
+    // Also activates the app on macOS
+    window.set_focus()?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::{Context, Result};
+    use gtk::prelude::*;
+    use std::path::PathBuf;
+
+    /// Where a second launch leaves its activation token
+    fn token_path() -> Option<PathBuf> {
+        // Only the per-user runtime directory is private enough for tokens
+        dirs::runtime_dir().map(|dir| dir.join("opcode-activation-token"))
+    }
+
+    /// Save this launch's activation token for the running instance,
+    /// removing any older one so it isn't used for this launch
+    pub(super) fn save_activation_token() -> Result<()> {
+        let Some(path) = token_path() else {
+            return Ok(());
+        };
+        let token = ["XDG_ACTIVATION_TOKEN", "DESKTOP_STARTUP_ID"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|token| !token.is_empty());
+        match token {
+            Some(token) => crate::utils::atomic_write::atomic_write(&path, token)
+                .with_context(|| format!("Failed to write {}", path.display())),
+            None => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// The token the latest forwarding launch left, if any
+    pub(super) fn take_activation_token() -> Option<String> {
+        let path = token_path()?;
+        let token = std::fs::read_to_string(&path).ok()?;
+        let _ = std::fs::remove_file(&path);
+        Some(token.trim().to_string()).filter(|token| !token.is_empty())
+    }
+
+    /// Present the window, activating it with `token` when there is one
+    pub(super) fn present(window: &tauri::WebviewWindow, token: Option<&str>) -> Result<()> {
+        let gtk_window = window.gtk_window()?;
+        let Some(token) = token else {
+            // On X11, GTK uses the X server's time for `_NET_ACTIVE_WINDOW`
+            gtk_window.present();
+            return Ok(());
+        };
+
+        // On Wayland GTK passes the startup ID to `xdg_activation_v1.activate`
+        gtk_window.set_startup_id(token);
+        match super::startup_id_timestamp(token) {
+            Some(timestamp) => gtk_window.present_with_time(timestamp),
+            None => gtk_window.present(),
+        }
+        Ok(())
+    }
+}
+
+/// The X server timestamp a startup notification ID ends with
+///
+/// IDs look like `launcher-1234-host-opcode-0_TIME5678`; xdg-activation
+/// tokens have no timestamp.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn startup_id_timestamp(startup_id: &str) -> Option<u32> {
+    let (_, timestamp) = startup_id.rsplit_once("_TIME")?;
+    timestamp.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_id_timestamp() {
+        assert_eq!(
+            startup_id_timestamp("gnome-shell-1234-host-opcode-0_TIME5678"),
+            Some(5678)
+        );
+        assert_eq!(startup_id_timestamp("a1b2c3d4-e5f6"), None);
+        assert_eq!(startup_id_timestamp("launcher_TIMEsoon"), None);
+    }
+}
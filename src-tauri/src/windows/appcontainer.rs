@@ -0,0 +1,496 @@
+//! AppContainer sandbox for agent and tool subprocesses
+//!
+//! An AppContainer is the isolation Windows uses for Store apps: the process
+//! runs as the current user but with an extra container SID and only the
+//! capabilities it was given, so it can open files and directories only when
+//! their ACL grants the container (or `ALL APPLICATION PACKAGES`) access, and
+//! it can only use the network when granted a network capability. Unlike the
+//! Low integrity mode in [`super::integrity`], this also blocks reading the
+//! user's files.
+//!
+//! [`AppContainerLauncher`] creates the container profile on first use,
+//! grants the container access to the configured paths through the ACL APIs
+//! in [`super::permissions`] and starts processes inside the container. The
+//! grants are [`PathGrants`], which take the entries off the user's files
+//! again when dropped, so nothing outlives the run that needed it.
+//!
+//! # Example
+//! ```rust
+//! use crate::windows::appcontainer::{AppContainerCapability, AppContainerLauncher};
+//!
+//! fn run_sandboxed(project: &str, node_dir: &str) -> anyhow::Result<()> {
+//!     let mut launcher = AppContainerLauncher::new("opcode.agent-sandbox");
+//!     launcher.capabilities.push(AppContainerCapability::InternetClient);
+//!     launcher.readable_paths.push(node_dir.into());
+//!     launcher.writable_paths.push(project.into());
+//!
+//!     let node = r"C:\Program Files\nodejs\node.exe";
+//!     let child = launcher.launch(node, &["script.js"], Some(project.as_ref()))?;
+//!     // Dropping the child takes the container's access to the paths away
+//!     println!("Sandboxed run exited with {:?}", child.wait(None)?);
+//!     Ok(())
+//! }
+//! ```
+
+use super::integrity::{ChildStartup, ChildStdio, ContainedChild};
+use super::permissions::{apply_acl_changes, diff_acl, get_file_acl, AceEntry, AceType};
+use super::{to_wide_string, LocalSids};
+use crate::utils::cmdline::join_windows_args;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::shared::sddl::{ConvertSidToStringSidW, ConvertStringSidToSidW};
+use winapi::shared::winerror::{ERROR_ALREADY_EXISTS, ERROR_FILE_NOT_FOUND};
+use winapi::um::processthreadsapi::{CreateProcessW, PROCESS_INFORMATION};
+use winapi::um::securitybaseapi::FreeSid;
+use winapi::um::userenv::{
+    CreateAppContainerProfile, DeleteAppContainerProfile, DeriveAppContainerSidFromAppContainerName,
+};
+use winapi::um::winbase::LocalFree;
+use winapi::um::winnt::{
+    DELETE, FILE_GENERIC_EXECUTE, FILE_GENERIC_READ, FILE_GENERIC_WRITE, HRESULT, PSID,
+    SECURITY_CAPABILITIES, SE_GROUP_ENABLED, SID_AND_ATTRIBUTES,
+};
+
+/// `ProcThreadAttributeSecurityCapabilities`, which winapi does not define
+const PROC_THREAD_ATTRIBUTE_SECURITY_CAPABILITIES: usize = 0x0002_0009;
+
+/// Capabilities that can be granted to the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppContainerCapability {
+    /// Outbound connections to the internet
+    InternetClient,
+    /// Outbound and inbound connections to the internet
+    InternetClientServer,
+    /// Connections within the home or work network, including localhost servers
+    PrivateNetworkClientServer,
+}
+
+impl AppContainerCapability {
+    /// Well-known SID of the capability
+    pub fn sid(&self) -> &'static str {
+        match self {
+            AppContainerCapability::InternetClient => "S-1-15-3-1",
+            AppContainerCapability::InternetClientServer => "S-1-15-3-2",
+            AppContainerCapability::PrivateNetworkClientServer => "S-1-15-3-3",
+        }
+    }
+}
+
+/// Starts processes inside an AppContainer
+///
+/// # Fields
+/// - `name`: Profile name, unique per sandbox (e.g. `opcode.agent-sandbox`)
+/// - `display_name`: Name shown in Windows tools
+/// - `capabilities`: Capabilities granted at launch; none means no network
+/// - `readable_paths`: Files and directories the container may read and execute
+/// - `writable_paths`: Files and directories the container may also modify
+/// - `stdio`: Which of opcode's handles the processes get
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppContainerLauncher {
+    pub name: String,
+    pub display_name: String,
+    pub capabilities: Vec<AppContainerCapability>,
+    pub readable_paths: Vec<PathBuf>,
+    pub writable_paths: Vec<PathBuf>,
+    pub stdio: ChildStdio,
+}
+
+/// `HRESULT_FROM_WIN32`
+fn hresult_from_win32(code: DWORD) -> HRESULT {
+    ((code & 0xFFFF) | 0x8007_0000) as HRESULT
+}
+
+/// The error an HRESULT stands for
+///
+/// Win32 errors wrapped by `HRESULT_FROM_WIN32` are unwrapped with
+/// `HRESULT_CODE`, so they read like any other OS error; other HRESULTs
+/// keep their hexadecimal form.
+fn hresult_error(result: HRESULT) -> std::io::Error {
+    if (result as u32) & 0xFFFF_0000 == 0x8007_0000 {
+        std::io::Error::from_raw_os_error(result & 0xFFFF)
+    } else {
+        std::io::Error::other(format!("HRESULT {:#010X}", result as u32))
+    }
+}
+
+/// Access granted to a container by [`AppContainerLauncher::grant_path_access`],
+/// taken away again when dropped
+///
+/// Only the entries the grant added are removed, so access the container
+/// already had stays as it was.
+#[must_use = "the access is taken away again when the grants are dropped"]
+#[derive(Debug, Default)]
+pub struct PathGrants {
+    granted: Vec<(String, AceEntry)>,
+}
+
+impl Drop for PathGrants {
+    fn drop(&mut self) {
+        for (path, grant) in self.granted.drain(..).rev() {
+            if let Err(e) = revoke_grant(&path, &grant) {
+                warn!(
+                    "Failed to take the sandbox's access to {} away: {:#}",
+                    path, e
+                );
+            }
+        }
+    }
+}
+
+/// Remove the explicit entries matching `grant` from the ACL of `path`
+fn revoke_grant(path: &str, grant: &AceEntry) -> Result<()> {
+    let current = get_file_acl(path)?;
+    let desired: Vec<AceEntry> = current
+        .iter()
+        .filter(|entry| entry.inherited || !entry.same_grant(grant))
+        .cloned()
+        .collect();
+    apply_acl_changes(path, &diff_acl(&current, &desired))?;
+    debug!("Took the sandbox's access to {} away", path);
+    Ok(())
+}
+
+/// A process started by [`AppContainerLauncher::launch`]
+///
+/// Holds the container's [`PathGrants`]; keep it until the process exits.
+pub struct SandboxedChild {
+    child: ContainedChild,
+    _grants: PathGrants,
+}
+
+impl Deref for SandboxedChild {
+    type Target = ContainedChild;
+
+    fn deref(&self) -> &ContainedChild {
+        &self.child
+    }
+}
+
+/// A SID allocated by the AppContainer APIs, freed with `FreeSid` on drop
+struct ContainerSid(PSID);
+
+impl Drop for ContainerSid {
+    fn drop(&mut self) {
+        unsafe {
+            FreeSid(self.0);
+        }
+    }
+}
+
+/// String form of a container SID
+fn sid_to_string(sid: &ContainerSid) -> Result<String> {
+    unsafe {
+        let mut sid_string: *mut u16 = ptr::null_mut();
+        if ConvertSidToStringSidW(sid.0, &mut sid_string) == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to convert the AppContainer SID");
+        }
+        let mut len = 0;
+        while *sid_string.add(len) != 0 {
+            len += 1;
+        }
+        let text = String::from_utf16_lossy(std::slice::from_raw_parts(sid_string, len));
+        LocalFree(sid_string as _);
+        Ok(text)
+    }
+}
+
+impl AppContainerLauncher {
+    /// A launcher for the container `name` without capabilities or path grants
+    pub fn new(name: &str) -> Self {
+        AppContainerLauncher {
+            name: name.to_string(),
+            display_name: format!("Opcode sandbox ({})", name),
+            capabilities: Vec::new(),
+            readable_paths: Vec::new(),
+            writable_paths: Vec::new(),
+            stdio: ChildStdio::Detached,
+        }
+    }
+
+    /// Create the container profile if it does not exist yet
+    fn ensure_profile(&self) -> Result<ContainerSid> {
+        let wide_name = to_wide_string(&self.name);
+        let wide_display_name = to_wide_string(&self.display_name);
+        let mut sid: PSID = ptr::null_mut();
+
+        unsafe {
+            let result = CreateAppContainerProfile(
+                wide_name.as_ptr(),
+                wide_display_name.as_ptr(),
+                wide_display_name.as_ptr(),
+                ptr::null_mut(),
+                0,
+                &mut sid,
+            );
+            if result == hresult_from_win32(ERROR_ALREADY_EXISTS) {
+                let result =
+                    DeriveAppContainerSidFromAppContainerName(wide_name.as_ptr(), &mut sid);
+                if result < 0 {
+                    return Err(hresult_error(result))
+                        .with_context(|| format!("Failed to look up AppContainer {}", self.name));
+                }
+            } else if result < 0 {
+                return Err(hresult_error(result))
+                    .with_context(|| format!("Failed to create AppContainer {}", self.name));
+            } else {
+                info!("Created AppContainer profile {}", self.name);
+            }
+        }
+
+        Ok(ContainerSid(sid))
+    }
+
+    /// The container's SID in string form, creating the profile if needed
+    pub fn container_sid(&self) -> Result<String> {
+        sid_to_string(&self.ensure_profile()?)
+    }
+
+    /// Grant the container access to the configured paths until the
+    /// returned [`PathGrants`] are dropped
+    ///
+    /// Grants are added next to the existing entries and only when missing.
+    /// If a path fails, the grants made so far are removed again.
+    pub fn grant_path_access(&self, container_sid: &str) -> Result<PathGrants> {
+        let read = FILE_GENERIC_READ | FILE_GENERIC_EXECUTE;
+        let write = read | FILE_GENERIC_WRITE | DELETE;
+        let grants = self
+            .readable_paths
+            .iter()
+            .map(|path| (path, read))
+            .chain(self.writable_paths.iter().map(|path| (path, write)));
+
+        let mut granted = PathGrants::default();
+        for (path, access_mask) in grants {
+            let mut grant = AceEntry::new(container_sid, access_mask, AceType::Allow);
+            if path.is_dir() {
+                grant.object_inherit = true;
+                grant.container_inherit = true;
+            }
+
+            let path = path.to_string_lossy().to_string();
+            let current = get_file_acl(&path)?;
+            let mut desired = current.clone();
+            desired.push(grant.clone());
+            let changes = diff_acl(&current, &desired);
+            if changes.is_empty() {
+                continue;
+            }
+            apply_acl_changes(&path, &changes)
+                .with_context(|| format!("Failed to grant the sandbox access to {}", path))?;
+            granted.granted.push((path, grant));
+        }
+
+        Ok(granted)
+    }
+
+    /// Start `program` inside the container
+    ///
+    /// Creates the profile and grants path access first; the access lasts
+    /// as long as the returned child. Programs outside the Windows and
+    /// Program Files directories (which every container can read) need
+    /// their directory in `readable_paths`. The child gets the handles
+    /// `stdio` asks for and no console.
+    ///
+    /// # Arguments
+    /// * `program` - Executable to run
+    /// * `args` - Arguments, quoted for the Windows command line as needed
+    /// * `working_dir` - Working directory, or `None` to use opcode's
+    pub fn launch(
+        &self,
+        program: &str,
+        args: &[&str],
+        working_dir: Option<&Path>,
+    ) -> Result<SandboxedChild> {
+        let container = self.ensure_profile()?;
+        let grants = self.grant_path_access(&sid_to_string(&container)?)?;
+        let child = self.spawn_in(&container, program, args, working_dir)?;
+        Ok(SandboxedChild {
+            child,
+            _grants: grants,
+        })
+    }
+
+    /// Start `program` inside the container without granting path access
+    ///
+    /// For a process whose grants someone else holds, like the Claude
+    /// wrapper, which opcode may kill before it could remove them.
+    pub fn spawn(
+        &self,
+        program: &str,
+        args: &[&str],
+        working_dir: Option<&Path>,
+    ) -> Result<ContainedChild> {
+        let container = self.ensure_profile()?;
+        self.spawn_in(&container, program, args, working_dir)
+    }
+
+    fn spawn_in(
+        &self,
+        container: &ContainerSid,
+        program: &str,
+        args: &[&str],
+        working_dir: Option<&Path>,
+    ) -> Result<ContainedChild> {
+        let mut command_line: Vec<&str> = vec![program];
+        command_line.extend_from_slice(args);
+        let mut wide_command_line = to_wide_string(&join_windows_args(&command_line));
+        let wide_dir = working_dir.map(|dir| to_wide_string(&dir.to_string_lossy()));
+
+        info!(
+            "Launching {} in AppContainer {} with capabilities {:?}",
+            program, self.name, self.capabilities
+        );
+
+        unsafe {
+            let mut capability_sids = LocalSids(Vec::with_capacity(self.capabilities.len()));
+            for capability in &self.capabilities {
+                let wide_sid = to_wide_string(capability.sid());
+                let mut sid: PSID = ptr::null_mut();
+                if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+                    return Err(std::io::Error::last_os_error())
+                        .with_context(|| format!("Invalid capability SID for {:?}", capability));
+                }
+                capability_sids.0.push(sid);
+            }
+            let mut capabilities: Vec<SID_AND_ATTRIBUTES> = capability_sids
+                .0
+                .iter()
+                .map(|&sid| SID_AND_ATTRIBUTES {
+                    Sid: sid,
+                    Attributes: SE_GROUP_ENABLED,
+                })
+                .collect();
+
+            let mut security_capabilities = SECURITY_CAPABILITIES {
+                AppContainerSid: container.0,
+                Capabilities: if capabilities.is_empty() {
+                    ptr::null_mut()
+                } else {
+                    capabilities.as_mut_ptr()
+                },
+                CapabilityCount: capabilities.len() as DWORD,
+                Reserved: 0,
+            };
+
+            let mut startup = ChildStartup::new(self.stdio, 1)?;
+            startup.set_attribute(
+                PROC_THREAD_ATTRIBUTE_SECURITY_CAPABILITIES,
+                &mut security_capabilities as *mut _ as _,
+                std::mem::size_of::<SECURITY_CAPABILITIES>(),
+            )?;
+
+            let mut process_info: PROCESS_INFORMATION = std::mem::zeroed();
+            if CreateProcessW(
+                ptr::null(),
+                wide_command_line.as_mut_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                startup.inherit_handles(),
+                startup.creation_flags(),
+                ptr::null_mut(),
+                wide_dir.as_ref().map_or(ptr::null(), |dir| dir.as_ptr()),
+                startup.startup_info(),
+                &mut process_info,
+            ) == FALSE
+            {
+                let error = std::io::Error::last_os_error();
+                warn!("Failed to launch {} in AppContainer: {}", program, error);
+                return Err(error).with_context(|| format!("Failed to start {}", program));
+            }
+
+            debug!(
+                "Started {} in AppContainer {} with PID {}",
+                program, self.name, process_info.dwProcessId
+            );
+            Ok(ContainedChild::from_process_information(&process_info))
+        }
+    }
+
+    /// Delete the container profile, returning whether it existed
+    ///
+    /// Entries of [`PathGrants`] still held are left in place; they refer to
+    /// a SID nothing can run as anymore.
+    pub fn delete_profile(&self) -> Result<bool> {
+        let wide_name = to_wide_string(&self.name);
+        let result = unsafe { DeleteAppContainerProfile(wide_name.as_ptr()) };
+        if result == hresult_from_win32(ERROR_FILE_NOT_FOUND) {
+            return Ok(false);
+        }
+        if result < 0 {
+            return Err(hresult_error(result))
+                .with_context(|| format!("Failed to delete AppContainer {}", self.name));
+        }
+
+        info!("Deleted AppContainer profile {}", self.name);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hresult_from_win32() {
+        assert_eq!(
+            hresult_from_win32(ERROR_ALREADY_EXISTS),
+            0x800700B7u32 as HRESULT
+        );
+        assert_eq!(AppContainerCapability::InternetClient.sid(), "S-1-15-3-1");
+    }
+
+    #[test]
+    fn test_hresult_error() {
+        let error = hresult_error(hresult_from_win32(ERROR_FILE_NOT_FOUND));
+        assert_eq!(error.raw_os_error(), Some(ERROR_FILE_NOT_FOUND as i32));
+
+        // E_INVALIDARG wraps ERROR_INVALID_PARAMETER, E_UNEXPECTED no Win32 error
+        let error = hresult_error(0x80070057u32 as HRESULT);
+        assert_eq!(error.raw_os_error(), Some(0x57));
+        let error = hresult_error(0x8000FFFFu32 as HRESULT);
+        assert_eq!(error.raw_os_error(), None);
+        assert!(error.to_string().contains("0x8000FFFF"));
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_launch_in_appcontainer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut launcher = AppContainerLauncher::new("opcode.test-sandbox");
+        launcher.writable_paths.push(dir.path().to_path_buf());
+
+        let sid = launcher.container_sid().unwrap();
+        assert!(sid.starts_with("S-1-15-2-"));
+
+        let child = launcher
+            .launch(
+                r"C:\Windows\System32\cmd.exe",
+                &["/C", "echo sandboxed> out.txt"],
+                Some(dir.path()),
+            )
+            .unwrap();
+        assert_eq!(
+            child
+                .wait(Some(std::time::Duration::from_secs(30)))
+                .unwrap(),
+            Some(0)
+        );
+        assert!(dir.path().join("out.txt").exists());
+
+        // The grant goes with the child
+        let path = dir.path().to_string_lossy().to_string();
+        drop(child);
+        assert!(!get_file_acl(&path)
+            .unwrap()
+            .iter()
+            .any(|entry| entry.sid == sid));
+        assert!(launcher.delete_profile().unwrap());
+    }
+}
@@ -0,0 +1,408 @@
+//! Authenticode verification of the Claude CLI before launch
+//!
+//! The CLI is found by searching `PATH` and well-known install locations, so
+//! a planted or modified `claude.exe` (or the `node.exe` an npm shim runs)
+//! would receive the user's API keys. [`verify_binary_signature`] checks the
+//! embedded Authenticode signature with `WinVerifyTrust` and reports the
+//! publisher; [`check_launch_binaries`] applies the configured
+//! [`SignaturePolicy`] to everything a launch would execute.
+//!
+//! Only embedded signatures are checked. Scripts such as `claude.cmd` cannot
+//! carry one, so for npm shims the interpreter is verified instead.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use windows_sys::Win32::Foundation::{
+    CERT_E_CHAINING, CERT_E_EXPIRED, CERT_E_REVOCATION_FAILURE, CERT_E_REVOKED,
+    CERT_E_UNTRUSTEDROOT, CRYPT_E_FILE_ERROR, CRYPT_E_NO_REVOCATION_CHECK,
+    CRYPT_E_REVOCATION_OFFLINE, TRUST_E_BAD_DIGEST, TRUST_E_EXPLICIT_DISTRUST, TRUST_E_NOSIGNATURE,
+    TRUST_E_SUBJECT_FORM_UNKNOWN, TRUST_E_SUBJECT_NOT_TRUSTED,
+};
+use windows_sys::Win32::Security::Cryptography::{
+    CertGetNameStringW, CERT_CONTEXT, CERT_NAME_ISSUER_FLAG, CERT_NAME_SIMPLE_DISPLAY_TYPE,
+};
+use windows_sys::Win32::Security::WinTrust::{
+    WTHelperGetProvCertFromChain, WTHelperGetProvSignerFromChain, WTHelperProvDataFromStateData,
+    WinVerifyTrust, WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_DATA, WINTRUST_DATA_0,
+    WINTRUST_FILE_INFO, WTD_CACHE_ONLY_URL_RETRIEVAL, WTD_CHOICE_FILE, WTD_REVOKE_WHOLECHAIN,
+    WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+};
+
+/// app_settings key the signature policy is stored under
+const POLICY_SETTINGS_KEY: &str = "binary_signature_policy";
+
+/// Result of checking a binary's Authenticode signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// Signed, unmodified and chaining to a trusted root
+    Valid,
+    /// No embedded signature
+    Unsigned,
+    /// The file was modified after it was signed
+    Tampered,
+    /// Signed, but the certificate is not trusted (self-signed, unknown root, distrusted)
+    Untrusted,
+    /// The signing certificate expired and the signature has no valid timestamp
+    Expired,
+    /// The signing certificate was revoked
+    Revoked,
+    /// Signed and trusted, but no cached revocation information was available
+    RevocationUnchecked,
+    /// The signature could not be checked
+    Unknown,
+}
+
+impl SignatureStatus {
+    fn from_hresult(code: i32) -> Self {
+        match code {
+            0 => SignatureStatus::Valid,
+            TRUST_E_NOSIGNATURE | TRUST_E_SUBJECT_FORM_UNKNOWN => SignatureStatus::Unsigned,
+            TRUST_E_BAD_DIGEST => SignatureStatus::Tampered,
+            CERT_E_UNTRUSTEDROOT
+            | CERT_E_CHAINING
+            | TRUST_E_EXPLICIT_DISTRUST
+            | TRUST_E_SUBJECT_NOT_TRUSTED => SignatureStatus::Untrusted,
+            CERT_E_EXPIRED => SignatureStatus::Expired,
+            CERT_E_REVOKED => SignatureStatus::Revoked,
+            CERT_E_REVOCATION_FAILURE
+            | CRYPT_E_REVOCATION_OFFLINE
+            | CRYPT_E_NO_REVOCATION_CHECK => SignatureStatus::RevocationUnchecked,
+            _ => SignatureStatus::Unknown,
+        }
+    }
+}
+
+/// Signature details of a binary
+#[derive(Debug, Clone, Serialize)]
+pub struct BinarySignature {
+    pub path: String,
+    pub status: SignatureStatus,
+    /// Subject of the signing certificate, e.g. "Anthropic, PBC"
+    pub publisher: Option<String>,
+    /// Issuer of the signing certificate
+    pub issuer: Option<String>,
+    /// `WinVerifyTrust` result, for diagnostics
+    pub error_code: i32,
+}
+
+impl BinarySignature {
+    pub fn is_valid(&self) -> bool {
+        self.status == SignatureStatus::Valid
+    }
+}
+
+/// What to do when a binary about to be launched is not validly signed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    /// Don't check signatures
+    Off,
+    /// Launch anyway, but log and report the problem
+    #[default]
+    Warn,
+    /// Don't launch
+    Refuse,
+}
+
+/// Read the signature policy from the database, falling back to the default
+pub fn load_signature_policy(conn: &Connection) -> SignaturePolicy {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        params![POLICY_SETTINGS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+/// Save the signature policy to the database
+pub fn save_signature_policy(conn: &Connection, policy: SignaturePolicy) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![POLICY_SETTINGS_KEY, serde_json::to_string(&policy)?],
+    )
+    .with_context(|| format!("Failed to save {}", POLICY_SETTINGS_KEY))?;
+    Ok(())
+}
+
+/// Check the embedded Authenticode signature of `path`
+///
+/// Revocation is checked against cached CRLs only, so a launch never waits on
+/// the network.
+pub fn verify_binary_signature(path: &Path) -> Result<BinarySignature> {
+    if !path.is_file() {
+        anyhow::bail!("{} does not exist", path.display());
+    }
+
+    let wide_path: Vec<u16> = path
+        .as_os_str()
+        .to_string_lossy()
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut file_info = WINTRUST_FILE_INFO {
+        cbStruct: std::mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: wide_path.as_ptr(),
+        hFile: std::ptr::null_mut(),
+        pgKnownSubject: std::ptr::null_mut(),
+    };
+    let mut data: WINTRUST_DATA = unsafe { std::mem::zeroed() };
+    data.cbStruct = std::mem::size_of::<WINTRUST_DATA>() as u32;
+    data.dwUIChoice = WTD_UI_NONE;
+    data.fdwRevocationChecks = WTD_REVOKE_WHOLECHAIN;
+    data.dwUnionChoice = WTD_CHOICE_FILE;
+    data.Anonymous = WINTRUST_DATA_0 {
+        pFile: &mut file_info,
+    };
+    data.dwStateAction = WTD_STATEACTION_VERIFY;
+    data.dwProvFlags = WTD_CACHE_ONLY_URL_RETRIEVAL;
+
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let code = unsafe {
+        WinVerifyTrust(
+            std::ptr::null_mut(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut _,
+        )
+    };
+
+    // The signer is available even when the chain isn't trusted
+    let signer = unsafe { signer_certificate(&data) };
+    let (publisher, issuer) = match signer {
+        Some(cert) => unsafe {
+            (
+                certificate_name(cert, 0),
+                certificate_name(cert, CERT_NAME_ISSUER_FLAG),
+            )
+        },
+        None => (None, None),
+    };
+
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe {
+        WinVerifyTrust(
+            std::ptr::null_mut(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut _,
+        )
+    };
+
+    if code == CRYPT_E_FILE_ERROR {
+        anyhow::bail!("Failed to read {} to verify its signature", path.display());
+    }
+
+    let signature = BinarySignature {
+        path: path.to_string_lossy().to_string(),
+        status: SignatureStatus::from_hresult(code),
+        publisher,
+        issuer,
+        error_code: code,
+    };
+    debug!("Signature of {}: {:?}", path.display(), signature);
+    Ok(signature)
+}
+
+/// The leaf certificate of the first signer recorded in the WinVerifyTrust state
+unsafe fn signer_certificate(data: &WINTRUST_DATA) -> Option<*const CERT_CONTEXT> {
+    if data.hWVTStateData.is_null() {
+        return None;
+    }
+    let provider = WTHelperProvDataFromStateData(data.hWVTStateData);
+    if provider.is_null() {
+        return None;
+    }
+    let signer = WTHelperGetProvSignerFromChain(provider, 0, 0, 0);
+    if signer.is_null() {
+        return None;
+    }
+    let cert = WTHelperGetProvCertFromChain(signer, 0);
+    if cert.is_null() || (*cert).pCert.is_null() {
+        return None;
+    }
+    Some((*cert).pCert)
+}
+
+/// Simple display name of a certificate's subject (or issuer with `CERT_NAME_ISSUER_FLAG`)
+unsafe fn certificate_name(cert: *const CERT_CONTEXT, flags: u32) -> Option<String> {
+    let len = CertGetNameStringW(
+        cert,
+        CERT_NAME_SIMPLE_DISPLAY_TYPE,
+        flags,
+        std::ptr::null(),
+        std::ptr::null_mut(),
+        0,
+    );
+    if len <= 1 {
+        return None;
+    }
+    let mut buffer = vec![0u16; len as usize];
+    CertGetNameStringW(
+        cert,
+        CERT_NAME_SIMPLE_DISPLAY_TYPE,
+        flags,
+        std::ptr::null(),
+        buffer.as_mut_ptr(),
+        len,
+    );
+    Some(String::from_utf16_lossy(&buffer[..len as usize - 1]))
+}
+
+/// Executables a launch of `claude_path` runs
+///
+/// An `.exe` runs by itself. npm installs a `claude.cmd` shim (or an
+/// extensionless script) that runs `node.exe` from the same directory if
+/// there is one, otherwise from `PATH`.
+pub fn launch_binaries(claude_path: &str) -> Vec<PathBuf> {
+    let path = Path::new(claude_path);
+    let is_exe = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("exe"))
+        .unwrap_or(false);
+    if is_exe {
+        return vec![path.to_path_buf()];
+    }
+
+    let bundled_node = path
+        .parent()
+        .map(|dir| dir.join("node.exe"))
+        .filter(|node| node.is_file());
+    bundled_node
+        .or_else(|| which::which("node.exe").ok())
+        .into_iter()
+        .collect()
+}
+
+/// Verify everything launching `claude_path` would execute and apply `policy`
+///
+/// Returns the signatures that were checked. With [`SignaturePolicy::Refuse`]
+/// any binary that is not validly signed is an error; a signature whose
+/// revocation status is unknown is only a warning.
+pub fn check_launch_binaries(
+    claude_path: &str,
+    policy: SignaturePolicy,
+) -> Result<Vec<BinarySignature>> {
+    if policy == SignaturePolicy::Off {
+        return Ok(Vec::new());
+    }
+
+    let mut signatures = Vec::new();
+    for binary in launch_binaries(claude_path) {
+        let signature = verify_binary_signature(&binary)?;
+        if signature.is_valid() {
+            info!(
+                "{} is signed by {}",
+                binary.display(),
+                signature
+                    .publisher
+                    .as_deref()
+                    .unwrap_or("an unknown publisher")
+            );
+        } else if signature.status == SignatureStatus::RevocationUnchecked {
+            // Only cached CRLs are used, so this is the normal state offline
+            warn!(
+                "Could not check whether the certificate of {} was revoked",
+                binary.display()
+            );
+        } else if policy == SignaturePolicy::Refuse {
+            anyhow::bail!(
+                "Refusing to launch {}: its signature is {:?} (error {:#010x})",
+                binary.display(),
+                signature.status,
+                signature.error_code
+            );
+        } else {
+            warn!(
+                "{} is not validly signed ({:?}, error {:#010x})",
+                binary.display(),
+                signature.status,
+                signature.error_code
+            );
+        }
+        signatures.push(signature);
+    }
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_status_from_hresult() {
+        assert_eq!(SignatureStatus::from_hresult(0), SignatureStatus::Valid);
+        assert_eq!(
+            SignatureStatus::from_hresult(TRUST_E_NOSIGNATURE),
+            SignatureStatus::Unsigned
+        );
+        assert_eq!(
+            SignatureStatus::from_hresult(TRUST_E_BAD_DIGEST),
+            SignatureStatus::Tampered
+        );
+        assert_eq!(
+            SignatureStatus::from_hresult(CERT_E_UNTRUSTEDROOT),
+            SignatureStatus::Untrusted
+        );
+        assert_eq!(
+            SignatureStatus::from_hresult(CRYPT_E_REVOCATION_OFFLINE),
+            SignatureStatus::RevocationUnchecked
+        );
+        assert_eq!(SignatureStatus::from_hresult(-1), SignatureStatus::Unknown);
+    }
+
+    #[test]
+    fn test_launch_binaries() {
+        assert_eq!(
+            launch_binaries(r"C:\Users\me\.local\bin\claude.exe"),
+            vec![PathBuf::from(r"C:\Users\me\.local\bin\claude.exe")]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let shim = dir.path().join("claude.cmd");
+        std::fs::write(&shim, "@echo off").unwrap();
+        std::fs::write(dir.path().join("node.exe"), "").unwrap();
+        assert_eq!(
+            launch_binaries(&shim.to_string_lossy()),
+            vec![dir.path().join("node.exe")]
+        );
+    }
+
+    #[test]
+    fn test_unsigned_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("claude.exe");
+        std::fs::write(&binary, b"MZ not really a binary").unwrap();
+
+        let signature = verify_binary_signature(&binary).unwrap();
+        assert!(!signature.is_valid());
+        assert!(signature.publisher.is_none());
+
+        let path = binary.to_string_lossy();
+        assert!(check_launch_binaries(&path, SignaturePolicy::Off)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            check_launch_binaries(&path, SignaturePolicy::Warn)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(check_launch_binaries(&path, SignaturePolicy::Refuse).is_err());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_verify_signed_system_binary() {
+        let windows = super::super::known_folders::get_known_folder(
+            super::super::known_folders::KnownFolder::Windows,
+        )
+        .unwrap();
+        let signature = verify_binary_signature(&windows.join("explorer.exe")).unwrap();
+        assert!(signature.is_valid());
+        assert!(signature.publisher.is_some());
+    }
+}
@@ -0,0 +1,352 @@
+//! Windows Defender Controlled Folder Access detection
+//!
+//! Controlled Folder Access (CFA) blocks apps that are not on its allow list
+//! from changing files in protected folders, which include Documents,
+//! Desktop and Pictures. Projects kept under Documents then fail with a plain
+//! "access denied" that looks like a permissions problem. [`explain_access_denied`]
+//! turns such failures into a [`ControlledFolderAccessError`] carrying the
+//! steps to allow opcode, which the UI can show as-is.
+//!
+//! The state is read with `Get-MpPreference`, which standard users may call,
+//! unlike reading Defender's registry keys.
+
+use crate::process::command::{
+    powershell_command, run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT,
+};
+use crate::utils::paths::path_contains;
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Controlled Folder Access mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlledFolderAccessState {
+    Disabled,
+    Enabled,
+    /// Blocks are only logged
+    AuditMode,
+    /// Only writes to disk sectors are blocked, files are not
+    BlockDiskModificationOnly,
+    AuditDiskModificationOnly,
+    Unknown,
+}
+
+impl ControlledFolderAccessState {
+    fn from_value(value: u32) -> Self {
+        match value {
+            0 => ControlledFolderAccessState::Disabled,
+            1 => ControlledFolderAccessState::Enabled,
+            2 => ControlledFolderAccessState::AuditMode,
+            3 => ControlledFolderAccessState::BlockDiskModificationOnly,
+            4 => ControlledFolderAccessState::AuditDiskModificationOnly,
+            _ => ControlledFolderAccessState::Unknown,
+        }
+    }
+
+    /// Whether writes to files in protected folders are blocked
+    pub fn blocks_file_writes(&self) -> bool {
+        *self == ControlledFolderAccessState::Enabled
+    }
+}
+
+/// Controlled Folder Access configuration as it applies to opcode
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlledFolderAccessStatus {
+    pub state: ControlledFolderAccessState,
+    /// Default protected folders plus the ones added by the user or policy
+    pub protected_folders: Vec<String>,
+    pub allowed_applications: Vec<String>,
+    /// Path of the running opcode executable
+    pub executable: String,
+    /// Whether the executable is on the allow list. Defender also allows
+    /// some well-known signed apps implicitly, which can't be queried.
+    pub app_allowed: bool,
+}
+
+impl ControlledFolderAccessStatus {
+    /// The protected folder containing `path`, if any
+    pub fn protected_folder_for(&self, path: &Path) -> Option<&str> {
+        self.protected_folders
+            .iter()
+            .find(|folder| !folder.is_empty() && path_contains(folder, path))
+            .map(|folder| folder.as_str())
+    }
+
+    /// Whether CFA would block opcode from writing to `path`
+    pub fn blocks(&self, path: &Path) -> bool {
+        self.state.blocks_file_writes()
+            && !self.app_allowed
+            && self.protected_folder_for(path).is_some()
+    }
+}
+
+/// Error returned by [`explain_access_denied`] when Controlled Folder Access
+/// is the likely reason a write was denied
+///
+/// Detect it with `downcast_ref::<ControlledFolderAccessError>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ControlledFolderAccessError {
+    pub path: String,
+    /// The protected folder containing `path`
+    pub protected_folder: String,
+    pub executable: String,
+    /// What the user can do about it, in order
+    pub remediation: Vec<String>,
+}
+
+impl std::fmt::Display for ControlledFolderAccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Windows Defender Controlled Folder Access blocked opcode from changing {} because {} is a protected folder",
+            self.path, self.protected_folder
+        )
+    }
+}
+
+impl std::error::Error for ControlledFolderAccessError {}
+
+/// `Get-MpPreference` fields we read
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MpPreference {
+    #[serde(default)]
+    enable_controlled_folder_access: Option<u32>,
+    #[serde(default, deserialize_with = "string_list")]
+    controlled_folder_access_allowed_applications: Vec<String>,
+    #[serde(default, deserialize_with = "string_list")]
+    controlled_folder_access_protected_folders: Vec<String>,
+}
+
+/// `ConvertTo-Json` writes an empty list as `null` and may unwrap single items
+fn string_list<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::One(item)) => vec![item],
+        Some(OneOrMany::Many(items)) => items,
+    })
+}
+
+fn query_mp_preference() -> Result<MpPreference> {
+    let script = "Get-MpPreference | Select-Object EnableControlledFolderAccess, \
+                  ControlledFolderAccessAllowedApplications, ControlledFolderAccessProtectedFolders \
+                  | ConvertTo-Json -Compress";
+    let output = run_with_timeout_blocking(
+        &mut powershell_command(script, &[]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute PowerShell command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "Get-MpPreference failed: {}",
+            stderr.trim()
+        ));
+    }
+    serde_json::from_slice(&output.stdout).context("Failed to parse Get-MpPreference output")
+}
+
+/// Folders CFA protects without any configuration, for the current user and
+/// the Public profile
+fn default_protected_folders() -> Vec<PathBuf> {
+    use super::known_folders::{get_known_folder, KnownFolder};
+
+    [
+        KnownFolder::Documents,
+        KnownFolder::Pictures,
+        KnownFolder::Videos,
+        KnownFolder::Music,
+        KnownFolder::Desktop,
+        KnownFolder::Favorites,
+        KnownFolder::PublicDocuments,
+        KnownFolder::PublicPictures,
+        KnownFolder::PublicVideos,
+        KnownFolder::PublicMusic,
+        KnownFolder::PublicDesktop,
+    ]
+    .into_iter()
+    .filter_map(|folder| get_known_folder(folder).ok())
+    .collect()
+}
+
+fn same_executable(a: &str, b: &str) -> bool {
+    let normalize = |p: &str| p.trim().trim_matches('"').replace('/', "\\").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// Steps the user can take to let opcode write to `protected_folder`
+pub fn remediation_steps(executable: &str, protected_folder: &str) -> Vec<String> {
+    vec![
+        "Open Windows Security and go to Virus & threat protection > Ransomware protection > Manage ransomware protection".to_string(),
+        format!(
+            "Under Controlled folder access, choose \"Allow an app through Controlled folder access\" and add {}",
+            executable
+        ),
+        format!(
+            "Alternatively, move the project out of {} (for example to C:\\Projects)",
+            protected_folder
+        ),
+        "If your organization manages Windows Defender, ask your administrator to allow opcode".to_string(),
+    ]
+}
+
+fn build_status(preference: MpPreference, executable: String) -> ControlledFolderAccessStatus {
+    let mut protected_folders: Vec<String> = default_protected_folders()
+        .into_iter()
+        .map(|folder| folder.to_string_lossy().to_string())
+        .collect();
+    protected_folders.extend(preference.controlled_folder_access_protected_folders);
+
+    let app_allowed = preference
+        .controlled_folder_access_allowed_applications
+        .iter()
+        .any(|app| same_executable(app, &executable));
+
+    ControlledFolderAccessStatus {
+        state: preference
+            .enable_controlled_folder_access
+            .map(ControlledFolderAccessState::from_value)
+            .unwrap_or(ControlledFolderAccessState::Disabled),
+        protected_folders,
+        allowed_applications: preference.controlled_folder_access_allowed_applications,
+        executable,
+        app_allowed,
+    }
+}
+
+/// Read the Controlled Folder Access configuration
+///
+/// Fails when Defender isn't the active antivirus (`Get-MpPreference` is then
+/// unavailable); CFA is not in effect in that case.
+pub fn get_controlled_folder_access_status() -> Result<ControlledFolderAccessStatus> {
+    let executable = std::env::current_exe()
+        .context("Failed to get the current executable")?
+        .to_string_lossy()
+        .to_string();
+    let status = build_status(query_mp_preference()?, executable);
+    debug!(
+        "Controlled Folder Access: {:?}, opcode allowed: {}",
+        status.state, status.app_allowed
+    );
+    Ok(status)
+}
+
+/// Explain an I/O error from writing `path`
+///
+/// Access-denied errors inside a protected folder while CFA is enabled and
+/// opcode is not allowed become a [`ControlledFolderAccessError`]; everything
+/// else is returned unchanged.
+pub fn explain_access_denied(path: &Path, error: std::io::Error) -> anyhow::Error {
+    if error.kind() != std::io::ErrorKind::PermissionDenied {
+        return error.into();
+    }
+
+    let status = match get_controlled_folder_access_status() {
+        Ok(status) => status,
+        Err(e) => {
+            debug!("Could not check Controlled Folder Access: {:#}", e);
+            return error.into();
+        }
+    };
+    if !status.blocks(path) {
+        return error.into();
+    }
+
+    let protected_folder = status
+        .protected_folder_for(path)
+        .unwrap_or_default()
+        .to_string();
+    warn!(
+        "Write to {} was likely blocked by Controlled Folder Access ({})",
+        path.display(),
+        protected_folder
+    );
+    anyhow::Error::new(ControlledFolderAccessError {
+        path: path.to_string_lossy().to_string(),
+        remediation: remediation_steps(&status.executable, &protected_folder),
+        protected_folder,
+        executable: status.executable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mp_preference() {
+        let preference: MpPreference = serde_json::from_str(
+            r#"{"EnableControlledFolderAccess":1,"ControlledFolderAccessAllowedApplications":"C:\\Program Files\\Opcode\\opcode.exe","ControlledFolderAccessProtectedFolders":null}"#,
+        )
+        .unwrap();
+        assert_eq!(preference.enable_controlled_folder_access, Some(1));
+        assert_eq!(
+            preference.controlled_folder_access_allowed_applications,
+            vec![r"C:\Program Files\Opcode\opcode.exe"]
+        );
+        assert!(preference
+            .controlled_folder_access_protected_folders
+            .is_empty());
+    }
+
+    #[test]
+    fn test_blocks() {
+        let preference = MpPreference {
+            enable_controlled_folder_access: Some(1),
+            controlled_folder_access_allowed_applications: vec![r"C:\Tools\other.exe".to_string()],
+            controlled_folder_access_protected_folders: vec![r"D:\Work".to_string()],
+        };
+        let status = build_status(
+            preference,
+            r"C:\Program Files\Opcode\opcode.exe".to_string(),
+        );
+        assert!(!status.app_allowed);
+        assert!(status.blocks(Path::new(r"d:\work\project\src\main.rs")));
+        assert!(!status.blocks(Path::new(r"D:\Workspace\project")));
+        assert!(!status.blocks(Path::new(r"C:\Projects\app")));
+        assert_eq!(
+            status.protected_folder_for(Path::new(r"D:\Work\x")),
+            Some(r"D:\Work")
+        );
+
+        let preference = MpPreference {
+            enable_controlled_folder_access: Some(2),
+            controlled_folder_access_protected_folders: vec![r"D:\Work".to_string()],
+            ..Default::default()
+        };
+        let status = build_status(preference, r"C:\opcode.exe".to_string());
+        assert!(!status.blocks(Path::new(r"D:\Work\project")));
+    }
+
+    #[test]
+    fn test_controlled_folder_access_error_display() {
+        let error = ControlledFolderAccessError {
+            path: r"C:\Users\me\Documents\app\main.rs".to_string(),
+            protected_folder: r"C:\Users\me\Documents".to_string(),
+            executable: r"C:\Program Files\Opcode\opcode.exe".to_string(),
+            remediation: remediation_steps(
+                r"C:\Program Files\Opcode\opcode.exe",
+                r"C:\Users\me\Documents",
+            ),
+        };
+        assert!(error.to_string().contains("Controlled Folder Access"));
+        assert!(error.remediation[1].contains(r"C:\Program Files\Opcode\opcode.exe"));
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_controlled_folder_access_status() {
+        let status = get_controlled_folder_access_status().unwrap();
+        assert!(!status.protected_folders.is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//! Windows Defender path exclusions for project directories
+//!
+//! Real-time scanning inspects every file opcode writes, and a session can
+//! write thousands of small checkpoint files, which slows it down
+//! noticeably. [`set_defender_exclusion`] adds or removes a Defender path
+//! exclusion for a project or opcode's data directory with
+//! `Add-MpPreference`/`Remove-MpPreference`.
+//!
+//! An exclusion lowers the machine's protection, so it is only changed after
+//! the user explicitly agreed (see [`ExclusionConsentRequired`]). Changing
+//! exclusions needs administrator rights; unelevated opcode goes through the
+//! elevation broker.
+
+use super::elevation_broker::{run_as_admin, BrokerRequest};
+use super::known_folders::{get_known_folder, KnownFolder};
+use crate::process::command::{
+    powershell_command, run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT,
+};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::path::Path;
+
+/// Error returned by [`set_defender_exclusion`] when the user has not agreed
+/// to the change yet
+///
+/// Detect it with `downcast_ref::<ExclusionConsentRequired>()`, show
+/// `warning`, and call again with consent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExclusionConsentRequired {
+    pub path: String,
+    pub excluded: bool,
+    /// What the user is agreeing to
+    pub warning: String,
+}
+
+impl std::fmt::Display for ExclusionConsentRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.warning)
+    }
+}
+
+impl std::error::Error for ExclusionConsentRequired {}
+
+/// Reject paths whose exclusion would switch off scanning for far more than a project
+fn validate_exclusion_path(path: &str) -> Result<()> {
+    let path_ref = Path::new(path);
+    if !path_ref.is_absolute() {
+        anyhow::bail!("Exclusion path must be absolute: {}", path);
+    }
+    if path_ref.parent().is_none() {
+        anyhow::bail!("Refusing to exclude the whole drive {}", path);
+    }
+    if path.contains(['*', '?', '%']) {
+        anyhow::bail!(
+            "Exclusion path must not contain wildcards or variables: {}",
+            path
+        );
+    }
+
+    let too_broad = [
+        KnownFolder::Profile,
+        KnownFolder::Windows,
+        KnownFolder::ProgramFiles,
+        KnownFolder::ProgramFilesX86,
+        KnownFolder::ProgramData,
+        KnownFolder::RoamingAppData,
+        KnownFolder::LocalAppData,
+    ]
+    .map(|folder| get_known_folder(folder).ok());
+    let normalize = |p: &Path| {
+        p.to_string_lossy()
+            .replace('/', "\\")
+            .trim_end_matches('\\')
+            .to_lowercase()
+    };
+    let candidate = normalize(path_ref);
+    if too_broad
+        .iter()
+        .flatten()
+        .any(|dir: &std::path::PathBuf| normalize(dir) == candidate)
+    {
+        anyhow::bail!(
+            "Refusing to exclude {}; choose a project directory instead",
+            path
+        );
+    }
+    Ok(())
+}
+
+/// Paths excluded from Defender scanning
+///
+/// Returns `None` when they can't be read, which is the case for standard
+/// users: Defender hides exclusions from non-administrators.
+pub fn list_defender_exclusions() -> Result<Option<Vec<String>>> {
+    let script = "(Get-MpPreference).ExclusionPath | ConvertTo-Json -Compress";
+    let output = run_with_timeout_blocking(
+        &mut powershell_command(script, &[]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute PowerShell command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "Get-MpPreference failed: {}",
+            stderr.trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let paths: Vec<String> = match serde_json::from_str::<serde_json::Value>(stdout.trim()) {
+        Ok(serde_json::Value::Array(items)) => items
+            .into_iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        Ok(serde_json::Value::String(item)) => vec![item],
+        _ => Vec::new(),
+    };
+
+    // Non-administrators get a single placeholder entry instead of the list
+    if paths.iter().any(|p| p.starts_with("N/A")) {
+        return Ok(None);
+    }
+    Ok(Some(paths))
+}
+
+/// Add or remove the exclusion in this (elevated) process
+///
+/// Used directly when opcode runs as administrator, and by the elevation broker.
+pub fn apply_defender_exclusion(path: &str, excluded: bool) -> Result<()> {
+    validate_exclusion_path(path)?;
+
+    let cmdlet = if excluded {
+        "Add-MpPreference"
+    } else {
+        "Remove-MpPreference"
+    };
+    let script = format!(
+        "{} -ExclusionPath $env:OPCODE_PS_PATH -ErrorAction Stop",
+        cmdlet
+    );
+    let output = run_with_timeout_blocking(
+        &mut powershell_command(&script, &[("PATH", path)]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute PowerShell command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("{} failed: {}", cmdlet, stderr.trim()));
+    }
+
+    info!(
+        "{} Defender exclusion for {}",
+        if excluded { "Added" } else { "Removed" },
+        path
+    );
+    Ok(())
+}
+
+/// Add (`excluded = true`) or remove a Defender path exclusion
+///
+/// Without `consent` nothing is changed and an [`ExclusionConsentRequired`]
+/// error describing the change is returned. Removing an exclusion is always
+/// safe and needs no consent. Shows a UAC prompt if the elevation broker is
+/// not running yet.
+pub async fn set_defender_exclusion(path: &str, excluded: bool, consent: bool) -> Result<()> {
+    validate_exclusion_path(path)?;
+    if excluded && !Path::new(path).is_dir() {
+        anyhow::bail!("{} is not a directory", path);
+    }
+
+    if excluded && !consent {
+        return Err(anyhow::Error::new(ExclusionConsentRequired {
+            path: path.to_string(),
+            excluded,
+            warning: format!(
+                "Windows Defender will no longer scan files in {}. Only exclude directories whose contents you trust.",
+                path
+            ),
+        }));
+    }
+
+    let request = if excluded {
+        BrokerRequest::AddDefenderExclusion {
+            path: path.to_string(),
+        }
+    } else {
+        BrokerRequest::RemoveDefenderExclusion {
+            path: path.to_string(),
+        }
+    };
+    run_as_admin(request).await.map_err(|e| {
+        warn!(
+            "Changing the Defender exclusion for {} failed: {:#}",
+            path, e
+        );
+        e
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_exclusion_path() {
+        assert!(validate_exclusion_path(r"C:\Projects\app").is_ok());
+        assert!(validate_exclusion_path(r"C:\").is_err());
+        assert!(validate_exclusion_path(r"Projects\app").is_err());
+        assert!(validate_exclusion_path(r"C:\Projects\*").is_err());
+        assert!(validate_exclusion_path(r"%USERPROFILE%\app").is_err());
+
+        let home = dirs::home_dir().unwrap();
+        assert!(validate_exclusion_path(&home.to_string_lossy()).is_err());
+        assert!(validate_exclusion_path(&format!("{}\\", home.display())).is_err());
+        assert!(validate_exclusion_path(&home.join(".claude").to_string_lossy()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_set_defender_exclusion_requires_consent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+
+        let error = set_defender_exclusion(&path, true, false)
+            .await
+            .unwrap_err();
+        let consent = error.downcast_ref::<ExclusionConsentRequired>().unwrap();
+        assert_eq!(consent.path, path);
+        assert!(consent.excluded);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_list_defender_exclusions() {
+        let exclusions = list_defender_exclusions().unwrap();
+        if !super::super::permissions::is_running_as_admin().unwrap() {
+            assert!(exclusions.is_none());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! EFS (Encrypting File System) status and encryption of sensitive directories
+//!
+//! Transcripts, checkpoints and stored credentials live in plain files under
+//! the user's profile. On NTFS volumes of Windows editions that include EFS,
+//! [`encrypt_directory`] encrypts such a directory with the user's EFS key,
+//! so the files can't be read from another account or after the disk is
+//! removed. Files created in an encrypted directory are encrypted
+//! automatically.
+
+use super::to_wide_path;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use winapi::shared::minwindef::{DWORD, FALSE, MAX_PATH};
+use winapi::um::fileapi::{GetFileAttributesW, INVALID_FILE_ATTRIBUTES};
+use winapi::um::winnt::FILE_ATTRIBUTE_ENCRYPTED;
+
+/// EFS state of a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EfsStatus {
+    /// The volume supports EFS. Windows Home editions can't encrypt even
+    /// when it does; [`encrypt_directory`] reports that when trying
+    pub supported: bool,
+    pub encrypted: bool,
+}
+
+/// Whether a file or directory is encrypted with EFS
+pub fn is_efs_encrypted(path: &Path) -> Result<bool> {
+    let wide_path = to_wide_path(path);
+    let attributes = unsafe { GetFileAttributesW(wide_path.as_ptr()) };
+    if attributes == INVALID_FILE_ATTRIBUTES {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to read the attributes of {}", path.display()));
+    }
+    Ok(attributes & FILE_ATTRIBUTE_ENCRYPTED != 0)
+}
+
+/// Root of the volume holding `path`, e.g. `C:\` or a mount point
+pub(crate) fn volume_root(path: &Path) -> Result<PathBuf> {
+    use winapi::um::fileapi::GetVolumePathNameW;
+
+    let wide_path = to_wide_path(path);
+    let mut volume = [0u16; MAX_PATH + 1];
+    if unsafe {
+        GetVolumePathNameW(
+            wide_path.as_ptr(),
+            volume.as_mut_ptr(),
+            volume.len() as DWORD,
+        )
+    } == FALSE
+    {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to find the volume of {}", path.display()));
+    }
+    let len = volume.iter().position(|&c| c == 0).unwrap_or(volume.len());
+    Ok(PathBuf::from(String::from_utf16_lossy(&volume[..len])))
+}
+
+/// Whether the volume holding `path` supports EFS (NTFS does, FAT and most
+/// network shares don't)
+pub fn volume_supports_efs(path: &Path) -> Result<bool> {
+    use winapi::um::fileapi::GetVolumeInformationW;
+    use winapi::um::winnt::FILE_SUPPORTS_ENCRYPTION;
+
+    let volume = to_wide_path(&volume_root(path)?);
+    let mut flags: DWORD = 0;
+    if unsafe {
+        GetVolumeInformationW(
+            volume.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut flags,
+            std::ptr::null_mut(),
+            0,
+        )
+    } == FALSE
+    {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to query the volume of {}", path.display()));
+    }
+    Ok(flags & FILE_SUPPORTS_ENCRYPTION != 0)
+}
+
+/// EFS support and encryption state of `path`
+pub fn get_efs_status(path: &Path) -> Result<EfsStatus> {
+    Ok(EfsStatus {
+        supported: volume_supports_efs(path)?,
+        encrypted: is_efs_encrypted(path)?,
+    })
+}
+
+/// Encrypt (`encrypt = true`) or decrypt a single file or directory
+fn set_encrypted(path: &Path, encrypt: bool) -> Result<()> {
+    use winapi::shared::winerror::ERROR_NOT_SUPPORTED;
+    use windows_sys::Win32::Storage::FileSystem::{DecryptFileW, EncryptFileW};
+
+    let wide_path = to_wide_path(path);
+    let ok = unsafe {
+        if encrypt {
+            EncryptFileW(wide_path.as_ptr())
+        } else {
+            DecryptFileW(wide_path.as_ptr(), 0)
+        }
+    };
+    if ok == FALSE {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(ERROR_NOT_SUPPORTED as i32) {
+            anyhow::bail!("EFS is not available on this edition of Windows or on this volume");
+        }
+        return Err(error).with_context(|| {
+            format!(
+                "Failed to {} {}",
+                if encrypt { "encrypt" } else { "decrypt" },
+                path.display()
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Apply [`set_encrypted`] to `dir`, then to everything below it
+///
+/// Marking the directory first makes files created meanwhile get the new
+/// state too. Returns the number of entries that were changed.
+fn set_directory_encrypted(dir: &Path, encrypt: bool) -> Result<usize> {
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+
+    let mut changed = 0;
+    let mut pending: Vec<PathBuf> = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        if is_efs_encrypted(&current)? != encrypt {
+            set_encrypted(&current, encrypt)?;
+            changed += 1;
+        }
+        if !current.is_dir() {
+            continue;
+        }
+
+        let entries = std::fs::read_dir(&current)
+            .with_context(|| format!("Failed to read {}", current.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            // Symlinks and junctions could lead outside the directory
+            if file_type.is_symlink() {
+                debug!("Skipping symlink {}", entry.path().display());
+                continue;
+            }
+            pending.push(entry.path());
+        }
+    }
+    Ok(changed)
+}
+
+/// Encrypt a directory and everything in it with EFS
+///
+/// Files added later are encrypted automatically. Already encrypted entries
+/// are skipped, so this can be rerun after a partial failure. Returns the
+/// number of files and directories that were encrypted.
+pub fn encrypt_directory(dir: &Path) -> Result<usize> {
+    if !volume_supports_efs(dir)? {
+        anyhow::bail!(
+            "The volume holding {} does not support EFS encryption",
+            dir.display()
+        );
+    }
+
+    let encrypted = set_directory_encrypted(dir, true)?;
+    info!("Encrypted {} entries in {}", encrypted, dir.display());
+    Ok(encrypted)
+}
+
+/// Decrypt a directory and everything in it, undoing [`encrypt_directory`]
+pub fn decrypt_directory(dir: &Path) -> Result<usize> {
+    let decrypted = set_directory_encrypted(dir, false).map_err(|e| {
+        warn!("Decrypting {} failed: {:#}", dir.display(), e);
+        e
+    })?;
+    info!("Decrypted {} entries in {}", decrypted, dir.display());
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_requires_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("transcript.jsonl");
+        std::fs::write(&file, "{}").unwrap();
+        assert!(set_directory_encrypted(&file, true).is_err());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows with EFS
+    fn test_encrypt_and_decrypt_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("transcript.jsonl");
+        std::fs::write(&file, "{}").unwrap();
+        if !get_efs_status(dir.path()).unwrap().supported {
+            return;
+        }
+
+        assert_eq!(encrypt_directory(dir.path()).unwrap(), 2);
+        assert!(is_efs_encrypted(&file).unwrap());
+        assert_eq!(encrypt_directory(dir.path()).unwrap(), 0);
+
+        let added = dir.path().join("later.jsonl");
+        std::fs::write(&added, "{}").unwrap();
+        assert!(is_efs_encrypted(&added).unwrap());
+
+        assert_eq!(decrypt_directory(dir.path()).unwrap(), 3);
+        assert!(!is_efs_encrypted(&file).unwrap());
+    }
+}
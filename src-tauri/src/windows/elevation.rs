@@ -0,0 +1,445 @@
+//! Running helpers with administrator rights on every platform
+//!
+//! [`Elevation`] is implemented by the UAC prompt on Windows, `pkexec`
+//! (polkit) on Linux and an `osascript` administrator prompt on macOS, so
+//! features that need a privileged helper, such as installing a service,
+//! ask once through [`elevation`] and get the same [`ElevationOutcome`]
+//! everywhere.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+
+/// How [`Elevation::run_elevated`] starts the elevated process
+///
+/// # Fields
+/// - `wait_for_exit`: Wait for the elevated process to exit and report its exit code
+/// - `timeout`: How long to wait for the exit; `None` waits indefinitely
+/// - `working_dir`: Working directory of the elevated process
+/// - `show_window`: Show the process's window (hidden otherwise; Windows only)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElevationOptions {
+    pub wait_for_exit: bool,
+    pub timeout: Option<std::time::Duration>,
+    pub working_dir: Option<String>,
+    pub show_window: bool,
+}
+
+/// Result of [`Elevation::run_elevated`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ElevationOutcome {
+    /// The user declined the prompt or failed to authenticate
+    Declined,
+    /// The elevated process was started and is still running (or wasn't waited for)
+    Started { pid: u32 },
+    /// The elevated process ran to completion
+    Exited { pid: u32, exit_code: u32 },
+}
+
+/// A way to run a program with administrator rights after asking the user
+#[async_trait]
+pub trait Elevation: Send + Sync {
+    /// Short name of the backend, for logs and diagnostics
+    fn backend(&self) -> &'static str;
+
+    /// Whether the backend can prompt on this machine
+    fn is_available(&self) -> bool;
+
+    /// Run `executable_path` with `args` elevated
+    ///
+    /// Where the prompt is shown by the started process rather than before
+    /// it (polkit, osascript), a declined prompt can only be reported as
+    /// [`ElevationOutcome::Declined`] when `options.wait_for_exit` is set.
+    async fn run_elevated(
+        &self,
+        executable_path: &str,
+        args: &[&str],
+        options: &ElevationOptions,
+    ) -> Result<ElevationOutcome>;
+}
+
+/// The elevation backend for the platform Opcode was built for
+pub fn elevation() -> &'static dyn Elevation {
+    #[cfg(target_os = "windows")]
+    return &UacElevation;
+
+    #[cfg(target_os = "linux")]
+    return &PolkitElevation;
+
+    #[cfg(target_os = "macos")]
+    return &AppleScriptElevation;
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    return &UnsupportedElevation;
+}
+
+/// Run a program elevated and wait for it, returning whether the prompt was accepted
+///
+/// polkit and osascript only report a declined prompt through the exit status
+/// of the elevated program, so this waits for it to exit; the Windows version
+/// in `permissions` returns as soon as UAC has answered.
+#[cfg(not(target_os = "windows"))]
+pub async fn request_elevation(executable_path: &str, args: &[&str]) -> Result<bool> {
+    let options = ElevationOptions {
+        wait_for_exit: true,
+        ..Default::default()
+    };
+    let outcome = elevation()
+        .run_elevated(executable_path, args, &options)
+        .await?;
+    Ok(outcome != ElevationOutcome::Declined)
+}
+
+/// Elevation through the UAC prompt
+#[cfg(target_os = "windows")]
+pub struct UacElevation;
+
+#[cfg(target_os = "windows")]
+#[async_trait]
+impl Elevation for UacElevation {
+    fn backend(&self) -> &'static str {
+        "uac"
+    }
+
+    fn is_available(&self) -> bool {
+        let is_administrator = super::permissions::is_administrator();
+        super::permissions::get_uac_configuration()
+            .map(|config| config.elevation_blocked_reason(is_administrator).is_none())
+            .unwrap_or(true)
+    }
+
+    async fn run_elevated(
+        &self,
+        executable_path: &str,
+        args: &[&str],
+        options: &ElevationOptions,
+    ) -> Result<ElevationOutcome> {
+        super::permissions::request_elevation_with_options(executable_path, args, options).await
+    }
+}
+
+/// Elevation through `pkexec`, which asks the session's polkit agent
+#[cfg(target_os = "linux")]
+pub struct PolkitElevation;
+
+/// `pkexec` exit code when the authentication dialog was dismissed
+#[cfg(target_os = "linux")]
+const PKEXEC_DISMISSED: i32 = 126;
+
+/// `pkexec` exit code when the user is not authorized or failed to authenticate
+#[cfg(target_os = "linux")]
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl Elevation for PolkitElevation {
+    fn backend(&self) -> &'static str {
+        "polkit"
+    }
+
+    fn is_available(&self) -> bool {
+        which::which("pkexec").is_ok()
+    }
+
+    async fn run_elevated(
+        &self,
+        executable_path: &str,
+        args: &[&str],
+        options: &ElevationOptions,
+    ) -> Result<ElevationOutcome> {
+        use crate::utils::cmdline::join_posix_args;
+
+        log::info!("Requesting polkit elevation for: {}", executable_path);
+        check_executable(executable_path)?;
+
+        // pkexec starts the program in root's home, so change directory in a shell
+        let mut command = tokio::process::Command::new("pkexec");
+        match &options.working_dir {
+            Some(dir) => {
+                let mut program = vec![executable_path];
+                program.extend_from_slice(args);
+                let script = format!(
+                    "cd {} && exec {}",
+                    crate::utils::cmdline::quote_posix(dir),
+                    join_posix_args(&program)
+                );
+                command.args(["/bin/sh", "-c", &script]);
+            }
+            None => {
+                command.arg(executable_path).args(args);
+            }
+        }
+
+        let outcome = spawn_and_wait(command, options, |code| {
+            matches!(code, PKEXEC_DISMISSED | PKEXEC_NOT_AUTHORIZED)
+        })
+        .await?;
+        log_outcome(&outcome);
+        Ok(outcome)
+    }
+}
+
+/// Elevation through an `osascript` administrator prompt
+///
+/// `AuthorizationExecuteWithPrivileges` is deprecated; `do shell script ...
+/// with administrator privileges` shows the same system dialog.
+#[cfg(target_os = "macos")]
+pub struct AppleScriptElevation;
+
+/// AppleScript error number for a cancelled dialog (`userCanceledErr`)
+#[cfg(target_os = "macos")]
+const USER_CANCELED: &str = "(-128)";
+
+#[cfg(target_os = "macos")]
+#[async_trait]
+impl Elevation for AppleScriptElevation {
+    fn backend(&self) -> &'static str {
+        "osascript"
+    }
+
+    fn is_available(&self) -> bool {
+        std::path::Path::new("/usr/bin/osascript").exists()
+    }
+
+    async fn run_elevated(
+        &self,
+        executable_path: &str,
+        args: &[&str],
+        options: &ElevationOptions,
+    ) -> Result<ElevationOutcome> {
+        use anyhow::Context;
+
+        log::info!(
+            "Requesting administrator privileges for: {}",
+            executable_path
+        );
+        check_executable(executable_path)?;
+
+        let script = elevated_shell_script(executable_path, args, options.working_dir.as_deref());
+        let mut command = tokio::process::Command::new("/usr/bin/osascript");
+        command.args(["-e", &script]);
+
+        if !options.wait_for_exit {
+            let child = command
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .context("Failed to start osascript")?;
+            let outcome = ElevationOutcome::Started {
+                pid: child.id().unwrap_or_default(),
+            };
+            log_outcome(&outcome);
+            return Ok(outcome);
+        }
+
+        // The script's exit code only appears in the error message, so read it from stderr
+        let child = command
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .context("Failed to start osascript")?;
+        let pid = child.id().unwrap_or_default();
+        let wait = child.wait_with_output();
+        let output = match options.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+                Ok(output) => output,
+                Err(_) => {
+                    log::warn!(
+                        "Elevated process {} is still running after {:?}",
+                        pid,
+                        timeout
+                    );
+                    return Ok(ElevationOutcome::Started { pid });
+                }
+            },
+            None => wait.await,
+        }
+        .context("Failed to wait for osascript")?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let outcome = if output.status.success() {
+            ElevationOutcome::Exited { pid, exit_code: 0 }
+        } else if stderr.contains(USER_CANCELED) {
+            ElevationOutcome::Declined
+        } else {
+            ElevationOutcome::Exited {
+                pid,
+                exit_code: script_exit_code(&stderr).unwrap_or(1),
+            }
+        };
+        log_outcome(&outcome);
+        Ok(outcome)
+    }
+}
+
+/// Build a `do shell script` AppleScript that runs the program as root
+#[cfg(target_os = "macos")]
+fn elevated_shell_script(
+    executable_path: &str,
+    args: &[&str],
+    working_dir: Option<&str>,
+) -> String {
+    use crate::utils::cmdline::{join_posix_args, quote_posix};
+
+    let mut program = vec![executable_path];
+    program.extend_from_slice(args);
+    let mut shell = join_posix_args(&program);
+    if let Some(dir) = working_dir {
+        shell = format!("cd {} && {}", quote_posix(dir), shell);
+    }
+    let literal = shell.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(
+        "do shell script \"{}\" with administrator privileges",
+        literal
+    )
+}
+
+/// Exit code of a failed `do shell script`, reported as `... (<code>)`
+#[cfg(target_os = "macos")]
+fn script_exit_code(stderr: &str) -> Option<u32> {
+    let stderr = stderr.trim_end();
+    let start = stderr.rfind('(')?;
+    stderr[start + 1..].strip_suffix(')')?.parse().ok()
+}
+
+/// Elevation on platforms without a supported prompt
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+pub struct UnsupportedElevation;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+#[async_trait]
+impl Elevation for UnsupportedElevation {
+    fn backend(&self) -> &'static str {
+        "unsupported"
+    }
+
+    fn is_available(&self) -> bool {
+        false
+    }
+
+    async fn run_elevated(
+        &self,
+        _executable_path: &str,
+        _args: &[&str],
+        _options: &ElevationOptions,
+    ) -> Result<ElevationOutcome> {
+        Err(super::unsupported::Unsupported::new("Running programs as administrator").into())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn check_executable(executable_path: &str) -> Result<()> {
+    if !std::path::Path::new(executable_path).exists() {
+        anyhow::bail!("Executable not found: {}", executable_path);
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn log_outcome(outcome: &ElevationOutcome) {
+    match outcome {
+        ElevationOutcome::Declined => log::warn!("User denied elevation request"),
+        ElevationOutcome::Started { pid } => log::info!("Started elevated process {}", pid),
+        ElevationOutcome::Exited { pid, exit_code } => {
+            log::info!("Elevated process {} exited with code {}", pid, exit_code)
+        }
+    }
+}
+
+/// Start `command` and, if asked to, wait for it within `options.timeout`
+///
+/// `is_declined` recognizes the exit codes the prompting program uses for a
+/// declined or failed authentication.
+#[cfg(target_os = "linux")]
+async fn spawn_and_wait(
+    mut command: tokio::process::Command,
+    options: &ElevationOptions,
+    is_declined: impl Fn(i32) -> bool,
+) -> Result<ElevationOutcome> {
+    use anyhow::Context;
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to start {:?}", command.as_std().get_program()))?;
+    let pid = child.id().unwrap_or_default();
+    if !options.wait_for_exit {
+        return Ok(ElevationOutcome::Started { pid });
+    }
+
+    let status = match options.timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(status) => status,
+            Err(_) => {
+                log::warn!(
+                    "Elevated process {} is still running after {:?}",
+                    pid,
+                    timeout
+                );
+                return Ok(ElevationOutcome::Started { pid });
+            }
+        },
+        None => child.wait().await,
+    }
+    .context("Failed to wait for the elevated process")?;
+
+    Ok(match status.code() {
+        Some(code) if is_declined(code) => ElevationOutcome::Declined,
+        // Killed by a signal
+        None => ElevationOutcome::Exited { pid, exit_code: 1 },
+        Some(code) => ElevationOutcome::Exited {
+            pid,
+            exit_code: code as u32,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_spawn_and_wait() {
+        let mut command = tokio::process::Command::new("sh");
+        command.args(["-c", "exit 3"]);
+        let options = ElevationOptions {
+            wait_for_exit: true,
+            ..Default::default()
+        };
+        let outcome = spawn_and_wait(command, &options, |code| code == 126)
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            ElevationOutcome::Exited { exit_code: 3, .. }
+        ));
+
+        let mut command = tokio::process::Command::new("sh");
+        command.args(["-c", "exit 126"]);
+        let outcome = spawn_and_wait(command, &options, |code| code == 126)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ElevationOutcome::Declined);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_elevated_shell_script() {
+        assert_eq!(
+            elevated_shell_script("/usr/bin/tool", &["say \"hi\""], Some("/tmp/a b")),
+            r#"do shell script "cd '/tmp/a b' && /usr/bin/tool 'say \"hi\"'" with administrator privileges"#
+        );
+        assert_eq!(script_exit_code("execution error: failed (3)\n"), Some(3));
+        assert_eq!(script_exit_code("execution error"), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[tokio::test]
+    async fn test_missing_executable() {
+        let result = elevation()
+            .run_elevated("/nonexistent/helper", &[], &ElevationOptions::default())
+            .await;
+        assert!(result.is_err());
+    }
+}
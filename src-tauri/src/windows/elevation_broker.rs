@@ -0,0 +1,787 @@
+//! Persistent elevation broker
+//!
+//! Registry and ACL operations on machine-wide locations, and changes to
+//! Windows Defender settings, need administrator rights. Instead of starting
+//! a new elevated process (and showing a new UAC prompt) for every such
+//! operation, opcode can start one elevated copy of itself with
+//! `--elevation-broker` and send it requests over a named pipe.
+//! The user sees a single UAC prompt per session. Callers go through
+//! [`run_as_admin`], which skips the broker when opcode is already elevated.
+//!
+//! For one-off operations, [`run_elevated_with_result`] relaunches a program
+//! elevated and waits for it to hand back an [`ElevationReport`] over a pipe
+//! of its own, instead of only seeing that a process was started.
+//!
+//! # Security
+//! - The pipe name is random per launch, and the broker creates it as the
+//!   first and only instance, so another process cannot squat on it
+//! - The pipe's DACL only admits the user who launched the broker, and its
+//!   Medium integrity label lets that user's unelevated opcode connect
+//! - Remote clients are rejected
+//! - The broker only serves the process ID it was launched by, and opcode only
+//!   talks to a pipe served by the process it launched
+//! - The broker serves a single connection and exits when it closes, so it
+//!   never outlives opcode
+//!
+//! # Example
+//! ```rust
+//! use crate::windows::elevation_broker::{run_as_admin, BrokerRequest};
+//! use crate::windows::registry::RegistryChange;
+//!
+//! async fn register_machine_wide(plan: Vec<RegistryChange>) -> anyhow::Result<()> {
+//!     run_as_admin(BrokerRequest::ApplyRegistryPlan { plan, take_ownership: false }).await
+//! }
+//! ```
+
+use super::defender_exclusions::apply_defender_exclusion;
+use super::permissions::{
+    apply_acl_changes, current_user_sid, is_running_as_admin, remove_file_acl, reset_file_acl,
+    set_file_acl, shell_execute_elevated, take_file_ownership, AclChange,
+};
+use super::registry::{
+    apply_registry_plan_with_consent, take_registry_key_ownership, RegistryChange, RegistryRoot,
+};
+use super::to_wide_string;
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::os::windows::io::AsRawHandle;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions};
+use winapi::shared::minwindef::{DWORD, FALSE};
+
+/// Command line flag that turns an opcode process into the broker
+pub const ELEVATION_BROKER_FLAG: &str = "--elevation-broker";
+
+/// How long the broker waits for opcode to connect after starting
+const BROKER_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long opcode waits for the broker's pipe to appear once it started
+const PIPE_WAIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `ERROR_PIPE_BUSY`
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// An operation the broker performs with administrator rights
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BrokerRequest {
+    /// Check that the broker is alive
+    Ping,
+    /// [`apply_registry_plan_with_consent`]
+    ApplyRegistryPlan {
+        plan: Vec<RegistryChange>,
+        take_ownership: bool,
+    },
+    /// [`take_registry_key_ownership`]
+    TakeRegistryKeyOwnership { root: RegistryRoot, key: String },
+    /// [`set_file_acl`]
+    SetFileAcl { path: String, permissions: String },
+    /// [`remove_file_acl`]
+    RemoveFileAcl { path: String, principal: String },
+    /// [`reset_file_acl`]
+    ResetFileAcl { path: String },
+    /// [`apply_acl_changes`]
+    ApplyAclChanges {
+        path: String,
+        changes: Vec<AclChange>,
+    },
+    /// [`take_file_ownership`]
+    TakeFileOwnership { path: String },
+    /// [`apply_defender_exclusion`] with `excluded = true`
+    AddDefenderExclusion { path: String },
+    /// [`apply_defender_exclusion`] with `excluded = false`
+    RemoveDefenderExclusion { path: String },
+}
+
+/// The broker's answer to a [`BrokerRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BrokerResponse {
+    Ok,
+    Error { message: String },
+}
+
+/// Connection to a running elevation broker
+pub struct ElevationBroker {
+    pid: u32,
+    pipe: tokio::sync::Mutex<BufReader<NamedPipeClient>>,
+    connected: AtomicBool,
+}
+
+impl ElevationBroker {
+    /// Start an elevated broker and connect to it
+    ///
+    /// Shows the UAC prompt; fails if the user declines it.
+    pub async fn launch() -> Result<Self> {
+        let pipe_name = format!(r"\\.\pipe\opcode-elevation-{}", uuid::Uuid::new_v4());
+        let user_sid = current_user_sid()?;
+        let exe = std::env::current_exe().context("Failed to locate the opcode executable")?;
+        let args = [
+            ELEVATION_BROKER_FLAG.to_string(),
+            pipe_name.clone(),
+            std::process::id().to_string(),
+            user_sid,
+        ];
+
+        info!("Starting elevation broker on {}", pipe_name);
+        let exe_path = exe.to_string_lossy().to_string();
+        let pid = tokio::task::spawn_blocking(move || {
+            match shell_execute_elevated(&exe_path, &args, None, false)? {
+                Some(process) => Ok(process.pid()),
+                None => {
+                    warn!("User declined the elevation broker UAC prompt");
+                    Err(anyhow::anyhow!("Administrator access was declined"))
+                }
+            }
+        })
+        .await
+        .context("Elevation broker launch task failed")??;
+
+        let client = connect_to_broker(&pipe_name, pid).await?;
+        let broker = ElevationBroker {
+            pid,
+            pipe: tokio::sync::Mutex::new(BufReader::new(client)),
+            connected: AtomicBool::new(true),
+        };
+        broker.call(BrokerRequest::Ping).await?;
+
+        info!("Elevation broker {} is ready", pid);
+        Ok(broker)
+    }
+
+    /// Process ID of the broker
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Whether the pipe to the broker is still usable
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Perform an operation in the broker
+    ///
+    /// Requests are handled one at a time. An error from the operation is
+    /// returned with the broker's message; the broker stays available.
+    pub async fn call(&self, request: BrokerRequest) -> Result<()> {
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+
+        let mut pipe = self.pipe.lock().await;
+        let response = async {
+            pipe.get_mut().write_all(line.as_bytes()).await?;
+            let mut response = String::new();
+            if pipe.read_line(&mut response).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+            }
+            Ok(response)
+        }
+        .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.connected.store(false, Ordering::SeqCst);
+                return Err(e).context("Lost the connection to the elevation broker");
+            }
+        };
+
+        match serde_json::from_str(&response).context("Invalid response from the elevation broker")? {
+            BrokerResponse::Ok => Ok(()),
+            BrokerResponse::Error { message } => Err(anyhow::anyhow!(message)),
+        }
+    }
+}
+
+/// The broker shared by the whole app, launched on first use
+static SHARED_BROKER: tokio::sync::Mutex<Option<Arc<ElevationBroker>>> =
+    tokio::sync::Mutex::const_new(None);
+
+/// Get the app-wide broker, starting it (with a UAC prompt) if it is not
+/// running yet or its connection was lost
+pub async fn shared_elevation_broker() -> Result<Arc<ElevationBroker>> {
+    let mut shared = SHARED_BROKER.lock().await;
+    if let Some(broker) = shared.as_ref().filter(|b| b.is_connected()) {
+        return Ok(broker.clone());
+    }
+
+    let broker = Arc::new(ElevationBroker::launch().await?);
+    *shared = Some(broker.clone());
+    Ok(broker)
+}
+
+/// Perform `request` with administrator rights
+///
+/// Runs it in this process when opcode is already elevated and sends it to
+/// the [shared broker](shared_elevation_broker) otherwise, so callers never
+/// start an elevated process of their own.
+pub async fn run_as_admin(request: BrokerRequest) -> Result<()> {
+    if is_running_as_admin().unwrap_or(false) {
+        return tokio::task::spawn_blocking(move || handle_request(request))
+            .await
+            .context("Elevated operation task failed")?;
+    }
+    shared_elevation_broker().await?.call(request).await
+}
+
+/// Run the broker if the command line asks for it
+///
+/// Call this first thing in `main`. Returns the exit code when this process
+/// was started as the broker, `None` when it is a normal opcode process.
+pub fn run_elevation_broker_from_args(args: &[String]) -> Option<i32> {
+    let (pipe_name, client_pid, client_sid) = parse_broker_args(args)?;
+
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start the elevation broker runtime: {}", e);
+            return Some(1);
+        }
+    };
+
+    match runtime.block_on(serve(&pipe_name, client_pid, &client_sid)) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            error!("Elevation broker failed: {:#}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Pipe name, client process ID and client user SID from the broker's command line
+///
+/// The flag must be the first argument, as opcode starts the broker with
+/// nothing else, so it can't be smuggled in after a deep link.
+fn parse_broker_args(args: &[String]) -> Option<(String, u32, String)> {
+    let [_, flag, pipe_name, client_pid, client_sid] = args else {
+        return None;
+    };
+    if flag != ELEVATION_BROKER_FLAG
+        || !pipe_name.starts_with(r"\\.\pipe\opcode-elevation-")
+        || !is_sid_string(client_sid)
+    {
+        return None;
+    }
+    Some((
+        pipe_name.clone(),
+        client_pid.parse().ok()?,
+        client_sid.clone(),
+    ))
+}
+
+/// Whether `sid` is a SID in string form such as `S-1-5-21-1-2-3-1001`
+///
+/// It goes into the pipe's SDDL as is, so anything else, such as a `)`
+/// closing the ACE early, must be rejected.
+fn is_sid_string(sid: &str) -> bool {
+    sid.strip_prefix("S-1-").is_some_and(|rest| {
+        rest.split('-')
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// Serve requests from the opcode process `client_pid` until it disconnects
+async fn serve(pipe_name: &str, client_pid: u32, client_sid: &str) -> Result<()> {
+    // Full access for the client user and SYSTEM only; the Medium label lets
+    // the unelevated client write to a pipe created by an elevated process
+    let sddl = format!("D:P(A;;GA;;;{})(A;;GA;;;SY)S:(ML;;NW;;;ME)", client_sid);
+    let server = create_pipe(pipe_name, &sddl)?;
+    tokio::time::timeout(BROKER_CONNECT_TIMEOUT, server.connect())
+        .await
+        .context("opcode did not connect to the elevation broker")?
+        .context("Failed to accept the opcode connection")?;
+
+    let connected_pid = pipe_client_pid(&server)?;
+    if connected_pid != client_pid {
+        anyhow::bail!(
+            "Rejected connection from process {} (expected {})",
+            connected_pid,
+            client_pid
+        );
+    }
+    info!("Elevation broker serving process {}", client_pid);
+
+    let mut pipe = BufReader::new(server);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if pipe.read_line(&mut line).await? == 0 {
+            info!("opcode disconnected, stopping elevation broker");
+            return Ok(());
+        }
+
+        let response = match serde_json::from_str::<BrokerRequest>(&line) {
+            Ok(request) => {
+                debug!("Elevation broker request: {:?}", request);
+                match tokio::task::spawn_blocking(move || handle_request(request)).await {
+                    Ok(Ok(())) => BrokerResponse::Ok,
+                    Ok(Err(e)) => BrokerResponse::Error {
+                        message: format!("{:#}", e),
+                    },
+                    Err(e) => BrokerResponse::Error {
+                        message: format!("Elevation broker task failed: {}", e),
+                    },
+                }
+            }
+            Err(e) => BrokerResponse::Error {
+                message: format!("Invalid elevation broker request: {}", e),
+            },
+        };
+
+        let mut reply = serde_json::to_string(&response)?;
+        reply.push('\n');
+        pipe.get_mut().write_all(reply.as_bytes()).await?;
+    }
+}
+
+fn handle_request(request: BrokerRequest) -> Result<()> {
+    match request {
+        BrokerRequest::Ping => Ok(()),
+        BrokerRequest::ApplyRegistryPlan {
+            plan,
+            take_ownership,
+        } => apply_registry_plan_with_consent(&plan, take_ownership),
+        BrokerRequest::TakeRegistryKeyOwnership { root, key } => {
+            take_registry_key_ownership(root, &key)
+        }
+        BrokerRequest::SetFileAcl { path, permissions } => set_file_acl(&path, &permissions),
+        BrokerRequest::RemoveFileAcl { path, principal } => remove_file_acl(&path, &principal),
+        BrokerRequest::ResetFileAcl { path } => reset_file_acl(&path),
+        BrokerRequest::ApplyAclChanges { path, changes } => apply_acl_changes(&path, &changes),
+        BrokerRequest::TakeFileOwnership { path } => take_file_ownership(&path),
+        BrokerRequest::AddDefenderExclusion { path } => apply_defender_exclusion(&path, true),
+        BrokerRequest::RemoveDefenderExclusion { path } => apply_defender_exclusion(&path, false),
+    }
+}
+
+/// Create a single-instance local pipe protected by the security descriptor `sddl`
+fn create_pipe(pipe_name: &str, sddl: &str) -> Result<NamedPipeServer> {
+    use winapi::shared::sddl::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::PSECURITY_DESCRIPTOR;
+
+    let sddl = to_wide_string(sddl);
+
+    unsafe {
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl.as_ptr(),
+            SDDL_REVISION_1 as DWORD,
+            &mut descriptor,
+            ptr::null_mut(),
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to build the pipe's security descriptor");
+        }
+
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+            lpSecurityDescriptor: descriptor,
+            bInheritHandle: FALSE,
+        };
+        let server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .max_instances(1)
+            .create_with_security_attributes_raw(pipe_name, &mut attributes as *mut _ as _);
+        LocalFree(descriptor as _);
+
+        server.with_context(|| format!("Failed to create the pipe {}", pipe_name))
+    }
+}
+
+/// Process ID of the client connected to a pipe
+fn pipe_client_pid(server: &NamedPipeServer) -> Result<u32> {
+    use winapi::um::winbase::GetNamedPipeClientProcessId;
+
+    let mut pid: DWORD = 0;
+    if unsafe { GetNamedPipeClientProcessId(server.as_raw_handle() as _, &mut pid) } == FALSE {
+        return Err(std::io::Error::last_os_error()).context("Failed to identify the pipe client");
+    }
+    Ok(pid)
+}
+
+/// Connect to the broker's pipe, waiting for the broker to create it, and
+/// make sure it is served by `broker_pid`
+async fn connect_to_broker(pipe_name: &str, broker_pid: u32) -> Result<NamedPipeClient> {
+    use winapi::um::winbase::GetNamedPipeServerProcessId;
+
+    let deadline = tokio::time::Instant::now() + PIPE_WAIT_TIMEOUT;
+    let client = loop {
+        match ClientOptions::new().open(pipe_name) {
+            Ok(client) => break client,
+            Err(e)
+                if (e.kind() == std::io::ErrorKind::NotFound
+                    || e.raw_os_error() == Some(ERROR_PIPE_BUSY))
+                    && tokio::time::Instant::now() < deadline =>
+            {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => {
+                return Err(e).context("Failed to connect to the elevation broker");
+            }
+        }
+    };
+
+    let mut server_pid: DWORD = 0;
+    if unsafe { GetNamedPipeServerProcessId(client.as_raw_handle() as _, &mut server_pid) } == FALSE {
+        return Err(std::io::Error::last_os_error()).context("Failed to identify the pipe server");
+    }
+    if server_pid != broker_pid {
+        anyhow::bail!(
+            "The broker pipe is served by process {} instead of the launched broker {}",
+            server_pid,
+            broker_pid
+        );
+    }
+
+    Ok(client)
+}
+
+/// Command line flag carrying the pipe an elevated relaunch reports its result on
+pub const ELEVATION_RESULT_FLAG: &str = "--elevation-result-pipe";
+
+/// Outcome of an operation performed by an elevated relaunch, reported back
+/// to the unelevated opcode with [`report_elevation_result`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElevationReport {
+    pub success: bool,
+    /// Human-readable summary or error message
+    pub message: Option<String>,
+    /// Operation-specific result data
+    pub details: Option<serde_json::Value>,
+}
+
+/// What [`run_elevated_with_result`] learned about the elevated relaunch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ElevatedRunOutcome {
+    /// The user declined the UAC prompt
+    Declined,
+    /// The elevated process reported back
+    Reported { report: ElevationReport },
+    /// The elevated process exited without reporting
+    Exited { exit_code: u32 },
+}
+
+/// Relaunch a program elevated and wait for it to report its result
+///
+/// The program is started with `--elevation-result-pipe <pipe> <pid>`
+/// appended to `args` and is expected to call [`report_elevation_result`]
+/// before exiting. The pipe is created by this process, only admits the
+/// current user and administrators, and only accepts a report from the
+/// process that was launched. Programs that exit without reporting yield
+/// [`ElevatedRunOutcome::Exited`] with their exit code.
+///
+/// # Arguments
+/// * `executable_path` - Program to run elevated, usually opcode itself
+/// * `args` - Arguments selecting the operation
+/// * `timeout` - How long to wait for the report; `None` waits indefinitely
+///
+/// # Example
+/// ```rust
+/// use crate::windows::elevation_broker::{run_elevated_with_result, ElevatedRunOutcome};
+///
+/// async fn register_for_all_users() -> anyhow::Result<()> {
+///     let exe = std::env::current_exe()?;
+///     match run_elevated_with_result(&exe.to_string_lossy(), &["--register-machine"], None).await? {
+///         ElevatedRunOutcome::Reported { report } if report.success => Ok(()),
+///         ElevatedRunOutcome::Reported { report } => Err(anyhow::anyhow!(report.message.unwrap_or_default())),
+///         ElevatedRunOutcome::Exited { exit_code } => Err(anyhow::anyhow!("Exited with {}", exit_code)),
+///         ElevatedRunOutcome::Declined => Err(anyhow::anyhow!("Administrator access was declined")),
+///     }
+/// }
+/// ```
+pub async fn run_elevated_with_result(
+    executable_path: &str,
+    args: &[&str],
+    timeout: Option<Duration>,
+) -> Result<ElevatedRunOutcome> {
+    let pipe_name = format!(r"\\.\pipe\opcode-elevation-result-{}", uuid::Uuid::new_v4());
+    let sddl = format!("D:P(A;;GA;;;{})(A;;GA;;;BA)(A;;GA;;;SY)", current_user_sid()?);
+    let server = create_pipe(&pipe_name, &sddl)?;
+
+    let mut elevated_args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    elevated_args.extend([
+        ELEVATION_RESULT_FLAG.to_string(),
+        pipe_name.clone(),
+        std::process::id().to_string(),
+    ]);
+
+    // The process handle can't leave the blocking task, so it sends the PID
+    // out and then waits for the exit there
+    let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+    let executable = executable_path.to_string();
+    let exited = tokio::task::spawn_blocking(move || -> Result<Option<u32>> {
+        let process = shell_execute_elevated(&executable, &elevated_args, None, false)?;
+        let _ = pid_tx.send(process.as_ref().map(|p| p.pid()));
+        match process {
+            Some(process) => process.wait(None),
+            None => Ok(None),
+        }
+    });
+
+    let Ok(Some(pid)) = pid_rx.await else {
+        // Either declined, or the launch failed and the task holds the error
+        exited.await.context("Elevation task failed")??;
+        return Ok(ElevatedRunOutcome::Declined);
+    };
+    info!("Waiting for elevated process {} to report on {}", pid, pipe_name);
+
+    let wait_for_report = async {
+        // A report that arrives as the process exits must win over the exit
+        tokio::select! {
+            biased;
+            connected = server.connect() => {
+                connected.context("Failed to accept the elevated process's report")?;
+                let reporter = pipe_client_pid(&server)?;
+                if reporter != pid {
+                    anyhow::bail!("Rejected a report from process {} (expected {})", reporter, pid);
+                }
+
+                let mut line = String::new();
+                BufReader::new(server).read_line(&mut line).await?;
+                let report: ElevationReport = serde_json::from_str(&line)
+                    .context("Invalid report from the elevated process")?;
+                Ok(ElevatedRunOutcome::Reported { report })
+            }
+            exit_code = exited => {
+                let exit_code = exit_code.context("Elevation task failed")??.unwrap_or(1);
+                Ok(ElevatedRunOutcome::Exited { exit_code })
+            }
+        }
+    };
+
+    let outcome = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, wait_for_report)
+            .await
+            .with_context(|| format!("Elevated process {} did not report within {:?}", pid, timeout))??,
+        None => wait_for_report.await?,
+    };
+    debug!("Elevated process {} outcome: {:?}", pid, outcome);
+    Ok(outcome)
+}
+
+/// Report the outcome of an elevated relaunch to the opcode that started it
+///
+/// Returns `Ok(false)` if this process was not started by
+/// [`run_elevated_with_result`], so it can be called unconditionally.
+///
+/// # Arguments
+/// * `args` - This process's command line
+/// * `report` - Outcome to hand back
+pub fn report_elevation_result(args: &[String], report: &ElevationReport) -> Result<bool> {
+    use std::io::Write;
+    use winapi::um::winbase::GetNamedPipeServerProcessId;
+
+    let Some((pipe_name, caller_pid)) = parse_result_args(args) else {
+        return Ok(false);
+    };
+
+    let mut pipe = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&pipe_name)
+        .with_context(|| format!("Failed to open the result pipe {}", pipe_name))?;
+
+    let mut server_pid: DWORD = 0;
+    if unsafe { GetNamedPipeServerProcessId(pipe.as_raw_handle() as _, &mut server_pid) } == FALSE {
+        return Err(std::io::Error::last_os_error()).context("Failed to identify the pipe server");
+    }
+    if server_pid != caller_pid {
+        anyhow::bail!(
+            "The result pipe is served by process {} instead of the caller {}",
+            server_pid,
+            caller_pid
+        );
+    }
+
+    let mut line = serde_json::to_string(report)?;
+    line.push('\n');
+    pipe.write_all(line.as_bytes())
+        .context("Failed to send the elevation result")?;
+    Ok(true)
+}
+
+/// Result pipe name and caller process ID from an elevated relaunch's command line
+fn parse_result_args(args: &[String]) -> Option<(String, u32)> {
+    let position = args.iter().position(|a| a == ELEVATION_RESULT_FLAG)?;
+    let pipe_name = args.get(position + 1)?;
+    let caller_pid = args.get(position + 2)?.parse().ok()?;
+
+    if !pipe_name.starts_with(r"\\.\pipe\opcode-elevation-result-") {
+        return None;
+    }
+    Some((pipe_name.clone(), caller_pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_broker_args() {
+        let args: Vec<String> = [
+            r"C:\Program Files\opcode\opcode.exe",
+            ELEVATION_BROKER_FLAG,
+            r"\\.\pipe\opcode-elevation-1234",
+            "4242",
+            "S-1-5-21-1-2-3-1001",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(
+            parse_broker_args(&args),
+            Some((
+                r"\\.\pipe\opcode-elevation-1234".to_string(),
+                4242,
+                "S-1-5-21-1-2-3-1001".to_string()
+            ))
+        );
+
+        assert_eq!(parse_broker_args(&args[..1]), None);
+        assert_eq!(parse_broker_args(&args[..4]), None);
+
+        let mut foreign_pipe = args.clone();
+        foreign_pipe[2] = r"\\.\pipe\other".to_string();
+        assert_eq!(parse_broker_args(&foreign_pipe), None);
+
+        let mut after_deep_link = args.clone();
+        after_deep_link.insert(1, "--deep-link".to_string());
+        assert_eq!(parse_broker_args(&after_deep_link), None);
+
+        let mut injected_sid = args.clone();
+        injected_sid[4] = "S-1-5-21-1-2-3-1001)(A;;GA;;;WD".to_string();
+        assert_eq!(parse_broker_args(&injected_sid), None);
+    }
+
+    #[test]
+    fn test_is_sid_string() {
+        assert!(is_sid_string("S-1-5-18"));
+        assert!(is_sid_string("S-1-5-21-1-2-3-1001"));
+        assert!(!is_sid_string("S-1-"));
+        assert!(!is_sid_string("S-1-5--18"));
+        assert!(!is_sid_string("S-1-5-21)(A;;GA;;;WD"));
+        assert!(!is_sid_string("WD"));
+    }
+
+    #[test]
+    fn test_parse_result_args() {
+        let args: Vec<String> = [
+            "opcode.exe",
+            "--register-machine",
+            ELEVATION_RESULT_FLAG,
+            r"\\.\pipe\opcode-elevation-result-99",
+            "4242",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(
+            parse_result_args(&args),
+            Some((r"\\.\pipe\opcode-elevation-result-99".to_string(), 4242))
+        );
+        assert_eq!(parse_result_args(&args[..2]), None);
+
+        // Not started by run_elevated_with_result: nothing to report
+        assert!(!report_elevation_result(
+            &args[..2],
+            &ElevationReport {
+                success: true,
+                message: None,
+                details: None,
+            }
+        )
+        .unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore] // Integration test - requires Windows
+    async fn test_report_elevation_result() {
+        let pipe_name = format!(r"\\.\pipe\opcode-elevation-result-{}", uuid::Uuid::new_v4());
+        let sddl = format!("D:P(A;;GA;;;{})", current_user_sid().unwrap());
+        let server = create_pipe(&pipe_name, &sddl).unwrap();
+
+        let report = ElevationReport {
+            success: false,
+            message: Some("Access is denied".to_string()),
+            details: Some(serde_json::json!({ "key": "HKLM\\Software\\Classes\\.opcode" })),
+        };
+        let args = vec![
+            ELEVATION_RESULT_FLAG.to_string(),
+            pipe_name.clone(),
+            std::process::id().to_string(),
+        ];
+        let sent = report.clone();
+        let reporter = tokio::task::spawn_blocking(move || report_elevation_result(&args, &sent));
+
+        server.connect().await.unwrap();
+        assert_eq!(pipe_client_pid(&server).unwrap(), std::process::id());
+        let mut line = String::new();
+        BufReader::new(server).read_line(&mut line).await.unwrap();
+        assert_eq!(serde_json::from_str::<ElevationReport>(&line).unwrap(), report);
+        assert!(reporter.await.unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = BrokerRequest::SetFileAcl {
+            path: r"C:\ProgramData\opcode".to_string(),
+            permissions: "Users:R".to_string(),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"op":"set_file_acl","path":"C:\\ProgramData\\opcode","permissions":"Users:R"}"#
+        );
+        assert_eq!(serde_json::from_str::<BrokerRequest>(&json).unwrap(), request);
+
+        let response: BrokerResponse =
+            serde_json::from_str(r#"{"status":"error","message":"denied"}"#).unwrap();
+        assert_eq!(
+            response,
+            BrokerResponse::Error {
+                message: "denied".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Integration test - requires Windows
+    async fn test_serve_and_call() {
+        let pipe_name = format!(r"\\.\pipe\opcode-elevation-{}", uuid::Uuid::new_v4());
+        let own_pid = std::process::id();
+        let sid = current_user_sid().unwrap();
+
+        let server_pipe = pipe_name.clone();
+        let server = tokio::spawn(async move { serve(&server_pipe, own_pid, &sid).await });
+
+        let client = connect_to_broker(&pipe_name, own_pid).await.unwrap();
+        let broker = ElevationBroker {
+            pid: own_pid,
+            pipe: tokio::sync::Mutex::new(BufReader::new(client)),
+            connected: AtomicBool::new(true),
+        };
+        broker.call(BrokerRequest::Ping).await.unwrap();
+        let error = broker
+            .call(BrokerRequest::ResetFileAcl {
+                path: r"C:\opcode\does\not\exist".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert!(!error.to_string().is_empty());
+        assert!(broker.is_connected());
+
+        drop(broker);
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+//! Time since the user's last keyboard or mouse input
+//!
+//! `GetLastInputInfo` reports input to any window in the session, so typing
+//! in another application or reading in opcode's own window both count as
+//! activity, unlike the app-level events background maintenance otherwise
+//! relies on.
+
+use anyhow::Result;
+use std::time::Duration;
+use winapi::um::sysinfoapi::GetTickCount;
+use winapi::um::winuser::{GetLastInputInfo, LASTINPUTINFO};
+
+/// How long ago the last keyboard or mouse input in this session was
+pub fn input_idle_time() -> Result<Duration> {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    // Both tick counts wrap after 49.7 days; the wrapping difference stays
+    // correct across the wrap
+    let idle_ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+    Ok(Duration::from_millis(u64::from(idle_ms)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_input_idle_time() {
+        let idle = input_idle_time().unwrap();
+        assert!(idle < Duration::from_secs(50 * 24 * 60 * 60));
+    }
+}
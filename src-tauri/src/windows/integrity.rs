@@ -0,0 +1,753 @@
+//! Windows mandatory integrity levels for files and processes
+//!
+//! Every process token and securable object carries an integrity label
+//! (Low, Medium, High, ...). A process cannot write to objects labelled above
+//! its own level, whatever the ACL says, so running an untrusted script at Low
+//! integrity keeps it from modifying the user's files and registry while
+//! still letting it read them. This is a lightweight containment mode for
+//! agent-generated scripts, not a sandbox: Low processes can still read most
+//! data and use the network.
+//!
+//! # Features
+//! - **File Labels**: Read and set the integrity label of files and directories
+//! - **Process Levels**: Read the integrity level of the current or any process
+//! - **Low Integrity Spawning**: Start children with a Low integrity token
+//!
+//! # Example
+//! ```rust
+//! use crate::windows::integrity::*;
+//!
+//! fn run_untrusted(script: &str, scratch_dir: &str) -> anyhow::Result<()> {
+//!     // Let the Low integrity script write to its scratch directory only
+//!     set_file_integrity_level(scratch_dir, IntegrityLevel::Low)?;
+//!
+//!     let child = spawn_low_integrity(
+//!         "powershell.exe",
+//!         &["-NoProfile", "-File", script],
+//!         Some(std::path::Path::new(scratch_dir)),
+//!     )?;
+//!     let exit_code = child.wait(None)?;
+//!     println!("Script exited with {:?}", exit_code);
+//!     Ok(())
+//! }
+//! ```
+
+use super::{to_wide_path, to_wide_string};
+use crate::utils::cmdline::join_windows_args;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::ptr;
+use std::time::Duration;
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use winapi::shared::sddl::ConvertStringSidToSidW;
+use winapi::shared::winerror::{ERROR_SUCCESS, WAIT_TIMEOUT};
+use winapi::um::accctrl::SE_FILE_OBJECT;
+use winapi::um::aclapi::{GetNamedSecurityInfoW, SetNamedSecurityInfoW};
+use winapi::um::handleapi::{CloseHandle, SetHandleInformation, INVALID_HANDLE_VALUE};
+use winapi::um::processenv::GetStdHandle;
+use winapi::um::processthreadsapi::{
+    CreateProcessAsUserW, DeleteProcThreadAttributeList, GetCurrentProcess, GetExitCodeProcess,
+    GetProcessId, InitializeProcThreadAttributeList, OpenProcess, OpenProcessToken,
+    TerminateProcess, UpdateProcThreadAttribute, LPPROC_THREAD_ATTRIBUTE_LIST, PROCESS_INFORMATION,
+    STARTUPINFOW,
+};
+use winapi::um::securitybaseapi::{
+    AddMandatoryAce, DuplicateTokenEx, GetAce, GetAclInformation, GetLengthSid, GetSidSubAuthority,
+    GetSidSubAuthorityCount, GetTokenInformation, InitializeAcl, SetTokenInformation,
+};
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::{
+    LocalFree, CREATE_NO_WINDOW, CREATE_UNICODE_ENVIRONMENT, EXTENDED_STARTUPINFO_PRESENT,
+    HANDLE_FLAG_INHERIT, INFINITE, STARTF_USESTDHANDLES, STD_ERROR_HANDLE, STD_INPUT_HANDLE,
+    STD_OUTPUT_HANDLE, WAIT_OBJECT_0,
+};
+use winapi::um::winnt::{
+    AclSizeInformation, SecurityImpersonation, TokenIntegrityLevel, TokenPrimary, ACE_HEADER, ACL,
+    ACL_REVISION, ACL_SIZE_INFORMATION, CONTAINER_INHERIT_ACE, HANDLE, LABEL_SECURITY_INFORMATION,
+    OBJECT_INHERIT_ACE, PACL, PROCESS_QUERY_LIMITED_INFORMATION, PSECURITY_DESCRIPTOR, PSID,
+    SECURITY_MANDATORY_HIGH_RID, SECURITY_MANDATORY_LOW_RID, SECURITY_MANDATORY_MEDIUM_RID,
+    SECURITY_MANDATORY_SYSTEM_RID, SE_GROUP_INTEGRITY, SYSTEM_MANDATORY_LABEL_ACE,
+    SYSTEM_MANDATORY_LABEL_ACE_TYPE, SYSTEM_MANDATORY_LABEL_NO_WRITE_UP, TOKEN_ADJUST_DEFAULT,
+    TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE, TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+};
+
+/// Mandatory integrity level of a token or object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityLevel {
+    Untrusted,
+    Low,
+    Medium,
+    High,
+    System,
+}
+
+impl IntegrityLevel {
+    /// The relative identifier of the level's label SID (`S-1-16-<rid>`)
+    pub fn rid(&self) -> DWORD {
+        match self {
+            IntegrityLevel::Untrusted => 0,
+            IntegrityLevel::Low => SECURITY_MANDATORY_LOW_RID,
+            IntegrityLevel::Medium => SECURITY_MANDATORY_MEDIUM_RID,
+            IntegrityLevel::High => SECURITY_MANDATORY_HIGH_RID,
+            IntegrityLevel::System => SECURITY_MANDATORY_SYSTEM_RID,
+        }
+    }
+
+    /// The level a label RID falls into (e.g. Medium Plus counts as Medium)
+    pub fn from_rid(rid: DWORD) -> Self {
+        if rid < SECURITY_MANDATORY_LOW_RID {
+            IntegrityLevel::Untrusted
+        } else if rid < SECURITY_MANDATORY_MEDIUM_RID {
+            IntegrityLevel::Low
+        } else if rid < SECURITY_MANDATORY_HIGH_RID {
+            IntegrityLevel::Medium
+        } else if rid < SECURITY_MANDATORY_SYSTEM_RID {
+            IntegrityLevel::High
+        } else {
+            IntegrityLevel::System
+        }
+    }
+
+    /// The label SID in string form
+    pub fn label_sid(&self) -> String {
+        format!("S-1-16-{}", self.rid())
+    }
+}
+
+/// The integrity level a label SID stands for
+unsafe fn level_of_sid(sid: PSID) -> IntegrityLevel {
+    let count = *GetSidSubAuthorityCount(sid) as DWORD;
+    if count == 0 {
+        return IntegrityLevel::Untrusted;
+    }
+    IntegrityLevel::from_rid(*GetSidSubAuthority(sid, count - 1))
+}
+
+/// Read the integrity label of a file or directory
+///
+/// Objects without an explicit label are treated as Medium by Windows, so
+/// that is what's returned for them.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+pub fn get_file_integrity_level(file_path: &str) -> Result<IntegrityLevel> {
+    debug!("Reading integrity label of: {}", file_path);
+
+    let wide_path = to_wide_path(file_path);
+    let mut sacl: PACL = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+    unsafe {
+        let result = GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            LABEL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut sacl,
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to read the integrity label of {}", file_path));
+        }
+
+        let mut level = IntegrityLevel::Medium;
+        let mut size_info: ACL_SIZE_INFORMATION = std::mem::zeroed();
+        if !sacl.is_null()
+            && GetAclInformation(
+                sacl,
+                &mut size_info as *mut _ as _,
+                std::mem::size_of::<ACL_SIZE_INFORMATION>() as DWORD,
+                AclSizeInformation,
+            ) != FALSE
+        {
+            for index in 0..size_info.AceCount {
+                let mut ace: *mut winapi::ctypes::c_void = ptr::null_mut();
+                if GetAce(sacl, index, &mut ace) == FALSE {
+                    continue;
+                }
+                if (*(ace as *const ACE_HEADER)).AceType == SYSTEM_MANDATORY_LABEL_ACE_TYPE {
+                    let label = &*(ace as *const SYSTEM_MANDATORY_LABEL_ACE);
+                    level = level_of_sid(&label.SidStart as *const DWORD as PSID);
+                    break;
+                }
+            }
+        }
+
+        LocalFree(descriptor as _);
+        Ok(level)
+    }
+}
+
+/// Set the integrity label of a file or directory
+///
+/// The label uses the no-write-up policy: processes below `level` can read
+/// and execute the object but not modify it. Directory labels are inherited
+/// by files and subdirectories created later. Lowering a label to Low is
+/// allowed for the object's owner; raising it above the caller's own level
+/// requires administrator rights (`SeRelabelPrivilege`).
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+/// * `level` - Integrity level to assign
+pub fn set_file_integrity_level(file_path: &str, level: IntegrityLevel) -> Result<()> {
+    info!("Setting integrity level of {} to {:?}", file_path, level);
+
+    let inherit_flags = if Path::new(file_path).is_dir() {
+        OBJECT_INHERIT_ACE | CONTAINER_INHERIT_ACE
+    } else {
+        0
+    };
+
+    unsafe {
+        let wide_sid = to_wide_string(&level.label_sid());
+        let mut sid: PSID = ptr::null_mut();
+        if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+            return Err(std::io::Error::last_os_error()).context("Failed to create the label SID");
+        }
+
+        let acl_size = std::mem::size_of::<ACL>()
+            + std::mem::size_of::<SYSTEM_MANDATORY_LABEL_ACE>()
+            - std::mem::size_of::<DWORD>()
+            + GetLengthSid(sid) as usize;
+        // DWORD-aligned buffer, as InitializeAcl requires
+        let mut buffer = vec![0u32; acl_size.div_ceil(4)];
+        let sacl = buffer.as_mut_ptr() as PACL;
+
+        let built = InitializeAcl(sacl, acl_size as DWORD, ACL_REVISION as DWORD) != FALSE
+            && AddMandatoryAce(
+                sacl,
+                ACL_REVISION as DWORD,
+                inherit_flags as DWORD,
+                SYSTEM_MANDATORY_LABEL_NO_WRITE_UP,
+                sid,
+            ) != FALSE;
+        let build_error = std::io::Error::last_os_error();
+        LocalFree(sid as _);
+        if !built {
+            return Err(build_error).context("Failed to build the integrity label");
+        }
+
+        let mut wide_path = to_wide_path(file_path);
+        let result = SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            LABEL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            sacl,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to set the integrity label of {}", file_path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Closes a kernel handle on drop
+#[derive(Debug)]
+pub(crate) struct OwnedHandle(pub(crate) HANDLE);
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+/// Read the integrity level of a token opened with `TOKEN_QUERY`
+pub(crate) unsafe fn token_integrity_level(token: HANDLE) -> Result<IntegrityLevel> {
+    let mut size: DWORD = 0;
+    GetTokenInformation(token, TokenIntegrityLevel, ptr::null_mut(), 0, &mut size);
+    if size == 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("Failed to query the token integrity level size");
+    }
+
+    // DWORD-aligned buffer for TOKEN_MANDATORY_LABEL and the SID after it
+    let mut buffer = vec![0u32; (size as usize).div_ceil(4)];
+    if GetTokenInformation(
+        token,
+        TokenIntegrityLevel,
+        buffer.as_mut_ptr() as _,
+        size,
+        &mut size,
+    ) == FALSE
+    {
+        return Err(std::io::Error::last_os_error())
+            .context("Failed to query the token integrity level");
+    }
+
+    let label = &*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+    Ok(level_of_sid(label.Label.Sid))
+}
+
+/// Read the integrity level of a process
+///
+/// # Arguments
+/// * `pid` - Process ID, or `None` for the current process
+pub fn get_process_integrity_level(pid: Option<u32>) -> Result<IntegrityLevel> {
+    unsafe {
+        let process = match pid {
+            Some(pid) => {
+                let _debug_privilege = super::permissions::debug_privilege_if_elevated();
+                let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+                if handle.is_null() {
+                    return Err(std::io::Error::last_os_error())
+                        .with_context(|| format!("Failed to open process {}", pid));
+                }
+                OwnedHandle(handle)
+            }
+            // The pseudo handle must not be closed
+            None => OwnedHandle(ptr::null_mut()),
+        };
+        let process_handle = if process.0.is_null() {
+            GetCurrentProcess()
+        } else {
+            process.0
+        };
+
+        let mut token: HANDLE = ptr::null_mut();
+        if OpenProcessToken(process_handle, TOKEN_QUERY, &mut token) == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to open the process token");
+        }
+        let token = OwnedHandle(token);
+
+        token_integrity_level(token.0)
+    }
+}
+
+/// A process started by [`spawn_low_integrity`], with a restricted token, in
+/// an AppContainer or elevated
+pub struct ContainedChild {
+    process: OwnedHandle,
+    pid: u32,
+}
+
+impl ContainedChild {
+    /// Take ownership of a process created with `CreateProcess*`
+    ///
+    /// # Safety
+    /// The handles in `info` must be valid and not owned elsewhere.
+    pub(crate) unsafe fn from_process_information(info: &PROCESS_INFORMATION) -> Self {
+        CloseHandle(info.hThread);
+        ContainedChild {
+            process: OwnedHandle(info.hProcess),
+            pid: info.dwProcessId,
+        }
+    }
+
+    /// Take ownership of a process handle, e.g. from `ShellExecuteExW`
+    ///
+    /// # Safety
+    /// `process` must be a valid process handle not owned elsewhere.
+    pub(crate) unsafe fn from_process_handle(process: HANDLE) -> Self {
+        ContainedChild {
+            pid: GetProcessId(process),
+            process: OwnedHandle(process),
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The process handle, owned by `self`
+    pub(crate) fn handle(&self) -> HANDLE {
+        self.process.0
+    }
+
+    /// Wait for the process to exit and return its exit code
+    ///
+    /// Returns `Ok(None)` if `timeout` elapses first; `None` waits forever.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<Option<u32>> {
+        let millis = timeout
+            .map(|t| t.as_millis().min(INFINITE as u128 - 1) as DWORD)
+            .unwrap_or(INFINITE);
+
+        unsafe {
+            match WaitForSingleObject(self.process.0, millis) {
+                WAIT_OBJECT_0 => {
+                    let mut code: DWORD = 0;
+                    if GetExitCodeProcess(self.process.0, &mut code) == FALSE {
+                        return Err(std::io::Error::last_os_error())
+                            .context("Failed to read the exit code");
+                    }
+                    Ok(Some(code))
+                }
+                WAIT_TIMEOUT => Ok(None),
+                _ => Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to wait for process {}", self.pid)),
+            }
+        }
+    }
+
+    /// Terminate the process
+    pub fn kill(&self) -> Result<()> {
+        unsafe {
+            if TerminateProcess(self.process.0, 1) == FALSE {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to terminate process {}", self.pid));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start a program with a Low integrity token
+///
+/// The child runs as the current user but cannot modify files, directories
+/// or registry keys labelled Medium or above, which covers almost everything
+/// the user owns. Give it a writable scratch directory by labelling one Low
+/// with [`set_file_integrity_level`]. The child gets no console window and
+/// does not inherit opcode's handles, so its output is not captured; have
+/// the program write to a file in the scratch directory instead.
+///
+/// # Arguments
+/// * `program` - Executable to run (resolved through `PATH` like `CreateProcess` does)
+/// * `args` - Arguments, quoted for the Windows command line as needed
+/// * `working_dir` - Working directory, or `None` to use opcode's
+pub fn spawn_low_integrity(
+    program: &str,
+    args: &[&str],
+    working_dir: Option<&Path>,
+) -> Result<ContainedChild> {
+    info!("Spawning {} at Low integrity", program);
+
+    unsafe {
+        let token = open_current_process_token(
+            TOKEN_DUPLICATE | TOKEN_ADJUST_DEFAULT | TOKEN_QUERY | TOKEN_ASSIGN_PRIMARY,
+        )?;
+
+        let mut low_token: HANDLE = ptr::null_mut();
+        if DuplicateTokenEx(
+            token.0,
+            0,
+            ptr::null_mut(),
+            SecurityImpersonation,
+            TokenPrimary,
+            &mut low_token,
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error()).context("Failed to duplicate the token");
+        }
+        let low_token = OwnedHandle(low_token);
+
+        set_token_integrity_level(low_token.0, IntegrityLevel::Low)?;
+        spawn_with_token(
+            low_token.0,
+            program,
+            args,
+            working_dir,
+            ChildStdio::Detached,
+        )
+    }
+}
+
+/// Open the current process's token with the given access
+pub(crate) fn open_current_process_token(access: DWORD) -> Result<OwnedHandle> {
+    let mut token: HANDLE = ptr::null_mut();
+    unsafe {
+        if OpenProcessToken(GetCurrentProcess(), access, &mut token) == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to open the process token");
+        }
+    }
+    Ok(OwnedHandle(token))
+}
+
+/// Set the integrity level of a token opened with `TOKEN_ADJUST_DEFAULT`
+///
+/// # Safety
+/// `token` must be a valid token handle.
+pub(crate) unsafe fn set_token_integrity_level(token: HANDLE, level: IntegrityLevel) -> Result<()> {
+    let wide_sid = to_wide_string(&level.label_sid());
+    let mut sid: PSID = ptr::null_mut();
+    if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+        return Err(std::io::Error::last_os_error()).context("Failed to create the label SID");
+    }
+
+    let mut label: TOKEN_MANDATORY_LABEL = std::mem::zeroed();
+    label.Label.Sid = sid;
+    label.Label.Attributes = SE_GROUP_INTEGRITY;
+    let lowered = SetTokenInformation(
+        token,
+        TokenIntegrityLevel,
+        &mut label as *mut _ as _,
+        (std::mem::size_of::<TOKEN_MANDATORY_LABEL>() + GetLengthSid(sid) as usize) as DWORD,
+    ) != FALSE;
+    let error = std::io::Error::last_os_error();
+    LocalFree(sid as _);
+    if !lowered {
+        return Err(error)
+            .with_context(|| format!("Failed to set the token integrity level to {:?}", level));
+    }
+    Ok(())
+}
+
+/// Which of opcode's handles a contained child gets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChildStdio {
+    /// None; the child has no console and its output is lost
+    #[default]
+    Detached,
+    /// Opcode's standard input, output and error and nothing else, e.g. so
+    /// the Claude wrapper's pipes reach the Claude it starts
+    Inherit,
+}
+
+/// `PROC_THREAD_ATTRIBUTE_HANDLE_LIST`, which winapi does not define
+const PROC_THREAD_ATTRIBUTE_HANDLE_LIST: usize = 0x0002_0002;
+
+/// `STARTUPINFOEXW`, which winapi does not define
+#[repr(C)]
+struct StartupInfoEx {
+    startup_info: STARTUPINFOW,
+    attribute_list: LPPROC_THREAD_ATTRIBUTE_LIST,
+}
+
+/// Startup information for `CreateProcess*`, with the handles a
+/// [`ChildStdio`] asks for and room for further process attributes
+pub(crate) struct ChildStartup {
+    info: StartupInfoEx,
+    // Pointer-aligned buffer for the opaque attribute list
+    attribute_list: Vec<usize>,
+    inherited: Vec<HANDLE>,
+}
+
+impl ChildStartup {
+    /// Startup information for `stdio`, with room for `attributes` more
+    /// attributes set with [`ChildStartup::set_attribute`]
+    pub(crate) fn new(stdio: ChildStdio, attributes: DWORD) -> Result<Self> {
+        unsafe {
+            let mut info: StartupInfoEx = std::mem::zeroed();
+            let mut inherited = Vec::new();
+            if stdio == ChildStdio::Inherit {
+                let handles = [STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, STD_ERROR_HANDLE]
+                    .map(|id| GetStdHandle(id));
+                for &handle in &handles {
+                    if handle.is_null()
+                        || handle == INVALID_HANDLE_VALUE
+                        || inherited.contains(&handle)
+                    {
+                        continue;
+                    }
+                    if SetHandleInformation(handle, HANDLE_FLAG_INHERIT, HANDLE_FLAG_INHERIT)
+                        == FALSE
+                    {
+                        return Err(std::io::Error::last_os_error())
+                            .context("Failed to make a standard handle inheritable");
+                    }
+                    inherited.push(handle);
+                }
+                info.startup_info.dwFlags |= STARTF_USESTDHANDLES;
+                info.startup_info.hStdInput = handles[0];
+                info.startup_info.hStdOutput = handles[1];
+                info.startup_info.hStdError = handles[2];
+            }
+
+            let count = attributes + DWORD::from(!inherited.is_empty());
+            let mut attribute_list = Vec::new();
+            if count > 0 {
+                let mut size: SIZE_T = 0;
+                InitializeProcThreadAttributeList(ptr::null_mut(), count, 0, &mut size);
+                attribute_list = vec![0usize; size.div_ceil(std::mem::size_of::<usize>())];
+                if InitializeProcThreadAttributeList(
+                    attribute_list.as_mut_ptr() as _,
+                    count,
+                    0,
+                    &mut size,
+                ) == FALSE
+                {
+                    return Err(std::io::Error::last_os_error())
+                        .context("Failed to initialize the process attribute list");
+                }
+            }
+
+            let mut startup = ChildStartup {
+                info,
+                attribute_list,
+                inherited,
+            };
+            if !startup.inherited.is_empty() {
+                // Only the listed handles are inherited, not every inheritable one
+                let handles = startup.inherited.as_mut_ptr();
+                let size = startup.inherited.len() * std::mem::size_of::<HANDLE>();
+                startup.set_attribute(PROC_THREAD_ATTRIBUTE_HANDLE_LIST, handles as _, size)?;
+            }
+            Ok(startup)
+        }
+    }
+
+    /// Set a process attribute
+    ///
+    /// # Safety
+    /// `value` must point to `size` bytes that stay valid until the process
+    /// is created.
+    pub(crate) unsafe fn set_attribute(
+        &mut self,
+        attribute: usize,
+        value: *mut winapi::ctypes::c_void,
+        size: usize,
+    ) -> Result<()> {
+        if UpdateProcThreadAttribute(
+            self.attribute_list.as_mut_ptr() as _,
+            0,
+            attribute,
+            value,
+            size,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to set process attribute {:#x}", attribute));
+        }
+        Ok(())
+    }
+
+    /// `bInheritHandles` for `CreateProcess*`
+    pub(crate) fn inherit_handles(&self) -> BOOL {
+        if self.inherited.is_empty() {
+            FALSE
+        } else {
+            TRUE
+        }
+    }
+
+    /// `dwCreationFlags` for `CreateProcess*`
+    pub(crate) fn creation_flags(&self) -> DWORD {
+        let flags = CREATE_NO_WINDOW | CREATE_UNICODE_ENVIRONMENT;
+        if self.attribute_list.is_empty() {
+            flags
+        } else {
+            flags | EXTENDED_STARTUPINFO_PRESENT
+        }
+    }
+
+    /// `lpStartupInfo` for `CreateProcess*`
+    pub(crate) fn startup_info(&mut self) -> *mut STARTUPINFOW {
+        if self.attribute_list.is_empty() {
+            self.info.startup_info.cb = std::mem::size_of::<STARTUPINFOW>() as DWORD;
+            self.info.attribute_list = ptr::null_mut();
+        } else {
+            self.info.startup_info.cb = std::mem::size_of::<StartupInfoEx>() as DWORD;
+            self.info.attribute_list = self.attribute_list.as_mut_ptr() as _;
+        }
+        &mut self.info.startup_info
+    }
+}
+
+impl Drop for ChildStartup {
+    fn drop(&mut self) {
+        if !self.attribute_list.is_empty() {
+            unsafe {
+                DeleteProcThreadAttributeList(self.attribute_list.as_mut_ptr() as _);
+            }
+        }
+    }
+}
+
+/// Start a program with the given primary token
+///
+/// # Safety
+/// `token` must be a valid primary token opened with `TOKEN_ASSIGN_PRIMARY`.
+pub(crate) unsafe fn spawn_with_token(
+    token: HANDLE,
+    program: &str,
+    args: &[&str],
+    working_dir: Option<&Path>,
+    stdio: ChildStdio,
+) -> Result<ContainedChild> {
+    let mut command_line: Vec<&str> = vec![program];
+    command_line.extend_from_slice(args);
+    let mut wide_command_line = to_wide_string(&join_windows_args(&command_line));
+    let wide_dir = working_dir.map(|dir| to_wide_string(&dir.to_string_lossy()));
+
+    let mut startup = ChildStartup::new(stdio, 0)?;
+    let mut process_info: PROCESS_INFORMATION = std::mem::zeroed();
+
+    if CreateProcessAsUserW(
+        token,
+        ptr::null(),
+        wide_command_line.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        startup.inherit_handles(),
+        startup.creation_flags(),
+        ptr::null_mut(),
+        wide_dir.as_ref().map_or(ptr::null(), |dir| dir.as_ptr()),
+        startup.startup_info(),
+        &mut process_info,
+    ) == FALSE
+    {
+        let error = std::io::Error::last_os_error();
+        warn!("Failed to spawn {}: {}", program, error);
+        return Err(error).with_context(|| format!("Failed to start {}", program));
+    }
+
+    debug!("Started {} with PID {}", program, process_info.dwProcessId);
+    Ok(ContainedChild::from_process_information(&process_info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integrity_level_rids() {
+        for level in [
+            IntegrityLevel::Untrusted,
+            IntegrityLevel::Low,
+            IntegrityLevel::Medium,
+            IntegrityLevel::High,
+            IntegrityLevel::System,
+        ] {
+            assert_eq!(IntegrityLevel::from_rid(level.rid()), level);
+        }
+        // Medium Plus (UIAccess processes) counts as Medium
+        assert_eq!(IntegrityLevel::from_rid(0x2010), IntegrityLevel::Medium);
+        assert_eq!(IntegrityLevel::Low.label_sid(), "S-1-16-4096");
+        assert!(IntegrityLevel::Low < IntegrityLevel::Medium);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_file_integrity_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+
+        set_file_integrity_level(&path, IntegrityLevel::Low).unwrap();
+        assert_eq!(
+            get_file_integrity_level(&path).unwrap(),
+            IntegrityLevel::Low
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_spawn_low_integrity() {
+        assert!(get_process_integrity_level(None).unwrap() >= IntegrityLevel::Medium);
+
+        let child =
+            spawn_low_integrity("cmd.exe", &["/C", "ping -n 3 127.0.0.1 >NUL"], None).unwrap();
+        assert_eq!(
+            get_process_integrity_level(Some(child.pid())).unwrap(),
+            IntegrityLevel::Low
+        );
+        assert_eq!(child.wait(Some(Duration::from_secs(30))).unwrap(), Some(0));
+    }
+}
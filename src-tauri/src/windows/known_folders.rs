@@ -0,0 +1,152 @@
+//! Known Folder lookup through `SHGetKnownFolderPath`
+//!
+//! `%USERPROFILE%\Documents` and friends are wrong whenever a folder has been
+//! moved: OneDrive Known Folder Move, Group Policy folder redirection to a
+//! network share, or a user relocating Downloads to another drive.
+//! [`get_known_folder`] asks the shell for the real location instead.
+
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use windows_sys::core::{GUID, PWSTR};
+use windows_sys::Win32::UI::Shell as shell;
+
+/// A folder Windows can relocate per user or per machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KnownFolder {
+    /// `%USERPROFILE%`
+    Profile,
+    Desktop,
+    Documents,
+    Downloads,
+    Pictures,
+    Videos,
+    Music,
+    Favorites,
+    /// `%APPDATA%`
+    RoamingAppData,
+    /// `%LOCALAPPDATA%`
+    LocalAppData,
+    /// `%USERPROFILE%\AppData\LocalLow`, writable from Low integrity
+    LocalAppDataLow,
+    /// Per-user installs, `%LOCALAPPDATA%\Programs`
+    UserProgramFiles,
+    /// `%ProgramData%`
+    ProgramData,
+    ProgramFiles,
+    /// 32-bit Program Files; the same as [`KnownFolder::ProgramFiles`] on
+    /// 32-bit Windows
+    ProgramFilesX86,
+    /// `%PUBLIC%`
+    Public,
+    PublicDesktop,
+    PublicDocuments,
+    PublicPictures,
+    PublicVideos,
+    PublicMusic,
+    /// `%SystemRoot%`
+    Windows,
+    /// `%SystemRoot%\System32`
+    System,
+}
+
+impl KnownFolder {
+    fn id(self) -> GUID {
+        match self {
+            KnownFolder::Profile => shell::FOLDERID_Profile,
+            KnownFolder::Desktop => shell::FOLDERID_Desktop,
+            KnownFolder::Documents => shell::FOLDERID_Documents,
+            KnownFolder::Downloads => shell::FOLDERID_Downloads,
+            KnownFolder::Pictures => shell::FOLDERID_Pictures,
+            KnownFolder::Videos => shell::FOLDERID_Videos,
+            KnownFolder::Music => shell::FOLDERID_Music,
+            KnownFolder::Favorites => shell::FOLDERID_Favorites,
+            KnownFolder::RoamingAppData => shell::FOLDERID_RoamingAppData,
+            KnownFolder::LocalAppData => shell::FOLDERID_LocalAppData,
+            KnownFolder::LocalAppDataLow => shell::FOLDERID_LocalAppDataLow,
+            KnownFolder::UserProgramFiles => shell::FOLDERID_UserProgramFiles,
+            KnownFolder::ProgramData => shell::FOLDERID_ProgramData,
+            KnownFolder::ProgramFiles => shell::FOLDERID_ProgramFiles,
+            KnownFolder::ProgramFilesX86 => shell::FOLDERID_ProgramFilesX86,
+            KnownFolder::Public => shell::FOLDERID_Public,
+            KnownFolder::PublicDesktop => shell::FOLDERID_PublicDesktop,
+            KnownFolder::PublicDocuments => shell::FOLDERID_PublicDocuments,
+            KnownFolder::PublicPictures => shell::FOLDERID_PublicPictures,
+            KnownFolder::PublicVideos => shell::FOLDERID_PublicVideos,
+            KnownFolder::PublicMusic => shell::FOLDERID_PublicMusic,
+            KnownFolder::Windows => shell::FOLDERID_Windows,
+            KnownFolder::System => shell::FOLDERID_System,
+        }
+    }
+}
+
+/// Current location of a known folder
+///
+/// Honors redirection and OneDrive Known Folder Move. The folder isn't
+/// required to exist, so a redirected folder on an unreachable share still
+/// resolves to its path.
+pub fn get_known_folder(folder: KnownFolder) -> Result<PathBuf> {
+    use windows_sys::Win32::System::Com::CoTaskMemFree;
+
+    let id = folder.id();
+    let mut raw: PWSTR = std::ptr::null_mut();
+    let hr = unsafe {
+        shell::SHGetKnownFolderPath(
+            &id,
+            shell::KF_FLAG_DONT_VERIFY as u32,
+            std::ptr::null_mut(),
+            &mut raw,
+        )
+    };
+    // The buffer must be freed even when the call fails
+    let path = if raw.is_null() {
+        None
+    } else {
+        let len = (0..).take_while(|&i| unsafe { *raw.add(i) } != 0).count();
+        let path = String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(raw, len) });
+        unsafe { CoTaskMemFree(raw as _) };
+        Some(path)
+    };
+
+    match path {
+        Some(path) if hr >= 0 && !path.is_empty() => {
+            debug!("Known folder {:?} is {}", folder, path);
+            Ok(PathBuf::from(path))
+        }
+        _ => Err(anyhow::anyhow!(
+            "Failed to resolve the {:?} folder (error {:#010x})",
+            folder,
+            hr
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_folder_serialization() {
+        assert_eq!(
+            serde_json::to_string(&KnownFolder::LocalAppData).unwrap(),
+            "\"local_app_data\""
+        );
+        let folder: KnownFolder = serde_json::from_str("\"program_files_x86\"").unwrap();
+        assert_eq!(folder, KnownFolder::ProgramFilesX86);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_known_folder() {
+        let profile = get_known_folder(KnownFolder::Profile).unwrap();
+        assert!(profile.is_dir());
+        let local = get_known_folder(KnownFolder::LocalAppData).unwrap();
+        assert!(local.is_absolute());
+        assert!(get_known_folder(KnownFolder::System)
+            .unwrap()
+            .join("kernel32.dll")
+            .exists());
+    }
+}
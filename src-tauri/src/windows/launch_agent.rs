@@ -0,0 +1,141 @@
+//! Start at login on macOS through a per-user LaunchAgent
+//!
+//! launchd starts every agent in `~/Library/LaunchAgents` whose plist has
+//! `RunAtLoad` set when the user logs in, which is the macOS counterpart of
+//! the Windows `Run` key. Enabling auto-start writes such a plist for the
+//! app and disabling it removes the plist again.
+
+use crate::utils::atomic_write::atomic_write;
+use anyhow::{Context, Result};
+use log::info;
+use std::path::{Path, PathBuf};
+
+/// Bundle identifier the agent labels are namespaced under
+const LABEL_PREFIX: &str = "opcode.asterisk.so";
+
+/// launchd label of the agent for `app_name`, e.g. `opcode.asterisk.so.opcode`
+pub fn launch_agent_label(app_name: &str) -> String {
+    let name: String = app_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}.{}", LABEL_PREFIX, name)
+}
+
+/// Where the agent plist for `app_name` lives
+pub fn launch_agent_path(app_name: &str) -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not find home directory")?;
+    Ok(home
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", launch_agent_label(app_name))))
+}
+
+/// Escape text for an XML plist string
+pub(super) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Contents of a LaunchAgent plist that starts `executable_path` at login
+pub fn launch_agent_plist(label: &str, executable_path: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+</dict>
+</plist>
+"#,
+        xml_escape(label),
+        xml_escape(executable_path)
+    )
+}
+
+/// Enable or disable starting `executable_path` when the user logs in
+///
+/// Disabling succeeds when no agent is installed.
+pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
+    info!("Setting auto-start for {}: {}", app_name, enabled);
+    let path = launch_agent_path(app_name)?;
+
+    if !enabled {
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("Removed LaunchAgent {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+        }
+        return Ok(());
+    }
+
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for auto-start: {} (check installation)",
+            executable_path
+        );
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let plist = launch_agent_plist(&launch_agent_label(app_name), executable_path);
+    atomic_write(&path, plist)?;
+    info!("Installed LaunchAgent {}", path.display());
+    Ok(())
+}
+
+/// Whether a LaunchAgent for `app_name` is installed
+pub fn is_auto_start_enabled(app_name: &str) -> Result<bool> {
+    Ok(launch_agent_path(app_name)?.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launch_agent_label() {
+        assert_eq!(launch_agent_label("Opcode"), "opcode.asterisk.so.opcode");
+        assert_eq!(
+            launch_agent_label("My App/1"),
+            "opcode.asterisk.so.my-app-1"
+        );
+    }
+
+    #[test]
+    fn test_launch_agent_plist() {
+        let plist = launch_agent_plist(
+            "opcode.asterisk.so.opcode",
+            "/Applications/A & B.app/Contents/MacOS/opcode",
+        );
+        assert!(plist.contains("<string>opcode.asterisk.so.opcode</string>"));
+        assert!(
+            plist.contains("<string>/Applications/A &amp; B.app/Contents/MacOS/opcode</string>")
+        );
+        assert!(plist.contains("<key>RunAtLoad</key>\n    <true/>"));
+    }
+}
@@ -0,0 +1,228 @@
+//! URL schemes and document types on macOS through Launch Services
+//!
+//! The `opcode://` scheme and the `.opc` document type are declared in the
+//! bundle's Info.plist (`CFBundleURLTypes`, `CFBundleDocumentTypes` and
+//! `UTExportedTypeDeclarations`), which Launch Services reads when the app
+//! is installed. That makes Opcode *a* handler; when another app claimed
+//! the scheme or extension first, registering here makes Opcode the
+//! default, the counterpart of writing the Windows registry keys.
+
+use super::registry::RegistrationScope;
+use anyhow::{Context, Result};
+use log::info;
+use std::ffi::c_void;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Bundle identifier from tauri.conf.json
+const BUNDLE_ID: &str = "opcode.asterisk.so";
+
+type CFTypeRef = *const c_void;
+type CFStringRef = *const c_void;
+type CFURLRef = *const c_void;
+type OSStatus = i32;
+
+const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+/// `kLSRolesAll`
+const LS_ROLES_ALL: u32 = 0xFFFF_FFFF;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFStringCreateWithBytes(
+        alloc: CFTypeRef,
+        bytes: *const u8,
+        num_bytes: isize,
+        encoding: u32,
+        is_external_representation: u8,
+    ) -> CFStringRef;
+    fn CFURLCreateFromFileSystemRepresentation(
+        alloc: CFTypeRef,
+        buffer: *const u8,
+        buf_len: isize,
+        is_directory: u8,
+    ) -> CFURLRef;
+    fn CFRelease(cf: CFTypeRef);
+}
+
+#[link(name = "CoreServices", kind = "framework")]
+extern "C" {
+    fn LSRegisterURL(url: CFURLRef, update: u8) -> OSStatus;
+    fn LSSetDefaultHandlerForURLScheme(scheme: CFStringRef, handler: CFStringRef) -> OSStatus;
+    fn LSSetDefaultRoleHandlerForContentType(
+        content_type: CFStringRef,
+        role: u32,
+        handler: CFStringRef,
+    ) -> OSStatus;
+    fn UTTypeCreatePreferredIdentifierForTag(
+        tag_class: CFStringRef,
+        tag: CFStringRef,
+        conforming_to: CFStringRef,
+    ) -> CFStringRef;
+}
+
+/// An owned Core Foundation object, released on drop
+struct CfOwned(CFTypeRef);
+
+impl CfOwned {
+    fn string(text: &str) -> Result<Self> {
+        let string = unsafe {
+            CFStringCreateWithBytes(
+                std::ptr::null(),
+                text.as_ptr(),
+                text.len() as isize,
+                K_CF_STRING_ENCODING_UTF8,
+                0,
+            )
+        };
+        Self::check(string, "string")
+    }
+
+    fn directory_url(path: &Path) -> Result<Self> {
+        let bytes = path.as_os_str().as_bytes();
+        let url = unsafe {
+            CFURLCreateFromFileSystemRepresentation(
+                std::ptr::null(),
+                bytes.as_ptr(),
+                bytes.len() as isize,
+                1,
+            )
+        };
+        Self::check(url, "URL")
+    }
+
+    fn check(object: CFTypeRef, what: &str) -> Result<Self> {
+        if object.is_null() {
+            anyhow::bail!("Failed to create Core Foundation {}", what);
+        }
+        Ok(CfOwned(object))
+    }
+}
+
+impl Drop for CfOwned {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0) };
+    }
+}
+
+/// The `.app` bundle `executable_path` is part of
+fn app_bundle(executable_path: &Path) -> Option<&Path> {
+    executable_path
+        .ancestors()
+        .find(|ancestor| ancestor.extension().is_some_and(|e| e == "app"))
+}
+
+/// Make Launch Services read the Info.plist of the bundle that contains
+/// `executable_path`, so the declared types and schemes are known
+fn register_bundle(executable_path: &str) -> Result<()> {
+    let bundle = app_bundle(Path::new(executable_path)).with_context(|| {
+        format!(
+            "{} is not inside an .app bundle; install Opcode to register it as a handler",
+            executable_path
+        )
+    })?;
+    let url = CfOwned::directory_url(bundle)?;
+    let status = unsafe { LSRegisterURL(url.0, 1) };
+    if status != 0 {
+        anyhow::bail!(
+            "Failed to register {} with Launch Services (OSStatus {})",
+            bundle.display(),
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Make Opcode the default app for `*<extension>` files
+///
+/// The extension must be declared in Info.plist, which is where its type
+/// identifier, description and icon come from; `program_id` and
+/// `description` are only used on Windows.
+pub fn register_file_association(
+    extension: &str,
+    _program_id: &str,
+    executable_path: &str,
+    _description: &str,
+) -> Result<Option<RegistrationScope>> {
+    info!("Registering file association: {}", extension);
+    let tag = extension
+        .strip_prefix('.')
+        .filter(|tag| !tag.is_empty())
+        .with_context(|| format!("Invalid file extension: {}", extension))?;
+    register_bundle(executable_path)?;
+
+    let tag_class = CfOwned::string("public.filename-extension")?;
+    let tag = CfOwned::string(tag)?;
+    let content_type = CfOwned::check(
+        unsafe { UTTypeCreatePreferredIdentifierForTag(tag_class.0, tag.0, std::ptr::null()) },
+        "type identifier",
+    )?;
+    let handler = CfOwned::string(BUNDLE_ID)?;
+    let status =
+        unsafe { LSSetDefaultRoleHandlerForContentType(content_type.0, LS_ROLES_ALL, handler.0) };
+    if status != 0 {
+        anyhow::bail!(
+            "Failed to make Opcode the default app for {} files (OSStatus {})",
+            extension,
+            status
+        );
+    }
+
+    info!("Successfully registered file association: {}", extension);
+    Ok(Some(RegistrationScope::User))
+}
+
+/// Make Opcode the default handler of `<protocol>://` links
+///
+/// macOS delivers the links as Apple Events rather than arguments; the app
+/// picks them up through `RunEvent::Opened`.
+pub fn register_url_protocol(
+    protocol: &str,
+    executable_path: &str,
+    _description: &str,
+) -> Result<Option<RegistrationScope>> {
+    info!("Registering URL protocol: {}", protocol);
+    if protocol.is_empty()
+        || !protocol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    {
+        anyhow::bail!("Invalid URL protocol: {}", protocol);
+    }
+    register_bundle(executable_path)?;
+
+    let scheme = CfOwned::string(protocol)?;
+    let handler = CfOwned::string(BUNDLE_ID)?;
+    let status = unsafe { LSSetDefaultHandlerForURLScheme(scheme.0, handler.0) };
+    if status != 0 {
+        anyhow::bail!(
+            "Failed to make Opcode the handler of {}:// links (OSStatus {})",
+            protocol,
+            status
+        );
+    }
+
+    info!("Successfully registered URL protocol: {}://", protocol);
+    Ok(Some(RegistrationScope::User))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_bundle() {
+        assert_eq!(
+            app_bundle(Path::new("/Applications/opcode.app/Contents/MacOS/opcode")),
+            Some(Path::new("/Applications/opcode.app"))
+        );
+        assert_eq!(app_bundle(Path::new("/usr/local/bin/opcode")), None);
+    }
+
+    #[test]
+    fn test_invalid_registrations() {
+        assert!(register_file_association("", "Opcode.Document", "/bin/sh", "x").is_err());
+        assert!(register_url_protocol("op code", "/bin/sh", "x").is_err());
+        // Not inside a bundle
+        assert!(register_url_protocol("opcode", "/bin/sh", "x").is_err());
+    }
+}
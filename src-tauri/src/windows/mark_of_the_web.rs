@@ -0,0 +1,259 @@
+//! Mark-of-the-Web detection and removal
+//!
+//! Browsers and archive tools record where a downloaded file came from in a
+//! `Zone.Identifier` alternate data stream. Windows then blocks or warns about
+//! scripts and executables carrying it (SmartScreen, PowerShell execution
+//! policy, Office protected view), which makes downloaded agent definitions
+//! and plugins fail in ways that are hard to trace back to the download.
+//!
+//! [`get_mark_of_the_web`] and [`find_marked_files`] detect the mark;
+//! [`ensure_unblocked`] removes it, but only when the user agreed to, and
+//! otherwise fails with a [`BlockedFileError`] the UI can ask about.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::utils::ads::{read_ads, remove_ads};
+
+/// Name of the alternate data stream holding the mark
+const ZONE_IDENTIFIER_STREAM: &str = "Zone.Identifier";
+
+/// URL security zone a file was downloaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityZone {
+    LocalMachine,
+    Intranet,
+    Trusted,
+    Internet,
+    Restricted,
+    Unknown,
+}
+
+impl SecurityZone {
+    fn from_id(id: u32) -> Self {
+        match id {
+            0 => SecurityZone::LocalMachine,
+            1 => SecurityZone::Intranet,
+            2 => SecurityZone::Trusted,
+            3 => SecurityZone::Internet,
+            4 => SecurityZone::Restricted,
+            _ => SecurityZone::Unknown,
+        }
+    }
+
+    /// Whether Windows treats files from this zone as untrusted
+    pub fn is_untrusted(&self) -> bool {
+        matches!(self, SecurityZone::Internet | SecurityZone::Restricted)
+    }
+}
+
+/// Contents of a file's `Zone.Identifier` stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MarkOfTheWeb {
+    pub zone: SecurityZone,
+    /// Page the download was started from, if the browser recorded it
+    pub referrer_url: Option<String>,
+    /// URL the file was downloaded from, if the browser recorded it
+    pub host_url: Option<String>,
+}
+
+/// Error returned by [`ensure_unblocked`] when a file carries the
+/// Mark-of-the-Web and the user has not agreed to remove it
+///
+/// Detect it with `downcast_ref::<BlockedFileError>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockedFileError {
+    pub path: String,
+    pub mark: MarkOfTheWeb,
+}
+
+impl std::fmt::Display for BlockedFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} was downloaded from the internet", self.path)?;
+        if let Some(url) = &self.mark.host_url {
+            write!(f, " ({})", url)?;
+        }
+        write!(f, " and is blocked by Windows")
+    }
+}
+
+impl std::error::Error for BlockedFileError {}
+
+/// Parse the INI-style contents of a `Zone.Identifier` stream
+fn parse_zone_identifier(content: &str) -> MarkOfTheWeb {
+    let mut mark = MarkOfTheWeb {
+        zone: SecurityZone::Unknown,
+        referrer_url: None,
+        host_url: None,
+    };
+
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "ZoneId" => {
+                mark.zone = value
+                    .parse()
+                    .map(SecurityZone::from_id)
+                    .unwrap_or(SecurityZone::Unknown)
+            }
+            "ReferrerUrl" => mark.referrer_url = Some(value.to_string()),
+            "HostUrl" => mark.host_url = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    mark
+}
+
+/// Read the Mark-of-the-Web of a file, or `None` if it has none
+///
+/// Files on volumes without alternate data streams (FAT32, network shares
+/// of other systems) never carry the mark.
+pub fn get_mark_of_the_web(path: &Path) -> Result<Option<MarkOfTheWeb>> {
+    let content = read_ads(path, ZONE_IDENTIFIER_STREAM)?;
+    Ok(content.map(|content| parse_zone_identifier(&String::from_utf8_lossy(&content))))
+}
+
+/// Whether Windows will block or warn about the file because it was downloaded
+pub fn is_blocked(path: &Path) -> Result<bool> {
+    Ok(get_mark_of_the_web(path)?
+        .map(|mark| mark.zone.is_untrusted())
+        .unwrap_or(false))
+}
+
+/// Find blocked files in `dir` and its subdirectories, e.g. an extracted plugin
+///
+/// Symlinks are not followed. Files whose mark can't be read are logged and
+/// left out.
+pub fn find_marked_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut marked = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let entries = std::fs::read_dir(&current)
+            .with_context(|| format!("Failed to read {}", current.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                match is_blocked(&entry.path()) {
+                    Ok(true) => marked.push(entry.path()),
+                    Ok(false) => {}
+                    // A stream that can't be read says nothing about the
+                    // zone, so the file counts as unknown, not the scan failed
+                    Err(e) => warn!(
+                        "Failed to read the Mark-of-the-Web of {}: {:#}",
+                        entry.path().display(),
+                        e
+                    ),
+                }
+            }
+        }
+    }
+
+    debug!("Found {} blocked files in {}", marked.len(), dir.display());
+    Ok(marked)
+}
+
+/// Remove the Mark-of-the-Web from a file, returning whether it had one
+///
+/// Equivalent to "Unblock" in the file's properties or PowerShell's
+/// `Unblock-File`. Only call this after the user explicitly agreed, typically
+/// in response to a [`BlockedFileError`].
+pub fn remove_mark_of_the_web(path: &Path) -> Result<bool> {
+    let removed = remove_ads(path, ZONE_IDENTIFIER_STREAM)?;
+    if removed {
+        info!("Removed Mark-of-the-Web from {}", path.display());
+    }
+    Ok(removed)
+}
+
+/// Make sure Windows won't block `path` because it was downloaded
+///
+/// Unblocked files pass. A blocked file is unblocked when `unblock` is set
+/// (the user agreed); otherwise a [`BlockedFileError`] is returned so the
+/// caller can ask and try again.
+pub fn ensure_unblocked(path: &Path, unblock: bool) -> Result<()> {
+    let Some(mark) = get_mark_of_the_web(path)? else {
+        return Ok(());
+    };
+    if !mark.zone.is_untrusted() {
+        return Ok(());
+    }
+
+    if !unblock {
+        return Err(anyhow::Error::new(BlockedFileError {
+            path: path.to_string_lossy().to_string(),
+            mark,
+        }));
+    }
+    remove_mark_of_the_web(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zone_identifier() {
+        let mark = parse_zone_identifier(
+            "[ZoneTransfer]\r\nZoneId=3\r\nReferrerUrl=https://github.com/\r\nHostUrl=https://raw.githubusercontent.com/agent.opcode.json\r\n",
+        );
+        assert_eq!(mark.zone, SecurityZone::Internet);
+        assert!(mark.zone.is_untrusted());
+        assert_eq!(mark.referrer_url.as_deref(), Some("https://github.com/"));
+        assert_eq!(
+            mark.host_url.as_deref(),
+            Some("https://raw.githubusercontent.com/agent.opcode.json")
+        );
+
+        let mark = parse_zone_identifier("[ZoneTransfer]\nZoneId=1\n");
+        assert_eq!(mark.zone, SecurityZone::Intranet);
+        assert!(!mark.zone.is_untrusted());
+        assert_eq!(parse_zone_identifier("").zone, SecurityZone::Unknown);
+    }
+
+    #[test]
+    fn test_blocked_file_error_display() {
+        let error = BlockedFileError {
+            path: r"C:\Downloads\agent.opcode.json".to_string(),
+            mark: parse_zone_identifier("ZoneId=3\nHostUrl=https://example.com/agent.json"),
+        };
+        assert_eq!(
+            error.to_string(),
+            r"C:\Downloads\agent.opcode.json was downloaded from the internet (https://example.com/agent.json) and is blocked by Windows"
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_mark_and_unblock() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("agent.opcode.json");
+        std::fs::write(&file, "{}").unwrap();
+        assert_eq!(get_mark_of_the_web(&file).unwrap(), None);
+
+        crate::utils::ads::write_ads(
+            &file,
+            ZONE_IDENTIFIER_STREAM,
+            "[ZoneTransfer]\r\nZoneId=3\r\n",
+        )
+        .unwrap();
+        assert!(is_blocked(&file).unwrap());
+        assert_eq!(find_marked_files(dir.path()).unwrap(), vec![file.clone()]);
+
+        let error = ensure_unblocked(&file, false).unwrap_err();
+        assert!(error.downcast_ref::<BlockedFileError>().is_some());
+        ensure_unblocked(&file, true).unwrap();
+        assert!(!is_blocked(&file).unwrap());
+        assert!(!remove_mark_of_the_web(&file).unwrap());
+    }
+}
@@ -1,23 +1,184 @@
 //! Windows-specific runtime features for Opcode
 //!
 //! This module provides Windows-specific functionality including:
-//! - Process management with kill process tree functionality
+//! - Process management with kill process tree functionality, behind a
+//!   `ProcessManager` trait with one backend per platform
 //! - Registry operations for file associations and URL protocols
-//! - Permissions management including UAC and admin privilege checking
+//! - Permissions management including UAC policy detection and admin privilege checking
 //! - Windows ACL and security descriptor handling
+//! - Windows Terminal profile fragment for the Claude CLI
+//! - Mandatory integrity labels and Low integrity child processes
+//! - AppContainer sandbox for agent and tool subprocesses
+//! - Restricted-token child processes that never inherit admin rights
+//! - Elevation broker that performs admin operations after a single UAC prompt
+//! - An `Elevation` trait over UAC, polkit (`pkexec`) and macOS `osascript`
+//! - API keys and OAuth tokens stored in Windows Credential Manager, the
+//!   macOS Keychain or the Secret Service, behind a `SecretStore` trait
+//! - Windows Hello confirmation for destructive operations
+//! - Authenticode verification of the Claude CLI before launch
+//! - Mark-of-the-Web detection and unblocking of downloaded files
+//! - Controlled Folder Access detection with remediation steps
+//! - Consent-gated Windows Defender exclusions for project directories
+//! - Preflight checks of the elevation and privileges an operation needs
+//! - Temporary files and directories readable only by the current user,
+//!   through an explicit DACL on Windows and owner-only modes on Unix
+//! - EFS encryption status and encryption of sensitive directories
+//! - BitLocker and removable-media status of project and data drives
+//! - Known Folder lookup that follows folder redirection
+//! - Time since the last keyboard or mouse input, for idle detection
+//! - Start at login through a LaunchAgent on macOS and an XDG autostart
+//!   entry on Linux
+//! - File associations and URL schemes through `.desktop` entries on Linux
+//!   and Launch Services on macOS
+//! - macOS privacy (TCC) permission status, prompts and System Settings panes
+//! - A headless agent runner installed as a Windows service, systemd user
+//!   unit or launchd job
+//! - An `Unsupported` error for features the running platform doesn't have
+//! - Bringing the running instance's window to the front when Opcode is
+//!   launched again
 //!
-//! All functionality is only available when compiled for Windows and follows
-//! the project's error handling patterns with anyhow::Result<T>.
+//! All functionality is only available when compiled for Windows, except
+//! process management, which has a procfs backend on Linux, a libproc
+//! backend on macOS and a `ps` backend on other Unix systems, elevation,
+//! which uses polkit on Linux and an administrator prompt on macOS, secret
+//! storage, which uses the Keychain on macOS and the Secret Service on Linux
+//! and FreeBSD, privilege detection, which reads the effective UID and
+//! capability sets on Linux, and window activation, which uses GTK on Linux.
+//! It follows the project's error handling patterns with anyhow::Result<T>.
 
 #[cfg(target_os = "windows")]
 pub mod process;
 
+#[cfg(target_os = "linux")]
+#[path = "process_linux.rs"]
+pub mod process;
+
+#[cfg(target_os = "macos")]
+#[path = "process_macos.rs"]
+pub mod process;
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+#[path = "process_unix.rs"]
+pub mod process;
+
+#[cfg(unix)]
+mod process_posix;
+
+pub mod process_manager;
+
+pub mod unsupported;
+
+pub mod registration_scope;
+
+pub mod activation;
+
+pub mod elevation;
+
 #[cfg(target_os = "windows")]
 pub mod registry;
 
 #[cfg(target_os = "windows")]
 pub mod permissions;
 
+#[cfg(target_os = "linux")]
+#[path = "permissions_linux.rs"]
+pub mod permissions;
+
+#[cfg(target_os = "windows")]
+pub mod startup_task;
+
+#[cfg(target_os = "windows")]
+pub mod integrity;
+
+#[cfg(target_os = "windows")]
+pub mod appcontainer;
+
+#[cfg(target_os = "windows")]
+pub mod restricted_token;
+
+#[cfg(target_os = "windows")]
+pub mod elevation_broker;
+
+pub mod secret_store;
+
+#[cfg(target_os = "windows")]
+pub mod secrets;
+
+#[cfg(target_os = "macos")]
+#[path = "secrets_macos.rs"]
+pub mod secrets;
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+#[path = "secrets_linux.rs"]
+pub mod secrets;
+
+#[cfg(target_os = "windows")]
+pub mod service;
+
+#[cfg(target_os = "linux")]
+#[path = "service_systemd.rs"]
+pub mod service;
+
+#[cfg(target_os = "macos")]
+#[path = "service_launchd.rs"]
+pub mod service;
+
+pub mod user_presence;
+
+#[cfg(target_os = "windows")]
+pub mod authenticode;
+
+#[cfg(target_os = "windows")]
+pub mod mark_of_the_web;
+
+#[cfg(target_os = "windows")]
+pub mod controlled_folder_access;
+
+#[cfg(target_os = "windows")]
+pub mod defender_exclusions;
+
+#[cfg(target_os = "windows")]
+pub mod preflight;
+
+#[cfg(target_os = "windows")]
+pub mod secure_temp;
+
+#[cfg(unix)]
+#[path = "secure_temp_unix.rs"]
+pub mod secure_temp;
+
+#[cfg(target_os = "windows")]
+pub mod efs;
+
+#[cfg(target_os = "windows")]
+pub mod volume_protection;
+
+#[cfg(target_os = "windows")]
+pub mod known_folders;
+
+#[cfg(target_os = "windows")]
+pub mod terminal;
+
+#[cfg(target_os = "windows")]
+pub mod input_idle;
+
+#[cfg(target_os = "macos")]
+pub mod launch_agent;
+
+#[cfg(target_os = "linux")]
+pub mod xdg_autostart;
+
+#[cfg(target_os = "linux")]
+pub mod xdg_handlers;
+
+#[cfg(target_os = "macos")]
+pub mod launch_services;
+
+#[cfg(target_os = "macos")]
+pub mod tcc;
+
+pub use unsupported::{is_unsupported, CommandError, Unsupported};
+
 // Re-export all Windows functionality
 #[cfg(target_os = "windows")]
 pub use process::*;
@@ -28,24 +189,173 @@ pub use registry::*;
 #[cfg(target_os = "windows")]
 pub use permissions::*;
 
-// No-op implementations for non-Windows platforms to maintain API compatibility
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "windows")]
+pub use terminal::*;
+
+#[cfg(target_os = "windows")]
+pub use integrity::*;
+
+#[cfg(target_os = "windows")]
+pub use appcontainer::*;
+
+#[cfg(target_os = "windows")]
+pub use restricted_token::*;
+
+#[cfg(target_os = "windows")]
+pub use elevation_broker::*;
+
+#[cfg(target_os = "windows")]
+pub use secrets::*;
+
+#[cfg(target_os = "windows")]
+pub use user_presence::*;
+
+#[cfg(target_os = "windows")]
+pub use authenticode::*;
+
+#[cfg(target_os = "windows")]
+pub use mark_of_the_web::*;
+
+#[cfg(target_os = "windows")]
+pub use controlled_folder_access::*;
+
+#[cfg(target_os = "windows")]
+pub use defender_exclusions::*;
+
+#[cfg(target_os = "windows")]
+pub use preflight::*;
+
+#[cfg(target_os = "windows")]
+pub use secure_temp::*;
+
+#[cfg(target_os = "windows")]
+pub use efs::*;
+
+#[cfg(target_os = "windows")]
+pub use volume_protection::*;
+
+#[cfg(target_os = "windows")]
+pub use known_folders::*;
+
+/// Convert a Rust string to a null-terminated wide string for Windows APIs
+#[cfg(target_os = "windows")]
+pub(crate) fn to_wide_string(s: &str) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    std::ffi::OsStr::new(s)
+        .encode_wide()
+        .chain(Some(0))
+        .collect()
+}
+
+/// Convert a file path to a null-terminated wide string for Windows APIs, in
+/// extended-length form when it exceeds `MAX_PATH`
+#[cfg(target_os = "windows")]
+pub(crate) fn to_wide_path<P: AsRef<std::path::Path>>(path: P) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    crate::utils::paths::to_extended_length_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect()
+}
+
+/// SIDs allocated by `ConvertStringSidToSidW`, freed on drop
+#[cfg(target_os = "windows")]
+pub(crate) struct LocalSids(pub(crate) Vec<winapi::um::winnt::PSID>);
+
+#[cfg(target_os = "windows")]
+impl Drop for LocalSids {
+    fn drop(&mut self) {
+        for sid in self.0.drain(..) {
+            unsafe {
+                winapi::um::winbase::LocalFree(sid as _);
+            }
+        }
+    }
+}
+
+// Fallbacks for platforms without a backend, keeping the Windows API.
+// Operations that can't work return an `Unsupported` error instead of
+// pretending to succeed.
+#[cfg(not(any(target_os = "windows", unix)))]
 pub mod process {
+    use super::unsupported::Unsupported;
     use anyhow::Result;
 
-    /// Kill a process tree by PID (no-op on non-Windows)
+    const FEATURE: &str = "Process management";
+
+    /// Kill a process tree by PID (unsupported on this platform)
     pub async fn kill_process_tree(_pid: u32) -> Result<bool> {
-        Ok(false)
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// Check whether a process is running (unsupported on this platform)
+    pub async fn is_process_running(_pid: u32) -> Result<bool> {
+        Err(Unsupported::new(FEATURE).into())
     }
 
-    /// List processes by name (no-op on non-Windows)
+    /// List processes by name (unsupported on this platform)
     pub async fn list_processes_by_name(_name: &str) -> Result<Vec<u32>> {
-        Ok(vec![])
+        Err(Unsupported::new(FEATURE).into())
     }
 
-    /// Check if process is elevated (no-op on non-Windows)
+    /// Check if process is elevated (unsupported on this platform)
     pub async fn is_process_elevated(_pid: u32) -> Result<bool> {
-        Ok(false)
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// Process metadata, matching the Windows `ProcessInfo`
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ProcessInfo {
+        pub pid: u32,
+        pub name: String,
+        pub parent_pid: Option<u32>,
+        pub is_elevated: bool,
+    }
+
+    /// Get information about processes (unsupported on this platform)
+    pub async fn get_process_info(_pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// Check if the current process is elevated (unsupported on this platform)
+    pub fn is_current_process_elevated() -> Result<bool> {
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// [`ProcessManager`](super::process_manager::ProcessManager) for
+    /// platforms without a process backend
+    pub struct UnsupportedProcessManager;
+
+    #[async_trait::async_trait]
+    impl super::process_manager::ProcessManager for UnsupportedProcessManager {
+        fn backend(&self) -> &'static str {
+            "unsupported"
+        }
+
+        async fn kill_tree(&self, pid: u32) -> Result<bool> {
+            kill_process_tree(pid).await
+        }
+
+        async fn is_running(&self, pid: u32) -> Result<bool> {
+            is_process_running(pid).await
+        }
+
+        async fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+            list_processes_by_name(name).await
+        }
+
+        async fn is_elevated(&self, pid: u32) -> Result<bool> {
+            is_process_elevated(pid).await
+        }
+
+        async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+            get_process_info(pids).await
+        }
+
+        fn is_current_elevated(&self) -> Result<bool> {
+            is_current_process_elevated()
+        }
     }
 }
 
@@ -53,38 +363,145 @@ pub mod process {
 pub mod registry {
     use anyhow::Result;
 
-    /// Register file association (no-op on non-Windows)
-    pub fn register_file_association(_extension: &str, _program_id: &str, _executable_path: &str, _description: &str) -> Result<()> {
-        Ok(())
+    pub use super::registration_scope::RegistrationScope;
+
+    /// Register file association through a `.desktop` entry
+    #[cfg(target_os = "linux")]
+    pub fn register_file_association(extension: &str, program_id: &str, executable_path: &str, description: &str) -> Result<Option<RegistrationScope>> {
+        super::xdg_handlers::register_file_association(extension, program_id, executable_path, description)
     }
 
-    /// Register URL protocol (no-op on non-Windows)
-    pub fn register_url_protocol(_protocol: &str, _executable_path: &str, _description: &str) -> Result<()> {
-        Ok(())
+    /// Register URL protocol through a `.desktop` entry
+    #[cfg(target_os = "linux")]
+    pub fn register_url_protocol(protocol: &str, executable_path: &str, description: &str) -> Result<Option<RegistrationScope>> {
+        super::xdg_handlers::register_url_protocol(protocol, executable_path, description)
+    }
+
+    /// Register file association through Launch Services
+    #[cfg(target_os = "macos")]
+    pub fn register_file_association(extension: &str, program_id: &str, executable_path: &str, description: &str) -> Result<Option<RegistrationScope>> {
+        super::launch_services::register_file_association(extension, program_id, executable_path, description)
+    }
+
+    /// Register URL protocol through Launch Services
+    #[cfg(target_os = "macos")]
+    pub fn register_url_protocol(protocol: &str, executable_path: &str, description: &str) -> Result<Option<RegistrationScope>> {
+        super::launch_services::register_url_protocol(protocol, executable_path, description)
+    }
+
+    /// Register file association (unsupported on other platforms)
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn register_file_association(_extension: &str, _program_id: &str, _executable_path: &str, _description: &str) -> Result<Option<RegistrationScope>> {
+        Err(super::unsupported::Unsupported::new("File associations").into())
+    }
+
+    /// Register URL protocol (unsupported on other platforms)
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn register_url_protocol(_protocol: &str, _executable_path: &str, _description: &str) -> Result<Option<RegistrationScope>> {
+        Err(super::unsupported::Unsupported::new("URL protocols").into())
+    }
+
+    /// Set auto-start on login through a LaunchAgent
+    #[cfg(target_os = "macos")]
+    pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
+        super::launch_agent::set_auto_start(app_name, executable_path, enabled)
     }
 
-    /// Set auto-start on login (no-op on non-Windows)
-    pub fn set_auto_start(_app_name: &str, _executable_path: &str, _enabled: bool) -> Result<()> {
+    /// Set auto-start on login through an XDG autostart entry
+    #[cfg(target_os = "linux")]
+    pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
+        super::xdg_autostart::set_auto_start(app_name, executable_path, enabled)
+    }
+
+    /// Set auto-start on login (unsupported on other platforms)
+    ///
+    /// Disabling succeeds, as auto-start can never have been enabled.
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    pub fn set_auto_start(_app_name: &str, _executable_path: &str, enabled: bool) -> Result<()> {
+        if enabled {
+            return Err(super::unsupported::Unsupported::new("Start at login").into());
+        }
         Ok(())
     }
 }
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 pub mod permissions {
     use anyhow::Result;
 
-    /// Check if running with admin privileges (no-op on non-Windows)
+    pub use super::elevation::request_elevation;
+
+    /// Check if running as root
     pub fn is_running_as_admin() -> Result<bool> {
-        Ok(false)
+        #[cfg(unix)]
+        return Ok(unsafe { libc::geteuid() } == 0);
+
+        #[cfg(not(unix))]
+        Err(super::unsupported::Unsupported::new("Privilege detection").into())
     }
 
-    /// Request UAC elevation (no-op on non-Windows)
-    pub async fn request_elevation(_executable_path: &str, _args: &[&str]) -> Result<bool> {
+    /// Set Windows ACL on file (unsupported on non-Windows)
+    ///
+    /// Use `utils::file_permissions` for access levels every platform has.
+    pub fn set_file_acl(_file_path: &str, _permissions: &str) -> Result<()> {
+        Err(super::unsupported::Unsupported::new("Windows ACLs").into())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+pub mod service {
+    use super::unsupported::Unsupported;
+    use anyhow::Result;
+
+    const FEATURE: &str = "Background services";
+
+    /// Argument that starts the executable as the headless agent runner
+    pub const AGENT_RUNNER_ARG: &str = "--agent-runner";
+
+    /// State of the agent runner service, matching the Windows `ServiceStatus`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ServiceStatus {
+        NotInstalled,
+        Stopped,
+        Running,
+    }
+
+    /// Install the agent runner (unsupported on this platform)
+    pub fn install_service(_executable_path: &str) -> Result<()> {
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// Remove the agent runner (nothing is ever installed on this platform)
+    pub fn uninstall_service() -> Result<bool> {
         Ok(false)
     }
 
-    /// Set Windows ACL on file (no-op on non-Windows)
-    pub fn set_file_acl(_file_path: &str, _permissions: &str) -> Result<()> {
-        Ok(())
+    /// Start the agent runner (unsupported on this platform)
+    pub fn start_service() -> Result<()> {
+        Err(Unsupported::new(FEATURE).into())
+    }
+
+    /// Status of the agent runner (never installed on this platform)
+    pub fn service_status() -> Result<ServiceStatus> {
+        Ok(ServiceStatus::NotInstalled)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(not(target_os = "windows"))]
+pub mod terminal {
+    use anyhow::Result;
+    use std::path::PathBuf;
+
+    /// Register the Windows Terminal profile (unsupported on non-Windows)
+    pub fn install_terminal_profile(_claude_path: &str) -> Result<Option<PathBuf>> {
+        Err(super::unsupported::Unsupported::new("Windows Terminal profiles").into())
+    }
+
+    /// Remove the Windows Terminal profile
+    ///
+    /// Returns `Ok(false)`, as no profile can have been installed.
+    pub fn remove_terminal_profile() -> Result<bool> {
+        Ok(false)
+    }
+}
@@ -4,9 +4,20 @@
 //! designed for robust, security-aware applications.
 //!
 //! # Features
-//! - **UAC Integration**: Seamless User Account Control handling
+//! - **UAC Integration**: Seamless User Account Control handling, adapted to
+//!   the machine's UAC policy (disabled UAC, auto-denied requests)
 //! - **Privilege Detection**: Runtime administrator status checking
+//! - **Token Privileges**: Scoped enabling of privileges such as `SeDebugPrivilege`,
+//!   and enumeration of privileges, groups and integrity level for diagnostics
 //! - **ACL Management**: Fine-grained file and directory permissions
+//! - **ACL Inspection**: Structured access control entries read natively via
+//!   `GetNamedSecurityInfoW`, so the UI can show who can access a file
+//! - **File Ownership**: Consent-gated takeover of files left behind by an
+//!   elevated session
+//! - **Audit Entries**: SACL entries that make Windows log access to
+//!   sensitive files such as stored credentials
+//! - **ACL Convergence**: Idempotent diffing and applying of a desired ACL,
+//!   for single files or whole directory trees with progress and cancellation
 //! - **Security Context**: Process token and elevation analysis
 //! - **Path-based Security**: Automatic privilege requirement detection
 //!
@@ -55,12 +66,17 @@
 //! }
 //! ```
 
+use super::{to_wide_path, to_wide_string, LocalSids};
+use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
+use crate::utils::cmdline::join_windows_args;
 use anyhow::{Context, Result};
-use log::{debug, error, info, warn};
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
 use winapi::shared::ntdef::{HANDLE, NULL};
 use winapi::shared::winerror::ERROR_SUCCESS;
@@ -70,15 +86,12 @@ use winapi::um::securitybaseapi::{GetTokenInformation, InitializeSecurityDescrip
 use winapi::um::winnt::{
     DACL_SECURITY_INFORMATION, FILE_ALL_ACCESS, FILE_GENERIC_EXECUTE, FILE_GENERIC_READ,
     FILE_GENERIC_WRITE, GENERIC_ALL, PSECURITY_DESCRIPTOR, SECURITY_DESCRIPTOR,
-    SE_FILE_OBJECT, TOKEN_ELEVATION, TOKEN_QUERY, PACL,
-    SECURITY_DESCRIPTOR_REVISION, TokenElevation,
+    TOKEN_ELEVATION, TOKEN_QUERY, PACL,
+    SECURITY_DESCRIPTOR_REVISION, TokenElevation, SE_GROUP_ENABLED, SE_GROUP_INTEGRITY,
+    SE_GROUP_LOGON_ID, SE_GROUP_USE_FOR_DENY_ONLY, SE_PRIVILEGE_ENABLED,
+    SE_PRIVILEGE_ENABLED_BY_DEFAULT,
 };
 
-/// Convert a Rust string to a wide string for Windows API
-fn to_wide_string(s: &str) -> Vec<u16> {
-    OsStr::new(s).encode_wide().chain(Some(0)).collect()
-}
-
 /// Check if the current process is running with administrator privileges
 ///
 /// This function checks whether the current process has elevated privileges
@@ -144,10 +157,13 @@ pub fn is_running_as_admin() -> Result<bool> {
     }
 }
 
+pub use super::elevation::{ElevationOptions, ElevationOutcome};
+
 /// Request UAC elevation by restarting the process with elevated privileges
 ///
-/// This function attempts to restart the specified executable with elevated
-/// privileges using the Windows UAC prompt.
+/// This function starts the specified executable with elevated privileges
+/// through the Windows UAC prompt and returns once it has started. Use
+/// [`request_elevation_with_options`] to wait for its exit code.
 ///
 /// # Arguments
 /// * `executable_path` - Path to the executable to run elevated
@@ -176,6 +192,42 @@ pub fn is_running_as_admin() -> Result<bool> {
 /// }
 /// ```
 pub async fn request_elevation(executable_path: &str, args: &[&str]) -> Result<bool> {
+    let outcome =
+        request_elevation_with_options(executable_path, args, &ElevationOptions::default()).await?;
+    Ok(outcome != ElevationOutcome::Declined)
+}
+
+/// Start a program elevated through the UAC prompt
+///
+/// Uses `ShellExecuteExW` with the `runas` verb, so arguments are passed
+/// exactly as given (quoted for the Windows command line as needed) and the
+/// process handle is available to wait for the exit code. The UAC prompt
+/// itself has no timeout; `options.timeout` only limits the wait for the
+/// process to exit, after which it is reported as [`ElevationOutcome::Started`].
+///
+/// # Arguments
+/// * `executable_path` - Path to the executable to run elevated
+/// * `args` - Command-line arguments to pass to the elevated process
+/// * `options` - Whether to wait for the process and how to start it
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{request_elevation_with_options, ElevationOptions, ElevationOutcome};
+///
+/// async fn install_service(exe: &str) -> anyhow::Result<()> {
+///     let options = ElevationOptions { wait_for_exit: true, ..Default::default() };
+///     match request_elevation_with_options(exe, &["--install-service"], &options).await? {
+///         ElevationOutcome::Exited { exit_code: 0, .. } => Ok(()),
+///         ElevationOutcome::Exited { exit_code, .. } => Err(anyhow::anyhow!("Installer failed with {}", exit_code)),
+///         _ => Err(anyhow::anyhow!("Administrator privileges required")),
+///     }
+/// }
+/// ```
+pub async fn request_elevation_with_options(
+    executable_path: &str,
+    args: &[&str],
+    options: &ElevationOptions,
+) -> Result<ElevationOutcome> {
     info!("Requesting UAC elevation for: {}", executable_path);
 
     // Verify executable exists
@@ -183,49 +235,653 @@ pub async fn request_elevation(executable_path: &str, args: &[&str]) -> Result<b
         return Err(anyhow::anyhow!("Executable not found: {}", executable_path));
     }
 
-    // Build arguments string
-    let args_string = args.join(" ");
-
-    // Use PowerShell to request elevation
-    let script = format!(
-        r#"
-        $psi = New-Object System.Diagnostics.ProcessStartInfo
-        $psi.FileName = "{}"
-        $psi.Arguments = "{}"
-        $psi.Verb = "runas"
-        $psi.UseShellExecute = $true
-
-        try {{
-            $process = [System.Diagnostics.Process]::Start($psi)
-            Write-Output "Success"
-        }} catch {{
-            Write-Output "Failed: $_"
-        }}
-        "#,
-        executable_path.replace('\\', "\\\\"),
-        args_string.replace('"', "`\"")
-    );
+    let executable_path = executable_path.to_string();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let options = options.clone();
+
+    // ShellExecuteExW blocks while the UAC prompt is shown
+    let outcome = tokio::task::spawn_blocking(move || -> Result<ElevationOutcome> {
+        let Some(process) = shell_execute_elevated(
+            &executable_path,
+            &args,
+            options.working_dir.as_deref(),
+            options.show_window,
+        )?
+        else {
+            return Ok(ElevationOutcome::Declined);
+        };
+        let pid = process.pid();
+
+        if !options.wait_for_exit {
+            return Ok(ElevationOutcome::Started { pid });
+        }
+        Ok(match process.wait(options.timeout)? {
+            Some(exit_code) => ElevationOutcome::Exited { pid, exit_code },
+            None => {
+                warn!("Elevated process {} is still running after {:?}", pid, options.timeout);
+                ElevationOutcome::Started { pid }
+            }
+        })
+    })
+    .await
+    .context("Elevation task failed")??;
+
+    match outcome {
+        ElevationOutcome::Declined => warn!("User denied elevation request"),
+        ElevationOutcome::Started { pid } => info!("Started elevated process {}", pid),
+        ElevationOutcome::Exited { pid, exit_code } => {
+            info!("Elevated process {} exited with code {}", pid, exit_code)
+        }
+    }
+    Ok(outcome)
+}
+
+/// Registry key holding the UAC policy
+const UAC_POLICY_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Policies\System";
+
+/// What happens when an administrator in Admin Approval Mode needs elevation
+/// (`ConsentPromptBehaviorAdmin`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminConsentBehavior {
+    ElevateWithoutPrompting,
+    CredentialsOnSecureDesktop,
+    ConsentOnSecureDesktop,
+    Credentials,
+    Consent,
+    /// The Windows default: prompt for consent for non-Windows binaries
+    ConsentForNonWindowsBinaries,
+    Unknown,
+}
+
+impl AdminConsentBehavior {
+    fn from_value(value: u32) -> Self {
+        match value {
+            0 => AdminConsentBehavior::ElevateWithoutPrompting,
+            1 => AdminConsentBehavior::CredentialsOnSecureDesktop,
+            2 => AdminConsentBehavior::ConsentOnSecureDesktop,
+            3 => AdminConsentBehavior::Credentials,
+            4 => AdminConsentBehavior::Consent,
+            5 => AdminConsentBehavior::ConsentForNonWindowsBinaries,
+            _ => AdminConsentBehavior::Unknown,
+        }
+    }
+}
+
+/// What happens when a standard user needs elevation (`ConsentPromptBehaviorUser`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserConsentBehavior {
+    /// Elevation requests are denied without a prompt
+    AutomaticallyDeny,
+    CredentialsOnSecureDesktop,
+    /// The Windows default
+    Credentials,
+    Unknown,
+}
+
+impl UserConsentBehavior {
+    fn from_value(value: u32) -> Self {
+        match value {
+            0 => UserConsentBehavior::AutomaticallyDeny,
+            1 => UserConsentBehavior::CredentialsOnSecureDesktop,
+            3 => UserConsentBehavior::Credentials,
+            _ => UserConsentBehavior::Unknown,
+        }
+    }
+}
+
+/// User Account Control policy of the machine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UacConfiguration {
+    /// `EnableLUA`: administrators run in Admin Approval Mode. When off, UAC
+    /// is disabled: administrators always have a full token and standard
+    /// users cannot elevate at all
+    pub enabled: bool,
+    pub admin_consent: AdminConsentBehavior,
+    pub user_consent: UserConsentBehavior,
+    /// `PromptOnSecureDesktop`
+    pub secure_desktop: bool,
+    /// `FilterAdministratorToken`: Admin Approval Mode for the built-in
+    /// Administrator account
+    pub built_in_admin_approval_mode: bool,
+}
+
+impl Default for UacConfiguration {
+    /// The Windows defaults, used for values that are not set
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            admin_consent: AdminConsentBehavior::ConsentForNonWindowsBinaries,
+            user_consent: UserConsentBehavior::Credentials,
+            secure_desktop: true,
+            built_in_admin_approval_mode: false,
+        }
+    }
+}
+
+impl UacConfiguration {
+    /// Why a process of an administrator (`is_administrator`, see
+    /// [`TokenInfo::is_administrator`]) or a standard user cannot be
+    /// elevated, if it can't
+    ///
+    /// With UAC disabled, `runas` starts a standard user's process unelevated
+    /// without any prompt, and with automatic denial it always fails. The
+    /// standard user settings don't apply to an administrator's filtered token.
+    pub fn elevation_blocked_reason(&self, is_administrator: bool) -> Option<&'static str> {
+        if is_administrator {
+            return None;
+        }
+        if !self.enabled {
+            Some("User Account Control is disabled, so standard users cannot elevate; run opcode from an administrator account")
+        } else if self.user_consent == UserConsentBehavior::AutomaticallyDeny {
+            Some("Group Policy denies elevation requests from standard users; ask your administrator")
+        } else {
+            None
+        }
+    }
+
+    /// Settings that weaken UAC or make elevation impossible, for diagnostics
+    pub fn issues(&self) -> Vec<&'static str> {
+        let mut issues = Vec::new();
+        if !self.enabled {
+            issues.push("User Account Control is disabled");
+        }
+        if self.enabled && self.admin_consent == AdminConsentBehavior::ElevateWithoutPrompting {
+            issues.push("Administrators are elevated without a prompt");
+        }
+        if self.user_consent == UserConsentBehavior::AutomaticallyDeny {
+            issues.push("Elevation requests from standard users are denied automatically");
+        }
+        if self.enabled && !self.secure_desktop {
+            issues.push("UAC prompts are not shown on the secure desktop");
+        }
+        issues
+    }
+}
+
+/// Read the UAC policy from `HKLM\...\Policies\System`
+///
+/// Values that are not set have their Windows defaults.
+pub fn get_uac_configuration() -> Result<UacConfiguration> {
+    use super::registry::read_registry_dword;
+    use winapi::um::winreg::HKEY_LOCAL_MACHINE;
+
+    let read = |name: &str| unsafe { read_registry_dword(HKEY_LOCAL_MACHINE, UAC_POLICY_KEY, name) };
+    let defaults = UacConfiguration::default();
+
+    let configuration = UacConfiguration {
+        enabled: read("EnableLUA")?.map_or(defaults.enabled, |v| v != 0),
+        admin_consent: read("ConsentPromptBehaviorAdmin")?
+            .map_or(defaults.admin_consent, AdminConsentBehavior::from_value),
+        user_consent: read("ConsentPromptBehaviorUser")?
+            .map_or(defaults.user_consent, UserConsentBehavior::from_value),
+        secure_desktop: read("PromptOnSecureDesktop")?.map_or(defaults.secure_desktop, |v| v != 0),
+        built_in_admin_approval_mode: read("FilterAdministratorToken")?
+            .map_or(defaults.built_in_admin_approval_mode, |v| v != 0),
+    };
+    debug!("UAC configuration: {:?}", configuration);
+    Ok(configuration)
+}
+
+/// Start a program elevated with `ShellExecuteExW(runas)`, returning the
+/// process, or `None` if the user declined the UAC prompt
+pub(crate) fn shell_execute_elevated(
+    executable_path: &str,
+    args: &[String],
+    working_dir: Option<&str>,
+    show_window: bool,
+) -> Result<Option<super::integrity::ContainedChild>> {
+    use super::integrity::ContainedChild;
+    use winapi::shared::winerror::ERROR_CANCELLED;
+    use winapi::um::shellapi::{
+        ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW,
+    };
+    use winapi::um::winuser::{SW_HIDE, SW_SHOWNORMAL};
+
+    // Don't show a prompt that cannot lead to an elevated process
+    match get_uac_configuration() {
+        Ok(uac) => {
+            if let Some(reason) = uac.elevation_blocked_reason(is_administrator()) {
+                return Err(anyhow::anyhow!(
+                    "Cannot elevate {}: {}",
+                    executable_path,
+                    reason
+                ));
+            }
+        }
+        Err(e) => debug!("Could not read the UAC configuration: {:#}", e),
+    }
 
-    let output = tokio::process::Command::new("powershell")
-        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
-        .output()
-        .await
-        .context("Failed to execute PowerShell command")?;
+    let verb = to_wide_string("runas");
+    let file = to_wide_string(executable_path);
+    let parameters = to_wide_string(&join_windows_args(args));
+    let directory = working_dir.map(to_wide_string);
 
-    let output_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    unsafe {
+        let mut info: SHELLEXECUTEINFOW = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.lpDirectory = directory.as_ref().map_or(ptr::null(), |d| d.as_ptr());
+        info.nShow = if show_window { SW_SHOWNORMAL } else { SW_HIDE };
+
+        if ShellExecuteExW(&mut info) == FALSE {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_CANCELLED as i32) {
+                return Ok(None);
+            }
+            return Err(error)
+                .with_context(|| format!("Failed to start {} elevated", executable_path));
+        }
+        if info.hProcess.is_null() {
+            anyhow::bail!("{} was started elevated without a process handle", executable_path);
+        }
+
+        Ok(Some(ContainedChild::from_process_handle(info.hProcess)))
+    }
+}
+
+/// Name of the privilege needed to open processes of other users
+pub const SE_DEBUG_NAME: &str = "SeDebugPrivilege";
+
+/// Privileges currently enabled through [`enable_privilege`], with the
+/// number of guards using each and whether the last one must disable it again
+static ENABLED_PRIVILEGES: Mutex<Option<HashMap<String, (usize, bool)>>> = Mutex::new(None);
+
+/// Keeps a privilege enabled; the previous state is restored when the last
+/// guard for the privilege is dropped
+#[derive(Debug)]
+pub struct PrivilegeGuard {
+    name: String,
+}
+
+impl Drop for PrivilegeGuard {
+    fn drop(&mut self) {
+        let Ok(mut enabled) = ENABLED_PRIVILEGES.lock() else {
+            return;
+        };
+        let Some(privileges) = enabled.as_mut() else {
+            return;
+        };
+        let Some((users, revert)) = privileges.get_mut(&self.name) else {
+            return;
+        };
+
+        *users -= 1;
+        if *users == 0 {
+            let revert = *revert;
+            privileges.remove(&self.name);
+            if revert {
+                match adjust_privilege(&self.name, false) {
+                    Ok(_) => debug!("Disabled {}", self.name),
+                    Err(e) => warn!("Failed to disable {}: {:#}", self.name, e),
+                }
+            }
+        }
+    }
+}
 
-    if output_str.starts_with("Success") {
-        info!("Successfully started elevated process");
-        Ok(true)
-    } else if output_str.contains("canceled") || output_str.contains("denied") {
-        warn!("User denied elevation request");
-        Ok(false)
+/// Enable a privilege in the current process token
+///
+/// The privilege must already be held by the token, which for privileges
+/// like `SeDebugPrivilege` means running elevated; otherwise an error is
+/// returned. Guards are counted per privilege, so overlapping callers don't
+/// disable it under each other's feet. Child processes started while the
+/// guard is alive inherit the enabled privilege.
+///
+/// # Arguments
+/// * `name` - Privilege name, e.g. [`SE_DEBUG_NAME`]
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{enable_privilege, SE_DEBUG_NAME};
+///
+/// fn inspect_service_process(pid: u32) -> anyhow::Result<()> {
+///     let _debug = enable_privilege(SE_DEBUG_NAME)?;
+///     // ... open the process with PROCESS_QUERY_INFORMATION ...
+///     Ok(())
+/// }
+/// ```
+pub fn enable_privilege(name: &str) -> Result<PrivilegeGuard> {
+    let mut enabled = ENABLED_PRIVILEGES
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Privilege state lock poisoned: {}", e))?;
+    let privileges = enabled.get_or_insert_with(HashMap::new);
+
+    if let Some((users, _)) = privileges.get_mut(name) {
+        *users += 1;
     } else {
-        error!("Failed to elevate: {}", output_str);
-        Err(anyhow::anyhow!("Failed to request elevation: {}", output_str))
+        let changed = adjust_privilege(name, true)?;
+        debug!("Enabled {}", name);
+        privileges.insert(name.to_string(), (1, changed));
+    }
+
+    Ok(PrivilegeGuard {
+        name: name.to_string(),
+    })
+}
+
+/// Run `f` with a privilege enabled, restoring its previous state afterwards
+///
+/// # Arguments
+/// * `name` - Privilege name, e.g. [`SE_DEBUG_NAME`]
+/// * `f` - Work that needs the privilege
+pub fn with_privilege<T>(name: &str, f: impl FnOnce() -> T) -> Result<T> {
+    let _guard = enable_privilege(name)?;
+    Ok(f())
+}
+
+/// Enable `SeDebugPrivilege` when running elevated, so that processes of
+/// other users and services can be inspected; `None` otherwise
+pub(crate) fn debug_privilege_if_elevated() -> Option<PrivilegeGuard> {
+    if !is_running_as_admin().unwrap_or(false) {
+        return None;
+    }
+    enable_privilege(SE_DEBUG_NAME)
+        .map_err(|e| debug!("Continuing without {}: {:#}", SE_DEBUG_NAME, e))
+        .ok()
+}
+
+/// Enable or disable a privilege, returning whether its state changed
+fn adjust_privilege(name: &str, enable: bool) -> Result<bool> {
+    use winapi::shared::winerror::ERROR_NOT_ALL_ASSIGNED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::securitybaseapi::AdjustTokenPrivileges;
+    use winapi::um::winbase::LookupPrivilegeValueW;
+    use winapi::um::winnt::{
+        LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+    };
+
+    let wide_name = to_wide_string(name);
+
+    unsafe {
+        let mut luid = std::mem::zeroed();
+        if LookupPrivilegeValueW(ptr::null(), wide_name.as_ptr(), &mut luid) == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Unknown privilege: {}", name));
+        }
+
+        let mut token: HANDLE = ptr::null_mut();
+        if OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+            &mut token,
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error()).context("Failed to open process token");
+        }
+
+        let mut new_state = TOKEN_PRIVILEGES {
+            PrivilegeCount: 1,
+            Privileges: [LUID_AND_ATTRIBUTES {
+                Luid: luid,
+                Attributes: if enable { SE_PRIVILEGE_ENABLED } else { 0 },
+            }],
+        };
+        let mut previous_state: TOKEN_PRIVILEGES = std::mem::zeroed();
+        let mut previous_size: DWORD = 0;
+
+        let adjusted = AdjustTokenPrivileges(
+            token,
+            FALSE,
+            &mut new_state,
+            std::mem::size_of::<TOKEN_PRIVILEGES>() as DWORD,
+            &mut previous_state,
+            &mut previous_size,
+        );
+        // AdjustTokenPrivileges succeeds even when the token lacks the privilege
+        let last_error = GetLastError();
+        CloseHandle(token);
+
+        if adjusted == FALSE {
+            return Err(std::io::Error::from_raw_os_error(last_error as i32))
+                .with_context(|| format!("Failed to adjust {}", name));
+        }
+        if last_error == ERROR_NOT_ALL_ASSIGNED {
+            anyhow::bail!("The process does not hold {} (not running elevated?)", name);
+        }
+
+        Ok(previous_state.PrivilegeCount > 0)
+    }
+}
+
+/// A privilege held by a token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenPrivilege {
+    /// Privilege name, e.g. `SeBackupPrivilege`
+    pub name: String,
+    pub enabled: bool,
+    pub enabled_by_default: bool,
+}
+
+/// A group a token is a member of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenGroup {
+    pub sid: String,
+    /// `DOMAIN\name`, if the SID could be resolved
+    pub account: Option<String>,
+    pub enabled: bool,
+    /// Only used to deny access, e.g. Administrators in a filtered UAC token
+    pub deny_only: bool,
+    /// The logon session SID
+    pub logon_id: bool,
+}
+
+/// How the token relates to UAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenElevationKind {
+    /// UAC is off, or the user is not an administrator
+    Default,
+    /// Elevated administrator token
+    Full,
+    /// Filtered token of an administrator running unelevated
+    Limited,
+}
+
+/// Security context of the current process
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenInfo {
+    pub user_sid: String,
+    pub user_account: Option<String>,
+    pub elevated: bool,
+    pub elevation: TokenElevationKind,
+    pub integrity_level: super::integrity::IntegrityLevel,
+    pub privileges: Vec<TokenPrivilege>,
+    /// Group memberships, without the integrity label
+    pub groups: Vec<TokenGroup>,
+}
+
+impl TokenInfo {
+    /// Whether the token belongs to an administrator, elevated or running
+    /// with the filtered token UAC gives administrators
+    pub fn is_administrator(&self) -> bool {
+        self.elevated || self.elevation == TokenElevationKind::Limited
+    }
+
+    /// Whether the token holds `name`, enabled or not
+    pub fn holds_privilege(&self, name: &str) -> bool {
+        self.privileges.iter().any(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the token is an enabled member of the group with `sid`
+    pub fn is_member(&self, sid: &str) -> bool {
+        self.groups
+            .iter()
+            .any(|g| g.enabled && !g.deny_only && g.sid.eq_ignore_ascii_case(sid))
+    }
+
+    /// Explain why an operation needing `privileges` and at least
+    /// `min_integrity` may fail with this token
+    ///
+    /// Privileges that are held but disabled are not reported, since
+    /// [`enable_privilege`] can enable them. Returns an empty list when the
+    /// token should suffice.
+    pub fn diagnose(
+        &self,
+        privileges: &[&str],
+        min_integrity: super::integrity::IntegrityLevel,
+    ) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.integrity_level < min_integrity {
+            problems.push(format!(
+                "running at {:?} integrity, {:?} is required",
+                self.integrity_level, min_integrity
+            ));
+        }
+        for name in privileges {
+            if !self.holds_privilege(name) {
+                let hint = if self.elevation == TokenElevationKind::Limited {
+                    " (available when running as administrator)"
+                } else {
+                    ""
+                };
+                problems.push(format!("{} not held{}", name, hint));
+            }
+        }
+        problems
+    }
+}
+
+/// Whether the current user is an administrator, see [`TokenInfo::is_administrator`]
+///
+/// `false` if the token can't be read.
+pub(crate) fn is_administrator() -> bool {
+    get_current_token_info()
+        .map(|token| token.is_administrator())
+        .unwrap_or(false)
+}
+
+/// Read the privileges, groups and integrity level of the current process token
+///
+/// Meant for diagnostics: lets an error report say "SeBackupPrivilege not
+/// held" or "running at Low integrity" instead of only "access denied".
+pub fn get_current_token_info() -> Result<TokenInfo> {
+    use winapi::um::winnt::{
+        TokenElevationType, TokenElevationTypeFull, TokenElevationTypeLimited, TokenGroups,
+        TokenPrivileges, TOKEN_ELEVATION_TYPE, TOKEN_GROUPS, TOKEN_PRIVILEGES, TOKEN_USER,
+    };
+
+    let token = super::integrity::open_current_process_token(TOKEN_QUERY)?;
+
+    unsafe {
+        let user = current_user_token()?;
+        let (user_sid, user_account) = lookup_sid((*(user.as_ptr() as *const TOKEN_USER)).User.Sid);
+
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size: DWORD = 0;
+        if GetTokenInformation(
+            token.0,
+            TokenElevation,
+            &mut elevation as *mut _ as _,
+            std::mem::size_of::<TOKEN_ELEVATION>() as DWORD,
+            &mut size,
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error()).context("Failed to query token elevation");
+        }
+
+        let mut elevation_type: TOKEN_ELEVATION_TYPE = 0;
+        let elevation_kind = if GetTokenInformation(
+            token.0,
+            TokenElevationType,
+            &mut elevation_type as *mut _ as _,
+            std::mem::size_of::<TOKEN_ELEVATION_TYPE>() as DWORD,
+            &mut size,
+        ) == FALSE
+        {
+            TokenElevationKind::Default
+        } else if elevation_type == TokenElevationTypeFull {
+            TokenElevationKind::Full
+        } else if elevation_type == TokenElevationTypeLimited {
+            TokenElevationKind::Limited
+        } else {
+            TokenElevationKind::Default
+        };
+
+        let buffer = token_information(token.0, TokenPrivileges)
+            .context("Failed to query token privileges")?;
+        let list = &*(buffer.as_ptr() as *const TOKEN_PRIVILEGES);
+        let entries =
+            std::slice::from_raw_parts(list.Privileges.as_ptr(), list.PrivilegeCount as usize);
+        let privileges = entries
+            .iter()
+            .map(|entry| {
+                let mut luid = entry.Luid;
+                TokenPrivilege {
+                    name: privilege_name(&mut luid),
+                    enabled: entry.Attributes & SE_PRIVILEGE_ENABLED != 0,
+                    enabled_by_default: entry.Attributes & SE_PRIVILEGE_ENABLED_BY_DEFAULT != 0,
+                }
+            })
+            .collect();
+
+        let buffer =
+            token_information(token.0, TokenGroups).context("Failed to query token groups")?;
+        let list = &*(buffer.as_ptr() as *const TOKEN_GROUPS);
+        let entries = std::slice::from_raw_parts(list.Groups.as_ptr(), list.GroupCount as usize);
+        let groups = entries
+            .iter()
+            .filter(|entry| entry.Attributes & SE_GROUP_INTEGRITY == 0)
+            .map(|entry| {
+                let (sid, account) = lookup_sid(entry.Sid);
+                TokenGroup {
+                    sid,
+                    account,
+                    enabled: entry.Attributes & SE_GROUP_ENABLED != 0,
+                    deny_only: entry.Attributes & SE_GROUP_USE_FOR_DENY_ONLY != 0,
+                    logon_id: entry.Attributes & SE_GROUP_LOGON_ID == SE_GROUP_LOGON_ID,
+                }
+            })
+            .collect();
+
+        Ok(TokenInfo {
+            user_sid,
+            user_account,
+            elevated: elevation.TokenIsElevated != 0,
+            elevation: elevation_kind,
+            integrity_level: super::integrity::token_integrity_level(token.0)?,
+            privileges,
+            groups,
+        })
+    }
+}
+
+/// Read a variable-size token information class into an aligned buffer
+unsafe fn token_information(
+    token: HANDLE,
+    class: winapi::um::winnt::TOKEN_INFORMATION_CLASS,
+) -> std::io::Result<Vec<u64>> {
+    let mut size: DWORD = 0;
+    GetTokenInformation(token, class, ptr::null_mut(), 0, &mut size);
+    if size == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    if GetTokenInformation(token, class, buffer.as_mut_ptr() as _, size, &mut size) == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(buffer)
+}
+
+/// Name of a privilege LUID, e.g. `SeDebugPrivilege`
+unsafe fn privilege_name(luid: &mut winapi::shared::ntdef::LUID) -> String {
+    use winapi::um::winbase::LookupPrivilegeNameW;
+
+    let mut name = [0u16; 128];
+    let mut len = name.len() as DWORD;
+    if LookupPrivilegeNameW(ptr::null(), luid, name.as_mut_ptr(), &mut len) == FALSE {
+        return format!("<LUID {}:{}>", luid.HighPart, luid.LowPart);
     }
+    String::from_utf16_lossy(&name[..len as usize])
 }
 
+
 /// Set Windows ACL (Access Control List) on a file
 ///
 /// This function modifies the Windows ACL for a file, controlling who can
@@ -268,12 +924,14 @@ pub fn set_file_acl(file_path: &str, permissions: &str) -> Result<()> {
     }
 
     // Use icacls command for simplicity (available on all Windows versions)
-    let output = std::process::Command::new("icacls")
-        .arg(file_path)
-        .arg("/grant")
-        .arg(permissions)
-        .output()
-        .context("Failed to execute icacls command")?;
+    let output = run_with_timeout_blocking(
+        std::process::Command::new("icacls")
+            .arg(file_path)
+            .arg("/grant")
+            .arg(permissions),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute icacls command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -302,12 +960,14 @@ pub fn remove_file_acl(file_path: &str, principal: &str) -> Result<()> {
     }
 
     // Use icacls to remove permissions
-    let output = std::process::Command::new("icacls")
-        .arg(file_path)
-        .arg("/remove")
-        .arg(principal)
-        .output()
-        .context("Failed to execute icacls command")?;
+    let output = run_with_timeout_blocking(
+        std::process::Command::new("icacls")
+            .arg(file_path)
+            .arg("/remove")
+            .arg(principal),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute icacls command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -335,11 +995,13 @@ pub fn reset_file_acl(file_path: &str) -> Result<()> {
     }
 
     // Use icacls to reset permissions
-    let output = std::process::Command::new("icacls")
-        .arg(file_path)
-        .arg("/reset")
-        .output()
-        .context("Failed to execute icacls command")?;
+    let output = run_with_timeout_blocking(
+        std::process::Command::new("icacls")
+            .arg(file_path)
+            .arg("/reset"),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute icacls command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -352,6 +1014,13 @@ pub fn reset_file_acl(file_path: &str) -> Result<()> {
 
 /// Check if a file or directory requires administrator access
 ///
+/// Evaluates the path's DACL against the token of a standard user: the
+/// current token, or its unelevated linked token when opcode runs elevated.
+/// A path requires admin access when that token may not write it (or, for
+/// directories, create files in it). Paths that don't exist yet are judged by
+/// their nearest existing parent. Only when the ACL cannot be read does this
+/// fall back to probing with a temporary file.
+///
 /// # Arguments
 /// * `path` - Path to check
 ///
@@ -360,48 +1029,62 @@ pub fn reset_file_acl(file_path: &str) -> Result<()> {
 /// * `Ok(false)` if the path is accessible to standard users
 /// * `Err(...)` if unable to determine access requirements
 pub fn requires_admin_access(path: &str) -> Result<bool> {
-    debug!("Checking if path requires admin access: {}", path);
-
-    // Common system directories that typically require admin access
-    let admin_paths = [
-        r"c:\windows",
-        r"c:\program files",
-        r"c:\program files (x86)",
-        r"c:\programdata",
-    ];
+    use winapi::um::winnt::{FILE_ADD_FILE, FILE_WRITE_DATA};
 
-    let path_lower = path.to_lowercase();
+    debug!("Checking if path requires admin access: {}", path);
 
-    for &admin_path in &admin_paths {
-        if path_lower.starts_with(admin_path) {
-            debug!("Path {} is in admin-protected directory", path);
-            return Ok(true);
+    let Some(target) = Path::new(path).ancestors().find(|p| p.exists()) else {
+        warn!("Unable to determine access requirements for {}: no part of it exists", path);
+        return Ok(false);
+    };
+    let target_str = target.to_string_lossy();
+    let needed = if target.is_dir() { FILE_ADD_FILE } else { FILE_WRITE_DATA };
+
+    match effective_access_mask(&target_str, true) {
+        Ok(granted) => {
+            let requires_admin = granted & needed != needed;
+            debug!(
+                "Path {} {} admin access (standard user granted {:#x} on {})",
+                path,
+                if requires_admin { "requires" } else { "does not require" },
+                granted,
+                target_str
+            );
+            Ok(requires_admin)
+        }
+        Err(e) => {
+            debug!("Could not evaluate the ACL of {}, probing instead: {:#}", target_str, e);
+            Ok(probe_requires_admin(target))
         }
     }
+}
 
-    // Try to test write access
-    let test_path = if Path::new(path).is_dir() {
-        Path::new(path).join(".opcode_test")
+/// Whether writing a temporary file next to or into `path` is denied
+fn probe_requires_admin(path: &Path) -> bool {
+    let test_path = if path.is_dir() {
+        path.join(".opcode_test")
     } else {
-        Path::new(path).with_extension("opcode_test")
+        path.with_extension("opcode_test")
     };
 
-    // Attempt to create a test file
-    match std::fs::write(&test_path, b"test") {
-        Ok(_) => {
-            // Clean up test file
+    // Owner-only, so the probe can't be read or swapped by other users meanwhile
+    match super::secure_temp::create_secure_file(&test_path) {
+        Ok(file) => {
+            drop(file);
             let _ = std::fs::remove_file(&test_path);
-            debug!("Path {} is writable by current user", path);
-            Ok(false)
+            debug!("Path {} is writable by current user", path.display());
+            false
+        }
+        Err(e)
+            if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                == Some(std::io::ErrorKind::PermissionDenied) =>
+        {
+            debug!("Path {} requires elevated permissions", path.display());
+            true
         }
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::PermissionDenied {
-                debug!("Path {} requires elevated permissions", path);
-                Ok(true)
-            } else {
-                warn!("Unable to determine access requirements for {}: {}", path, e);
-                Ok(false)
-            }
+            warn!("Unable to determine access requirements for {}: {:#}", path.display(), e);
+            false
         }
     }
 }
@@ -461,12 +1144,21 @@ pub fn create_security_descriptor(allow_everyone: bool, admin_only: bool) -> Res
 
 /// Get effective permissions for the current user on a file
 ///
+/// Runs `AccessCheck` with the process token against the file's security
+/// descriptor, so deny entries, group membership and inherited entries are
+/// all taken into account the way Windows itself evaluates them. Deleting is
+/// possible with `DELETE` on the file or `FILE_DELETE_CHILD` on its parent
+/// directory. The read-only attribute blocks writing and deleting regardless
+/// of the ACL and is applied on top.
+///
 /// # Arguments
 /// * `file_path` - Path to check permissions for
 ///
 /// # Returns
 /// * Tuple of (can_read, can_write, can_execute, can_delete)
 pub fn get_effective_permissions(file_path: &str) -> Result<(bool, bool, bool, bool)> {
+    use winapi::um::winnt::{DELETE, FILE_DELETE_CHILD};
+
     debug!("Getting effective permissions for: {}", file_path);
 
     let path = Path::new(file_path);
@@ -475,88 +1167,1878 @@ pub fn get_effective_permissions(file_path: &str) -> Result<(bool, bool, bool, b
         return Err(anyhow::anyhow!("Path does not exist: {}", file_path));
     }
 
-    // Use standard Rust fs operations to check basic permissions
-    let metadata = path.metadata()
-        .context("Failed to get file metadata")?;
+    let readonly = path
+        .metadata()
+        .context("Failed to get file metadata")?
+        .permissions()
+        .readonly();
+
+    let granted = effective_access_mask(file_path, false)?;
+    let parent_granted = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => effective_access_mask(&parent.to_string_lossy(), false).unwrap_or_else(|e| {
+            debug!("Could not check access to the parent of {}: {:#}", file_path, e);
+            0
+        }),
+        None => 0,
+    };
 
-    let readonly = metadata.permissions().readonly();
+    let has = |mask: u32, rights: u32| mask & rights == rights;
+    let can_read = has(granted, FILE_GENERIC_READ);
+    let can_write = !readonly && has(granted, FILE_GENERIC_WRITE);
+    let can_execute = has(granted, FILE_GENERIC_EXECUTE);
+    let can_delete =
+        !readonly && (has(granted, DELETE) || has(parent_granted, FILE_DELETE_CHILD));
 
-    // Check read permission by attempting to open for reading
-    let can_read = std::fs::File::open(path).is_ok();
+    debug!("Permissions for {}: read={}, write={}, execute={}, delete={} (granted {:#x})",
+           file_path, can_read, can_write, can_execute, can_delete, granted);
 
-    // Check write permission
-    let can_write = !readonly && !requires_admin_access(file_path).unwrap_or(true);
+    Ok((can_read, can_write, can_execute, can_delete))
+}
 
-    // Check execute permission (for .exe files)
-    let can_execute = if let Some(ext) = path.extension() {
-        let ext_lower = ext.to_string_lossy().to_lowercase();
-        matches!(ext_lower.as_str(), "exe" | "bat" | "cmd" | "com" | "scr" | "msi")
-    } else {
-        false
+/// Access mask the current process token is granted on a file or directory
+///
+/// With `as_standard_user`, an elevated process is evaluated with its
+/// unelevated linked token instead, i.e. as the same user without UAC
+/// elevation.
+fn effective_access_mask(file_path: &str, as_standard_user: bool) -> Result<u32> {
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::GetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::AccessCheck;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{
+        GENERIC_MAPPING, GROUP_SECURITY_INFORMATION, MAXIMUM_ALLOWED, OWNER_SECURITY_INFORMATION,
+        PRIVILEGE_SET,
     };
 
-    // Check delete permission (similar to write for parent directory)
-    let parent_path = path.parent()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| String::from("."));
-    let can_delete = !readonly && !requires_admin_access(&parent_path).unwrap_or(true);
+    let wide_path = to_wide_path(file_path);
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
 
-    debug!("Permissions for {}: read={}, write={}, execute={}, delete={}",
-           file_path, can_read, can_write, can_execute, can_delete);
+    unsafe {
+        // AccessCheck needs the owner and group as well as the DACL
+        let result = GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to read the security descriptor of {}", file_path));
+        }
 
-    Ok((can_read, can_write, can_execute, can_delete))
+        let impersonation = match access_check_token(as_standard_user) {
+            Ok(token) => token,
+            Err(e) => {
+                LocalFree(descriptor as _);
+                return Err(e);
+            }
+        };
+
+        let mut mapping = GENERIC_MAPPING {
+            GenericRead: FILE_GENERIC_READ,
+            GenericWrite: FILE_GENERIC_WRITE,
+            GenericExecute: FILE_GENERIC_EXECUTE,
+            GenericAll: FILE_ALL_ACCESS,
+        };
+        let mut privileges: PRIVILEGE_SET = std::mem::zeroed();
+        let mut privileges_size = std::mem::size_of::<PRIVILEGE_SET>() as DWORD;
+        let mut granted: DWORD = 0;
+        let mut status = FALSE;
+
+        let checked = AccessCheck(
+            descriptor,
+            impersonation.0,
+            MAXIMUM_ALLOWED,
+            &mut mapping,
+            &mut privileges,
+            &mut privileges_size,
+            &mut granted,
+            &mut status,
+        );
+        let error = std::io::Error::last_os_error();
+        LocalFree(descriptor as _);
+
+        if checked == FALSE {
+            return Err(error).with_context(|| format!("Failed to check access to {}", file_path));
+        }
+        // With MAXIMUM_ALLOWED a failed status just means nothing is granted
+        Ok(if status == FALSE { 0 } else { granted })
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
+/// Impersonation token for `AccessCheck`: a duplicate of the process token,
+/// or with `as_standard_user` the unelevated linked token of an elevated process
+fn access_check_token(as_standard_user: bool) -> Result<super::integrity::OwnedHandle> {
+    use super::integrity::OwnedHandle;
+    use winapi::um::securitybaseapi::DuplicateToken;
+    use winapi::um::winnt::{
+        SecurityImpersonation, TokenElevationType, TokenElevationTypeFull, TokenLinkedToken,
+        TOKEN_DUPLICATE, TOKEN_ELEVATION_TYPE, TOKEN_LINKED_TOKEN,
+    };
 
-    #[test]
-    fn test_is_running_as_admin() {
-        // This test can run in any context
-        let result = is_running_as_admin();
-        assert!(result.is_ok());
+    unsafe {
+        let mut token: HANDLE = ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY | TOKEN_DUPLICATE, &mut token) == FALSE {
+            return Err(std::io::Error::last_os_error()).context("Failed to open process token");
+        }
+        let token = OwnedHandle(token);
+
+        if as_standard_user {
+            let mut elevation_type: TOKEN_ELEVATION_TYPE = 0;
+            let mut size: DWORD = 0;
+            let is_full = GetTokenInformation(
+                token.0,
+                TokenElevationType,
+                &mut elevation_type as *mut _ as _,
+                std::mem::size_of::<TOKEN_ELEVATION_TYPE>() as DWORD,
+                &mut size,
+            ) != FALSE
+                && elevation_type == TokenElevationTypeFull;
+
+            if is_full {
+                // The linked token comes back as an identification token,
+                // which AccessCheck accepts as is
+                let mut linked: TOKEN_LINKED_TOKEN = std::mem::zeroed();
+                if GetTokenInformation(
+                    token.0,
+                    TokenLinkedToken,
+                    &mut linked as *mut _ as _,
+                    std::mem::size_of::<TOKEN_LINKED_TOKEN>() as DWORD,
+                    &mut size,
+                ) == FALSE
+                {
+                    return Err(std::io::Error::last_os_error())
+                        .context("Failed to get the unelevated linked token");
+                }
+                return Ok(OwnedHandle(linked.LinkedToken));
+            }
+        }
 
-        let is_admin = result.expect("Failed to check admin privileges - ensure Windows API is accessible");
-        println!("Running as administrator: {}", is_admin);
+        // AccessCheck only accepts impersonation tokens
+        let mut impersonation: HANDLE = ptr::null_mut();
+        if DuplicateToken(token.0, SecurityImpersonation, &mut impersonation) == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to duplicate the process token");
+        }
+        Ok(OwnedHandle(impersonation))
     }
+}
 
-    #[test]
-    fn test_requires_admin_access() {
-        // Test known system directories
-        let result = requires_admin_access(r"C:\Windows\System32");
-        assert!(result.is_ok());
-        assert!(result.expect("Failed to check admin access for System32"), "System32 should require admin access");
+/// Whether an access control entry grants, denies or audits access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AceType {
+    Allow,
+    Deny,
+    Audit,
+}
 
-        // Test user temp directory (should not require admin)
-        let temp_dir = env::temp_dir();
-        let temp_path = temp_dir.to_str().expect("Temp directory path should be valid UTF-8");
-        let result = requires_admin_access(temp_path);
-        assert!(result.is_ok());
-        assert!(!result.expect("Failed to check admin access for temp directory"), "Temp directory should not require admin access");
-    }
+/// One entry of a file's discretionary access control list
+///
+/// # Fields
+/// - `principal`: Account name (`DOMAIN\name`), if the SID could be resolved
+/// - `sid`: The principal's SID in string form (e.g. `S-1-5-32-544`)
+/// - `access_mask`: Raw access mask of the entry
+/// - `rights`: Readable summary of the mask (`full_control`, `read`, `write`, ...);
+///   output only, left empty when deserializing
+/// - `ace_type`: Whether access is allowed or denied
+/// - `inherited`: Whether the entry was inherited from a parent directory
+/// - `object_inherit` / `container_inherit`: Whether files / subdirectories inherit it
+/// - `inherit_only`: Whether the entry applies only to children, not the object itself
+/// - `no_propagate_inherit`: Whether inheritance stops after the direct children
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AceEntry {
+    pub principal: Option<String>,
+    pub sid: String,
+    pub access_mask: u32,
+    #[serde(skip_deserializing)]
+    pub rights: Vec<&'static str>,
+    pub ace_type: AceType,
+    pub inherited: bool,
+    pub object_inherit: bool,
+    pub container_inherit: bool,
+    pub inherit_only: bool,
+    pub no_propagate_inherit: bool,
+}
 
-    #[test]
-    fn test_get_effective_permissions() {
-        // Test on temp file
-        let temp_dir = env::temp_dir();
-        let test_file = temp_dir.join("opcode_perm_test.txt");
+impl AceEntry {
+    /// An explicit entry for `sid` that applies to the object itself only
+    ///
+    /// Set `object_inherit` / `container_inherit` on the result for entries
+    /// that directories should pass on to their children.
+    pub fn new(sid: &str, access_mask: u32, ace_type: AceType) -> Self {
+        ace_entry(sid.to_string(), None, access_mask, ace_type, 0)
+    }
 
-        // Create test file
-        std::fs::write(&test_file, b"test content").expect("Failed to create test file in temp directory");
+    /// Whether two entries grant the same access to the same principal,
+    /// ignoring whether they are inherited
+    pub(crate) fn same_grant(&self, other: &AceEntry) -> bool {
+        self.sid.eq_ignore_ascii_case(&other.sid)
+            && self.ace_type == other.ace_type
+            && self.access_mask == other.access_mask
+            && self.object_inherit == other.object_inherit
+            && self.container_inherit == other.container_inherit
+            && self.inherit_only == other.inherit_only
+            && self.no_propagate_inherit == other.no_propagate_inherit
+    }
+}
 
-        let test_path = test_file.to_str().expect("Test file path should be valid UTF-8");
-        let result = get_effective_permissions(test_path);
-        assert!(result.is_ok(), "Failed to get effective permissions for test file");
+/// One step towards a desired access control list, as computed by [`diff_acl`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AclChange {
+    /// Stop inheriting entries from the parent directory, dropping the inherited ones
+    DisableInheritance,
+    /// Add an explicit entry
+    Add { entry: AceEntry },
+    /// Remove an explicit entry
+    Remove { entry: AceEntry },
+}
 
-        let (can_read, can_write, can_execute, can_delete) = result.expect("Should successfully get permissions for temp file");
-        assert!(can_read, "Should be able to read temp file");
+/// Read the access control list of a file or directory
+///
+/// Unlike [`set_file_acl`] and friends, which shell out to `icacls`, this
+/// reads the DACL natively and returns one [`AceEntry`] per entry, in ACL
+/// order (which is also the order Windows evaluates them in). A missing DACL,
+/// which grants everyone full access, is reported as a single entry for
+/// `Everyone`.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::get_file_acl;
+///
+/// fn main() -> anyhow::Result<()> {
+///     for entry in get_file_acl(r"C:\Users\me\project")? {
+///         println!("{:?} {:?}: {:?}", entry.ace_type, entry.principal, entry.rights);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn get_file_acl(file_path: &str) -> Result<Vec<AceEntry>> {
+    read_file_dacl(file_path).map(|(entries, _)| entries)
+}
+
+/// Read the DACL entries of a file and whether the DACL is protected from inheritance
+fn read_file_dacl(file_path: &str) -> Result<(Vec<AceEntry>, bool)> {
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::GetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::{GetAce, GetAclInformation, GetSecurityDescriptorControl};
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{
+        AclSizeInformation, ACCESS_ALLOWED_ACE, ACCESS_ALLOWED_ACE_TYPE, ACCESS_DENIED_ACE_TYPE,
+        ACE_HEADER, ACL_SIZE_INFORMATION, SECURITY_DESCRIPTOR_CONTROL, SE_DACL_PROTECTED,
+        SYSTEM_AUDIT_ACE_TYPE,
+    };
+
+    debug!("Reading ACL of: {}", file_path);
+
+    let wide_path = to_wide_path(file_path);
+    let mut dacl: PACL = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+    unsafe {
+        let result = GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut dacl,
+            ptr::null_mut(),
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to read the ACL of {}", file_path));
+        }
+
+        let mut control: SECURITY_DESCRIPTOR_CONTROL = 0;
+        let mut revision: DWORD = 0;
+        let protected = GetSecurityDescriptorControl(descriptor, &mut control, &mut revision)
+            != FALSE
+            && control & SE_DACL_PROTECTED != 0;
+
+        if dacl.is_null() {
+            LocalFree(descriptor as _);
+            return Ok((vec![AceEntry {
+                principal: Some("Everyone".to_string()),
+                sid: "S-1-1-0".to_string(),
+                access_mask: FILE_ALL_ACCESS,
+                rights: describe_access_mask(FILE_ALL_ACCESS),
+                ace_type: AceType::Allow,
+                inherited: false,
+                object_inherit: true,
+                container_inherit: true,
+                inherit_only: false,
+                no_propagate_inherit: false,
+            }], protected));
+        }
+
+        let mut size_info: ACL_SIZE_INFORMATION = std::mem::zeroed();
+        if GetAclInformation(
+            dacl,
+            &mut size_info as *mut _ as _,
+            std::mem::size_of::<ACL_SIZE_INFORMATION>() as DWORD,
+            AclSizeInformation,
+        ) == FALSE
+        {
+            LocalFree(descriptor as _);
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to read the ACL size of {}", file_path));
+        }
+
+        let mut entries = Vec::with_capacity(size_info.AceCount as usize);
+        for index in 0..size_info.AceCount {
+            let mut ace: *mut winapi::ctypes::c_void = ptr::null_mut();
+            if GetAce(dacl, index, &mut ace) == FALSE {
+                continue;
+            }
+
+            let header = &*(ace as *const ACE_HEADER);
+            let ace_type = match header.AceType {
+                ACCESS_ALLOWED_ACE_TYPE => AceType::Allow,
+                ACCESS_DENIED_ACE_TYPE => AceType::Deny,
+                SYSTEM_AUDIT_ACE_TYPE => AceType::Audit,
+                other => {
+                    debug!("Skipping unsupported ACE type {} in {}", other, file_path);
+                    continue;
+                }
+            };
+
+            // Allowed, denied and audit ACEs share the same layout
+            let body = &*(ace as *const ACCESS_ALLOWED_ACE);
+            let (sid, principal) = lookup_sid(&body.SidStart as *const DWORD as _);
+            entries.push(ace_entry(sid, principal, body.Mask, ace_type, header.AceFlags));
+        }
+
+        let revision = (*sacl).AclRevision;
+        LocalFree(descriptor as _);
+        Ok(FileSacl {
+            entries,
+            other_aces,
+            revision,
+            protected,
+        })
+    }
+}
+
+fn ace_entry(
+    sid: String,
+    principal: Option<String>,
+    access_mask: u32,
+    ace_type: AceType,
+    flags: u8,
+) -> AceEntry {
+    use winapi::um::winnt::{
+        CONTAINER_INHERIT_ACE, INHERITED_ACE, INHERIT_ONLY_ACE, NO_PROPAGATE_INHERIT_ACE,
+        OBJECT_INHERIT_ACE,
+    };
+
+    AceEntry {
+        principal,
+        sid,
+        access_mask,
+        rights: describe_access_mask(access_mask),
+        ace_type,
+        inherited: flags & INHERITED_ACE != 0,
+        object_inherit: flags & OBJECT_INHERIT_ACE != 0,
+        container_inherit: flags & CONTAINER_INHERIT_ACE != 0,
+        inherit_only: flags & INHERIT_ONLY_ACE != 0,
+        no_propagate_inherit: flags & NO_PROPAGATE_INHERIT_ACE != 0,
+    }
+}
+
+/// Compute the changes that turn the `current` ACL into the `desired` one
+///
+/// `desired` is the complete list of entries the file should end up with.
+/// Explicit entries that are not desired are removed and missing ones are
+/// added. Inherited entries satisfy a desired entry granting the same access;
+/// if any inherited entry is not desired, inheritance is disabled and every
+/// desired entry becomes explicit. An ACL already in the desired state yields
+/// no changes, so converging a file twice is a no-op.
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{apply_acl_changes, diff_acl, get_file_acl, AceEntry, AceType};
+/// use winapi::um::winnt::FILE_ALL_ACCESS;
+///
+/// fn restrict_to_user(path: &str, user_sid: &str) -> anyhow::Result<()> {
+///     let desired = [AceEntry::new(user_sid, FILE_ALL_ACCESS, AceType::Allow)];
+///     let changes = diff_acl(&get_file_acl(path)?, &desired);
+///     apply_acl_changes(path, &changes)
+/// }
+/// ```
+pub fn diff_acl(current: &[AceEntry], desired: &[AceEntry]) -> Vec<AclChange> {
+    let is_desired = |entry: &AceEntry| desired.iter().any(|d| d.same_grant(entry));
+    let disable_inheritance = current
+        .iter()
+        .any(|entry| entry.inherited && !is_desired(entry));
+
+    let mut changes = Vec::new();
+    if disable_inheritance {
+        changes.push(AclChange::DisableInheritance);
+    }
+
+    for entry in current.iter().filter(|e| !e.inherited) {
+        if !is_desired(entry) {
+            changes.push(AclChange::Remove {
+                entry: entry.clone(),
+            });
+        }
+    }
+
+    let mut added: Vec<&AceEntry> = Vec::new();
+    for entry in desired {
+        let satisfied = current.iter().any(|e| {
+            e.same_grant(entry) && (!e.inherited || !disable_inheritance)
+        });
+        if !satisfied && !added.iter().any(|a| a.same_grant(entry)) {
+            added.push(entry);
+            changes.push(AclChange::Add {
+                entry: AceEntry {
+                    inherited: false,
+                    ..entry.clone()
+                },
+            });
+        }
+    }
+
+    changes
+}
+
+/// Apply changes computed by [`diff_acl`] to a file or directory
+///
+/// The file's explicit entries are re-read, the changes applied to them and
+/// the result written back natively in canonical order (denies before
+/// allows). Entries are matched by principal and access, so applying the
+/// same changes twice leaves the ACL as it was after the first time. An
+/// empty change list does not touch the file.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+/// * `changes` - Changes to apply
+pub fn apply_acl_changes(file_path: &str, changes: &[AclChange]) -> Result<()> {
+    if changes.is_empty() {
+        debug!("ACL of {} is already in the desired state", file_path);
+        return Ok(());
+    }
+
+    let (current, protected) = read_file_dacl(file_path)?;
+    let mut disable_inheritance = protected;
+    let mut entries: Vec<AceEntry> = current.into_iter().filter(|e| !e.inherited).collect();
+
+    for change in changes {
+        match change {
+            AclChange::DisableInheritance => disable_inheritance = true,
+            AclChange::Remove { entry } => entries.retain(|e| !e.same_grant(entry)),
+            AclChange::Add { entry } => {
+                if entry.ace_type == AceType::Audit {
+                    anyhow::bail!(
+                        "Audit entries belong to the system ACL and cannot be added to {}",
+                        file_path
+                    );
+                }
+                if !entries.iter().any(|e| e.same_grant(entry)) {
+                    entries.push(AceEntry {
+                        inherited: false,
+                        ..entry.clone()
+                    });
+                }
+            }
+        }
+    }
+
+    // Canonical order: explicit denies before explicit allows
+    entries.sort_by_key(|entry| entry.ace_type != AceType::Deny);
+
+    info!(
+        "Applying {} ACL changes to {} ({} explicit entries, inheritance {})",
+        changes.len(),
+        file_path,
+        entries.len(),
+        if disable_inheritance { "disabled" } else { "enabled" }
+    );
+    write_file_dacl(file_path, &entries, disable_inheritance)
+}
+
+/// Progress of [`set_directory_acl_recursive`], reported after each entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AclProgress {
+    /// Entries handled so far, including failed ones
+    pub processed: u64,
+    /// Entries found below the directory (including the directory itself)
+    pub total: u64,
+    /// Entries whose ACL could not be applied
+    pub failed: u64,
+    /// Path of the entry just handled
+    pub current: String,
+}
+
+/// Outcome of [`set_directory_acl_recursive`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RecursiveAclResult {
+    /// Entries whose ACL already matched the spec
+    pub unchanged: u64,
+    /// Entries whose ACL was changed
+    pub changed: u64,
+    /// Entries that could not be updated, with the reason
+    pub errors: Vec<String>,
+    /// The walk was stopped through the cancellation flag
+    pub cancelled: bool,
+}
+
+/// Converge the ACL of a directory and everything below it to `spec`
+///
+/// Every directory gets `spec` as given; files get the entries that apply
+/// to the object itself (inherit-only entries dropped, inheritance flags
+/// cleared). Each entry is updated through [`diff_acl`] and
+/// [`apply_acl_changes`], so re-running after a cancellation or failure only
+/// touches what is still different. Symbolic links and junctions are
+/// neither followed nor modified.
+///
+/// `cancel` is checked between entries, and `on_progress` is called after
+/// each one so callers can forward progress to the UI.
+///
+/// # Arguments
+/// * `dir_path` - Root of the tree to update
+/// * `spec` - Desired entries, as for [`diff_acl`]
+/// * `cancel` - Set to stop the walk early
+/// * `on_progress` - Called after every entry
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{set_directory_acl_recursive, AceEntry, AceType};
+/// use std::sync::atomic::AtomicBool;
+/// use winapi::um::winnt::FILE_ALL_ACCESS;
+///
+/// fn lock_down(dir: &str, user_sid: &str) -> anyhow::Result<()> {
+///     let mut owner = AceEntry::new(user_sid, FILE_ALL_ACCESS, AceType::Allow);
+///     owner.object_inherit = true;
+///     owner.container_inherit = true;
+///
+///     let cancel = AtomicBool::new(false);
+///     let result = set_directory_acl_recursive(dir, &[owner], &cancel, |progress| {
+///         println!("{}/{} {}", progress.processed, progress.total, progress.current);
+///     })?;
+///     println!("{} changed, {} failed", result.changed, result.errors.len());
+///     Ok(())
+/// }
+/// ```
+pub fn set_directory_acl_recursive(
+    dir_path: &str,
+    spec: &[AceEntry],
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&AclProgress),
+) -> Result<RecursiveAclResult> {
+    let root = Path::new(dir_path);
+    if !root.is_dir() {
+        anyhow::bail!("Not a directory: {}", dir_path);
+    }
+
+    info!("Applying ACL recursively to: {}", dir_path);
+
+    let mut result = RecursiveAclResult::default();
+    let mut entries = Vec::new();
+    for entry in walkdir::WalkDir::new(root).follow_links(false) {
+        if cancel.load(Ordering::Relaxed) {
+            result.cancelled = true;
+            return Ok(result);
+        }
+        match entry {
+            Ok(entry) if entry.path_is_symlink() => {
+                debug!("Skipping link: {}", entry.path().display());
+            }
+            Ok(entry) => entries.push((entry.file_type().is_dir(), entry.into_path())),
+            Err(e) => result.errors.push(e.to_string()),
+        }
+    }
+
+    let file_spec = object_only_spec(spec);
+    let mut progress = AclProgress {
+        processed: 0,
+        total: entries.len() as u64,
+        failed: result.errors.len() as u64,
+        current: String::new(),
+    };
+
+    for (is_dir, path) in entries {
+        if cancel.load(Ordering::Relaxed) {
+            info!(
+                "Cancelled recursive ACL update of {} after {} of {} entries",
+                dir_path, progress.processed, progress.total
+            );
+            result.cancelled = true;
+            break;
+        }
+
+        let path = path.to_string_lossy().to_string();
+        let desired = if is_dir { spec } else { &file_spec };
+        let outcome = get_file_acl(&path).and_then(|current| {
+            let changes = diff_acl(&current, desired);
+            apply_acl_changes(&path, &changes).map(|()| !changes.is_empty())
+        });
+        match outcome {
+            Ok(true) => result.changed += 1,
+            Ok(false) => result.unchanged += 1,
+            Err(e) => {
+                warn!("Failed to apply ACL to {}: {:#}", path, e);
+                result.errors.push(format!("{}: {:#}", path, e));
+                progress.failed += 1;
+            }
+        }
+
+        progress.processed += 1;
+        progress.current = path;
+        on_progress(&progress);
+    }
+
+    info!(
+        "Recursive ACL update of {}: {} changed, {} unchanged, {} failed",
+        dir_path,
+        result.changed,
+        result.unchanged,
+        result.errors.len()
+    );
+    Ok(result)
+}
+
+/// The part of a directory spec that applies to files
+fn object_only_spec(spec: &[AceEntry]) -> Vec<AceEntry> {
+    spec.iter()
+        .filter(|entry| !entry.inherit_only)
+        .map(|entry| AceEntry {
+            object_inherit: false,
+            container_inherit: false,
+            no_propagate_inherit: false,
+            ..entry.clone()
+        })
+        .collect()
+}
+
+/// Name of the privilege needed to take ownership of objects the user has no access to
+pub const SE_TAKE_OWNERSHIP_NAME: &str = "SeTakeOwnershipPrivilege";
+
+/// Account owning a file or directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileOwner {
+    /// `DOMAIN\name` of the owner, if the SID could be resolved
+    pub account: Option<String>,
+    /// Owner SID in string form (e.g. `S-1-5-32-544`)
+    pub sid: String,
+}
+
+impl std::fmt::Display for FileOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.account {
+            Some(account) => write!(f, "{} ({})", account, self.sid),
+            None => write!(f, "{}", self.sid),
+        }
+    }
+}
+
+/// A file exists but its ACL denies opcode access
+///
+/// Typically the file was written by an elevated session, which makes the
+/// Administrators group its owner. Returned (inside `anyhow::Error`, so use
+/// `downcast_ref`) by [`with_file_ownership_consent`] when the user has not
+/// agreed to take the file over.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileLockedError {
+    pub path: String,
+    /// Owner of the file, if it could be read
+    pub owner: Option<FileOwner>,
+}
+
+impl std::fmt::Display for FileLockedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Access to {} is denied", self.path)?;
+        match &self.owner {
+            Some(owner) => write!(f, "; the file is owned by {}", owner),
+            None => write!(f, "; the file's owner could not be determined"),
+        }
+    }
+}
+
+impl std::error::Error for FileLockedError {}
+
+/// Read the owner of a file or directory
+///
+/// Reading the owner only needs `READ_CONTROL`, which is usually still
+/// granted when the file's contents are not.
+pub fn get_file_owner(file_path: &str) -> Result<FileOwner> {
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::GetNamedSecurityInfoW;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{OWNER_SECURITY_INFORMATION, PSID};
+
+    let wide_path = to_wide_path(file_path);
+    let mut owner: PSID = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+    unsafe {
+        let result = GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            &mut owner,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to read the owner of {}", file_path));
+        }
+
+        let (sid, account) = lookup_sid(owner);
+        LocalFree(descriptor as _);
+        Ok(FileOwner { account, sid })
+    }
+}
+
+/// Take ownership of a file or directory and grant the current user full control
+///
+/// Requires an elevated process (for [`SE_TAKE_OWNERSHIP_NAME`]). Only call
+/// this after the user explicitly consented, typically in response to a
+/// [`FileLockedError`]. Explicit entries denying the current user are removed;
+/// all other entries are kept. Directories are not taken over recursively.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+pub fn take_file_ownership(file_path: &str) -> Result<()> {
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::SetNamedSecurityInfoW;
+    use winapi::um::winnt::{OWNER_SECURITY_INFORMATION, TOKEN_USER};
+
+    info!("Taking ownership of {}", file_path);
+
+    let _take_ownership = enable_privilege(SE_TAKE_OWNERSHIP_NAME)
+        .context("Taking ownership of a file requires running opcode as administrator")?;
+
+    let user_sid = unsafe {
+        let token_user = current_user_token()?;
+        let sid = (*(token_user.as_ptr() as *const TOKEN_USER)).User.Sid;
+        let mut wide_path = to_wide_path(file_path);
+
+        let result = SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            sid,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to take ownership of {}", file_path));
+        }
+
+        lookup_sid(sid).0
+    };
+
+    // As the owner we can now read and rewrite the DACL
+    let is_dir = Path::new(file_path).is_dir();
+    let mut grant = AceEntry::new(&user_sid, FILE_ALL_ACCESS, AceType::Allow);
+    grant.object_inherit = is_dir;
+    grant.container_inherit = is_dir;
+
+    let mut changes: Vec<AclChange> = get_file_acl(file_path)?
+        .into_iter()
+        .filter(|e| {
+            !e.inherited && e.ace_type == AceType::Deny && e.sid.eq_ignore_ascii_case(&user_sid)
+        })
+        .map(|entry| AclChange::Remove { entry })
+        .collect();
+    changes.push(AclChange::Add { entry: grant });
+    apply_acl_changes(file_path, &changes)?;
+
+    info!("Took ownership of {}", file_path);
+    Ok(())
+}
+
+/// Run a file operation, optionally taking ownership of the file if access is denied
+///
+/// Without consent an access-denied failure is returned as a
+/// [`FileLockedError`] naming the file's owner, so the UI can ask the user.
+/// Set `take_ownership` only after the user explicitly agreed to it: the file
+/// is then taken over with [`take_file_ownership`] and `op` retried once.
+/// Other errors are returned unchanged.
+///
+/// # Arguments
+/// * `file_path` - File the operation touches
+/// * `take_ownership` - Whether the user consented to taking ownership
+/// * `op` - The operation, e.g. writing the file
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{with_file_ownership_consent, FileLockedError};
+///
+/// fn restore_file(path: &str, content: &[u8], consent: bool) -> anyhow::Result<()> {
+///     match with_file_ownership_consent(path, consent, || std::fs::write(path, content)) {
+///         Err(e) if e.downcast_ref::<FileLockedError>().is_some() => {
+///             // Ask the user, then call again with consent
+///             Err(e)
+///         }
+///         result => result,
+///     }
+/// }
+/// ```
+pub fn with_file_ownership_consent<T>(
+    file_path: &str,
+    take_ownership: bool,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> Result<T> {
+    match op() {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let locked = FileLockedError {
+                path: file_path.to_string(),
+                owner: get_file_owner(file_path)
+                    .map_err(|e| debug!("Could not read owner of {}: {:#}", file_path, e))
+                    .ok(),
+            };
+            if !take_ownership {
+                return Err(anyhow::Error::new(locked));
+            }
+
+            warn!("{}; taking ownership with user consent", locked);
+            take_file_ownership(file_path)?;
+            op().with_context(|| format!("Access to {} is still denied after taking ownership", file_path))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Name of the privilege needed to read and change system access control lists
+pub const SE_SECURITY_NAME: &str = "SeSecurityPrivilege";
+
+/// One entry of a file's system access control list (SACL)
+///
+/// Windows writes an event to the Security log whenever an access matching
+/// the entry succeeds or fails, provided the "Audit object access" policy
+/// is enabled on the machine.
+///
+/// # Fields
+/// - `principal`: Account name (`DOMAIN\name`), if the SID could be resolved
+/// - `sid`: The audited principal's SID (`S-1-1-0` audits everyone)
+/// - `access_mask`: Accesses to audit
+/// - `rights`: Readable summary of the mask (`full_control`, `read`, `write`, ...)
+/// - `on_success` / `on_failure`: Whether granted / denied accesses are logged
+/// - `inherited`: Whether the entry was inherited from a parent directory
+/// - `object_inherit` / `container_inherit`: Whether files / subdirectories inherit it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub principal: Option<String>,
+    pub sid: String,
+    pub access_mask: u32,
+    pub rights: Vec<&'static str>,
+    pub on_success: bool,
+    pub on_failure: bool,
+    pub inherited: bool,
+    pub object_inherit: bool,
+    pub container_inherit: bool,
+}
+
+impl AuditEntry {
+    /// An explicit entry auditing both successful and failed accesses by `sid`
+    pub fn new(sid: &str, access_mask: u32) -> Self {
+        AuditEntry {
+            principal: None,
+            sid: sid.to_string(),
+            access_mask,
+            rights: describe_access_mask(access_mask),
+            on_success: true,
+            on_failure: true,
+            inherited: false,
+            object_inherit: false,
+            container_inherit: false,
+        }
+    }
+
+    /// Whether two entries audit the same accesses, ignoring whether they are inherited
+    fn same_audit(&self, other: &AuditEntry) -> bool {
+        self.sid.eq_ignore_ascii_case(&other.sid)
+            && self.access_mask == other.access_mask
+            && self.on_success == other.on_success
+            && self.on_failure == other.on_failure
+            && self.object_inherit == other.object_inherit
+            && self.container_inherit == other.container_inherit
+    }
+}
+
+/// Read the audit entries of a file or directory
+///
+/// Reading a SACL requires [`SE_SECURITY_NAME`], which only elevated
+/// processes hold, so this fails when opcode is not running as administrator.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+pub fn get_file_audit_entries(file_path: &str) -> Result<Vec<AuditEntry>> {
+    let _security = enable_privilege(SE_SECURITY_NAME)
+        .context("Reading audit entries requires running opcode as administrator")?;
+    read_file_sacl(file_path).map(|sacl| sacl.entries)
+}
+
+/// Audit accesses to a file or directory, e.g. the credentials store
+///
+/// The entry is added next to the existing ones; nothing is written if an
+/// entry auditing the same accesses already exists. Returns whether the
+/// SACL changed. Requires running as administrator.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+/// * `entry` - Entry to add; `inherited` is ignored
+///
+/// # Example
+/// ```rust
+/// use crate::windows::permissions::{add_file_audit_entry, AuditEntry};
+/// use winapi::um::winnt::FILE_ALL_ACCESS;
+///
+/// fn audit_secrets(path: &str) -> anyhow::Result<()> {
+///     // Log every access by anyone
+///     add_file_audit_entry(path, &AuditEntry::new("S-1-1-0", FILE_ALL_ACCESS))?;
+///     Ok(())
+/// }
+/// ```
+pub fn add_file_audit_entry(file_path: &str, entry: &AuditEntry) -> Result<bool> {
+    let _security = enable_privilege(SE_SECURITY_NAME)
+        .context("Changing audit entries requires running opcode as administrator")?;
+
+    let mut sacl = read_file_sacl(file_path)?;
+    if sacl
+        .entries
+        .iter()
+        .any(|e| !e.inherited && e.same_audit(entry))
+    {
+        debug!("{} is already audited for {}", file_path, entry.sid);
+        return Ok(false);
+    }
+
+    sacl.entries.retain(|e| !e.inherited);
+    sacl.entries.push(AuditEntry {
+        inherited: false,
+        ..entry.clone()
+    });
+
+    info!(
+        "Auditing {:?} access to {} by {}",
+        entry.rights, file_path, entry.sid
+    );
+    write_file_sacl(file_path, &sacl)?;
+    Ok(true)
+}
+
+/// Stop auditing accesses described by `entry`
+///
+/// Removes the explicit entries auditing the same accesses as `entry`;
+/// inherited entries can only be removed on the parent. Returns whether the
+/// SACL changed. Requires running as administrator.
+///
+/// # Arguments
+/// * `file_path` - Path to the file or directory
+/// * `entry` - Entry to remove, as returned by [`get_file_audit_entries`]
+pub fn remove_file_audit_entry(file_path: &str, entry: &AuditEntry) -> Result<bool> {
+    let _security = enable_privilege(SE_SECURITY_NAME)
+        .context("Changing audit entries requires running opcode as administrator")?;
+
+    let mut sacl = read_file_sacl(file_path)?;
+    sacl.entries.retain(|e| !e.inherited);
+    let explicit = sacl.entries.len();
+    sacl.entries.retain(|e| !e.same_audit(entry));
+    if sacl.entries.len() == explicit {
+        debug!(
+            "{} has no matching audit entry for {}",
+            file_path, entry.sid
+        );
+        return Ok(false);
+    }
+
+    info!("Removing audit entry for {} from {}", entry.sid, file_path);
+    write_file_sacl(file_path, &sacl)?;
+    Ok(true)
+}
+
+/// A file's SACL as [`read_file_sacl`] returns it
+struct FileSacl {
+    /// Entries [`AuditEntry`] can represent
+    entries: Vec<AuditEntry>,
+    /// Explicit entries it can't, such as object or callback audit entries
+    /// and ones with inherit-only flags, as raw ACEs
+    ///
+    /// Written back unchanged so rewriting the SACL loses no audit settings.
+    /// Inherited ones are left out, like inherited [`AuditEntry`]s; Windows
+    /// propagates them again from the parent.
+    other_aces: Vec<Vec<u8>>,
+    /// Revision of the ACL, `ACL_REVISION_DS` if it holds object entries
+    revision: u8,
+    /// Whether the SACL is protected from inheritance
+    protected: bool,
+}
+
+/// Whether an ACE of `ace_type` with `flags` maps to an [`AuditEntry`]
+/// without losing anything
+fn is_plain_audit_ace(ace_type: u8, flags: u8) -> bool {
+    use winapi::um::winnt::{
+        CONTAINER_INHERIT_ACE, FAILED_ACCESS_ACE_FLAG, INHERITED_ACE, OBJECT_INHERIT_ACE,
+        SUCCESSFUL_ACCESS_ACE_FLAG, SYSTEM_AUDIT_ACE_TYPE,
+    };
+
+    let known_flags = SUCCESSFUL_ACCESS_ACE_FLAG
+        | FAILED_ACCESS_ACE_FLAG
+        | INHERITED_ACE
+        | OBJECT_INHERIT_ACE
+        | CONTAINER_INHERIT_ACE;
+    ace_type == SYSTEM_AUDIT_ACE_TYPE && flags & !known_flags == 0
+}
+
+/// Read the SACL of a file; the caller must hold `SeSecurityPrivilege`
+fn read_file_sacl(file_path: &str) -> Result<FileSacl> {
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::GetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::{GetAce, GetAclInformation, GetSecurityDescriptorControl};
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{
+        AclSizeInformation, ACE_HEADER, ACL_REVISION, ACL_SIZE_INFORMATION, CONTAINER_INHERIT_ACE,
+        FAILED_ACCESS_ACE_FLAG, INHERITED_ACE, OBJECT_INHERIT_ACE, SACL_SECURITY_INFORMATION,
+        SECURITY_DESCRIPTOR_CONTROL, SE_SACL_PROTECTED, SUCCESSFUL_ACCESS_ACE_FLAG,
+        SYSTEM_AUDIT_ACE,
+    };
+
+    debug!("Reading audit entries of: {}", file_path);
+
+    let wide_path = to_wide_path(file_path);
+    let mut sacl: PACL = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+    unsafe {
+        let result = GetNamedSecurityInfoW(
+            wide_path.as_ptr(),
+            SE_FILE_OBJECT,
+            SACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut sacl,
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to read the audit entries of {}", file_path));
+        }
+
+        let mut control: SECURITY_DESCRIPTOR_CONTROL = 0;
+        let mut revision: DWORD = 0;
+        let protected = GetSecurityDescriptorControl(descriptor, &mut control, &mut revision)
+            != FALSE
+            && control & SE_SACL_PROTECTED != 0;
+
+        if sacl.is_null() {
+            LocalFree(descriptor as _);
+            return Ok(FileSacl {
+                entries: Vec::new(),
+                other_aces: Vec::new(),
+                revision: ACL_REVISION,
+                protected,
+            });
+        }
+
+        let mut size_info: ACL_SIZE_INFORMATION = std::mem::zeroed();
+        if GetAclInformation(
+            sacl,
+            &mut size_info as *mut _ as _,
+            std::mem::size_of::<ACL_SIZE_INFORMATION>() as DWORD,
+            AclSizeInformation,
+        ) == FALSE
+        {
+            LocalFree(descriptor as _);
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to read the SACL size of {}", file_path));
+        }
+
+        let mut entries = Vec::with_capacity(size_info.AceCount as usize);
+        let mut other_aces = Vec::new();
+        for index in 0..size_info.AceCount {
+            let mut ace: *mut winapi::ctypes::c_void = ptr::null_mut();
+            if GetAce(sacl, index, &mut ace) == FALSE {
+                LocalFree(descriptor as _);
+                return Err(std::io::Error::last_os_error()).with_context(|| {
+                    format!("Failed to read SACL entry {} of {}", index, file_path)
+                });
+            }
+
+            let header = &*(ace as *const ACE_HEADER);
+            if !is_plain_audit_ace(header.AceType, header.AceFlags) {
+                if header.AceFlags & INHERITED_ACE == 0 {
+                    debug!(
+                        "Keeping SACL entry of type {} in {} as is",
+                        header.AceType, file_path
+                    );
+                    let raw = std::slice::from_raw_parts(ace as *const u8, header.AceSize as usize);
+                    other_aces.push(raw.to_vec());
+                }
+                continue;
+            }
+
+            let body = &*(ace as *const SYSTEM_AUDIT_ACE);
+            let (sid, principal) = lookup_sid(&body.SidStart as *const DWORD as _);
+            let flags = header.AceFlags;
+            entries.push(AuditEntry {
+                principal,
+                sid,
+                access_mask: body.Mask,
+                rights: describe_access_mask(body.Mask),
+                on_success: flags & SUCCESSFUL_ACCESS_ACE_FLAG != 0,
+                on_failure: flags & FAILED_ACCESS_ACE_FLAG != 0,
+                inherited: flags & INHERITED_ACE != 0,
+                object_inherit: flags & OBJECT_INHERIT_ACE != 0,
+                container_inherit: flags & CONTAINER_INHERIT_ACE != 0,
+            });
+        }
+
+        LocalFree(descriptor as _);
+        Ok((entries, protected))
+    }
+}
+
+/// Replace the SACL of a file with the explicit entries of `sacl`; the
+/// caller must hold `SeSecurityPrivilege`
+fn write_file_sacl(file_path: &str, sacl: &FileSacl) -> Result<()> {
+    use winapi::shared::sddl::ConvertStringSidToSidW;
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::SetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::{AddAce, AddAuditAccessAceEx, GetLengthSid, InitializeAcl};
+    use winapi::um::winnt::{
+        ACL, ACL_REVISION, CONTAINER_INHERIT_ACE, MAXDWORD, OBJECT_INHERIT_ACE,
+        PROTECTED_SACL_SECURITY_INFORMATION, PSID, SACL_SECURITY_INFORMATION, SYSTEM_AUDIT_ACE,
+        UNPROTECTED_SACL_SECURITY_INFORMATION,
+    };
+
+    let entries: Vec<&AuditEntry> = sacl.entries.iter().filter(|e| !e.inherited).collect();
+    let revision = sacl.revision.max(ACL_REVISION) as DWORD;
+    let mut sids = LocalSids(Vec::with_capacity(entries.len()));
+    let mut acl_size =
+        std::mem::size_of::<ACL>() + sacl.other_aces.iter().map(Vec::len).sum::<usize>();
+
+    unsafe {
+        for entry in &entries {
+            let wide_sid = to_wide_string(&entry.sid);
+            let mut sid: PSID = ptr::null_mut();
+            if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Invalid SID: {}", entry.sid));
+            }
+            sids.0.push(sid);
+            acl_size += std::mem::size_of::<SYSTEM_AUDIT_ACE>() - std::mem::size_of::<DWORD>()
+                + GetLengthSid(sid) as usize;
+        }
+
+        // DWORD-aligned buffer, as InitializeAcl requires
+        let mut buffer = vec![0u32; acl_size.div_ceil(4)];
+        let acl = buffer.as_mut_ptr() as PACL;
+        if InitializeAcl(acl, acl_size as DWORD, revision) == FALSE {
+            return Err(std::io::Error::last_os_error()).context("Failed to initialize the SACL");
+        }
+
+        for ace in &sacl.other_aces {
+            if AddAce(
+                acl,
+                revision,
+                MAXDWORD,
+                ace.as_ptr() as *mut _,
+                ace.len() as DWORD,
+            ) == FALSE
+            {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to keep an existing audit entry");
+            }
+        }
+
+        for (entry, &sid) in entries.iter().zip(&sids.0) {
+            let mut flags = 0;
+            if entry.object_inherit {
+                flags |= OBJECT_INHERIT_ACE;
+            }
+            if entry.container_inherit {
+                flags |= CONTAINER_INHERIT_ACE;
+            }
+
+            if AddAuditAccessAceEx(
+                acl,
+                revision,
+                flags as DWORD,
+                entry.access_mask,
+                sid,
+                entry.on_success as i32,
+                entry.on_failure as i32,
+            ) == FALSE
+            {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to add the audit entry for {}", entry.sid));
+            }
+        }
+
+        let inheritance = if sacl.protected {
+            PROTECTED_SACL_SECURITY_INFORMATION
+        } else {
+            UNPROTECTED_SACL_SECURITY_INFORMATION
+        };
+        let mut wide_path = to_wide_path(file_path);
+        let result = SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            SACL_SECURITY_INFORMATION | inheritance,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            acl,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to write the audit entries of {}", file_path));
+        }
+    }
+
+    Ok(())
+}
+
+/// SID of the user running this process, in string form
+pub fn current_user_sid() -> Result<String> {
+    unsafe {
+        let token_user = current_user_token()?;
+        let sid = (*(token_user.as_ptr() as *const winapi::um::winnt::TOKEN_USER)).User.Sid;
+        Ok(lookup_sid(sid).0)
+    }
+}
+
+/// The `TOKEN_USER` of the current process, in a suitably aligned buffer
+pub(crate) unsafe fn current_user_token() -> Result<Vec<u64>> {
+    use winapi::um::winnt::TokenUser;
+
+    let mut token: HANDLE = ptr::null_mut();
+    if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == FALSE {
+        return Err(std::io::Error::last_os_error()).context("Failed to open process token");
+    }
+
+    let mut size: DWORD = 0;
+    GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut size);
+
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    let ok = GetTokenInformation(token, TokenUser, buffer.as_mut_ptr() as _, size, &mut size);
+    let error = std::io::Error::last_os_error();
+    CloseHandle(token);
+
+    if ok == FALSE {
+        return Err(error).context("Failed to query the current user");
+    }
+    Ok(buffer)
+}
+
+/// Replace the DACL of a file with the given explicit entries
+fn write_file_dacl(file_path: &str, entries: &[AceEntry], protected: bool) -> Result<()> {
+    use winapi::shared::sddl::ConvertStringSidToSidW;
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::SetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::{
+        AddAccessAllowedAceEx, AddAccessDeniedAceEx, GetLengthSid, InitializeAcl,
+    };
+    use winapi::um::winnt::{
+        ACCESS_ALLOWED_ACE, ACL, ACL_REVISION, CONTAINER_INHERIT_ACE, INHERIT_ONLY_ACE,
+        NO_PROPAGATE_INHERIT_ACE, OBJECT_INHERIT_ACE, PROTECTED_DACL_SECURITY_INFORMATION,
+        PSID, UNPROTECTED_DACL_SECURITY_INFORMATION,
+    };
+
+    let mut sids = LocalSids(Vec::with_capacity(entries.len()));
+    let mut acl_size = std::mem::size_of::<ACL>();
+
+    unsafe {
+        for entry in entries {
+            let wide_sid = to_wide_string(&entry.sid);
+            let mut sid: PSID = ptr::null_mut();
+            if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Invalid SID: {}", entry.sid));
+            }
+            sids.0.push(sid);
+            acl_size += std::mem::size_of::<ACCESS_ALLOWED_ACE>() - std::mem::size_of::<DWORD>()
+                + GetLengthSid(sid) as usize;
+        }
+
+        // DWORD-aligned buffer, as InitializeAcl requires
+        let mut buffer = vec![0u32; acl_size.div_ceil(4)];
+        let acl = buffer.as_mut_ptr() as PACL;
+        if InitializeAcl(acl, acl_size as DWORD, ACL_REVISION as DWORD) == FALSE {
+            return Err(std::io::Error::last_os_error()).context("Failed to initialize the ACL");
+        }
+
+        for (entry, &sid) in entries.iter().zip(&sids.0) {
+            let mut flags = 0;
+            if entry.object_inherit {
+                flags |= OBJECT_INHERIT_ACE;
+            }
+            if entry.container_inherit {
+                flags |= CONTAINER_INHERIT_ACE;
+            }
+            if entry.inherit_only {
+                flags |= INHERIT_ONLY_ACE;
+            }
+            if entry.no_propagate_inherit {
+                flags |= NO_PROPAGATE_INHERIT_ACE;
+            }
+
+            let add = match entry.ace_type {
+                AceType::Allow => AddAccessAllowedAceEx,
+                AceType::Deny => AddAccessDeniedAceEx,
+                AceType::Audit => anyhow::bail!("Audit entries cannot be written to a DACL"),
+            };
+            if add(
+                acl,
+                ACL_REVISION as DWORD,
+                flags as DWORD,
+                entry.access_mask,
+                sid,
+            ) == FALSE
+            {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to add the ACL entry for {}", entry.sid));
+            }
+        }
+
+        let inheritance = if protected {
+            PROTECTED_DACL_SECURITY_INFORMATION
+        } else {
+            UNPROTECTED_DACL_SECURITY_INFORMATION
+        };
+        let mut wide_path = to_wide_path(file_path);
+        let result = SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | inheritance,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            acl,
+            ptr::null_mut(),
+        );
+        if result != ERROR_SUCCESS {
+            return Err(std::io::Error::from_raw_os_error(result as i32))
+                .with_context(|| format!("Failed to write the ACL of {}", file_path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Summarize an access mask the way the Explorer security tab does
+fn describe_access_mask(mask: u32) -> Vec<&'static str> {
+    use winapi::um::winnt::{DELETE, GENERIC_EXECUTE, GENERIC_READ, GENERIC_WRITE};
+
+    let has = |bits: u32| mask & bits == bits;
+    if mask & GENERIC_ALL != 0 || has(FILE_ALL_ACCESS) {
+        return vec!["full_control"];
+    }
+
+    let mut rights = Vec::new();
+    if mask & GENERIC_READ != 0 || has(FILE_GENERIC_READ) {
+        rights.push("read");
+    }
+    if mask & GENERIC_WRITE != 0 || has(FILE_GENERIC_WRITE) {
+        rights.push("write");
+    }
+    if mask & GENERIC_EXECUTE != 0 || has(FILE_GENERIC_EXECUTE) {
+        rights.push("execute");
+    }
+    if has(DELETE) {
+        rights.push("delete");
+    }
+    if rights.is_empty() {
+        rights.push("special");
+    }
+    rights
+}
+
+/// Resolve a SID to its string form and, if possible, its `DOMAIN\name` account
+///
+/// # Safety
+/// `sid` must point to a valid SID.
+pub(crate) unsafe fn lookup_sid(sid: winapi::um::winnt::PSID) -> (String, Option<String>) {
+    use winapi::shared::sddl::ConvertSidToStringSidW;
+    use winapi::um::winbase::{LocalFree, LookupAccountSidW};
+    use winapi::um::winnt::SID_NAME_USE;
+
+    let mut sid_string: *mut u16 = ptr::null_mut();
+    let sid_text = if ConvertSidToStringSidW(sid, &mut sid_string) != 0 {
+        let text = from_wide_ptr(sid_string);
+        LocalFree(sid_string as _);
+        text
+    } else {
+        "<unknown SID>".to_string()
+    };
+
+    let mut name = [0u16; 256];
+    let mut domain = [0u16; 256];
+    let mut name_len = name.len() as DWORD;
+    let mut domain_len = domain.len() as DWORD;
+    let mut sid_type: SID_NAME_USE = 0;
+
+    let account = if LookupAccountSidW(
+        ptr::null(),
+        sid,
+        name.as_mut_ptr(),
+        &mut name_len,
+        domain.as_mut_ptr(),
+        &mut domain_len,
+        &mut sid_type,
+    ) != 0
+    {
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        Some(if domain.is_empty() { name } else { format!(r"{}\{}", domain, name) })
+    } else {
+        None
+    };
+
+    (sid_text, account)
+}
+
+/// Read a NUL-terminated wide string
+unsafe fn from_wide_ptr(ptr: *const u16) -> String {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::known_folders::{get_known_folder, KnownFolder};
+    use std::env;
+
+    #[test]
+    fn test_describe_access_mask() {
+        assert_eq!(describe_access_mask(FILE_ALL_ACCESS), vec!["full_control"]);
+        assert_eq!(describe_access_mask(GENERIC_ALL), vec!["full_control"]);
+        assert_eq!(
+            describe_access_mask(FILE_GENERIC_READ | FILE_GENERIC_EXECUTE),
+            vec!["read", "execute"]
+        );
+        assert_eq!(describe_access_mask(0x0000_0100), vec!["special"]);
+    }
+
+    #[test]
+    fn test_token_info_diagnose() {
+        use super::super::integrity::IntegrityLevel;
+
+        let info = TokenInfo {
+            user_sid: "S-1-5-21-1-2-3-1001".to_string(),
+            user_account: None,
+            elevated: false,
+            elevation: TokenElevationKind::Limited,
+            integrity_level: IntegrityLevel::Low,
+            privileges: vec![TokenPrivilege {
+                name: "SeChangeNotifyPrivilege".to_string(),
+                enabled: true,
+                enabled_by_default: true,
+            }],
+            groups: vec![TokenGroup {
+                sid: "S-1-5-32-544".to_string(),
+                account: None,
+                enabled: false,
+                deny_only: true,
+                logon_id: false,
+            }],
+        };
+
+        assert!(info.holds_privilege("sechangenotifyprivilege"));
+        assert!(!info.is_member("S-1-5-32-544"));
+        assert!(info
+            .diagnose(&["SeChangeNotifyPrivilege"], IntegrityLevel::Low)
+            .is_empty());
+        assert_eq!(
+            info.diagnose(&["SeBackupPrivilege"], IntegrityLevel::Medium),
+            vec![
+                "running at Low integrity, Medium is required".to_string(),
+                "SeBackupPrivilege not held (available when running as administrator)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_current_token_info() {
+        let info = get_current_token_info().unwrap();
+        assert!(info.user_sid.starts_with("S-1-5-"));
+        assert!(info.holds_privilege("SeChangeNotifyPrivilege"));
+        assert_eq!(info.elevated, is_running_as_admin().unwrap());
+    }
+
+    #[test]
+    fn test_uac_configuration() {
+        let uac = UacConfiguration::default();
+        assert!(uac.issues().is_empty());
+        assert_eq!(uac.elevation_blocked_reason(false), None);
+
+        let disabled = UacConfiguration { enabled: false, ..Default::default() };
+        assert_eq!(disabled.issues(), vec!["User Account Control is disabled"]);
+        assert!(disabled.elevation_blocked_reason(false).is_some());
+        assert_eq!(disabled.elevation_blocked_reason(true), None);
+
+        let denied = UacConfiguration {
+            user_consent: UserConsentBehavior::from_value(0),
+            ..Default::default()
+        };
+        assert!(denied.elevation_blocked_reason(false).is_some());
+        assert_eq!(AdminConsentBehavior::from_value(9), AdminConsentBehavior::Unknown);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_uac_configuration() {
+        let uac = get_uac_configuration().unwrap();
+        if uac == UacConfiguration::default() {
+            assert!(uac.issues().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_diff_acl() {
+        let user = "S-1-5-21-1-2-3-1001";
+        let mut inherited = AceEntry::new("S-1-5-32-545", FILE_GENERIC_READ, AceType::Allow);
+        inherited.inherited = true;
+        let stale = AceEntry::new("S-1-1-0", FILE_ALL_ACCESS, AceType::Allow);
+        let owner = AceEntry::new(user, FILE_ALL_ACCESS, AceType::Allow);
+        let current = vec![stale.clone(), owner.clone(), inherited.clone()];
+
+        // Keeping the inherited entry only removes the stale grant
+        let desired = vec![owner.clone(), inherited.clone()];
+        assert_eq!(
+            diff_acl(&current, &desired),
+            vec![AclChange::Remove { entry: stale.clone() }]
+        );
+
+        // Restricting to the user also cuts inheritance
+        let desired = vec![owner.clone()];
+        assert_eq!(
+            diff_acl(&current, &desired),
+            vec![
+                AclChange::DisableInheritance,
+                AclChange::Remove { entry: stale },
+            ]
+        );
+
+        // Converged ACLs need no changes
+        let converged = std::slice::from_ref(&owner);
+        assert!(diff_acl(converged, &[owner.clone(), owner.clone()]).is_empty());
+        assert_eq!(
+            diff_acl(&[], &[owner.clone(), owner.clone()]),
+            vec![AclChange::Add { entry: owner }]
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_apply_acl_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("credentials.json");
+        std::fs::write(&file, "{}").unwrap();
+        let path = file.to_string_lossy().to_string();
+
+        let mut desired: Vec<AceEntry> = get_file_acl(&path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| AceEntry { inherited: false, ..entry })
+            .collect();
+        desired.push(AceEntry::new(
+            "S-1-5-21-1-2-3-1001",
+            FILE_GENERIC_READ,
+            AceType::Allow,
+        ));
+
+        let changes = diff_acl(&get_file_acl(&path).unwrap(), &desired);
+        assert!(!changes.is_empty());
+        apply_acl_changes(&path, &changes).unwrap();
+        assert!(diff_acl(&get_file_acl(&path).unwrap(), &desired).is_empty());
+    }
+
+    #[test]
+    fn test_object_only_spec() {
+        let mut inheritable = AceEntry::new("S-1-5-18", FILE_ALL_ACCESS, AceType::Allow);
+        inheritable.object_inherit = true;
+        inheritable.container_inherit = true;
+        let mut children_only = inheritable.clone();
+        children_only.inherit_only = true;
+
+        assert_eq!(
+            object_only_spec(&[inheritable, children_only]),
+            vec![AceEntry::new("S-1-5-18", FILE_ALL_ACCESS, AceType::Allow)]
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_set_directory_acl_recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("nested").join("file.txt"), "x").unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+
+        let mut spec: Vec<AceEntry> = get_file_acl(&path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| AceEntry { inherited: false, ..entry })
+            .collect();
+        spec.push(AceEntry::new(
+            "S-1-5-21-1-2-3-1001",
+            FILE_GENERIC_READ,
+            AceType::Allow,
+        ));
+
+        let cancel = AtomicBool::new(false);
+        let mut reports = Vec::new();
+        let result =
+            set_directory_acl_recursive(&path, &spec, &cancel, |p| reports.push(p.clone()))
+                .unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last().unwrap().processed, 3);
+        assert_eq!(result.changed + result.unchanged, 3);
+        assert!(result.errors.is_empty());
+
+        cancel.store(true, Ordering::Relaxed);
+        let result = set_directory_acl_recursive(&path, &spec, &cancel, |_| {}).unwrap();
+        assert!(result.cancelled);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_enable_privilege() {
+        assert!(enable_privilege("SeNotARealPrivilege").is_err());
+
+        if is_running_as_admin().unwrap() {
+            let outer = enable_privilege(SE_DEBUG_NAME).unwrap();
+            assert_eq!(with_privilege(SE_DEBUG_NAME, || 42).unwrap(), 42);
+            // The inner guard must not have disabled the privilege
+            let users = ENABLED_PRIVILEGES.lock().unwrap().as_ref().unwrap()[SE_DEBUG_NAME].0;
+            assert_eq!(users, 1);
+            drop(outer);
+        } else {
+            assert!(with_privilege(SE_DEBUG_NAME, || ()).is_err());
+        }
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_file_acl() {
+        let temp_dir = env::temp_dir();
+        let entries = get_file_acl(&temp_dir.to_string_lossy()).expect("Failed to read ACL");
+        assert!(!entries.is_empty());
+        assert!(entries.iter().all(|entry| entry.sid.starts_with("S-1-")));
+    }
+
+    #[tokio::test]
+    #[ignore] // Integration test - requires Windows and answering the UAC prompt
+    async fn test_request_elevation_exit_code() {
+        let cmd = env::var("ComSpec").unwrap_or_else(|_| r"C:\Windows\System32\cmd.exe".to_string());
+        let options = ElevationOptions {
+            wait_for_exit: true,
+            timeout: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let outcome = request_elevation_with_options(&cmd, &["/C", "exit 3"], &options)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ElevationOutcome::Exited { exit_code: 3, .. }));
+
+        assert!(request_elevation_with_options(r"C:\missing\app.exe", &[], &options)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_file_locked_error_display() {
+        let error = FileLockedError {
+            path: r"C:\project\src\main.rs".to_string(),
+            owner: Some(FileOwner {
+                account: Some(r"BUILTIN\Administrators".to_string()),
+                sid: "S-1-5-32-544".to_string(),
+            }),
+        };
+        assert_eq!(
+            error.to_string(),
+            concat!(
+                r"Access to C:\project\src\main.rs is denied; ",
+                r"the file is owned by BUILTIN\Administrators (S-1-5-32-544)"
+            )
+        );
+
+        let wrapped = anyhow::Error::new(error.clone());
+        assert_eq!(wrapped.downcast_ref::<FileLockedError>(), Some(&error));
+    }
+
+    #[test]
+    fn test_audit_entry_same_audit() {
+        let entry = AuditEntry::new("S-1-1-0", FILE_ALL_ACCESS);
+        assert!(entry.on_success && entry.on_failure);
+        assert_eq!(entry.rights, vec!["full_control"]);
+
+        let inherited = AuditEntry {
+            inherited: true,
+            principal: Some("Everyone".to_string()),
+            sid: "s-1-1-0".to_string(),
+            ..entry.clone()
+        };
+        assert!(entry.same_audit(&inherited));
+
+        let failures_only = AuditEntry {
+            on_success: false,
+            ..entry.clone()
+        };
+        assert!(!entry.same_audit(&failures_only));
+    }
+
+    #[test]
+    fn test_is_plain_audit_ace() {
+        use winapi::um::winnt::{
+            INHERIT_ONLY_ACE, OBJECT_INHERIT_ACE, SUCCESSFUL_ACCESS_ACE_FLAG,
+            SYSTEM_AUDIT_ACE_TYPE, SYSTEM_AUDIT_OBJECT_ACE_TYPE,
+        };
+
+        let flags = SUCCESSFUL_ACCESS_ACE_FLAG | OBJECT_INHERIT_ACE;
+        assert!(is_plain_audit_ace(SYSTEM_AUDIT_ACE_TYPE, flags));
+        // Kept as raw ACEs instead of being dropped on rewrite
+        assert!(!is_plain_audit_ace(SYSTEM_AUDIT_OBJECT_ACE_TYPE, flags));
+        assert!(!is_plain_audit_ace(
+            SYSTEM_AUDIT_ACE_TYPE,
+            flags | INHERIT_ONLY_ACE
+        ));
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows (elevated)
+    fn test_file_audit_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("credentials.json");
+        std::fs::write(&file, "{}").unwrap();
+        let path = file.to_string_lossy().to_string();
+
+        let entry = AuditEntry::new("S-1-1-0", FILE_GENERIC_READ);
+        assert!(add_file_audit_entry(&path, &entry).unwrap());
+        assert!(!add_file_audit_entry(&path, &entry).unwrap());
+        assert!(get_file_audit_entries(&path)
+            .unwrap()
+            .iter()
+            .any(|e| !e.inherited && e.same_audit(&entry)));
+
+        assert!(remove_file_audit_entry(&path, &entry).unwrap());
+        assert!(!remove_file_audit_entry(&path, &entry).unwrap());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_with_file_ownership_consent() {
+        let test_file = env::temp_dir().join("opcode_ownership_test.txt");
+        let path = test_file.to_string_lossy().to_string();
+        std::fs::write(&test_file, b"before").expect("Failed to create test file");
+
+        let owner = get_file_owner(&path).expect("Failed to read owner");
+        assert!(owner.sid.starts_with("S-1-"));
+
+        // Deny ourselves write access
+        let user_sid = unsafe {
+            let token_user = current_user_token().unwrap();
+            lookup_sid((*(token_user.as_ptr() as *const winapi::um::winnt::TOKEN_USER)).User.Sid).0
+        };
+        let deny = AceEntry::new(&user_sid, winapi::um::winnt::FILE_WRITE_DATA, AceType::Deny);
+        apply_acl_changes(&path, &[AclChange::Add { entry: deny }]).unwrap();
+
+        let error = with_file_ownership_consent(&path, false, || std::fs::write(&test_file, b"after"))
+            .unwrap_err();
+        assert!(error.downcast_ref::<FileLockedError>().is_some());
+
+        if is_running_as_admin().unwrap() {
+            with_file_ownership_consent(&path, true, || std::fs::write(&test_file, b"after")).unwrap();
+            assert_eq!(std::fs::read(&test_file).unwrap(), b"after");
+        } else {
+            reset_file_acl(&path).unwrap();
+        }
+
+        std::fs::remove_file(&test_file).expect("Failed to clean up ownership test file");
+    }
+
+    #[test]
+    fn test_is_running_as_admin() {
+        // This test can run in any context
+        let result = is_running_as_admin();
+        assert!(result.is_ok());
+
+        let is_admin = result.expect("Failed to check admin privileges - ensure Windows API is accessible");
+        println!("Running as administrator: {}", is_admin);
+    }
+
+    #[test]
+    fn test_requires_admin_access() {
+        // Test known system directories
+        let result = requires_admin_access(r"C:\Windows\System32");
+        assert!(result.is_ok());
+        assert!(result.expect("Failed to check admin access for System32"), "System32 should require admin access");
+
+        // Test user temp directory (should not require admin)
+        let temp_dir = env::temp_dir();
+        let temp_path = temp_dir.to_str().expect("Temp directory path should be valid UTF-8");
+        let result = requires_admin_access(temp_path);
+        assert!(result.is_ok());
+        assert!(!result.expect("Failed to check admin access for temp directory"), "Temp directory should not require admin access");
+
+        // Paths that don't exist yet are judged by their nearest existing parent
+        let missing = temp_dir.join("opcode_not_created").join("nested");
+        assert!(!requires_admin_access(&missing.to_string_lossy()).unwrap());
+        let program_files = get_known_folder(KnownFolder::ProgramFiles).unwrap();
+        let missing = program_files.join("opcode_not_created");
+        assert!(requires_admin_access(&missing.to_string_lossy()).unwrap());
+
+        // No probe files are left behind
+        assert!(!temp_dir.join(".opcode_test").exists());
+    }
+
+    #[test]
+    fn test_get_effective_permissions() {
+        // Test on temp file
+        let temp_dir = env::temp_dir();
+        let test_file = temp_dir.join("opcode_perm_test.txt");
+
+        // Create test file
+        std::fs::write(&test_file, b"test content").expect("Failed to create test file in temp directory");
+
+        let test_path = test_file.to_str().expect("Test file path should be valid UTF-8");
+        let result = get_effective_permissions(test_path);
+        assert!(result.is_ok(), "Failed to get effective permissions for test file");
+
+        let (can_read, can_write, _, can_delete) = result.expect("Should successfully get permissions for temp file");
+        assert!(can_read, "Should be able to read temp file");
         assert!(can_write, "Should be able to write temp file");
-        assert!(!can_execute, "Text file should not be executable");
         assert!(can_delete, "Should be able to delete temp file");
 
+        // An explicit deny entry for the current user wins over the inherited grants
+        let user_sid = unsafe {
+            let token_user = current_user_token().unwrap();
+            lookup_sid((*(token_user.as_ptr() as *const winapi::um::winnt::TOKEN_USER)).User.Sid).0
+        };
+        let deny = AceEntry::new(&user_sid, winapi::um::winnt::FILE_WRITE_DATA, AceType::Deny);
+        apply_acl_changes(test_path, &[AclChange::Add { entry: deny }]).unwrap();
+
+        let (can_read, can_write, _, _) = get_effective_permissions(test_path).unwrap();
+        assert!(can_read, "Deny write entry should not block reading");
+        assert!(!can_write, "Deny write entry should block writing");
+
         // Clean up
+        reset_file_acl(test_path).expect("Failed to reset test file ACL");
         std::fs::remove_file(&test_file).expect("Failed to clean up test file");
     }
 
@@ -0,0 +1,284 @@
+//! Privilege detection for Linux
+//!
+//! Offers the same entry points as the Windows `permissions` module. Being
+//! "admin" means running with effective UID 0 or holding `CAP_SYS_ADMIN`,
+//! which is how services and containers grant rights without root. The
+//! capability sets come from `/proc/<pid>/status`, and a root process
+//! started through `sudo` or `pkexec` is told apart from a real root login
+//! so files aren't left behind owned by root in the user's home.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::Serialize;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub use super::elevation::request_elevation;
+
+/// A Linux capability Opcode cares about, by its bit number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Chown = 0,
+    DacOverride = 1,
+    DacReadSearch = 2,
+    Fowner = 3,
+    Kill = 5,
+    Setgid = 6,
+    Setuid = 7,
+    NetBindService = 10,
+    NetAdmin = 12,
+    SysPtrace = 19,
+    SysAdmin = 21,
+}
+
+impl Capability {
+    /// All capabilities this module knows about
+    pub const ALL: &'static [Capability] = &[
+        Capability::Chown,
+        Capability::DacOverride,
+        Capability::DacReadSearch,
+        Capability::Fowner,
+        Capability::Kill,
+        Capability::Setgid,
+        Capability::Setuid,
+        Capability::NetBindService,
+        Capability::NetAdmin,
+        Capability::SysPtrace,
+        Capability::SysAdmin,
+    ];
+
+    fn mask(self) -> u64 {
+        1 << self as u64
+    }
+}
+
+/// Capability sets of a process, as bit masks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapabilitySets {
+    pub inheritable: u64,
+    pub permitted: u64,
+    pub effective: u64,
+    pub bounding: u64,
+    pub ambient: u64,
+}
+
+impl CapabilitySets {
+    /// Whether `capability` is in effect
+    pub fn has_effective(&self, capability: Capability) -> bool {
+        self.effective & capability.mask() != 0
+    }
+
+    /// The known capabilities in effect
+    pub fn effective_capabilities(&self) -> Vec<Capability> {
+        Capability::ALL
+            .iter()
+            .copied()
+            .filter(|&capability| self.has_effective(capability))
+            .collect()
+    }
+}
+
+/// User IDs and capability sets from the text of `/proc/<pid>/status`
+fn parse_status(status: &str) -> Option<(u32, CapabilitySets)> {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let mask = |name: &str| u64::from_str_radix(field(name)?, 16).ok();
+
+    let effective_uid = field("Uid")?.split_whitespace().nth(1)?.parse().ok()?;
+    let sets = CapabilitySets {
+        inheritable: mask("CapInh")?,
+        permitted: mask("CapPrm")?,
+        effective: mask("CapEff")?,
+        bounding: mask("CapBnd")?,
+        // Missing before Linux 4.3
+        ambient: mask("CapAmb").unwrap_or(0),
+    };
+    Some((effective_uid, sets))
+}
+
+/// Effective user ID and capability sets of `pid`
+pub fn process_privileges(pid: u32) -> Result<(u32, CapabilitySets)> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid))
+        .with_context(|| format!("Process {} not found", pid))?;
+    parse_status(&status).with_context(|| format!("Unexpected status of process {}", pid))
+}
+
+/// Capability sets of the current process
+pub fn current_capabilities() -> Result<CapabilitySets> {
+    process_privileges(std::process::id()).map(|(_, sets)| sets)
+}
+
+/// Whether the current process holds `capability`
+pub fn has_capability(capability: Capability) -> Result<bool> {
+    Ok(current_capabilities()?.has_effective(capability))
+}
+
+/// Whether the given privileges amount to administrator rights
+pub fn is_admin(effective_uid: u32, capabilities: &CapabilitySets) -> bool {
+    effective_uid == 0 || capabilities.has_effective(Capability::SysAdmin)
+}
+
+/// Check if running with root rights or `CAP_SYS_ADMIN`
+pub fn is_running_as_admin() -> Result<bool> {
+    let effective_uid = unsafe { libc::geteuid() };
+    Ok(is_admin(effective_uid, &current_capabilities()?))
+}
+
+/// The user who started a root process through `sudo` or `pkexec`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ElevatedBy {
+    /// `sudo` or `pkexec`
+    pub tool: &'static str,
+    pub uid: u32,
+    /// Login name, when the tool reports it
+    pub user: Option<String>,
+}
+
+fn elevated_by_from(var: impl Fn(&str) -> Option<String>) -> Option<ElevatedBy> {
+    if let Some(uid) = var("SUDO_UID").and_then(|uid| uid.parse().ok()) {
+        return Some(ElevatedBy {
+            tool: "sudo",
+            uid,
+            user: var("SUDO_USER"),
+        });
+    }
+    let uid = var("PKEXEC_UID")?.parse().ok()?;
+    Some(ElevatedBy {
+        tool: "pkexec",
+        uid,
+        user: None,
+    })
+}
+
+/// Who ran Opcode as root through `sudo` or `pkexec`, if that's how it runs
+///
+/// `None` for a real root login or an unprivileged process; the environment
+/// variables are ignored unless the process actually runs as root.
+pub fn elevated_by() -> Option<ElevatedBy> {
+    if unsafe { libc::geteuid() } != 0 {
+        return None;
+    }
+    elevated_by_from(|name| std::env::var(name).ok())
+}
+
+/// Give what root created below `path` back to the user who ran Opcode
+/// through `sudo` or `pkexec`
+///
+/// Does nothing unless [`elevated_by`] names a user and `path` sits in a
+/// directory that user owns, such as the data directory when `sudo` kept
+/// `HOME`. Otherwise the user could no longer open the database once Opcode
+/// runs unprivileged again. Symlinks are changed, not followed.
+pub fn hand_back_to_invoking_user(path: &Path) -> Result<()> {
+    let Some(by) = elevated_by() else {
+        return Ok(());
+    };
+    let owned_by_user = path
+        .parent()
+        .and_then(|parent| std::fs::metadata(parent).ok())
+        .is_some_and(|metadata| metadata.uid() == by.uid);
+    if !owned_by_user || !path.exists() {
+        return Ok(());
+    }
+    if !has_capability(Capability::Chown)? {
+        anyhow::bail!(
+            "Can't give {} back to user {}: CAP_CHOWN is missing",
+            path.display(),
+            by.uid
+        );
+    }
+
+    let changed = chown_root_owned(path, by.uid)?;
+    if changed > 0 {
+        info!(
+            "Gave {} files below {} back to user {}",
+            changed,
+            path.display(),
+            by.uid
+        );
+    }
+    Ok(())
+}
+
+/// Make `uid` the owner of `path` and everything below it that root owns,
+/// returning how many entries changed
+fn chown_root_owned(path: &Path, uid: u32) -> Result<usize> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut changed = 0;
+    if metadata.uid() == 0 {
+        std::os::unix::fs::lchown(path, Some(uid), None)
+            .with_context(|| format!("Failed to change the owner of {}", path.display()))?;
+        changed += 1;
+    }
+    if metadata.is_dir() {
+        let entries = std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for entry in entries {
+            match entry {
+                Ok(entry) => changed += chown_root_owned(&entry.path(), uid)?,
+                Err(e) => debug!("Skipping an entry of {}: {}", path.display(), e),
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Set Windows ACL on file (unsupported on Linux)
+///
+/// Use `utils::file_permissions` for access levels every platform has.
+pub fn set_file_acl(_file_path: &str, _permissions: &str) -> Result<()> {
+    Err(super::unsupported::Unsupported::new("Windows ACLs").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Name:\tbash\nUid:\t1000\t0\t0\t0\nGid:\t1000\t1000\t1000\t1000\n\
+        CapInh:\t0000000000000000\nCapPrm:\t0000000000200000\nCapEff:\t0000000000200000\n\
+        CapBnd:\t000001ffffffffff\nCapAmb:\t0000000000000000\n";
+
+    #[test]
+    fn test_parse_status() {
+        let (uid, sets) = parse_status(STATUS).unwrap();
+        assert_eq!(uid, 0);
+        assert!(sets.has_effective(Capability::SysAdmin));
+        assert!(!sets.has_effective(Capability::Chown));
+        assert_eq!(sets.effective_capabilities(), vec![Capability::SysAdmin]);
+        assert!(is_admin(1000, &sets));
+        assert!(!is_admin(1000, &CapabilitySets::default()));
+        assert!(parse_status("Name:\tbash\n").is_none());
+    }
+
+    #[test]
+    fn test_elevated_by() {
+        let sudo = elevated_by_from(|name| match name {
+            "SUDO_UID" => Some("1000".to_string()),
+            "SUDO_USER" => Some("me".to_string()),
+            _ => None,
+        });
+        assert_eq!(
+            sudo,
+            Some(ElevatedBy {
+                tool: "sudo",
+                uid: 1000,
+                user: Some("me".to_string()),
+            })
+        );
+        let pkexec = elevated_by_from(|name| (name == "PKEXEC_UID").then(|| "1001".to_string()));
+        assert_eq!(pkexec.map(|by| (by.tool, by.uid)), Some(("pkexec", 1001)));
+        assert_eq!(elevated_by_from(|_| None), None);
+    }
+
+    #[test]
+    fn test_current_privileges() {
+        let (uid, sets) = process_privileges(std::process::id()).unwrap();
+        assert_eq!(uid, unsafe { libc::geteuid() });
+        assert_eq!(is_running_as_admin().unwrap(), is_admin(uid, &sets));
+    }
+}
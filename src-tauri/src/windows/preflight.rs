@@ -0,0 +1,290 @@
+//! Preflight checks for operations that need elevation or privileges
+//!
+//! [`preflight`] describes what a planned operation needs (an elevated
+//! process, privileges such as `SeSecurityPrivilege`) and whether the current
+//! process has it, so the UI can ask for elevation before starting instead of
+//! reporting an access-denied error afterwards.
+
+use super::integrity::IntegrityLevel;
+use super::permissions::{
+    get_current_token_info, get_file_owner, get_uac_configuration, requires_admin_access,
+    TokenInfo, SE_SECURITY_NAME, SE_TAKE_OWNERSHIP_NAME,
+};
+use super::registry::RegistryChange;
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// An operation whose requirements can be checked before running it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    /// Apply registry changes from one of the `plan_*` functions, e.g. a
+    /// machine-wide file association
+    ApplyRegistryPlan { changes: Vec<RegistryChange> },
+    /// Change the ACL of a file or directory
+    SetAcl { path: String },
+    /// Add or remove audit entries on a file or directory
+    SetAuditEntries { path: String },
+    /// Take ownership of a file or directory
+    TakeOwnership { path: String },
+    /// Create or overwrite a file
+    WriteFile { path: String },
+    /// Install or remove a Windows service
+    InstallService { name: String },
+}
+
+impl Operation {
+    fn describe(&self) -> String {
+        match self {
+            Operation::ApplyRegistryPlan { changes } => {
+                format!("Apply {} registry changes", changes.len())
+            }
+            Operation::SetAcl { path } => format!("Change permissions of {}", path),
+            Operation::SetAuditEntries { path } => format!("Change auditing of {}", path),
+            Operation::TakeOwnership { path } => format!("Take ownership of {}", path),
+            Operation::WriteFile { path } => format!("Write {}", path),
+            Operation::InstallService { name } => format!("Install the {} service", name),
+        }
+    }
+}
+
+/// What an operation needs, independent of the current process
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Requirements {
+    elevation: bool,
+    privileges: Vec<&'static str>,
+}
+
+/// A privilege an operation needs and whether the current token has it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PrivilegeRequirement {
+    pub name: String,
+    /// Held by the current token; disabled privileges are enabled when needed
+    pub held: bool,
+}
+
+/// Result of [`preflight`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RequirementReport {
+    /// Human-readable description of the operation
+    pub operation: String,
+    pub requires_elevation: bool,
+    pub required_privileges: Vec<PrivilegeRequirement>,
+    /// Whether opcode is running elevated
+    pub elevated: bool,
+    /// The operation can run in this process as it is
+    pub satisfied: bool,
+    /// Why elevating won't help (e.g. UAC disabled for a standard user)
+    pub elevation_blocked: Option<String>,
+    /// What is missing, suitable for showing to the user
+    pub problems: Vec<String>,
+}
+
+impl RequirementReport {
+    /// Whether the UI should offer to elevate before running the operation
+    pub fn should_prompt_for_elevation(&self) -> bool {
+        !self.satisfied && !self.elevated && self.elevation_blocked.is_none()
+    }
+}
+
+/// Work out what `operation` needs; may read file owners and ACLs
+fn requirements(operation: &Operation, token: &TokenInfo) -> Requirements {
+    let owned_by_user = |path: &str| match get_file_owner(path) {
+        Ok(owner) => token.user_sid == owner.sid,
+        Err(e) => {
+            debug!("Could not read the owner of {}: {:#}", path, e);
+            false
+        }
+    };
+
+    match operation {
+        Operation::ApplyRegistryPlan { changes } => Requirements {
+            elevation: changes.iter().any(|change| change.requires_elevation),
+            privileges: Vec::new(),
+        },
+        // Owners can always change the DACL
+        Operation::SetAcl { path } => Requirements {
+            elevation: !owned_by_user(path),
+            privileges: Vec::new(),
+        },
+        Operation::SetAuditEntries { .. } => Requirements {
+            elevation: true,
+            privileges: vec![SE_SECURITY_NAME],
+        },
+        Operation::TakeOwnership { path } if owned_by_user(path) => Requirements::default(),
+        Operation::TakeOwnership { .. } => Requirements {
+            elevation: true,
+            privileges: vec![SE_TAKE_OWNERSHIP_NAME],
+        },
+        Operation::WriteFile { path } => Requirements {
+            elevation: requires_admin_access(path).unwrap_or_else(|e| {
+                warn!("Could not check access to {}: {:#}", path, e);
+                false
+            }),
+            privileges: Vec::new(),
+        },
+        Operation::InstallService { .. } => Requirements {
+            elevation: true,
+            privileges: Vec::new(),
+        },
+    }
+}
+
+/// Compare requirements against the current token
+fn evaluate(
+    operation: &Operation,
+    requirements: &Requirements,
+    token: &TokenInfo,
+    elevation_blocked: Option<&str>,
+) -> RequirementReport {
+    let mut problems = Vec::new();
+    if requirements.elevation && !token.elevated {
+        problems.push("Administrator privileges are required".to_string());
+    }
+    problems.extend(token.diagnose(&requirements.privileges, IntegrityLevel::Medium));
+
+    let required_privileges = requirements
+        .privileges
+        .iter()
+        .map(|name| PrivilegeRequirement {
+            name: name.to_string(),
+            held: token.holds_privilege(name),
+        })
+        .collect();
+
+    RequirementReport {
+        operation: operation.describe(),
+        requires_elevation: requirements.elevation,
+        required_privileges,
+        elevated: token.elevated,
+        satisfied: problems.is_empty(),
+        elevation_blocked: if requirements.elevation && !token.elevated {
+            elevation_blocked.map(str::to_string)
+        } else {
+            None
+        },
+        problems,
+    }
+}
+
+/// Check whether `operation` can run in this process, and what it needs if not
+///
+/// Reads file owners and ACLs for path-based operations; it doesn't change
+/// anything.
+pub fn preflight(operation: &Operation) -> Result<RequirementReport> {
+    let token = get_current_token_info()?;
+    let requirements = requirements(operation, &token);
+
+    let elevation_blocked = match get_uac_configuration() {
+        Ok(uac) => uac.elevation_blocked_reason(token.is_administrator()),
+        Err(e) => {
+            debug!("Could not read the UAC configuration: {:#}", e);
+            None
+        }
+    };
+
+    let report = evaluate(operation, &requirements, &token, elevation_blocked);
+    debug!("Preflight for {}: {:?}", report.operation, report.problems);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::permissions::{TokenElevationKind, TokenPrivilege};
+    use super::*;
+
+    fn token(elevated: bool, privileges: &[&str]) -> TokenInfo {
+        TokenInfo {
+            user_sid: "S-1-5-21-1-2-3-1001".to_string(),
+            user_account: None,
+            elevated,
+            elevation: if elevated {
+                TokenElevationKind::Full
+            } else {
+                TokenElevationKind::Limited
+            },
+            integrity_level: if elevated {
+                IntegrityLevel::High
+            } else {
+                IntegrityLevel::Medium
+            },
+            privileges: privileges
+                .iter()
+                .map(|name| TokenPrivilege {
+                    name: name.to_string(),
+                    enabled: false,
+                    enabled_by_default: false,
+                })
+                .collect(),
+            groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_is_administrator() {
+        assert!(token(true, &[]).is_administrator());
+        // An administrator running with the filtered token
+        assert!(token(false, &[]).is_administrator());
+        let standard_user = TokenInfo {
+            elevation: TokenElevationKind::Default,
+            ..token(false, &[])
+        };
+        assert!(!standard_user.is_administrator());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let operation = Operation::SetAuditEntries {
+            path: r"C:\data".to_string(),
+        };
+        let needs = Requirements {
+            elevation: true,
+            privileges: vec![SE_SECURITY_NAME],
+        };
+
+        let report = evaluate(&operation, &needs, &token(false, &[]), None);
+        assert!(!report.satisfied);
+        assert!(report.should_prompt_for_elevation());
+        assert_eq!(report.problems.len(), 2);
+        assert!(!report.required_privileges[0].held);
+
+        let report = evaluate(
+            &operation,
+            &needs,
+            &token(false, &[]),
+            Some("UAC is disabled"),
+        );
+        assert_eq!(report.elevation_blocked.as_deref(), Some("UAC is disabled"));
+        assert!(!report.should_prompt_for_elevation());
+
+        let report = evaluate(&operation, &needs, &token(true, &[SE_SECURITY_NAME]), None);
+        assert!(report.satisfied);
+        assert!(report.problems.is_empty());
+        assert!(report.required_privileges[0].held);
+    }
+
+    #[test]
+    fn test_registry_plan_requirements() {
+        let changes: Vec<RegistryChange> = serde_json::from_str(
+            r#"[{"root":"HKEY_CURRENT_USER","key":"Software\\Classes\\.opcode","action":{"type":"delete_value","name":""},"requires_elevation":false}]"#,
+        )
+        .unwrap();
+        let operation = Operation::ApplyRegistryPlan { changes };
+
+        let needs = requirements(&operation, &token(false, &[]));
+        assert_eq!(needs, Requirements::default());
+        assert!(evaluate(&operation, &needs, &token(false, &[]), None).satisfied);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_preflight_install_service() {
+        let report = preflight(&Operation::InstallService {
+            name: "opcode".to_string(),
+        })
+        .unwrap();
+        assert!(report.requires_elevation);
+        assert_eq!(report.satisfied, report.elevated);
+    }
+}
@@ -8,6 +8,7 @@
 //! - **Process Discovery**: Find processes by name with advanced filtering
 //! - **Privilege Detection**: Check elevation status and administrator privileges
 //! - **Process Information**: Detailed metadata including parent relationships
+//! - **Minidumps**: Thread and handle dumps of hung processes for diagnosis
 //! - **Error Resilience**: Comprehensive error handling with graceful degradation
 //!
 //! # Platform Compatibility
@@ -82,9 +83,9 @@
 //! ```
 
 use anyhow::{Context, Result};
+use crate::process::command::{run_with_timeout, DEFAULT_COMMAND_TIMEOUT};
 use log::{debug, error, info, warn};
 use std::collections::HashSet;
-use std::process::Command;
 use tokio::process::Command as TokioCommand;
 
 /// Comprehensive process information structure
@@ -143,15 +144,11 @@ pub struct ProcessInfo {
 
 /// Kill a process tree (process and all its children) by PID
 ///
-/// Recursively terminates a process and all its descendant processes using a
-/// bottom-up approach to ensure clean shutdown. This function implements a
-/// two-phase termination strategy:
+/// Terminates a process and all its descendant processes with `taskkill /T`,
+/// which walks the tree itself instead of depending on WMI queries.
 ///
-/// 1. **Discovery Phase**: Build complete process hierarchy using WMI queries
-/// 2. **Termination Phase**: Kill children first, then parent (bottom-up)
-///
-/// Each process termination attempts graceful shutdown first (`taskkill /PID`),
-/// then forced termination if graceful fails (`taskkill /F /PID`).
+/// Termination attempts graceful shutdown first (`taskkill /T /PID`), then
+/// forced termination if graceful fails (`taskkill /T /F /PID`).
 ///
 /// # Arguments
 /// * `pid` - Process ID of the root process to terminate
@@ -164,15 +161,10 @@ pub struct ProcessInfo {
 /// # Errors
 /// This function can return errors in several scenarios:
 /// - **Access Denied**: Insufficient privileges to terminate the process
-/// - **System Command Failure**: `taskkill` or `wmic` commands fail
+/// - **System Command Failure**: `taskkill` fails
 /// - **Process Protection**: Attempting to kill protected system processes
 /// - **Resource Exhaustion**: System too loaded to execute commands
 ///
-/// # Performance
-/// - Time complexity: O(n) where n is total processes in system
-/// - Typical execution: 100-500ms depending on process tree size
-/// - Memory usage: ~1MB for process relationship mapping
-///
 /// # Examples
 ///
 /// ## Basic Usage
@@ -250,56 +242,37 @@ pub struct ProcessInfo {
 /// - Antivirus or security software
 ///
 /// # Platform Behavior
-/// - **Windows**: Full implementation using taskkill and wmic
+/// - **Windows**: Full implementation using `taskkill /T`
 /// - **Non-Windows**: Returns `Ok(false)` (no-op implementation)
 pub async fn kill_process_tree(pid: u32) -> Result<bool> {
     info!("Attempting to kill process tree starting from PID {}", pid);
 
-    // First, get all child processes recursively
-    let child_pids = get_child_processes_recursive(pid).await
-        .context("Failed to get child processes")?;
-
-    debug!("Found {} child processes to terminate", child_pids.len());
-
-    // Kill child processes first (bottom-up approach)
-    for &child_pid in &child_pids {
-        if child_pid != pid {
-            debug!("Terminating child process {}", child_pid);
-            let _ = kill_single_process(child_pid).await; // Continue even if some fail
-        }
-    }
-
-    // Finally, kill the root process
-    debug!("Terminating root process {}", pid);
-    kill_single_process(pid).await
-        .context(format!("Failed to kill root process {}", pid))
-}
-
-/// Kill a single process by PID
-async fn kill_single_process(pid: u32) -> Result<bool> {
-    // First try graceful termination with taskkill
-    let output = TokioCommand::new("taskkill")
-        .args(["/PID", &pid.to_string()])
-        .output()
-        .await
-        .context("Failed to execute taskkill command")?;
+    // taskkill /T walks the tree itself, so this cannot hang on a stalled WMI
+    // query; run_with_timeout relies on that to kill a hung helper command
+    let output = run_with_timeout(
+        TokioCommand::new("taskkill").args(["/T", "/PID", &pid.to_string()]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to execute taskkill command")?;
 
     if output.status.success() {
-        info!("Successfully terminated process {} gracefully", pid);
+        info!("Successfully terminated process tree {} gracefully", pid);
         return Ok(true);
     }
 
     // If graceful termination failed, try forced termination
     warn!("Graceful termination failed for PID {}, attempting forced termination", pid);
 
-    let output = TokioCommand::new("taskkill")
-        .args(["/F", "/PID", &pid.to_string()])
-        .output()
-        .await
-        .context("Failed to execute forced taskkill command")?;
+    let output = run_with_timeout(
+        TokioCommand::new("taskkill").args(["/T", "/F", "/PID", &pid.to_string()]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to execute forced taskkill command")?;
 
     if output.status.success() {
-        info!("Successfully force-terminated process {}", pid);
+        info!("Successfully force-terminated process tree {}", pid);
         Ok(true)
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -313,41 +286,15 @@ async fn kill_single_process(pid: u32) -> Result<bool> {
     }
 }
 
-/// Get all child processes recursively
-async fn get_child_processes_recursive(parent_pid: u32) -> Result<Vec<u32>> {
-    let mut all_children = Vec::new();
-    let mut to_process = vec![parent_pid];
-    let mut visited = HashSet::new();
-
-    // Get all process relationships first
-    let process_map = get_process_parent_map().await
-        .context("Failed to get process parent relationships")?;
-
-    while let Some(current_pid) = to_process.pop() {
-        if visited.contains(&current_pid) {
-            continue; // Avoid infinite loops
-        }
-        visited.insert(current_pid);
-        all_children.push(current_pid);
-
-        // Find direct children of current process
-        for (&pid, &ppid) in &process_map {
-            if ppid == current_pid && !visited.contains(&pid) {
-                to_process.push(pid);
-            }
-        }
-    }
-
-    Ok(all_children)
-}
-
 /// Get a map of PID -> Parent PID for all running processes
 async fn get_process_parent_map() -> Result<std::collections::HashMap<u32, u32>> {
-    let output = TokioCommand::new("wmic")
-        .args(["process", "get", "ProcessId,ParentProcessId", "/format:csv"])
-        .output()
-        .await
-        .context("Failed to execute wmic command")?;
+    let output = run_with_timeout(
+        TokioCommand::new("wmic")
+            .args(["process", "get", "ProcessId,ParentProcessId", "/format:csv"]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to execute wmic command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -396,11 +343,13 @@ async fn get_process_parent_map() -> Result<std::collections::HashMap<u32, u32>>
 pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
     debug!("Searching for processes with name: {}", name);
 
-    let output = TokioCommand::new("tasklist")
-        .args(["/FI", &format!("IMAGENAME eq {}", name), "/FO", "CSV", "/NH"])
-        .output()
-        .await
-        .context("Failed to execute tasklist command")?;
+    let output = run_with_timeout(
+        TokioCommand::new("tasklist")
+            .args(["/FI", &format!("IMAGENAME eq {}", name), "/FO", "CSV", "/NH"]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to execute tasklist command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -437,8 +386,9 @@ pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
 ///
 /// # Returns
 /// * `Ok(true)` if the process is elevated
-/// * `Ok(false)` if the process is not elevated or not found
-/// * `Err(...)` if there was an error checking the process
+/// * `Ok(false)` if the process is not elevated
+/// * `Err(...)` if the process doesn't exist or its token can't be read, as
+///   happens for an elevated process checked from an unelevated one
 ///
 /// # Example
 /// ```rust
@@ -452,44 +402,89 @@ pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
 /// }
 /// ```
 pub async fn is_process_elevated(pid: u32) -> Result<bool> {
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{OpenProcess, OpenProcessToken};
+    use winapi::um::securitybaseapi::GetTokenInformation;
+    use winapi::um::winnt::{
+        TokenElevation, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+
     debug!("Checking elevation status for process {}", pid);
 
-    // Use PowerShell to check process elevation status
-    let script = format!(
-        r#"
-        try {{
-            $process = Get-Process -Id {} -ErrorAction Stop
-            $processHandle = $process.Handle
-            $identity = [System.Security.Principal.WindowsIdentity]::GetCurrent()
-            $principal = New-Object System.Security.Principal.WindowsPrincipal($identity)
-            $isAdmin = $principal.IsInRole([System.Security.Principal.WindowsBuiltInRole]::Administrator)
-            Write-Output $isAdmin.ToString()
-        }} catch {{
-            Write-Output "False"
-        }}
-        "#,
-        pid
-    );
-
-    let output = TokioCommand::new("powershell")
-        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
-        .output()
-        .await
-        .context("Failed to execute PowerShell command")?;
+    // Read the elevation flag from the process token instead of asking a
+    // PowerShell script, which only ever reported the caller's own status
+    let is_elevated = unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+        if process.is_null() {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to open process {}", pid));
+        }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        warn!("PowerShell command failed for PID {}: {}", pid, stderr);
-        return Ok(false);
-    }
+        let mut token: HANDLE = std::ptr::null_mut();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        CloseHandle(process);
+        if opened == FALSE {
+            // Tokens of elevated processes can't be opened from an unelevated one
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to open the token of process {}", pid));
+        }
 
-    let output_str = String::from_utf8_lossy(&output.stdout).trim().to_lowercase();
-    let is_elevated = output_str == "true";
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as DWORD;
+        let queried = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut _ as *mut _,
+            size,
+            &mut size,
+        );
+        CloseHandle(token);
+        if queried == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to query the elevation of process {}", pid));
+        }
+        elevation.TokenIsElevated != 0
+    };
 
     debug!("Process {} elevation status: {}", pid, is_elevated);
     Ok(is_elevated)
 }
 
+/// Check whether a process is still running
+///
+/// A process that exists but can't be opened, such as a protected system
+/// process, counts as running.
+pub async fn is_process_running(pid: u32) -> Result<bool> {
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::shared::winerror::{ERROR_ACCESS_DENIED, ERROR_INVALID_PARAMETER};
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+        if process.is_null() {
+            let error = std::io::Error::last_os_error();
+            return match error.raw_os_error().map(|code| code as DWORD) {
+                Some(ERROR_INVALID_PARAMETER) => Ok(false),
+                Some(ERROR_ACCESS_DENIED) => Ok(true),
+                _ => Err(error).with_context(|| format!("Failed to open process {}", pid)),
+            };
+        }
+
+        let mut exit_code: DWORD = 0;
+        let queried = GetExitCodeProcess(process, &mut exit_code);
+        CloseHandle(process);
+        if queried == FALSE {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to query the exit code of process {}", pid));
+        }
+        Ok(exit_code == STILL_ACTIVE)
+    }
+}
+
 /// Get detailed process information including name, parent PID, and elevation status
 ///
 /// # Arguments
@@ -515,12 +510,17 @@ pub async fn is_process_elevated(pid: u32) -> Result<bool> {
 pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
     let mut process_info = Vec::new();
 
+    // Lets the helper commands see processes of other users when elevated
+    let _debug_privilege = super::permissions::debug_privilege_if_elevated();
+
     // Get basic process information using tasklist
-    let output = TokioCommand::new("tasklist")
-        .args(["/FO", "CSV", "/NH"])
-        .output()
-        .await
-        .context("Failed to execute tasklist command")?;
+    let output = run_with_timeout(
+        TokioCommand::new("tasklist")
+            .args(["/FO", "CSV", "/NH"]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to execute tasklist command")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -572,6 +572,62 @@ pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
     Ok(process_info)
 }
 
+/// Write a minidump of a process to `path`, for diagnosing a hung or
+/// misbehaving Claude session
+///
+/// The dump holds the threads, their stacks and the open handles, not the
+/// whole address space. When opcode is elevated, `SeDebugPrivilege` is
+/// enabled while the process is open, so processes of other users can be
+/// dumped as well. Blocks until the dump is written.
+///
+/// # Arguments
+/// * `pid` - Process ID to dump
+/// * `path` - File to write; replaced if it exists
+pub fn write_minidump(pid: u32, path: &std::path::Path) -> Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::shared::minwindef::FALSE;
+    use winapi::um::dbghelp::{MiniDumpWithHandleData, MiniDumpWithThreadInfo, MiniDumpWriteDump};
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::winnt::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
+
+    let _debug_privilege = super::permissions::debug_privilege_if_elevated();
+    // Stacks can hold API keys, so only the current user may read the dump
+    let _ = std::fs::remove_file(path);
+    let file = super::secure_temp::create_secure_file(path)?;
+
+    let written = unsafe {
+        let process = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, FALSE, pid);
+        if process.is_null() {
+            drop(file);
+            let _ = std::fs::remove_file(path);
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to open process {}", pid));
+        }
+
+        let written = MiniDumpWriteDump(
+            process,
+            pid,
+            file.as_raw_handle() as _,
+            MiniDumpWithHandleData | MiniDumpWithThreadInfo,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        let error = std::io::Error::last_os_error();
+        CloseHandle(process);
+        (written != FALSE).then_some(()).ok_or(error)
+    };
+
+    if let Err(e) = written {
+        drop(file);
+        let _ = std::fs::remove_file(path);
+        return Err(e).with_context(|| format!("Failed to write a minidump of process {}", pid));
+    }
+    debug!("Wrote a minidump of process {} to {}", pid, path.display());
+    Ok(())
+}
+
 /// Check if current process is running with administrator privileges
 ///
 /// This is a convenience function to check the current process elevation status.
@@ -581,26 +637,42 @@ pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
 /// * `Ok(false)` if the current process is not elevated
 /// * `Err(...)` if there was an error checking the status
 pub fn is_current_process_elevated() -> Result<bool> {
-    // Use Windows API to check current process token
-    let script = r#"
-        $identity = [System.Security.Principal.WindowsIdentity]::GetCurrent()
-        $principal = New-Object System.Security.Principal.WindowsPrincipal($identity)
-        $isAdmin = $principal.IsInRole([System.Security.Principal.WindowsBuiltInRole]::Administrator)
-        Write-Output $isAdmin.ToString()
-    "#;
-
-    let output = std::process::Command::new("powershell")
-        .args(["-NoProfile", "-NonInteractive", "-Command", script])
-        .output()
-        .context("Failed to execute PowerShell command")?;
+    super::permissions::is_running_as_admin()
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow::anyhow!("PowerShell command failed: {}", stderr));
+/// [`ProcessManager`](super::process_manager::ProcessManager) backed by
+/// `taskkill`, WMI and process tokens
+pub struct WindowsProcessManager;
+
+#[async_trait::async_trait]
+impl super::process_manager::ProcessManager for WindowsProcessManager {
+    fn backend(&self) -> &'static str {
+        "windows"
+    }
+
+    async fn kill_tree(&self, pid: u32) -> Result<bool> {
+        kill_process_tree(pid).await
+    }
+
+    async fn is_running(&self, pid: u32) -> Result<bool> {
+        is_process_running(pid).await
     }
 
-    let output_str = String::from_utf8_lossy(&output.stdout).trim().to_lowercase();
-    Ok(output_str == "true")
+    async fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+        list_processes_by_name(name).await
+    }
+
+    async fn is_elevated(&self, pid: u32) -> Result<bool> {
+        is_process_elevated(pid).await
+    }
+
+    async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+        get_process_info(pids).await
+    }
+
+    fn is_current_elevated(&self) -> Result<bool> {
+        is_current_process_elevated()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,311 @@
+//! Process management for Linux, read from procfs
+//!
+//! Offers the same functions as the Windows `process` module. The process
+//! table comes from `/proc/<pid>/stat` instead of `wmic` and `tasklist`,
+//! and processes are signalled directly instead of through `taskkill`.
+//! A session's tree is found both by walking parent links and through its
+//! process group, so grandchildren whose parent already exited (and were
+//! reparented to init) are still taken down with it.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::path::Path;
+use std::time::Duration;
+
+pub use super::process_posix::ProcessInfo;
+use super::process_posix::{terminate_tree, tree_pids, TableEntry};
+
+/// How often [`kill_process_tree`] checks whether the tree has exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The fields of `/proc/<pid>/stat` this module uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProcStat {
+    pid: u32,
+    /// Executable name, truncated by the kernel to 15 bytes
+    comm: String,
+    state: char,
+    ppid: u32,
+    pgrp: u32,
+}
+
+impl ProcStat {
+    /// Zombies have exited and only wait to be reaped
+    fn is_running(&self) -> bool {
+        !matches!(self.state, 'Z' | 'X' | 'x')
+    }
+}
+
+impl TableEntry for ProcStat {
+    fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn ppid(&self) -> u32 {
+        self.ppid
+    }
+
+    fn pgid(&self) -> u32 {
+        self.pgrp
+    }
+}
+
+/// Parse a `/proc/<pid>/stat` line
+///
+/// The name is in parentheses and may itself contain spaces and
+/// parentheses, so it runs up to the last `)` of the line.
+fn parse_stat(line: &str) -> Option<ProcStat> {
+    let open = line.find('(')?;
+    let close = line.rfind(')')?;
+    let pid = line[..open].trim().parse().ok()?;
+    let comm = line.get(open + 1..close)?.to_string();
+    let mut fields = line[close + 1..].split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let ppid = fields.next()?.parse().ok()?;
+    let pgrp = fields.next()?.parse().ok()?;
+    Some(ProcStat {
+        pid,
+        comm,
+        state,
+        ppid,
+        pgrp,
+    })
+}
+
+fn read_stat(pid: u32) -> Option<ProcStat> {
+    std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|line| parse_stat(&line))
+}
+
+/// Every process visible in `/proc`
+fn all_processes() -> Result<Vec<ProcStat>> {
+    let entries = std::fs::read_dir("/proc").context("Failed to read /proc")?;
+    Ok(entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        // Processes can exit between listing and reading
+        .filter_map(read_stat)
+        .collect())
+}
+
+fn is_running(pid: u32) -> bool {
+    read_stat(pid).is_some_and(|stat| stat.is_running())
+}
+
+/// Kill a process tree (process and all its descendants) by PID
+///
+/// Sends SIGTERM to the whole tree, children first, waits up to
+/// [`TERMINATE_GRACE`](super::process_posix::TERMINATE_GRACE) for it to
+/// exit and then sends SIGKILL to whatever is left. The calling process is
+/// never signalled, even if it is part of the tree.
+///
+/// # Returns
+/// * `Ok(true)` - Process tree was terminated
+/// * `Ok(false)` - Process was not found or already terminated
+/// * `Err(anyhow::Error)` - The root process couldn't be signalled, e.g.
+///   because it belongs to another user
+pub async fn kill_process_tree(pid: u32) -> Result<bool> {
+    info!("Attempting to kill process tree starting from PID {}", pid);
+    if pid == 0 {
+        // kill(0, ...) would signal our own process group
+        anyhow::bail!("Refusing to kill PID 0");
+    }
+
+    let processes = all_processes()?;
+    if !processes.iter().any(|p| p.pid == pid && p.is_running()) {
+        debug!("Process {} was already terminated or not found", pid);
+        return Ok(false);
+    }
+
+    let own_pid = std::process::id();
+    let pids: Vec<u32> = tree_pids(pid, &processes)
+        .into_iter()
+        .filter(|&p| p != own_pid)
+        .collect();
+    debug!("Found {} processes to terminate", pids.len());
+
+    terminate_tree(pid, pids, EXIT_POLL_INTERVAL, |remaining| async move {
+        Ok(remaining.into_iter().filter(|&p| is_running(p)).collect())
+    })
+    .await
+}
+
+/// Name of the executable `pid` runs, from its `exe` link
+///
+/// Unreadable for processes of other users.
+fn exe_name(pid: u32) -> Option<String> {
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+    Some(exe.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Basename of the first command-line argument of `pid`
+///
+/// Scripts run by an interpreter, such as the Claude CLI under node, are
+/// only recognizable by this.
+fn argv0_name(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let argv0 = cmdline.split(|&b| b == 0).next()?;
+    let argv0 = String::from_utf8_lossy(argv0);
+    Path::new(argv0.as_ref())
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Check whether a process is still running (zombies count as exited)
+pub async fn is_process_running(pid: u32) -> Result<bool> {
+    Ok(is_running(pid))
+}
+
+/// List all processes with a specific name
+///
+/// A process matches when `name` is its executable's file name, the file
+/// name of its first argument, or its (possibly truncated) kernel name.
+/// Unlike on Windows the comparison is case-sensitive.
+pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
+    debug!("Searching for processes with name: {}", name);
+    let pids: Vec<u32> = all_processes()?
+        .into_iter()
+        .filter(|process| process.is_running())
+        .filter(|process| {
+            process.comm == name
+                || exe_name(process.pid).as_deref() == Some(name)
+                || argv0_name(process.pid).as_deref() == Some(name)
+        })
+        .map(|process| process.pid)
+        .collect();
+    debug!("Found {} processes named {}", pids.len(), name);
+    Ok(pids)
+}
+
+/// Check if a process runs as root or holds `CAP_SYS_ADMIN`
+///
+/// Looks at the effective user ID and capabilities in `/proc/<pid>/status`.
+pub async fn is_process_elevated(pid: u32) -> Result<bool> {
+    let (effective_uid, capabilities) = super::permissions::process_privileges(pid)?;
+    Ok(super::permissions::is_admin(effective_uid, &capabilities))
+}
+
+/// Get information about the given processes
+///
+/// Returns one entry per PID, in order. Processes that can't be read are
+/// named `PID-<pid>` and have no parent.
+pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+    let mut process_info = Vec::with_capacity(pids.len());
+    for &pid in pids {
+        let stat = read_stat(pid);
+        let name = exe_name(pid)
+            .or_else(|| stat.as_ref().map(|stat| stat.comm.clone()))
+            .unwrap_or_else(|| format!("PID-{}", pid));
+        let parent_pid = stat.map(|stat| stat.ppid).filter(|&ppid| ppid > 0);
+        let is_elevated = is_process_elevated(pid).await.unwrap_or(false);
+        process_info.push(ProcessInfo {
+            pid,
+            name,
+            parent_pid,
+            is_elevated,
+        });
+    }
+    Ok(process_info)
+}
+
+/// Check if the current process runs as root or holds `CAP_SYS_ADMIN`
+pub fn is_current_process_elevated() -> Result<bool> {
+    super::permissions::is_running_as_admin()
+}
+
+/// [`ProcessManager`](super::process_manager::ProcessManager) backed by procfs
+pub struct ProcfsProcessManager;
+
+#[async_trait::async_trait]
+impl super::process_manager::ProcessManager for ProcfsProcessManager {
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    async fn kill_tree(&self, pid: u32) -> Result<bool> {
+        kill_process_tree(pid).await
+    }
+
+    async fn is_running(&self, pid: u32) -> Result<bool> {
+        is_process_running(pid).await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+        list_processes_by_name(name).await
+    }
+
+    async fn is_elevated(&self, pid: u32) -> Result<bool> {
+        is_process_elevated(pid).await
+    }
+
+    async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+        get_process_info(pids).await
+    }
+
+    fn is_current_elevated(&self) -> Result<bool> {
+        is_current_process_elevated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let parsed = parse_stat("4242 (my (odd) name) S 1 4242 4242 0 -1 4194560 96 0").unwrap();
+        assert_eq!(parsed.pid, 4242);
+        assert_eq!(parsed.comm, "my (odd) name");
+        assert_eq!(parsed.state, 'S');
+        assert_eq!(parsed.ppid, 1);
+        assert_eq!(parsed.pgrp, 4242);
+
+        assert!(parse_stat("").is_none());
+        assert!(parse_stat("12 (truncated").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_kill_process_tree() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 60 & wait"])
+            .spawn()
+            .unwrap();
+        let root = child.id();
+
+        // Wait for the shell to start its sleep
+        let mut grandchild = None;
+        for _ in 0..100 {
+            grandchild = all_processes()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.ppid == root)
+                .map(|p| p.pid);
+            if grandchild.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let grandchild = grandchild.expect("sleep did not start");
+
+        assert!(kill_process_tree(root).await.unwrap());
+        child.wait().unwrap();
+        assert!(!is_running(grandchild));
+        assert!(!kill_process_tree(root).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_process_lookup() {
+        let own_pid = std::process::id();
+        let own = read_stat(own_pid).unwrap();
+        let pids = list_processes_by_name(&own.comm).await.unwrap();
+        assert!(pids.contains(&own_pid));
+
+        let info = get_process_info(&[own_pid]).await.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].pid, own_pid);
+        assert!(!info[0].name.is_empty());
+        assert_eq!(info[0].parent_pid, Some(own.ppid));
+        assert_eq!(info[0].is_elevated, is_current_process_elevated().unwrap());
+    }
+}
@@ -0,0 +1,443 @@
+//! Process management for macOS, read through libproc
+//!
+//! Offers the same functions as the Windows `process` module. The process
+//! table comes from `proc_listallpids` and `proc_pidinfo`, the same source
+//! `ps` uses. A tree is terminated by signalling whole process groups where
+//! a member of the tree leads one, so subprocesses that were reparented to
+//! launchd after their parent exited stop with the session too.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CStr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub use super::process_posix::ProcessInfo;
+use super::process_posix::{check_signal, send_signal, TERMINATE_GRACE};
+
+/// How often [`kill_process_tree`] checks whether the tree has exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The fields of `proc_bsdinfo` this module uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProcEntry {
+    pid: u32,
+    ppid: u32,
+    pgid: u32,
+    /// Effective user ID
+    uid: u32,
+    name: String,
+    zombie: bool,
+}
+
+/// Read a NUL-terminated name from a `proc_bsdinfo` field
+fn c_name(field: &[libc::c_char]) -> String {
+    if !field.contains(&0) {
+        let bytes: Vec<u8> = field.iter().map(|&c| c as u8).collect();
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+    unsafe { CStr::from_ptr(field.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `proc_bsdinfo` of `pid`, or `None` if it exited or can't be read
+fn read_entry(pid: u32) -> Option<ProcEntry> {
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    let written = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            &mut info as *mut _ as *mut libc::c_void,
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+
+    // pbi_name holds up to 32 bytes of the name, pbi_comm only 16
+    let long_name = c_name(&info.pbi_name);
+    Some(ProcEntry {
+        pid,
+        ppid: info.pbi_ppid,
+        pgid: info.pbi_pgid,
+        uid: info.pbi_uid,
+        name: if long_name.is_empty() {
+            c_name(&info.pbi_comm)
+        } else {
+            long_name
+        },
+        zombie: info.pbi_status == libc::SZOMB,
+    })
+}
+
+/// Every process this user can see
+fn all_processes() -> Result<Vec<ProcEntry>> {
+    // The count can grow between the size query and the read, so leave room
+    let count = unsafe { libc::proc_listallpids(std::ptr::null_mut(), 0) };
+    if count < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to list processes");
+    }
+    let mut pids = vec![0 as libc::pid_t; count as usize + 64];
+    let size = (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int;
+    let count = unsafe { libc::proc_listallpids(pids.as_mut_ptr() as *mut libc::c_void, size) };
+    if count < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to list processes");
+    }
+    pids.truncate(count as usize);
+
+    Ok(pids
+        .into_iter()
+        .filter(|&pid| pid > 0)
+        // Processes can exit between listing and reading
+        .filter_map(|pid| read_entry(pid as u32))
+        .collect())
+}
+
+/// What [`kill_process_tree`] signals for the tree of `root`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TreeTargets {
+    /// Process groups led by a member of the tree
+    groups: Vec<u32>,
+    /// Members of the tree outside those groups, children before parents
+    processes: Vec<u32>,
+}
+
+/// The processes below `root` and the process groups they lead
+///
+/// `protected_group` is never signalled as a whole; its members in the
+/// tree are signalled one by one instead.
+fn tree_targets(root: u32, processes: &[ProcEntry], protected_group: u32) -> TreeTargets {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for process in processes {
+        children.entry(process.ppid).or_default().push(process.pid);
+    }
+
+    let mut members = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([root]);
+    while let Some(pid) = queue.pop_front() {
+        if !visited.insert(pid) {
+            continue;
+        }
+        members.push(pid);
+        if let Some(kids) = children.get(&pid) {
+            queue.extend(kids.iter().copied());
+        }
+    }
+
+    let groups: Vec<u32> = processes
+        .iter()
+        .filter(|p| visited.contains(&p.pid) && p.pgid == p.pid && p.pgid != protected_group)
+        .map(|p| p.pid)
+        .collect();
+    let group_of: HashMap<u32, u32> = processes.iter().map(|p| (p.pid, p.pgid)).collect();
+
+    // Breadth-first puts parents first; signal from the leaves up
+    let mut processes: Vec<u32> = members
+        .into_iter()
+        .filter(|pid| !group_of.get(pid).is_some_and(|pgid| groups.contains(pgid)))
+        .collect();
+    processes.reverse();
+    TreeTargets { groups, processes }
+}
+
+/// Send `signal` to every member of process group `pgid`
+fn send_group_signal(pgid: u32, signal: i32) -> std::io::Result<bool> {
+    check_signal(unsafe { libc::killpg(pgid as libc::pid_t, signal) })
+}
+
+/// Signal everything in `targets`, failing only if `root` can't be signalled
+fn signal_tree(root: u32, targets: &TreeTargets, signal: i32) -> Result<()> {
+    for &pgid in &targets.groups {
+        match send_group_signal(pgid, signal) {
+            Ok(_) => {}
+            Err(e) if pgid == root => {
+                return Err(e).context(format!("Failed to kill root process group {}", root));
+            }
+            // Continue even if some children fail
+            Err(e) => warn!("Failed to signal process group {}: {}", pgid, e),
+        }
+    }
+    for &pid in &targets.processes {
+        match send_signal(pid, signal) {
+            Ok(_) => {}
+            Err(e) if pid == root => {
+                return Err(e).context(format!("Failed to kill root process {}", root));
+            }
+            Err(e) => warn!("Failed to signal process {}: {}", pid, e),
+        }
+    }
+    Ok(())
+}
+
+/// PIDs of the tree that are still running
+fn running_members(targets: &TreeTargets, processes: &[ProcEntry]) -> Vec<u32> {
+    processes
+        .iter()
+        .filter(|p| !p.zombie)
+        .filter(|p| targets.groups.contains(&p.pgid) || targets.processes.contains(&p.pid))
+        .map(|p| p.pid)
+        .collect()
+}
+
+/// Kill a process tree (process and all its descendants) by PID
+///
+/// Sends SIGTERM to the process groups the tree leads and to its other
+/// members, waits up to [`TERMINATE_GRACE`] for them to exit and then
+/// sends SIGKILL to whatever is left. The calling process and its own
+/// process group are never signalled, even if they are part of the tree.
+///
+/// # Returns
+/// * `Ok(true)` - Process tree was terminated
+/// * `Ok(false)` - Process was not found or already terminated
+/// * `Err(anyhow::Error)` - The root process couldn't be signalled, e.g.
+///   because it belongs to another user
+pub async fn kill_process_tree(pid: u32) -> Result<bool> {
+    info!("Attempting to kill process tree starting from PID {}", pid);
+    if pid == 0 {
+        // kill(0, ...) would signal our own process group
+        anyhow::bail!("Refusing to kill PID 0");
+    }
+
+    let processes = all_processes()?;
+    if !processes.iter().any(|p| p.pid == pid && !p.zombie) {
+        debug!("Process {} was already terminated or not found", pid);
+        return Ok(false);
+    }
+
+    let own_pid = std::process::id();
+    let own_group = unsafe { libc::getpgrp() } as u32;
+    let mut targets = tree_targets(pid, &processes, own_group);
+    targets.processes.retain(|&p| p != own_pid);
+    debug!(
+        "Terminating {} process groups and {} processes",
+        targets.groups.len(),
+        targets.processes.len()
+    );
+
+    signal_tree(pid, &targets, libc::SIGTERM)?;
+
+    let deadline = Instant::now() + TERMINATE_GRACE;
+    loop {
+        let remaining = running_members(&targets, &all_processes()?);
+        if remaining.is_empty() {
+            info!("Process tree of {} exited after SIGTERM", pid);
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                "{} processes of tree {} ignored SIGTERM, sending SIGKILL",
+                remaining.len(),
+                pid
+            );
+            break;
+        }
+        tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+    }
+
+    signal_tree(pid, &targets, libc::SIGKILL)?;
+    Ok(true)
+}
+
+/// Full path of the executable `pid` runs
+fn exe_path(pid: u32) -> Option<String> {
+    let mut buffer = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    let len = unsafe {
+        libc::proc_pidpath(
+            pid as libc::c_int,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len() as u32,
+        )
+    };
+    if len <= 0 {
+        return None;
+    }
+    buffer.truncate(len as usize);
+    Some(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// File name of the executable `pid` runs
+fn exe_name(pid: u32) -> Option<String> {
+    let path = exe_path(pid)?;
+    Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Check whether a process is still running (zombies count as exited)
+pub async fn is_process_running(pid: u32) -> Result<bool> {
+    Ok(read_entry(pid).is_some_and(|entry| !entry.zombie))
+}
+
+/// List all processes with a specific name
+///
+/// A process matches when `name` is its executable's file name or its
+/// (possibly truncated) kernel name. Unlike on Windows the comparison is
+/// case-sensitive.
+pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
+    debug!("Searching for processes with name: {}", name);
+    let pids: Vec<u32> = all_processes()?
+        .into_iter()
+        .filter(|process| !process.zombie)
+        .filter(|process| process.name == name || exe_name(process.pid).as_deref() == Some(name))
+        .map(|process| process.pid)
+        .collect();
+    debug!("Found {} processes named {}", pids.len(), name);
+    Ok(pids)
+}
+
+/// Check if a process runs as root
+pub async fn is_process_elevated(pid: u32) -> Result<bool> {
+    let entry = read_entry(pid).with_context(|| format!("Process {} not found", pid))?;
+    Ok(entry.uid == 0)
+}
+
+/// Get information about the given processes
+///
+/// Returns one entry per PID, in order. Processes that can't be read are
+/// named `PID-<pid>` and have no parent.
+pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+    Ok(pids
+        .iter()
+        .map(|&pid| {
+            let entry = read_entry(pid);
+            ProcessInfo {
+                pid,
+                name: exe_name(pid)
+                    .or_else(|| entry.as_ref().map(|entry| entry.name.clone()))
+                    .unwrap_or_else(|| format!("PID-{}", pid)),
+                parent_pid: entry.as_ref().map(|e| e.ppid).filter(|&ppid| ppid > 0),
+                is_elevated: entry.is_some_and(|e| e.uid == 0),
+            }
+        })
+        .collect())
+}
+
+/// Check if the current process runs as root
+pub fn is_current_process_elevated() -> Result<bool> {
+    Ok(unsafe { libc::geteuid() } == 0)
+}
+
+/// [`ProcessManager`](super::process_manager::ProcessManager) backed by libproc
+pub struct LibprocProcessManager;
+
+#[async_trait::async_trait]
+impl super::process_manager::ProcessManager for LibprocProcessManager {
+    fn backend(&self) -> &'static str {
+        "libproc"
+    }
+
+    async fn kill_tree(&self, pid: u32) -> Result<bool> {
+        kill_process_tree(pid).await
+    }
+
+    async fn is_running(&self, pid: u32) -> Result<bool> {
+        is_process_running(pid).await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+        list_processes_by_name(name).await
+    }
+
+    async fn is_elevated(&self, pid: u32) -> Result<bool> {
+        is_process_elevated(pid).await
+    }
+
+    async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+        get_process_info(pids).await
+    }
+
+    fn is_current_elevated(&self) -> Result<bool> {
+        is_current_process_elevated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pid: u32, ppid: u32, pgid: u32) -> ProcEntry {
+        ProcEntry {
+            pid,
+            ppid,
+            pgid,
+            uid: 501,
+            name: "sh".to_string(),
+            zombie: false,
+        }
+    }
+
+    #[test]
+    fn test_tree_targets() {
+        let processes = vec![
+            entry(1, 0, 1),
+            entry(10, 1, 10),
+            entry(11, 10, 10),
+            // Started its own group
+            entry(12, 11, 12),
+            entry(13, 12, 12),
+            entry(20, 1, 20),
+        ];
+        let targets = tree_targets(10, &processes, 0);
+        assert_eq!(targets.groups, vec![10, 12]);
+        assert!(targets.processes.is_empty());
+
+        // Not a group leader: signalled one by one, children first
+        let targets = tree_targets(11, &processes, 0);
+        assert_eq!(targets.groups, vec![12]);
+        assert_eq!(targets.processes, vec![11]);
+
+        // The caller's own group is split up instead
+        let targets = tree_targets(10, &processes, 10);
+        assert_eq!(targets.groups, vec![12]);
+        assert_eq!(targets.processes, vec![11, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_kill_process_tree() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 60 & wait"])
+            .spawn()
+            .unwrap();
+        let root = child.id();
+
+        // Wait for the shell to start its sleep
+        let mut grandchild = None;
+        for _ in 0..100 {
+            grandchild = all_processes()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.ppid == root)
+                .map(|p| p.pid);
+            if grandchild.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let grandchild = grandchild.expect("sleep did not start");
+
+        assert!(kill_process_tree(root).await.unwrap());
+        child.wait().unwrap();
+        assert!(!read_entry(grandchild).is_some_and(|e| !e.zombie));
+    }
+
+    #[tokio::test]
+    async fn test_process_lookup() {
+        let own_pid = std::process::id();
+        let own = read_entry(own_pid).unwrap();
+        let pids = list_processes_by_name(&own.name).await.unwrap();
+        assert!(pids.contains(&own_pid));
+
+        let info = get_process_info(&[own_pid]).await.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].pid, own_pid);
+        assert!(!info[0].name.is_empty());
+        assert_eq!(info[0].parent_pid, Some(own.ppid));
+        assert_eq!(info[0].is_elevated, is_current_process_elevated().unwrap());
+    }
+}
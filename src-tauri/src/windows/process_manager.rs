@@ -0,0 +1,93 @@
+//! One interface over the per-platform process backends
+//!
+//! The `process` module is a different file on every platform (taskkill and
+//! WMI on Windows, procfs on Linux, libproc on macOS, `ps` on other Unix
+//! systems), all offering the same free functions. [`ProcessManager`] puts
+//! them behind a trait so commands and the process registry hold a single
+//! handle from [`process_manager`]; a new capability is a trait method plus
+//! one implementation per backend.
+
+use super::process::ProcessInfo;
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Process operations every platform backend provides
+#[async_trait]
+pub trait ProcessManager: Send + Sync {
+    /// Short name of the backend, for logs and diagnostics
+    fn backend(&self) -> &'static str;
+
+    /// Terminate `pid` and all its descendants
+    ///
+    /// Returns `Ok(false)` when the process had already exited.
+    async fn kill_tree(&self, pid: u32) -> Result<bool>;
+
+    /// Whether `pid` is still running (zombies count as exited)
+    async fn is_running(&self, pid: u32) -> Result<bool>;
+
+    /// PIDs of running processes named `name`
+    async fn find_by_name(&self, name: &str) -> Result<Vec<u32>>;
+
+    /// Whether `pid` runs with administrator or root rights
+    async fn is_elevated(&self, pid: u32) -> Result<bool>;
+
+    /// Metadata of the given processes, one entry per PID in order
+    async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>>;
+
+    /// Whether Opcode itself runs with administrator or root rights
+    fn is_current_elevated(&self) -> Result<bool>;
+}
+
+/// The process backend for the platform Opcode was built for
+pub fn process_manager() -> &'static dyn ProcessManager {
+    #[cfg(target_os = "windows")]
+    return &super::process::WindowsProcessManager;
+
+    #[cfg(target_os = "linux")]
+    return &super::process::ProcfsProcessManager;
+
+    #[cfg(target_os = "macos")]
+    return &super::process::LibprocProcessManager;
+
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+    return &super::process::PsProcessManager;
+
+    #[cfg(not(any(target_os = "windows", unix)))]
+    return &super::process::UnsupportedProcessManager;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_manager_current_process() {
+        let manager = process_manager();
+        let own_pid = std::process::id();
+
+        let info = manager.info(&[own_pid]).await.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].pid, own_pid);
+        assert!(manager.is_running(own_pid).await.unwrap());
+        assert_eq!(
+            manager.is_elevated(own_pid).await.unwrap(),
+            manager.is_current_elevated().unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_manager_kill_tree() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let manager = process_manager();
+
+        assert!(manager.is_running(child.id()).await.unwrap());
+        assert!(manager.kill_tree(child.id()).await.unwrap());
+        child.wait().unwrap();
+        assert!(!manager.is_running(child.id()).await.unwrap());
+    }
+}
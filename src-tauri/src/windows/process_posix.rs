@@ -0,0 +1,209 @@
+//! Process tree handling shared by the Unix process backends
+//!
+//! The Linux, macOS and `ps` backends read the process table differently
+//! but signal processes the same way. The Linux and `ps` backends find a
+//! tree by walking parent links and the root's process group, then give it
+//! SIGTERM, a grace period and SIGKILL for whatever is left; macOS signals
+//! whole process groups instead and only shares the signalling helpers.
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// How long processes get to exit after SIGTERM before they get SIGKILL
+pub const TERMINATE_GRACE: Duration = Duration::from_secs(3);
+
+/// Process metadata, matching the Windows `ProcessInfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Process identifier (PID)
+    pub pid: u32,
+
+    /// Executable name, e.g. "node" or "claude"
+    pub name: String,
+
+    /// Parent process identifier, `None` for init (launchd on macOS) and
+    /// kernel threads
+    pub parent_pid: Option<u32>,
+
+    /// Whether the process runs as root (on Linux, also with `CAP_SYS_ADMIN`)
+    pub is_elevated: bool,
+}
+
+/// An entry of a backend's process table
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub trait TableEntry {
+    fn pid(&self) -> u32;
+    fn ppid(&self) -> u32;
+    fn pgid(&self) -> u32;
+}
+
+/// `root` and everything below it, children before their parents
+///
+/// Includes the members of `root`'s process group when it leads one, so
+/// grandchildren reparented to init are still found.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub fn tree_pids<P: TableEntry>(root: u32, processes: &[P]) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for process in processes {
+        children
+            .entry(process.ppid())
+            .or_default()
+            .push(process.pid());
+    }
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([root]);
+    if processes
+        .iter()
+        .any(|p| p.pid() == root && p.pgid() == root)
+    {
+        queue.extend(
+            processes
+                .iter()
+                .filter(|p| p.pgid() == root && p.pid() != root)
+                .map(|p| p.pid()),
+        );
+    }
+
+    while let Some(pid) = queue.pop_front() {
+        if !visited.insert(pid) {
+            continue;
+        }
+        order.push(pid);
+        if let Some(kids) = children.get(&pid) {
+            queue.extend(kids.iter().copied());
+        }
+    }
+
+    // Breadth-first puts parents first; signal from the leaves up so
+    // parents don't respawn children that were already stopped
+    order.retain(|&pid| pid != root);
+    order.reverse();
+    order.push(root);
+    order
+}
+
+/// Send `signal` to `pid`; `Ok(false)` if the process is already gone
+pub fn send_signal(pid: u32, signal: i32) -> std::io::Result<bool> {
+    check_signal(unsafe { libc::kill(pid as libc::pid_t, signal) })
+}
+
+/// Turn the result of `kill` or `killpg` into whether the target existed
+pub fn check_signal(result: libc::c_int) -> std::io::Result<bool> {
+    if result == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    if error.raw_os_error() == Some(libc::ESRCH) {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+/// Send `signal`, named `name` in messages, to every process in `pids`
+///
+/// Fails only if `root` can't be signalled; the others are logged and
+/// skipped.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn signal_all(root: u32, pids: &[u32], signal: i32, name: &str) -> Result<()> {
+    for &target in pids {
+        match send_signal(target, signal) {
+            Ok(_) => {}
+            Err(e) if target == root => {
+                return Err(e).context(format!("Failed to send {} to root process {}", name, root));
+            }
+            Err(e) => warn!("Failed to send {} to process {}: {}", name, target, e),
+        }
+    }
+    Ok(())
+}
+
+/// SIGTERM `pids`, wait up to [`TERMINATE_GRACE`] for them to exit and
+/// SIGKILL whatever is left
+///
+/// `pids` is the tree of `root` as [`tree_pids`] orders it, without the
+/// calling process. `still_running` gets the PIDs not yet seen exiting and
+/// returns those that still run; it is called every `poll_interval`.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub async fn terminate_tree<F, Fut>(
+    root: u32,
+    pids: Vec<u32>,
+    poll_interval: Duration,
+    mut still_running: F,
+) -> Result<bool>
+where
+    F: FnMut(Vec<u32>) -> Fut,
+    Fut: Future<Output = Result<Vec<u32>>>,
+{
+    signal_all(root, &pids, libc::SIGTERM, "SIGTERM")?;
+
+    let deadline = Instant::now() + TERMINATE_GRACE;
+    let mut remaining = pids;
+    loop {
+        remaining = still_running(remaining).await?;
+        if remaining.is_empty() {
+            info!("Process tree of {} exited after SIGTERM", root);
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+
+    warn!(
+        "{} processes of tree {} ignored SIGTERM, sending SIGKILL",
+        remaining.len(),
+        root
+    );
+    signal_all(root, &remaining, libc::SIGKILL, "SIGKILL")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Entry(u32, u32, u32);
+
+    impl TableEntry for Entry {
+        fn pid(&self) -> u32 {
+            self.0
+        }
+        fn ppid(&self) -> u32 {
+            self.1
+        }
+        fn pgid(&self) -> u32 {
+            self.2
+        }
+    }
+
+    #[test]
+    fn test_tree_pids() {
+        let processes = vec![
+            Entry(1, 0, 1),
+            Entry(10, 1, 10),
+            Entry(11, 10, 10),
+            Entry(12, 11, 10),
+            // Reparented to init, but still in the session's group
+            Entry(13, 1, 10),
+            Entry(20, 1, 20),
+        ];
+        let pids = tree_pids(10, &processes);
+        assert_eq!(pids.last(), Some(&10));
+        assert_eq!(pids.len(), 4);
+        assert!(pids.contains(&13));
+        assert!(!pids.contains(&20));
+        let position = |pid| pids.iter().position(|&p| p == pid).unwrap();
+        assert!(position(12) < position(11));
+
+        // Not a group leader: only the descendants
+        let pids = tree_pids(11, &processes);
+        assert_eq!(pids, vec![12, 11]);
+    }
+}
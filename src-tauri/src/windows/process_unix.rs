@@ -0,0 +1,253 @@
+//! Process management for other Unix systems, read from `ps`
+//!
+//! Offers the same functions as the Windows `process` module on systems
+//! without procfs or libproc, such as FreeBSD, where headless agent runners
+//! still need to stop a session's whole tree. The process table comes from
+//! POSIX `ps -A -o`, and processes are signalled directly.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+pub use super::process_posix::ProcessInfo;
+use super::process_posix::{terminate_tree, tree_pids, TableEntry};
+use crate::process::command::{run_with_timeout, DEFAULT_COMMAND_TIMEOUT};
+
+/// How often [`kill_process_tree`] checks whether the tree has exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// One line of `ps` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PsEntry {
+    pid: u32,
+    ppid: u32,
+    pgid: u32,
+    uid: u32,
+    state: String,
+    name: String,
+}
+
+impl PsEntry {
+    /// Zombies have exited and only wait to be reaped
+    fn is_running(&self) -> bool {
+        !self.state.starts_with('Z')
+    }
+}
+
+impl TableEntry for PsEntry {
+    fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn ppid(&self) -> u32 {
+        self.ppid
+    }
+
+    fn pgid(&self) -> u32 {
+        self.pgid
+    }
+}
+
+/// Parse a `pid ppid pgid uid state comm` line; the name may contain spaces
+fn parse_ps_line(line: &str) -> Option<PsEntry> {
+    let mut fields = line.split_whitespace();
+    let pid = fields.next()?.parse().ok()?;
+    let ppid = fields.next()?.parse().ok()?;
+    let pgid = fields.next()?.parse().ok()?;
+    let uid = fields.next()?.parse().ok()?;
+    let state = fields.next()?.to_string();
+    let name = fields.collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return None;
+    }
+    Some(PsEntry {
+        pid,
+        ppid,
+        pgid,
+        uid,
+        state,
+        name,
+    })
+}
+
+/// Every process `ps` lists
+async fn all_processes() -> Result<Vec<PsEntry>> {
+    let output = run_with_timeout(
+        tokio::process::Command::new("ps").args(["-A", "-o", "pid=,ppid=,pgid=,uid=,state=,comm="]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .await
+    .context("Failed to list processes with ps")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ps failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_ps_line)
+        .collect())
+}
+
+/// Kill a process tree (process and all its descendants) by PID
+///
+/// Sends SIGTERM to the whole tree, children first, waits up to
+/// [`TERMINATE_GRACE`](super::process_posix::TERMINATE_GRACE) for it to
+/// exit and then sends SIGKILL to whatever is left. The calling process is
+/// never signalled.
+pub async fn kill_process_tree(pid: u32) -> Result<bool> {
+    info!("Attempting to kill process tree starting from PID {}", pid);
+    if pid == 0 {
+        anyhow::bail!("Refusing to kill PID 0");
+    }
+
+    let processes = all_processes().await?;
+    if !processes.iter().any(|p| p.pid == pid && p.is_running()) {
+        debug!("Process {} was already terminated or not found", pid);
+        return Ok(false);
+    }
+
+    let own_pid = std::process::id();
+    let pids: Vec<u32> = tree_pids(pid, &processes)
+        .into_iter()
+        .filter(|&p| p != own_pid)
+        .collect();
+
+    terminate_tree(pid, pids, EXIT_POLL_INTERVAL, |remaining| async move {
+        let running: HashSet<u32> = all_processes()
+            .await?
+            .into_iter()
+            .filter(|p| p.is_running())
+            .map(|p| p.pid)
+            .collect();
+        Ok(remaining
+            .into_iter()
+            .filter(|p| running.contains(p))
+            .collect())
+    })
+    .await
+}
+
+/// Check whether a process is still running (zombies count as exited)
+pub async fn is_process_running(pid: u32) -> Result<bool> {
+    Ok(all_processes()
+        .await?
+        .iter()
+        .any(|process| process.pid == pid && process.is_running()))
+}
+
+/// List all processes with a specific name
+///
+/// Compares against the (possibly truncated) command name `ps` reports.
+/// Unlike on Windows the comparison is case-sensitive.
+pub async fn list_processes_by_name(name: &str) -> Result<Vec<u32>> {
+    debug!("Searching for processes with name: {}", name);
+    Ok(all_processes()
+        .await?
+        .into_iter()
+        .filter(|process| process.is_running() && process.name == name)
+        .map(|process| process.pid)
+        .collect())
+}
+
+/// Check if a process runs as root
+pub async fn is_process_elevated(pid: u32) -> Result<bool> {
+    let process = all_processes()
+        .await?
+        .into_iter()
+        .find(|p| p.pid == pid)
+        .with_context(|| format!("Process {} not found", pid))?;
+    Ok(process.uid == 0)
+}
+
+/// Get information about the given processes
+///
+/// Returns one entry per PID, in order. Processes that can't be found are
+/// named `PID-<pid>` and have no parent.
+pub async fn get_process_info(pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+    let processes: HashMap<u32, PsEntry> = all_processes()
+        .await?
+        .into_iter()
+        .map(|p| (p.pid, p))
+        .collect();
+    Ok(pids
+        .iter()
+        .map(|&pid| match processes.get(&pid) {
+            Some(process) => ProcessInfo {
+                pid,
+                name: process.name.clone(),
+                parent_pid: Some(process.ppid).filter(|&ppid| ppid > 0),
+                is_elevated: process.uid == 0,
+            },
+            None => ProcessInfo {
+                pid,
+                name: format!("PID-{}", pid),
+                parent_pid: None,
+                is_elevated: false,
+            },
+        })
+        .collect())
+}
+
+/// Check if the current process runs as root
+pub fn is_current_process_elevated() -> Result<bool> {
+    Ok(unsafe { libc::geteuid() } == 0)
+}
+
+/// [`ProcessManager`](super::process_manager::ProcessManager) backed by `ps`
+pub struct PsProcessManager;
+
+#[async_trait::async_trait]
+impl super::process_manager::ProcessManager for PsProcessManager {
+    fn backend(&self) -> &'static str {
+        "ps"
+    }
+
+    async fn kill_tree(&self, pid: u32) -> Result<bool> {
+        kill_process_tree(pid).await
+    }
+
+    async fn is_running(&self, pid: u32) -> Result<bool> {
+        is_process_running(pid).await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Vec<u32>> {
+        list_processes_by_name(name).await
+    }
+
+    async fn is_elevated(&self, pid: u32) -> Result<bool> {
+        is_process_elevated(pid).await
+    }
+
+    async fn info(&self, pids: &[u32]) -> Result<Vec<ProcessInfo>> {
+        get_process_info(pids).await
+    }
+
+    fn is_current_elevated(&self) -> Result<bool> {
+        is_current_process_elevated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ps_line() {
+        assert_eq!(
+            parse_ps_line("  812     1   812     0 Ss   Google Chrome"),
+            Some(PsEntry {
+                pid: 812,
+                ppid: 1,
+                pgid: 812,
+                uid: 0,
+                state: "Ss".to_string(),
+                name: "Google Chrome".to_string(),
+            })
+        );
+        assert_eq!(parse_ps_line("  812     1   812     0 Ss"), None);
+        assert_eq!(parse_ps_line("PID PPID"), None);
+    }
+}
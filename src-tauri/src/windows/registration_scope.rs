@@ -0,0 +1,42 @@
+//! Where file associations and URL protocols are registered
+//!
+//! Shared by the Windows registry code and the `.desktop` entry and Launch
+//! Services backends, which only ever register for the current user.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Where file associations and URL protocols are registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationScope {
+    /// For all users (needs elevation); `HKEY_LOCAL_MACHINE\Software\Classes`
+    /// on Windows
+    Machine,
+    /// For the current user only; `HKEY_CURRENT_USER\Software\Classes` on
+    /// Windows
+    User,
+}
+
+impl fmt::Display for RegistrationScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistrationScope::Machine => write!(f, "machine"),
+            RegistrationScope::User => write!(f, "user"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_scope_names() {
+        assert_eq!(RegistrationScope::Machine.to_string(), "machine");
+        assert_eq!(
+            serde_json::to_value(RegistrationScope::User).unwrap(),
+            "user"
+        );
+    }
+}
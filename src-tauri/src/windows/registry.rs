@@ -6,9 +6,17 @@
 //! # Features
 //! - **File Associations**: Register custom file types with Windows Shell
 //! - **URL Protocols**: Handle custom URI schemes (e.g., `myapp://action`)
-//! - **Auto-Start Management**: Configure Windows startup behavior
+//! - **Auto-Start Management**: Configure Windows startup behavior, through the
+//!   `Run` key or, for MSIX-packaged installs, the package's startup task
 //! - **Registry Safety**: Atomic operations with automatic rollback on failure
 //! - **Permission Aware**: Handles UAC and privilege requirements gracefully
+//! - **Portable Mode**: All writes are skipped when running from a portable install
+//! - **Dry-Run Planning**: `plan_*` functions list the exact keys/values a
+//!   registration would change, and which need elevation, without writing
+//! - **Locked Keys**: Keys whose ACLs deny access fail with a typed
+//!   [`RegistryKeyLockedError`] naming the owner; ownership is only taken with consent
+//! - **Policy Aware**: Associations enforced by Group Policy fail with an
+//!   [`AssociationManagedError`] ("managed by your organization")
 //!
 //! # Security Considerations
 //! Registry operations typically require administrator privileges for system-wide
@@ -53,20 +61,34 @@
 //! }
 //! ```
 
+pub use super::registration_scope::RegistrationScope;
+use super::to_wide_string;
 use anyhow::{Context, Result};
+use crate::utils::cmdline::quote_windows_arg_always;
+use super::startup_task::{get_startup_task_state, is_packaged, set_startup_task_enabled, STARTUP_TASK_ID};
 use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use winapi::um::winreg::{RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW};
+use winapi::um::winreg::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegOpenKeyExW, RegQueryValueExW, RegSetValueExW,
+};
 use winapi::shared::minwindef::{DWORD, HKEY};
-use winapi::shared::winerror::ERROR_SUCCESS;
-use winapi::um::winnt::{KEY_ALL_ACCESS, REG_SZ};
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
+use winapi::shared::winerror::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+use winapi::um::winnt::{KEY_ALL_ACCESS, KEY_READ, REG_EXPAND_SZ, REG_SZ};
 use std::ptr;
 
-/// Convert a Rust string to a wide string for Windows API
-fn to_wide_string(s: &str) -> Vec<u16> {
-    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+/// Registry key holding the per-user environment variables
+const ENVIRONMENT_KEY: &str = "Environment";
+
+/// Registry key holding per-user auto-start entries
+const RUN_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+
+/// Per-user App Paths key used by the Run dialog and ShellExecute
+const APP_PATHS_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\App Paths";
+
+/// Wrap a Win32 status code so callers can inspect it via `io::Error::kind`
+fn os_error(code: i32) -> std::io::Error {
+    std::io::Error::from_raw_os_error(code)
 }
 
 /// Create or open a registry key
@@ -88,14 +110,14 @@ unsafe fn create_registry_key(root: HKEY, path: &str) -> Result<HKEY> {
     );
 
     if result != ERROR_SUCCESS as i32 {
-        return Err(anyhow::anyhow!("Failed to create registry key {}: error code {}", path, result));
+        return Err(os_error(result)).with_context(|| format!("Failed to create registry key {}", path));
     }
 
     Ok(key)
 }
 
-/// Set a string value in the registry
-unsafe fn set_registry_value(key: HKEY, name: &str, value: &str) -> Result<()> {
+/// Set a string value of the given type (`REG_SZ` or `REG_EXPAND_SZ`) in the registry
+unsafe fn set_registry_value_with_type(key: HKEY, name: &str, value: &str, value_type: DWORD) -> Result<()> {
     let wide_name = to_wide_string(name);
     let wide_value = to_wide_string(value);
     let value_bytes = (wide_value.len() * 2) as DWORD;
@@ -104,18 +126,942 @@ unsafe fn set_registry_value(key: HKEY, name: &str, value: &str) -> Result<()> {
         key,
         wide_name.as_ptr(),
         0,
-        REG_SZ,
+        value_type,
         wide_value.as_ptr() as *const u8,
         value_bytes,
     );
 
     if result != ERROR_SUCCESS as i32 {
-        return Err(anyhow::anyhow!("Failed to set registry value {}: error code {}", name, result));
+        return Err(os_error(result)).with_context(|| format!("Failed to set registry value {}", name));
     }
 
     Ok(())
 }
 
+/// Read a string value from the registry
+///
+/// Returns `Ok(None)` if the key or the value does not exist.
+unsafe fn read_registry_string(root: HKEY, path: &str, name: &str) -> Result<Option<String>> {
+    let wide_path = to_wide_string(path);
+    let wide_name = to_wide_string(name);
+    let mut key: HKEY = ptr::null_mut();
+
+    let result = RegOpenKeyExW(root, wide_path.as_ptr(), 0, KEY_READ, &mut key);
+    if result == ERROR_FILE_NOT_FOUND as i32 {
+        return Ok(None);
+    }
+    if result != ERROR_SUCCESS as i32 {
+        return Err(anyhow::anyhow!("Failed to open registry key {}: error code {}", path, result));
+    }
+
+    // First query the size of the value, then read it
+    let mut value_type: DWORD = 0;
+    let mut size: DWORD = 0;
+    let result = RegQueryValueExW(
+        key,
+        wide_name.as_ptr(),
+        ptr::null_mut(),
+        &mut value_type,
+        ptr::null_mut(),
+        &mut size,
+    );
+
+    if result == ERROR_FILE_NOT_FOUND as i32 {
+        RegCloseKey(key);
+        return Ok(None);
+    }
+    if result != ERROR_SUCCESS as i32 {
+        RegCloseKey(key);
+        return Err(anyhow::anyhow!("Failed to query registry value {}: error code {}", name, result));
+    }
+    if value_type != REG_SZ && value_type != REG_EXPAND_SZ {
+        RegCloseKey(key);
+        return Err(anyhow::anyhow!("Registry value {} is not a string (type {})", name, value_type));
+    }
+
+    let mut buffer: Vec<u16> = vec![0; (size as usize / 2) + 1];
+    let mut size = (buffer.len() * 2) as DWORD;
+    let result = RegQueryValueExW(
+        key,
+        wide_name.as_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        buffer.as_mut_ptr() as *mut u8,
+        &mut size,
+    );
+
+    RegCloseKey(key);
+
+    if result != ERROR_SUCCESS as i32 {
+        return Err(anyhow::anyhow!("Failed to read registry value {}: error code {}", name, result));
+    }
+
+    // Trim at the first NUL terminator
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Ok(Some(String::from_utf16_lossy(&buffer[..len])))
+}
+
+/// Read a `REG_DWORD` value from the registry
+///
+/// Returns `Ok(None)` if the key or the value does not exist.
+pub(crate) unsafe fn read_registry_dword(root: HKEY, path: &str, name: &str) -> Result<Option<u32>> {
+    use winapi::um::winnt::REG_DWORD;
+
+    let wide_path = to_wide_string(path);
+    let wide_name = to_wide_string(name);
+    let mut key: HKEY = ptr::null_mut();
+
+    let result = RegOpenKeyExW(root, wide_path.as_ptr(), 0, KEY_READ, &mut key);
+    if result == ERROR_FILE_NOT_FOUND as i32 {
+        return Ok(None);
+    }
+    if result != ERROR_SUCCESS as i32 {
+        return Err(os_error(result)).with_context(|| format!("Failed to open registry key {}", path));
+    }
+
+    let mut value_type: DWORD = 0;
+    let mut value: DWORD = 0;
+    let mut size = std::mem::size_of::<DWORD>() as DWORD;
+    let result = RegQueryValueExW(
+        key,
+        wide_name.as_ptr(),
+        ptr::null_mut(),
+        &mut value_type,
+        &mut value as *mut DWORD as *mut u8,
+        &mut size,
+    );
+    RegCloseKey(key);
+
+    if result == ERROR_FILE_NOT_FOUND as i32 {
+        return Ok(None);
+    }
+    if result != ERROR_SUCCESS as i32 {
+        return Err(os_error(result)).with_context(|| format!("Failed to query registry value {}", name));
+    }
+    if value_type != REG_DWORD {
+        return Err(anyhow::anyhow!("Registry value {} is not a DWORD (type {})", name, value_type));
+    }
+
+    Ok(Some(value))
+}
+
+/// Portable installs must not leave traces in the registry, so every write
+/// becomes a logged no-op when [`crate::portable::is_portable`] is set
+fn skip_in_portable_mode(operation: &str) -> bool {
+    if crate::portable::is_portable() {
+        info!("Portable mode: skipping {}", operation);
+        return true;
+    }
+    false
+}
+
+/// Registry hive targeted by a planned change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistryRoot {
+    #[serde(rename = "HKEY_CLASSES_ROOT")]
+    ClassesRoot,
+    #[serde(rename = "HKEY_CURRENT_USER")]
+    CurrentUser,
+    #[serde(rename = "HKEY_LOCAL_MACHINE")]
+    LocalMachine,
+}
+
+impl RegistryRoot {
+    fn hkey(self) -> HKEY {
+        use winapi::um::winreg::{HKEY_CLASSES_ROOT, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+        match self {
+            RegistryRoot::ClassesRoot => HKEY_CLASSES_ROOT,
+            RegistryRoot::CurrentUser => HKEY_CURRENT_USER,
+            RegistryRoot::LocalMachine => HKEY_LOCAL_MACHINE,
+        }
+    }
+
+    /// Writes under `HKEY_CLASSES_ROOT` land in `HKLM\Software\Classes` unless
+    /// the key already exists per-user, so they need an elevated process
+    fn requires_elevation(self) -> bool {
+        matches!(self, RegistryRoot::ClassesRoot | RegistryRoot::LocalMachine)
+    }
+}
+
+impl std::fmt::Display for RegistryRoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryRoot::ClassesRoot => write!(f, "HKEY_CLASSES_ROOT"),
+            RegistryRoot::CurrentUser => write!(f, "HKEY_CURRENT_USER"),
+            RegistryRoot::LocalMachine => write!(f, "HKEY_LOCAL_MACHINE"),
+        }
+    }
+}
+
+/// What a planned change does to its key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegistryAction {
+    /// Create the key if needed and set a string value (`REG_EXPAND_SZ` when `expandable`)
+    SetValue { name: String, data: String, expandable: bool },
+    /// Delete a value; missing values are ignored
+    DeleteValue { name: String },
+}
+
+/// A single registry modification, as returned by the `plan_*` functions
+///
+/// Plans are shown to the user before consenting and can then be executed
+/// unchanged with [`apply_registry_plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryChange {
+    pub root: RegistryRoot,
+    pub key: String,
+    pub action: RegistryAction,
+    pub requires_elevation: bool,
+}
+
+impl RegistryChange {
+    fn new(root: RegistryRoot, key: &str, action: RegistryAction) -> Self {
+        Self {
+            root,
+            key: key.to_string(),
+            action,
+            requires_elevation: root.requires_elevation(),
+        }
+    }
+
+    fn set_value(root: RegistryRoot, key: &str, name: &str, data: &str) -> Self {
+        Self::new(
+            root,
+            key,
+            RegistryAction::SetValue {
+                name: name.to_string(),
+                data: data.to_string(),
+                expandable: false,
+            },
+        )
+    }
+
+    fn set_expandable_value(root: RegistryRoot, key: &str, name: &str, data: &str) -> Self {
+        Self::new(
+            root,
+            key,
+            RegistryAction::SetValue {
+                name: name.to_string(),
+                data: data.to_string(),
+                expandable: true,
+            },
+        )
+    }
+
+    fn delete_value(root: RegistryRoot, key: &str, name: &str) -> Self {
+        Self::new(root, key, RegistryAction::DeleteValue { name: name.to_string() })
+    }
+}
+
+impl std::fmt::Display for RegistryChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value_label = |name: &str| if name.is_empty() { "(Default)".to_string() } else { name.to_string() };
+
+        match &self.action {
+            RegistryAction::SetValue { name, data, .. } => {
+                write!(f, r#"set {}\{} [{}] = "{}""#, self.root, self.key, value_label(name), data)
+            }
+            RegistryAction::DeleteValue { name } => {
+                write!(f, r"delete {}\{} [{}]", self.root, self.key, value_label(name))
+            }
+        }
+    }
+}
+
+/// Class registrations below `HKEY_LOCAL_MACHINE` / `HKEY_CURRENT_USER`
+const CLASSES_KEY: &str = r"Software\Classes";
+
+impl RegistrationScope {
+    /// Machine-wide when running elevated, otherwise per-user
+    ///
+    /// Per-user registrations take precedence over machine-wide ones in
+    /// `HKEY_CLASSES_ROOT`, so the fallback behaves the same for this user.
+    pub fn detect() -> Self {
+        match crate::windows::permissions::is_running_as_admin() {
+            Ok(true) => RegistrationScope::Machine,
+            Ok(false) => {
+                info!("Not running elevated, registering for the current user only");
+                RegistrationScope::User
+            }
+            Err(e) => {
+                warn!("Could not determine elevation, registering for the current user only: {}", e);
+                RegistrationScope::User
+            }
+        }
+    }
+
+    fn root(self) -> RegistryRoot {
+        match self {
+            RegistrationScope::Machine => RegistryRoot::LocalMachine,
+            RegistrationScope::User => RegistryRoot::CurrentUser,
+        }
+    }
+}
+
+/// Move the `HKEY_CLASSES_ROOT` changes of a plan to the classes key of `scope`
+///
+/// `plan_*` functions describe class registrations relative to
+/// `HKEY_CLASSES_ROOT`; this pins them to an explicit hive, which also
+/// updates `requires_elevation`. Other changes are kept as they are.
+pub fn plan_for_scope(plan: Vec<RegistryChange>, scope: RegistrationScope) -> Vec<RegistryChange> {
+    plan.into_iter()
+        .map(|change| match change.root {
+            RegistryRoot::ClassesRoot => RegistryChange::new(
+                scope.root(),
+                &format!(r"{}\{}", CLASSES_KEY, change.key),
+                change.action,
+            ),
+            _ => change,
+        })
+        .collect()
+}
+
+/// Apply a plan produced by one of the `plan_*` functions
+///
+/// Changes are applied in order; the first failure stops the plan and is
+/// returned with the failing change in its context. A key whose ACL denies
+/// access fails with a [`RegistryKeyLockedError`].
+pub fn apply_registry_plan(plan: &[RegistryChange]) -> Result<()> {
+    apply_registry_plan_with_consent(plan, false)
+}
+
+/// Apply a plan, optionally taking ownership of keys that deny access
+///
+/// Keys left behind by other installers sometimes carry ACLs that block even
+/// administrators. Without consent such a change fails with a
+/// [`RegistryKeyLockedError`] naming the key's owner. Set `take_ownership`
+/// only after the user explicitly agreed to it: the locked key is then
+/// taken over with [`take_registry_key_ownership`] and the change retried once.
+pub fn apply_registry_plan_with_consent(plan: &[RegistryChange], take_ownership: bool) -> Result<()> {
+    if skip_in_portable_mode("registry plan") {
+        return Ok(());
+    }
+
+    for change in plan {
+        debug!("Applying registry change: {}", change);
+
+        let result = match unsafe { apply_registry_change(change) } {
+            Err(e) if is_access_denied(&e) => {
+                let locked = locked_key_error(change.root, &change.key);
+                if !take_ownership {
+                    return Err(anyhow::Error::new(locked));
+                }
+
+                warn!("{}; taking ownership with user consent", locked);
+                take_registry_key_ownership(locked.root, &locked.key)?;
+                unsafe { apply_registry_change(change) }
+            }
+            result => result,
+        };
+
+        result.with_context(|| format!("Failed to {}", change))?;
+    }
+
+    Ok(())
+}
+
+unsafe fn apply_registry_change(change: &RegistryChange) -> Result<()> {
+    let root = change.root.hkey();
+
+    match &change.action {
+        RegistryAction::SetValue { name, data, expandable } => {
+            let key = create_registry_key(root, &change.key)?;
+            let value_type = if *expandable { REG_EXPAND_SZ } else { REG_SZ };
+            let result = set_registry_value_with_type(key, name, data, value_type);
+            RegCloseKey(key);
+            result
+        }
+        RegistryAction::DeleteValue { name } => {
+            use winapi::um::winreg::RegDeleteValueW;
+
+            let wide_path = to_wide_string(&change.key);
+            let mut key: HKEY = ptr::null_mut();
+
+            let result = RegOpenKeyExW(root, wide_path.as_ptr(), 0, KEY_ALL_ACCESS, &mut key);
+            if result == ERROR_FILE_NOT_FOUND as i32 {
+                debug!("Registry key {} not found, nothing to delete", change.key);
+                return Ok(());
+            }
+            if result != ERROR_SUCCESS as i32 {
+                return Err(os_error(result)).with_context(|| format!("Failed to open registry key {}", change.key));
+            }
+
+            let wide_name = to_wide_string(name);
+            let result = RegDeleteValueW(key, wide_name.as_ptr());
+            RegCloseKey(key);
+
+            if result == ERROR_FILE_NOT_FOUND as i32 {
+                warn!("Registry value {} was not found under {}", name, change.key);
+            } else if result != ERROR_SUCCESS as i32 {
+                return Err(anyhow::anyhow!("Failed to delete registry value {}: error code {}", name, result));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Account owning a registry key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegistryKeyOwner {
+    /// `DOMAIN\name` of the owner, if the SID could be resolved
+    pub account: Option<String>,
+    /// Owner SID in string form (e.g. `S-1-5-32-544`)
+    pub sid: String,
+}
+
+impl std::fmt::Display for RegistryKeyOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.account {
+            Some(account) => write!(f, "{} ({})", account, self.sid),
+            None => write!(f, "{}", self.sid),
+        }
+    }
+}
+
+/// A registry key exists but its ACL denies opcode access
+///
+/// Usually the key was created by another installer. Returned (inside
+/// `anyhow::Error`, so use `downcast_ref`) by [`apply_registry_plan`] and the
+/// `register_*` functions; pass consent to [`apply_registry_plan_with_consent`]
+/// to take the key over.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegistryKeyLockedError {
+    pub root: RegistryRoot,
+    /// The existing key that denies access (may be a parent of the planned key)
+    pub key: String,
+    /// Owner of the key, if it could be read
+    pub owner: Option<RegistryKeyOwner>,
+}
+
+impl std::fmt::Display for RegistryKeyLockedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, r"Access to {}\{} is denied", self.root, self.key)?;
+        match &self.owner {
+            Some(owner) => write!(f, "; the key is owned by {}", owner)?,
+            None => write!(f, "; the key's owner could not be determined")?,
+        }
+        write!(f, " and its permissions do not allow changes")
+    }
+}
+
+impl std::error::Error for RegistryKeyLockedError {}
+
+/// Whether an error was caused by a Win32 access-denied status
+fn is_access_denied(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+    })
+}
+
+/// Build the error for an access-denied `key`, pointing at the deepest key
+/// that exists, since that is the one whose ACL blocked the write
+fn locked_key_error(root: RegistryRoot, key: &str) -> RegistryKeyLockedError {
+    let key = deepest_existing_key(root, key);
+    let owner = match inspect_registry_key_owner(root, &key) {
+        Ok(owner) => owner,
+        Err(e) => {
+            debug!("Could not read owner of {}\\{}: {:#}", root, key, e);
+            None
+        }
+    };
+
+    RegistryKeyLockedError { root, key, owner }
+}
+
+/// `key` and each of its parents, shortest first
+fn key_prefixes(key: &str) -> Vec<String> {
+    let parts: Vec<&str> = key.split('\\').filter(|p| !p.is_empty()).collect();
+    (1..=parts.len()).map(|n| parts[..n].join("\\")).collect()
+}
+
+fn deepest_existing_key(root: RegistryRoot, key: &str) -> String {
+    for prefix in key_prefixes(key).into_iter().rev() {
+        let wide_path = to_wide_string(&prefix);
+        let mut handle: HKEY = ptr::null_mut();
+
+        // Access denied still means the key exists
+        let result = unsafe { RegOpenKeyExW(root.hkey(), wide_path.as_ptr(), 0, KEY_READ, &mut handle) };
+        if result == ERROR_SUCCESS as i32 {
+            unsafe { RegCloseKey(handle) };
+        }
+        if result != ERROR_FILE_NOT_FOUND as i32 {
+            return prefix;
+        }
+    }
+
+    key.to_string()
+}
+
+/// Object name of a registry key for the `*NamedSecurityInfo` APIs
+fn registry_object_name(root: RegistryRoot, key: &str) -> String {
+    match root {
+        RegistryRoot::ClassesRoot => format!(r"CLASSES_ROOT\{}", key),
+        RegistryRoot::CurrentUser => format!(r"CURRENT_USER\{}", key),
+        RegistryRoot::LocalMachine => format!(r"MACHINE\{}", key),
+    }
+}
+
+/// Read the owner of a registry key
+///
+/// Returns `Ok(None)` if the key does not exist. Reading the owner only needs
+/// `READ_CONTROL`, which owners of locked keys rarely revoke.
+pub fn inspect_registry_key_owner(root: RegistryRoot, key: &str) -> Result<Option<RegistryKeyOwner>> {
+    use winapi::um::accctrl::SE_REGISTRY_KEY;
+    use winapi::um::aclapi::GetNamedSecurityInfoW;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID};
+
+    let wide_name = to_wide_string(&registry_object_name(root, key));
+    let mut owner: PSID = ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+    unsafe {
+        let result = GetNamedSecurityInfoW(
+            wide_name.as_ptr(),
+            SE_REGISTRY_KEY,
+            OWNER_SECURITY_INFORMATION,
+            &mut owner,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut descriptor,
+        );
+
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(None);
+        }
+        if result != ERROR_SUCCESS {
+            return Err(os_error(result as i32))
+                .with_context(|| format!(r"Failed to read security info of {}\{}", root, key));
+        }
+
+        let info = describe_sid(owner);
+        LocalFree(descriptor as _);
+        Ok(Some(info))
+    }
+}
+
+/// Resolve the owner SID of a registry key
+unsafe fn describe_sid(sid: winapi::um::winnt::PSID) -> RegistryKeyOwner {
+    let (sid, account) = super::permissions::lookup_sid(sid);
+    RegistryKeyOwner { account, sid }
+}
+
+/// Take ownership of a registry key and grant the current user full control
+///
+/// Requires an elevated process (for `SeTakeOwnershipPrivilege`). Only call
+/// this after the user explicitly consented, typically in response to a
+/// [`RegistryKeyLockedError`]; other ACL entries on the key are kept.
+pub fn take_registry_key_ownership(root: RegistryRoot, key: &str) -> Result<()> {
+    use winapi::um::accctrl::{
+        EXPLICIT_ACCESS_W, GRANT_ACCESS, SE_REGISTRY_KEY, SUB_CONTAINERS_AND_OBJECTS_INHERIT,
+        TRUSTEE_IS_SID, TRUSTEE_IS_USER,
+    };
+    use winapi::um::aclapi::{GetNamedSecurityInfoW, SetEntriesInAclW, SetNamedSecurityInfoW};
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{DACL_SECURITY_INFORMATION, OWNER_SECURITY_INFORMATION, PACL, PSECURITY_DESCRIPTOR};
+
+    if skip_in_portable_mode("taking ownership of a registry key") {
+        return Ok(());
+    }
+
+    info!(r"Taking ownership of registry key {}\{}", root, key);
+
+    unsafe {
+        let _take_ownership = super::permissions::enable_privilege(super::permissions::SE_TAKE_OWNERSHIP_NAME)
+            .context("Taking ownership of a registry key requires running opcode as administrator")?;
+
+        let token_user = super::permissions::current_user_token()?;
+        let sid = (*(token_user.as_ptr() as *const winapi::um::winnt::TOKEN_USER)).User.Sid;
+        let mut wide_name = to_wide_string(&registry_object_name(root, key));
+
+        let result = SetNamedSecurityInfoW(
+            wide_name.as_mut_ptr(),
+            SE_REGISTRY_KEY,
+            OWNER_SECURITY_INFORMATION,
+            sid,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        if result != ERROR_SUCCESS {
+            return Err(os_error(result as i32)).with_context(|| format!(r"Failed to take ownership of {}\{}", root, key));
+        }
+
+        // As the owner we can now read and extend the DACL
+        let mut dacl: PACL = ptr::null_mut();
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        let result = GetNamedSecurityInfoW(
+            wide_name.as_ptr(),
+            SE_REGISTRY_KEY,
+            DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut dacl,
+            ptr::null_mut(),
+            &mut descriptor,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(os_error(result as i32)).with_context(|| format!(r"Failed to read permissions of {}\{}", root, key));
+        }
+
+        let mut access: EXPLICIT_ACCESS_W = std::mem::zeroed();
+        access.grfAccessPermissions = KEY_ALL_ACCESS;
+        access.grfAccessMode = GRANT_ACCESS;
+        access.grfInheritance = SUB_CONTAINERS_AND_OBJECTS_INHERIT;
+        access.Trustee.TrusteeForm = TRUSTEE_IS_SID;
+        access.Trustee.TrusteeType = TRUSTEE_IS_USER;
+        access.Trustee.ptstrName = sid as *mut u16;
+
+        let mut new_dacl: PACL = ptr::null_mut();
+        let result = SetEntriesInAclW(1, &mut access, dacl, &mut new_dacl);
+        LocalFree(descriptor as _);
+        if result != ERROR_SUCCESS {
+            return Err(os_error(result as i32)).context("Failed to build the new access control list");
+        }
+
+        let result = SetNamedSecurityInfoW(
+            wide_name.as_mut_ptr(),
+            SE_REGISTRY_KEY,
+            DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            new_dacl,
+            ptr::null_mut(),
+        );
+        LocalFree(new_dacl as _);
+        if result != ERROR_SUCCESS {
+            return Err(os_error(result as i32)).with_context(|| format!(r"Failed to update permissions of {}\{}", root, key));
+        }
+    }
+
+    info!(r"Took ownership of {}\{}", root, key);
+    Ok(())
+}
+
+/// Explorer policy key holding `NoFileAssociate`
+const EXPLORER_POLICIES_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Policies\Explorer";
+
+/// System policy key holding `DefaultAssociationsConfiguration`
+const SYSTEM_POLICIES_KEY: &str = r"SOFTWARE\Policies\Microsoft\Windows\System";
+
+/// A Group Policy setting that controls default associations
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssociationPolicy {
+    /// `NoFileAssociate` prevents changing file associations
+    NoFileAssociate { root: RegistryRoot },
+    /// A default associations XML assigns the extension or protocol to a handler
+    DefaultAssociationsConfiguration {
+        config_path: String,
+        prog_id: Option<String>,
+        application_name: Option<String>,
+    },
+}
+
+impl std::fmt::Display for AssociationPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssociationPolicy::NoFileAssociate { root } => {
+                write!(f, r"NoFileAssociate is set in {}\{}", root, EXPLORER_POLICIES_KEY)
+            }
+            AssociationPolicy::DefaultAssociationsConfiguration {
+                config_path,
+                application_name,
+                prog_id,
+            } => {
+                write!(f, "DefaultAssociationsConfiguration ({})", config_path)?;
+                match (application_name, prog_id) {
+                    (Some(name), _) => write!(f, " assigns it to {}", name),
+                    (None, Some(prog_id)) => write!(f, " assigns it to {}", prog_id),
+                    (None, None) => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// An association cannot be registered because it is managed by policy
+///
+/// Returned (inside `anyhow::Error`) by [`register_file_association_with_options`]
+/// and [`register_url_protocol`] on managed machines, so the UI can show
+/// "managed by your organization" instead of a registry failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AssociationManagedError {
+    /// Extension (e.g. `.opc`) or protocol (e.g. `opcode`)
+    pub identifier: String,
+    pub policies: Vec<AssociationPolicy>,
+}
+
+impl std::fmt::Display for AssociationManagedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The handler for {} is managed by your organization", self.identifier)?;
+        let policies: Vec<String> = self.policies.iter().map(ToString::to_string).collect();
+        if !policies.is_empty() {
+            write!(f, ": {}", policies.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssociationManagedError {}
+
+/// Find Group Policy settings that enforce the handler for an extension or protocol
+///
+/// Returns an empty list on unmanaged machines. A default associations XML
+/// only counts if it lists `identifier`; an unreadable XML is logged and ignored.
+pub fn detect_association_policies(identifier: &str) -> Result<Vec<AssociationPolicy>> {
+    let mut policies = Vec::new();
+
+    for root in [RegistryRoot::LocalMachine, RegistryRoot::CurrentUser] {
+        if unsafe { read_registry_dword(root.hkey(), EXPLORER_POLICIES_KEY, "NoFileAssociate") }? == Some(1) {
+            policies.push(AssociationPolicy::NoFileAssociate { root });
+        }
+    }
+
+    let config_path = unsafe {
+        read_registry_string(
+            RegistryRoot::LocalMachine.hkey(),
+            SYSTEM_POLICIES_KEY,
+            "DefaultAssociationsConfiguration",
+        )
+    }?;
+    if let Some(config_path) = config_path.filter(|p| !p.trim().is_empty()) {
+        match std::fs::read_to_string(&config_path) {
+            Ok(xml) => {
+                let entry = parse_default_associations(&xml)
+                    .into_iter()
+                    .find(|a| a.identifier.eq_ignore_ascii_case(identifier));
+                if let Some(entry) = entry {
+                    policies.push(AssociationPolicy::DefaultAssociationsConfiguration {
+                        config_path,
+                        prog_id: entry.prog_id,
+                        application_name: entry.application_name,
+                    });
+                }
+            }
+            Err(e) => warn!("Cannot read default associations configuration {}: {}", config_path, e),
+        }
+    }
+
+    Ok(policies)
+}
+
+/// Fail with [`AssociationManagedError`] if policy enforces the handler
+fn ensure_association_not_managed(identifier: &str) -> Result<()> {
+    let policies = detect_association_policies(identifier)?;
+    if policies.is_empty() {
+        return Ok(());
+    }
+
+    let error = AssociationManagedError {
+        identifier: identifier.to_string(),
+        policies,
+    };
+    warn!("{}", error);
+    Err(anyhow::Error::new(error))
+}
+
+/// One `<Association>` element of a default associations XML
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DefaultAssociation {
+    identifier: String,
+    prog_id: Option<String>,
+    application_name: Option<String>,
+}
+
+/// Extract `<Association Identifier=".." ProgId=".." ApplicationName=".."/>` entries
+fn parse_default_associations(xml: &str) -> Vec<DefaultAssociation> {
+    xml.split("<Association")
+        .skip(1)
+        .filter_map(|element| {
+            let element = &element[..element.find('>').unwrap_or(element.len())];
+            Some(DefaultAssociation {
+                identifier: xml_attribute(element, "Identifier")?,
+                prog_id: xml_attribute(element, "ProgId"),
+                application_name: xml_attribute(element, "ApplicationName"),
+            })
+        })
+        .collect()
+}
+
+fn xml_attribute(element: &str, name: &str) -> Option<String> {
+    let start = element.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = start + element[start..].find('"')?;
+    Some(
+        element[start..end]
+            .replace("&quot;", "\"")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Icon resource used for a registered file type
+///
+/// Points at an icon inside an executable/DLL (by index) or at a standalone
+/// `.ico` file (index 0), and is stored as `"path,index"` under `DefaultIcon`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconResource {
+    /// Path to the `.exe`, `.dll` or `.ico` file holding the icon
+    pub path: String,
+    /// Icon index inside the resource file (negative values are resource IDs)
+    pub index: i32,
+}
+
+impl IconResource {
+    /// Create an icon resource from a path and index
+    pub fn new(path: impl Into<String>, index: i32) -> Self {
+        Self {
+            path: path.into(),
+            index,
+        }
+    }
+
+    /// Format the icon as a `DefaultIcon` registry value
+    fn to_registry_value(&self) -> String {
+        format!("{},{}", self.path, self.index)
+    }
+}
+
+/// Windows Script Host drop handler, which forwards dropped paths to the
+/// default verb's command line
+const SHELL_DROP_HANDLER_CLSID: &str = "{60254CA5-953B-11CF-8C96-00AA00B8708C}";
+
+/// Content type registered when none is configured
+const DEFAULT_CONTENT_TYPE: &str = "application/x-opcode";
+
+/// PerceivedType values understood by the Windows shell
+const PERCEIVED_TYPES: &[&str] = &[
+    "text",
+    "image",
+    "audio",
+    "video",
+    "compressed",
+    "document",
+    "system",
+    "application",
+    "gamemedia",
+    "contacts",
+];
+
+/// Optional settings for [`register_file_association_with_options`]
+///
+/// The defaults match [`register_file_association`].
+#[derive(Debug, Clone, Default)]
+pub struct FileAssociationOptions {
+    /// Icon shown by Explorer for files of this type (defaults to the executable's first icon)
+    pub icon: Option<IconResource>,
+    /// MIME type written to `Content Type` (defaults to `application/x-opcode`)
+    pub content_type: Option<String>,
+    /// Shell PerceivedType (e.g. "text"), which enables the matching preview handler
+    pub perceived_type: Option<String>,
+    /// Display name for the type, either plain text or an indirect `@file,-id` string
+    pub friendly_type_name: Option<String>,
+    /// Extra context-menu verbs; a verb named `open` replaces the default one
+    pub verbs: Vec<ShellVerb>,
+    /// Verb invoked on double-click (defaults to `open`)
+    pub default_verb: Option<String>,
+    /// Accept folders and files dragged onto files of this type; they are
+    /// passed to the default verb after `%1` (via `%*`)
+    pub drop_target: bool,
+}
+
+/// A context-menu verb registered under `<ProgID>\shell\<name>`
+///
+/// The command line is the quoted executable path followed by `arguments`,
+/// which should reference the file as `"%1"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellVerb {
+    /// Verb key name (e.g. "resume")
+    pub name: String,
+    /// Menu text (e.g. "Resume session in Opcode"); the shell derives one from the name if unset
+    pub label: Option<String>,
+    /// Arguments passed after the executable (e.g. `--resume "%1"`)
+    pub arguments: String,
+}
+
+impl ShellVerb {
+    /// Create a verb with a menu label
+    pub fn new(name: impl Into<String>, label: impl Into<String>, arguments: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            label: Some(label.into()),
+            arguments: arguments.into(),
+        }
+    }
+
+    fn command_line(&self, executable_path: &str) -> String {
+        format!("{} {}", quote_windows_arg_always(executable_path), self.arguments)
+    }
+}
+
+/// Verbs to register: the default `open` verb plus any extra ones, where an
+/// extra verb with the same name replaces the default
+fn resolve_verbs(verbs: &[ShellVerb]) -> Result<Vec<ShellVerb>> {
+    let mut resolved: Vec<ShellVerb> = Vec::new();
+
+    for verb in verbs {
+        if verb.name.is_empty()
+            || !verb.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(anyhow::anyhow!("Invalid shell verb name: '{}'", verb.name));
+        }
+        if resolved.iter().any(|v| v.name.eq_ignore_ascii_case(&verb.name)) {
+            return Err(anyhow::anyhow!("Duplicate shell verb: '{}'", verb.name));
+        }
+        resolved.push(verb.clone());
+    }
+
+    if !resolved.iter().any(|v| v.name.eq_ignore_ascii_case("open")) {
+        resolved.insert(
+            0,
+            ShellVerb {
+                name: "open".to_string(),
+                label: None,
+                arguments: quote_windows_arg_always("%1"),
+            },
+        );
+    }
+
+    Ok(resolved)
+}
+
+/// Check that a PerceivedType is one the shell recognizes
+fn validate_perceived_type(perceived_type: &str) -> Result<()> {
+    if PERCEIVED_TYPES
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(perceived_type))
+    {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Unsupported PerceivedType '{}' (expected one of: {})",
+            perceived_type,
+            PERCEIVED_TYPES.join(", ")
+        ))
+    }
+}
+
+/// Check that a content type has the `type/subtype` shape
+fn validate_content_type(content_type: &str) -> Result<()> {
+    match content_type.split_once('/') {
+        Some((kind, subtype))
+            if !kind.is_empty()
+                && !subtype.is_empty()
+                && !content_type.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!("Invalid content type: {}", content_type)),
+    }
+}
+
 /// Register a file association in the Windows Registry
 ///
 /// This function registers a custom file extension with a program, allowing
@@ -128,7 +1074,8 @@ unsafe fn set_registry_value(key: HKEY, name: &str, value: &str) -> Result<()> {
 /// * `description` - Human-readable description of the file type
 ///
 /// # Returns
-/// * `Ok(())` if registration successful
+/// * `Ok(Some(scope))` with the scope registered in (see [`RegistrationScope::detect`])
+/// * `Ok(None)` in portable mode, where nothing is written
 /// * `Err(...)` if registration failed
 ///
 /// # Example
@@ -150,9 +1097,85 @@ pub fn register_file_association(
     program_id: &str,
     executable_path: &str,
     description: &str,
-) -> Result<()> {
+) -> Result<Option<RegistrationScope>> {
+    register_file_association_with_options(
+        extension,
+        program_id,
+        executable_path,
+        description,
+        &FileAssociationOptions::default(),
+    )
+}
+
+/// Register a file association with additional options
+///
+/// Same as [`register_file_association`], but allows customizing how the file
+/// type is presented, e.g. giving `.opc` project files and exported session
+/// files distinct icons in Explorer, or registering exported Markdown/JSON
+/// sessions as text so Explorer can preview them.
+///
+/// # Arguments
+/// * `extension` - File extension to register (e.g., ".opc")
+/// * `program_id` - Unique program identifier (e.g., "Opcode.Document")
+/// * `executable_path` - Full path to the executable
+/// * `description` - Human-readable description of the file type
+/// * `options` - Additional registration settings
+///
+/// # Example
+/// ```rust
+/// use crate::windows::registry::{register_file_association_with_options, FileAssociationOptions, IconResource, ShellVerb};
+///
+/// fn main() -> anyhow::Result<()> {
+///     let exe = r"C:\Program Files\Opcode\opcode.exe";
+///     register_file_association_with_options(
+///         ".opcsession",
+///         "Opcode.Session",
+///         exe,
+///         "Opcode Session Export",
+///         &FileAssociationOptions {
+///             icon: Some(IconResource::new(exe, 1)),
+///             content_type: Some("application/json".to_string()),
+///             perceived_type: Some("text".to_string()),
+///             verbs: vec![ShellVerb::new("resume", "Resume session in Opcode", r#"--resume "%1""#)],
+///             ..Default::default()
+///         },
+///     )?;
+///     Ok(())
+/// }
+/// ```
+pub fn register_file_association_with_options(
+    extension: &str,
+    program_id: &str,
+    executable_path: &str,
+    description: &str,
+    options: &FileAssociationOptions,
+) -> Result<Option<RegistrationScope>> {
     info!("Registering file association for extension: {}", extension);
 
+    if skip_in_portable_mode("file association registration") {
+        return Ok(None);
+    }
+    ensure_association_not_managed(extension)?;
+
+    let scope = RegistrationScope::detect();
+    let plan = plan_file_association_with_options(extension, program_id, executable_path, description, options)?;
+    apply_registry_plan(&plan_for_scope(plan, scope))?;
+
+    info!("Successfully registered file association for {} ({} scope)", extension, scope);
+    Ok(Some(scope))
+}
+
+/// Plan a file association without touching the registry
+///
+/// Runs the same validation as [`register_file_association_with_options`] and
+/// returns the exact changes it would make.
+pub fn plan_file_association_with_options(
+    extension: &str,
+    program_id: &str,
+    executable_path: &str,
+    description: &str,
+    options: &FileAssociationOptions,
+) -> Result<Vec<RegistryChange>> {
     // Ensure extension starts with a dot
     let ext = if extension.starts_with('.') {
         extension.to_string()
@@ -160,245 +1183,589 @@ pub fn register_file_association(
         format!(".{}", extension)
     };
 
-    // Verify executable exists
-    if !Path::new(executable_path).exists() {
-        return Err(anyhow::anyhow!("Executable not found for file association: {} (check installation)", executable_path));
+    // Verify executable exists
+    if !Path::new(executable_path).exists() {
+        return Err(anyhow::anyhow!("Executable not found for file association: {} (check installation)", executable_path));
+    }
+
+    // Use the executable's icon unless a custom icon was requested
+    let icon = options
+        .icon
+        .clone()
+        .unwrap_or_else(|| IconResource::new(executable_path, 0));
+
+    if !Path::new(&icon.path).exists() {
+        return Err(anyhow::anyhow!("Icon resource not found for file association: {}", icon.path));
+    }
+
+    let content_type = options.content_type.as_deref().unwrap_or(DEFAULT_CONTENT_TYPE);
+    validate_content_type(content_type)?;
+
+    if let Some(perceived_type) = &options.perceived_type {
+        validate_perceived_type(perceived_type)?;
+    }
+
+    let mut verbs = resolve_verbs(&options.verbs)?;
+    if let Some(default_verb) = &options.default_verb {
+        if !verbs.iter().any(|v| v.name.eq_ignore_ascii_case(default_verb)) {
+            return Err(anyhow::anyhow!("Default verb '{}' is not registered", default_verb));
+        }
+    }
+
+    // The drop handler runs the default verb, so it must accept the dropped paths
+    if options.drop_target {
+        let default_verb = options.default_verb.as_deref().unwrap_or("open");
+        if let Some(verb) = verbs.iter_mut().find(|v| v.name.eq_ignore_ascii_case(default_verb)) {
+            if !verb.arguments.contains("%*") {
+                verb.arguments.push_str(" %*");
+            }
+        }
+    }
+
+    let root = RegistryRoot::ClassesRoot;
+
+    // Point the extension at the program ID and set its content type
+    let mut plan = vec![
+        RegistryChange::set_value(root, &ext, "", program_id),
+        RegistryChange::set_value(root, &ext, "Content Type", content_type),
+    ];
+
+    // Set perceived type so the shell picks a matching preview handler
+    if let Some(perceived_type) = &options.perceived_type {
+        plan.push(RegistryChange::set_value(root, &ext, "PerceivedType", perceived_type));
+    }
+
+    // Register the program ID with its description
+    plan.push(RegistryChange::set_value(root, program_id, "", description));
+
+    if let Some(friendly_type_name) = &options.friendly_type_name {
+        plan.push(RegistryChange::set_value(root, program_id, "FriendlyTypeName", friendly_type_name));
+    }
+
+    // Shell verbs, each with its own command line ("%1" is the file argument)
+    for verb in &verbs {
+        let verb_key = format!(r"{}\shell\{}", program_id, verb.name);
+        if let Some(label) = &verb.label {
+            plan.push(RegistryChange::set_value(root, &verb_key, "", label));
+        }
+        plan.push(RegistryChange::set_value(
+            root,
+            &format!(r"{}\command", verb_key),
+            "",
+            &verb.command_line(executable_path),
+        ));
+    }
+
+    if let Some(default_verb) = &options.default_verb {
+        plan.push(RegistryChange::set_value(
+            root,
+            &format!(r"{}\shell", program_id),
+            "",
+            default_verb,
+        ));
+    }
+
+    if options.drop_target {
+        plan.push(RegistryChange::set_value(
+            root,
+            &format!(r"{}\shellex\DropHandler", program_id),
+            "",
+            SHELL_DROP_HANDLER_CLSID,
+        ));
+    }
+
+    plan.push(RegistryChange::set_value(
+        root,
+        &format!(r"{}\DefaultIcon", program_id),
+        "",
+        &icon.to_registry_value(),
+    ));
+
+    Ok(plan)
+}
+
+/// Register a URL protocol in the Windows Registry
+///
+/// This function registers a custom URL protocol (e.g., "opcode://") allowing
+/// the program to handle custom URI schemes.
+///
+/// # Arguments
+/// * `protocol` - Protocol name (e.g., "opcode")
+/// * `executable_path` - Full path to the executable
+/// * `description` - Human-readable description
+///
+/// # Returns
+/// * `Ok(Some(scope))` with the scope registered in (see [`RegistrationScope::detect`])
+/// * `Ok(None)` in portable mode, where nothing is written
+/// * `Err(...)` if registration failed
+///
+/// # Example
+/// ```rust
+/// use crate::windows::registry::register_url_protocol;
+///
+/// fn main() -> anyhow::Result<()> {
+///     register_url_protocol(
+///         "opcode",
+///         r"C:\Program Files\Opcode\opcode.exe",
+///         "Opcode Protocol"
+///     )?;
+///     Ok(())
+/// }
+/// ```
+pub fn register_url_protocol(
+    protocol: &str,
+    executable_path: &str,
+    description: &str,
+) -> Result<Option<RegistrationScope>> {
+    info!("Registering URL protocol: {}", protocol);
+
+    if skip_in_portable_mode("URL protocol registration") {
+        return Ok(None);
+    }
+    ensure_association_not_managed(protocol)?;
+
+    let scope = RegistrationScope::detect();
+    let plan = plan_url_protocol(protocol, executable_path, description)?;
+    apply_registry_plan(&plan_for_scope(plan, scope))?;
+
+    info!("Successfully registered URL protocol: {}:// ({} scope)", protocol, scope);
+    Ok(Some(scope))
+}
+
+/// Plan a URL protocol registration without touching the registry
+///
+/// Runs the same validation as [`register_url_protocol`] and returns the
+/// exact changes it would make.
+pub fn plan_url_protocol(
+    protocol: &str,
+    executable_path: &str,
+    description: &str,
+) -> Result<Vec<RegistryChange>> {
+    // Verify executable exists
+    if !Path::new(executable_path).exists() {
+        return Err(anyhow::anyhow!("Executable not found for URL protocol: {} (check installation)", executable_path));
+    }
+
+    let root = RegistryRoot::ClassesRoot;
+
+    Ok(vec![
+        // Describe the protocol and mark it as a URL protocol
+        RegistryChange::set_value(root, protocol, "", description),
+        RegistryChange::set_value(root, protocol, "URL Protocol", ""),
+        // Pass the URL as a single opaque argument behind a dedicated switch
+        // so it is validated as a deep link rather than parsed as CLI flags
+        RegistryChange::set_value(
+            root,
+            &format!(r"{}\shell\open\command", protocol),
+            "",
+            &format!(
+                "{} {} {}",
+                quote_windows_arg_always(executable_path),
+                crate::commands::deep_link::DEEP_LINK_SWITCH,
+                quote_windows_arg_always("%1")
+            ),
+        ),
+        RegistryChange::set_value(
+            root,
+            &format!(r"{}\DefaultIcon", protocol),
+            "",
+            &format!("{},0", executable_path),
+        ),
+    ])
+}
+
+/// Set auto-start on Windows login
+///
+/// This function adds or removes the application from Windows startup by
+/// modifying the Run registry key.
+/// MSIX-packaged installs toggle the package's startup task instead, in which
+/// case `app_name` and `executable_path` are not used.
+///
+/// # Arguments
+/// * `app_name` - Application name for the registry entry
+/// * `executable_path` - Full path to the executable
+/// * `enabled` - true to enable auto-start, false to disable
+///
+/// # Returns
+/// * `Ok(())` if operation successful
+/// * `Err(...)` if operation failed
+///
+/// # Example
+/// ```rust
+/// use crate::windows::registry::set_auto_start;
+///
+/// fn main() -> anyhow::Result<()> {
+///     // Enable auto-start
+///     set_auto_start(
+///         "Opcode",
+///         r"C:\Program Files\Opcode\opcode.exe",
+///         true
+///     )?;
+///
+///     // Disable auto-start
+///     set_auto_start("Opcode", "", false)?;
+///     Ok(())
+/// }
+/// ```
+pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
+    info!("Setting auto-start for {}: {}", app_name, enabled);
+
+    // Packaged installs ignore the Run key and use the manifest's startup task
+    if is_packaged() {
+        set_startup_task_enabled(STARTUP_TASK_ID, enabled)?;
+        return Ok(());
     }
 
-    unsafe {
-        use winapi::um::winreg::HKEY_CLASSES_ROOT;
-
-        // Register the extension
-        let ext_key = create_registry_key(HKEY_CLASSES_ROOT, &ext)
-            .context("Failed to create extension key")?;
-
-        // Set the default value to the program ID
-        set_registry_value(ext_key, "", program_id)
-            .context("Failed to set extension program ID")?;
-
-        // Set content type
-        set_registry_value(ext_key, "Content Type", "application/x-opcode")
-            .context("Failed to set content type")?;
-
-        RegCloseKey(ext_key);
-
-        // Register the program ID
-        let prog_key = create_registry_key(HKEY_CLASSES_ROOT, program_id)
-            .context("Failed to create program ID key")?;
-
-        // Set description
-        set_registry_value(prog_key, "", description)
-            .context("Failed to set program description")?;
+    if skip_in_portable_mode("auto-start change") {
+        return Ok(());
+    }
 
-        RegCloseKey(prog_key);
+    let plan = plan_auto_start(app_name, executable_path, enabled)?;
+    apply_registry_plan(&plan)?;
 
-        // Register the shell command
-        let shell_open_path = format!(r"{}\shell\open\command", program_id);
-        let shell_key = create_registry_key(HKEY_CLASSES_ROOT, &shell_open_path)
-            .context("Failed to create shell command key")?;
+    if enabled {
+        info!("Successfully enabled auto-start for {}", app_name);
+    } else {
+        info!("Successfully disabled auto-start for {}", app_name);
+    }
+    Ok(())
+}
 
-        // Set command line (executable path with "%1" for the file argument)
-        let command = format!(r#""{}" "%1""#, executable_path);
-        set_registry_value(shell_key, "", &command)
-            .context("Failed to set shell command")?;
+/// Plan an auto-start change without touching the registry
+///
+/// Runs the same validation as [`set_auto_start`] and returns the exact
+/// changes it would make.
+pub fn plan_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<Vec<RegistryChange>> {
+    let root = RegistryRoot::CurrentUser;
 
-        RegCloseKey(shell_key);
+    if !enabled {
+        return Ok(vec![RegistryChange::delete_value(root, RUN_KEY, app_name)]);
+    }
 
-        // Register the icon
-        let icon_path = format!(r"{}\DefaultIcon", program_id);
-        let icon_key = create_registry_key(HKEY_CLASSES_ROOT, &icon_path)
-            .context("Failed to create icon key")?;
+    // Verify executable exists
+    if !Path::new(executable_path).exists() {
+        return Err(anyhow::anyhow!("Executable not found for auto-start: {} (check installation)", executable_path));
+    }
 
-        // Use the executable's icon
-        let icon_value = format!("{},0", executable_path);
-        set_registry_value(icon_key, "", &icon_value)
-            .context("Failed to set icon")?;
+    Ok(vec![RegistryChange::set_value(
+        root,
+        RUN_KEY,
+        app_name,
+        &quote_windows_arg_always(executable_path),
+    )])
+}
 
-        RegCloseKey(icon_key);
-    }
+/// Auto-start registration state for an application
+///
+/// Describes what is currently stored under the `Run` key compared to the
+/// location of the running executable.
+///
+/// # Fields
+/// - `enabled`: Whether an auto-start entry exists for the application
+/// - `registered_path`: Executable path stored in the `Run` key, if any
+/// - `current_path`: Path of the currently running executable
+/// - `is_stale`: Whether the stored path differs from the current install location
+/// - `mechanism`: How auto-start is registered for this install
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoStartStatus {
+    pub enabled: bool,
+    pub registered_path: Option<String>,
+    pub current_path: String,
+    pub is_stale: bool,
+    pub mechanism: AutoStartMechanism,
+}
 
-    info!("Successfully registered file association for {}", extension);
-    Ok(())
+/// How auto-start is registered, chosen at runtime by packaging format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoStartMechanism {
+    /// A value under `HKCU\...\CurrentVersion\Run` (installer builds)
+    RunKey,
+    /// The `windows.startupTask` declared in the MSIX manifest
+    StartupTask,
 }
 
-/// Register a URL protocol in the Windows Registry
+/// Get the auto-start status for an application
 ///
-/// This function registers a custom URL protocol (e.g., "opcode://") allowing
-/// the program to handle custom URI schemes.
+/// Reads the `Run` registry key and compares the stored executable path with
+/// the location of the currently running executable. An entry is considered
+/// stale when the app was moved or updated to a different install location.
 ///
 /// # Arguments
-/// * `protocol` - Protocol name (e.g., "opcode")
-/// * `executable_path` - Full path to the executable
-/// * `description` - Human-readable description
+/// * `app_name` - Application name used for the registry entry
 ///
 /// # Returns
-/// * `Ok(())` if registration successful
-/// * `Err(...)` if registration failed
+/// * `Ok(AutoStartStatus)` describing the current registration
+/// * `Err(...)` if the registry could not be read
 ///
 /// # Example
 /// ```rust
-/// use crate::windows::registry::register_url_protocol;
+/// use crate::windows::registry::get_auto_start_status;
 ///
 /// fn main() -> anyhow::Result<()> {
-///     register_url_protocol(
-///         "opcode",
-///         r"C:\Program Files\Opcode\opcode.exe",
-///         "Opcode Protocol"
-///     )?;
+///     let status = get_auto_start_status("Opcode")?;
+///     if status.is_stale {
+///         println!("Auto-start points to {:?}", status.registered_path);
+///     }
 ///     Ok(())
 /// }
 /// ```
-pub fn register_url_protocol(
-    protocol: &str,
-    executable_path: &str,
-    description: &str,
-) -> Result<()> {
-    info!("Registering URL protocol: {}", protocol);
-
-    // Verify executable exists
-    if !Path::new(executable_path).exists() {
-        return Err(anyhow::anyhow!("Executable not found for URL protocol: {} (check installation)", executable_path));
+pub fn get_auto_start_status(app_name: &str) -> Result<AutoStartStatus> {
+    debug!("Querying auto-start status for {}", app_name);
+
+    let current_path = std::env::current_exe()
+        .context("Failed to determine current executable path")?
+        .to_string_lossy()
+        .to_string();
+
+    // Startup tasks always launch the package's own executable, so they cannot go stale
+    if is_packaged() {
+        let state = get_startup_task_state(STARTUP_TASK_ID)?;
+        return Ok(AutoStartStatus {
+            enabled: state.is_enabled(),
+            registered_path: None,
+            current_path,
+            is_stale: false,
+            mechanism: AutoStartMechanism::StartupTask,
+        });
     }
 
-    unsafe {
-        use winapi::um::winreg::HKEY_CLASSES_ROOT;
-
-        // Create protocol key
-        let protocol_key = create_registry_key(HKEY_CLASSES_ROOT, protocol)
-            .context("Failed to create protocol key")?;
+    let registered_path = unsafe {
+        use winapi::um::winreg::HKEY_CURRENT_USER;
 
-        // Set description
-        set_registry_value(protocol_key, "", description)
-            .context("Failed to set protocol description")?;
+        read_registry_string(HKEY_CURRENT_USER, RUN_KEY, app_name)
+            .context("Failed to read Run registry key")?
+    };
 
-        // Mark as URL protocol
-        set_registry_value(protocol_key, "URL Protocol", "")
-            .context("Failed to set URL protocol flag")?;
+    let is_stale = match &registered_path {
+        Some(path) => !same_executable_path(path, &current_path),
+        None => false,
+    };
 
-        RegCloseKey(protocol_key);
+    Ok(AutoStartStatus {
+        enabled: registered_path.is_some(),
+        registered_path,
+        current_path,
+        is_stale,
+        mechanism: AutoStartMechanism::RunKey,
+    })
+}
 
-        // Create shell command
-        let shell_command_path = format!(r"{}\shell\open\command", protocol);
-        let command_key = create_registry_key(HKEY_CLASSES_ROOT, &shell_command_path)
-            .context("Failed to create shell command key")?;
+/// Repair a stale auto-start entry
+///
+/// If auto-start is enabled but points to a different executable (e.g. after
+/// the app was moved or updated), the entry is rewritten to point at the
+/// currently running executable. Nothing is changed when auto-start is
+/// disabled or already up to date.
+///
+/// # Arguments
+/// * `app_name` - Application name used for the registry entry
+///
+/// # Returns
+/// * `Ok(true)` if a stale entry was repaired
+/// * `Ok(false)` if no repair was necessary
+/// * `Err(...)` if the registry could not be read or updated
+pub fn repair_auto_start(app_name: &str) -> Result<bool> {
+    if skip_in_portable_mode("auto-start repair") {
+        return Ok(false);
+    }
 
-        // Set command with URL parameter
-        let command = format!(r#""{}" "%1""#, executable_path);
-        set_registry_value(command_key, "", &command)
-            .context("Failed to set protocol command")?;
+    let status = get_auto_start_status(app_name)?;
 
-        RegCloseKey(command_key);
+    if !status.enabled || !status.is_stale {
+        debug!("Auto-start for {} does not need repair", app_name);
+        return Ok(false);
+    }
 
-        // Set default icon
-        let icon_path = format!(r"{}\DefaultIcon", protocol);
-        let icon_key = create_registry_key(HKEY_CLASSES_ROOT, &icon_path)
-            .context("Failed to create icon key")?;
+    warn!(
+        "Auto-start for {} points to stale path {:?}, updating to {}",
+        app_name, status.registered_path, status.current_path
+    );
 
-        let icon_value = format!("{},0", executable_path);
-        set_registry_value(icon_key, "", &icon_value)
-            .context("Failed to set protocol icon")?;
+    set_auto_start(app_name, &status.current_path, true)
+        .context("Failed to repair auto-start entry")?;
 
-        RegCloseKey(icon_key);
-    }
+    info!("Successfully repaired auto-start for {}", app_name);
+    Ok(true)
+}
 
-    info!("Successfully registered URL protocol: {}://", protocol);
-    Ok(())
+/// Compare two executable paths the way Windows does (quotes stripped, case-insensitive)
+fn same_executable_path(stored: &str, current: &str) -> bool {
+    let stored = stored.trim().trim_matches('"');
+    let current = current.trim().trim_matches('"');
+    stored.eq_ignore_ascii_case(current)
 }
 
-/// Set auto-start on Windows login
+/// Add a directory to the user `PATH` environment variable
 ///
-/// This function adds or removes the application from Windows startup by
-/// modifying the Run registry key.
+/// Appends the directory to `HKEY_CURRENT_USER\Environment\Path` and broadcasts
+/// `WM_SETTINGCHANGE` so newly started terminals pick up the change without a
+/// logoff. Directories already present (compared case-insensitively and
+/// ignoring trailing separators) are not added twice.
 ///
 /// # Arguments
-/// * `app_name` - Application name for the registry entry
-/// * `executable_path` - Full path to the executable
-/// * `enabled` - true to enable auto-start, false to disable
+/// * `dir` - Directory to add (e.g., the folder containing the opcode CLI shim)
 ///
 /// # Returns
-/// * `Ok(())` if operation successful
-/// * `Err(...)` if operation failed
+/// * `Ok(true)` if the directory was added
+/// * `Ok(false)` if the directory was already on the user PATH
+/// * `Err(...)` if the registry could not be read or updated
 ///
 /// # Example
 /// ```rust
-/// use crate::windows::registry::set_auto_start;
+/// use crate::windows::registry::add_to_path;
 ///
 /// fn main() -> anyhow::Result<()> {
-///     // Enable auto-start
-///     set_auto_start(
-///         "Opcode",
-///         r"C:\Program Files\Opcode\opcode.exe",
-///         true
-///     )?;
-///
-///     // Disable auto-start
-///     set_auto_start("Opcode", "", false)?;
+///     add_to_path(r"C:\Users\me\AppData\Local\Opcode\bin")?;
 ///     Ok(())
 /// }
 /// ```
-pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
-    info!("Setting auto-start for {}: {}", app_name, enabled);
+pub fn add_to_path(dir: &str) -> Result<bool> {
+    info!("Adding {} to user PATH", dir);
+
+    if skip_in_portable_mode("user PATH update") {
+        return Ok(false);
+    }
 
+    let plan = plan_add_to_path(dir)?;
+    if plan.is_empty() {
+        debug!("{} is already on the user PATH", dir);
+        return Ok(false);
+    }
+
+    apply_registry_plan(&plan)?;
+    broadcast_environment_change();
+
+    info!("Successfully added {} to user PATH", dir);
+    Ok(true)
+}
+
+/// Plan adding a directory to the user `PATH` without touching the registry
+///
+/// Returns an empty plan if the directory is already present.
+pub fn plan_add_to_path(dir: &str) -> Result<Vec<RegistryChange>> {
+    if !Path::new(dir).is_dir() {
+        return Err(anyhow::anyhow!("Directory not found for PATH entry: {}", dir));
+    }
+
+    Ok(path_list_add(&read_user_path()?, dir)
+        .map(|updated| vec![user_path_change(&updated)])
+        .unwrap_or_default())
+}
+
+/// Remove a directory from the user `PATH` environment variable
+///
+/// # Arguments
+/// * `dir` - Directory to remove
+///
+/// # Returns
+/// * `Ok(true)` if the directory was removed
+/// * `Ok(false)` if the directory was not on the user PATH
+/// * `Err(...)` if the registry could not be read or updated
+pub fn remove_from_path(dir: &str) -> Result<bool> {
+    info!("Removing {} from user PATH", dir);
+
+    if skip_in_portable_mode("user PATH update") {
+        return Ok(false);
+    }
+
+    let plan = plan_remove_from_path(dir)?;
+    if plan.is_empty() {
+        debug!("{} is not on the user PATH", dir);
+        return Ok(false);
+    }
+
+    apply_registry_plan(&plan)?;
+    broadcast_environment_change();
+
+    info!("Successfully removed {} from user PATH", dir);
+    Ok(true)
+}
+
+/// Plan removing a directory from the user `PATH` without touching the registry
+///
+/// Returns an empty plan if the directory is not present.
+pub fn plan_remove_from_path(dir: &str) -> Result<Vec<RegistryChange>> {
+    Ok(path_list_remove(&read_user_path()?, dir)
+        .map(|updated| vec![user_path_change(&updated)])
+        .unwrap_or_default())
+}
+
+/// Read the raw user `Path` value (empty if not set)
+fn read_user_path() -> Result<String> {
     unsafe {
         use winapi::um::winreg::HKEY_CURRENT_USER;
 
-        const RUN_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Run";
+        Ok(read_registry_string(HKEY_CURRENT_USER, ENVIRONMENT_KEY, "Path")
+            .context("Failed to read user PATH")?
+            .unwrap_or_default())
+    }
+}
 
-        if enabled {
-            // Verify executable exists
-            if !Path::new(executable_path).exists() {
-                return Err(anyhow::anyhow!("Executable not found for auto-start: {} (check installation)", executable_path));
-            }
+/// Write the user `Path` value, keeping it expandable so `%VAR%` entries still work
+fn user_path_change(value: &str) -> RegistryChange {
+    RegistryChange::set_expandable_value(RegistryRoot::CurrentUser, ENVIRONMENT_KEY, "Path", value)
+}
 
-            // Open Run key
-            let run_key = create_registry_key(HKEY_CURRENT_USER, RUN_KEY)
-                .context("Failed to open Run registry key")?;
-
-            // Set the auto-start value
-            set_registry_value(run_key, app_name, executable_path)
-                .context("Failed to set auto-start value")?;
-
-            RegCloseKey(run_key);
-
-            info!("Successfully enabled auto-start for {}", app_name);
-        } else {
-            // Remove the auto-start entry
-            let run_key_wide = to_wide_string(RUN_KEY);
-            let app_name_wide = to_wide_string(app_name);
-
-            // Open the Run key
-            let mut run_key: HKEY = ptr::null_mut();
-            let result = RegCreateKeyExW(
-                HKEY_CURRENT_USER,
-                run_key_wide.as_ptr(),
-                0,
-                ptr::null_mut(),
-                0,
-                KEY_ALL_ACCESS,
-                ptr::null_mut(),
-                &mut run_key,
-                ptr::null_mut(),
-            );
-
-            if result == ERROR_SUCCESS as i32 {
-                // Delete the value
-                use winapi::um::winreg::RegDeleteValueW;
-                let delete_result = RegDeleteValueW(run_key, app_name_wide.as_ptr());
-
-                RegCloseKey(run_key);
-
-                if delete_result == ERROR_SUCCESS as i32 {
-                    info!("Successfully disabled auto-start for {}", app_name);
-                } else {
-                    warn!("Auto-start entry for {} was not found or could not be deleted", app_name);
-                }
-            } else {
-                warn!("Could not open Run registry key");
-            }
-        }
+/// Notify running applications (Explorer in particular) that the environment changed
+fn broadcast_environment_change() {
+    use winapi::um::winuser::{SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE};
+
+    let environment = to_wide_string(ENVIRONMENT_KEY);
+    let mut result = 0;
+
+    let sent = unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            0,
+            environment.as_ptr() as isize,
+            SMTO_ABORTIFHUNG,
+            5000,
+            &mut result,
+        )
+    };
+
+    if sent == 0 {
+        warn!("Failed to broadcast environment change; new terminals may need a logoff to see PATH updates");
     }
+}
 
-    Ok(())
+/// Compare two PATH entries the way Windows does (case-insensitive, trailing separators ignored)
+fn same_path_entry(a: &str, b: &str) -> bool {
+    let a = a.trim().trim_end_matches(['\\', '/']);
+    let b = b.trim().trim_end_matches(['\\', '/']);
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Append `dir` to a `;`-separated PATH list, returning `None` if already present
+fn path_list_add(list: &str, dir: &str) -> Option<String> {
+    if list.split(';').any(|entry| same_path_entry(entry, dir)) {
+        return None;
+    }
+
+    let trimmed = list.trim_end_matches(';');
+    if trimmed.is_empty() {
+        Some(dir.to_string())
+    } else {
+        Some(format!("{};{}", trimmed, dir))
+    }
+}
+
+/// Remove `dir` from a `;`-separated PATH list, returning `None` if not present
+fn path_list_remove(list: &str, dir: &str) -> Option<String> {
+    let entries: Vec<&str> = list.split(';').filter(|entry| !entry.is_empty()).collect();
+    let remaining: Vec<&str> = entries
+        .iter()
+        .copied()
+        .filter(|entry| !same_path_entry(entry, dir))
+        .collect();
+
+    if remaining.len() == entries.len() {
+        None
+    } else {
+        Some(remaining.join(";"))
+    }
 }
 
 /// Remove a file association from the registry
@@ -413,6 +1780,10 @@ pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> R
 pub fn remove_file_association(extension: &str, program_id: &str) -> Result<()> {
     info!("Removing file association for extension: {}", extension);
 
+    if skip_in_portable_mode("file association removal") {
+        return Ok(());
+    }
+
     // Ensure extension starts with a dot
     let ext = if extension.starts_with('.') {
         extension.to_string()
@@ -450,6 +1821,10 @@ pub fn remove_file_association(extension: &str, program_id: &str) -> Result<()>
 pub fn remove_url_protocol(protocol: &str) -> Result<()> {
     info!("Removing URL protocol: {}", protocol);
 
+    if skip_in_portable_mode("URL protocol removal") {
+        return Ok(());
+    }
+
     unsafe {
         use winapi::um::winreg::HKEY_CLASSES_ROOT;
 
@@ -461,6 +1836,107 @@ pub fn remove_url_protocol(protocol: &str) -> Result<()> {
     Ok(())
 }
 
+/// Register the executable under the per-user App Paths key
+///
+/// This lets users launch the app by typing its name (e.g. `opcode`) in the
+/// Win+R Run dialog or the Start menu even when the install directory is not
+/// on PATH. The install directory is also recorded as the `Path` value so
+/// the process can locate DLLs shipped next to the executable.
+///
+/// # Arguments
+/// * `executable_path` - Full path to the executable; its file name becomes the key name
+///
+/// # Returns
+/// * `Ok(())` if registration successful
+/// * `Err(...)` if registration failed
+///
+/// # Example
+/// ```rust
+/// use crate::windows::registry::register_app_path;
+///
+/// fn main() -> anyhow::Result<()> {
+///     register_app_path(r"C:\Program Files\Opcode\opcode.exe")?;
+///     Ok(())
+/// }
+/// ```
+pub fn register_app_path(executable_path: &str) -> Result<()> {
+    info!("Registering App Paths entry for: {}", executable_path);
+
+    if skip_in_portable_mode("App Paths registration") {
+        return Ok(());
+    }
+
+    let plan = plan_app_path(executable_path)?;
+    apply_registry_plan(&plan)?;
+
+    info!("Successfully registered App Paths entry for {}", executable_path);
+    Ok(())
+}
+
+/// Plan an App Paths registration without touching the registry
+///
+/// Runs the same validation as [`register_app_path`] and returns the exact
+/// changes it would make.
+pub fn plan_app_path(executable_path: &str) -> Result<Vec<RegistryChange>> {
+    let exe = Path::new(executable_path);
+    if !exe.exists() {
+        return Err(anyhow::anyhow!("Executable not found for App Paths: {} (check installation)", executable_path));
+    }
+
+    let exe_name = exe
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid executable path: {}", executable_path))?;
+    let install_dir = exe
+        .parent()
+        .and_then(|dir| dir.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid executable path: {}", executable_path))?;
+
+    let root = RegistryRoot::CurrentUser;
+    let key = app_path_key(exe_name);
+
+    Ok(vec![
+        // The default value is the full path to launch
+        RegistryChange::set_value(root, &key, "", executable_path),
+        // Directories prepended to PATH for the launched process
+        RegistryChange::set_value(root, &key, "Path", install_dir),
+    ])
+}
+
+/// Remove the App Paths entry for an executable
+///
+/// # Arguments
+/// * `exe_name` - Executable file name (e.g., "opcode.exe" or "opcode")
+///
+/// # Returns
+/// * `Ok(())` if removal successful or entry didn't exist
+/// * `Err(...)` if removal failed
+pub fn remove_app_path(exe_name: &str) -> Result<()> {
+    info!("Removing App Paths entry for: {}", exe_name);
+
+    if skip_in_portable_mode("App Paths removal") {
+        return Ok(());
+    }
+
+    unsafe {
+        use winapi::um::winreg::HKEY_CURRENT_USER;
+
+        delete_registry_tree(HKEY_CURRENT_USER, &app_path_key(exe_name))?;
+    }
+
+    info!("Successfully removed App Paths entry for {}", exe_name);
+    Ok(())
+}
+
+/// Build the App Paths subkey for an executable, appending `.exe` if missing
+fn app_path_key(exe_name: &str) -> String {
+    if exe_name.to_ascii_lowercase().ends_with(".exe") {
+        format!(r"{}\{}", APP_PATHS_KEY, exe_name)
+    } else {
+        format!(r"{}\{}.exe", APP_PATHS_KEY, exe_name)
+    }
+}
+
 /// Delete a registry key and all its subkeys recursively
 unsafe fn delete_registry_tree(root: HKEY, path: &str) -> Result<()> {
     use winapi::um::winreg::RegDeleteTreeW;
@@ -531,4 +2007,239 @@ mod tests {
         let result = set_auto_start("OpcodeTest", "", false);
         assert!(result.is_ok(), "Auto-start disablement should succeed in test environment");
     }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_auto_start_status_and_repair() {
+        let exe_path = env::current_exe().expect("Failed to get current executable path");
+        let exe_str = exe_path.to_str().expect("Executable path should be valid UTF-8");
+
+        // Register a stale path that differs from the current executable
+        let stale_path = env::temp_dir().join("opcode_stale.exe");
+        std::fs::write(&stale_path, b"").expect("Failed to create stale executable placeholder");
+        let stale_str = stale_path.to_str().expect("Stale path should be valid UTF-8");
+        set_auto_start("OpcodeRepairTest", stale_str, true).expect("Failed to enable auto-start");
+
+        let status = get_auto_start_status("OpcodeRepairTest").expect("Failed to query auto-start status");
+        assert!(status.enabled, "Auto-start should be reported as enabled");
+        assert!(status.is_stale, "Auto-start entry should be reported as stale");
+
+        let repaired = repair_auto_start("OpcodeRepairTest").expect("Failed to repair auto-start");
+        assert!(repaired, "Stale auto-start entry should be repaired");
+
+        let status = get_auto_start_status("OpcodeRepairTest").expect("Failed to query auto-start status");
+        assert_eq!(status.registered_path.as_deref(), Some(exe_str));
+        assert!(!status.is_stale, "Repaired auto-start entry should not be stale");
+
+        // Clean up
+        let _ = set_auto_start("OpcodeRepairTest", "", false);
+        let _ = std::fs::remove_file(&stale_path);
+    }
+
+    #[test]
+    fn test_path_list_add_and_remove() {
+        let list = r"C:\Windows;C:\Tools\";
+        assert_eq!(path_list_add(list, r"c:\tools"), None);
+        assert_eq!(path_list_add(list, r"C:\Opcode\bin").as_deref(), Some(r"C:\Windows;C:\Tools\;C:\Opcode\bin"));
+        assert_eq!(path_list_add("", r"C:\Opcode\bin").as_deref(), Some(r"C:\Opcode\bin"));
+
+        assert_eq!(path_list_remove(list, r"C:\Opcode\bin"), None);
+        assert_eq!(path_list_remove(list, r"C:\TOOLS").as_deref(), Some(r"C:\Windows"));
+    }
+
+    #[test]
+    fn test_icon_resource_registry_value() {
+        let icon = IconResource::new(r"C:\Program Files\Opcode\opcode.exe", 2);
+        assert_eq!(icon.to_registry_value(), r"C:\Program Files\Opcode\opcode.exe,2");
+
+        let icon = IconResource::new(r"C:\Icons\session.ico", 0);
+        assert_eq!(icon.to_registry_value(), r"C:\Icons\session.ico,0");
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_app_path_registration() {
+        let exe_path = env::current_exe().expect("Failed to get current executable path");
+        let exe_str = exe_path.to_str().expect("Executable path should be valid UTF-8");
+        let exe_name = exe_path.file_name().unwrap().to_str().unwrap();
+
+        register_app_path(exe_str).expect("Failed to register App Paths entry");
+
+        let stored = unsafe {
+            use winapi::um::winreg::HKEY_CURRENT_USER;
+            read_registry_string(HKEY_CURRENT_USER, &app_path_key(exe_name), "")
+        };
+        assert_eq!(stored.unwrap().as_deref(), Some(exe_str));
+
+        remove_app_path(exe_name).expect("Failed to remove App Paths entry");
+    }
+
+    #[test]
+    fn test_locked_key_error() {
+        assert_eq!(
+            key_prefixes(r"Opcode.Document\shell\open"),
+            vec!["Opcode.Document", r"Opcode.Document\shell", r"Opcode.Document\shell\open"]
+        );
+        assert_eq!(registry_object_name(RegistryRoot::ClassesRoot, ".opc"), r"CLASSES_ROOT\.opc");
+
+        let error = RegistryKeyLockedError {
+            root: RegistryRoot::ClassesRoot,
+            key: ".opc".to_string(),
+            owner: Some(RegistryKeyOwner {
+                account: Some(r"NT SERVICE\TrustedInstaller".to_string()),
+                sid: "S-1-5-80-956008885-3418522649-1831038044-1853292631-2271478464".to_string(),
+            }),
+        };
+        assert!(error.to_string().contains(r"HKEY_CLASSES_ROOT\.opc is denied"));
+        assert!(error.to_string().contains(r"owned by NT SERVICE\TrustedInstaller"));
+
+        let wrapped = anyhow::Error::new(std::io::Error::from_raw_os_error(5)).context("Failed to create registry key");
+        assert!(is_access_denied(&wrapped));
+        assert!(!is_access_denied(&anyhow::anyhow!("other failure")));
+
+        let owner = inspect_registry_key_owner(RegistryRoot::CurrentUser, "Software").expect("Failed to read owner");
+        assert!(owner.is_some_and(|o| o.sid.starts_with("S-1-")));
+    }
+
+    #[test]
+    fn test_association_policies() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DefaultAssociations>
+  <Association Identifier=".opc" ProgId="Contoso.Document" ApplicationName="Contoso Editor" />
+  <Association Identifier="mailto" ProgId="Outlook.URL.mailto.15"/>
+</DefaultAssociations>"#;
+
+        let entries = parse_default_associations(xml);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].identifier, ".opc");
+        assert_eq!(entries[0].application_name.as_deref(), Some("Contoso Editor"));
+        assert_eq!(entries[1].prog_id.as_deref(), Some("Outlook.URL.mailto.15"));
+        assert_eq!(entries[1].application_name, None);
+
+        let error = AssociationManagedError {
+            identifier: ".opc".to_string(),
+            policies: vec![AssociationPolicy::DefaultAssociationsConfiguration {
+                config_path: r"C:\Policiesssoc.xml".to_string(),
+                prog_id: entries[0].prog_id.clone(),
+                application_name: entries[0].application_name.clone(),
+            }],
+        };
+        assert!(error.to_string().starts_with("The handler for .opc is managed by your organization"));
+        assert!(error.to_string().contains("assigns it to Contoso Editor"));
+
+        detect_association_policies(".opc").expect("Failed to read association policies");
+    }
+
+    #[test]
+    fn test_plan_for_scope() {
+        let plan = vec![
+            RegistryChange::set_value(RegistryRoot::ClassesRoot, ".opc", "", "Opcode.Document"),
+            RegistryChange::delete_value(RegistryRoot::CurrentUser, RUN_KEY, "Opcode"),
+        ];
+
+        let user = plan_for_scope(plan.clone(), RegistrationScope::User);
+        assert_eq!(user[0].root, RegistryRoot::CurrentUser);
+        assert_eq!(user[0].key, r"Software\Classes\.opc");
+        assert!(!user[0].requires_elevation);
+        assert_eq!(user[1], plan[1]);
+
+        let machine = plan_for_scope(plan, RegistrationScope::Machine);
+        assert_eq!(machine[0].root, RegistryRoot::LocalMachine);
+        assert!(machine[0].requires_elevation);
+    }
+
+    #[test]
+    fn test_registration_plans() {
+        let exe_path = env::current_exe().expect("Failed to get current executable path");
+        let exe_str = exe_path.to_str().expect("Executable path should be valid UTF-8");
+
+        let plan = plan_url_protocol("opcodetest", exe_str, "Opcode Test Protocol").unwrap();
+        assert_eq!(plan.len(), 4);
+        assert!(plan.iter().all(|change| change.root == RegistryRoot::ClassesRoot && change.requires_elevation));
+        assert_eq!(
+            plan[2].action,
+            RegistryAction::SetValue {
+                name: String::new(),
+                data: format!(r#""{}" --deep-link "%1""#, exe_str),
+                expandable: false,
+            }
+        );
+
+        let plan = plan_auto_start("OpcodeTest", "", false).unwrap();
+        assert_eq!(plan, vec![RegistryChange::delete_value(RegistryRoot::CurrentUser, RUN_KEY, "OpcodeTest")]);
+        assert!(!plan[0].requires_elevation);
+        assert_eq!(
+            plan[0].to_string(),
+            r"delete HKEY_CURRENT_USER\SOFTWARE\Microsoft\Windows\CurrentVersion\Run [OpcodeTest]"
+        );
+
+        assert!(plan_auto_start("OpcodeTest", r"C:\missing\opcode.exe", true).is_err());
+
+        let options = FileAssociationOptions {
+            drop_target: true,
+            ..Default::default()
+        };
+        let plan = plan_file_association_with_options(".opctest", "Opcode.TestDocument", exe_str, "Test", &options).unwrap();
+        assert!(plan.contains(&RegistryChange::set_value(
+            RegistryRoot::ClassesRoot,
+            r"Opcode.TestDocument\shellex\DropHandler",
+            "",
+            SHELL_DROP_HANDLER_CLSID,
+        )));
+        assert!(plan.contains(&RegistryChange::set_value(
+            RegistryRoot::ClassesRoot,
+            r"Opcode.TestDocument\shell\open\command",
+            "",
+            &format!(r#""{}" "%1" %*"#, exe_str),
+        )));
+    }
+
+    #[test]
+    fn test_resolve_verbs() {
+        let verbs = resolve_verbs(&[ShellVerb::new("resume", "Resume session in Opcode", r#"--resume "%1""#)]).unwrap();
+        let names: Vec<&str> = verbs.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["open", "resume"]);
+        assert_eq!(verbs[1].command_line(r"C:\opcode.exe"), r#""C:\opcode.exe" --resume "%1""#);
+
+        let custom_open = ShellVerb::new("open", "Open in Opcode", r#"--open "%1""#);
+        let verbs = resolve_verbs(std::slice::from_ref(&custom_open)).unwrap();
+        assert_eq!(verbs, vec![custom_open]);
+
+        assert!(resolve_verbs(&[ShellVerb::new("bad verb", "Bad", "")]).is_err());
+        assert!(resolve_verbs(&[
+            ShellVerb::new("export", "Export", "--export \"%1\""),
+            ShellVerb::new("Export", "Export again", "--export \"%1\""),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_app_path_key() {
+        assert_eq!(
+            app_path_key("opcode.exe"),
+            r"Software\Microsoft\Windows\CurrentVersion\App Paths\opcode.exe"
+        );
+        assert_eq!(
+            app_path_key("opcode"),
+            r"Software\Microsoft\Windows\CurrentVersion\App Paths\opcode.exe"
+        );
+    }
+
+    #[test]
+    fn test_association_type_validation() {
+        assert!(validate_content_type("text/markdown").is_ok());
+        assert!(validate_content_type(DEFAULT_CONTENT_TYPE).is_ok());
+        assert!(validate_content_type("markdown").is_err());
+        assert!(validate_content_type("text/ markdown").is_err());
+
+        assert!(validate_perceived_type("text").is_ok());
+        assert!(validate_perceived_type("Document").is_ok());
+        assert!(validate_perceived_type("spreadsheet").is_err());
+    }
+
+    #[test]
+    fn test_same_executable_path() {
+        assert!(same_executable_path(r#""C:\Program Files\Opcode\opcode.exe""#, r"c:\program files\opcode\OPCODE.exe"));
+        assert!(!same_executable_path(r"C:\Old\opcode.exe", r"C:\New\opcode.exe"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,227 @@
+//! Child processes with restricted tokens
+//!
+//! A child normally gets a copy of opcode's token, so when opcode runs
+//! elevated every Claude subprocess (and every tool it starts) has full
+//! administrator rights too. [`spawn_restricted`] creates the child's token
+//! with `CreateRestrictedToken` instead: administrator groups become
+//! deny-only (they can still deny access but never grant it), privileges are
+//! removed and the integrity level drops back to Medium, so the child behaves
+//! like an unelevated process of the same user. Agent runs use it when the
+//! runtime config asks for the restricted token sandbox (see
+//! [`ClaudeSandbox`](super::secrets::ClaudeSandbox)).
+//!
+//! # Example
+//! ```rust
+//! use crate::windows::integrity::ChildStdio;
+//! use crate::windows::restricted_token::{spawn_restricted, RestrictionSpec};
+//!
+//! fn run_claude(claude_path: &str, project: &str) -> anyhow::Result<()> {
+//!     let child = spawn_restricted(
+//!         claude_path,
+//!         &["--version"],
+//!         Some(std::path::Path::new(project)),
+//!         &RestrictionSpec::default(),
+//!         ChildStdio::Detached,
+//!     )?;
+//!     child.wait(None)?;
+//!     Ok(())
+//! }
+//! ```
+
+use super::integrity::{
+    open_current_process_token, set_token_integrity_level, spawn_with_token, ChildStdio,
+    ContainedChild, IntegrityLevel, OwnedHandle,
+};
+use super::{to_wide_string, LocalSids};
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::ptr;
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::shared::sddl::ConvertStringSidToSidW;
+use winapi::um::securitybaseapi::CreateRestrictedToken;
+use winapi::um::winnt::{
+    DISABLE_MAX_PRIVILEGE, HANDLE, PSID, SID_AND_ATTRIBUTES, TOKEN_ADJUST_DEFAULT,
+    TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE, TOKEN_QUERY,
+};
+
+/// Groups that carry administrative rights: Administrators, "Local account
+/// and member of Administrators group" and Backup Operators
+const ADMIN_GROUP_SIDS: &[&str] = &["S-1-5-32-544", "S-1-5-114", "S-1-5-32-551"];
+
+/// What to take away from the child's token
+///
+/// # Fields
+/// - `drop_admin_groups`: Make the administrator groups deny-only
+/// - `drop_privileges`: Remove every privilege except `SeChangeNotifyPrivilege`
+/// - `deny_only_sids`: Further group SIDs to make deny-only
+/// - `integrity_level`: Integrity level of the child; an elevated opcode runs
+///   at High, so `Medium` matches an unelevated process
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestrictionSpec {
+    pub drop_admin_groups: bool,
+    pub drop_privileges: bool,
+    pub deny_only_sids: Vec<String>,
+    pub integrity_level: Option<IntegrityLevel>,
+}
+
+impl Default for RestrictionSpec {
+    /// Drop everything that elevation adds
+    fn default() -> Self {
+        RestrictionSpec {
+            drop_admin_groups: true,
+            drop_privileges: true,
+            deny_only_sids: Vec::new(),
+            integrity_level: Some(IntegrityLevel::Medium),
+        }
+    }
+}
+
+impl RestrictionSpec {
+    /// Group SIDs that become deny-only in the child's token
+    fn deny_only_sids(&self) -> Vec<&str> {
+        let mut sids: Vec<&str> = Vec::new();
+        if self.drop_admin_groups {
+            sids.extend_from_slice(ADMIN_GROUP_SIDS);
+        }
+        for sid in &self.deny_only_sids {
+            if !sids.iter().any(|s| s.eq_ignore_ascii_case(sid)) {
+                sids.push(sid);
+            }
+        }
+        sids
+    }
+}
+
+/// Start a program with a token restricted according to `spec`
+///
+/// Groups the token does not contain are ignored, so the default spec also
+/// works when opcode is not elevated; the child then simply gets the same
+/// rights as opcode minus its privileges. The child gets the handles `stdio`
+/// asks for and no console window.
+///
+/// # Arguments
+/// * `program` - Executable to run
+/// * `args` - Arguments, quoted for the Windows command line as needed
+/// * `working_dir` - Working directory, or `None` to use opcode's
+/// * `spec` - What to remove from the token
+/// * `stdio` - Which of opcode's handles the child gets
+pub fn spawn_restricted(
+    program: &str,
+    args: &[&str],
+    working_dir: Option<&Path>,
+    spec: &RestrictionSpec,
+    stdio: ChildStdio,
+) -> Result<ContainedChild> {
+    let deny_only = spec.deny_only_sids();
+    info!(
+        "Spawning {} with a restricted token (deny-only groups: {:?}, privileges dropped: {}, integrity: {:?})",
+        program, deny_only, spec.drop_privileges, spec.integrity_level
+    );
+
+    unsafe {
+        let token = open_current_process_token(
+            TOKEN_DUPLICATE | TOKEN_ADJUST_DEFAULT | TOKEN_QUERY | TOKEN_ASSIGN_PRIMARY,
+        )?;
+
+        let mut sids = LocalSids(Vec::with_capacity(deny_only.len()));
+        for sid_string in &deny_only {
+            let wide_sid = to_wide_string(sid_string);
+            let mut sid: PSID = ptr::null_mut();
+            if ConvertStringSidToSidW(wide_sid.as_ptr(), &mut sid) == FALSE {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Invalid SID: {}", sid_string));
+            }
+            sids.0.push(sid);
+        }
+        let mut sids_to_disable: Vec<SID_AND_ATTRIBUTES> = sids
+            .0
+            .iter()
+            .map(|&sid| SID_AND_ATTRIBUTES {
+                Sid: sid,
+                Attributes: 0,
+            })
+            .collect();
+
+        let flags = if spec.drop_privileges {
+            DISABLE_MAX_PRIVILEGE
+        } else {
+            0
+        };
+        let mut restricted: HANDLE = ptr::null_mut();
+        if CreateRestrictedToken(
+            token.0,
+            flags,
+            sids_to_disable.len() as DWORD,
+            if sids_to_disable.is_empty() {
+                ptr::null_mut()
+            } else {
+                sids_to_disable.as_mut_ptr()
+            },
+            0,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            &mut restricted,
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to create the restricted token");
+        }
+        let restricted = OwnedHandle(restricted);
+
+        if let Some(level) = spec.integrity_level {
+            set_token_integrity_level(restricted.0, level)?;
+        }
+
+        spawn_with_token(restricted.0, program, args, working_dir, stdio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_only_sids() {
+        let spec = RestrictionSpec {
+            deny_only_sids: vec!["s-1-5-32-544".to_string(), "S-1-5-32-555".to_string()],
+            ..RestrictionSpec::default()
+        };
+        assert_eq!(
+            spec.deny_only_sids(),
+            vec!["S-1-5-32-544", "S-1-5-114", "S-1-5-32-551", "S-1-5-32-555"]
+        );
+
+        let spec = RestrictionSpec {
+            drop_admin_groups: false,
+            ..RestrictionSpec::default()
+        };
+        assert!(spec.deny_only_sids().is_empty());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_spawn_restricted() {
+        let child = spawn_restricted(
+            "cmd.exe",
+            &["/C", "ping -n 3 127.0.0.1 >NUL"],
+            None,
+            &RestrictionSpec::default(),
+            ChildStdio::Detached,
+        )
+        .unwrap();
+        assert!(
+            crate::windows::integrity::get_process_integrity_level(Some(child.pid())).unwrap()
+                <= IntegrityLevel::Medium
+        );
+        assert_eq!(
+            child
+                .wait(Some(std::time::Duration::from_secs(30)))
+                .unwrap(),
+            Some(0)
+        );
+    }
+}
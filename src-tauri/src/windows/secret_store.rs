@@ -0,0 +1,218 @@
+//! One interface over the per-platform secret stores
+//!
+//! API keys and OAuth tokens live in the credential store the platform
+//! provides: Credential Manager on Windows, the login Keychain on macOS and
+//! the Secret Service (GNOME Keyring, KWallet) on Linux and FreeBSD. The
+//! `secrets` module is a different file on every platform, all offering the
+//! same free functions; [`SecretStore`] puts them behind a trait so callers
+//! hold a single handle from [`secret_store`]. There is no plaintext
+//! fallback: where no store is available, storing a secret fails.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of every secret opcode owns
+pub(super) const TARGET_PREFIX: &str = "opcode";
+
+/// What a stored secret is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretKind {
+    AnthropicApiKey,
+    OpenRouterApiKey,
+    OAuthToken,
+}
+
+impl SecretKind {
+    pub(super) const ALL: [SecretKind; 3] = [
+        SecretKind::AnthropicApiKey,
+        SecretKind::OpenRouterApiKey,
+        SecretKind::OAuthToken,
+    ];
+
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            SecretKind::AnthropicApiKey => "anthropic_api_key",
+            SecretKind::OpenRouterApiKey => "openrouter_api_key",
+            SecretKind::OAuthToken => "oauth_token",
+        }
+    }
+
+    pub(super) fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+}
+
+/// A stored secret, without its value
+///
+/// # Fields
+/// - `kind`: What the secret is used for
+/// - `account`: Name distinguishing several secrets of one kind (e.g. `default`, `work`)
+/// - `target`: Name of the entry as shown by the platform's credential UI
+/// - `last_written`: When the secret was last stored, if the store reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SecretInfo {
+    pub kind: SecretKind,
+    pub account: String,
+    pub target: String,
+    pub last_written: Option<DateTime<Utc>>,
+}
+
+/// Entry name for a secret, e.g. `opcode:anthropic_api_key:default`
+pub(super) fn target_name(kind: SecretKind, account: &str) -> String {
+    format!("{}:{}:{}", TARGET_PREFIX, kind.as_str(), account)
+}
+
+/// Kind and account of an entry name created by [`target_name`]
+///
+/// The Secret Service keeps kind and account in separate attributes instead.
+#[cfg(any(target_os = "windows", target_os = "macos", test))]
+pub(super) fn parse_target_name(target: &str) -> Option<(SecretKind, String)> {
+    let rest = target.strip_prefix(TARGET_PREFIX)?.strip_prefix(':')?;
+    let (kind, account) = rest.split_once(':')?;
+    if account.is_empty() {
+        return None;
+    }
+    Some((SecretKind::from_str(kind)?, account.to_string()))
+}
+
+pub(super) fn validate_account(account: &str) -> Result<()> {
+    if account.is_empty() || account.chars().any(|c| c.is_control()) {
+        anyhow::bail!("Invalid secret account name: {:?}", account);
+    }
+    Ok(())
+}
+
+/// Secret operations every platform backend provides
+pub trait SecretStore: Send + Sync {
+    /// Short name of the backend, for logs and diagnostics
+    fn backend(&self) -> &'static str;
+
+    /// Store a secret, replacing any previous value for the same kind and account
+    fn store(&self, kind: SecretKind, account: &str, secret: &str) -> Result<()>;
+
+    /// Read a secret; `Ok(None)` if none is stored for the kind and account
+    fn read(&self, kind: SecretKind, account: &str) -> Result<Option<String>>;
+
+    /// Delete a secret, returning whether one was stored
+    fn delete(&self, kind: SecretKind, account: &str) -> Result<bool>;
+
+    /// The secrets opcode has stored, sorted by kind and account
+    fn list(&self) -> Result<Vec<SecretInfo>>;
+}
+
+/// Sort secrets by kind and account, as [`SecretStore::list`] returns them
+pub(super) fn sort_secrets(secrets: &mut [SecretInfo]) {
+    secrets.sort_by(|a, b| (a.kind.as_str(), &a.account).cmp(&(b.kind.as_str(), &b.account)));
+}
+
+/// The secret store for the platform Opcode was built for
+pub fn secret_store() -> &'static dyn SecretStore {
+    #[cfg(target_os = "windows")]
+    return &super::secrets::CredentialManagerStore;
+
+    #[cfg(target_os = "macos")]
+    return &super::secrets::KeychainStore;
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    return &super::secrets::SecretServiceStore;
+
+    #[cfg(not(any(
+        target_os = "windows",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "freebsd"
+    )))]
+    return &UnsupportedSecretStore;
+}
+
+/// [`SecretStore`] for platforms without a credential store
+///
+/// Nothing can be stored, so nothing is ever found.
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "freebsd"
+)))]
+pub struct UnsupportedSecretStore;
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "freebsd"
+)))]
+impl SecretStore for UnsupportedSecretStore {
+    fn backend(&self) -> &'static str {
+        "unsupported"
+    }
+
+    fn store(&self, _kind: SecretKind, _account: &str, _secret: &str) -> Result<()> {
+        Err(super::unsupported::Unsupported::new("Secure credential storage").into())
+    }
+
+    fn read(&self, _kind: SecretKind, account: &str) -> Result<Option<String>> {
+        validate_account(account)?;
+        Ok(None)
+    }
+
+    fn delete(&self, _kind: SecretKind, account: &str) -> Result<bool> {
+        validate_account(account)?;
+        Ok(false)
+    }
+
+    fn list(&self) -> Result<Vec<SecretInfo>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_name_round_trip() {
+        for kind in SecretKind::ALL {
+            let target = target_name(kind, "work:eu");
+            assert_eq!(
+                parse_target_name(&target),
+                Some((kind, "work:eu".to_string()))
+            );
+        }
+        assert_eq!(
+            target_name(SecretKind::OpenRouterApiKey, "default"),
+            "opcode:openrouter_api_key:default"
+        );
+
+        assert_eq!(parse_target_name("opcode:unknown_kind:default"), None);
+        assert_eq!(parse_target_name("opcode:oauth_token:"), None);
+        assert_eq!(parse_target_name("opcodex:oauth_token:default"), None);
+        assert_eq!(parse_target_name("git:https://github.com"), None);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires an unlocked credential store
+    fn test_secret_store_round_trip() {
+        let store = secret_store();
+        let account = format!("test-{}", uuid::Uuid::new_v4());
+        let kind = SecretKind::OAuthToken;
+
+        assert_eq!(store.read(kind, &account).unwrap(), None);
+        store.store(kind, &account, "token-1").unwrap();
+        store.store(kind, &account, "token-2").unwrap();
+        assert_eq!(
+            store.read(kind, &account).unwrap().as_deref(),
+            Some("token-2")
+        );
+        assert!(store
+            .list()
+            .unwrap()
+            .iter()
+            .any(|s| s.kind == kind && s.account == account));
+
+        assert!(store.delete(kind, &account).unwrap());
+        assert!(!store.delete(kind, &account).unwrap());
+    }
+}
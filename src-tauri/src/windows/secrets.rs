@@ -0,0 +1,722 @@
+//! API keys and tokens in Windows Credential Manager
+//!
+//! Secrets are stored as generic credentials named
+//! `opcode:<kind>:<account>` (e.g. `opcode:anthropic_api_key:default`), so
+//! they are encrypted with the user's logon credentials, never written to
+//! opcode's config files, and visible (and removable) under "Windows
+//! Credentials" in the Control Panel. Credentials persist on this machine
+//! only and do not roam with the user's profile.
+//!
+//! Secrets handed to child processes go through an inherited pipe rather than
+//! an environment variable (see [`pass_secret`]), since other processes of
+//! the same user can read a process's environment. Claude reads its keys
+//! from the environment, so opcode starts it through a copy of itself that
+//! reads the pipes (see [`run_claude_wrapper_from_args`]). The wrapper also
+//! starts Claude in a sandbox when opcode asks for one (see
+//! [`ClaudeSandbox`]).
+//!
+//! # Example
+//! ```rust
+//! use crate::windows::secrets::{read_secret, store_secret, SecretKind};
+//!
+//! fn remember_key(key: &str) -> anyhow::Result<()> {
+//!     store_secret(SecretKind::AnthropicApiKey, "default", key)?;
+//!     assert_eq!(read_secret(SecretKind::AnthropicApiKey, "default")?.as_deref(), Some(key));
+//!     Ok(())
+//! }
+//! ```
+
+use super::appcontainer::{AppContainerCapability, AppContainerLauncher};
+use super::integrity::{ChildStdio, ContainedChild};
+use super::restricted_token::{spawn_restricted, RestrictionSpec};
+use super::to_wide_string;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::ptr;
+use winapi::shared::minwindef::{DWORD, FALSE, FILETIME};
+use winapi::shared::winerror::ERROR_NOT_FOUND;
+use winapi::um::wincred::{
+    CredDeleteW, CredEnumerateW, CredFree, CredReadW, CredWriteW, CREDENTIALW,
+    CRED_MAX_CREDENTIAL_BLOB_SIZE, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC, PCREDENTIALW,
+};
+
+use super::secret_store::{
+    parse_target_name, sort_secrets, target_name, validate_account, TARGET_PREFIX,
+};
+pub use super::secret_store::{SecretInfo, SecretKind};
+
+/// Read a NUL-terminated wide string
+unsafe fn from_wide_ptr(ptr: *const u16) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+}
+
+fn filetime_to_utc(time: &FILETIME) -> Option<DateTime<Utc>> {
+    // 100ns intervals since 1601-01-01
+    let intervals = ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+    let seconds = (intervals / 10_000_000) as i64 - 11_644_473_600;
+    let nanos = (intervals % 10_000_000) as u32 * 100;
+    DateTime::from_timestamp(seconds, nanos)
+}
+
+/// Store a secret, replacing any previous value for the same kind and account
+///
+/// # Arguments
+/// * `kind` - What the secret is used for
+/// * `account` - Name distinguishing several secrets of one kind
+/// * `secret` - The key or token; at most 2560 bytes of UTF-8
+pub fn store_secret(kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+    validate_account(account)?;
+    if secret.len() > CRED_MAX_CREDENTIAL_BLOB_SIZE as usize {
+        anyhow::bail!(
+            "Secret is {} bytes, Credential Manager stores at most {}",
+            secret.len(),
+            CRED_MAX_CREDENTIAL_BLOB_SIZE
+        );
+    }
+
+    let target = target_name(kind, account);
+    let mut wide_target = to_wide_string(&target);
+    let mut wide_user = to_wide_string(account);
+    let mut blob = secret.as_bytes().to_vec();
+
+    let result = unsafe {
+        let mut credential: CREDENTIALW = std::mem::zeroed();
+        credential.Type = CRED_TYPE_GENERIC;
+        credential.TargetName = wide_target.as_mut_ptr();
+        credential.UserName = wide_user.as_mut_ptr();
+        credential.CredentialBlobSize = blob.len() as DWORD;
+        credential.CredentialBlob = blob.as_mut_ptr();
+        credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+
+        if CredWriteW(&mut credential, 0) == FALSE {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    };
+
+    // Don't leave a copy of the secret behind in freed memory
+    blob.iter_mut().for_each(|b| *b = 0);
+
+    result.with_context(|| format!("Failed to store {}", target))?;
+    info!("Stored {} in Credential Manager", target);
+    Ok(())
+}
+
+/// Read a secret; `Ok(None)` if none is stored for the kind and account
+pub fn read_secret(kind: SecretKind, account: &str) -> Result<Option<String>> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    let wide_target = to_wide_string(&target);
+
+    unsafe {
+        let mut credential: PCREDENTIALW = ptr::null_mut();
+        if CredReadW(wide_target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == FALSE {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_NOT_FOUND as i32) {
+                debug!("No {} in Credential Manager", target);
+                return Ok(None);
+            }
+            return Err(error).with_context(|| format!("Failed to read {}", target));
+        }
+
+        let blob = std::slice::from_raw_parts(
+            (*credential).CredentialBlob,
+            (*credential).CredentialBlobSize as usize,
+        );
+        let secret = String::from_utf8(blob.to_vec());
+        CredFree(credential as _);
+
+        secret
+            .map(Some)
+            .with_context(|| format!("{} is not valid UTF-8", target))
+    }
+}
+
+/// Delete a secret, returning whether one was stored
+pub fn delete_secret(kind: SecretKind, account: &str) -> Result<bool> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    let wide_target = to_wide_string(&target);
+
+    unsafe {
+        if CredDeleteW(wide_target.as_ptr(), CRED_TYPE_GENERIC, 0) == FALSE {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_NOT_FOUND as i32) {
+                return Ok(false);
+            }
+            return Err(error).with_context(|| format!("Failed to delete {}", target));
+        }
+    }
+
+    info!("Deleted {} from Credential Manager", target);
+    Ok(true)
+}
+
+/// List the secrets opcode has stored, without their values
+///
+/// Sorted by kind and account. Credentials under the `opcode:` prefix that
+/// opcode did not create are skipped.
+pub fn list_secrets() -> Result<Vec<SecretInfo>> {
+    let filter = to_wide_string(&format!("{}:*", TARGET_PREFIX));
+
+    let mut secrets = Vec::new();
+    unsafe {
+        let mut count: DWORD = 0;
+        let mut credentials: *mut PCREDENTIALW = ptr::null_mut();
+        if CredEnumerateW(filter.as_ptr(), 0, &mut count, &mut credentials) == FALSE {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_NOT_FOUND as i32) {
+                return Ok(secrets);
+            }
+            return Err(error).context("Failed to enumerate Credential Manager entries");
+        }
+
+        for &credential in std::slice::from_raw_parts(credentials, count as usize) {
+            if (*credential).Type != CRED_TYPE_GENERIC {
+                continue;
+            }
+            let target = from_wide_ptr((*credential).TargetName);
+            match parse_target_name(&target) {
+                Some((kind, account)) => secrets.push(SecretInfo {
+                    kind,
+                    account,
+                    target,
+                    last_written: filetime_to_utc(&(*credential).LastWritten),
+                }),
+                None => debug!("Skipping unrecognized credential {}", target),
+            }
+        }
+        CredFree(credentials as _);
+    }
+
+    sort_secrets(&mut secrets);
+    Ok(secrets)
+}
+
+/// [`SecretStore`](super::secret_store::SecretStore) backed by Credential Manager
+pub struct CredentialManagerStore;
+
+impl super::secret_store::SecretStore for CredentialManagerStore {
+    fn backend(&self) -> &'static str {
+        "credential_manager"
+    }
+
+    fn store(&self, kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+        store_secret(kind, account, secret)
+    }
+
+    fn read(&self, kind: SecretKind, account: &str) -> Result<Option<String>> {
+        read_secret(kind, account)
+    }
+
+    fn delete(&self, kind: SecretKind, account: &str) -> Result<bool> {
+        delete_secret(kind, account)
+    }
+
+    fn list(&self) -> Result<Vec<SecretInfo>> {
+        list_secrets()
+    }
+}
+
+/// Prefix of the environment variable carrying the handle of an inherited
+/// secret, followed by the name of the variable it replaces
+pub const SECRET_HANDLE_PREFIX: &str = "OPCODE_SECRET_HANDLE_";
+
+/// Name of the variable carrying the handle for the secret `name`
+fn handle_var_name(name: &str) -> String {
+    format!("{}{}", SECRET_HANDLE_PREFIX, name)
+}
+
+/// A secret waiting in an anonymous pipe for a child process to read it
+///
+/// The child inherits the read end of the pipe and only learns its handle
+/// value from the environment, so the secret itself never shows up in the
+/// child's environment block, where tools like Process Explorer display it.
+/// The read end is only inheritable while [`spawn_inheriting`] starts the
+/// intended child. Keep the value alive until then; dropping it closes
+/// opcode's copy of the handle.
+#[derive(Debug)]
+pub struct InheritedSecret {
+    read: super::integrity::OwnedHandle,
+}
+
+impl InheritedSecret {
+    /// Write `secret` to a new pipe
+    pub fn new(secret: &str) -> Result<Self> {
+        use super::integrity::OwnedHandle;
+        use winapi::um::fileapi::WriteFile;
+        use winapi::um::namedpipeapi::CreatePipe;
+
+        let bytes = secret.as_bytes();
+        let mut read = ptr::null_mut();
+        let mut write = ptr::null_mut();
+
+        unsafe {
+            // Size the buffer so the write below completes without a reader
+            let size = (bytes.len() as DWORD).max(4096);
+            if CreatePipe(&mut read, &mut write, ptr::null_mut(), size) == FALSE {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to create the secret pipe");
+            }
+            let read = OwnedHandle(read);
+            let write = OwnedHandle(write);
+
+            let mut written: DWORD = 0;
+            if WriteFile(
+                write.0,
+                bytes.as_ptr() as _,
+                bytes.len() as DWORD,
+                &mut written,
+                ptr::null_mut(),
+            ) == FALSE
+                || written as usize != bytes.len()
+            {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to write the secret pipe");
+            }
+            // Closing the write end lets the child read up to end of file
+            drop(write);
+
+            Ok(Self { read })
+        }
+    }
+
+    fn set_inheritable(&self, inheritable: bool) -> std::io::Result<()> {
+        use winapi::um::handleapi::SetHandleInformation;
+        use winapi::um::winbase::HANDLE_FLAG_INHERIT;
+
+        let flags = if inheritable { HANDLE_FLAG_INHERIT } else { 0 };
+        if unsafe { SetHandleInformation(self.read.0, HANDLE_FLAG_INHERIT, flags) } == FALSE {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Handle value of the read end, as seen by the child
+    pub fn handle_value(&self) -> usize {
+        self.read.0 as usize
+    }
+
+    /// Tell `command` where to read the secret that would otherwise be
+    /// passed in the environment variable `name`
+    ///
+    /// Removes `name` from the child's environment in case opcode itself has it.
+    pub fn pass_to(&self, command: &mut std::process::Command, name: &str) {
+        command.env_remove(name);
+        command.env(handle_var_name(name), self.handle_value().to_string());
+    }
+}
+
+/// Held while a child that inherits secret pipes is spawned
+static SPAWN_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Spawn a child that inherits `secrets`
+///
+/// Rust's `Command` lets a child inherit every inheritable handle, so the
+/// pipes are made inheritable just for `spawn` and one spawn at a time.
+/// Another process opcode starts at that instant without going through
+/// here could still inherit one; it doesn't know the handle value, and the
+/// pipe can only be read once.
+pub fn spawn_inheriting<T>(
+    secrets: &[InheritedSecret],
+    spawn: impl FnOnce() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let _guard = SPAWN_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let result = secrets
+        .iter()
+        .try_for_each(|secret| secret.set_inheritable(true))
+        .and_then(|()| spawn());
+    for secret in secrets {
+        if let Err(e) = secret.set_inheritable(false) {
+            warn!("Failed to stop a secret pipe from being inherited: {}", e);
+        }
+    }
+    result
+}
+
+/// How [`pass_secret`] handed a secret to a child
+#[derive(Debug)]
+pub enum SecretChannel {
+    /// Through a pipe; spawn the child with [`spawn_inheriting`]
+    Handle(InheritedSecret),
+    /// In the environment variable, because the pipe could not be set up
+    Environment,
+}
+
+/// Pass `secret` to the child started by `command`, which would otherwise
+/// receive it in the environment variable `name`
+///
+/// Uses an [`InheritedSecret`] and falls back to setting `name` when the
+/// pipe can't be created. The child (or a wrapper around it) reads the
+/// secret with [`read_inherited_secret`].
+pub fn pass_secret(
+    command: &mut std::process::Command,
+    name: &str,
+    secret: &str,
+) -> SecretChannel {
+    match InheritedSecret::new(secret) {
+        Ok(inherited) => {
+            inherited.pass_to(command, name);
+            debug!("Passing {} through an inherited handle", name);
+            SecretChannel::Handle(inherited)
+        }
+        Err(e) => {
+            warn!(
+                "Passing {} in the environment instead of a handle: {:#}",
+                name, e
+            );
+            command.env(name, secret);
+            SecretChannel::Environment
+        }
+    }
+}
+
+/// Read a secret passed by [`pass_secret`], in the child process
+///
+/// Reads the inherited pipe once, closes it and removes the handle variable,
+/// so the secret can't be read again or leak to grandchildren. Falls back to
+/// the environment variable `name` itself when no handle was passed.
+pub fn read_inherited_secret(name: &str) -> Result<Option<String>> {
+    use super::integrity::OwnedHandle;
+    use winapi::shared::winerror::ERROR_BROKEN_PIPE;
+    use winapi::um::fileapi::{GetFileType, ReadFile};
+    use winapi::um::winbase::FILE_TYPE_PIPE;
+
+    let var = handle_var_name(name);
+    let Ok(value) = std::env::var(&var) else {
+        return Ok(std::env::var(name).ok());
+    };
+    std::env::remove_var(&var);
+
+    let handle: usize = value
+        .parse()
+        .with_context(|| format!("Invalid secret handle in {}: {:?}", var, value))?;
+    let handle = OwnedHandle(handle as _);
+
+    unsafe {
+        if GetFileType(handle.0) != FILE_TYPE_PIPE {
+            anyhow::bail!("{} does not refer to an inherited pipe", var);
+        }
+
+        let mut secret = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let mut read: DWORD = 0;
+            if ReadFile(
+                handle.0,
+                buffer.as_mut_ptr() as _,
+                buffer.len() as DWORD,
+                &mut read,
+                ptr::null_mut(),
+            ) == FALSE
+            {
+                let error = std::io::Error::last_os_error();
+                if error.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) {
+                    break;
+                }
+                return Err(error).context("Failed to read the inherited secret");
+            }
+            if read == 0 {
+                break;
+            }
+            secret.extend_from_slice(&buffer[..read as usize]);
+        }
+
+        String::from_utf8(secret)
+            .map(Some)
+            .context("Inherited secret is not valid UTF-8")
+    }
+}
+
+/// Argument that starts opcode as the wrapper around a Claude process,
+/// followed by Claude's path and arguments
+pub const CLAUDE_WRAPPER_ARG: &str = "--claude-wrapper";
+
+/// Environment variable carrying the wrapper's [`ClaudeSandbox`], as JSON
+const CLAUDE_SANDBOX_VAR: &str = "OPCODE_CLAUDE_SANDBOX";
+
+/// Sandbox the Claude wrapper starts Claude in
+///
+/// Opcode prepares the sandbox and holds what it was granted until the run
+/// ends, since the wrapper may be killed before it could clean up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClaudeSandbox {
+    /// An AppContainer opcode has set up, see [`AppContainerLauncher::spawn`]
+    AppContainer {
+        name: String,
+        capabilities: Vec<AppContainerCapability>,
+    },
+    /// A restricted token, see [`spawn_restricted`]
+    RestrictedToken { spec: RestrictionSpec },
+}
+
+impl ClaudeSandbox {
+    /// Have the wrapper started by `command` use this sandbox
+    pub fn pass_to(&self, command: &mut std::process::Command) -> Result<()> {
+        let json = serde_json::to_string(self).context("Failed to serialize the sandbox")?;
+        command.env(CLAUDE_SANDBOX_VAR, json);
+        Ok(())
+    }
+
+    /// The sandbox opcode passed to this wrapper, if any
+    fn take() -> Result<Option<Self>> {
+        let Ok(json) = std::env::var(CLAUDE_SANDBOX_VAR) else {
+            return Ok(None);
+        };
+        std::env::remove_var(CLAUDE_SANDBOX_VAR);
+        serde_json::from_str(&json)
+            .map(Some)
+            .with_context(|| format!("Invalid {}: {}", CLAUDE_SANDBOX_VAR, json))
+    }
+
+    /// Start `program` in the sandbox with the wrapper's standard handles
+    fn spawn(&self, program: &str, args: &[String]) -> Result<ContainedChild> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match self {
+            ClaudeSandbox::AppContainer { name, capabilities } => {
+                let mut launcher = AppContainerLauncher::new(name);
+                launcher.capabilities = capabilities.clone();
+                launcher.stdio = ChildStdio::Inherit;
+                launcher.spawn(program, &args, None)
+            }
+            ClaudeSandbox::RestrictedToken { spec } => {
+                spawn_restricted(program, &args, None, spec, ChildStdio::Inherit)
+            }
+        }
+    }
+}
+
+/// Run opcode as the Claude wrapper if `args` ask for it, returning the exit code
+///
+/// The wrapper reads the secrets passed with [`pass_secret`] once and runs
+/// Claude with them, in a job object so that Claude exits with the wrapper
+/// when opcode kills it.
+pub fn run_claude_wrapper_from_args(args: &[String]) -> Option<i32> {
+    if args.get(1).map(String::as_str) != Some(CLAUDE_WRAPPER_ARG) {
+        return None;
+    }
+    let Some(program) = args.get(2) else {
+        error!("{} needs the path of Claude", CLAUDE_WRAPPER_ARG);
+        return Some(2);
+    };
+
+    match run_claude_wrapper(program, &args[3..]) {
+        Ok(code) => Some(code),
+        Err(e) => {
+            error!("Claude wrapper failed: {:#}", e);
+            Some(1)
+        }
+    }
+}
+
+fn run_claude_wrapper(program: &str, args: &[String]) -> Result<i32> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::jobapi2::AssignProcessToJobObject;
+
+    let names: Vec<String> = std::env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .filter_map(|key| key.strip_prefix(SECRET_HANDLE_PREFIX).map(str::to_string))
+        .collect();
+    let mut secrets = Vec::new();
+    for name in names {
+        if let Some(secret) = read_inherited_secret(&name)? {
+            secrets.push((name, secret));
+        }
+    }
+
+    let job = kill_on_close_job()?;
+
+    if let Some(sandbox) = ClaudeSandbox::take()? {
+        // Sandboxed children inherit the wrapper's environment, and the
+        // wrapper runs no other threads that could read it meanwhile
+        for (name, secret) in &secrets {
+            std::env::set_var(name, secret);
+        }
+        let child = sandbox.spawn(program, args)?;
+        if unsafe { AssignProcessToJobObject(job.0, child.handle()) } == FALSE {
+            warn!(
+                "Claude may outlive its wrapper: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let code = child.wait(None).context("Failed to wait for Claude")?;
+        return Ok(code.map_or(1, |code| code as i32));
+    }
+
+    let mut command = std::process::Command::new(program);
+    command.args(args).envs(secrets);
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program))?;
+    if unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle() as _) } == FALSE {
+        warn!(
+            "Claude may outlive its wrapper: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    let status = child.wait().context("Failed to wait for Claude")?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Job object that kills its processes when its last handle closes
+fn kill_on_close_job() -> Result<super::integrity::OwnedHandle> {
+    use super::integrity::OwnedHandle;
+    use winapi::um::jobapi2::{CreateJobObjectW, SetInformationJobObject};
+    use winapi::um::winnt::{
+        JobObjectExtendedLimitInformation, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    unsafe {
+        let job = CreateJobObjectW(ptr::null_mut(), ptr::null());
+        if job.is_null() {
+            return Err(std::io::Error::last_os_error()).context("Failed to create a job object");
+        }
+        let job = OwnedHandle(job);
+
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        if SetInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            &mut limits as *mut _ as _,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as DWORD,
+        ) == FALSE
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to configure the job object");
+        }
+        Ok(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_var_name() {
+        assert_eq!(
+            handle_var_name("ANTHROPIC_API_KEY"),
+            "OPCODE_SECRET_HANDLE_ANTHROPIC_API_KEY"
+        );
+    }
+
+    #[test]
+    fn test_run_claude_wrapper_from_args() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(run_claude_wrapper_from_args(&args(&["opcode.exe"])), None);
+        assert_eq!(
+            run_claude_wrapper_from_args(&args(&["opcode.exe", "--agent-runner"])),
+            None
+        );
+        assert_eq!(
+            run_claude_wrapper_from_args(&args(&["opcode.exe", CLAUDE_WRAPPER_ARG])),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_claude_sandbox_json() {
+        let sandbox = ClaudeSandbox::AppContainer {
+            name: "opcode.agent-run-7".to_string(),
+            capabilities: vec![AppContainerCapability::InternetClient],
+        };
+        let json = serde_json::to_value(&sandbox).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "kind": "app_container",
+                "name": "opcode.agent-run-7",
+                "capabilities": ["internet_client"]
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<ClaudeSandbox>(json).unwrap(),
+            sandbox
+        );
+
+        let sandbox = ClaudeSandbox::RestrictedToken {
+            spec: RestrictionSpec::default(),
+        };
+        let json = serde_json::to_value(&sandbox).unwrap();
+        assert_eq!(json["kind"], "restricted_token");
+        assert_eq!(json["spec"]["drop_admin_groups"], true);
+        assert_eq!(
+            serde_json::from_value::<ClaudeSandbox>(json).unwrap(),
+            sandbox
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_inherited_secret_round_trip() {
+        let name = "OPCODE_TEST_INHERITED_SECRET";
+        let inherited = InheritedSecret::new("sk-ant-test").unwrap();
+        std::env::set_var(handle_var_name(name), inherited.handle_value().to_string());
+
+        // The reader closes the handle, as the child would
+        std::mem::forget(inherited);
+        assert_eq!(read_inherited_secret(name).unwrap().as_deref(), Some("sk-ant-test"));
+        assert!(std::env::var(handle_var_name(name)).is_err());
+        assert_eq!(read_inherited_secret(name).unwrap(), None);
+    }
+
+    #[test]
+    fn test_filetime_to_utc() {
+        // 2024-01-01T00:00:00Z
+        let intervals: u64 = (1_704_067_200 + 11_644_473_600) * 10_000_000;
+        let time = FILETIME {
+            dwLowDateTime: intervals as u32,
+            dwHighDateTime: (intervals >> 32) as u32,
+        };
+        assert_eq!(
+            filetime_to_utc(&time).unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_input() {
+        assert!(store_secret(SecretKind::AnthropicApiKey, "", "key").is_err());
+        assert!(read_secret(SecretKind::AnthropicApiKey, "a\nb").is_err());
+        let too_long = "x".repeat(CRED_MAX_CREDENTIAL_BLOB_SIZE as usize + 1);
+        assert!(store_secret(SecretKind::AnthropicApiKey, "default", &too_long).is_err());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_store_read_list_delete() {
+        let account = format!("test-{}", uuid::Uuid::new_v4());
+        let kind = SecretKind::OAuthToken;
+
+        assert_eq!(read_secret(kind, &account).unwrap(), None);
+        store_secret(kind, &account, "token-1").unwrap();
+        store_secret(kind, &account, "token-2").unwrap();
+        assert_eq!(read_secret(kind, &account).unwrap().as_deref(), Some("token-2"));
+
+        let listed = list_secrets().unwrap();
+        let info = listed
+            .iter()
+            .find(|s| s.kind == kind && s.account == account)
+            .expect("stored secret should be listed");
+        assert!(info.last_written.is_some());
+
+        assert!(delete_secret(kind, &account).unwrap());
+        assert!(!delete_secret(kind, &account).unwrap());
+        assert_eq!(read_secret(kind, &account).unwrap(), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! API keys and tokens in the freedesktop Secret Service
+//!
+//! Offers the same functions as the Windows `secrets` module. Secrets are
+//! items in the default collection (the "Login" keyring of GNOME Keyring or
+//! KWallet) labelled `opcode:<kind>:<account>` and found by their
+//! `application`, `kind` and `account` attributes, so they show up in
+//! Seahorse and are encrypted with the user's login password.
+//!
+//! Headless machines often run no Secret Service at all. Storing a secret
+//! fails there rather than writing it to a file in plaintext.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use secret_service::blocking::{Item, SecretService};
+use secret_service::EncryptionType;
+use std::collections::HashMap;
+
+use super::secret_store::{sort_secrets, target_name, validate_account, TARGET_PREFIX};
+pub use super::secret_store::{SecretInfo, SecretKind};
+
+/// Attributes identifying one secret, or all of opcode's without a kind
+fn attributes(kind: Option<SecretKind>, account: Option<&str>) -> HashMap<&'static str, &str> {
+    let mut attributes = HashMap::from([("application", TARGET_PREFIX)]);
+    if let Some(kind) = kind {
+        attributes.insert("kind", kind.as_str());
+    }
+    if let Some(account) = account {
+        attributes.insert("account", account);
+    }
+    attributes
+}
+
+/// Why storing fails on machines without a Secret Service
+const NO_SECRET_SERVICE: &str =
+    "No Secret Service is running; install and unlock GNOME Keyring or KWallet to store secrets";
+
+fn connect() -> Result<SecretService<'static>> {
+    SecretService::connect(EncryptionType::Dh).context(NO_SECRET_SERVICE)
+}
+
+/// The item stored for `kind` and `account`, unlocked, if any
+///
+/// Unlocking a locked item makes the Secret Service ask for the keyring
+/// password.
+fn find_item<'a>(
+    service: &'a SecretService<'a>,
+    kind: SecretKind,
+    account: &str,
+) -> Result<Option<Item<'a>>> {
+    let found = service
+        .search_items(attributes(Some(kind), Some(account)))
+        .context("Failed to search the Secret Service")?;
+    if let Some(item) = found.unlocked.into_iter().next() {
+        return Ok(Some(item));
+    }
+    match found.locked.into_iter().next() {
+        Some(item) => {
+            item.unlock().context("Failed to unlock the keyring")?;
+            Ok(Some(item))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Store a secret, replacing any previous value for the same kind and account
+pub fn store_secret(kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    let service = connect()?;
+    let collection = service
+        .get_default_collection()
+        .context("The Secret Service has no default keyring")?;
+    if collection.is_locked().unwrap_or(true) {
+        collection
+            .unlock()
+            .context("Failed to unlock the keyring")?;
+    }
+
+    collection
+        .create_item(
+            &target,
+            attributes(Some(kind), Some(account)),
+            secret.as_bytes(),
+            true,
+            "text/plain",
+        )
+        .with_context(|| format!("Failed to store {}", target))?;
+    info!("Stored {} in the Secret Service", target);
+    Ok(())
+}
+
+/// Read a secret; `Ok(None)` if none is stored for the kind and account
+pub fn read_secret(kind: SecretKind, account: &str) -> Result<Option<String>> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    let service = connect()?;
+    let Some(item) = find_item(&service, kind, account)? else {
+        debug!("No {} in the Secret Service", target);
+        return Ok(None);
+    };
+
+    let secret = item
+        .get_secret()
+        .with_context(|| format!("Failed to read {}", target))?;
+    String::from_utf8(secret)
+        .map(Some)
+        .with_context(|| format!("{} is not valid UTF-8", target))
+}
+
+/// Delete a secret, returning whether one was stored
+pub fn delete_secret(kind: SecretKind, account: &str) -> Result<bool> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    let service = connect()?;
+    let Some(item) = find_item(&service, kind, account)? else {
+        return Ok(false);
+    };
+
+    item.delete()
+        .with_context(|| format!("Failed to delete {}", target))?;
+    info!("Deleted {} from the Secret Service", target);
+    Ok(true)
+}
+
+/// Kind and account of an item from its attributes
+fn parse_attributes(attributes: &HashMap<String, String>) -> Option<(SecretKind, String)> {
+    if attributes.get("application").map(String::as_str) != Some(TARGET_PREFIX) {
+        return None;
+    }
+    let kind = SecretKind::from_str(attributes.get("kind")?)?;
+    let account = attributes.get("account").filter(|a| !a.is_empty())?;
+    Some((kind, account.clone()))
+}
+
+/// List the secrets opcode has stored, without their values
+///
+/// Sorted by kind and account. Items in locked keyrings are listed too,
+/// without unlocking them.
+pub fn list_secrets() -> Result<Vec<SecretInfo>> {
+    let service = connect()?;
+    let found = service
+        .search_items(attributes(None, None))
+        .context("Failed to search the Secret Service")?;
+
+    let mut secrets = Vec::new();
+    for item in found.unlocked.iter().chain(found.locked.iter()) {
+        let item_attributes = item
+            .get_attributes()
+            .context("Failed to read Secret Service item attributes")?;
+        match parse_attributes(&item_attributes) {
+            Some((kind, account)) => secrets.push(SecretInfo {
+                target: target_name(kind, &account),
+                kind,
+                account,
+                last_written: item
+                    .get_modified()
+                    .ok()
+                    .and_then(|seconds| DateTime::<Utc>::from_timestamp(seconds as i64, 0)),
+            }),
+            None => debug!(
+                "Skipping unrecognized Secret Service item {:?}",
+                item_attributes
+            ),
+        }
+    }
+    sort_secrets(&mut secrets);
+    Ok(secrets)
+}
+
+/// [`SecretStore`](super::secret_store::SecretStore) backed by the Secret Service
+pub struct SecretServiceStore;
+
+impl super::secret_store::SecretStore for SecretServiceStore {
+    fn backend(&self) -> &'static str {
+        "secret_service"
+    }
+
+    fn store(&self, kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+        store_secret(kind, account, secret)
+    }
+
+    fn read(&self, kind: SecretKind, account: &str) -> Result<Option<String>> {
+        read_secret(kind, account)
+    }
+
+    fn delete(&self, kind: SecretKind, account: &str) -> Result<bool> {
+        delete_secret(kind, account)
+    }
+
+    fn list(&self) -> Result<Vec<SecretInfo>> {
+        list_secrets()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attributes() {
+        let stored: HashMap<String, String> =
+            attributes(Some(SecretKind::OAuthToken), Some("work"))
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        assert_eq!(
+            parse_attributes(&stored),
+            Some((SecretKind::OAuthToken, "work".to_string()))
+        );
+
+        let mut foreign = stored.clone();
+        foreign.insert("application".to_string(), "other".to_string());
+        assert_eq!(parse_attributes(&foreign), None);
+
+        let mut unknown = stored;
+        unknown.insert("kind".to_string(), "ssh_key".to_string());
+        assert_eq!(parse_attributes(&unknown), None);
+    }
+}
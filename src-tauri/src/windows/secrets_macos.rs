@@ -0,0 +1,135 @@
+//! API keys and tokens in the macOS login Keychain
+//!
+//! Offers the same functions as the Windows `secrets` module. Secrets are
+//! generic passwords with the service `opcode` and the account
+//! `opcode:<kind>:<account>`, so they are encrypted by the Keychain, listed
+//! under "opcode" in Keychain Access and readable without a prompt only by
+//! the signed Opcode app that created them.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use security_framework::base::Error as SecurityError;
+use security_framework::item::{ItemClass, ItemSearchOptions, Limit};
+use security_framework::passwords::{
+    delete_generic_password, get_generic_password, set_generic_password,
+};
+
+use super::secret_store::{
+    parse_target_name, sort_secrets, target_name, validate_account, TARGET_PREFIX,
+};
+pub use super::secret_store::{SecretInfo, SecretKind};
+
+/// `errSecItemNotFound`
+const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+fn is_not_found(error: &SecurityError) -> bool {
+    error.code() == ERR_SEC_ITEM_NOT_FOUND
+}
+
+/// Store a secret, replacing any previous value for the same kind and account
+pub fn store_secret(kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    set_generic_password(TARGET_PREFIX, &target, secret.as_bytes())
+        .with_context(|| format!("Failed to store {} in the Keychain", target))?;
+    info!("Stored {} in the Keychain", target);
+    Ok(())
+}
+
+/// Read a secret; `Ok(None)` if none is stored for the kind and account
+///
+/// macOS may ask the user to allow access when the app's signature changed
+/// since the secret was stored.
+pub fn read_secret(kind: SecretKind, account: &str) -> Result<Option<String>> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    match get_generic_password(TARGET_PREFIX, &target) {
+        Ok(secret) => String::from_utf8(secret)
+            .map(Some)
+            .with_context(|| format!("{} is not valid UTF-8", target)),
+        Err(e) if is_not_found(&e) => {
+            debug!("No {} in the Keychain", target);
+            Ok(None)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", target)),
+    }
+}
+
+/// Delete a secret, returning whether one was stored
+pub fn delete_secret(kind: SecretKind, account: &str) -> Result<bool> {
+    validate_account(account)?;
+    let target = target_name(kind, account);
+    match delete_generic_password(TARGET_PREFIX, &target) {
+        Ok(()) => {
+            info!("Deleted {} from the Keychain", target);
+            Ok(true)
+        }
+        Err(e) if is_not_found(&e) => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to delete {}", target)),
+    }
+}
+
+/// List the secrets opcode has stored, without their values
+///
+/// Sorted by kind and account. The Keychain search doesn't return dates in
+/// a usable form, so `last_written` is always `None`.
+pub fn list_secrets() -> Result<Vec<SecretInfo>> {
+    let results = match ItemSearchOptions::new()
+        .class(ItemClass::generic_password())
+        .service(TARGET_PREFIX)
+        .load_attributes(true)
+        .limit(Limit::All)
+        .search()
+    {
+        Ok(results) => results,
+        Err(e) if is_not_found(&e) => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to search the Keychain"),
+    };
+
+    let mut secrets: Vec<SecretInfo> = results
+        .iter()
+        .filter_map(|result| result.simplify_dict())
+        .filter_map(|attributes| {
+            let target = attributes.get("acct")?.clone();
+            match parse_target_name(&target) {
+                Some((kind, account)) => Some(SecretInfo {
+                    kind,
+                    account,
+                    target,
+                    last_written: None,
+                }),
+                None => {
+                    debug!("Skipping unrecognized Keychain item {}", target);
+                    None
+                }
+            }
+        })
+        .collect();
+    sort_secrets(&mut secrets);
+    Ok(secrets)
+}
+
+/// [`SecretStore`](super::secret_store::SecretStore) backed by the Keychain
+pub struct KeychainStore;
+
+impl super::secret_store::SecretStore for KeychainStore {
+    fn backend(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn store(&self, kind: SecretKind, account: &str, secret: &str) -> Result<()> {
+        store_secret(kind, account, secret)
+    }
+
+    fn read(&self, kind: SecretKind, account: &str) -> Result<Option<String>> {
+        read_secret(kind, account)
+    }
+
+    fn delete(&self, kind: SecretKind, account: &str) -> Result<bool> {
+        delete_secret(kind, account)
+    }
+
+    fn list(&self) -> Result<Vec<SecretInfo>> {
+        list_secrets()
+    }
+}
@@ -0,0 +1,383 @@
+//! Temporary files and directories only the current user can access
+//!
+//! Files created in `%TEMP%` inherit its ACL, which on many machines lets
+//! other users and services read them. [`create_secure_temp_file`] and
+//! [`create_secure_temp_dir`] instead create them with an explicit, protected
+//! DACL granting access to the current user only. Anything created inside a
+//! secure directory inherits that DACL, so staging areas can be filled with
+//! ordinary `std::fs` calls.
+//!
+//! Both return guards that delete the file or directory when dropped unless
+//! `keep` is called. [`set_owner_only_dacl`] gives an existing file or
+//! directory a similar protected DACL.
+
+use super::{to_wide_path, to_wide_string};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::fs::File;
+use std::os::windows::io::FromRawHandle;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::shared::winerror::{ERROR_ALREADY_EXISTS, ERROR_FILE_EXISTS};
+use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+use winapi::um::winnt::PSECURITY_DESCRIPTOR;
+
+/// Attempts at finding an unused name before giving up
+const MAX_NAME_ATTEMPTS: usize = 8;
+
+/// A temporary file readable only by the current user, deleted on drop
+#[derive(Debug)]
+pub struct SecureTempFile {
+    path: PathBuf,
+    file: File,
+    keep: bool,
+}
+
+impl SecureTempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The open file, for reading and writing
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    /// Keep the file after the guard is dropped, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for SecureTempFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                debug!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A temporary directory accessible only by the current user, deleted with
+/// its contents on drop
+#[derive(Debug)]
+pub struct SecureTempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl SecureTempDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the directory after the guard is dropped, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for SecureTempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            if let Err(e) = std::fs::remove_dir_all(&self.path) {
+                warn!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Security descriptor with a protected DACL granting only the current user
+/// full control, inherited by files and subdirectories
+struct OwnerOnlyDescriptor(PSECURITY_DESCRIPTOR);
+
+impl OwnerOnlyDescriptor {
+    fn new() -> Result<Self> {
+        let sid = super::permissions::current_user_sid()?;
+        Self::from_sddl(&format!("D:P(A;OICI;FA;;;{})", sid))
+    }
+
+    fn from_sddl(sddl: &str) -> Result<Self> {
+        use winapi::shared::sddl::{
+            ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+        };
+
+        let sddl = to_wide_string(sddl);
+
+        let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+        if unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1 as DWORD,
+                &mut descriptor,
+                ptr::null_mut(),
+            )
+        } == FALSE
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to build the owner-only security descriptor");
+        }
+        Ok(Self(descriptor))
+    }
+
+    fn attributes(&self) -> SECURITY_ATTRIBUTES {
+        SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+            lpSecurityDescriptor: self.0,
+            bInheritHandle: FALSE,
+        }
+    }
+}
+
+impl Drop for OwnerOnlyDescriptor {
+    fn drop(&mut self) {
+        unsafe {
+            winapi::um::winbase::LocalFree(self.0 as _);
+        }
+    }
+}
+
+fn is_already_exists(e: &std::io::Error) -> bool {
+    matches!(
+        e.raw_os_error().map(|code| code as u32),
+        Some(ERROR_FILE_EXISTS) | Some(ERROR_ALREADY_EXISTS)
+    )
+}
+
+/// Create a new file at `path` that only the current user can access
+///
+/// Fails if the file already exists. Use this instead of [`std::fs::write`]
+/// for files whose contents other users must not see, wherever they live.
+pub fn create_secure_file(path: &Path) -> Result<File> {
+    create_file(path, &OwnerOnlyDescriptor::new()?)
+        .with_context(|| format!("Failed to create {}", path.display()))
+}
+
+fn create_file(path: &Path, descriptor: &OwnerOnlyDescriptor) -> std::io::Result<File> {
+    use winapi::um::fileapi::{CreateFileW, CREATE_NEW};
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::winnt::{
+        FILE_ATTRIBUTE_TEMPORARY, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+        GENERIC_READ, GENERIC_WRITE,
+    };
+
+    let wide_path = to_wide_path(path);
+    let mut attributes = descriptor.attributes();
+    let handle = unsafe {
+        CreateFileW(
+            wide_path.as_ptr(),
+            GENERIC_READ | GENERIC_WRITE,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            &mut attributes,
+            CREATE_NEW,
+            FILE_ATTRIBUTE_TEMPORARY,
+            ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_handle(handle as _) })
+}
+
+fn create_dir(path: &Path, descriptor: &OwnerOnlyDescriptor) -> std::io::Result<()> {
+    use winapi::um::fileapi::CreateDirectoryW;
+
+    let wide_path = to_wide_path(path);
+    let mut attributes = descriptor.attributes();
+    if unsafe { CreateDirectoryW(wide_path.as_ptr(), &mut attributes) } == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// SDDL of the DACL [`set_owner_only_dacl`] writes
+///
+/// Besides the current user, SYSTEM and Administrators keep full control as
+/// they have in the user's profile folder, so backup and admin tools still
+/// work; Administrators could take ownership anyway.
+fn owner_only_sddl(user_sid: &str, is_dir: bool, readable_by_users: bool) -> String {
+    // Directories pass the ACEs on to everything created inside them
+    let inherit = if is_dir { "OICI" } else { "" };
+    let mut sddl = format!(
+        "D:P(A;{0};FA;;;SY)(A;{0};FA;;;BA)(A;{0};FA;;;{1})",
+        inherit, user_sid
+    );
+    if readable_by_users {
+        sddl.push_str(&format!("(A;{};FRFX;;;BU)", inherit));
+    }
+    sddl
+}
+
+/// Give an existing file or directory a protected DACL granting only the
+/// current user (and SYSTEM and Administrators) access
+///
+/// With `readable_by_users`, other users may also read it. The DACL no
+/// longer inherits from the parent folder; for a directory it is inherited
+/// by what is created inside it later.
+pub fn set_owner_only_dacl(path: &Path, readable_by_users: bool) -> Result<()> {
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::accctrl::SE_FILE_OBJECT;
+    use winapi::um::aclapi::SetNamedSecurityInfoW;
+    use winapi::um::securitybaseapi::GetSecurityDescriptorDacl;
+    use winapi::um::winnt::{DACL_SECURITY_INFORMATION, PACL, PROTECTED_DACL_SECURITY_INFORMATION};
+
+    let sid = super::permissions::current_user_sid()?;
+    let descriptor =
+        OwnerOnlyDescriptor::from_sddl(&owner_only_sddl(&sid, path.is_dir(), readable_by_users))?;
+
+    let mut present = FALSE;
+    let mut defaulted = FALSE;
+    let mut dacl: PACL = ptr::null_mut();
+    if unsafe { GetSecurityDescriptorDacl(descriptor.0, &mut present, &mut dacl, &mut defaulted) }
+        == FALSE
+    {
+        return Err(std::io::Error::last_os_error()).context("Failed to read the owner-only DACL");
+    }
+
+    let mut wide_path = to_wide_path(path);
+    let status = unsafe {
+        SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            dacl,
+            ptr::null_mut(),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return Err(std::io::Error::from_raw_os_error(status as i32))
+            .with_context(|| format!("Failed to set the DACL of {}", path.display()));
+    }
+    debug!("Restricted {} to the current user", path.display());
+    Ok(())
+}
+
+/// Unused path in the temp directory for an artifact named after `prefix`
+fn temp_path(prefix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "opcode-{}-{}",
+        prefix,
+        uuid::Uuid::new_v4().simple()
+    ))
+}
+
+/// Create a temporary file in `%TEMP%` readable only by the current user
+///
+/// `prefix` becomes part of the file name, e.g. `"export"`.
+pub fn create_secure_temp_file(prefix: &str) -> Result<SecureTempFile> {
+    let descriptor = OwnerOnlyDescriptor::new()?;
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = temp_path(prefix);
+        match create_file(&path, &descriptor) {
+            Ok(file) => {
+                debug!("Created secure temp file {}", path.display());
+                return Ok(SecureTempFile {
+                    path,
+                    file,
+                    keep: false,
+                });
+            }
+            Err(e) if is_already_exists(&e) => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp file {}", path.display()))
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Failed to find an unused temp file name"))
+}
+
+/// Create a temporary directory in `%TEMP%` accessible only by the current user
+///
+/// Files and directories created inside it inherit its DACL.
+pub fn create_secure_temp_dir(prefix: &str) -> Result<SecureTempDir> {
+    let descriptor = OwnerOnlyDescriptor::new()?;
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = temp_path(prefix);
+        match create_dir(&path, &descriptor) {
+            Ok(()) => {
+                debug!("Created secure temp directory {}", path.display());
+                return Ok(SecureTempDir { path, keep: false });
+            }
+            Err(e) if is_already_exists(&e) => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp directory {}", path.display()))
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Failed to find an unused temp directory name"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_path() {
+        let first = temp_path("export");
+        let name = first.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("opcode-export-"));
+        assert_ne!(first, temp_path("export"));
+    }
+
+    #[test]
+    fn test_owner_only_sddl() {
+        assert_eq!(
+            owner_only_sddl("S-1-5-21-1-2-3-1001", false, false),
+            "D:P(A;;FA;;;SY)(A;;FA;;;BA)(A;;FA;;;S-1-5-21-1-2-3-1001)"
+        );
+        assert_eq!(
+            owner_only_sddl("S-1-5-21-1-2-3-1001", true, true),
+            "D:P(A;OICI;FA;;;SY)(A;OICI;FA;;;BA)(A;OICI;FA;;;S-1-5-21-1-2-3-1001)(A;OICI;FRFX;;;BU)"
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_secure_temp_file_acl() {
+        use super::super::permissions::{current_user_sid, get_file_acl};
+        use std::io::Write;
+
+        let temp = create_secure_temp_file("test").unwrap();
+        temp.as_file().write_all(b"secret").unwrap();
+
+        let sid = current_user_sid().unwrap();
+        let entries = get_file_acl(&temp.path().to_string_lossy()).unwrap();
+        assert!(!entries.is_empty());
+        assert!(entries
+            .iter()
+            .all(|entry| entry.sid == sid && !entry.inherited));
+
+        let path = temp.path().to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_secure_temp_dir_inherits_acl() {
+        use super::super::permissions::{current_user_sid, get_file_acl};
+
+        let dir = create_secure_temp_dir("staging").unwrap();
+        let staged = dir.path().join("agent.json");
+        std::fs::write(&staged, "{}").unwrap();
+
+        let sid = current_user_sid().unwrap();
+        let entries = get_file_acl(&staged.to_string_lossy()).unwrap();
+        assert!(entries.iter().all(|entry| entry.sid == sid));
+
+        let kept = dir.keep();
+        assert!(kept.exists());
+        std::fs::remove_dir_all(kept).unwrap();
+    }
+}
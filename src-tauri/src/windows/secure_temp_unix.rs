@@ -0,0 +1,188 @@
+//! Temporary files and directories only the current user can access on Unix
+//!
+//! Offers the same functions as the Windows `secure_temp` module. Instead of
+//! a DACL, files are created with mode `0600` and directories with `0700`,
+//! whatever the umask, so other users can't read what is staged in `/tmp`.
+//!
+//! Both guards delete the file or directory when dropped unless `keep` is
+//! called.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+/// Attempts at finding an unused name before giving up
+const MAX_NAME_ATTEMPTS: usize = 8;
+
+/// A temporary file readable only by the current user, deleted on drop
+#[derive(Debug)]
+pub struct SecureTempFile {
+    path: PathBuf,
+    file: File,
+    keep: bool,
+}
+
+impl SecureTempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The open file, for reading and writing
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    /// Keep the file after the guard is dropped, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for SecureTempFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                debug!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A temporary directory accessible only by the current user, deleted with
+/// its contents on drop
+#[derive(Debug)]
+pub struct SecureTempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl SecureTempDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the directory after the guard is dropped, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for SecureTempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            if let Err(e) = std::fs::remove_dir_all(&self.path) {
+                warn!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn create_file(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+fn create_dir(path: &Path) -> std::io::Result<()> {
+    DirBuilder::new().mode(0o700).create(path)
+}
+
+/// Create a new file at `path` that only the current user can access
+///
+/// Fails if the file already exists. Use this instead of [`std::fs::write`]
+/// for files whose contents other users must not see, wherever they live.
+pub fn create_secure_file(path: &Path) -> Result<File> {
+    create_file(path).with_context(|| format!("Failed to create {}", path.display()))
+}
+
+/// Unused path in the temp directory for an artifact named after `prefix`
+fn temp_path(prefix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "opcode-{}-{}",
+        prefix,
+        uuid::Uuid::new_v4().simple()
+    ))
+}
+
+/// Create a temporary file in the temp directory readable only by the
+/// current user
+///
+/// `prefix` becomes part of the file name, e.g. `"export"`.
+pub fn create_secure_temp_file(prefix: &str) -> Result<SecureTempFile> {
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = temp_path(prefix);
+        match create_file(&path) {
+            Ok(file) => {
+                debug!("Created secure temp file {}", path.display());
+                return Ok(SecureTempFile {
+                    path,
+                    file,
+                    keep: false,
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp file {}", path.display()))
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Failed to find an unused temp file name"))
+}
+
+/// Create a temporary directory in the temp directory accessible only by
+/// the current user
+pub fn create_secure_temp_dir(prefix: &str) -> Result<SecureTempDir> {
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = temp_path(prefix);
+        match create_dir(&path) {
+            Ok(()) => {
+                debug!("Created secure temp directory {}", path.display());
+                return Ok(SecureTempDir { path, keep: false });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp directory {}", path.display()))
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Failed to find an unused temp directory name"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_secure_temp_file_mode() {
+        let temp = create_secure_temp_file("test").unwrap();
+        let mode = temp.as_file().metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let path = temp.path().to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_secure_temp_dir_mode() {
+        let dir = create_secure_temp_dir("staging").unwrap();
+        let mode = std::fs::metadata(dir.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let kept = dir.keep();
+        assert!(kept.exists());
+        std::fs::remove_dir_all(kept).unwrap();
+    }
+}
@@ -0,0 +1,473 @@
+//! Headless agent runner as a Windows service
+//!
+//! Scheduled agents have to run while the app is closed. [`install_service`]
+//! registers the Opcode executable, started with [`AGENT_RUNNER_ARG`], as a
+//! per-user service template. Windows starts an instance of it in each
+//! user's logon session, running as that user, so agents see the same
+//! `~/.claude`, projects and stored credentials as in the app and no
+//! password has to be stored. Like the systemd and launchd versions, the
+//! runner only runs while the user is logged in.
+//!
+//! Installing and removing the template needs administrator rights; the app
+//! runs [`run_service_helper_from_args`] elevated for that. In runner mode
+//! the executable registers with the service control dispatcher through
+//! [`run_as_service`], or the SCM stops it after 30 seconds.
+
+use super::to_wide_string;
+use anyhow::{Context, Result};
+use log::{error, info};
+use serde::Serialize;
+use std::ffi::c_void;
+use std::path::Path;
+use std::ptr;
+use std::sync::OnceLock;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, ERROR_ACCESS_DENIED, ERROR_CALL_NOT_IMPLEMENTED,
+    ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, ERROR_SERVICE_ALREADY_RUNNING,
+    ERROR_SERVICE_DOES_NOT_EXIST, ERROR_SERVICE_EXISTS, ERROR_SERVICE_NOT_ACTIVE,
+    ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR,
+};
+use windows_sys::Win32::Security::{
+    GetTokenInformation, TokenStatistics, TOKEN_QUERY, TOKEN_STATISTICS,
+};
+use windows_sys::Win32::Storage::FileSystem::DELETE;
+use windows_sys::Win32::System::Services::{
+    ChangeServiceConfig2W, CloseServiceHandle, ControlService, CreateServiceW, DeleteService,
+    OpenSCManagerW, OpenServiceW, QueryServiceStatus, RegisterServiceCtrlHandlerExW,
+    SetServiceStatus, StartServiceCtrlDispatcherW, StartServiceW, SC_HANDLE, SC_MANAGER_CONNECT,
+    SC_MANAGER_CREATE_SERVICE, SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_ALL_ACCESS,
+    SERVICE_AUTO_START, SERVICE_CONFIG_DESCRIPTION, SERVICE_CONTROL_INTERROGATE,
+    SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_DESCRIPTIONW, SERVICE_ERROR_NORMAL,
+    SERVICE_QUERY_STATUS, SERVICE_RUNNING, SERVICE_START, SERVICE_START_PENDING, SERVICE_STATUS,
+    SERVICE_STATUS_HANDLE, SERVICE_STOP, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+    SERVICE_TABLE_ENTRYW, SERVICE_USER_OWN_PROCESS,
+};
+use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Argument that starts the executable as the headless agent runner
+pub const AGENT_RUNNER_ARG: &str = "--agent-runner";
+
+/// Argument of the elevated helper that installs the service template
+pub const INSTALL_SERVICE_ARG: &str = "--install-agent-runner";
+
+/// Argument of the elevated helper that removes the service template
+pub const UNINSTALL_SERVICE_ARG: &str = "--uninstall-agent-runner";
+
+/// Name of the service in the Service Control Manager
+pub const SERVICE_NAME: &str = "OpcodeAgentRunner";
+
+const SERVICE_DISPLAY_NAME: &str = "Opcode Agent Runner";
+const SERVICE_DESCRIPTION: &str = "Runs scheduled Opcode agents while the app is closed.";
+
+/// State of the agent runner service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceStatus {
+    NotInstalled,
+    Stopped,
+    Running,
+}
+
+/// A Service Control Manager handle, closed on drop
+struct ServiceHandle(SC_HANDLE);
+
+impl Drop for ServiceHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseServiceHandle(self.0);
+        }
+    }
+}
+
+/// The last error, explaining that administrator rights are missing when
+/// that is the cause
+fn scm_error(action: &str) -> anyhow::Error {
+    let error = std::io::Error::last_os_error();
+    if error.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+        anyhow::anyhow!("Failed to {}: administrator rights are required", action)
+    } else {
+        anyhow::Error::new(error).context(format!("Failed to {}", action))
+    }
+}
+
+fn open_manager(access: u32) -> Result<ServiceHandle> {
+    let handle = unsafe { OpenSCManagerW(ptr::null(), ptr::null(), access) };
+    if handle.is_null() {
+        return Err(scm_error("open the Service Control Manager"));
+    }
+    Ok(ServiceHandle(handle))
+}
+
+/// The service `name`, or `None` if it isn't installed
+fn open_service(manager: &ServiceHandle, name: &str, access: u32) -> Result<Option<ServiceHandle>> {
+    let wide_name = to_wide_string(name);
+    let handle = unsafe { OpenServiceW(manager.0, wide_name.as_ptr(), access) };
+    if handle.is_null() {
+        if std::io::Error::last_os_error().raw_os_error()
+            == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32)
+        {
+            return Ok(None);
+        }
+        return Err(scm_error(&format!("open the {} service", name)));
+    }
+    Ok(Some(ServiceHandle(handle)))
+}
+
+/// Name of the service instance in the current user's logon session
+///
+/// Windows names per-user instances after the template and the logon
+/// session's LUID. An elevated process runs in a logon session of its own,
+/// so this only finds the instance from the user's unelevated token.
+fn user_instance_name() -> Result<String> {
+    unsafe {
+        let mut token = ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to open the process token");
+        }
+        let mut statistics: TOKEN_STATISTICS = std::mem::zeroed();
+        let mut size = 0;
+        let ok = GetTokenInformation(
+            token,
+            TokenStatistics,
+            &mut statistics as *mut _ as *mut c_void,
+            std::mem::size_of::<TOKEN_STATISTICS>() as u32,
+            &mut size,
+        );
+        CloseHandle(token);
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to query the logon session");
+        }
+        Ok(format!(
+            "{}_{:x}",
+            SERVICE_NAME, statistics.AuthenticationId.LowPart
+        ))
+    }
+}
+
+/// Command line the SCM starts the service with
+fn service_command_line(executable_path: &str) -> String {
+    format!("\"{}\" {}", executable_path, AGENT_RUNNER_ARG)
+}
+
+/// Install the agent runner as an auto-start per-user service template
+///
+/// # Arguments
+/// * `executable_path` - The Opcode executable; it should live where only
+///   administrators can change it, such as Program Files
+///
+/// Needs administrator rights. Fails if the service is already installed;
+/// uninstall it first to change the executable. Users get their instance of
+/// the service the next time they log on.
+pub fn install_service(executable_path: &str) -> Result<()> {
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for the agent runner: {}",
+            executable_path
+        );
+    }
+
+    let manager = open_manager(SC_MANAGER_CONNECT | SC_MANAGER_CREATE_SERVICE)?;
+    let name = to_wide_string(SERVICE_NAME);
+    let display_name = to_wide_string(SERVICE_DISPLAY_NAME);
+    let command_line = to_wide_string(&service_command_line(executable_path));
+
+    // Instances of a user service template run as the logged-on user, so
+    // the template has no account or password of its own
+    let handle = unsafe {
+        CreateServiceW(
+            manager.0,
+            name.as_ptr(),
+            display_name.as_ptr(),
+            SERVICE_ALL_ACCESS,
+            SERVICE_USER_OWN_PROCESS,
+            SERVICE_AUTO_START,
+            SERVICE_ERROR_NORMAL,
+            command_line.as_ptr(),
+            ptr::null(),
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+
+    if handle.is_null() {
+        if std::io::Error::last_os_error().raw_os_error() == Some(ERROR_SERVICE_EXISTS as i32) {
+            anyhow::bail!("The {} service is already installed", SERVICE_NAME);
+        }
+        return Err(scm_error(&format!("create the {} service", SERVICE_NAME)));
+    }
+    let service = ServiceHandle(handle);
+
+    let mut description = to_wide_string(SERVICE_DESCRIPTION);
+    let mut info = SERVICE_DESCRIPTIONW {
+        lpDescription: description.as_mut_ptr(),
+    };
+    unsafe {
+        ChangeServiceConfig2W(
+            service.0,
+            SERVICE_CONFIG_DESCRIPTION,
+            &mut info as *mut _ as *const _,
+        );
+    }
+
+    info!("Installed the {} service template", SERVICE_NAME);
+    Ok(())
+}
+
+/// Stop and remove the agent runner service, returning whether it was installed
+///
+/// Needs administrator rights. Windows removes the template, and the users'
+/// instances with it, once every handle to it is closed.
+pub fn uninstall_service() -> Result<bool> {
+    let manager = open_manager(SC_MANAGER_CONNECT)?;
+    let Some(service) = open_service(
+        &manager,
+        SERVICE_NAME,
+        SERVICE_STOP | SERVICE_QUERY_STATUS | DELETE,
+    )?
+    else {
+        return Ok(false);
+    };
+
+    unsafe {
+        let mut status: SERVICE_STATUS = std::mem::zeroed();
+        if ControlService(service.0, SERVICE_CONTROL_STOP, &mut status) == 0
+            && std::io::Error::last_os_error().raw_os_error()
+                != Some(ERROR_SERVICE_NOT_ACTIVE as i32)
+        {
+            return Err(scm_error(&format!("stop the {} service", SERVICE_NAME)));
+        }
+        if DeleteService(service.0) == 0 {
+            return Err(scm_error(&format!("delete the {} service", SERVICE_NAME)));
+        }
+    }
+
+    info!("Uninstalled the {} service", SERVICE_NAME);
+    Ok(true)
+}
+
+/// Start the current user's instance of the agent runner service
+///
+/// Succeeds when it is already running.
+pub fn start_service() -> Result<()> {
+    let manager = open_manager(SC_MANAGER_CONNECT)?;
+    if open_service(&manager, SERVICE_NAME, SERVICE_QUERY_STATUS)?.is_none() {
+        anyhow::bail!("The {} service is not installed", SERVICE_NAME);
+    }
+    let service = open_service(&manager, &user_instance_name()?, SERVICE_START)?
+        .context("The agent runner becomes available after signing out and back in")?;
+
+    if unsafe { StartServiceW(service.0, 0, ptr::null()) } == 0 {
+        if std::io::Error::last_os_error().raw_os_error()
+            == Some(ERROR_SERVICE_ALREADY_RUNNING as i32)
+        {
+            return Ok(());
+        }
+        return Err(scm_error(&format!("start the {} service", SERVICE_NAME)));
+    }
+    info!("Started the {} service", SERVICE_NAME);
+    Ok(())
+}
+
+/// Whether the agent runner service is installed and running for the current user
+pub fn service_status() -> Result<ServiceStatus> {
+    let manager = open_manager(SC_MANAGER_CONNECT)?;
+    if open_service(&manager, SERVICE_NAME, SERVICE_QUERY_STATUS)?.is_none() {
+        return Ok(ServiceStatus::NotInstalled);
+    }
+    // Installed during this logon: the instance appears at the next one
+    let Some(service) = open_service(&manager, &user_instance_name()?, SERVICE_QUERY_STATUS)?
+    else {
+        return Ok(ServiceStatus::Stopped);
+    };
+
+    let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { QueryServiceStatus(service.0, &mut status) } == 0 {
+        return Err(scm_error(&format!("query the {} service", SERVICE_NAME)));
+    }
+    Ok(match status.dwCurrentState {
+        SERVICE_RUNNING | SERVICE_START_PENDING => ServiceStatus::Running,
+        _ => ServiceStatus::Stopped,
+    })
+}
+
+/// Install or uninstall the service if the arguments ask for it
+///
+/// The app starts itself elevated with [`INSTALL_SERVICE_ARG`] or
+/// [`UNINSTALL_SERVICE_ARG`]. Returns the helper's exit code, or `None` when
+/// the arguments aren't for the helper. The service is always installed for
+/// the executable running the helper, never for a path from the arguments.
+/// The outcome is also reported to the opcode that started the helper with
+/// [`run_elevated_with_result`](super::elevation_broker::run_elevated_with_result).
+pub fn run_service_helper_from_args(args: &[String]) -> Option<i32> {
+    use super::elevation_broker::{report_elevation_result, ElevationReport};
+
+    let result = match args.get(1).map(String::as_str) {
+        Some(INSTALL_SERVICE_ARG) => std::env::current_exe()
+            .context("Failed to locate the opcode executable")
+            .and_then(|exe| install_service(&exe.to_string_lossy())),
+        Some(UNINSTALL_SERVICE_ARG) => uninstall_service().map(|_| ()),
+        _ => return None,
+    };
+
+    let report = ElevationReport {
+        success: result.is_ok(),
+        message: result.as_ref().err().map(|e| format!("{:#}", e)),
+        details: None,
+    };
+    if let Err(e) = report_elevation_result(args, &report) {
+        error!("Failed to report the service helper's result: {:#}", e);
+    }
+
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Agent runner service helper failed: {:#}", e);
+            1
+        }
+    })
+}
+
+/// The runner's body and how to stop it, for the dispatcher's thread
+struct ServiceCallbacks {
+    run: fn() -> i32,
+    stop: fn(),
+}
+
+static CALLBACKS: OnceLock<ServiceCallbacks> = OnceLock::new();
+
+/// Handle the service reports its state through, as an address
+static STATUS_HANDLE: OnceLock<usize> = OnceLock::new();
+
+/// Run the agent runner under the Service Control Manager
+///
+/// Blocks while `run` serves the service on a thread of the dispatcher.
+/// `stop` is called when the SCM stops the service and must make `run`
+/// return. Returns `Ok(false)` without calling `run` when the process wasn't
+/// started by the SCM, e.g. from a console.
+pub fn run_as_service(run: fn() -> i32, stop: fn()) -> Result<bool> {
+    let _ = CALLBACKS.set(ServiceCallbacks { run, stop });
+
+    let mut name = to_wide_string(SERVICE_NAME);
+    let table = [
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: name.as_mut_ptr(),
+            lpServiceProc: Some(service_main),
+        },
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: ptr::null_mut(),
+            lpServiceProc: None,
+        },
+    ];
+
+    if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) {
+            return Ok(false);
+        }
+        return Err(error).context("Failed to connect to the Service Control Manager");
+    }
+    Ok(true)
+}
+
+unsafe extern "system" fn service_main(_argc: u32, argv: *mut *mut u16) {
+    // The SCM passes the instance's name first
+    let handle = RegisterServiceCtrlHandlerExW(*argv, Some(control_handler), ptr::null());
+    if handle.is_null() {
+        error!(
+            "Failed to register the service control handler: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    let _ = STATUS_HANDLE.set(handle as usize);
+
+    set_service_status(SERVICE_RUNNING, 0);
+    info!("Agent runner service started");
+    let exit_code = CALLBACKS.get().map_or(1, |callbacks| (callbacks.run)());
+    set_service_status(SERVICE_STOPPED, exit_code);
+}
+
+unsafe extern "system" fn control_handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            info!("Agent runner service is stopping");
+            set_service_status(SERVICE_STOP_PENDING, 0);
+            if let Some(callbacks) = CALLBACKS.get() {
+                (callbacks.stop)();
+            }
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+/// Report the service's state, and `exit_code` once it has stopped
+fn set_service_status(state: u32, exit_code: i32) {
+    let Some(&handle) = STATUS_HANDLE.get() else {
+        return;
+    };
+    let mut status = SERVICE_STATUS {
+        dwServiceType: SERVICE_USER_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: if state == SERVICE_RUNNING {
+            SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+        } else {
+            0
+        },
+        dwWin32ExitCode: if exit_code == 0 {
+            NO_ERROR
+        } else {
+            ERROR_SERVICE_SPECIFIC_ERROR
+        },
+        dwServiceSpecificExitCode: exit_code as u32,
+        dwCheckPoint: 0,
+        dwWaitHint: if state == SERVICE_STOP_PENDING {
+            30_000
+        } else {
+            0
+        },
+    };
+    unsafe {
+        SetServiceStatus(handle as SERVICE_STATUS_HANDLE, &mut status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_command_line() {
+        assert_eq!(
+            service_command_line(r"C:\Program Files\Opcode\opcode.exe"),
+            r#""C:\Program Files\Opcode\opcode.exe" --agent-runner"#
+        );
+    }
+
+    #[test]
+    fn test_run_service_helper_from_args() {
+        let args = |values: &[&str]| values.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(run_service_helper_from_args(&args(&["opcode.exe"])), None);
+        assert_eq!(
+            run_service_helper_from_args(&args(&["opcode.exe", AGENT_RUNNER_ARG])),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_service_status() {
+        // Reading the status needs no administrator rights
+        assert!(service_status().is_ok());
+    }
+}
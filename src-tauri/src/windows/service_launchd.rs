@@ -0,0 +1,198 @@
+//! Headless agent runner as a launchd job
+//!
+//! Offers the same functions as the Windows `service` module. The job is a
+//! per-user agent in `~/Library/LaunchAgents` rather than a system daemon in
+//! `/Library/LaunchDaemons`, so it needs no administrator rights and agents
+//! run as the user, with access to their files and Keychain. Unlike the
+//! start-at-login agent it runs in the `Background` session type, without
+//! the GUI, and launchd restarts it if it crashes.
+
+use super::launch_agent::xml_escape;
+use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
+use crate::utils::atomic_write::atomic_write;
+use anyhow::{Context, Result};
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Argument that starts the executable as the headless agent runner
+pub const AGENT_RUNNER_ARG: &str = "--agent-runner";
+
+/// launchd label of the job
+pub const SERVICE_NAME: &str = "opcode.asterisk.so.agent-runner";
+
+/// State of the agent runner service, matching the Windows `ServiceStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceStatus {
+    NotInstalled,
+    Stopped,
+    Running,
+}
+
+/// Where the job plist lives
+pub fn job_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not find home directory")?;
+    Ok(home
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", SERVICE_NAME)))
+}
+
+/// Contents of the plist that runs `executable_path` as the agent runner
+pub fn job_plist(executable_path: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>{}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ProcessType</key>
+    <string>Background</string>
+    <key>LimitLoadToSessionType</key>
+    <array>
+        <string>Aqua</string>
+        <string>Background</string>
+    </array>
+</dict>
+</plist>
+"#,
+        xml_escape(SERVICE_NAME),
+        xml_escape(executable_path),
+        AGENT_RUNNER_ARG
+    )
+}
+
+/// launchd domain of the current user's GUI session
+fn user_domain() -> String {
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+/// Run `launchctl` with `args`
+fn launchctl(args: &[&str]) -> Result<std::process::Output> {
+    run_with_timeout_blocking(
+        std::process::Command::new("/bin/launchctl").args(args),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to run launchctl")
+}
+
+/// Run `launchctl` with `args`, failing on a non-zero exit
+fn launchctl_checked(args: &[&str]) -> Result<()> {
+    let output = launchctl(args)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "launchctl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Install and load the agent runner job, which launchd then starts at login
+///
+/// Replaces an existing job, so this also updates the executable path.
+pub fn install_service(executable_path: &str) -> Result<()> {
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for the agent runner: {}",
+            executable_path
+        );
+    }
+
+    let path = job_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Unload the old job first; fails harmlessly when none is loaded
+    let target = format!("{}/{}", user_domain(), SERVICE_NAME);
+    launchctl(&["bootout", &target])?;
+
+    atomic_write(&path, job_plist(executable_path))?;
+    let path_arg = path.to_string_lossy();
+    launchctl_checked(&["bootstrap", &user_domain(), &path_arg])?;
+    info!("Installed launchd job {}", path.display());
+    Ok(())
+}
+
+/// Unload and remove the agent runner job, returning whether it was installed
+pub fn uninstall_service() -> Result<bool> {
+    let path = job_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    // Stops the runner; fails harmlessly when the job isn't loaded
+    launchctl(&["bootout", &format!("{}/{}", user_domain(), SERVICE_NAME)])?;
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    info!("Removed launchd job {}", path.display());
+    Ok(true)
+}
+
+/// Start the installed agent runner job
+///
+/// Succeeds when it is already running.
+pub fn start_service() -> Result<()> {
+    let path = job_path()?;
+    if !path.exists() {
+        anyhow::bail!("The {} job is not installed", SERVICE_NAME);
+    }
+    let target = format!("{}/{}", user_domain(), SERVICE_NAME);
+    if !launchctl(&["print", &target])?.status.success() {
+        launchctl_checked(&["bootstrap", &user_domain(), &path.to_string_lossy()])?;
+    }
+    launchctl_checked(&["kickstart", &target])?;
+    info!("Started {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Whether the agent runner job is installed and running
+pub fn service_status() -> Result<ServiceStatus> {
+    if !job_path()?.exists() {
+        return Ok(ServiceStatus::NotInstalled);
+    }
+    let output = launchctl(&["print", &format!("{}/{}", user_domain(), SERVICE_NAME)])?;
+    if !output.status.success() {
+        return Ok(ServiceStatus::Stopped);
+    }
+    Ok(
+        if String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.trim() == "state = running")
+        {
+            ServiceStatus::Running
+        } else {
+            ServiceStatus::Stopped
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_plist() {
+        let plist = job_plist("/Applications/A & B.app/Contents/MacOS/opcode");
+        assert!(plist.contains("<string>opcode.asterisk.so.agent-runner</string>"));
+        assert!(plist.contains(
+            "<string>/Applications/A &amp; B.app/Contents/MacOS/opcode</string>\n        <string>--agent-runner</string>"
+        ));
+        assert!(plist.contains("<string>Background</string>"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Headless agent runner as a systemd user unit
+//!
+//! Offers the same functions as the Windows `service` module. The unit runs
+//! the Opcode executable with [`AGENT_RUNNER_ARG`] in the user's own systemd
+//! instance, so it needs no root and agents see the user's files. A user
+//! instance only runs while the user is logged in, unless lingering is
+//! enabled with `loginctl enable-linger`; [`install_service`] doesn't change
+//! that setting.
+
+use crate::process::command::{run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT};
+use crate::utils::atomic_write::atomic_write;
+use anyhow::{Context, Result};
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Argument that starts the executable as the headless agent runner
+pub const AGENT_RUNNER_ARG: &str = "--agent-runner";
+
+/// Name of the systemd unit
+pub const SERVICE_NAME: &str = "opcode-agent-runner.service";
+
+/// State of the agent runner service, matching the Windows `ServiceStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceStatus {
+    NotInstalled,
+    Stopped,
+    Running,
+}
+
+/// Where the unit file lives
+///
+/// Follows `$XDG_CONFIG_HOME`, which defaults to `~/.config`.
+pub fn unit_path() -> Result<PathBuf> {
+    let config = dirs::config_dir().context("Could not find config directory")?;
+    Ok(config.join("systemd").join("user").join(SERVICE_NAME))
+}
+
+/// Quote one word of an `ExecStart` command line
+///
+/// Words with spaces or quotes are double-quoted with C-style escapes, and
+/// `%` is doubled so systemd doesn't read it as a specifier.
+fn quote_unit_arg(arg: &str) -> String {
+    let quoted = if arg.is_empty() || arg.contains([' ', '\t', '"', '\'', '\\', ';']) {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            if matches!(c, '"' | '\\') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        arg.to_string()
+    };
+    quoted.replace('%', "%%")
+}
+
+/// Contents of the unit that runs `executable_path` as the agent runner
+pub fn unit_file(executable_path: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Opcode agent runner\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={} {}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        quote_unit_arg(executable_path),
+        AGENT_RUNNER_ARG
+    )
+}
+
+/// Run `systemctl --user` with `args`, failing on a non-zero exit
+fn systemctl(args: &[&str]) -> Result<std::process::Output> {
+    let output = run_with_timeout_blocking(
+        std::process::Command::new("systemctl")
+            .arg("--user")
+            .args(args),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to run systemctl; is systemd running?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
+
+/// Install the agent runner as a user unit that starts with the user's session
+///
+/// Replaces an existing unit, so this also updates the executable path.
+pub fn install_service(executable_path: &str) -> Result<()> {
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for the agent runner: {}",
+            executable_path
+        );
+    }
+
+    let path = unit_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    atomic_write(&path, unit_file(executable_path))?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", SERVICE_NAME])?;
+    info!("Installed systemd user unit {}", path.display());
+    Ok(())
+}
+
+/// Stop and remove the agent runner unit, returning whether it was installed
+pub fn uninstall_service() -> Result<bool> {
+    let path = unit_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    systemctl(&["disable", "--now", SERVICE_NAME])?;
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    systemctl(&["daemon-reload"])?;
+    info!("Removed systemd user unit {}", path.display());
+    Ok(true)
+}
+
+/// Start the installed agent runner unit
+///
+/// Succeeds when it is already running.
+pub fn start_service() -> Result<()> {
+    if !unit_path()?.exists() {
+        anyhow::bail!("The {} unit is not installed", SERVICE_NAME);
+    }
+    systemctl(&["start", SERVICE_NAME])?;
+    info!("Started {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Whether the agent runner unit is installed and running
+pub fn service_status() -> Result<ServiceStatus> {
+    if !unit_path()?.exists() {
+        return Ok(ServiceStatus::NotInstalled);
+    }
+    // `is-active` exits non-zero for anything but active units
+    let output = run_with_timeout_blocking(
+        std::process::Command::new("systemctl").args(["--user", "is-active", SERVICE_NAME]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to run systemctl; is systemd running?")?;
+    Ok(match String::from_utf8_lossy(&output.stdout).trim() {
+        "active" | "activating" | "reloading" => ServiceStatus::Running,
+        _ => ServiceStatus::Stopped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_unit_arg() {
+        assert_eq!(quote_unit_arg("/usr/bin/opcode"), "/usr/bin/opcode");
+        assert_eq!(
+            quote_unit_arg("/opt/My Apps/opcode"),
+            "\"/opt/My Apps/opcode\""
+        );
+        assert_eq!(quote_unit_arg("/opt/a\"b"), "\"/opt/a\\\"b\"");
+        assert_eq!(quote_unit_arg("/opt/100%/opcode"), "/opt/100%%/opcode");
+    }
+
+    #[test]
+    fn test_unit_file() {
+        let unit = unit_file("/opt/My Apps/opcode");
+        assert!(unit.contains("ExecStart=\"/opt/My Apps/opcode\" --agent-runner\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+    }
+}
@@ -0,0 +1,163 @@
+//! Auto-start for packaged (MSIX) installs
+//!
+//! Packaged apps cannot start from the `Run` registry key; Windows ignores
+//! entries pointing into the package's install directory. They declare a
+//! `windows.startupTask` extension in the package manifest instead, which is
+//! toggled through the WinRT `StartupTask` API. [`is_packaged`] decides at
+//! runtime which mechanism the auto-start functions in the registry module use.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::Serialize;
+use windows::ApplicationModel::{StartupTask, StartupTaskState as WinStartupTaskState};
+use windows::Win32::Foundation::{APPMODEL_ERROR_NO_PACKAGE, ERROR_INSUFFICIENT_BUFFER};
+use windows::Win32::Storage::Packaging::Appx::GetCurrentPackageFullName;
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+
+/// `TaskId` of the `uap5:StartupTask` declared in the MSIX manifest
+pub const STARTUP_TASK_ID: &str = "OpcodeStartup";
+
+/// State of the packaged startup task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupTaskState {
+    Disabled,
+    /// Turned off in Task Manager or Settings; only the user can turn it back on
+    DisabledByUser,
+    DisabledByPolicy,
+    Enabled,
+    EnabledByPolicy,
+}
+
+impl StartupTaskState {
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self,
+            StartupTaskState::Enabled | StartupTaskState::EnabledByPolicy
+        )
+    }
+
+    fn from_winrt(state: WinStartupTaskState) -> Self {
+        match state {
+            WinStartupTaskState::DisabledByUser => StartupTaskState::DisabledByUser,
+            WinStartupTaskState::DisabledByPolicy => StartupTaskState::DisabledByPolicy,
+            WinStartupTaskState::Enabled => StartupTaskState::Enabled,
+            WinStartupTaskState::EnabledByPolicy => StartupTaskState::EnabledByPolicy,
+            _ => StartupTaskState::Disabled,
+        }
+    }
+}
+
+/// Whether the running executable belongs to an MSIX package
+pub fn is_packaged() -> bool {
+    let mut length = 0u32;
+    let result = unsafe { GetCurrentPackageFullName(&mut length, None) };
+
+    // With a zero-length buffer a packaged process reports the required size
+    match result {
+        ERROR_INSUFFICIENT_BUFFER => true,
+        APPMODEL_ERROR_NO_PACKAGE => false,
+        other => {
+            debug!(
+                "GetCurrentPackageFullName returned {:?}, assuming unpackaged",
+                other
+            );
+            false
+        }
+    }
+}
+
+/// Get the current state of the packaged startup task
+pub fn get_startup_task_state(task_id: &str) -> Result<StartupTaskState> {
+    let task_id = task_id.to_string();
+    in_multithreaded_apartment(move || {
+        let task = find_startup_task(&task_id)?;
+        let state = task.State().context("Failed to read startup task state")?;
+        Ok(StartupTaskState::from_winrt(state))
+    })
+}
+
+/// Enable or disable the packaged startup task
+///
+/// Enabling fails when the user turned the task off in Task Manager or a
+/// policy forbids it, since apps are not allowed to override either.
+pub fn set_startup_task_enabled(task_id: &str, enabled: bool) -> Result<StartupTaskState> {
+    let task_id = task_id.to_string();
+    let state = in_multithreaded_apartment(move || {
+        let task = find_startup_task(&task_id)?;
+        if enabled {
+            let state = task
+                .RequestEnableAsync()
+                .and_then(|operation| operation.get())
+                .context("Failed to enable startup task")?;
+            Ok(StartupTaskState::from_winrt(state))
+        } else {
+            task.Disable().context("Failed to disable startup task")?;
+            Ok(StartupTaskState::Disabled)
+        }
+    })?;
+
+    match state {
+        StartupTaskState::DisabledByUser if enabled => Err(anyhow::anyhow!(
+            "Auto-start was turned off in Task Manager; turn it back on there to enable it"
+        )),
+        StartupTaskState::DisabledByPolicy if enabled => Err(anyhow::anyhow!(
+            "Auto-start is disabled by your organization's policy"
+        )),
+        _ => {
+            info!("Startup task {:?}", state);
+            Ok(state)
+        }
+    }
+}
+
+fn find_startup_task(task_id: &str) -> Result<StartupTask> {
+    StartupTask::GetAsync(&task_id.into())
+        .and_then(|operation| operation.get())
+        .with_context(|| {
+            format!(
+                "Startup task {} is not declared in the package manifest",
+                task_id
+            )
+        })
+}
+
+/// Run WinRT calls on a worker thread in the multithreaded apartment
+///
+/// Blocking on an async operation from the UI thread's single-threaded
+/// apartment can deadlock, so calls are made from a dedicated MTA thread.
+pub(crate) fn in_multithreaded_apartment<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    std::thread::spawn(move || {
+        let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        let result = f();
+        if initialized {
+            unsafe { CoUninitialize() };
+        }
+        result
+    })
+    .join()
+    .map_err(|_| anyhow::anyhow!("WinRT worker thread panicked"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_packaged() {
+        // Tests run from cargo, never from an MSIX package
+        assert!(!is_packaged());
+    }
+
+    #[test]
+    fn test_startup_task_state() {
+        assert!(StartupTaskState::from_winrt(WinStartupTaskState::EnabledByPolicy).is_enabled());
+        assert!(!StartupTaskState::from_winrt(WinStartupTaskState::DisabledByUser).is_enabled());
+        assert_eq!(
+            StartupTaskState::from_winrt(WinStartupTaskState(42)),
+            StartupTaskState::Disabled
+        );
+    }
+}
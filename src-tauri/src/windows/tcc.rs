@@ -0,0 +1,260 @@
+//! macOS privacy (TCC) permissions Opcode and the Claude CLI depend on
+//!
+//! Transparency, Consent and Control blocks access to Desktop, Documents,
+//! Downloads, iCloud Drive and removable volumes, Apple Events to other
+//! apps and debugging tools until the user allows them. A denied read looks
+//! like any other permission error, so a session started in `~/Documents`
+//! would just fail. This module reports each permission's status, asks for
+//! the ones macOS can prompt for and opens the right System Settings pane
+//! for the ones it can't.
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Bundle identifier from tauri.conf.json, as TCC records the app
+const BUNDLE_ID: &str = "opcode.asterisk.so";
+
+/// System-wide TCC database; readable only with Full Disk Access
+const SYSTEM_TCC_DB: &str = "/Library/Application Support/com.apple.TCC/TCC.db";
+
+/// A privacy permission shown in System Settings > Privacy & Security
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyPermission {
+    /// Access to all files, including other apps' data
+    FullDiskAccess,
+    /// Access to Desktop, Documents, Downloads and other protected folders
+    FilesAndFolders,
+    /// Sending Apple Events to other apps, such as Terminal
+    Automation,
+    /// Running tools that bypass Gatekeeper, such as debuggers
+    DeveloperTools,
+}
+
+impl PrivacyPermission {
+    /// Anchor of the permission's pane in System Settings
+    fn settings_anchor(self) -> &'static str {
+        match self {
+            PrivacyPermission::FullDiskAccess => "Privacy_AllFiles",
+            PrivacyPermission::FilesAndFolders => "Privacy_FilesAndFolders",
+            PrivacyPermission::Automation => "Privacy_Automation",
+            PrivacyPermission::DeveloperTools => "Privacy_DevTools",
+        }
+    }
+}
+
+/// Whether a permission was granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionStatus {
+    Granted,
+    Denied,
+    /// The user hasn't been asked yet
+    NotDetermined,
+    /// macOS doesn't tell, e.g. because reading the status needs Full Disk Access
+    Unknown,
+}
+
+/// Status of Full Disk Access, from whether the system TCC database opens
+pub fn full_disk_access_status() -> PermissionStatus {
+    match std::fs::File::open(SYSTEM_TCC_DB) {
+        Ok(_) => PermissionStatus::Granted,
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => PermissionStatus::Denied,
+        Err(e) => {
+            warn!("Could not check Full Disk Access: {}", e);
+            PermissionStatus::Unknown
+        }
+    }
+}
+
+/// Status of the Developer Tools permission
+///
+/// There is no API for it, so it's read from the system TCC database, which
+/// only works once Full Disk Access is granted.
+pub fn developer_tools_status() -> PermissionStatus {
+    let conn = match rusqlite::Connection::open_with_flags(
+        SYSTEM_TCC_DB,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+    ) {
+        Ok(conn) => conn,
+        Err(_) => return PermissionStatus::Unknown,
+    };
+    let auth_value = conn.query_row(
+        "SELECT auth_value FROM access WHERE service = 'kTCCServiceDeveloperTool' AND client = ?1",
+        [BUNDLE_ID],
+        |row| row.get::<_, i64>(0),
+    );
+    match auth_value {
+        // 2 is "allowed", 3 "limited"; 0 is "denied"
+        Ok(2) | Ok(3) => PermissionStatus::Granted,
+        Ok(_) => PermissionStatus::Denied,
+        Err(rusqlite::Error::QueryReturnedNoRows) => PermissionStatus::NotDetermined,
+        Err(e) => {
+            warn!("Could not read the Developer Tools permission: {}", e);
+            PermissionStatus::Unknown
+        }
+    }
+}
+
+#[repr(C)]
+struct AEDesc {
+    descriptor_type: u32,
+    data_handle: *mut c_void,
+}
+
+#[link(name = "CoreServices", kind = "framework")]
+extern "C" {
+    fn AECreateDesc(type_code: u32, data: *const c_void, size: isize, result: *mut AEDesc) -> i16;
+    fn AEDisposeDesc(desc: *mut AEDesc) -> i16;
+    fn AEDeterminePermissionToAutomateTarget(
+        target: *const AEDesc,
+        event_class: u32,
+        event_id: u32,
+        ask_user_if_needed: u8,
+    ) -> i32;
+}
+
+/// `typeApplicationBundleID`
+const TYPE_APPLICATION_BUNDLE_ID: u32 = u32::from_be_bytes(*b"bund");
+/// `typeWildCard`, matching every event class and ID
+const TYPE_WILD_CARD: u32 = u32::from_be_bytes(*b"****");
+const ERR_AE_EVENT_NOT_PERMITTED: i32 = -1743;
+const ERR_AE_EVENT_WOULD_REQUIRE_USER_CONSENT: i32 = -1744;
+/// `procNotFound`: the target app isn't running
+const PROC_NOT_FOUND: i32 = -600;
+
+/// Status of the Automation permission for the app `target_bundle_id`
+///
+/// With `prompt` set, macOS shows its consent dialog if the user hasn't
+/// been asked yet; this blocks until they answer. The target app must be
+/// running, otherwise the status is [`PermissionStatus::Unknown`].
+pub fn automation_status(target_bundle_id: &str, prompt: bool) -> Result<PermissionStatus> {
+    let mut target = AEDesc {
+        descriptor_type: 0,
+        data_handle: std::ptr::null_mut(),
+    };
+    let err = unsafe {
+        AECreateDesc(
+            TYPE_APPLICATION_BUNDLE_ID,
+            target_bundle_id.as_ptr() as *const c_void,
+            target_bundle_id.len() as isize,
+            &mut target,
+        )
+    };
+    if err != 0 {
+        anyhow::bail!("Failed to address {} (OSErr {})", target_bundle_id, err);
+    }
+    let status = unsafe {
+        AEDeterminePermissionToAutomateTarget(&target, TYPE_WILD_CARD, TYPE_WILD_CARD, prompt as u8)
+    };
+    unsafe { AEDisposeDesc(&mut target) };
+
+    Ok(match status {
+        0 => PermissionStatus::Granted,
+        ERR_AE_EVENT_NOT_PERMITTED => PermissionStatus::Denied,
+        ERR_AE_EVENT_WOULD_REQUIRE_USER_CONSENT => PermissionStatus::NotDetermined,
+        PROC_NOT_FOUND => PermissionStatus::Unknown,
+        other => {
+            warn!(
+                "Unexpected Automation status for {}: OSStatus {}",
+                target_bundle_id, other
+            );
+            PermissionStatus::Unknown
+        }
+    })
+}
+
+/// Open the System Settings pane where `permission` is granted
+pub fn open_privacy_settings(permission: PrivacyPermission) -> Result<()> {
+    let url = format!(
+        "x-apple.systempreferences:com.apple.preference.security?{}",
+        permission.settings_anchor()
+    );
+    info!("Opening privacy settings: {}", url);
+    let status = std::process::Command::new("/usr/bin/open")
+        .arg(&url)
+        .status()
+        .context("Failed to run open")?;
+    if !status.success() {
+        anyhow::bail!("Failed to open System Settings ({})", status);
+    }
+    Ok(())
+}
+
+/// Folders TCC guards, relative to the home folder
+const PROTECTED_HOME_FOLDERS: &[&str] = &[
+    "Desktop",
+    "Documents",
+    "Downloads",
+    "Library/Mobile Documents",
+];
+
+/// The protected folder `path` is inside, if any
+fn protected_folder(path: &Path, home: &Path) -> Option<PathBuf> {
+    if let Ok(rest) = path.strip_prefix("/Volumes") {
+        return rest
+            .components()
+            .next()
+            .map(|volume| Path::new("/Volumes").join(volume));
+    }
+    PROTECTED_HOME_FOLDERS
+        .iter()
+        .map(|folder| home.join(folder))
+        .find(|folder| path.starts_with(folder))
+}
+
+/// Make sure Opcode may read `path` before a session is started in it
+///
+/// Listing the folder makes macOS ask for access the first time. A denial
+/// is turned into an error that names the pane to fix it in, instead of the
+/// Claude CLI failing on its first read.
+pub fn check_folder_access(path: &Path) -> Result<()> {
+    let Some(home) = dirs::home_dir() else {
+        return Ok(());
+    };
+    let Some(folder) = protected_folder(path, &home) else {
+        return Ok(());
+    };
+
+    // Check the deepest folder that exists; the project may not be created yet
+    let existing = path.ancestors().find(|ancestor| ancestor.exists());
+    match existing.map(std::fs::read_dir) {
+        Some(Err(e)) if e.kind() == ErrorKind::PermissionDenied => anyhow::bail!(
+            "macOS is blocking access to {}. Allow Opcode in System Settings > Privacy & Security > Files and Folders (or Full Disk Access), then try again.",
+            folder.display()
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_folder() {
+        let home = Path::new("/Users/me");
+        assert_eq!(
+            protected_folder(Path::new("/Users/me/Documents/app"), home),
+            Some(PathBuf::from("/Users/me/Documents"))
+        );
+        assert_eq!(
+            protected_folder(Path::new("/Volumes/Backup/src"), home),
+            Some(PathBuf::from("/Volumes/Backup"))
+        );
+        assert_eq!(protected_folder(Path::new("/Users/me/src/app"), home), None);
+        assert_eq!(
+            protected_folder(Path::new("/Users/me/DocumentsOld"), home),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unprotected_folder_access() {
+        assert!(check_folder_access(Path::new("/usr/bin")).is_ok());
+    }
+}
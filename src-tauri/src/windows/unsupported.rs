@@ -0,0 +1,152 @@
+//! Error for features the running platform doesn't have
+//!
+//! The fallback modules for platforms without a backend return
+//! [`Unsupported`] rather than pretending to succeed, so callers can tell
+//! "not available here" from a real failure and the frontend can hide or
+//! explain the feature instead of reporting an error. Commands pass it on
+//! as a [`CommandError`], which keeps the distinction through serialization.
+
+use serde::Serialize;
+use std::fmt;
+
+/// A feature that isn't available on the platform Opcode runs on
+///
+/// Travels inside `anyhow::Error`; check for it with [`is_unsupported`].
+///
+/// # Example
+/// ```rust
+/// use opcode_lib::windows::unsupported::{is_unsupported, Unsupported};
+///
+/// let error = anyhow::Error::from(Unsupported::new("Windows ACLs"));
+/// assert!(is_unsupported(&error));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Unsupported {
+    /// What the caller tried to use, e.g. `Windows ACLs`
+    pub feature: &'static str,
+    /// Operating system of the running build, as in `std::env::consts::OS`
+    pub platform: &'static str,
+}
+
+impl Unsupported {
+    /// `feature` isn't supported on the platform this build targets
+    pub fn new(feature: &'static str) -> Self {
+        Self {
+            feature,
+            platform: std::env::consts::OS,
+        }
+    }
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not supported on {}", self.feature, self.platform)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Whether `error` is, or was caused by, an [`Unsupported`] feature
+pub fn is_unsupported(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| cause.downcast_ref::<Unsupported>().is_some())
+}
+
+/// Error returned by commands that use platform features
+///
+/// Serializes as `{ "kind": "unsupported", "feature", "platform", "message" }`
+/// or `{ "kind": "failed", "message" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandError {
+    /// The feature isn't available on this platform
+    Unsupported {
+        feature: &'static str,
+        platform: &'static str,
+        message: String,
+    },
+    /// The operation failed
+    Failed { message: String },
+}
+
+impl From<Unsupported> for CommandError {
+    fn from(unsupported: Unsupported) -> Self {
+        CommandError::Unsupported {
+            feature: unsupported.feature,
+            platform: unsupported.platform,
+            message: unsupported.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(error: anyhow::Error) -> Self {
+        match error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Unsupported>())
+        {
+            Some(unsupported) => CommandError::Unsupported {
+                feature: unsupported.feature,
+                platform: unsupported.platform,
+                message: format!("{:#}", error),
+            },
+            None => CommandError::Failed {
+                message: format!("{:#}", error),
+            },
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Failed { message }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unsupported { message, .. } | CommandError::Failed { message } => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_is_unsupported() {
+        let error = Err::<(), _>(Unsupported::new("Windows Terminal profiles"))
+            .context("Failed to install the terminal profile")
+            .unwrap_err();
+        assert!(is_unsupported(&error));
+        assert!(!is_unsupported(&anyhow::anyhow!("Access is denied")));
+        assert_eq!(
+            Unsupported::new("Windows ACLs").to_string(),
+            format!("Windows ACLs is not supported on {}", std::env::consts::OS)
+        );
+    }
+
+    #[test]
+    fn test_command_error() {
+        let error = Err::<(), _>(Unsupported::new("Windows Terminal profiles"))
+            .context("Failed to install the terminal profile")
+            .unwrap_err();
+        let body = serde_json::to_value(CommandError::from(error)).unwrap();
+        assert_eq!(body["kind"], "unsupported");
+        assert_eq!(body["feature"], "Windows Terminal profiles");
+        assert_eq!(body["platform"], std::env::consts::OS);
+
+        let body =
+            serde_json::to_value(CommandError::from(anyhow::anyhow!("Access is denied"))).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "kind": "failed", "message": "Access is denied" })
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Windows Hello confirmation for destructive operations
+//!
+//! When the user turns it on in settings, operations that are hard to undo or
+//! expose secrets ("delete all checkpoints", "reveal API key", "kill all
+//! sessions") first ask for Windows Hello (face, fingerprint or PIN) through
+//! the WinRT `UserConsentVerifier`. The prompt is parented to opcode's window
+//! when it is in the foreground, so it cannot end up behind it.
+//!
+//! The settings live in the `app_settings` table as JSON, like the other
+//! feature settings. They exist on every platform, but elsewhere than on
+//! Windows there is no prompt: checking for one fails with
+//! [`Unsupported`](super::unsupported::Unsupported).
+
+use anyhow::{Context, Result};
+use log::warn;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_os = "windows"))]
+use super::unsupported::Unsupported;
+
+#[cfg(target_os = "windows")]
+use super::startup_task::in_multithreaded_apartment;
+#[cfg(target_os = "windows")]
+use log::info;
+#[cfg(target_os = "windows")]
+use windows::core::HSTRING;
+#[cfg(target_os = "windows")]
+use windows::Security::Credentials::UI::{
+    UserConsentVerificationResult, UserConsentVerifier, UserConsentVerifierAvailability,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::HWND;
+#[cfg(target_os = "windows")]
+use windows::Win32::System::WinRT::IUserConsentVerifierInterop;
+#[cfg(target_os = "windows")]
+use windows_future::IAsyncOperation;
+
+/// app_settings key the confirmation settings are stored under (as JSON)
+const SETTINGS_KEY: &str = "user_presence";
+
+/// Whether Windows Hello can be used on this machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserPresenceAvailability {
+    Available,
+    DeviceNotPresent,
+    NotConfiguredForUser,
+    DisabledByPolicy,
+    DeviceBusy,
+}
+
+#[cfg(target_os = "windows")]
+impl UserPresenceAvailability {
+    fn from_winrt(availability: UserConsentVerifierAvailability) -> Self {
+        match availability {
+            UserConsentVerifierAvailability::Available => UserPresenceAvailability::Available,
+            UserConsentVerifierAvailability::NotConfiguredForUser => {
+                UserPresenceAvailability::NotConfiguredForUser
+            }
+            UserConsentVerifierAvailability::DisabledByPolicy => {
+                UserPresenceAvailability::DisabledByPolicy
+            }
+            UserConsentVerifierAvailability::DeviceBusy => UserPresenceAvailability::DeviceBusy,
+            _ => UserPresenceAvailability::DeviceNotPresent,
+        }
+    }
+}
+
+/// Outcome of a Windows Hello prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserPresenceResult {
+    Verified,
+    Canceled,
+    RetriesExhausted,
+    DeviceNotPresent,
+    NotConfiguredForUser,
+    DisabledByPolicy,
+    DeviceBusy,
+}
+
+impl UserPresenceResult {
+    pub fn is_verified(&self) -> bool {
+        *self == UserPresenceResult::Verified
+    }
+
+    #[cfg(target_os = "windows")]
+    fn from_winrt(result: UserConsentVerificationResult) -> Self {
+        match result {
+            UserConsentVerificationResult::Verified => UserPresenceResult::Verified,
+            UserConsentVerificationResult::NotConfiguredForUser => {
+                UserPresenceResult::NotConfiguredForUser
+            }
+            UserConsentVerificationResult::DisabledByPolicy => UserPresenceResult::DisabledByPolicy,
+            UserConsentVerificationResult::DeviceBusy => UserPresenceResult::DeviceBusy,
+            UserConsentVerificationResult::RetriesExhausted => UserPresenceResult::RetriesExhausted,
+            UserConsentVerificationResult::Canceled => UserPresenceResult::Canceled,
+            _ => UserPresenceResult::DeviceNotPresent,
+        }
+    }
+}
+
+/// Operations that can be put behind a Windows Hello prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtectedOperation {
+    DeleteAllCheckpoints,
+    RevealApiKey,
+    KillAllSessions,
+}
+
+impl ProtectedOperation {
+    /// Text shown in the Windows Hello prompt
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ProtectedOperation::DeleteAllCheckpoints => {
+                "Confirm that you want to delete all checkpoints"
+            }
+            ProtectedOperation::RevealApiKey => "Confirm that you want to reveal your API key",
+            ProtectedOperation::KillAllSessions => {
+                "Confirm that you want to stop all running sessions"
+            }
+        }
+    }
+}
+
+/// Which operations require Windows Hello
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPresenceSettings {
+    pub enabled: bool,
+    /// Operations that need confirmation while `enabled` is set
+    #[serde(default = "default_protected_operations")]
+    pub operations: Vec<ProtectedOperation>,
+}
+
+fn default_protected_operations() -> Vec<ProtectedOperation> {
+    vec![
+        ProtectedOperation::DeleteAllCheckpoints,
+        ProtectedOperation::RevealApiKey,
+        ProtectedOperation::KillAllSessions,
+    ]
+}
+
+impl Default for UserPresenceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            operations: default_protected_operations(),
+        }
+    }
+}
+
+impl UserPresenceSettings {
+    /// Whether `operation` has to be confirmed with Windows Hello
+    pub fn requires(&self, operation: ProtectedOperation) -> bool {
+        self.enabled && self.operations.contains(&operation)
+    }
+}
+
+/// Read the confirmation settings from the database, falling back to defaults
+pub fn load_user_presence_settings(conn: &Connection) -> UserPresenceSettings {
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        params![SETTINGS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|value| serde_json::from_str(&value).ok())
+    .unwrap_or_default()
+}
+
+/// Save the confirmation settings to the database
+pub fn save_user_presence_settings(
+    conn: &Connection,
+    settings: &UserPresenceSettings,
+) -> Result<()> {
+    let value = serde_json::to_string(settings)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        params![SETTINGS_KEY, value],
+    )
+    .with_context(|| format!("Failed to save {}", SETTINGS_KEY))?;
+    Ok(())
+}
+
+/// Check whether Windows Hello is set up and usable
+#[cfg(target_os = "windows")]
+pub fn user_presence_availability() -> Result<UserPresenceAvailability> {
+    in_multithreaded_apartment(|| {
+        let availability = UserConsentVerifier::CheckAvailabilityAsync()
+            .and_then(|operation| operation.get())
+            .context("Failed to check Windows Hello availability")?;
+        Ok(UserPresenceAvailability::from_winrt(availability))
+    })
+}
+
+/// Check whether Windows Hello is set up and usable (unsupported on other
+/// platforms)
+#[cfg(not(target_os = "windows"))]
+pub fn user_presence_availability() -> Result<UserPresenceAvailability> {
+    Err(Unsupported::new("Windows Hello").into())
+}
+
+/// Ask the user to confirm their presence with Windows Hello
+///
+/// Blocks until the prompt is answered; call it from a blocking task.
+/// `reason` is shown in the prompt.
+#[cfg(target_os = "windows")]
+pub fn verify_user_presence(reason: &str) -> Result<UserPresenceResult> {
+    let message = HSTRING::from(reason);
+    let owner = own_foreground_window();
+
+    let result = in_multithreaded_apartment(move || {
+        let operation: IAsyncOperation<UserConsentVerificationResult> = match owner {
+            Some(hwnd) => {
+                let interop =
+                    windows::core::factory::<UserConsentVerifier, IUserConsentVerifierInterop>()
+                        .context("Failed to get the UserConsentVerifier interop factory")?;
+                unsafe { interop.RequestVerificationForWindowAsync(HWND(hwnd as _), &message) }
+            }
+            None => UserConsentVerifier::RequestVerificationAsync(&message),
+        }
+        .context("Failed to show the Windows Hello prompt")?;
+
+        let result = operation
+            .get()
+            .context("Windows Hello verification failed")?;
+        Ok(UserPresenceResult::from_winrt(result))
+    })?;
+
+    info!("Windows Hello verification: {:?}", result);
+    Ok(result)
+}
+
+/// Ask the user to confirm their presence (unsupported on other platforms)
+#[cfg(not(target_os = "windows"))]
+pub fn verify_user_presence(_reason: &str) -> Result<UserPresenceResult> {
+    Err(Unsupported::new("Windows Hello").into())
+}
+
+/// Require Windows Hello for `operation` if the settings ask for it
+///
+/// Returns `Ok(())` when no confirmation is needed or the user confirmed, and
+/// an error explaining why otherwise. When Windows Hello is not set up the
+/// operation is refused rather than silently allowed, since the user asked for
+/// the protection.
+pub fn confirm_protected_operation(
+    settings: &UserPresenceSettings,
+    operation: ProtectedOperation,
+) -> Result<()> {
+    if !settings.requires(operation) {
+        return Ok(());
+    }
+
+    match verify_user_presence(operation.reason())? {
+        UserPresenceResult::Verified => Ok(()),
+        UserPresenceResult::Canceled => {
+            Err(anyhow::anyhow!("Windows Hello confirmation was canceled"))
+        }
+        other => {
+            warn!(
+                "Windows Hello confirmation for {:?} failed: {:?}",
+                operation, other
+            );
+            Err(anyhow::anyhow!(
+                "Windows Hello confirmation failed ({:?}); turn off the confirmation in settings if Windows Hello is not available",
+                other
+            ))
+        }
+    }
+}
+
+/// opcode's foreground window, used as the owner of the prompt
+#[cfg(target_os = "windows")]
+fn own_foreground_window() -> Option<isize> {
+    use winapi::um::winuser::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return None;
+        }
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        (pid == std::process::id()).then_some(hwnd as isize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_presence_settings() {
+        let settings = UserPresenceSettings::default();
+        assert!(!settings.requires(ProtectedOperation::RevealApiKey));
+
+        let settings: UserPresenceSettings = serde_json::from_str(r#"{"enabled":true}"#).unwrap();
+        assert!(settings.requires(ProtectedOperation::DeleteAllCheckpoints));
+        assert!(settings.requires(ProtectedOperation::KillAllSessions));
+
+        let settings = UserPresenceSettings {
+            enabled: true,
+            operations: vec![ProtectedOperation::RevealApiKey],
+        };
+        assert!(settings.requires(ProtectedOperation::RevealApiKey));
+        assert!(!settings.requires(ProtectedOperation::KillAllSessions));
+        assert!(
+            confirm_protected_operation(&settings, ProtectedOperation::KillAllSessions).is_ok()
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_user_presence_result() {
+        assert!(
+            UserPresenceResult::from_winrt(UserConsentVerificationResult::Verified).is_verified()
+        );
+        assert_eq!(
+            UserPresenceResult::from_winrt(UserConsentVerificationResult(42)),
+            UserPresenceResult::DeviceNotPresent
+        );
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_user_presence_availability() {
+        assert!(user_presence_availability().is_ok());
+    }
+}
@@ -0,0 +1,188 @@
+//! BitLocker status of the drives holding projects and opcode's data
+//!
+//! Checkpoints, transcripts and stored credentials are only as safe as the
+//! drive they are on. [`get_volume_protection_status`] reports whether a
+//! volume is removable and whether BitLocker protects it, so the security
+//! panel can warn about sensitive data on an unencrypted USB drive.
+//!
+//! The BitLocker state is read from the shell's
+//! `System.Volume.BitLockerProtection` property, which unlike WMI's
+//! `Win32_EncryptableVolume` doesn't require administrator rights.
+
+use super::efs::{volume_root, volume_supports_efs};
+use super::to_wide_path;
+use crate::process::command::{
+    powershell_command, run_with_timeout_blocking, DEFAULT_COMMAND_TIMEOUT,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use serde::Serialize;
+use std::path::Path;
+
+/// BitLocker state of a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitLockerState {
+    On,
+    Off,
+    Encrypting,
+    Decrypting,
+    /// Encrypted, but the key is stored in the clear until resumed
+    Suspended,
+    /// Encrypted and not unlocked yet
+    Locked,
+    /// Encrypted without a protector yet, so the key is in the clear
+    WaitingForActivation,
+    /// The volume can't be protected by BitLocker, or BitLocker isn't
+    /// available on this edition of Windows
+    NotSupported,
+    Unknown,
+}
+
+impl BitLockerState {
+    /// Map a `System.Volume.BitLockerProtection` value
+    fn from_shell_property(value: Option<u32>) -> Self {
+        match value {
+            None | Some(0) => BitLockerState::NotSupported,
+            Some(1) => BitLockerState::On,
+            Some(2) => BitLockerState::Off,
+            Some(3) => BitLockerState::Encrypting,
+            Some(4) => BitLockerState::Decrypting,
+            Some(5) => BitLockerState::Suspended,
+            Some(6) => BitLockerState::Locked,
+            Some(8) => BitLockerState::WaitingForActivation,
+            Some(_) => BitLockerState::Unknown,
+        }
+    }
+
+    /// Whether data on the volume is protected at rest
+    pub fn is_protected(&self) -> bool {
+        matches!(self, BitLockerState::On | BitLockerState::Locked)
+    }
+}
+
+/// Protection of the volume holding a path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VolumeProtectionStatus {
+    /// Volume root, e.g. `E:\`
+    pub volume: String,
+    /// USB sticks and memory cards. External hard disks usually report as fixed
+    pub removable: bool,
+    pub bitlocker: BitLockerState,
+    /// Whether EFS could be used instead (see [`super::efs`])
+    pub efs_supported: bool,
+}
+
+impl VolumeProtectionStatus {
+    /// Warning for the security panel when sensitive data on this volume is
+    /// exposed to anyone who gets hold of the drive
+    pub fn warning(&self) -> Option<String> {
+        if !self.removable || self.bitlocker.is_protected() {
+            return None;
+        }
+        let mut warning = format!(
+            "{} is a removable drive without BitLocker protection; anyone with the drive can read checkpoints and credentials stored on it",
+            self.volume
+        );
+        if self.bitlocker != BitLockerState::NotSupported {
+            warning.push_str(". Turn on BitLocker To Go for this drive");
+        } else if self.efs_supported {
+            warning.push_str(". Consider encrypting opcode's data directory with EFS");
+        }
+        Some(warning)
+    }
+}
+
+/// Whether `volume` is a removable drive
+fn is_removable(volume: &Path) -> bool {
+    use winapi::um::fileapi::GetDriveTypeW;
+    use winapi::um::winbase::DRIVE_REMOVABLE;
+
+    let wide = to_wide_path(volume);
+    unsafe { GetDriveTypeW(wide.as_ptr()) == DRIVE_REMOVABLE }
+}
+
+/// Read `System.Volume.BitLockerProtection` for `volume`
+fn bitlocker_shell_property(volume: &Path) -> Result<Option<u32>> {
+    let script = "$ns = (New-Object -ComObject Shell.Application).NameSpace($env:OPCODE_PS_VOLUME); if ($ns) { $ns.Self.ExtendedProperty('System.Volume.BitLockerProtection') }";
+    let output = run_with_timeout_blocking(
+        &mut powershell_command(script, &[("VOLUME", &volume.to_string_lossy())]),
+        DEFAULT_COMMAND_TIMEOUT,
+    )
+    .context("Failed to execute PowerShell command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "Reading the BitLocker status failed: {}",
+            stderr.trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+}
+
+/// BitLocker and removable-media status of the volume holding `path`
+///
+/// `path` can be a drive (`E:`) or any path on it, such as a project
+/// directory or opcode's data directory.
+pub fn get_volume_protection_status(path: &str) -> Result<VolumeProtectionStatus> {
+    let mut path = path.to_string();
+    if path.len() == 2 && path.ends_with(':') {
+        path.push('\\');
+    }
+    let volume = volume_root(Path::new(&path))?;
+
+    let status = VolumeProtectionStatus {
+        volume: volume.to_string_lossy().to_string(),
+        removable: is_removable(&volume),
+        bitlocker: BitLockerState::from_shell_property(bitlocker_shell_property(&volume)?),
+        efs_supported: volume_supports_efs(&volume).unwrap_or(false),
+    };
+    debug!("Volume protection of {}: {:?}", path, status);
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitlocker_state() {
+        assert_eq!(
+            BitLockerState::from_shell_property(None),
+            BitLockerState::NotSupported
+        );
+        assert!(BitLockerState::from_shell_property(Some(1)).is_protected());
+        assert!(!BitLockerState::from_shell_property(Some(5)).is_protected());
+        assert_eq!(
+            BitLockerState::from_shell_property(Some(42)),
+            BitLockerState::Unknown
+        );
+    }
+
+    #[test]
+    fn test_volume_protection_warning() {
+        let mut status = VolumeProtectionStatus {
+            volume: r"E:\".to_string(),
+            removable: true,
+            bitlocker: BitLockerState::Off,
+            efs_supported: false,
+        };
+        assert!(status.warning().unwrap().contains("BitLocker To Go"));
+
+        status.bitlocker = BitLockerState::On;
+        assert_eq!(status.warning(), None);
+
+        status.removable = false;
+        status.bitlocker = BitLockerState::Off;
+        assert_eq!(status.warning(), None);
+    }
+
+    #[test]
+    #[ignore] // Integration test - requires Windows
+    fn test_get_volume_protection_status() {
+        let status = get_volume_protection_status("C:").unwrap();
+        assert_eq!(status.volume, r"C:\");
+        assert!(!status.removable);
+    }
+}
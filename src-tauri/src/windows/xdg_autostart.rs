@@ -0,0 +1,144 @@
+//! Start at login on Linux through an XDG autostart entry
+//!
+//! Desktop environments that follow the XDG Autostart specification (GNOME,
+//! KDE, Xfce and others) launch every `.desktop` entry in
+//! `~/.config/autostart` when the user logs in. Enabling auto-start writes
+//! such an entry for the app and disabling it removes the entry again.
+
+use crate::utils::atomic_write::atomic_write;
+use anyhow::{Context, Result};
+use log::info;
+use std::path::{Path, PathBuf};
+
+/// File name of the autostart entry for `app_name`, e.g. `opcode.desktop`
+pub fn autostart_file_name(app_name: &str) -> String {
+    let name: String = app_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}.desktop", name)
+}
+
+/// Where the autostart entry for `app_name` lives
+///
+/// Follows `$XDG_CONFIG_HOME`, which defaults to `~/.config`.
+pub fn autostart_path(app_name: &str) -> Result<PathBuf> {
+    let config = dirs::config_dir().context("Could not find config directory")?;
+    Ok(config.join("autostart").join(autostart_file_name(app_name)))
+}
+
+/// Quote one argument of an `Exec` key as the Desktop Entry spec requires
+///
+/// Arguments with reserved characters are double-quoted with `"`, `` ` ``,
+/// `$` and `\` backslash-escaped. Backslashes are then doubled once more
+/// because the key's value is itself an escaped string, and `%` is doubled
+/// so it isn't read as a field code.
+pub(crate) fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`',
+    ];
+    let quoted = if arg.is_empty() || arg.contains(RESERVED) {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            if matches!(c, '"' | '`' | '$' | '\\') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        arg.to_string()
+    };
+    quoted.replace('\\', "\\\\").replace('%', "%%")
+}
+
+/// Contents of an autostart entry that starts `executable_path` at login
+pub fn autostart_entry(app_name: &str, executable_path: &str) -> String {
+    // Names are plain strings; only line breaks would break the file
+    let name = app_name.replace(['\n', '\r'], " ");
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n\
+         Exec={}\n\
+         Terminal=false\n\
+         Hidden=false\n\
+         X-GNOME-Autostart-enabled=true\n",
+        name,
+        quote_exec_arg(executable_path)
+    )
+}
+
+/// Enable or disable starting `executable_path` when the user logs in
+///
+/// Disabling succeeds when no entry is installed.
+pub fn set_auto_start(app_name: &str, executable_path: &str, enabled: bool) -> Result<()> {
+    info!("Setting auto-start for {}: {}", app_name, enabled);
+    let path = autostart_path(app_name)?;
+
+    if !enabled {
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("Removed autostart entry {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+        }
+        return Ok(());
+    }
+
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for auto-start: {} (check installation)",
+            executable_path
+        );
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    atomic_write(&path, autostart_entry(app_name, executable_path))?;
+    info!("Installed autostart entry {}", path.display());
+    Ok(())
+}
+
+/// Whether an autostart entry for `app_name` is installed
+pub fn is_auto_start_enabled(app_name: &str) -> Result<bool> {
+    Ok(autostart_path(app_name)?.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_exec_arg() {
+        assert_eq!(quote_exec_arg("/usr/bin/opcode"), "/usr/bin/opcode");
+        assert_eq!(
+            quote_exec_arg("/opt/My Apps/opcode"),
+            "\"/opt/My Apps/opcode\""
+        );
+        assert_eq!(quote_exec_arg("/opt/$HOME/a"), "\"/opt/\\\\$HOME/a\"");
+        assert_eq!(quote_exec_arg("/opt/100%/a"), "/opt/100%%/a");
+        assert_eq!(quote_exec_arg(""), "\"\"");
+    }
+
+    #[test]
+    fn test_autostart_entry() {
+        assert_eq!(autostart_file_name("Opcode"), "opcode.desktop");
+        let entry = autostart_entry("Opcode", "/home/me/Opcode.AppImage");
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("\nExec=/home/me/Opcode.AppImage\n"));
+        assert!(entry.contains("\nHidden=false\n"));
+        assert!(entry.contains("\nX-GNOME-Autostart-enabled=true\n"));
+    }
+}
@@ -0,0 +1,257 @@
+//! File associations and URL schemes on Linux desktops
+//!
+//! Freedesktop desktops pick the app that opens a file type or URL scheme
+//! from `.desktop` entries listing it under `MimeType`. Registering writes
+//! a hidden entry for Opcode to `~/.local/share/applications`, teaches the
+//! shared MIME database the file extension with `xdg-mime install`, makes
+//! the entry the default with `xdg-mime default` and refreshes the desktop
+//! database so file managers and browsers notice right away.
+//!
+//! The `xdg-utils` tools are optional: without them the entries are still
+//! written and get picked up the next time the desktop rebuilds its cache.
+
+use super::registry::RegistrationScope;
+use super::xdg_autostart::quote_exec_arg;
+use crate::commands::deep_link::DEEP_LINK_SWITCH;
+use crate::utils::atomic_write::atomic_write;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `name` reduced to the characters allowed in a desktop file ID
+fn desktop_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// MIME type registered for the files of `program_id`, e.g.
+/// `application/x-opcode-document` for `Opcode.Document`
+pub fn mime_type_for(program_id: &str) -> String {
+    format!("application/x-{}", desktop_id(program_id))
+}
+
+/// `~/.local/share/applications`, following `$XDG_DATA_HOME`
+fn applications_dir() -> Result<PathBuf> {
+    Ok(dirs::data_dir()
+        .context("Could not find data directory")?
+        .join("applications"))
+}
+
+/// Escape text for an XML element or attribute
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Contents of a hidden `.desktop` entry that opens `mime_type` with
+/// `executable_path`, passing the file or URL as `field_code`
+pub fn handler_entry(
+    name: &str,
+    executable_path: &str,
+    extra_args: &[&str],
+    field_code: &str,
+    mime_type: &str,
+) -> String {
+    let mut exec = vec![quote_exec_arg(executable_path)];
+    exec.extend(extra_args.iter().map(|arg| quote_exec_arg(arg)));
+    exec.push(field_code.to_string());
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n\
+         Exec={}\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         MimeType={};\n",
+        name.replace(['\n', '\r'], " "),
+        exec.join(" "),
+        mime_type
+    )
+}
+
+/// Shared MIME database package that maps `*<extension>` to `mime_type`
+pub fn mime_package(mime_type: &str, extension: &str, description: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+    <mime-type type="{}">
+        <comment>{}</comment>
+        <glob pattern="*{}"/>
+    </mime-type>
+</mime-info>
+"#,
+        xml_escape(mime_type),
+        xml_escape(description),
+        xml_escape(extension)
+    )
+}
+
+/// Run an `xdg-utils` helper, warning instead of failing when it is
+/// missing or fails
+fn run_helper(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => warn!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Could not run {}: {}", program, e),
+    }
+}
+
+/// Write `entry` as `<id>.desktop`, make it the default for `mime_type`
+/// and refresh the desktop database
+fn install_handler(id: &str, entry: &str, mime_type: &str) -> Result<()> {
+    let dir = applications_dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let file_name = format!("{}.desktop", id);
+    atomic_write(dir.join(&file_name), entry)?;
+
+    run_helper("xdg-mime", &["default", &file_name, mime_type]);
+    run_helper("update-desktop-database", &[&dir.to_string_lossy()]);
+    Ok(())
+}
+
+fn ensure_executable(executable_path: &str, purpose: &str) -> Result<()> {
+    if !Path::new(executable_path).exists() {
+        anyhow::bail!(
+            "Executable not found for {}: {} (check installation)",
+            purpose,
+            executable_path
+        );
+    }
+    Ok(())
+}
+
+/// Register `executable_path` as the handler of `*<extension>` files
+///
+/// Always registers for the current user only.
+pub fn register_file_association(
+    extension: &str,
+    program_id: &str,
+    executable_path: &str,
+    description: &str,
+) -> Result<Option<RegistrationScope>> {
+    info!(
+        "Registering file association: {} -> {}",
+        extension, program_id
+    );
+    if !extension.starts_with('.')
+        || extension.len() < 2
+        || !extension[1..].chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        anyhow::bail!("Invalid file extension: {}", extension);
+    }
+    ensure_executable(executable_path, "file association")?;
+
+    let id = desktop_id(program_id);
+    let mime_type = mime_type_for(program_id);
+
+    // xdg-mime names the package after the file, which must be
+    // "<vendor>-<name>.xml"
+    let package = super::secure_temp::create_secure_temp_dir("mime")?;
+    let package_path = package.path().join(format!("opcode-{}.xml", id));
+    std::fs::write(
+        &package_path,
+        mime_package(&mime_type, extension, description),
+    )
+    .with_context(|| format!("Failed to write {}", package_path.display()))?;
+    run_helper(
+        "xdg-mime",
+        &["install", "--mode", "user", &package_path.to_string_lossy()],
+    );
+
+    let entry = handler_entry(description, executable_path, &[], "%f", &mime_type);
+    install_handler(&id, &entry, &mime_type)?;
+    info!("Successfully registered file association: {}", extension);
+    Ok(Some(RegistrationScope::User))
+}
+
+/// Register `executable_path` as the handler of `<protocol>://` links
+///
+/// Links are passed after the deep link switch, as on Windows. Always
+/// registers for the current user only.
+pub fn register_url_protocol(
+    protocol: &str,
+    executable_path: &str,
+    description: &str,
+) -> Result<Option<RegistrationScope>> {
+    info!("Registering URL protocol: {}", protocol);
+    if protocol.is_empty()
+        || !protocol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    {
+        anyhow::bail!("Invalid URL protocol: {}", protocol);
+    }
+    ensure_executable(executable_path, "URL protocol")?;
+
+    let mime_type = format!("x-scheme-handler/{}", protocol.to_ascii_lowercase());
+    let entry = handler_entry(
+        description,
+        executable_path,
+        &[DEEP_LINK_SWITCH],
+        "%u",
+        &mime_type,
+    );
+    install_handler(
+        &format!("{}-url-handler", desktop_id(protocol)),
+        &entry,
+        &mime_type,
+    )?;
+    info!("Successfully registered URL protocol: {}://", protocol);
+    Ok(Some(RegistrationScope::User))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handler_entry() {
+        assert_eq!(
+            mime_type_for("Opcode.Document"),
+            "application/x-opcode-document"
+        );
+
+        let entry = handler_entry(
+            "Opcode",
+            "/opt/Opcode App/opcode",
+            &["--deep-link"],
+            "%u",
+            "x-scheme-handler/opcode",
+        );
+        assert!(entry.contains("\nExec=\"/opt/Opcode App/opcode\" --deep-link %u\n"));
+        assert!(entry.contains("\nMimeType=x-scheme-handler/opcode;\n"));
+        assert!(entry.contains("\nNoDisplay=true\n"));
+    }
+
+    #[test]
+    fn test_mime_package() {
+        let package = mime_package("application/x-opcode-document", ".opc", "Opcode <Project>");
+        assert!(package.contains(r#"<mime-type type="application/x-opcode-document">"#));
+        assert!(package.contains("<comment>Opcode &lt;Project&gt;</comment>"));
+        assert!(package.contains(r#"<glob pattern="*.opc"/>"#));
+    }
+
+    #[test]
+    fn test_invalid_registrations() {
+        assert!(register_file_association("opc", "Opcode.Document", "/bin/sh", "x").is_err());
+        assert!(register_file_association(".o p", "Opcode.Document", "/bin/sh", "x").is_err());
+        assert!(register_url_protocol("op code", "/bin/sh", "x").is_err());
+        assert!(register_url_protocol("opcode", "/missing/opcode", "x").is_err());
+    }
+}
@@ -134,6 +134,24 @@ function AppContent() {
     return () => window.removeEventListener('keydown', handleKeyDown);
   }, [view]);
 
+  // Report input to the backend so idle-triggered maintenance waits while
+  // the user is active; at most once a minute
+  useEffect(() => {
+    let lastReported = 0;
+    const handleInput = () => {
+      const now = Date.now();
+      if (now - lastReported < 60_000) return;
+      lastReported = now;
+      api.reportUserActivity().catch(() => {});
+    };
+
+    const events = ['keydown', 'pointerdown', 'pointermove', 'wheel'] as const;
+    events.forEach((event) => window.addEventListener(event, handleInput, { passive: true }));
+    return () => {
+      events.forEach((event) => window.removeEventListener(event, handleInput));
+    };
+  }, []);
+
   // Listen for Claude not found events
   useEffect(() => {
     const handleClaudeNotFound = () => {
@@ -170,7 +188,7 @@ function AppContent() {
     try {
       setLoading(true);
       setError(null);
-      const sessionList = await api.getProjectSessions(project.id);
+      const sessionList = await api.getProjectSessions(project.id, project.alias_ids);
       setSessions(sessionList);
       setSelectedProject(project);
     } catch (err) {
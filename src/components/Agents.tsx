@@ -12,7 +12,7 @@ import { Button } from '@/components/ui/button';
 import { Badge } from '@/components/ui/badge';
 import { Card } from '@/components/ui/card';
 import { Toast } from '@/components/ui/toast';
-import { api, type Agent, type AgentRunWithMetrics } from '@/lib/api';
+import { api, importAgentFile, type Agent, type AgentRunWithMetrics } from '@/lib/api';
 import { open as openDialog, save } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
 import { GitHubAgentBrowser } from '@/components/GitHubAgentBrowser';
@@ -130,7 +130,7 @@ export const Agents: React.FC = () => {
       });
 
       if (selected) {
-        const importedAgent = await api.importAgentFromFile(selected as string);
+        const importedAgent = await importAgentFile(selected as string);
         setToast({ message: `Imported agent: ${importedAgent.name}`, type: 'success' });
         loadAgents();
       }
@@ -19,7 +19,7 @@ import { Button } from '@/components/ui/button';
 import { Badge } from '@/components/ui/badge';
 import { ScrollArea } from '@/components/ui/scroll-area';
 import { Toast } from '@/components/ui/toast';
-import { api, type Agent, type AgentRunWithMetrics } from '@/lib/api';
+import { api, importAgentFile, type Agent, type AgentRunWithMetrics } from '@/lib/api';
 import { useTabState } from '@/hooks/useTabState';
 import { formatISOTimestamp } from '@/lib/date-utils';
 import { open as openDialog, save } from '@tauri-apps/plugin-dialog';
@@ -165,7 +165,7 @@ export const AgentsModal: React.FC<AgentsModalProps> = ({ open, onOpenChange })
       });
       
       if (filePath) {
-        const agent = await api.importAgentFromFile(filePath as string);
+        const agent = await importAgentFile(filePath as string);
         loadAgents(); // Refresh list
         setToast({ message: `Agent "${agent.name}" imported successfully`, type: "success" });
       }
@@ -30,7 +30,7 @@ import {
   DialogHeader,
   DialogTitle,
 } from "@/components/ui/dialog";
-import { api, type Agent, type AgentRunWithMetrics } from "@/lib/api";
+import { api, importAgentFile, type Agent, type AgentRunWithMetrics } from "@/lib/api";
 import { save, open } from "@tauri-apps/plugin-dialog";
 import { invoke } from "@tauri-apps/api/core";
 import { cn } from "@/lib/utils";
@@ -233,7 +233,7 @@ export const CCAgents: React.FC<CCAgentsProps> = ({ onBack, className }) => {
       }
       
       // Import the agent from the selected file
-      await api.importAgentFromFile(filePath as string);
+      await importAgentFile(filePath as string);
       
       setToast({ message: "Agent imported successfully", type: "success" });
       await loadAgents();
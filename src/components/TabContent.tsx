@@ -64,7 +64,7 @@ const TabPanel: React.FC<TabPanelProps> = ({ tab, isActive }) => {
     try {
       setLoading(true);
       setError(null);
-      const sessionList = await api.getProjectSessions(project.id);
+      const sessionList = await api.getProjectSessions(project.id, project.alias_ids);
       setSessions(sessionList);
       setSelectedProject(project);
       
@@ -19,7 +19,7 @@ import { Tooltip, TooltipContent, TooltipProvider, TooltipTrigger } from "@/comp
 import { Dialog, DialogContent, DialogHeader, DialogTitle, DialogDescription, DialogFooter } from "@/components/ui/dialog";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
-import { api, type Checkpoint, type TimelineNode, type SessionTimeline, type CheckpointDiff } from "@/lib/api";
+import { api, restoreCheckpointAsking, type Checkpoint, type TimelineNode, type SessionTimeline, type CheckpointDiff } from "@/lib/api";
 import { cn } from "@/lib/utils";
 import { formatDistanceToNow } from "date-fns";
 import { useTrackEvent } from "@/hooks";
@@ -171,7 +171,7 @@ export const TimelineNavigator: React.FC<TimelineNavigatorProps> = ({
       );
       
       // Then restore
-      await api.restoreCheckpoint(checkpoint.id, sessionId, projectId, projectPath);
+      await restoreCheckpointAsking(checkpoint.id, sessionId, projectId, projectPath);
       
       // Track checkpoint restoration
       trackEvent.checkpointRestored({
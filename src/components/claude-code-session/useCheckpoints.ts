@@ -1,5 +1,5 @@
 import { useState, useCallback } from 'react';
-import { api } from '@/lib/api';
+import { api, restoreCheckpointAsking } from '@/lib/api';
 
 // Local checkpoint format for UI display
 interface Checkpoint {
@@ -70,7 +70,7 @@ export function useCheckpoints({ sessionId, projectId, projectPath, onToast }: U
     if (!sessionId) return;
     
     try {
-      await api.restoreCheckpoint(checkpointId, sessionId, projectId, projectPath);
+      await restoreCheckpointAsking(checkpointId, sessionId, projectId, projectPath);
       showToast("Checkpoint restored successfully", 'success');
       // Return true to indicate success
       return true;
@@ -31,6 +31,8 @@ export interface Project {
   created_at: number;
   /** Unix timestamp of the most recent session (if any) */
   most_recent_session?: number;
+  /** IDs of other project directories for the same folder */
+  alias_ids?: string[];
 }
 
 /**
@@ -124,6 +126,172 @@ export interface Agent {
   updated_at: string;
 }
 
+export type SecretKind = "anthropic_api_key" | "openrouter_api_key" | "oauth_token";
+
+/**
+ * A secret in the platform secret store; the value is never returned
+ */
+export interface SecretInfo {
+  kind: SecretKind;
+  account: string;
+  target: string;
+  last_written?: string;
+}
+
+/**
+ * Outcome of moving the API keys out of Claude's settings file
+ */
+export type SettingsSecretsMigration =
+  | { status: "nothing_to_move" }
+  | { status: "consent_required"; secrets: string[]; warning: string }
+  | { status: "migrated"; moved: string[] };
+
+/**
+ * Error thrown by commands that use platform features, so an unavailable
+ * feature can be told apart from a failure
+ */
+export type CommandError =
+  | { kind: "unsupported"; feature: string; platform: string; message: string }
+  | { kind: "failed"; message: string };
+
+/**
+ * Whether `error` says the feature isn't available on this platform
+ */
+export function isUnsupportedError(error: unknown): error is CommandError & { kind: "unsupported" } {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    (error as { kind?: unknown }).kind === "unsupported"
+  );
+}
+
+/**
+ * Whether `error` says Windows blocks a file because it was downloaded; the
+ * operation can be retried with `unblock` once the user agreed
+ */
+export function isBlockedFileError(error: unknown): boolean {
+  return typeof error === "string" && error.endsWith("is blocked by Windows");
+}
+
+/**
+ * Imports an agent from a file, asking before unblocking a downloaded one
+ */
+export async function importAgentFile(filePath: string): Promise<Agent> {
+  try {
+    return await api.importAgentFromFile(filePath);
+  } catch (error) {
+    if (isBlockedFileError(error) && window.confirm(`${error}. Unblock it and import it anyway?`)) {
+      return await api.importAgentFromFile(filePath, true);
+    }
+    throw error;
+  }
+}
+
+/**
+ * Restores a checkpoint, asking before taking over files another account owns
+ */
+export async function restoreCheckpointAsking(
+  checkpointId: string,
+  sessionId: string,
+  projectId: string,
+  projectPath: string
+): Promise<CheckpointResult> {
+  const result = await api.restoreCheckpoint(checkpointId, sessionId, projectId, projectPath);
+  if (
+    result.locked_files.length > 0 &&
+    window.confirm(
+      `${result.locked_files.length} file(s) could not be restored because another account, usually an administrator session, owns them. Take ownership and restore them? This needs opcode to run as administrator.`
+    )
+  ) {
+    return await api.restoreCheckpoint(checkpointId, sessionId, projectId, projectPath, true);
+  }
+  return result;
+}
+
+/**
+ * Outcome of changing a Defender exclusion; nothing changed while consent is required
+ */
+export type ExclusionChange =
+  | { status: "applied" }
+  | { status: "consent_required"; warning: string };
+
+/**
+ * EFS state of a directory
+ */
+export interface EfsStatus {
+  /** The volume supports EFS; Windows Home editions still can't encrypt */
+  supported: boolean;
+  encrypted: boolean;
+}
+
+export type BitLockerState =
+  | "on"
+  | "off"
+  | "encrypting"
+  | "decrypting"
+  | "suspended"
+  | "locked"
+  | "waiting_for_activation"
+  | "not_supported"
+  | "unknown";
+
+/**
+ * Protection of the volume holding a path
+ */
+export interface VolumeProtectionStatus {
+  /** Volume root, e.g. `E:\` */
+  volume: string;
+  removable: boolean;
+  bitlocker: BitLockerState;
+  efs_supported: boolean;
+  /** What the security panel should warn about, if anything */
+  warning: string | null;
+}
+
+/**
+ * A minidump of a hung run
+ */
+export interface ProcessDump {
+  path: string;
+  size: number;
+  /** RFC 3339 modification time */
+  created: string | null;
+}
+
+export type ProtectedOperation = "delete_all_checkpoints" | "reveal_api_key" | "kill_all_sessions";
+
+/**
+ * Which operations ask for Windows Hello first
+ */
+export interface UserPresenceSettings {
+  enabled: boolean;
+  operations: ProtectedOperation[];
+}
+
+export type UserPresenceAvailability =
+  | "available"
+  | "device_not_present"
+  | "not_configured_for_user"
+  | "disabled_by_policy"
+  | "device_busy";
+
+export interface AgentVersion {
+  id?: number;
+  agent_id: number;
+  version: number;
+  name: string;
+  icon: string;
+  system_prompt: string;
+  default_task?: string;
+  model: string;
+  enable_file_read: boolean;
+  enable_file_write: boolean;
+  enable_network: boolean;
+  hooks?: string;
+  note?: string;
+  created_at: string;
+}
+
 export interface AgentExport {
   version: number;
   exported_at: string;
@@ -145,6 +313,16 @@ export interface GitHubAgentFile {
   sha: string;
 }
 
+/** Why a Claude session or agent run ended */
+export type ExitClassification =
+  | 'success'
+  | 'user_cancelled'
+  | 'auth_error'
+  | 'rate_limited'
+  | 'crashed'
+  | 'killed_by_opcode'
+  | 'unknown';
+
 export interface AgentRun {
   id?: number;
   agent_id: number;
@@ -159,6 +337,18 @@ export interface AgentRun {
   process_started_at?: string;
   created_at: string;
   completed_at?: string;
+  exit_code?: number;
+  exit_classification?: ExitClassification;
+}
+
+export interface SessionRun {
+  id: number;
+  session_id?: string;
+  project_path: string;
+  model: string;
+  exit_code?: number;
+  exit_classification?: ExitClassification;
+  completed_at: string;
 }
 
 export interface AgentRunMetrics {
@@ -184,6 +374,8 @@ export interface AgentRunWithMetrics {
   process_started_at?: string;
   created_at: string;
   completed_at?: string;
+  exit_code?: number;
+  exit_classification?: ExitClassification;
   metrics?: AgentRunMetrics;
   output?: string; // Real-time JSONL content
 }
@@ -263,6 +455,8 @@ export interface CheckpointMetadata {
   userPrompt: string;
   fileChanges: number;
   snapshotSize: number;
+  /** Online-only files that were not snapshotted and survive a restore */
+  skippedPlaceholders?: string[];
 }
 
 /**
@@ -311,6 +505,8 @@ export interface CheckpointResult {
   checkpoint: Checkpoint;
   filesProcessed: number;
   warnings: string[];
+  /** Files a restore couldn't write because another account owns them */
+  locked_files: string[];
 }
 
 /**
@@ -491,11 +687,12 @@ export const api = {
   /**
    * Retrieves sessions for a specific project
    * @param projectId - The ID of the project to retrieve sessions for
+   * @param aliasIds - IDs of other directories for the same project folder
    * @returns Promise resolving to an array of sessions
    */
-  async getProjectSessions(projectId: string): Promise<Session[]> {
+  async getProjectSessions(projectId: string, aliasIds?: string[]): Promise<Session[]> {
     try {
-      return await invoke<Session[]>('get_project_sessions', { projectId });
+      return await invoke<Session[]>('get_project_sessions', { projectId, aliasIds });
     } catch (error) {
       console.error("Failed to get project sessions:", error);
       throw error;
@@ -634,6 +831,253 @@ export const api = {
     }
   },
 
+  /**
+   * Lists the secrets in the platform secret store
+   * @returns Promise resolving to the stored secrets, without their values
+   */
+  async listSecrets(): Promise<SecretInfo[]> {
+    try {
+      return await invoke<SecretInfo[]>("list_secrets");
+    } catch (error) {
+      console.error("Failed to list secrets:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Stores a secret, replacing any previous value
+   * @param kind - The kind of secret
+   * @param secret - The secret value
+   * @param account - The account to store it under; defaults to "default"
+   */
+  async storeSecret(kind: SecretKind, secret: string, account?: string): Promise<void> {
+    try {
+      return await invoke<void>("store_secret", { kind, account, secret });
+    } catch (error) {
+      console.error("Failed to store secret:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Deletes a secret
+   * @param kind - The kind of secret
+   * @param account - The account it is stored under; defaults to "default"
+   * @returns Promise resolving to whether a secret was deleted
+   */
+  async deleteSecret(kind: SecretKind, account?: string): Promise<boolean> {
+    try {
+      return await invoke<boolean>("delete_secret", { kind, account });
+    } catch (error) {
+      console.error("Failed to delete secret:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Reads a stored secret's value, after Windows Hello if the settings ask for it
+   * @param kind - The kind of secret
+   * @param account - The account it is stored under; defaults to "default"
+   * @returns Promise resolving to the value, or null if none is stored
+   */
+  async revealSecret(kind: SecretKind, account?: string): Promise<string | null> {
+    try {
+      return await invoke<string | null>("reveal_secret", { kind, account });
+    } catch (error) {
+      console.error("Failed to reveal secret:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Moves the API keys in ~/.claude/settings.json into the secret store
+   * @param consent - Whether the user agreed to the keys listed by a first call without it
+   * @returns Promise resolving to the keys that will be or were removed from the file
+   */
+  async migrateSettingsSecrets(consent?: boolean): Promise<SettingsSecretsMigration> {
+    try {
+      return await invoke<SettingsSecretsMigration>("migrate_settings_secrets", { consent });
+    } catch (error) {
+      console.error("Failed to move secrets out of the Claude settings:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Gets which operations ask for Windows Hello first
+   */
+  async getUserPresenceSettings(): Promise<UserPresenceSettings> {
+    try {
+      return await invoke<UserPresenceSettings>("get_user_presence_settings");
+    } catch (error) {
+      console.error("Failed to get Windows Hello settings:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Saves which operations ask for Windows Hello first
+   * @param settings - The settings to save
+   */
+  async saveUserPresenceSettings(settings: UserPresenceSettings): Promise<void> {
+    try {
+      return await invoke<void>("save_user_presence_settings", { settings });
+    } catch (error) {
+      console.error("Failed to save Windows Hello settings:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Checks whether Windows Hello is set up and usable
+   * @returns Promise resolving to the availability; unsupported off Windows
+   */
+  async getUserPresenceAvailability(): Promise<UserPresenceAvailability> {
+    try {
+      return await invoke<UserPresenceAvailability>("get_user_presence_availability");
+    } catch (error) {
+      console.error("Failed to check Windows Hello availability:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Registers the "Opcode Claude Shell" Windows Terminal profile for the detected Claude CLI
+   * @returns Promise resolving to the fragment path, or null in portable mode
+   */
+  async installTerminalProfile(): Promise<string | null> {
+    try {
+      return await invoke<string | null>("install_terminal_profile");
+    } catch (error) {
+      console.error("Failed to install terminal profile:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Removes the "Opcode Claude Shell" Windows Terminal profile
+   * @returns Promise resolving to whether a profile was installed
+   */
+  async removeTerminalProfile(): Promise<boolean> {
+    try {
+      return await invoke<boolean>("remove_terminal_profile");
+    } catch (error) {
+      console.error("Failed to remove terminal profile:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Lists the paths excluded from Windows Defender scanning
+   * @returns Promise resolving to the paths, or null if only administrators may read them
+   */
+  async listDefenderExclusions(): Promise<string[] | null> {
+    try {
+      return await invoke<string[] | null>("list_defender_exclusions");
+    } catch (error) {
+      console.error("Failed to list Defender exclusions:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Adds or removes the Windows Defender exclusion of a project or the opcode data directory
+   * @param path - The directory
+   * @param excluded - Whether to add the exclusion
+   * @param consent - Set after the user agreed to the warning of a first call
+   */
+  async setDefenderExclusion(path: string, excluded: boolean, consent?: boolean): Promise<ExclusionChange> {
+    try {
+      return await invoke<ExclusionChange>("set_defender_exclusion", { path, excluded, consent });
+    } catch (error) {
+      console.error("Failed to change Defender exclusion:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Gets the EFS state of a directory
+   * @param path - The directory; defaults to ~/.claude, which holds transcripts and credentials
+   */
+  async getEfsStatus(path?: string): Promise<EfsStatus> {
+    try {
+      return await invoke<EfsStatus>("get_efs_status", { path });
+    } catch (error) {
+      console.error("Failed to get EFS status:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Encrypts or decrypts a directory and everything in it with EFS
+   * @param encrypted - Whether to encrypt
+   * @param path - The directory; defaults to ~/.claude, which holds transcripts and credentials
+   * @returns Promise resolving to the number of entries changed
+   */
+  async setEfsEncrypted(encrypted: boolean, path?: string): Promise<number> {
+    try {
+      return await invoke<number>("set_efs_encrypted", { path, encrypted });
+    } catch (error) {
+      console.error("Failed to change EFS encryption:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Gets the BitLocker and removable-media status of the drive holding a path
+   * @param path - A drive such as "E:" or any path on it, e.g. a project directory
+   */
+  async getVolumeProtectionStatus(path: string): Promise<VolumeProtectionStatus> {
+    try {
+      return await invoke<VolumeProtectionStatus>("get_volume_protection_status", { path });
+    } catch (error) {
+      console.error("Failed to get volume protection status:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Writes a minidump of a running session or agent run (Windows only)
+   * @param runId - The run ID from the process registry
+   * @returns Promise resolving to the path of the dump
+   */
+  async captureProcessDump(runId: number): Promise<string> {
+    try {
+      return await invoke<string>("capture_process_dump", { runId });
+    } catch (error) {
+      console.error("Failed to capture process dump:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Lists the minidumps written so far, newest first
+   */
+  async listProcessDumps(): Promise<ProcessDump[]> {
+    try {
+      return await invoke<ProcessDump[]>("list_process_dumps");
+    } catch (error) {
+      console.error("Failed to list process dumps:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Downloads an update installer and checks it against its published checksum
+   * @param url - URL of the installer
+   * @param sha256 - Hex SHA-256 published with the release
+   * @param background - Whether the user didn't ask for it; then it only runs inside the maintenance window
+   * @returns Promise resolving to the path of the verified installer
+   */
+  async downloadUpdate(url: string, sha256: string, background?: boolean): Promise<string> {
+    try {
+      return await invoke<string>("download_update", { url, sha256, background });
+    } catch (error) {
+      console.error("Failed to download update:", error);
+      throw error;
+    }
+  },
+
   /**
    * Finds all CLAUDE.md files in a project directory
    * @param projectPath - The absolute path to the project
@@ -734,6 +1178,7 @@ export const api = {
    * @param default_task - Optional default task
    * @param model - Optional model
    * @param hooks - Optional hooks configuration as JSON string
+   * @param version_note - Optional note stored with the new version
    * @returns Promise resolving to the updated agent
    */
   async updateAgent(
@@ -743,7 +1188,8 @@ export const api = {
     system_prompt: string, 
     default_task?: string, 
     model?: string,
-    hooks?: string
+    hooks?: string,
+    version_note?: string
   ): Promise<Agent> {
     try {
       return await invoke<Agent>('update_agent', { 
@@ -753,7 +1199,8 @@ export const api = {
         systemPrompt: system_prompt,
         defaultTask: default_task,
         model,
-        hooks
+        hooks,
+        versionNote: version_note
       });
     } catch (error) {
       console.error("Failed to update agent:", error);
@@ -761,6 +1208,35 @@ export const api = {
     }
   },
 
+  /**
+   * Lists the stored versions of an agent, newest first
+   * @param id - The agent ID
+   * @returns Promise resolving to the agent's versions
+   */
+  async listAgentVersions(id: number): Promise<AgentVersion[]> {
+    try {
+      return await invoke<AgentVersion[]>('list_agent_versions', { id });
+    } catch (error) {
+      console.error("Failed to list agent versions:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Restores an agent to a stored version
+   * @param id - The agent ID
+   * @param version - The version to restore
+   * @returns Promise resolving to the restored agent
+   */
+  async rollbackAgent(id: number, version: number): Promise<Agent> {
+    try {
+      return await invoke<Agent>('rollback_agent', { id, version });
+    } catch (error) {
+      console.error("Failed to roll back agent:", error);
+      throw error;
+    }
+  },
+
   /**
    * Deletes an agent
    * @param id - The agent ID to delete
@@ -820,11 +1296,12 @@ export const api = {
   /**
    * Imports an agent from a file
    * @param filePath - The path to the JSON file
+   * @param unblock - Remove the Mark-of-the-Web if Windows blocks the file as downloaded
    * @returns Promise resolving to the imported agent
    */
-  async importAgentFromFile(filePath: string): Promise<Agent> {
+  async importAgentFromFile(filePath: string, unblock?: boolean): Promise<Agent> {
     try {
-      return await invoke<Agent>('import_agent_from_file', { filePath });
+      return await invoke<Agent>('import_agent_from_file', { filePath, unblock });
     } catch (error) {
       console.error("Failed to import agent from file:", error);
       throw error;
@@ -907,6 +1384,60 @@ export const api = {
     }
   },
 
+  /**
+   * Runs a failed agent run's task again
+   * @param runId - The run ID to retry
+   * @returns Promise resolving to the new run ID
+   */
+  async retryAgentRun(runId: number): Promise<number> {
+    try {
+      return await invoke<number>('retry_agent_run', { runId });
+    } catch (error) {
+      console.error("Failed to retry agent run:", error);
+      throw new Error(`Failed to retry agent run: ${error instanceof Error ? error.message : 'Unknown error'}`);
+    }
+  },
+
+  /**
+   * Lists how interactive Claude sessions ended
+   * @param projectPath - Optional project path to filter by
+   * @returns Promise resolving to the recorded session exits
+   */
+  async listSessionRuns(projectPath?: string): Promise<SessionRun[]> {
+    try {
+      return await invoke<SessionRun[]>('list_session_runs', { projectPath });
+    } catch (error) {
+      console.error("Failed to list session runs:", error);
+      throw new Error(`Failed to list session runs: ${error instanceof Error ? error.message : 'Unknown error'}`);
+    }
+  },
+
+  /**
+   * Gets the URL notified when a run or session ends
+   * @returns Promise resolving to the webhook URL, or null if none is set
+   */
+  async getRunWebhook(): Promise<string | null> {
+    try {
+      return await invoke<string | null>('get_run_webhook');
+    } catch (error) {
+      console.error("Failed to get run webhook:", error);
+      throw error;
+    }
+  },
+
+  /**
+   * Sets the URL notified when a run or session ends
+   * @param url - The webhook URL, or null to turn it off
+   */
+  async saveRunWebhook(url: string | null): Promise<void> {
+    try {
+      return await invoke<void>('save_run_webhook', { url });
+    } catch (error) {
+      console.error("Failed to save run webhook:", error);
+      throw error;
+    }
+  },
+
   /**
    * Lists all currently running agent sessions
    * @returns Promise resolving to list of running agent sessions
@@ -934,6 +1465,20 @@ export const api = {
     }
   },
 
+  /**
+   * Kills every running agent run and Claude session, after Windows Hello if
+   * the settings ask for it
+   * @returns Promise resolving to how many were stopped
+   */
+  async killAllSessions(): Promise<number> {
+    try {
+      return await invoke<number>('kill_all_sessions');
+    } catch (error) {
+      console.error("Failed to kill all sessions:", error);
+      throw error;
+    }
+  },
+
   /**
    * Gets the status of a specific agent session
    * @param runId - The run ID to check
@@ -1010,6 +1555,21 @@ export const api = {
     return invoke("load_session_history", { sessionId, projectId });
   },
 
+  /**
+   * Exports a session's JSONL history to a file
+   * @param sessionId - The session ID (UUID)
+   * @param projectId - The project ID
+   * @param filePath - Where to write the export
+   */
+  async exportSession(sessionId: string, projectId: string, filePath: string): Promise<void> {
+    try {
+      return await invoke('export_session', { sessionId, projectId, filePath });
+    } catch (error) {
+      console.error("Failed to export session:", error);
+      throw error;
+    }
+  },
+
   /**
    * Loads the JSONL history for a specific agent session
    * Similar to loadSessionHistory but searches across all project directories
@@ -1172,18 +1732,22 @@ export const api = {
 
   /**
    * Restores a session to a specific checkpoint
+   * @param takeOwnership - Take over the `locked_files` of an earlier attempt;
+   * only set after the user agreed
    */
   async restoreCheckpoint(
     checkpointId: string,
     sessionId: string,
     projectId: string,
-    projectPath: string
+    projectPath: string,
+    takeOwnership?: boolean
   ): Promise<CheckpointResult> {
     return invoke("restore_checkpoint", {
       checkpointId,
       sessionId,
       projectId,
-      projectPath
+      projectPath,
+      takeOwnership
     });
   },
 
@@ -1927,6 +2491,19 @@ export const api = {
     }
   },
 
+  /**
+   * Reports keyboard or mouse input in the window, so idle-triggered
+   * maintenance doesn't start while the user is reading or typing
+   */
+  async reportUserActivity(): Promise<void> {
+    try {
+      return await invoke<void>("report_user_activity");
+    } catch (error) {
+      console.error("Failed to report user activity:", error);
+      throw error;
+    }
+  },
+
   /**
    * Deletes a slash command
    * @param commandId - Unique identifier of the command to delete